@@ -0,0 +1,140 @@
+//! `ckb-multisig address`: lock args, lock script and address of a config.
+//!
+//! ```text
+//! ckb-multisig address --pubkey 0x02... --pubkey 0x03... --threshold 2 --code-hash <hash>
+//! ckb-multisig address --config config.toml --code-hash <hash> --verify ckb1...
+//! ```
+//!
+//! The config comes from a config file, or from the member keys given as
+//! compressed public keys or blake160 hashes. `--verify` recomputes the
+//! address and fails when the given one is for another config or lock.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use ckb_jsonrpc_types as json;
+use ckb_multisig_sdk::{blake160, constants::BLAKE160_SIZE, MultisigConfig};
+use ckb_sdk::{Address, AddressPayload, NetworkType};
+use ckb_types::{core::ScriptHashType, packed::Script, H256};
+use clap::Args;
+use serde_json::json;
+
+use crate::util::{
+    emit, load_config, parse_address, parse_h256, parse_hash_type, parse_hex, parse_network,
+    parse_since_arg,
+};
+
+#[derive(Args)]
+pub struct AddressArgs {
+    /// Config file, instead of the member keys
+    #[arg(long, conflicts_with_all = ["pubkey_hashes", "pubkeys", "threshold", "require_first_n", "since"])]
+    config: Option<PathBuf>,
+
+    /// blake160 of a member public key, in the multisig script order
+    #[arg(long = "pubkey-hash", value_parser = parse_hex::<BLAKE160_SIZE>)]
+    pubkey_hashes: Vec<[u8; BLAKE160_SIZE]>,
+
+    /// Compressed public key of a member, listed after the `--pubkey-hash` ones
+    #[arg(long = "pubkey", value_parser = parse_hex::<33>)]
+    pubkeys: Vec<[u8; 33]>,
+
+    #[arg(long, required_unless_present = "config")]
+    threshold: Option<u8>,
+
+    #[arg(long, default_value_t = 0)]
+    require_first_n: u8,
+
+    /// Since of the lock args, readable like "after epoch 180" or raw 0x...
+    #[arg(long, value_parser = parse_since_arg)]
+    since: Option<u64>,
+
+    /// Code hash of the deployed contract
+    #[arg(long, value_parser = parse_h256)]
+    code_hash: H256,
+
+    /// Hash type of the lock script: data, type, data1 or data2
+    #[arg(long, default_value = "type", value_parser = parse_hash_type)]
+    hash_type: ScriptHashType,
+
+    /// mainnet or testnet, the network of `--verify` when omitted, else mainnet
+    #[arg(long, value_parser = parse_network)]
+    network: Option<NetworkType>,
+
+    /// Address to check against the config
+    #[arg(long, value_parser = parse_address)]
+    verify: Option<Address>,
+}
+
+pub fn run(args: AddressArgs) -> Result<()> {
+    let config = match &args.config {
+        Some(path) => load_config(path)?,
+        None => {
+            let mut hashes = args.pubkey_hashes.clone();
+            hashes.extend(args.pubkeys.iter().map(|pubkey| blake160(pubkey)));
+            let threshold = args.threshold.expect("required without --config");
+            MultisigConfig::new(hashes, args.require_first_n, threshold)?.with_since(args.since)
+        }
+    };
+    let network = args
+        .network
+        .or_else(|| args.verify.as_ref().map(Address::network))
+        .unwrap_or(NetworkType::Mainnet);
+    let script = config.lock_script(&args.code_hash, args.hash_type);
+    let address = Address::new(network, AddressPayload::from(script.clone()), true);
+
+    if let Some(expected) = &args.verify {
+        let mismatches = mismatches(expected, &address, &script);
+        if !mismatches.is_empty() {
+            bail!(
+                "{} is not the address of the config:\n  {}",
+                expected,
+                mismatches.join("\n  ")
+            );
+        }
+    }
+    let output = json!({
+        "lock_args": format!("0x{}", hex::encode(config.lock_args())),
+        "lock_script": json::Script::from(script),
+        "address": address.to_string(),
+        "verified": args.verify.is_some(),
+    });
+    emit(output.clone(), || println!("{:#}", output));
+    Ok(())
+}
+
+/// How `expected` differs from the computed address.
+fn mismatches(expected: &Address, address: &Address, script: &Script) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    if expected.network() != address.network() {
+        mismatches.push(format!(
+            "network {}, expected {}",
+            expected.network().to_str(),
+            address.network().to_str()
+        ));
+    }
+    let other = json::Script::from(Script::from(expected));
+    let script = json::Script::from(script.clone());
+    if other.code_hash != script.code_hash {
+        mismatches.push(format!(
+            "code hash {:#x}, expected {:#x}",
+            other.code_hash, script.code_hash
+        ));
+    }
+    if other.hash_type != script.hash_type {
+        mismatches.push(format!(
+            "hash type {}, expected {}",
+            other.hash_type, script.hash_type
+        ));
+    }
+    if other.args != script.args {
+        mismatches.push(format!(
+            "lock args 0x{}, expected 0x{}",
+            hex::encode(other.args.as_bytes()),
+            hex::encode(script.args.as_bytes())
+        ));
+    }
+    if mismatches.is_empty() && expected.to_string() != address.to_string() {
+        mismatches.push("not in the full bech32m format".to_string());
+    }
+    mismatches
+}
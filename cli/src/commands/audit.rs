@@ -0,0 +1,115 @@
+//! `ckb-multisig audit`: re-verify an audit log of the approvals, offline.
+//!
+//! ```text
+//! ckb-multisig audit --log audit.jsonl --config treasury.toml
+//! ckb-multisig audit --log audit.jsonl --after <hash of the last entry checked>
+//! ```
+//!
+//! The log is the export of the coordination server, `GET /audit`. Every
+//! entry must match its hash and chain to the one before, and every signature
+//! must recover to its signer. With `--config`, every entry must be for one
+//! of the configs, by a member. `--after` checks the log extends the one
+//! audited before, whose last hash was kept, and prints the new entries only.
+//! See `audit.rs` of the SDK for the format.
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+use ckb_multisig_sdk::audit::{parse_log, verify_log};
+use clap::Args;
+use serde_json::json;
+
+use crate::util::{emit, load_config, parse_hex};
+
+#[derive(Args)]
+pub struct AuditArgs {
+    /// Audit log, JSON lines
+    #[arg(long)]
+    log: PathBuf,
+
+    /// Config file the approvals must be for, can be repeated
+    #[arg(long = "config")]
+    configs: Vec<PathBuf>,
+
+    /// Hash of the last entry of a previous audit, the log must extend it
+    #[arg(long)]
+    after: Option<String>,
+}
+
+pub fn run(args: AuditArgs) -> Result<()> {
+    let content =
+        fs::read_to_string(&args.log).with_context(|| format!("read {}", args.log.display()))?;
+    let entries = parse_log(&content)?;
+    verify_log(&entries)?;
+    let configs = args
+        .configs
+        .iter()
+        .map(|path| load_config(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let start = match &args.after {
+        Some(hash) => {
+            let hash: [u8; 32] = parse_hex(hash)?;
+            match entries.iter().position(|entry| entry.hash() == hash) {
+                Some(position) => position + 1,
+                None => bail!("the log doesn't extend the entry 0x{}", hex::encode(hash)),
+            }
+        }
+        None => 0,
+    };
+    for entry in &entries[start..] {
+        let approval = &entry.approval;
+        if !configs.is_empty()
+            && configs
+                .iter()
+                .all(|config| entry.check_member(config).is_err())
+        {
+            bail!(
+                "entry {}: 0x{} signed for lock args 0x{}, not by a member of the configs",
+                entry.seq,
+                hex::encode(approval.signer),
+                hex::encode(&approval.lock_args)
+            );
+        }
+        let value = json!({
+            "kind": "entry",
+            "seq": entry.seq,
+            "timestamp": approval.timestamp,
+            "signer": format!("0x{}", hex::encode(approval.signer)),
+            "proposal": format!("0x{}", hex::encode(approval.proposal)),
+            "digest": format!("0x{}", hex::encode(approval.digest)),
+            "lock_args": format!("0x{}", hex::encode(&approval.lock_args)),
+        });
+        emit(value, || {
+            println!(
+                "#{} at {}: 0x{} approved 0x{}",
+                entry.seq,
+                approval.timestamp,
+                hex::encode(approval.signer),
+                hex::encode(approval.proposal)
+            );
+            println!(
+                "     digest 0x{}, lock args 0x{}",
+                hex::encode(approval.digest),
+                hex::encode(&approval.lock_args)
+            );
+        });
+    }
+    let last_hash = entries
+        .last()
+        .map(|last| format!("0x{}", hex::encode(last.hash())));
+    let value = json!({
+        "kind": "summary",
+        "verified": entries.len() - start,
+        "last_hash": last_hash,
+    });
+    emit(value, || match &last_hash {
+        Some(last_hash) => println!(
+            "{} entries verified, last hash {}",
+            entries.len() - start,
+            last_hash
+        ),
+        None => println!("empty log"),
+    });
+    Ok(())
+}
@@ -0,0 +1,232 @@
+//! `ckb-multisig completions`: the completion script of a shell.
+//!
+//! ```text
+//! ckb-multisig completions --shell bash > /etc/bash_completion.d/ckb-multisig
+//! ckb-multisig completions --shell zsh > "${fpath[1]}/_ckb-multisig"
+//! ckb-multisig completions --shell fish > ~/.config/fish/completions/ckb-multisig.fish
+//! ```
+//!
+//! Generated from the command tree of the CLI itself: the subcommands and
+//! flags of every command, `help` aside. The values of the flags complete as file names.
+//! The zsh script is the bash one under `bashcompinit`.
+
+use anyhow::Result;
+use clap::{Arg, Args, Command, CommandFactory, ValueEnum};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    #[arg(long, value_enum)]
+    shell: Shell,
+}
+
+/// A command of the tree: its subcommand names from the root and what may
+/// follow it.
+struct Node {
+    path: Vec<String>,
+    subcommands: Vec<(String, String)>,
+    flags: Vec<Flag>,
+}
+
+struct Flag {
+    long: Option<String>,
+    short: Option<char>,
+    help: String,
+    takes_value: bool,
+}
+
+pub fn run(args: CompletionsArgs) -> Result<()> {
+    let mut command = crate::Cli::command();
+    command.build();
+    let name = command.get_name().to_string();
+    let mut nodes = Vec::new();
+    collect(&command, Vec::new(), &mut nodes);
+    let script = match args.shell {
+        Shell::Bash => bash(&name, &nodes),
+        Shell::Zsh => format!(
+            "#compdef {}\n\nautoload -U +X bashcompinit && bashcompinit\n\n{}",
+            name,
+            bash(&name, &nodes)
+        ),
+        Shell::Fish => fish(&name, &nodes),
+    };
+    print!("{}", script);
+    Ok(())
+}
+
+fn collect(command: &Command, path: Vec<String>, nodes: &mut Vec<Node>) {
+    let subcommands: Vec<_> = command
+        .get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+        .collect();
+    nodes.push(Node {
+        path: path.clone(),
+        subcommands: subcommands
+            .iter()
+            .map(|sub| (sub.get_name().to_string(), about(sub)))
+            .collect(),
+        flags: command
+            .get_arguments()
+            .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+            .map(flag)
+            .collect(),
+    });
+    for sub in subcommands {
+        let mut path = path.clone();
+        path.push(sub.get_name().to_string());
+        collect(sub, path, nodes);
+    }
+}
+
+fn flag(arg: &Arg) -> Flag {
+    Flag {
+        long: arg.get_long().map(str::to_string),
+        short: arg.get_short(),
+        help: first_line(arg.get_help().map(ToString::to_string)),
+        takes_value: arg.get_action().takes_values(),
+    }
+}
+
+fn about(command: &Command) -> String {
+    first_line(command.get_about().map(ToString::to_string))
+}
+
+fn first_line(text: Option<String>) -> String {
+    text.unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn bash(name: &str, nodes: &[Node]) -> String {
+    let function = format!("_{}", name.replace('-', "_"));
+    let key = |path: &[String]| {
+        let mut key = name.to_string();
+        for part in path {
+            key.push(' ');
+            key.push_str(part);
+        }
+        format!("\"{}\"", key)
+    };
+    let mut value_flags: Vec<String> = nodes
+        .iter()
+        .flat_map(|node| &node.flags)
+        .filter(|flag| flag.takes_value)
+        .flat_map(|flag| {
+            let long = flag.long.iter().map(|long| format!("--{}", long));
+            let short = flag.short.iter().map(|short| format!("-{}", short));
+            long.chain(short).collect::<Vec<_>>()
+        })
+        .collect();
+    value_flags.sort();
+    value_flags.dedup();
+
+    let mut script = format!("{}() {{\n    COMPREPLY=()\n", function);
+    script.push_str(
+        "    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n",
+    );
+    script.push_str(&format!("    local cmd=\"{}\" words i\n", name));
+    if !value_flags.is_empty() {
+        script.push_str(&format!(
+            "    case \"$prev\" in\n        {})\n            return ;;\n    esac\n",
+            value_flags.join("|")
+        ));
+    }
+    let commands: Vec<_> = nodes
+        .iter()
+        .filter(|node| !node.path.is_empty())
+        .map(|node| key(&node.path))
+        .collect();
+    if !commands.is_empty() {
+        script.push_str("    for ((i = 1; i < COMP_CWORD; i++)); do\n");
+        script.push_str("        case \"$cmd ${COMP_WORDS[i]}\" in\n");
+        script.push_str(&format!(
+            "            {})\n                cmd=\"$cmd ${{COMP_WORDS[i]}}\" ;;\n",
+            commands.join("|")
+        ));
+        script.push_str("        esac\n    done\n");
+    }
+    script.push_str("    case \"$cmd\" in\n");
+    for node in nodes {
+        let mut words: Vec<String> = node
+            .subcommands
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+        for flag in &node.flags {
+            words.extend(flag.long.iter().map(|long| format!("--{}", long)));
+            words.extend(flag.short.iter().map(|short| format!("-{}", short)));
+        }
+        script.push_str(&format!(
+            "        {})\n            words=\"{}\" ;;\n",
+            key(&node.path),
+            words.join(" ")
+        ));
+    }
+    script.push_str("    esac\n");
+    script.push_str("    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))\n}\n\n");
+    script.push_str(&format!("complete -o default -F {} {}\n", function, name));
+    script
+}
+
+fn fish(name: &str, nodes: &[Node]) -> String {
+    let mut script = String::new();
+    for node in nodes {
+        let mut conditions: Vec<String> = if node.path.is_empty() {
+            vec!["__fish_use_subcommand".to_string()]
+        } else {
+            node.path
+                .iter()
+                .map(|part| format!("__fish_seen_subcommand_from {}", part))
+                .collect()
+        };
+        if !node.path.is_empty() && !node.subcommands.is_empty() {
+            let children: Vec<_> = node
+                .subcommands
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect();
+            conditions.push(format!(
+                "not __fish_seen_subcommand_from {}",
+                children.join(" ")
+            ));
+        }
+        let condition = quote(&conditions.join("; and "));
+        for (sub, about) in &node.subcommands {
+            script.push_str(&format!(
+                "complete -c {} -n {} -f -a {} -d {}\n",
+                name,
+                condition,
+                sub,
+                quote(about)
+            ));
+        }
+        for flag in &node.flags {
+            let mut line = format!("complete -c {} -n {}", name, condition);
+            if let Some(long) = &flag.long {
+                line.push_str(&format!(" -l {}", long));
+            }
+            if let Some(short) = flag.short {
+                line.push_str(&format!(" -s {}", short));
+            }
+            if flag.takes_value {
+                line.push_str(" -r");
+            }
+            line.push_str(&format!(" -d {}\n", quote(&flag.help)));
+            script.push_str(&line);
+        }
+    }
+    script
+}
+
+/// `s` in fish single quotes.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
@@ -0,0 +1,597 @@
+//! `ckb-multisig config init`: write a config file with the cosigners.
+//!
+//! ```text
+//! ckb-multisig config init --output treasury.toml --code-hash <hash>
+//! ```
+//!
+//! Asks for the member keys one by one, in the multisig script order: a
+//! compressed public key or a pubkey hash pasted in hex, a file holding one
+//! or a keystore file, or `ledger <path>` for a key of the connected Ledger
+//! and `trezor <path>` for one of the connected Trezor or OneKey.
+//! Then the threshold, `require_first_n` and the since of the lock args.
+//! Before writing, it prints a fingerprint of every key for the cosigners to
+//! read out to each other, and asks for confirmation.
+//!
+//! `config compile` writes the configs of a policy, see `lock_policy.rs` of
+//! the SDK, and `config decompile` prints the policy of config files back:
+//!
+//! ```text
+//! ckb-multisig config compile --policy "2 of [alice, bob, carol]; 1 of [dave]" \
+//!     --members members.toml --output treasury.toml
+//! ckb-multisig config decompile --config treasury-0.toml --config treasury-1.toml
+//! ```
+//!
+//! The members file maps the names to public keys or pubkey hashes in hex,
+//! `alice = "0x..."`. A policy of several clauses is a config tree, written as
+//! one file per clause, `treasury-0.toml` and so on, the labels of the keys
+//! being the names.
+//!
+//! `config descriptor` prints the single line backup of a config, see
+//! `descriptor.rs` of the SDK, and `config from-descriptor` writes the config
+//! file of one:
+//!
+//! ```text
+//! ckb-multisig config descriptor --config treasury.toml
+//! ckb-multisig config from-descriptor --descriptor "multi(2,0,...)#..." --output treasury.toml
+//! ```
+//!
+//! `config rotate` moves the cells of a config under a new one, see
+//! `migrate.rs`.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use ckb_multisig_sdk::{
+    blake160,
+    config_file::ConfigFile,
+    config_tree::ConfigTree,
+    constants::BLAKE160_SIZE,
+    descriptor,
+    lock_policy::{Compiled, LockPolicy},
+    since::format_since,
+    MultisigConfig,
+};
+use ckb_sdk::{Address, AddressPayload, NetworkType};
+use ckb_types::{core::ScriptHashType, H256};
+use clap::{Args, Subcommand};
+use secp256k1::PublicKey;
+use serde_json::json;
+
+use crate::{
+    keystore,
+    util::{
+        emit, load_config_file, parse_h256, parse_hash_type, parse_hex, parse_network,
+        parse_since_arg,
+    },
+};
+
+#[cfg(any(feature = "ledger", feature = "trezor"))]
+use ckb_multisig_sdk::ledger::DerivationPath;
+#[cfg(feature = "ledger")]
+use ckb_multisig_sdk::ledger::{hid::HidTransport, LedgerSigner};
+#[cfg(feature = "trezor")]
+use ckb_multisig_sdk::trezor::{hid::HidTransport as TrezorHidTransport, TrezorSigner};
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Build a config interactively, write it and print its address
+    Init(InitArgs),
+    /// Write the configs of a policy such as "2 of [alice, bob, carol]"
+    Compile(CompileArgs),
+    /// Print the policy of config files
+    Decompile(DecompileArgs),
+    /// Print the descriptor of a config, its single line backup
+    Descriptor(DescriptorArgs),
+    /// Write the config file of a descriptor
+    FromDescriptor(FromDescriptorArgs),
+    /// Move the cells of a config under a new one: plan, sign, send and check
+    Rotate(Box<super::migrate::RotateArgs>),
+}
+
+#[derive(Args)]
+pub struct InitArgs {
+    /// Config file to write, TOML unless the extension is `.json`
+    #[arg(long)]
+    output: PathBuf,
+
+    /// Replace the file if it exists
+    #[arg(long)]
+    force: bool,
+
+    /// Code hash of the deployed contract, for the address
+    #[arg(long, value_parser = parse_h256)]
+    code_hash: Option<H256>,
+
+    /// Hash type of the lock script: data, type, data1 or data2
+    #[arg(long, default_value = "type", value_parser = parse_hash_type)]
+    hash_type: ScriptHashType,
+
+    /// mainnet or testnet
+    #[arg(long, default_value = "mainnet", value_parser = parse_network)]
+    network: NetworkType,
+}
+
+#[derive(Args)]
+pub struct CompileArgs {
+    /// The policy, clauses separated by `;`
+    #[arg(long)]
+    policy: String,
+
+    /// TOML file of the public keys or pubkey hashes of the members, by name
+    #[arg(long)]
+    members: Option<PathBuf>,
+
+    /// Config file to write, TOML unless the extension is `.json`, suffixed
+    /// with the index of the clause when there are several
+    #[arg(long)]
+    output: PathBuf,
+
+    /// Replace the files if they exist
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+pub struct DecompileArgs {
+    /// Config file, repeated for every config of a tree, in order
+    #[arg(long = "config", required = true)]
+    configs: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct DescriptorArgs {
+    /// Config file
+    #[arg(long)]
+    config: PathBuf,
+}
+
+#[derive(Args)]
+pub struct FromDescriptorArgs {
+    /// The descriptor, checksum included
+    #[arg(long)]
+    descriptor: String,
+
+    /// Config file to write, TOML unless the extension is `.json`
+    #[arg(long)]
+    output: PathBuf,
+
+    /// Replace the file if it exists
+    #[arg(long)]
+    force: bool,
+}
+
+/// A key entered for the config.
+struct Member {
+    pubkey_hash: [u8; BLAKE160_SIZE],
+    pubkey: Option<[u8; 33]>,
+    label: Option<String>,
+}
+
+pub fn run(command: ConfigCommand) -> Result<()> {
+    match command {
+        ConfigCommand::Init(args) => init(args),
+        ConfigCommand::Compile(args) => compile(args),
+        ConfigCommand::Decompile(args) => decompile(args),
+        ConfigCommand::Descriptor(args) => print_descriptor(args),
+        ConfigCommand::FromDescriptor(args) => from_descriptor(args),
+        ConfigCommand::Rotate(args) => super::migrate::rotate(*args),
+    }
+}
+
+fn init(args: InitArgs) -> Result<()> {
+    if args.output.exists() && !args.force {
+        bail!(
+            "{} exists, pass --force to replace it",
+            args.output.display()
+        );
+    }
+    let mut prompt = Prompt::new();
+    let members = read_members(&mut prompt)?;
+    let hashes: Vec<_> = members.iter().map(|member| member.pubkey_hash).collect();
+    let count = members.len();
+    let config = prompt.ask_with(&format!("threshold, 1 to {}", count), |line| {
+        let threshold: u8 = line.parse()?;
+        Ok(MultisigConfig::new(hashes.clone(), 0, threshold)?)
+    })?;
+    let threshold = config.threshold();
+    let config = prompt.ask_with(
+        &format!(
+            "how many of the first members must sign, 0 to {} [0]",
+            threshold
+        ),
+        |line| {
+            let first_n: u8 = if line.is_empty() { 0 } else { line.parse()? };
+            Ok(MultisigConfig::new(hashes.clone(), first_n, threshold)?)
+        },
+    )?;
+    let since = prompt.ask_with(
+        "since of the lock args, like \"after epoch 180\", empty for none",
+        |line| {
+            if line.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(parse_since_arg(line)?))
+            }
+        },
+    )?;
+    let config = config.with_since(since);
+
+    print_fingerprints(&config, &members);
+    let confirmed = prompt.ask_with(
+        "did every cosigner confirm the fingerprint of their key? [y/N]",
+        |line| Ok(line.eq_ignore_ascii_case("y") || line.eq_ignore_ascii_case("yes")),
+    )?;
+    if !confirmed {
+        bail!("nothing written");
+    }
+
+    let mut file = ConfigFile::from(&config);
+    for (entry, member) in file.keys.iter_mut().zip(&members) {
+        entry.pubkey = member
+            .pubkey
+            .map(|pubkey| format!("0x{}", hex::encode(pubkey)));
+        entry.label = member.label.clone();
+    }
+    write_config(&args.output, &file)?;
+    let address = args.code_hash.as_ref().map(|code_hash| {
+        let script = config.lock_script(code_hash, args.hash_type);
+        Address::new(args.network, AddressPayload::from(script), true).to_string()
+    });
+    let value = json!({
+        "written": [&args.output],
+        "lock_args": format!("0x{}", hex::encode(config.lock_args())),
+        "address": address,
+    });
+    emit(value, || {
+        println!("wrote {}", args.output.display());
+        println!("lock args 0x{}", hex::encode(config.lock_args()));
+        match &address {
+            Some(address) => println!("address {}", address),
+            None => println!(
+                "pass the code hash of the contract to `ckb-multisig address --config {}` for the address",
+                args.output.display()
+            ),
+        }
+    });
+    Ok(())
+}
+
+fn compile(args: CompileArgs) -> Result<()> {
+    let policy: LockPolicy = args.policy.parse()?;
+    let members = match &args.members {
+        Some(path) => load_members(path)?,
+        None => BTreeMap::new(),
+    };
+    let hashes = members
+        .iter()
+        .map(|(name, member)| (name.clone(), member.pubkey_hash))
+        .collect();
+    let compiled = policy.compile(&hashes)?;
+    let configs = compiled.configs();
+    let outputs: Vec<_> = if configs.len() == 1 {
+        vec![args.output.clone()]
+    } else {
+        (0..configs.len())
+            .map(|index| indexed_path(&args.output, index))
+            .collect()
+    };
+    if let Some(output) = outputs.iter().find(|output| output.exists() && !args.force) {
+        bail!("{} exists, pass --force to replace it", output.display());
+    }
+    for ((config, clause), output) in configs.iter().zip(&policy.clauses).zip(&outputs) {
+        let mut file = ConfigFile::from(config);
+        for (entry, name) in file.keys.iter_mut().zip(&clause.members) {
+            if let Some(member) = members.get(name) {
+                entry.pubkey = member
+                    .pubkey
+                    .map(|pubkey| format!("0x{}", hex::encode(pubkey)));
+                entry.label = Some(name.clone());
+            }
+        }
+        write_config(output, &file)?;
+    }
+    print_written(&outputs, &compiled.lock_args());
+    Ok(())
+}
+
+fn decompile(args: DecompileArgs) -> Result<()> {
+    let mut names = BTreeMap::new();
+    let mut configs = Vec::new();
+    for path in &args.configs {
+        let file = load_config_file(path)?;
+        let config = file.to_config()?;
+        for (hash, label) in config.pubkey_hashes().iter().zip(file.labels()) {
+            if let Some(label) = label {
+                names.insert(label.to_string(), *hash);
+            }
+        }
+        configs.push(config);
+    }
+    let compiled = if configs.len() == 1 && configs[0].proof().is_none() {
+        Compiled::Config(configs[0].clone())
+    } else {
+        let tree = ConfigTree::new(configs.clone())?;
+        if let Some(index) =
+            (0..configs.len()).find(|i| tree.config(*i).as_ref() != Some(&configs[*i]))
+        {
+            bail!(
+                "{} is not config #{} of the tree of the files, are they all given, in order?",
+                args.configs[index].display(),
+                index
+            );
+        }
+        Compiled::Tree(tree)
+    };
+    let policy = LockPolicy::decompile(&compiled, &names)?;
+    let lock_args = format!("0x{}", hex::encode(compiled.lock_args()));
+    let value = json!({ "policy": policy.to_string(), "lock_args": lock_args });
+    emit(value, || {
+        println!("{}", policy);
+        println!("lock args {}", lock_args);
+    });
+    Ok(())
+}
+
+fn print_descriptor(args: DescriptorArgs) -> Result<()> {
+    let config = load_config_file(&args.config)?.to_config()?;
+    let descriptor = descriptor::encode(&config)?;
+    emit(json!({ "descriptor": descriptor }), || {
+        println!("{}", descriptor)
+    });
+    Ok(())
+}
+
+fn from_descriptor(args: FromDescriptorArgs) -> Result<()> {
+    if args.output.exists() && !args.force {
+        bail!(
+            "{} exists, pass --force to replace it",
+            args.output.display()
+        );
+    }
+    let config = descriptor::decode(&args.descriptor)?;
+    write_config(&args.output, &ConfigFile::from(&config))?;
+    print_written(&[args.output], &config.lock_args());
+    Ok(())
+}
+
+/// The config files written and their lock args.
+fn print_written(paths: &[PathBuf], lock_args: &[u8]) {
+    let lock_args = format!("0x{}", hex::encode(lock_args));
+    let value = json!({ "written": paths, "lock_args": lock_args });
+    emit(value, || {
+        for path in paths {
+            println!("wrote {}", path.display());
+        }
+        println!("lock args {}", lock_args);
+    });
+}
+
+/// The members file, names to keys.
+fn load_members(path: &Path) -> Result<BTreeMap<String, Member>> {
+    let content = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let keys: BTreeMap<String, String> =
+        toml::from_str(&content).with_context(|| format!("parse {}", path.display()))?;
+    keys.into_iter()
+        .map(|(name, key)| {
+            let member = parse_key(&key).with_context(|| format!("key of {}", name))?;
+            Ok((name, member))
+        })
+        .collect()
+}
+
+/// `treasury.toml` as `treasury-<index>.toml`.
+fn indexed_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// Write `file`, TOML unless the extension of `path` is `.json`.
+fn write_config(path: &Path, file: &ConfigFile) -> Result<()> {
+    let content = if path.extension().is_some_and(|ext| ext == "json") {
+        file.to_json()?
+    } else {
+        file.to_toml()?
+    };
+    fs::write(path, content).with_context(|| format!("write {}", path.display()))
+}
+
+fn read_members(prompt: &mut Prompt) -> Result<Vec<Member>> {
+    let mut members: Vec<Member> = Vec::new();
+    loop {
+        let question = format!(
+            "key #{}: public key or pubkey hash in hex, a file, `ledger <path>` or `trezor <path>`{}",
+            members.len(),
+            if members.is_empty() {
+                ""
+            } else {
+                "; empty to finish"
+            }
+        );
+        let member = prompt.ask_with(&question, |line| {
+            if line.is_empty() {
+                if members.is_empty() {
+                    bail!("a config needs at least one key");
+                }
+                return Ok(None);
+            }
+            let member = read_member(line)?;
+            if let Some(index) = members
+                .iter()
+                .position(|other| other.pubkey_hash == member.pubkey_hash)
+            {
+                bail!("the same key as #{}", index);
+            }
+            Ok(Some(member))
+        })?;
+        let mut member = match member {
+            Some(member) => member,
+            None => return Ok(members),
+        };
+        eprintln!("  pubkey hash 0x{}", hex::encode(member.pubkey_hash));
+        let label = prompt.ask_with("label, empty for none", |line| Ok(line.to_string()))?;
+        member.label = Some(label).filter(|label| !label.is_empty());
+        members.push(member);
+    }
+}
+
+/// A key from what was entered: hex, a file, a Ledger or a Trezor path.
+fn read_member(line: &str) -> Result<Member> {
+    if let Some(path) = line.strip_prefix("ledger ") {
+        return ledger_member(path.trim());
+    }
+    if let Some(path) = line.strip_prefix("trezor ") {
+        return trezor_member(path.trim());
+    }
+    if let Ok(member) = parse_key(line) {
+        return Ok(member);
+    }
+    let path = Path::new(line);
+    if !path.exists() {
+        bail!(
+            "`{}` is not a compressed public key, a pubkey hash or a file",
+            line
+        );
+    }
+    file_member(path)
+}
+
+/// A compressed public key or a pubkey hash, in hex.
+fn parse_key(s: &str) -> Result<Member> {
+    if let Ok(pubkey) = parse_hex::<33>(s) {
+        PublicKey::from_slice(&pubkey).with_context(|| format!("invalid public key {}", s))?;
+        return Ok(Member {
+            pubkey_hash: blake160(&pubkey),
+            pubkey: Some(pubkey),
+            label: None,
+        });
+    }
+    Ok(Member {
+        pubkey_hash: parse_hex::<BLAKE160_SIZE>(s)?,
+        pubkey: None,
+        label: None,
+    })
+}
+
+/// A keystore file, or the key in hex on the first line.
+fn file_member(path: &Path) -> Result<Member> {
+    let content = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    if content.trim_start().starts_with('{') {
+        return Ok(Member {
+            pubkey_hash: keystore::load(path)?.identity()?,
+            pubkey: None,
+            label: None,
+        });
+    }
+    parse_key(content.lines().next().unwrap_or_default().trim())
+        .with_context(|| format!("no key in {}", path.display()))
+}
+
+#[cfg(feature = "ledger")]
+fn ledger_member(path: &str) -> Result<Member> {
+    let path: DerivationPath = path.parse()?;
+    let signer = LedgerSigner::new(HidTransport::new()?, path)?;
+    let pubkey = signer.pubkey().serialize();
+    Ok(Member {
+        pubkey_hash: blake160(&pubkey),
+        pubkey: Some(pubkey),
+        label: None,
+    })
+}
+
+#[cfg(not(feature = "ledger"))]
+fn ledger_member(_path: &str) -> Result<Member> {
+    bail!("built without the ledger feature")
+}
+
+#[cfg(feature = "trezor")]
+fn trezor_member(path: &str) -> Result<Member> {
+    let path: DerivationPath = path.parse()?;
+    let signer = TrezorSigner::new(TrezorHidTransport::new()?, path)?;
+    let pubkey = signer.pubkey().serialize();
+    Ok(Member {
+        pubkey_hash: blake160(&pubkey),
+        pubkey: Some(pubkey),
+        label: None,
+    })
+}
+
+#[cfg(not(feature = "trezor"))]
+fn trezor_member(_path: &str) -> Result<Member> {
+    bail!("built without the trezor feature")
+}
+
+/// The keys for the cosigners to compare by voice, in groups of four hex
+/// digits, and what the config requires.
+fn print_fingerprints(config: &MultisigConfig, members: &[Member]) {
+    eprintln!("read every fingerprint back with the owner of the key:");
+    for (i, member) in members.iter().enumerate() {
+        let hex = hex::encode(member.pubkey_hash);
+        let groups: Vec<_> = hex
+            .as_bytes()
+            .chunks(4)
+            .map(String::from_utf8_lossy)
+            .collect();
+        eprintln!(
+            "  #{} {:<12} {}{}",
+            i,
+            member.label.as_deref().unwrap_or("-"),
+            groups.join(" "),
+            if i < usize::from(config.require_first_n()) {
+                ", must sign"
+            } else {
+                ""
+            }
+        );
+    }
+    eprintln!(
+        "{} of {} keys must sign{}",
+        config.threshold(),
+        members.len(),
+        config
+            .since()
+            .map(|since| format!(", {}", format_since(since)))
+            .unwrap_or_default()
+    );
+}
+
+/// Questions answered on stdin, asked again until the answer is valid.
+struct Prompt {
+    lines: io::Lines<io::StdinLock<'static>>,
+}
+
+impl Prompt {
+    fn new() -> Self {
+        Prompt {
+            lines: io::stdin().lock().lines(),
+        }
+    }
+
+    fn ask_with<T, F>(&mut self, question: &str, mut parse: F) -> Result<T>
+    where
+        F: FnMut(&str) -> Result<T>,
+    {
+        loop {
+            eprint!("{}: ", question);
+            let line = match self.lines.next() {
+                Some(line) => line?,
+                None => bail!("the input ended before the config was complete"),
+            };
+            match parse(line.trim()) {
+                Ok(value) => return Ok(value),
+                Err(err) => eprintln!("  {:#}", err),
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! `ckb-multisig estimate-cycles`: what verifying a config costs.
+//!
+//! ```text
+//! ckb-multisig estimate-cycles --binary build/release/ckb-multisig --pubkeys 3 --threshold 2 --inputs 10
+//! ckb-multisig estimate-cycles --binary build/release/ckb-multisig --config config.toml --groups 5
+//! ```
+//!
+//! Builds a transaction of the given shape, signs it with made up keys of a
+//! config like the given one, and runs the binary on it. The cycles of the
+//! contract only depend on the shape: the number of keys, the threshold and
+//! the inputs and witnesses it hashes.
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+use ckb_mock_tx_types::{DummyResourceLoader, MockInfo, MockInput, MockTransaction};
+use ckb_multisig_sdk::{
+    unlock::BoxedSigner, MultisigConfig, MultisigScriptSigner, SecpSigner, Signer,
+};
+use ckb_sdk::types::ScriptGroup;
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, Cycle, ScriptHashType, TransactionBuilder},
+    packed::{CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+};
+use clap::Args;
+use serde_json::json;
+
+use crate::{
+    commands::simulate::{Simulator, MAX_CYCLES},
+    util::{emit, load_config},
+};
+
+#[derive(Args)]
+pub struct EstimateCyclesArgs {
+    /// Contract binary to run, e.g. build/release/ckb-multisig
+    #[arg(long)]
+    binary: PathBuf,
+
+    /// Config file to estimate, instead of its shape
+    #[arg(long, conflicts_with_all = ["pubkeys", "threshold", "require_first_n"])]
+    config: Option<PathBuf>,
+
+    /// Number of member keys
+    #[arg(long, required_unless_present = "config")]
+    pubkeys: Option<u8>,
+
+    #[arg(long, required_unless_present = "config")]
+    threshold: Option<u8>,
+
+    #[arg(long, default_value_t = 0)]
+    require_first_n: u8,
+
+    /// Inputs of each lock group
+    #[arg(long, default_value_t = 1)]
+    inputs: usize,
+
+    /// Lock groups in the transaction, each of another config of the same shape
+    #[arg(long, default_value_t = 1)]
+    groups: usize,
+
+    /// Outputs of the transaction
+    #[arg(long, default_value_t = 2)]
+    outputs: usize,
+}
+
+pub fn run(args: EstimateCyclesArgs) -> Result<()> {
+    if args.inputs == 0 || args.groups == 0 {
+        bail!("a transaction needs at least one group of one input");
+    }
+    let shape = match &args.config {
+        Some(path) => Shape::of(&load_config(path)?),
+        None => Shape {
+            pubkeys: args.pubkeys.expect("required without --config"),
+            require_first_n: args.require_first_n,
+            threshold: args.threshold.expect("required without --config"),
+            since: None,
+        },
+    };
+    let binary =
+        fs::read(&args.binary).with_context(|| format!("read {}", args.binary.display()))?;
+    let simulator = Simulator::new(Bytes::from(binary), MAX_CYCLES);
+    let mock = synthetic_tx(&simulator, &shape, &args)?;
+    let outcomes = simulator.run(&mock, &mut DummyResourceLoader {}, |script| {
+        simulator.runs(script)
+    })?;
+
+    let mut total = 0;
+    for outcome in &outcomes {
+        match &outcome.result {
+            Ok(cycles) => total += cycles,
+            Err(err) => bail!("the binary rejects the synthetic transaction: {}", err),
+        }
+    }
+    let per_group = total / outcomes.len() as Cycle;
+    let max_block_cycles = simulator.max_block_cycles();
+    let value = json!({
+        "inputs": args.inputs,
+        "threshold": shape.threshold,
+        "pubkeys": shape.pubkeys,
+        "group_cycles": per_group,
+        "groups": outcomes.len(),
+        "tx_cycles": total,
+        "max_tx_cycles": MAX_CYCLES,
+        "max_block_cycles": max_block_cycles,
+    });
+    emit(value, || {
+        println!(
+            "group of {} inputs, {} of {} keys: {} cycles",
+            args.inputs, shape.threshold, shape.pubkeys, per_group
+        );
+        println!(
+            "transaction of {} groups: {} cycles, {:.2}% of the {} cycles limit of a transaction",
+            outcomes.len(),
+            total,
+            total as f64 * 100.0 / MAX_CYCLES as f64,
+            MAX_CYCLES
+        );
+        println!(
+            "block: {} such transactions, {} groups, in the {} cycles limit",
+            max_block_cycles / total,
+            max_block_cycles / per_group,
+            max_block_cycles
+        );
+    });
+    if total > MAX_CYCLES {
+        bail!("the transaction exceeds the cycles limit, split it");
+    }
+    Ok(())
+}
+
+/// A signed transaction of the shape, with the cells it spends.
+fn synthetic_tx(
+    simulator: &Simulator,
+    shape: &Shape,
+    args: &EstimateCyclesArgs,
+) -> Result<MockTransaction> {
+    let code_hash = simulator.code_hash().unpack();
+    let since = shape.since.unwrap_or(0);
+    let mut builder = TransactionBuilder::default();
+    let mut inputs = Vec::new();
+    let mut groups = Vec::new();
+    for group in 0..args.groups {
+        let (config, signers) = synthetic_config(shape, group)?;
+        let lock = config.lock_script(&code_hash, ScriptHashType::Data1);
+        let mut script_group = ScriptGroup::from_lock_script(&lock);
+        for _ in 0..args.inputs {
+            let index = inputs.len();
+            let input = CellInput::new(OutPoint::new(Default::default(), index as u32), since);
+            builder = builder.input(input.clone());
+            inputs.push(MockInput {
+                input,
+                output: cell(&lock),
+                data: Bytes::new(),
+                header: None,
+            });
+            script_group.input_indices.push(index);
+        }
+        groups.push((MultisigScriptSigner::new(config, signers), script_group));
+    }
+    let lock = inputs[0].output.lock();
+    for _ in 0..args.outputs {
+        builder = builder.output(cell(&lock)).output_data(Bytes::new().pack());
+    }
+
+    let mut tx = builder.build();
+    for (signer, script_group) in &groups {
+        tx = signer.sign(&tx, script_group)?;
+    }
+    Ok(MockTransaction {
+        mock_info: MockInfo {
+            inputs,
+            ..Default::default()
+        },
+        tx: tx.data(),
+    })
+}
+
+/// What the cycles of a config depend on.
+struct Shape {
+    pubkeys: u8,
+    require_first_n: u8,
+    threshold: u8,
+    since: Option<u64>,
+}
+
+impl Shape {
+    fn of(config: &MultisigConfig) -> Self {
+        Shape {
+            pubkeys: config.pubkey_hashes().len() as u8,
+            require_first_n: config.require_first_n(),
+            threshold: config.threshold(),
+            since: config.since(),
+        }
+    }
+}
+
+/// A config of the shape, with the keys to reach its threshold. The keys are
+/// made up, different for each group.
+fn synthetic_config(shape: &Shape, group: usize) -> Result<(MultisigConfig, Vec<BoxedSigner>)> {
+    let count = usize::from(shape.pubkeys);
+    let mut signers = Vec::new();
+    let mut hashes = Vec::new();
+    for i in 0..count {
+        let mut key = [0u8; 32];
+        key[24..].copy_from_slice(&((group * count + i + 1) as u64).to_be_bytes());
+        let signer = SecpSigner::from_slice(&key)?;
+        hashes.push(signer.identity()?);
+        if i < usize::from(shape.threshold) {
+            signers.push(Box::new(signer) as BoxedSigner);
+        }
+    }
+    let config = MultisigConfig::new(hashes, shape.require_first_n, shape.threshold)?
+        .with_since(shape.since);
+    Ok((config, signers))
+}
+
+fn cell(lock: &Script) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(Capacity::bytes(1000).expect("capacity").pack())
+        .lock(lock.clone())
+        .build()
+}
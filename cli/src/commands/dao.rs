@@ -0,0 +1,523 @@
+//! `ckb-multisig dao`: earn the Nervos DAO compensation on the capacity of a
+//! config.
+//!
+//! ```text
+//! ckb-multisig dao list --config treasury.toml ...
+//! ckb-multisig dao deposit --config treasury.toml --amount 100000 --output deposit.json ...
+//! ckb-multisig dao withdraw --config treasury.toml --output withdraw.json ...
+//! ckb-multisig dao claim --config treasury.toml --output claim.json ...
+//! ```
+//!
+//! `withdraw` is phase 1 of the DAO withdraw, `claim` phase 2 once the lock
+//! period is over. Each writes a proposal, signed and sent as any other with
+//! `sign` and `send`, see `dao.rs` of the SDK for the builders. The deposit
+//! and phase 1 fees are paid with plain cells of the config, the change going
+//! back to it, the phase 2 fee out of the capacity claimed.
+
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use ckb_jsonrpc_types as json;
+use ckb_multisig_sdk::{
+    backend::ChainBackend,
+    batch::parse_ckb,
+    collector::MaturedCellCollector,
+    dao::{dao_type_script, DaoCell, DaoPhase, MultisigDao},
+    providers::{BackendCellCollector, BackendProvider},
+    request::SigningRequest,
+    scanner::{Scanner, TypeFilter},
+    since::format_since,
+    MultisigConfig,
+};
+use ckb_sdk::{
+    traits::{DefaultCellDepResolver, TransactionDependencyProvider},
+    tx_builder::{
+        balance_tx_capacity, gen_script_groups, CapacityBalancer, SinceSource, TxBuilder,
+    },
+    Address, ScriptId,
+};
+use ckb_types::{
+    core::{EpochNumberWithFraction, TransactionView},
+    packed::{OutPoint, Script, WitnessArgs},
+    prelude::*,
+};
+use clap::{Args, Subcommand};
+use serde_json::{json, Value};
+
+use crate::util::{
+    emit, format_ckb, load_config, parse_address, parse_h256, save_request, Chain, ChainArgs,
+};
+
+/// The since flags of an absolute epoch, to print epochs as a since.
+const EPOCH_SINCE: u64 = 0x2000_0000_0000_0000;
+
+#[derive(Subcommand)]
+pub enum DaoCommand {
+    /// DAO cells of a config, their compensation and when they can be claimed
+    List(ListArgs),
+    /// Propose to deposit capacity of a config into the DAO
+    Deposit(DepositArgs),
+    /// Propose phase 1 of the withdraw of deposits
+    Withdraw(WithdrawArgs),
+    /// Propose phase 2, claiming the capacity and compensation of withdrawn cells
+    Claim(ClaimArgs),
+}
+
+#[derive(Args)]
+pub struct ListArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Config file of the lock
+    #[arg(long)]
+    config: PathBuf,
+}
+
+#[derive(Args)]
+pub struct DepositArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Config file of the lock
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Amount in CKB of a deposit cell, can be repeated
+    #[arg(long = "amount", value_parser = parse_ckb, required = true)]
+    amounts: Vec<u64>,
+
+    /// Fee rate in shannons per 1000 bytes
+    #[arg(long, default_value_t = 1000)]
+    fee_rate: u64,
+
+    /// Proposal file to write
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct WithdrawArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Config file of the lock
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Deposit to withdraw as `tx_hash:index`, can be repeated, every
+    /// deposit by default
+    #[arg(long = "out-point", value_parser = parse_out_point)]
+    out_points: Vec<OutPoint>,
+
+    /// Fee rate in shannons per 1000 bytes
+    #[arg(long, default_value_t = 1000)]
+    fee_rate: u64,
+
+    /// Proposal file to write
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct ClaimArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Config file of the lock
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Withdrawn cell to claim as `tx_hash:index`, can be repeated, every
+    /// cell claimable at the tip by default
+    #[arg(long = "out-point", value_parser = parse_out_point)]
+    out_points: Vec<OutPoint>,
+
+    /// Address the claimed capacity goes to, the config itself by default
+    #[arg(long, value_parser = parse_address)]
+    to: Option<Address>,
+
+    /// Fee rate in shannons per 1000 bytes
+    #[arg(long, default_value_t = 1000)]
+    fee_rate: u64,
+
+    /// Proposal file to write
+    #[arg(long)]
+    output: PathBuf,
+}
+
+pub fn run(command: DaoCommand) -> Result<()> {
+    match command {
+        DaoCommand::List(args) => list(args),
+        DaoCommand::Deposit(args) => deposit(args),
+        DaoCommand::Withdraw(args) => withdraw(args),
+        DaoCommand::Claim(args) => claim(args),
+    }
+}
+
+fn list(args: ListArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    let chain = args.chain.resolve()?;
+    let (cells, tip) = dao_cells(&chain, &config)?;
+    for cell in &cells {
+        emit(cell_value(cell, tip), || {
+            println!("{}", cell_line(cell, tip))
+        });
+    }
+    let total = |phase| {
+        cells
+            .iter()
+            .filter(|cell| cell.phase == phase)
+            .map(|cell| cell.capacity() + cell.compensation)
+            .sum::<u64>()
+    };
+    let value = json!({
+        "kind": "summary",
+        "cells": cells.len(),
+        "deposited": total(DaoPhase::Deposited),
+        "withdrawing": total(DaoPhase::Prepared),
+    });
+    emit(value, || {
+        println!(
+            "{} cells, {} deposited and {} withdrawing, compensation included",
+            cells.len(),
+            format_ckb(total(DaoPhase::Deposited)),
+            format_ckb(total(DaoPhase::Prepared))
+        )
+    });
+    Ok(())
+}
+
+fn deposit(args: DepositArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    let chain = args.chain.resolve()?;
+    let dao = MultisigDao::new(config, &chain.code_hash, chain.hash_type);
+    let request = propose(
+        &chain,
+        &dao,
+        &dao.deposit(&args.amounts),
+        Some(args.fee_rate),
+        0,
+    )?;
+    save_request(&args.output, &request)?;
+    let amount = args.amounts.iter().sum();
+    let value = json!({
+        "output": args.output,
+        "tx_hash": format!("{:#x}", request.tx.hash()),
+        "deposit": amount,
+        "cells": args.amounts.len(),
+        "fee": request.fee,
+    });
+    emit(value, || {
+        println!("{}: {:#x}", args.output.display(), request.tx.hash());
+        println!(
+            "  deposit {} in {} cells, fee {}",
+            format_ckb(amount),
+            args.amounts.len(),
+            format_ckb(request.fee)
+        );
+    });
+    Ok(())
+}
+
+fn withdraw(args: WithdrawArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    let chain = args.chain.resolve()?;
+    let (cells, tip) = dao_cells(&chain, &config)?;
+    let cells = select(cells, &args.out_points, DaoPhase::Deposited, |_| true)?;
+    let dao = MultisigDao::new(config, &chain.code_hash, chain.hash_type);
+    let out_points = cells
+        .iter()
+        .map(|cell| cell.cell.out_point.clone())
+        .collect();
+    let request = propose(
+        &chain,
+        &dao,
+        &dao.prepare(out_points),
+        Some(args.fee_rate),
+        0,
+    )?;
+    save_request(&args.output, &request)?;
+    let value = json!({
+        "output": args.output,
+        "tx_hash": format!("{:#x}", request.tx.hash()),
+        "cells": cells.iter().map(|cell| cell_value(cell, tip)).collect::<Vec<_>>(),
+        "fee": request.fee,
+    });
+    emit(value, || {
+        println!("{}: {:#x}", args.output.display(), request.tx.hash());
+        for cell in &cells {
+            println!("{}", cell_line(cell, tip));
+        }
+        println!("  fee {}", format_ckb(request.fee));
+    });
+    Ok(())
+}
+
+fn claim(args: ClaimArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    let chain = args.chain.resolve()?;
+    let (cells, tip) = dao_cells(&chain, &config)?;
+    let cells = select(cells, &args.out_points, DaoPhase::Prepared, |cell| {
+        cell.is_claimable(tip)
+    })?;
+    for cell in &cells {
+        if !cell.is_claimable(tip) {
+            eprintln!(
+                "warning: {} can't be claimed before {}",
+                cell.cell.out_point,
+                epoch(cell.claimable_epoch)
+            );
+        }
+    }
+    let dao = MultisigDao::new(config, &chain.code_hash, chain.hash_type);
+    let out_points = cells
+        .iter()
+        .map(|cell| cell.cell.out_point.clone())
+        .collect();
+    let receiver = args.to.as_ref().map(Script::from);
+    let builder = dao.withdraw(out_points, receiver, Some(args.fee_rate))?;
+    let compensation = cells.iter().map(|cell| cell.compensation).sum();
+    let request = propose(&chain, &dao, &builder, None, compensation)?;
+    save_request(&args.output, &request)?;
+    let claimed = cells.iter().map(DaoCell::capacity).sum::<u64>() + compensation;
+    let value = json!({
+        "output": args.output,
+        "tx_hash": format!("{:#x}", request.tx.hash()),
+        "claim": claimed,
+        "cells": cells.len(),
+        "compensation": compensation,
+        "fee": request.fee,
+    });
+    emit(value, || {
+        println!("{}: {:#x}", args.output.display(), request.tx.hash());
+        println!(
+            "  claim {} of {} cells, {} compensation, fee {}",
+            format_ckb(claimed),
+            cells.len(),
+            format_ckb(compensation),
+            format_ckb(request.fee)
+        );
+    });
+    Ok(())
+}
+
+/// The DAO cells of the config, and the epoch of the tip.
+fn dao_cells(
+    chain: &Chain,
+    config: &MultisigConfig,
+) -> Result<(Vec<DaoCell>, EpochNumberWithFraction)> {
+    let scanner = Scanner::with_backend(chain.connect(), config, &chain.code_hash, chain.hash_type)
+        .with_filter(TypeFilter::Script(dao_type_script()));
+    let set = scanner.scan()?;
+    let live = set.cells.into_iter().map(|cell| cell.cell).collect();
+    let dao = MultisigDao::new(config.clone(), &chain.code_hash, chain.hash_type);
+    let cells = dao.cells(&*chain.connect(), live)?;
+    Ok((cells, set.tip.epoch))
+}
+
+/// The cells of `out_points`, which must be in `phase`, or without any every
+/// cell in `phase` matching `default`.
+fn select<F>(
+    cells: Vec<DaoCell>,
+    out_points: &[OutPoint],
+    phase: DaoPhase,
+    default: F,
+) -> Result<Vec<DaoCell>>
+where
+    F: Fn(&DaoCell) -> bool,
+{
+    let selected: Vec<_> = if out_points.is_empty() {
+        cells
+            .into_iter()
+            .filter(|cell| cell.phase == phase && default(cell))
+            .collect()
+    } else {
+        let mut selected = Vec::new();
+        for out_point in out_points {
+            match cells.iter().find(|cell| cell.cell.out_point == *out_point) {
+                Some(cell) if cell.phase == phase => selected.push(cell.clone()),
+                Some(_) => bail!("{} is not {}", out_point, phase_name(phase)),
+                None => bail!("{} is not a DAO cell of the config", out_point),
+            }
+        }
+        selected
+    };
+    if selected.is_empty() {
+        bail!("no DAO cell to spend");
+    }
+    Ok(selected)
+}
+
+/// The proposal of the transaction of `builder`, balanced with the plain
+/// cells of the config when `fee_rate` is given. `compensation` is the
+/// capacity the DAO inputs yield on top of their own.
+fn propose(
+    chain: &Chain,
+    dao: &MultisigDao,
+    builder: &dyn TxBuilder,
+    fee_rate: Option<u64>,
+    compensation: u64,
+) -> Result<SigningRequest> {
+    let cell_deps = chain.cell_deps()?;
+    let backend: Arc<dyn ChainBackend> = chain.connect().into();
+    let genesis = backend.genesis()?;
+    let mut cell_dep_resolver = DefaultCellDepResolver::from_genesis(&genesis)?;
+    cell_dep_resolver.insert(
+        ScriptId::from(dao.lock_script()),
+        cell_deps[0].clone(),
+        "ckb-multisig".to_string(),
+    );
+    let provider = BackendProvider::new(backend.clone());
+    let mut cell_collector = MaturedCellCollector::new(
+        BackendCellCollector::new(backend.clone()),
+        BackendProvider::new(backend),
+        &chain.code_hash,
+        chain.hash_type,
+    );
+
+    let tx = builder.build_base(
+        &mut cell_collector,
+        &cell_dep_resolver,
+        &provider,
+        &provider,
+    )?;
+    // every cell dep of the lock, before the fee is computed
+    let mut deps: Vec<_> = tx.cell_deps().into_iter().collect();
+    for dep in cell_deps {
+        if !deps.contains(&dep) {
+            deps.push(dep);
+        }
+    }
+    let mut tx = tx.as_advanced_builder().set_cell_deps(deps).build();
+    if let Some(fee_rate) = fee_rate {
+        let placeholder = WitnessArgs::new_builder()
+            .lock(Some(dao.config().placeholder_lock()).pack())
+            .build();
+        tx = with_placeholder(&tx, dao.lock_script(), &placeholder, &provider)?;
+        let balancer = CapacityBalancer::new_simple_with_since(
+            dao.lock_script().clone(),
+            placeholder,
+            SinceSource::Value(dao.config().since().unwrap_or(0)),
+            fee_rate,
+        );
+        tx = balance_tx_capacity(
+            &tx,
+            &balancer,
+            &mut cell_collector,
+            &provider,
+            &cell_dep_resolver,
+            &provider,
+        )?;
+    }
+    let tx = dao.apply_since(&tx, &provider)?;
+
+    let groups = gen_script_groups(&tx, &provider)?;
+    let script_group = groups
+        .lock_groups
+        .get(&dao.lock_script().calc_script_hash())
+        .context("the transaction spends no cell of the config")?
+        .clone();
+    let mut inputs = compensation;
+    for input in tx.inputs() {
+        let cell = provider.get_cell(&input.previous_output())?;
+        inputs += Unpack::<u64>::unpack(&cell.capacity());
+    }
+    let outputs: u64 = tx
+        .outputs()
+        .into_iter()
+        .map(|output| Unpack::<u64>::unpack(&output.capacity()))
+        .sum();
+    Ok(SigningRequest {
+        config: dao.config().clone(),
+        tx,
+        script_group,
+        fee: inputs - outputs,
+        genesis_hash: Some(genesis.hash().unpack()),
+    })
+}
+
+/// `tx` with the placeholder lock in the witness of its first input of
+/// `lock_script`, if any, e.g. the deposits of phase 1, so that the fee
+/// accounts for the signatures.
+fn with_placeholder(
+    tx: &TransactionView,
+    lock_script: &Script,
+    placeholder: &WitnessArgs,
+    tx_dep_provider: &dyn TransactionDependencyProvider,
+) -> Result<TransactionView> {
+    for (i, input) in tx.inputs().into_iter().enumerate() {
+        let cell = tx_dep_provider.get_cell(&input.previous_output())?;
+        if cell.lock() != *lock_script {
+            continue;
+        }
+        let mut witnesses: Vec<_> = tx.witnesses().into_iter().collect();
+        witnesses.resize(witnesses.len().max(i + 1), Default::default());
+        let witness = match witnesses[i].raw_data() {
+            data if data.is_empty() => WitnessArgs::default(),
+            data => WitnessArgs::from_slice(&data).context("invalid witness")?,
+        };
+        witnesses[i] = witness
+            .as_builder()
+            .lock(placeholder.lock())
+            .build()
+            .as_bytes()
+            .pack();
+        return Ok(tx.as_advanced_builder().set_witnesses(witnesses).build());
+    }
+    Ok(tx.clone())
+}
+
+fn cell_value(cell: &DaoCell, tip: EpochNumberWithFraction) -> Value {
+    let claimable = cell.claimable_epoch;
+    json!({
+        "kind": "cell",
+        "out_point": json::OutPoint::from(cell.cell.out_point.clone()),
+        "phase": phase_name(cell.phase),
+        "capacity": cell.capacity(),
+        "compensation": cell.compensation,
+        "claimable_epoch": {
+            "number": claimable.number(),
+            "index": claimable.index(),
+            "length": claimable.length(),
+        },
+        "claimable_now": cell.phase == DaoPhase::Prepared && cell.is_claimable(tip),
+    })
+}
+
+fn cell_line(cell: &DaoCell, tip: EpochNumberWithFraction) -> String {
+    let claim = match cell.phase {
+        DaoPhase::Deposited => {
+            format!("claimable {} if withdrawn now", epoch(cell.claimable_epoch))
+        }
+        DaoPhase::Prepared if cell.is_claimable(tip) => "claimable now".to_string(),
+        DaoPhase::Prepared => format!("claimable {}", epoch(cell.claimable_epoch)),
+    };
+    format!(
+        "{} {} {}, compensation {}, {}",
+        cell.cell.out_point,
+        phase_name(cell.phase),
+        format_ckb(cell.capacity()),
+        format_ckb(cell.compensation),
+        claim
+    )
+}
+
+fn phase_name(phase: DaoPhase) -> &'static str {
+    match phase {
+        DaoPhase::Deposited => "deposited",
+        DaoPhase::Prepared => "withdrawing",
+    }
+}
+
+fn epoch(epoch: EpochNumberWithFraction) -> String {
+    format_since(EPOCH_SINCE | epoch.full_value())
+}
+
+/// `tx_hash:index`.
+fn parse_out_point(s: &str) -> Result<OutPoint> {
+    let (tx_hash, index) = s
+        .split_once(':')
+        .with_context(|| format!("expected `tx_hash:index`, got `{}`", s))?;
+    let tx_hash = parse_h256(tx_hash)?;
+    Ok(OutPoint::new(tx_hash.pack(), index.parse()?))
+}
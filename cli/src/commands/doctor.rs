@@ -0,0 +1,292 @@
+//! `ckb-multisig doctor`: what stands between this machine and a spend.
+//!
+//! ```text
+//! ckb-multisig doctor --deployments deployments.toml --network mainnet --config treasury.toml
+//! ```
+//!
+//! Checks, in turn, that the node answers and is on the network given, that
+//! its indexer is enabled and synced, that the deployment resolves, its cell
+//! deps are live and hold the code of the code hash, and that the keystore
+//! holds a key of each config, marked as its member. The configs are those
+//! given and those stored in the keystore. Each failure comes with its fix:
+//!
+//! ```text
+//! ok   node: http://127.0.0.1:8114 at block 12408311
+//! FAIL indexer: not enabled on the node
+//!      fix: add "Indexer" to the modules of the [rpc] section of ckb.toml and restart the node
+//! ```
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use ckb_multisig_sdk::{deploy::find_code, deployment::Network, MultisigConfig};
+use ckb_sdk::CkbRpcClient;
+use clap::Args;
+use serde_json::json;
+
+use crate::{
+    keystore::KeyFile,
+    util::{emit, load_config, ChainArgs, KeystoreArgs},
+};
+
+/// Blocks the indexer may lag behind the node before its balances are
+/// stale.
+const INDEXER_LAG: u64 = 100;
+
+#[derive(Args)]
+pub struct DoctorArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Config file the keystore should hold a key of, can be repeated
+    #[arg(long = "config")]
+    configs: Vec<PathBuf>,
+
+    #[command(flatten)]
+    keystore: KeystoreArgs,
+}
+
+#[derive(Default)]
+struct Doctor {
+    checks: usize,
+    failed: usize,
+}
+
+impl Doctor {
+    fn pass(&mut self, check: &str, detail: String) {
+        self.checks += 1;
+        let value = json!({ "kind": "check", "check": check, "passed": true, "detail": detail });
+        emit(value, || println!("ok   {}: {}", check, detail));
+    }
+
+    fn fail(&mut self, check: &str, detail: String, fix: &str) {
+        self.checks += 1;
+        self.failed += 1;
+        let value = json!({
+            "kind": "check",
+            "check": check,
+            "passed": false,
+            "detail": detail,
+            "fix": fix,
+        });
+        emit(value, || {
+            println!("FAIL {}: {}", check, detail);
+            println!("     fix: {}", fix);
+        });
+    }
+}
+
+pub fn run(args: DoctorArgs) -> Result<()> {
+    let mut doctor = Doctor::default();
+    let rpc = &args.chain.rpc;
+    let client = CkbRpcClient::new(rpc);
+    let tip = match client.get_tip_block_number() {
+        Ok(tip) => {
+            let tip = tip.value();
+            doctor.pass("node", format!("{} at block {}", rpc, tip));
+            Some(tip)
+        }
+        Err(err) => {
+            doctor.fail(
+                "node",
+                format!("{} unreachable: {}", rpc, err),
+                "start the node, or pass the RPC of a running one with --rpc",
+            );
+            None
+        }
+    };
+
+    if let Some(tip) = tip {
+        if let Some(network) = args.chain.network {
+            check_network(&mut doctor, &client, network);
+        }
+        check_indexer(&mut doctor, &client, tip);
+        match args.chain.resolve() {
+            Ok(chain) if chain.cell_deps.is_empty() => doctor.fail(
+                "deployment",
+                format!("code hash {:#x} without any cell dep", chain.code_hash),
+                "pass --cell-dep, or --deployments and --network for those of the registry",
+            ),
+            Ok(chain) => {
+                match find_code(&client, &chain.cell_deps, &chain.code_hash, chain.hash_type) {
+                    Ok(out_point) => doctor.pass(
+                        "deployment",
+                        format!("code hash {:#x} in cell {}", chain.code_hash, out_point),
+                    ),
+                    Err(err) => doctor.fail(
+                        "deployment",
+                        err.to_string(),
+                        "check the registry entry of the network, or the --cell-dep and \
+                         --code-hash given, against the deployment on this network",
+                    ),
+                }
+            }
+            Err(err) => doctor.fail(
+                "deployment",
+                format!("{:#}", err),
+                "pass --deployments and --network, or --code-hash and --cell-dep",
+            ),
+        }
+    }
+
+    check_keystore(&mut doctor, &args)?;
+    if doctor.failed > 0 {
+        bail!("{} of {} checks failed", doctor.failed, doctor.checks);
+    }
+    let value = json!({ "kind": "result", "checks": doctor.checks });
+    emit(value, || println!("all {} checks passed", doctor.checks));
+    Ok(())
+}
+
+fn check_network(doctor: &mut Doctor, client: &CkbRpcClient, network: Network) {
+    let chain = match client.get_blockchain_info() {
+        Ok(info) => info.chain,
+        Err(err) => {
+            doctor.fail(
+                "network",
+                format!("no blockchain info: {}", err),
+                "check the node logs",
+            );
+            return;
+        }
+    };
+    let on = match chain.as_str() {
+        "ckb" => Network::Mainnet,
+        "ckb_testnet" => Network::Testnet,
+        _ => Network::Devnet,
+    };
+    if on == network {
+        doctor.pass(
+            "network",
+            format!("the node is on {}, chain {}", network, chain),
+        );
+    } else {
+        doctor.fail(
+            "network",
+            format!("the node is on {}, chain {}, not {}", on, chain, network),
+            &format!("pass the RPC of a {} node, or --network {}", network, on),
+        );
+    }
+}
+
+fn check_indexer(doctor: &mut Doctor, client: &CkbRpcClient, tip: u64) {
+    let fix =
+        "add \"Indexer\" to the modules of the [rpc] section of ckb.toml and restart the node";
+    match client.get_indexer_tip() {
+        Ok(Some(indexer)) => {
+            let indexed = indexer.block_number.value();
+            let lag = tip.saturating_sub(indexed);
+            if lag > INDEXER_LAG {
+                doctor.fail(
+                    "indexer",
+                    format!("at block {}, {} blocks behind", indexed, lag),
+                    "wait for the indexer to catch up, the balances and cells it gives are stale",
+                );
+            } else {
+                doctor.pass("indexer", format!("at block {}", indexed));
+            }
+        }
+        Ok(None) => doctor.fail("indexer", "no block indexed yet".to_string(), fix),
+        Err(err) => doctor.fail("indexer", format!("not enabled on the node: {}", err), fix),
+    }
+}
+
+fn check_keystore(doctor: &mut Doctor, args: &DoctorArgs) -> Result<()> {
+    let keystore = args.keystore.open()?;
+    let keys = keystore.list()?;
+    let mut configs = Vec::new();
+    for path in &args.configs {
+        let name = path.display().to_string();
+        configs.push((name.clone(), name, load_config(path)?));
+    }
+    for stored in keystore.configs()? {
+        let file = stored.path.display().to_string();
+        configs.push((stored.name.clone(), file, stored.config()?));
+    }
+    if configs.is_empty() {
+        if keys.is_empty() {
+            doctor.fail(
+                "keystore",
+                "no key and no config".to_string(),
+                "add your key with `ckb-multisig keystore new` or `keystore import`, then its \
+                 config with `keystore add-config`",
+            );
+        } else {
+            doctor.pass(
+                "keystore",
+                format!("{} keys, no config to check", keys.len()),
+            );
+        }
+        return Ok(());
+    }
+    for (name, file, config) in &configs {
+        check_config(doctor, name, file, config, keys.iter().map(|(_, key)| key));
+    }
+    Ok(())
+}
+
+fn check_config<'a>(
+    doctor: &mut Doctor,
+    name: &str,
+    file: &str,
+    config: &MultisigConfig,
+    keys: impl Iterator<Item = &'a KeyFile>,
+) {
+    let check = format!("keys of {}", name);
+    let mut members = Vec::new();
+    let mut unmarked = Vec::new();
+    for key in keys {
+        let is_member = key
+            .identity()
+            .is_ok_and(|identity| config.position(&identity).is_some());
+        if is_member {
+            members.push(key.name());
+            if !key.is_member(config) {
+                unmarked.push(key.name());
+            }
+        } else if key.is_member(config) {
+            doctor.fail(
+                &check,
+                format!("{} is marked as a member but is not one", key.name()),
+                &format!(
+                    "unmark it with `ckb-multisig keystore mark --key {} --config {} --unmark`",
+                    key.name(),
+                    file
+                ),
+            );
+        }
+    }
+    if members.is_empty() {
+        doctor.fail(
+            &check,
+            format!(
+                "no key of the keystore among the {} members",
+                config.pubkey_hashes().len()
+            ),
+            "import the key of this machine with `ckb-multisig keystore import`, or pass the \
+             keystore holding it with --keystore",
+        );
+        return;
+    }
+    for key in &unmarked {
+        doctor.fail(
+            &check,
+            format!("{} is a member but not marked, `--marked` skips it", key),
+            &format!(
+                "mark it with `ckb-multisig keystore mark --key {} --config {}`",
+                key, file
+            ),
+        );
+    }
+    if unmarked.is_empty() {
+        doctor.pass(
+            &check,
+            format!(
+                "{} of the {} members, {} to sign",
+                members.join(", "),
+                config.pubkey_hashes().len(),
+                config.threshold()
+            ),
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! `ckb-multisig history`: the transactions of a config, oldest first.
+//!
+//! ```text
+//! ckb-multisig history --config treasury.toml --proposals proposals/ --deployments ...
+//! ```
+//!
+//! One line per transaction touching the config lock, from the indexer:
+//!
+//! ```text
+//! block 1204518 0x3c1a... in  12,000 CKB from ckb1...
+//! block 1210007 0x9f02... out 344.9 CKB to ckb1...; approved by alice, bob
+//! ```
+//!
+//! The cosigners who approved a spend come from the proposals given, files
+//! or directories of proposal files such as the `--dir` of the coordination
+//! server, the members named after their labels in the config file. The
+//! addresses are of the network of `--network`, mainnet by default.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Result;
+use ckb_multisig_sdk::{
+    cobuild::format_capacity,
+    constants::BLAKE160_SIZE,
+    deployment::Network,
+    history::{Direction, History},
+    request::SigningRequest,
+};
+use ckb_sdk::{Address, AddressPayload, NetworkType};
+use ckb_types::{packed::Script, prelude::*, H256};
+use clap::Args;
+use serde_json::json;
+
+use crate::util::{emit, load_config_file, load_proposals, ChainArgs};
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Config file, its members named after their labels
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Proposal file, or directory of proposal files, the approvals of the
+    /// spends are read from, can be repeated
+    #[arg(long = "proposals")]
+    proposals: Vec<PathBuf>,
+}
+
+pub fn run(args: HistoryArgs) -> Result<()> {
+    let chain = args.chain.resolve()?;
+    let file = load_config_file(&args.config)?;
+    let config = file.to_config()?;
+    let labels = file.labels();
+    let member = |identity: &[u8; BLAKE160_SIZE]| {
+        let label = config
+            .position(identity)
+            .and_then(|position| labels[position]);
+        label
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("0x{}", hex::encode(identity)))
+    };
+    let network = match args.chain.network {
+        Some(Network::Mainnet) | None => NetworkType::Mainnet,
+        Some(_) => NetworkType::Testnet,
+    };
+    let proposals: BTreeMap<H256, SigningRequest> = load_proposals(&args.proposals)?
+        .into_iter()
+        .map(|request| (request.tx.hash().unpack(), request))
+        .collect();
+
+    let history =
+        History::with_backend(chain.connect(), &config, &chain.code_hash, chain.hash_type);
+    for entry in history.entries()? {
+        let counterparties: Vec<_> = entry
+            .counterparties
+            .iter()
+            .map(|lock| address(network, lock))
+            .collect();
+        let approvals = match proposals.get(&entry.tx_hash) {
+            Some(request) if entry.direction == Direction::Out => {
+                Some(request.signed()?.iter().map(&member).collect::<Vec<_>>())
+            }
+            _ => None,
+        };
+        let (direction, preposition) = match entry.direction {
+            Direction::In => ("in ", "from"),
+            Direction::Out => ("out", "to"),
+        };
+        let value = json!({
+            "block_number": entry.block_number,
+            "tx_hash": format!("{:#x}", entry.tx_hash),
+            "direction": direction.trim_end(),
+            "amount": entry.amount,
+            "counterparties": counterparties,
+            "approved_by": approvals,
+        });
+        let mut line = format!(
+            "block {} {:#x} {} {}",
+            entry.block_number,
+            entry.tx_hash,
+            direction,
+            format_capacity(entry.amount)
+        );
+        if !counterparties.is_empty() {
+            line.push_str(&format!(" {} {}", preposition, counterparties.join(", ")));
+        }
+        if entry.direction == Direction::Out {
+            match &approvals {
+                Some(signed) => line.push_str(&format!("; approved by {}", signed.join(", "))),
+                None => line.push_str("; no proposal"),
+            }
+        }
+        emit(value, || println!("{}", line));
+    }
+    Ok(())
+}
+
+fn address(network: NetworkType, lock: &Script) -> String {
+    Address::new(network, AddressPayload::from(lock.clone()), true).to_string()
+}
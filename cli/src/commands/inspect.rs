@@ -0,0 +1,234 @@
+//! `ckb-multisig inspect-tx`: what a proposal does, in plain words.
+//!
+//! ```text
+//! ckb-multisig inspect-tx --request proposal.json --config treasury.toml
+//! ```
+//!
+//! For the cosigners to read before they sign:
+//!
+//! ```text
+//! spend 12,345 CKB from treasury: 12,000 CKB to ckb1..., 344.9 CKB change back, 0.1 CKB fee
+//! requires 3 of 5, including alice; signed so far: alice, bob
+//! ```
+//!
+//! The config file is optional, the treasury is named after it and the
+//! members after their labels. Without it, the address and the pubkey hashes
+//! are shown instead. With `--policy`, the rules of the policy the proposal
+//! breaks are listed too, those `sign --policy` would refuse it for.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use ckb_multisig_sdk::{
+    cobuild::format_capacity, config::MultisigConfig, config_file::ConfigFile,
+    constants::BLAKE160_SIZE, request::SigningRequest, since::format_since,
+};
+use ckb_sdk::{Address, AddressPayload, NetworkType};
+use ckb_types::{packed::Script, prelude::*};
+use clap::Args;
+use serde_json::{json, Value};
+
+use crate::util::{emit, load_config_file, load_request, parse_network, PolicyArgs};
+
+#[derive(Args)]
+pub struct InspectTxArgs {
+    /// Proposal file
+    #[arg(long)]
+    request: PathBuf,
+
+    /// Config file of the proposal, for its name and the labels of the members
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// mainnet or testnet, the network of the addresses
+    #[arg(long, default_value = "mainnet", value_parser = parse_network)]
+    network: NetworkType,
+
+    #[command(flatten)]
+    policy: PolicyArgs,
+}
+
+pub fn run(args: InspectTxArgs) -> Result<()> {
+    let request = load_request(&args.request)?;
+    let config = &request.config;
+    let names = match &args.config {
+        Some(path) => Names::from_file(path, &load_config_file(path)?, config)?
+            .ok_or_else(|| anyhow!("{} is not the config of the proposal", path.display()))?,
+        None => Names::none(config),
+    };
+    let summary = Summary::new(&request, args.network, &names)?;
+    let violations: Vec<_> = match args.policy.load()? {
+        Some(policy) => policy
+            .violations(&request, &[])?
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let value = json!({
+        "from": summary.from,
+        "total": summary.total,
+        "payments": summary.payments,
+        "change": summary.change,
+        "fee": request.fee,
+        "threshold": config.threshold(),
+        "members": config.pubkey_hashes().len(),
+        "required": summary.required,
+        "since": config.since().map(format_since),
+        "signed": summary.signed,
+        "violations": violations,
+    });
+    emit(value, || {
+        println!("{}", summary.spend());
+        let signed = if summary.signed.is_empty() {
+            "none".to_string()
+        } else {
+            summary.signed.join(", ")
+        };
+        println!("{}; signed so far: {}", summary.requires, signed);
+        for violation in &violations {
+            println!("policy violation: {}", violation);
+        }
+    });
+    Ok(())
+}
+
+/// The names of a config, after its file, and of its members, after their
+/// labels.
+pub struct Names {
+    name: Option<String>,
+    labels: Vec<Option<String>>,
+}
+
+impl Names {
+    /// No names, the address and the pubkey hashes are shown instead.
+    pub fn none(config: &MultisigConfig) -> Self {
+        Names {
+            name: None,
+            labels: vec![None; config.pubkey_hashes().len()],
+        }
+    }
+
+    /// The names of the config file at `path`, `None` when it is not the
+    /// file of `config`.
+    pub fn from_file(
+        path: &Path,
+        file: &ConfigFile,
+        config: &MultisigConfig,
+    ) -> Result<Option<Self>> {
+        if file.to_config()?.multisig_script() != config.multisig_script() {
+            return Ok(None);
+        }
+        Ok(Some(Names {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned()),
+            labels: file
+                .labels()
+                .into_iter()
+                .map(|label| label.map(str::to_string))
+                .collect(),
+        }))
+    }
+
+    /// The member of `config` holding `identity`.
+    pub fn member(&self, config: &MultisigConfig, identity: &[u8; BLAKE160_SIZE]) -> String {
+        let label = config
+            .position(identity)
+            .and_then(|position| self.labels[position].clone());
+        label.unwrap_or_else(|| format!("0x{}", hex::encode(identity)))
+    }
+}
+
+/// What a proposal does, in plain words.
+pub struct Summary {
+    /// The config spent from.
+    pub from: String,
+    /// The capacity spent, the fee included.
+    pub total: u64,
+    /// Each payment, then the change and the fee.
+    pub parts: Vec<String>,
+    pub payments: Vec<Value>,
+    pub change: u64,
+    /// The members who must sign, of `require_first_n`.
+    pub required: Vec<String>,
+    /// The threshold, the required members and the since.
+    pub requires: String,
+    pub signed: Vec<String>,
+}
+
+impl Summary {
+    pub fn new(request: &SigningRequest, network: NetworkType, names: &Names) -> Result<Self> {
+        let config = &request.config;
+        let member = |identity: &[u8; BLAKE160_SIZE]| names.member(config, identity);
+        let lock = &request.script_group.script;
+        let mut total = request.fee;
+        let mut change = 0;
+        let mut parts = Vec::new();
+        let mut payments = Vec::new();
+        for output in request.tx.outputs() {
+            let capacity: u64 = output.capacity().unpack();
+            total += capacity;
+            if output.lock() == *lock && output.type_().is_none() {
+                change += capacity;
+                continue;
+            }
+            let to = address(network, &output.lock());
+            let mut part = format!("{} to {}", format_capacity(capacity), to);
+            if output.type_().is_some() {
+                part.push_str(" with a type script");
+            }
+            parts.push(part);
+            payments.push(json!({
+                "to": to,
+                "capacity": capacity,
+                "type_script": output.type_().is_some(),
+            }));
+        }
+        if change > 0 {
+            parts.push(format!("{} change back", format_capacity(change)));
+        }
+        parts.push(format!("{} fee", format_capacity(request.fee)));
+
+        let mut requires = format!(
+            "requires {} of {}",
+            config.threshold(),
+            config.pubkey_hashes().len()
+        );
+        let required: Vec<_> = config.pubkey_hashes()[..usize::from(config.require_first_n())]
+            .iter()
+            .map(&member)
+            .collect();
+        if !required.is_empty() {
+            requires.push_str(&format!(", including {}", required.join(", ")));
+        }
+        if let Some(since) = config.since() {
+            requires.push_str(&format!(", {}", format_since(since)));
+        }
+        Ok(Summary {
+            from: names.name.clone().unwrap_or_else(|| address(network, lock)),
+            total,
+            parts,
+            payments,
+            change,
+            required,
+            requires,
+            signed: request.signed()?.iter().map(&member).collect(),
+        })
+    }
+
+    /// `spend <total> CKB from <config>: <parts>`.
+    pub fn spend(&self) -> String {
+        format!(
+            "spend {} from {}: {}",
+            format_capacity(self.total),
+            self.from,
+            self.parts.join(", ")
+        )
+    }
+}
+
+fn address(network: NetworkType, lock: &Script) -> String {
+    Address::new(network, AddressPayload::from(lock.clone()), true).to_string()
+}
@@ -0,0 +1,334 @@
+//! Proposals to and from the files of other tools:
+//!
+//! ```text
+//! ckb-multisig export --request proposal.json --format ckb-cli --output tx.json
+//! ckb-multisig export --request proposal.json --format safe --nonce 7 --description "Q3 payroll" --output safe.json
+//! ckb-multisig import --tx tx.json --config config.toml --code-hash <hash> --output proposal.json
+//! ```
+//!
+//! The formats are the proposal itself, the container of the transaction with
+//! its config and script group; the transaction in the node RPC format; the
+//! `ckb-cli tx` file, which carries the configs and the signatures by lock
+//! args beside a transaction with the unsigned locks; and the Safe transaction
+//! service payload of treasury dashboards, see `safe.rs` of the SDK. An
+//! imported file is recognized by its content. Its input cells come from the node, for the
+//! script group and the fee, and the signatures it carries are checked.
+
+use std::{collections::HashMap, convert::TryInto, fs, path::PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ckb_jsonrpc_types as json;
+use ckb_multisig_sdk::{
+    constants::BLAKE160_SIZE, request::SigningRequest, safe::SafeProposal, unlock::set_lock,
+    MultisigConfig, MultisigLock,
+};
+use ckb_sdk::{types::ScriptGroup, Address, AddressPayload, CkbRpcClient, NetworkType};
+use ckb_types::{core::TransactionView, packed, prelude::*, H160};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::util::{
+    emit, load_config, load_config_file, load_request, load_tx, parse_network, save_request,
+    ChainArgs,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// The proposal file of this tool
+    Proposal,
+    /// The transaction in the node RPC format
+    Rpc,
+    /// The transaction file of `ckb-cli tx`
+    CkbCli,
+    /// The multisig transaction payload of the Safe transaction service
+    Safe,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Proposal file
+    #[arg(long)]
+    request: PathBuf,
+
+    #[arg(long, value_enum)]
+    format: Format,
+
+    /// Network of the member addresses in a `ckb-cli tx` file
+    #[arg(long, default_value = "mainnet", value_parser = parse_network)]
+    network: NetworkType,
+
+    /// Nonce of the proposal in a Safe payload, assigned by the dashboard
+    #[arg(long, default_value_t = 0)]
+    nonce: u64,
+
+    /// Description of the proposal in a Safe payload
+    #[arg(long)]
+    description: Option<String>,
+
+    /// Config file naming the members in a Safe payload
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// File to write
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct ImportArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Proposal, transaction in the node RPC format, `ckb-cli tx` file or Safe payload
+    #[arg(long)]
+    tx: PathBuf,
+
+    /// Config file of the lock the transaction spends from
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Proposal file to write
+    #[arg(long)]
+    output: PathBuf,
+}
+
+/// The transaction file of `ckb-cli tx`.
+#[derive(Serialize, Deserialize)]
+struct CkbCliTx {
+    transaction: json::Transaction,
+    #[serde(default)]
+    multisig_configs: HashMap<H160, CkbCliConfig>,
+    /// Signatures by the lock args they are for.
+    #[serde(default)]
+    signatures: HashMap<json::JsonBytes, Vec<json::JsonBytes>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CkbCliConfig {
+    sighash_addresses: Vec<String>,
+    require_first_n: u8,
+    threshold: u8,
+}
+
+pub fn export(args: ExportArgs) -> Result<()> {
+    let request = load_request(&args.request)?;
+    let content = match args.format {
+        Format::Proposal => request.to_json()?,
+        Format::Rpc => serde_json::to_string_pretty(&json::Transaction::from(request.tx.data()))?,
+        Format::CkbCli => serde_json::to_string_pretty(&to_ckb_cli(&request, args.network)?)?,
+        Format::Safe => {
+            let labels = match &args.config {
+                Some(path) => {
+                    let file = load_config_file(path)?;
+                    if file.to_config()? != request.config {
+                        bail!("{} is another config than the proposal", path.display());
+                    }
+                    file.labels()
+                        .into_iter()
+                        .map(|label| label.map(String::from))
+                        .collect()
+                }
+                None => vec![],
+            };
+            let mut proposal = SafeProposal::new(args.nonce, request.clone());
+            proposal.description = args.description.clone();
+            proposal.labels = labels;
+            proposal.to_json()?
+        }
+    };
+    fs::write(&args.output, content).with_context(|| format!("write {}", args.output.display()))?;
+    let value = json!({
+        "output": args.output,
+        "tx_hash": format!("{:#x}", request.tx.hash()),
+    });
+    emit(value, || {
+        println!("{}: {:#x}", args.output.display(), request.tx.hash())
+    });
+    Ok(())
+}
+
+pub fn import(args: ImportArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    let content =
+        fs::read_to_string(&args.tx).with_context(|| format!("read {}", args.tx.display()))?;
+    let file: Value =
+        serde_json::from_str(&content).with_context(|| format!("parse {}", args.tx.display()))?;
+    let mut nonce = None;
+    let mut description = None;
+    let mut signers = Vec::new();
+    let request = if file.get("safeTxHash").is_some() {
+        let proposal = SafeProposal::from_json(&content)
+            .with_context(|| format!("invalid Safe payload {}", args.tx.display()))?;
+        if proposal.request.config != config {
+            bail!("{} is a proposal of another config", args.tx.display());
+        }
+        nonce = Some(proposal.nonce);
+        description = proposal.description.clone();
+        for confirmation in proposal.confirmations()? {
+            signers.push(format!("0x{}", hex::encode(confirmation.owner)));
+        }
+        proposal.request
+    } else if file.get("config").is_some() {
+        let request = load_request(&args.tx)?;
+        if request.config != config {
+            bail!("{} is a proposal of another config", args.tx.display());
+        }
+        request
+    } else if file.get("multisig_configs").is_some() {
+        let file: CkbCliTx = serde_json::from_value(file)
+            .with_context(|| format!("invalid ckb-cli tx file {}", args.tx.display()))?;
+        let tx = packed::Transaction::from(file.transaction.clone()).into_view();
+        let mut request = resolve(&args.chain, config, tx)?;
+        check_ckb_cli_config(&file, &request.config)?;
+        let lock_args = request.config.lock_args();
+        let signatures = file
+            .signatures
+            .get(&json::JsonBytes::from_bytes(lock_args))
+            .cloned()
+            .unwrap_or_default();
+        for signature in signatures {
+            let signature = signature
+                .as_bytes()
+                .try_into()
+                .context("a signature is not 65 bytes")?;
+            let identity = request.add_signature(signature)?;
+            signers.push(format!("0x{}", hex::encode(identity)));
+        }
+        request
+    } else {
+        resolve(&args.chain, config, load_tx(&args.tx)?)?
+    };
+    save_request(&args.output, &request)?;
+    let lock = request.lock()?;
+    let value = json!({
+        "output": args.output,
+        "tx_hash": format!("{:#x}", request.tx.hash()),
+        "nonce": nonce,
+        "description": description,
+        "signed_by": signers,
+        "signatures": lock.filled_count(),
+        "slots": lock.signatures().len(),
+    });
+    emit(value, || {
+        if let Some(nonce) = nonce {
+            println!("nonce {}", nonce);
+        }
+        if let Some(description) = &description {
+            println!("  {}", description);
+        }
+        for signer in &signers {
+            println!("  signed by {}", signer);
+        }
+        println!(
+            "{}: {:#x}, {} of {} signatures",
+            args.output.display(),
+            request.tx.hash(),
+            lock.filled_count(),
+            lock.signatures().len()
+        );
+    });
+    Ok(())
+}
+
+/// The proposal of a transaction, its script group and fee from the input
+/// cells on the node.
+fn resolve(
+    chain: &ChainArgs,
+    config: MultisigConfig,
+    tx: TransactionView,
+) -> Result<SigningRequest> {
+    let chain = chain.resolve()?;
+    let client = CkbRpcClient::new(&chain.rpc);
+    let lock_script = chain.lock_script(&config);
+    let mut script_group = ScriptGroup::from_lock_script(&lock_script);
+    let mut inputs_capacity = 0u64;
+    for (index, input) in tx.inputs().into_iter().enumerate() {
+        let out_point = input.previous_output();
+        let cell = client
+            .get_live_cell(out_point.clone().into(), false)?
+            .cell
+            .with_context(|| format!("input #{} is not a live cell", index))?;
+        let output = packed::CellOutput::from(cell.output);
+        if output.lock() == lock_script {
+            script_group.input_indices.push(index);
+        }
+        inputs_capacity += Unpack::<u64>::unpack(&output.capacity());
+    }
+    if script_group.input_indices.is_empty() {
+        bail!("the transaction spends no cell of the config");
+    }
+    let outputs_capacity = tx
+        .outputs_capacity()
+        .context("the outputs capacity overflows")?
+        .as_u64();
+    let fee = inputs_capacity
+        .checked_sub(outputs_capacity)
+        .context("the outputs hold more than the inputs")?;
+    Ok(SigningRequest {
+        config,
+        tx,
+        script_group,
+        fee,
+        genesis_hash: Some(chain.genesis_hash()?),
+    })
+}
+
+fn to_ckb_cli(request: &SigningRequest, network: NetworkType) -> Result<CkbCliTx> {
+    let config = &request.config;
+    let lock_args = config.lock_args();
+    let signatures = request
+        .lock()?
+        .filled()
+        .map(|signature| json::JsonBytes::from_vec(signature.to_vec()))
+        .collect();
+    let unsigned = set_lock(
+        &request.tx,
+        &request.script_group,
+        &MultisigLock::new(config.clone()),
+    )?;
+    let sighash_addresses = config
+        .pubkey_hashes()
+        .iter()
+        .map(|hash| {
+            let payload = AddressPayload::from_pubkey_hash(H160(*hash));
+            Address::new(network, payload, true).to_string()
+        })
+        .collect();
+    let ckb_cli_config = CkbCliConfig {
+        sighash_addresses,
+        require_first_n: config.require_first_n(),
+        threshold: config.threshold(),
+    };
+    Ok(CkbCliTx {
+        transaction: unsigned.data().into(),
+        multisig_configs: HashMap::from([(H160(config.hash160()), ckb_cli_config)]),
+        signatures: HashMap::from([(json::JsonBytes::from_bytes(lock_args), signatures)]),
+    })
+}
+
+/// The config of a `ckb-cli tx` file must be the one of the proposal.
+fn check_ckb_cli_config(file: &CkbCliTx, config: &MultisigConfig) -> Result<()> {
+    let ckb_cli_config = match file.multisig_configs.get(&H160(config.hash160())) {
+        Some(ckb_cli_config) => ckb_cli_config,
+        None => bail!("the ckb-cli tx file has no entry for the config"),
+    };
+    let hashes = ckb_cli_config
+        .sighash_addresses
+        .iter()
+        .map(|address| {
+            let address: Address = address
+                .parse()
+                .map_err(|err| anyhow!("invalid address `{}`: {}", address, err))?;
+            let args = address.payload().args();
+            args.as_ref().try_into().context("not a sighash address")
+        })
+        .collect::<Result<Vec<[u8; BLAKE160_SIZE]>>>()?;
+    if hashes != config.pubkey_hashes()
+        || ckb_cli_config.require_first_n != config.require_first_n()
+        || ckb_cli_config.threshold != config.threshold()
+    {
+        bail!("the ckb-cli tx file has another config");
+    }
+    Ok(())
+}
@@ -0,0 +1,447 @@
+//! `ckb-multisig keystore`: the encrypted keys of a software cosigner.
+//!
+//! ```text
+//! ckb-multisig keystore new --label alice --config treasury.toml
+//! ckb-multisig keystore import --privkey-path alice.key --label alice
+//! ckb-multisig keystore import --keystore-file ckb-cli.json --label bob
+//! ckb-multisig keystore list --config treasury.toml
+//! ckb-multisig keystore mark --key alice --config treasury.toml
+//! ckb-multisig keystore add-config --name treasury --config treasury.toml
+//! ckb-multisig keystore configs
+//! ckb-multisig keystore cosigner --config treasury --member 0x... --label carol
+//! ckb-multisig keystore remove-config --name treasury
+//! ```
+//!
+//! The signing commands take the keys by `--key <label>`, or all the keys
+//! marked for the config of the proposal with `--marked`. The configs stored
+//! with `add-config` list the local keys and the external cosigners of each
+//! treasury, and mark the keys added later. See `keystore.rs` for the files.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Result};
+use ckb_multisig_sdk::{MultisigConfig, Signer};
+use clap::{ArgGroup, Args, Subcommand};
+use serde_json::json;
+
+use crate::{
+    keystore::{self, config_id, KeyFile},
+    util::{emit, load_config, load_config_file, parse_hex, read_privkey, KeystoreArgs},
+};
+
+#[derive(Subcommand)]
+pub enum KeystoreCommand {
+    /// Generate a key
+    New(NewArgs),
+    /// Encrypt a private key file, or add a keystore file of ckb-cli
+    Import(ImportArgs),
+    /// List the keys with their labels and configs
+    List(ListArgs),
+    /// Mark a key as a member of a config, or unmark it
+    Mark(MarkArgs),
+    /// Store a config under a name and mark the keys which are members of it
+    AddConfig(AddConfigArgs),
+    /// Forget a stored config and unmark its keys
+    RemoveConfig(RemoveConfigArgs),
+    /// List the stored configs with their local keys and external cosigners
+    Configs(ConfigsArgs),
+    /// Label an external cosigner of a stored config
+    Cosigner(CosignerArgs),
+}
+
+#[derive(Args)]
+pub struct NewArgs {
+    #[command(flatten)]
+    keystore: KeystoreArgs,
+
+    /// Name the signing commands take the key by
+    #[arg(long)]
+    label: Option<String>,
+
+    /// Config file the key is a member of, can be repeated
+    #[arg(long = "config")]
+    configs: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("source").required(true).args(["privkey_path", "keystore_file"])))]
+pub struct ImportArgs {
+    #[command(flatten)]
+    keystore: KeystoreArgs,
+
+    /// Private key file in the ckb-cli format, the hex key on the first line
+    #[arg(long)]
+    privkey_path: Option<PathBuf>,
+
+    /// Keystore file exported by ckb-cli or another keystore
+    #[arg(long)]
+    keystore_file: Option<PathBuf>,
+
+    /// Name the signing commands take the key by
+    #[arg(long)]
+    label: Option<String>,
+
+    /// Config file the key is a member of, can be repeated
+    #[arg(long = "config")]
+    configs: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ListArgs {
+    #[command(flatten)]
+    keystore: KeystoreArgs,
+
+    /// Only the keys marked for this config
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct MarkArgs {
+    #[command(flatten)]
+    keystore: KeystoreArgs,
+
+    /// Label or pubkey hash of the key
+    #[arg(long)]
+    key: String,
+
+    /// Config file the key is a member of
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Remove the mark instead
+    #[arg(long)]
+    unmark: bool,
+}
+
+#[derive(Args)]
+pub struct AddConfigArgs {
+    #[command(flatten)]
+    keystore: KeystoreArgs,
+
+    /// Name of the config in the store: letters, digits, - and _
+    #[arg(long)]
+    name: String,
+
+    /// Config file to store
+    #[arg(long)]
+    config: PathBuf,
+}
+
+#[derive(Args)]
+pub struct RemoveConfigArgs {
+    #[command(flatten)]
+    keystore: KeystoreArgs,
+
+    /// Name or id of the stored config
+    #[arg(long)]
+    name: String,
+}
+
+#[derive(Args)]
+pub struct ConfigsArgs {
+    #[command(flatten)]
+    keystore: KeystoreArgs,
+
+    /// Only the configs a key is a member of, by label or pubkey hash
+    #[arg(long)]
+    key: Option<String>,
+}
+
+#[derive(Args)]
+pub struct CosignerArgs {
+    #[command(flatten)]
+    keystore: KeystoreArgs,
+
+    /// Name or id of the stored config
+    #[arg(long)]
+    config: String,
+
+    /// Pubkey hash of the member
+    #[arg(long)]
+    member: String,
+
+    /// Name of the cosigner, shown instead of the pubkey hash
+    #[arg(long)]
+    label: Option<String>,
+
+    /// Compressed public key of the cosigner, checked against the hash
+    #[arg(long)]
+    pubkey: Option<String>,
+}
+
+pub fn run(command: KeystoreCommand) -> Result<()> {
+    match command {
+        KeystoreCommand::New(args) => new(args),
+        KeystoreCommand::Import(args) => import(args),
+        KeystoreCommand::List(args) => list(args),
+        KeystoreCommand::Mark(args) => mark(args),
+        KeystoreCommand::AddConfig(args) => add_config(args),
+        KeystoreCommand::RemoveConfig(args) => remove_config(args),
+        KeystoreCommand::Configs(args) => configs(args),
+        KeystoreCommand::Cosigner(args) => cosigner(args),
+    }
+}
+
+fn new(args: NewArgs) -> Result<()> {
+    let name = args.label.as_deref().unwrap_or("the new key");
+    let password = keystore::read_password(name, true)?;
+    add(
+        &args.keystore,
+        KeyFile::generate(&password)?,
+        args.label,
+        &args.configs,
+    )
+}
+
+fn import(args: ImportArgs) -> Result<()> {
+    let file = match (&args.privkey_path, &args.keystore_file) {
+        (Some(path), _) => {
+            let name = args.label.as_deref().unwrap_or("the imported key");
+            let password = keystore::read_password(name, true)?;
+            KeyFile::encrypt(&read_privkey(path)?, &password)?
+        }
+        (None, Some(path)) => {
+            let mut file = keystore::load(path)?;
+            // checks the password, and the key when the file names it
+            let password = keystore::read_password(&path.display().to_string(), false)?;
+            let identity = hex::encode(file.decrypt(&password)?.identity()?);
+            if file
+                .hash160
+                .as_deref()
+                .is_some_and(|hash| hash.trim_start_matches("0x") != identity)
+            {
+                bail!("{} holds the key of 0x{}", path.display(), identity);
+            }
+            file.hash160 = Some(identity);
+            file.configs.clear();
+            file
+        }
+        (None, None) => unreachable!("required argument group"),
+    };
+    add(&args.keystore, file, args.label, &args.configs)
+}
+
+fn add(
+    keystore: &KeystoreArgs,
+    mut file: KeyFile,
+    label: Option<String>,
+    configs: &[PathBuf],
+) -> Result<()> {
+    file.label = label;
+    for path in configs {
+        mark_member(&mut file, &load_config(path)?)?;
+    }
+    let keystore = keystore.open()?;
+    // the stored configs the key is a member of
+    let identity = file.identity()?;
+    for stored in keystore.configs()? {
+        let config = stored.config()?;
+        let id = config_id(&config);
+        if config.position(&identity).is_some() && !file.configs.contains(&id) {
+            file.configs.push(id);
+        }
+    }
+    let path = keystore.add(&file)?;
+    let value = json!({
+        "path": path,
+        "name": file.name(),
+        "pubkey_hash": format!("0x{}", hex::encode(identity)),
+    });
+    emit(value, || {
+        println!("{}: {}", path.display(), file.name());
+        println!("  pubkey hash 0x{}", hex::encode(identity));
+    });
+    Ok(())
+}
+
+fn list(args: ListArgs) -> Result<()> {
+    let config = args.config.as_deref().map(load_config).transpose()?;
+    let keystore = args.keystore.open()?;
+    let names = config_names(&keystore)?;
+    for (path, file) in keystore.list()? {
+        if config
+            .as_ref()
+            .is_some_and(|config| !file.is_member(config))
+        {
+            continue;
+        }
+        let identity = file
+            .identity()
+            .ok()
+            .map(|identity| format!("0x{}", hex::encode(identity)));
+        let configs: Vec<_> = file
+            .configs
+            .iter()
+            .map(|config| {
+                let name = names
+                    .iter()
+                    .find(|(id, _)| id == config)
+                    .map(|(_, name)| name.clone());
+                (config.clone(), name)
+            })
+            .collect();
+        let value = json!({
+            "name": file.name(),
+            "path": path,
+            "pubkey_hash": identity,
+            "configs": configs
+                .iter()
+                .map(|(id, name)| json!({ "id": id, "name": name }))
+                .collect::<Vec<_>>(),
+        });
+        emit(value, || {
+            println!("{}: {}", file.name(), path.display());
+            if let Some(identity) = &identity {
+                println!("  pubkey hash {}", identity);
+            }
+            for (id, name) in &configs {
+                match name {
+                    Some(name) => println!("  member of {} ({})", name, id),
+                    None => println!("  member of {}", id),
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+fn mark(args: MarkArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    let keystore = args.keystore.open()?;
+    let (path, mut file) = keystore.find(&args.key)?;
+    if args.unmark {
+        let id = config_id(&config);
+        file.configs.retain(|config| *config != id);
+    } else {
+        mark_member(&mut file, &config)?;
+    }
+    keystore::save(&path, &file)?;
+    let value = json!({ "name": file.name(), "configs": file.configs });
+    emit(value, || {
+        if file.configs.is_empty() {
+            println!("{}: member of no config", file.name());
+        } else {
+            println!("{}: member of {}", file.name(), file.configs.join(", "));
+        }
+    });
+    Ok(())
+}
+
+fn add_config(args: AddConfigArgs) -> Result<()> {
+    let keystore = args.keystore.open()?;
+    let stored = keystore.add_config(&args.name, &load_config_file(&args.config)?)?;
+    print_config(&keystore, &stored)
+}
+
+fn remove_config(args: RemoveConfigArgs) -> Result<()> {
+    let stored = args.keystore.open()?.remove_config(&args.name)?;
+    let id = stored.id()?;
+    let value = json!({ "removed": stored.name, "id": id });
+    emit(value, || println!("removed {} ({})", stored.name, id));
+    Ok(())
+}
+
+fn configs(args: ConfigsArgs) -> Result<()> {
+    let keystore = args.keystore.open()?;
+    let key = args
+        .key
+        .as_deref()
+        .map(|key| keystore.find(key).map(|(_, file)| file))
+        .transpose()?;
+    for stored in keystore.configs()? {
+        if let Some(key) = &key {
+            if !key.is_member(&stored.config()?) {
+                continue;
+            }
+        }
+        print_config(&keystore, &stored)?;
+    }
+    Ok(())
+}
+
+fn cosigner(args: CosignerArgs) -> Result<()> {
+    let keystore = args.keystore.open()?;
+    let mut stored = keystore.find_config(&args.config)?;
+    let index = stored
+        .config()?
+        .position(&parse_hex(&args.member)?)
+        .ok_or_else(|| anyhow!("{} is not a member of {}", args.member, stored.name))?;
+    let entry = &mut stored.file.keys[index];
+    if args.label.is_some() {
+        entry.label = args.label;
+    }
+    if let Some(pubkey) = args.pubkey {
+        entry.pubkey = Some(format!("0x{}", hex::encode(parse_hex::<33>(&pubkey)?)));
+    }
+    // checks the pubkey against the hash
+    stored.config()?;
+    keystore.save_config(&stored)?;
+    print_config(&keystore, &stored)
+}
+
+/// The ids and names of the stored configs.
+fn config_names(keystore: &keystore::Keystore) -> Result<Vec<(String, String)>> {
+    keystore
+        .configs()?
+        .into_iter()
+        .map(|stored| Ok((stored.id()?, stored.name)))
+        .collect()
+}
+
+/// A stored config with its cosigners, local or external.
+fn print_config(keystore: &keystore::Keystore, stored: &keystore::StoredConfig) -> Result<()> {
+    let config = stored.config()?;
+    let cosigners: Vec<_> = keystore
+        .cosigners(stored)?
+        .iter()
+        .map(|cosigner| {
+            let kind = if cosigner.is_local() {
+                "local"
+            } else {
+                "external"
+            };
+            let pubkey_hash = format!("0x{}", hex::encode(cosigner.pubkey_hash));
+            (kind, cosigner.name(), pubkey_hash)
+        })
+        .collect();
+    let value = json!({
+        "name": stored.name,
+        "path": stored.path,
+        "id": config_id(&config),
+        "threshold": config.threshold(),
+        "members": config.pubkey_hashes().len(),
+        "require_first_n": config.require_first_n(),
+        "cosigners": cosigners
+            .iter()
+            .map(|(kind, name, pubkey_hash)| {
+                json!({ "kind": kind, "name": name, "pubkey_hash": pubkey_hash })
+            })
+            .collect::<Vec<_>>(),
+    });
+    emit(value, || {
+        println!("{}: {}", stored.name, stored.path.display());
+        println!("  id {}", config_id(&config));
+        println!(
+            "  {} of {}, the first {} required",
+            config.threshold(),
+            config.pubkey_hashes().len(),
+            config.require_first_n()
+        );
+        for (kind, name, pubkey_hash) in &cosigners {
+            println!("  {} {} {}", kind, name, pubkey_hash);
+        }
+    });
+    Ok(())
+}
+
+fn mark_member(file: &mut KeyFile, config: &MultisigConfig) -> Result<()> {
+    if config.position(&file.identity()?).is_none() {
+        bail!("{} is not a member of the config", file.name());
+    }
+    let id = config_id(config);
+    if !file.configs.contains(&id) {
+        file.configs.push(id);
+    }
+    Ok(())
+}
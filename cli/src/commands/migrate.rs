@@ -0,0 +1,426 @@
+//! `ckb-multisig migrate`: rotate the keys of a config.
+//!
+//! ```text
+//! ckb-multisig migrate plan --from old.toml --to new.toml --dir migration ...
+//! ckb-multisig migrate sign --dir migration --privkey-path alice.key
+//! ckb-multisig migrate send --dir migration
+//! ```
+//!
+//! The transactions are planned once and written to `--dir`, cosigners then
+//! sign the same files in turn, any order works.
+//!
+//! `config rotate` drives the whole rotation from one machine, and is run
+//! again until it is over:
+//!
+//! ```text
+//! ckb-multisig config rotate --config old.toml --new-config new.toml --dir rotation --privkey-path alice.key ...
+//! ckb-multisig config rotate --config old.toml --new-config new.toml --dir rotation --collect bob/ ...
+//! ```
+//!
+//! The first run plans the migration. Every run merges the signatures of the
+//! copies of `--dir` the cosigners signed, `--collect`, and adds those of the
+//! local keys. Once every transaction is completely signed, they are sent,
+//! and the run waits for them to be committed and indexed, then fails unless
+//! the old config holds no cell anymore: cells locked by its since or in the
+//! Nervos DAO are left behind, as are the deposits made after the plan.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use ckb_jsonrpc_types as json;
+use ckb_multisig_sdk::{
+    migrate::{Migration, MigrationPlan},
+    scanner::Scanner,
+    unlock::MultisigScriptSigner,
+    MultisigConfig,
+};
+use ckb_sdk::CkbRpcClient;
+use ckb_types::{prelude::*, H256};
+use clap::{Args, Subcommand};
+use serde_json::json;
+
+use crate::util::{
+    emit, format_ckb, load_config, load_request, save_request, Chain, ChainArgs, SignerArgs,
+};
+
+const REQUEST_PREFIX: &str = "migration-";
+/// The pause between two checks of the transactions sent by `rotate`.
+const POLL_INTERVAL: Duration = Duration::from_secs(8);
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// Plan the migration transactions from the live cells of the old config
+    Plan(PlanArgs),
+    /// Add the signatures of local keys to the planned transactions
+    Sign(SignArgs),
+    /// Send the transactions once completely signed
+    Send(SendArgs),
+}
+
+#[derive(Args)]
+pub struct PlanArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Config file of the old lock
+    #[arg(long)]
+    from: PathBuf,
+
+    /// Config file of the new lock
+    #[arg(long)]
+    to: PathBuf,
+
+    /// Fee rate in shannons per 1000 bytes
+    #[arg(long, default_value_t = 1000)]
+    fee_rate: u64,
+
+    /// Directory the transactions are written to
+    #[arg(long)]
+    dir: PathBuf,
+}
+
+#[derive(Args)]
+pub struct SignArgs {
+    /// Directory of the planned transactions
+    #[arg(long)]
+    dir: PathBuf,
+
+    #[command(flatten)]
+    signers: SignerArgs,
+}
+
+#[derive(Args)]
+pub struct SendArgs {
+    /// Directory of the signed transactions
+    #[arg(long)]
+    dir: PathBuf,
+
+    /// RPC of a CKB node
+    #[arg(long, default_value = "http://127.0.0.1:8114")]
+    rpc: String,
+}
+
+#[derive(Args)]
+pub struct RotateArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Config file of the old lock
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Config file of the new lock
+    #[arg(long)]
+    new_config: PathBuf,
+
+    /// Directory of the migration, planned there on the first run
+    #[arg(long)]
+    dir: PathBuf,
+
+    /// Directory of a copy of the migration signed by a cosigner, can be
+    /// repeated
+    #[arg(long = "collect")]
+    collect: Vec<PathBuf>,
+
+    #[command(flatten)]
+    signers: SignerArgs,
+
+    /// Fee rate in shannons per 1000 bytes
+    #[arg(long, default_value_t = 1000)]
+    fee_rate: u64,
+}
+
+pub fn run(command: MigrateCommand) -> Result<()> {
+    match command {
+        MigrateCommand::Plan(args) => plan(args),
+        MigrateCommand::Sign(args) => sign(args),
+        MigrateCommand::Send(args) => send(args),
+    }
+}
+
+fn plan(args: PlanArgs) -> Result<()> {
+    let old = load_config(&args.from)?;
+    let new = load_config(&args.to)?;
+    let chain = &args.chain.resolve()?;
+    fs::create_dir_all(&args.dir).with_context(|| format!("create {}", args.dir.display()))?;
+    if !list_requests(&args.dir)?.is_empty() {
+        bail!("{} already holds a migration", args.dir.display());
+    }
+    write_plan(chain, &old, &new, args.fee_rate, &args.dir)
+}
+
+/// Plan the migration from the live cells of `old` into `dir`.
+fn write_plan(
+    chain: &Chain,
+    old: &MultisigConfig,
+    new: &MultisigConfig,
+    fee_rate: u64,
+    dir: &Path,
+) -> Result<()> {
+    if old.multisig_script() == new.multisig_script() && old.since() == new.since() {
+        bail!("the old and new configs are the same");
+    }
+    let cell_deps = chain.cell_deps()?;
+    let scanner = Scanner::with_backend(chain.connect(), old, &chain.code_hash, chain.hash_type);
+    let cells = scanner.scan()?;
+    let mut plan = Migration::new(
+        old.clone(),
+        chain.lock_script(old),
+        chain.lock_script(new),
+        cell_deps,
+        fee_rate,
+    )
+    .plan(&cells)?;
+
+    let genesis_hash = chain.genesis_hash()?;
+    for (i, request) in plan.requests.iter_mut().enumerate() {
+        request.genesis_hash = Some(genesis_hash.clone());
+        let path = dir.join(format!("{}{:03}.json", REQUEST_PREFIX, i));
+        save_request(&path, request)?;
+        let value = json!({
+            "kind": "request",
+            "path": path,
+            "tx_hash": format!("{:#x}", request.tx.hash()),
+            "inputs": request.tx.inputs().len(),
+            "fee": request.fee,
+        });
+        emit(value, || {
+            println!(
+                "{}: {} inputs, fee {}",
+                path.display(),
+                request.tx.inputs().len(),
+                format_ckb(request.fee)
+            )
+        });
+    }
+    for cell in &plan.pending {
+        let value = json!({
+            "kind": "pending",
+            "out_point": json::OutPoint::from(cell.cell.out_point.clone()),
+            "capacity": cell.capacity(),
+            "maturity": cell.maturity.to_string(),
+        });
+        emit(value, || {
+            println!(
+                "pending {}: {}, {}",
+                cell.cell.out_point,
+                format_ckb(cell.capacity()),
+                cell.maturity
+            )
+        });
+    }
+    for cell in &plan.skipped {
+        let value = json!({
+            "kind": "skipped",
+            "out_point": json::OutPoint::from(cell.cell.out_point.clone()),
+            "capacity": cell.capacity(),
+        });
+        emit(value, || {
+            println!(
+                "skipped {}: {} in the Nervos DAO, prepare the withdraw to the new lock",
+                cell.cell.out_point,
+                format_ckb(cell.capacity())
+            )
+        });
+    }
+    Ok(())
+}
+
+fn sign(args: SignArgs) -> Result<()> {
+    let mut plan = load_plan(&args.dir)?;
+    let config = match plan.requests.first() {
+        Some(request) => request.config.clone(),
+        None => bail!("no migration in {}", args.dir.display()),
+    };
+    let signers = args.signers.load(&config, None)?;
+    plan.sign(&MultisigScriptSigner::new(config, signers))?;
+    save_plan(&args.dir, &plan)
+}
+
+fn send(args: SendArgs) -> Result<()> {
+    let plan = load_plan(&args.dir)?;
+    for hash in plan.send(&CkbRpcClient::new(&args.rpc))? {
+        let value = json!({ "kind": "sent", "tx_hash": format!("{:#x}", hash) });
+        emit(value, || println!("{:#x}", hash));
+    }
+    Ok(())
+}
+
+pub fn rotate(args: RotateArgs) -> Result<()> {
+    let old = load_config(&args.config)?;
+    let new = load_config(&args.new_config)?;
+    let chain = &args.chain.resolve()?;
+    fs::create_dir_all(&args.dir).with_context(|| format!("create {}", args.dir.display()))?;
+    if list_requests(&args.dir)?.is_empty() {
+        write_plan(chain, &old, &new, args.fee_rate, &args.dir)?;
+    }
+    let mut plan = load_plan(&args.dir)?;
+    let new_lock = chain.lock_script(&new);
+    for request in &plan.requests {
+        let to_new = request
+            .tx
+            .outputs()
+            .into_iter()
+            .all(|output| output.lock() == new_lock);
+        if request.config != old || !to_new {
+            bail!(
+                "{} holds another migration than from {} to {}",
+                args.dir.display(),
+                args.config.display(),
+                args.new_config.display()
+            );
+        }
+    }
+
+    for dir in &args.collect {
+        for identity in plan.combine(&load_plan(dir)?)? {
+            let value = json!({
+                "kind": "signature",
+                "pubkey_hash": format!("0x{}", hex::encode(identity)),
+                "from": dir,
+            });
+            emit(value, || {
+                println!("0x{} from {}", hex::encode(identity), dir.display())
+            });
+        }
+    }
+    if args.signers.any() {
+        let signers = args.signers.load(&old, None)?;
+        plan.sign(&MultisigScriptSigner::new(old.clone(), signers))?;
+    }
+    save_plan(&args.dir, &plan)?;
+    if !plan.is_complete()? {
+        emit(json!({ "kind": "incomplete" }), || {
+            println!("not completely signed yet, collect the signatures and run rotate again")
+        });
+        return Ok(());
+    }
+
+    let client = CkbRpcClient::new(&chain.rpc);
+    let mut last_block = 0;
+    for request in &plan.requests {
+        let hash: H256 = request.tx.hash().unpack();
+        if status(&client, &hash)?.status == json::Status::Unknown {
+            request.send(&client)?;
+            let value = json!({ "kind": "sent", "tx_hash": format!("{:#x}", hash) });
+            emit(value, || println!("sent {:#x}", hash));
+        }
+        let number = loop {
+            let status = status(&client, &hash)?;
+            match status.status {
+                json::Status::Committed => break status.block_number.map_or(0, |n| n.value()),
+                json::Status::Rejected => bail!(
+                    "{:#x} rejected: {}",
+                    hash,
+                    status.reason.unwrap_or_default()
+                ),
+                _ => thread::sleep(POLL_INTERVAL),
+            }
+        };
+        let value = json!({
+            "kind": "committed",
+            "tx_hash": format!("{:#x}", hash),
+            "block_number": number,
+        });
+        emit(value, || {
+            println!("{:#x} committed in block {}", hash, number)
+        });
+        last_block = last_block.max(number);
+    }
+    while client
+        .get_indexer_tip()?
+        .is_none_or(|tip| tip.block_number.value() < last_block)
+    {
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let left =
+        Scanner::with_backend(chain.connect(), &old, &chain.code_hash, chain.hash_type).scan()?;
+    if left.cells.is_empty() {
+        emit(json!({ "kind": "empty", "config": args.config }), || {
+            println!("{} holds no cell anymore", args.config.display())
+        });
+        return Ok(());
+    }
+    for cell in &left.cells {
+        let value = json!({
+            "kind": "left",
+            "out_point": json::OutPoint::from(cell.cell.out_point.clone()),
+            "capacity": cell.capacity(),
+            "maturity": cell.maturity.to_string(),
+        });
+        emit(value, || {
+            println!(
+                "left {}: {}, {}",
+                cell.cell.out_point,
+                format_ckb(cell.capacity()),
+                cell.maturity
+            )
+        });
+    }
+    bail!(
+        "{} cells are left under {}, rotate them with a new --dir once mature",
+        left.cells.len(),
+        args.config.display()
+    )
+}
+
+fn status(client: &CkbRpcClient, hash: &H256) -> Result<json::TxStatus> {
+    Ok(client.get_transaction_status(hash.clone())?.tx_status)
+}
+
+fn save_plan(dir: &Path, plan: &MigrationPlan) -> Result<()> {
+    for (path, request) in list_requests(dir)?.iter().zip(&plan.requests) {
+        save_request(path, request)?;
+        let lock = request.lock()?;
+        let value = json!({
+            "kind": "status",
+            "path": path,
+            "tx_hash": format!("{:#x}", request.tx.hash()),
+            "signatures": lock.filled_count(),
+            "slots": lock.signatures().len(),
+        });
+        emit(value, || {
+            println!(
+                "{}: {} of {} signatures",
+                path.display(),
+                lock.filled_count(),
+                lock.signatures().len()
+            )
+        });
+    }
+    Ok(())
+}
+
+fn list_requests(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("read {}", dir.display()))? {
+        let path = entry?.path();
+        let is_request = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(REQUEST_PREFIX) && name.ends_with(".json"));
+        if is_request {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn load_plan(dir: &Path) -> Result<MigrationPlan> {
+    let requests = list_requests(dir)?
+        .iter()
+        .map(|path| load_request(path))
+        .collect::<Result<_>>()?;
+    Ok(MigrationPlan {
+        requests,
+        pending: vec![],
+        skipped: vec![],
+    })
+}
@@ -0,0 +1,19 @@
+pub mod address;
+pub mod audit;
+pub mod completions;
+pub mod config;
+pub mod cycles;
+pub mod dao;
+pub mod doctor;
+pub mod history;
+pub mod inspect;
+pub mod interop;
+pub mod keystore;
+pub mod migrate;
+pub mod proposal;
+pub mod review;
+pub mod simulate;
+pub mod verify;
+pub mod verify_binary;
+pub mod watch;
+pub mod witness;
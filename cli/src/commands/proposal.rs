@@ -0,0 +1,436 @@
+//! Spend proposals, from the proposer to the node:
+//!
+//! ```text
+//! ckb-multisig propose --config config.toml --to ckb1... --amount 1000 --output proposal.json ...
+//! ckb-multisig sign --request proposal.json --privkey-path alice.key
+//! ckb-multisig combine --request alice.json --request bob.json --output proposal.json
+//! ckb-multisig send --request proposal.json
+//! ```
+//!
+//! A proposal is a signing request file of the SDK. Cosigners sign it in
+//! turn, or each sign a copy which `combine` merges. With `--policy`, `sign`
+//! refuses the proposals breaking the off-chain policy of the machine, and
+//! with `--chain` those proposed on another chain than the one named.
+//!
+//! `sign --dir` signs a directory of proposals in one pass, for the
+//! air-gapped machines each round trip costs a walk to: the pending ones are
+//! summarized, numbered, and those approved at the prompt are signed in
+//! place, the passwords asked once per config:
+//!
+//! ```text
+//! ckb-multisig sign --dir /media/usb/proposals --config treasury.toml --key alice
+//! ```
+
+use std::{
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use ckb_multisig_sdk::{
+    batch::{parse_ckb, BatchTransfer, Payment},
+    cobuild,
+    constants::BLAKE160_SIZE,
+    request::SigningRequest,
+    scanner::Scanner,
+    unlock::MultisigScriptSigner,
+};
+use ckb_sdk::{Address, CkbRpcClient, NetworkType};
+use ckb_types::{packed::Script, H256};
+use clap::Args;
+use serde_json::json;
+
+use super::inspect::{Names, Summary};
+use crate::util::{
+    emit, format_ckb, load_config, load_config_file, load_request, parse_address, parse_chain,
+    parse_network, proposal_files, save_request, ChainArgs, PolicyArgs, SignerArgs,
+};
+
+#[derive(Args)]
+pub struct ProposeArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Config file of the lock the capacity is spent from
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Address of the recipient
+    #[arg(long, value_parser = parse_address)]
+    to: Address,
+
+    /// Amount in CKB, up to 8 decimals
+    #[arg(long, value_parser = parse_ckb)]
+    amount: u64,
+
+    /// Address the change goes to, the config itself by default
+    #[arg(long, value_parser = parse_address)]
+    change: Option<Address>,
+
+    /// Fee rate in shannons per 1000 bytes
+    #[arg(long, default_value_t = 1000)]
+    fee_rate: u64,
+
+    /// Proposal file to write
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct SignArgs {
+    /// Proposal file, updated in place unless `--output` is given
+    #[arg(long, required_unless_present = "dir", conflicts_with = "dir")]
+    request: Option<PathBuf>,
+
+    /// Directory of proposal files to sign in one pass, each updated in place
+    #[arg(long, conflicts_with = "output")]
+    dir: Option<PathBuf>,
+
+    /// Config file, for the names in the summary of `--dir`, can be repeated
+    #[arg(long = "config", requires = "dir")]
+    configs: Vec<PathBuf>,
+
+    /// mainnet or testnet, the network of the addresses of `--dir`
+    #[arg(long, default_value = "mainnet", value_parser = parse_network)]
+    network: NetworkType,
+
+    /// Sign every pending proposal of `--dir` without asking
+    #[arg(long, requires = "dir")]
+    yes: bool,
+
+    /// mainnet, testnet or the 0x genesis hash of another chain, refusing
+    /// the proposals for any other
+    #[arg(long, value_parser = parse_chain)]
+    chain: Option<H256>,
+
+    #[command(flatten)]
+    signers: SignerArgs,
+
+    #[command(flatten)]
+    policy: PolicyArgs,
+
+    /// Write the signed proposal there instead
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct CombineArgs {
+    /// Signed copy of the same proposal, can be repeated
+    #[arg(long = "request", required = true)]
+    requests: Vec<PathBuf>,
+
+    /// Proposal file with all the signatures
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct SendArgs {
+    /// Completely signed proposal file
+    #[arg(long)]
+    request: PathBuf,
+
+    /// RPC of a CKB node
+    #[arg(long, default_value = "http://127.0.0.1:8114")]
+    rpc: String,
+}
+
+pub fn propose(args: ProposeArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    let chain = &args.chain.resolve()?;
+    let cell_deps = chain.cell_deps()?;
+    let lock_script = chain.lock_script(&config);
+    let cells = Scanner::with_backend(chain.connect(), &config, &chain.code_hash, chain.hash_type)
+        .scan()?
+        .spendable();
+    let mut builder = BatchTransfer::new(config, lock_script, cell_deps, args.fee_rate);
+    if let Some(change) = &args.change {
+        builder = builder.change(Script::from(change));
+    }
+    let payment = Payment {
+        to: Script::from(&args.to),
+        capacity: args.amount,
+    };
+    let mut requests = builder.build(vec![payment], &cells)?;
+    if requests.len() != 1 {
+        bail!("the payment doesn't fit in one transaction, sweep the small cells first");
+    }
+    let mut request = requests.remove(0);
+    request.genesis_hash = Some(chain.genesis_hash()?);
+    save_request(&args.output, &request)?;
+    let digest = request.message()?;
+    let value = json!({
+        "output": args.output,
+        "tx_hash": format!("{:#x}", request.tx.hash()),
+        "to": args.to.to_string(),
+        "amount": args.amount,
+        "inputs": request.tx.inputs().len(),
+        "fee": request.fee,
+        "digest": format!("0x{}", hex::encode(digest)),
+    });
+    emit(value, || {
+        println!("{}: {:#x}", args.output.display(), request.tx.hash());
+        println!("  to {} {}", args.to, format_ckb(args.amount));
+        println!(
+            "  {} inputs, fee {}",
+            request.tx.inputs().len(),
+            format_ckb(request.fee)
+        );
+        println!("  digest 0x{}", hex::encode(digest));
+    });
+    Ok(())
+}
+
+pub fn sign(args: SignArgs) -> Result<()> {
+    let Some(path) = &args.request else {
+        return sign_dir(&args);
+    };
+    let mut request = load_request(path)?;
+    if let Some(chain) = &args.chain {
+        request.check_chain(chain)?;
+    }
+    let display = cobuild::message(&request.config, &request.tx, &request.script_group.script);
+    let signers = args.signers.load(&request.config, Some(display))?;
+    let mut policy = args.policy.load()?;
+    if let Some(policy) = &policy {
+        let violations = policy.violations(&request, &signers)?;
+        for violation in &violations {
+            eprintln!("policy violation: {}", violation);
+        }
+        if !violations.is_empty() {
+            bail!("the policy refuses to sign {}", path.display());
+        }
+    }
+    request.sign(&MultisigScriptSigner::new(request.config.clone(), signers))?;
+    let output = args.output.as_ref().unwrap_or(path);
+    save_request(output, &request)?;
+    if let Some(policy) = &mut policy {
+        policy.record(&request)?;
+    }
+    print_status(output, &request, &[])
+}
+
+/// Sign the pending proposals of `--dir` approved at the prompt.
+fn sign_dir(args: &SignArgs) -> Result<()> {
+    let dir = args.dir.as_ref().expect("required without --request");
+    let mut pending = Vec::new();
+    for path in proposal_files(std::slice::from_ref(dir))? {
+        let request = load_request(&path)?;
+        if let Some(chain) = &args.chain {
+            request
+                .check_chain(chain)
+                .with_context(|| format!("{}", path.display()))?;
+        }
+        if !request.is_complete()? {
+            pending.push((path, request));
+        }
+    }
+    if pending.is_empty() {
+        emit(json!({ "kind": "empty" }), || {
+            println!("no pending proposal in {}", dir.display())
+        });
+        return Ok(());
+    }
+    let configs = args
+        .configs
+        .iter()
+        .map(|path| Ok((path, load_config_file(path)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut policy = args.policy.load()?;
+    for (index, (path, request)) in pending.iter().enumerate() {
+        let mut names = Names::none(&request.config);
+        for (config_path, file) in &configs {
+            if let Some(found) = Names::from_file(config_path, file, &request.config)? {
+                names = found;
+                break;
+            }
+        }
+        let summary = Summary::new(request, args.network, &names)?;
+        let violations: Vec<_> = match &policy {
+            Some(policy) => policy
+                .violations(request, &[])?
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            None => Vec::new(),
+        };
+        let value = json!({
+            "kind": "proposal",
+            "number": index + 1,
+            "file": path,
+            "tx_hash": format!("{:#x}", request.tx.hash()),
+            "from": summary.from,
+            "total": summary.total,
+            "payments": summary.payments,
+            "fee": request.fee,
+            "signed": summary.signed,
+            "violations": violations,
+        });
+        emit(value, || {
+            println!("#{} {}: {}", index + 1, path.display(), summary.spend());
+            let signed = if summary.signed.is_empty() {
+                "none".to_string()
+            } else {
+                summary.signed.join(", ")
+            };
+            println!("   {}; signed so far: {}", summary.requires, signed);
+            for violation in &violations {
+                println!("   policy violation: {}", violation);
+            }
+        });
+    }
+
+    let approved = if args.yes {
+        (0..pending.len()).collect()
+    } else {
+        ask_approved(pending.len())?
+    };
+    let mut signers: Vec<MultisigScriptSigner> = Vec::new();
+    for index in approved {
+        let (path, request) = &mut pending[index];
+        let position = signers.iter().position(|signer| {
+            signer.config().multisig_script() == request.config.multisig_script()
+        });
+        let signer = match position {
+            Some(position) => &signers[position],
+            None => {
+                let keys = args.signers.load(&request.config, None)?;
+                signers.push(MultisigScriptSigner::new(request.config.clone(), keys));
+                signers.last().expect("pushed above")
+            }
+        };
+        if let Some(policy) = &policy {
+            let violations = policy.violations(request, signer.signers())?;
+            if let Some(violation) = violations.first() {
+                let value = json!({
+                    "kind": "refused",
+                    "file": path,
+                    "violation": violation.to_string(),
+                });
+                emit(value, || {
+                    println!("{}: refused by the policy, {}", path.display(), violation)
+                });
+                continue;
+            }
+        }
+        request.sign(signer)?;
+        save_request(path, request)?;
+        if let Some(policy) = &mut policy {
+            policy.record(request)?;
+        }
+        let lock = request.lock()?;
+        let value = json!({
+            "kind": "signed",
+            "file": path,
+            "tx_hash": format!("{:#x}", request.tx.hash()),
+            "signatures": lock.filled_count(),
+            "slots": lock.signatures().len(),
+            "complete": request.is_complete()?,
+        });
+        emit(value, || {
+            println!(
+                "{}: {} of {} signatures",
+                path.display(),
+                lock.filled_count(),
+                lock.signatures().len()
+            )
+        });
+    }
+    Ok(())
+}
+
+/// The proposals of `1..=count` approved on stdin, by index.
+fn ask_approved(count: usize) -> Result<Vec<usize>> {
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        eprint!("sign which? all, none or their numbers, e.g. 1 3: ");
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => bail!("no answer, nothing signed"),
+        };
+        match parse_approved(line.trim(), count) {
+            Ok(approved) => return Ok(approved),
+            Err(err) => eprintln!("  {:#}", err),
+        }
+    }
+}
+
+fn parse_approved(answer: &str, count: usize) -> Result<Vec<usize>> {
+    match answer {
+        "all" => return Ok((0..count).collect()),
+        "none" | "" => return Ok(Vec::new()),
+        _ => {}
+    }
+    let mut approved = Vec::new();
+    for number in answer.split(|c: char| c == ',' || c.is_whitespace()) {
+        if number.is_empty() {
+            continue;
+        }
+        match number.parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => approved.push(number - 1),
+            _ => bail!("`{}` is not a number from 1 to {}", number, count),
+        }
+    }
+    approved.sort_unstable();
+    approved.dedup();
+    Ok(approved)
+}
+
+pub fn combine(args: CombineArgs) -> Result<()> {
+    let mut paths = args.requests.iter();
+    let first = paths.next().expect("required argument");
+    let mut request = load_request(first)?;
+    let mut added = Vec::new();
+    for path in paths {
+        for identity in request.combine(&load_request(path)?)? {
+            added.push((identity, path.as_path()));
+        }
+    }
+    save_request(&args.output, &request)?;
+    print_status(&args.output, &request, &added)
+}
+
+pub fn send(args: SendArgs) -> Result<()> {
+    let request = load_request(&args.request)?;
+    let hash = request.send(&CkbRpcClient::new(&args.rpc))?;
+    emit(json!({ "tx_hash": format!("{:#x}", hash) }), || {
+        println!("{:#x}", hash)
+    });
+    Ok(())
+}
+
+/// The signatures of `request`, saved to `path`, and those `added` from the
+/// copies combined.
+fn print_status(
+    path: &Path,
+    request: &SigningRequest,
+    added: &[([u8; BLAKE160_SIZE], &Path)],
+) -> Result<()> {
+    let lock = request.lock()?;
+    let value = json!({
+        "output": path,
+        "tx_hash": format!("{:#x}", request.tx.hash()),
+        "signatures": lock.filled_count(),
+        "slots": lock.signatures().len(),
+        "complete": request.is_complete()?,
+        "added": added
+            .iter()
+            .map(|(identity, from)| {
+                json!({ "pubkey_hash": format!("0x{}", hex::encode(identity)), "from": from })
+            })
+            .collect::<Vec<_>>(),
+    });
+    emit(value, || {
+        for (identity, from) in added {
+            println!("0x{} from {}", hex::encode(identity), from.display());
+        }
+        println!(
+            "{}: {} of {} signatures",
+            path.display(),
+            lock.filled_count(),
+            lock.signatures().len()
+        );
+    });
+    Ok(())
+}
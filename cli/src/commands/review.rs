@@ -0,0 +1,314 @@
+//! `ckb-multisig review`: a terminal screen to sign the pending proposals,
+//! for the cosigners approving several a day.
+//!
+//! ```text
+//! ckb-multisig review --proposals proposals/ --config treasury.toml --key alice
+//! ```
+//!
+//! The proposals not yet complete are listed, and the selected one is shown
+//! as `inspect-tx` does, with the signature of each member:
+//!
+//! ```text
+//! > payroll.json   1 of 3  spend 12,345 CKB from treasury
+//!   sweep.json     0 of 2  spend 80 CKB from ops
+//!
+//! payroll.json 0x3c1a...
+//! spend 12,345 CKB from treasury: 12,000 CKB to ckb1..., 344.9 CKB change back, 0.1 CKB fee
+//! requires 3 of 5, including alice
+//!   [x] alice
+//!   [ ] bob
+//!   ...
+//!
+//! up/down or j/k select, s sign, r reject, g reload, q quit
+//! ```
+//!
+//! `s` signs the selected proposal in place with the keys given, as `sign`
+//! does, the keystore passwords asked for each signature and the proposal
+//! refused when it breaks `--policy`. `r` rejects it: the file is moved to
+//! the `rejected` directory next to it, out of the way of the commands and
+//! of the server reading the proposals, and is moved back to revive it. `g`
+//! reads the proposals again, with the signatures of the other cosigners.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use ckb_multisig_sdk::{
+    cobuild::{self, format_capacity},
+    config_file::ConfigFile,
+    request::SigningRequest,
+    unlock::MultisigScriptSigner,
+};
+use ckb_sdk::NetworkType;
+use clap::Args;
+
+use super::inspect::{Names, Summary};
+use crate::{
+    terminal::{Key, Screen},
+    util::{
+        json_output, load_config_file, load_request, parse_network, proposal_files, save_request,
+        PolicyArgs, SignerArgs,
+    },
+};
+
+const HELP: &str = "up/down or j/k select, s sign, r reject, g reload, q quit";
+const REJECTED: &str = "rejected";
+
+#[derive(Args)]
+pub struct ReviewArgs {
+    /// Proposal file, or directory of proposal files, can be repeated
+    #[arg(long = "proposals", required = true)]
+    proposals: Vec<PathBuf>,
+
+    /// Config file, for the names of its proposals and members, can be
+    /// repeated
+    #[arg(long = "config")]
+    configs: Vec<PathBuf>,
+
+    /// mainnet or testnet, the network of the addresses
+    #[arg(long, default_value = "mainnet", value_parser = parse_network)]
+    network: NetworkType,
+
+    #[command(flatten)]
+    signers: SignerArgs,
+
+    #[command(flatten)]
+    policy: PolicyArgs,
+}
+
+/// A pending proposal.
+struct Entry {
+    path: PathBuf,
+    request: SigningRequest,
+    names: Names,
+}
+
+struct Review {
+    args: ReviewArgs,
+    configs: Vec<(PathBuf, ConfigFile)>,
+    entries: Vec<Entry>,
+    selected: usize,
+    status: String,
+}
+
+pub fn run(args: ReviewArgs) -> Result<()> {
+    if json_output() {
+        bail!("review is interactive, it has no JSON output");
+    }
+    if !args.signers.any() {
+        bail!("no key to sign with");
+    }
+    let configs = args
+        .configs
+        .iter()
+        .map(|path| Ok((path.clone(), load_config_file(path)?)))
+        .collect::<Result<_>>()?;
+    let mut review = Review {
+        args,
+        configs,
+        entries: Vec::new(),
+        selected: 0,
+        status: String::new(),
+    };
+    review.reload()?;
+
+    let screen = Screen::enter()?;
+    loop {
+        screen.draw(&review.lines()?)?;
+        match screen.read_key()? {
+            Key::Up | Key::Char('k') => review.selected = review.selected.saturating_sub(1),
+            Key::Down | Key::Char('j') if review.selected + 1 < review.entries.len() => {
+                review.selected += 1
+            }
+            Key::Char('s') if !review.entries.is_empty() => {
+                screen.clear()?;
+                review.status = review.sign().unwrap_or_else(|err| format!("{:#}", err));
+            }
+            Key::Char('r') if !review.entries.is_empty() => {
+                review.status = review.reject().unwrap_or_else(|err| format!("{:#}", err));
+            }
+            Key::Char('g') => {
+                review.status = match review.reload() {
+                    Ok(()) => format!("{} pending", review.entries.len()),
+                    Err(err) => format!("{:#}", err),
+                };
+            }
+            Key::Char('q') | Key::Interrupt => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+impl Review {
+    /// Read the proposals again, the selection kept on the same file.
+    fn reload(&mut self) -> Result<()> {
+        let selected = self
+            .entries
+            .get(self.selected)
+            .map(|entry| entry.path.clone());
+        let mut entries = Vec::new();
+        for path in proposal_files(&self.args.proposals)? {
+            let request = load_request(&path)?;
+            if request.is_complete()? {
+                continue;
+            }
+            let names = self.names(&request)?;
+            entries.push(Entry {
+                path,
+                request,
+                names,
+            });
+        }
+        self.entries = entries;
+        self.selected = selected
+            .and_then(|selected| self.entries.iter().position(|entry| entry.path == selected))
+            .unwrap_or(0)
+            .min(self.entries.len().saturating_sub(1));
+        Ok(())
+    }
+
+    fn names(&self, request: &SigningRequest) -> Result<Names> {
+        for (path, file) in &self.configs {
+            if let Some(names) = Names::from_file(path, file, &request.config)? {
+                return Ok(names);
+            }
+        }
+        Ok(Names::none(&request.config))
+    }
+
+    fn lines(&self) -> Result<Vec<String>> {
+        let mut lines = vec![
+            format!("pending proposals: {}", self.entries.len()),
+            String::new(),
+        ];
+        let width = self
+            .entries
+            .iter()
+            .map(|entry| file_name(&entry.path).len())
+            .max()
+            .unwrap_or(0);
+        let mut summaries = Vec::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            let summary = Summary::new(&entry.request, self.args.network, &entry.names)?;
+            let config = &entry.request.config;
+            lines.push(format!(
+                "{} {:width$}  {} of {}  spend {} from {}",
+                if index == self.selected { ">" } else { " " },
+                file_name(&entry.path),
+                summary.signed.len(),
+                config.threshold(),
+                format_capacity(summary.total),
+                summary.from,
+                width = width
+            ));
+            summaries.push(summary);
+        }
+
+        if let (Some(entry), Some(summary)) = (
+            self.entries.get(self.selected),
+            summaries.get(self.selected),
+        ) {
+            let request = &entry.request;
+            let config = &request.config;
+            let signed = request.signed()?;
+            lines.push(String::new());
+            lines.push(format!(
+                "{} {:#x}",
+                file_name(&entry.path),
+                request.tx.hash()
+            ));
+            lines.push(summary.spend());
+            lines.push(summary.requires.clone());
+            for identity in config.pubkey_hashes() {
+                let mark = if signed.contains(identity) { "x" } else { " " };
+                lines.push(format!(
+                    "  [{}] {}",
+                    mark,
+                    entry.names.member(config, identity)
+                ));
+            }
+            if let Some(policy) = self.args.policy.load()? {
+                for violation in policy.violations(request, &[])? {
+                    lines.push(format!("policy violation: {}", violation));
+                }
+            }
+        }
+        lines.push(String::new());
+        lines.push(HELP.to_string());
+        lines.push(self.status.clone());
+        Ok(lines)
+    }
+
+    /// Sign the selected proposal, the keystore passwords asked for.
+    fn sign(&mut self) -> Result<String> {
+        let entry = &mut self.entries[self.selected];
+        let request = &mut entry.request;
+        let display = cobuild::message(&request.config, &request.tx, &request.script_group.script);
+        let signers = self.args.signers.load(&request.config, Some(display))?;
+        let mut policy = self.args.policy.load()?;
+        if let Some(policy) = &policy {
+            let violations = policy.violations(request, &signers)?;
+            if let Some(violation) = violations.first() {
+                bail!(
+                    "the policy refuses to sign {}: {}",
+                    file_name(&entry.path),
+                    violation
+                );
+            }
+        }
+        let before = request.signed()?.len();
+        request.sign(&MultisigScriptSigner::new(request.config.clone(), signers))?;
+        let signed = request.signed()?.len();
+        if signed == before {
+            bail!(
+                "no signature added to {}: the keys are not members, or signed already",
+                file_name(&entry.path)
+            );
+        }
+        save_request(&entry.path, request)?;
+        if let Some(policy) = &mut policy {
+            policy.record(request)?;
+        }
+        let status = if request.is_complete()? {
+            format!(
+                "{} complete, send it with `ckb-multisig send --request {}`",
+                file_name(&entry.path),
+                entry.path.display()
+            )
+        } else {
+            format!(
+                "{} signed, {} of {}",
+                file_name(&entry.path),
+                signed,
+                request.config.threshold()
+            )
+        };
+        self.reload()?;
+        Ok(status)
+    }
+
+    /// Move the selected proposal to the `rejected` directory next to it.
+    fn reject(&mut self) -> Result<String> {
+        let entry = &self.entries[self.selected];
+        let directory = entry
+            .path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(REJECTED);
+        let target = directory.join(file_name(&entry.path));
+        fs::create_dir_all(&directory)
+            .and_then(|_| fs::rename(&entry.path, &target))
+            .with_context(|| format!("move {} to {}", entry.path.display(), target.display()))?;
+        self.entries.remove(self.selected);
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+        Ok(format!("rejected, moved to {}", target.display()))
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
@@ -0,0 +1,333 @@
+//! `ckb-multisig simulate`: run the contract on a transaction locally.
+//!
+//! ```text
+//! ckb-multisig simulate --tx signed.json --binary build/release/ckb-multisig
+//! ckb-multisig simulate --tx mock_tx.json --binary build/release/ckb-multisig
+//! ```
+//!
+//! The transaction is a proposal, a transaction file of `decode-witness`, or
+//! a mock transaction of ckb-debugger which carries the cells it spends; the
+//! cells the file doesn't carry come from the node. The inputs of the lock
+//! run the given binary in place of the deployed contract: the input cells
+//! are not part of the transaction hash, the signatures stay valid.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ckb_chain_spec::consensus::{Consensus, ConsensusBuilder};
+use ckb_mock_tx_types::{MockResourceLoader, MockTransaction, ReprMockTransaction, Resource};
+use ckb_multisig_sdk::request::SigningRequest;
+use ckb_script::{ScriptError, ScriptGroupType, TransactionScriptsVerifier, TxVerifyEnv};
+use ckb_sdk::CkbRpcClient;
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        cell::{resolve_transaction, CellMetaBuilder, ResolvedTransaction},
+        hardfork::{HardForks, CKB2021, CKB2023},
+        Cycle, EpochNumberWithFraction, HeaderView, ScriptHashType, TransactionView,
+    },
+    packed::{self, Byte32, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use clap::Args;
+use serde_json::{json, Value};
+
+use crate::util::{emit, load_tx, parse_h256};
+
+/// Cycles limit of a transaction on the mainnet.
+pub const MAX_CYCLES: Cycle = 70_000_000;
+
+#[derive(Args)]
+pub struct SimulateArgs {
+    /// Proposal, transaction file or ckb-debugger mock transaction
+    #[arg(long)]
+    tx: PathBuf,
+
+    /// Contract binary to run, e.g. build/release/ckb-multisig
+    #[arg(long)]
+    binary: PathBuf,
+
+    /// Code hash of the locks the binary runs for, by default the lock of a
+    /// proposal, else the data hash of the binary
+    #[arg(long, value_parser = parse_h256)]
+    code_hash: Option<H256>,
+
+    /// RPC of a CKB node, for the cells the file doesn't carry
+    #[arg(long, default_value = "http://127.0.0.1:8114")]
+    rpc: String,
+
+    /// Cycles limit of a lock group
+    #[arg(long, default_value_t = MAX_CYCLES)]
+    max_cycles: Cycle,
+}
+
+pub fn run(args: SimulateArgs) -> Result<()> {
+    let binary =
+        fs::read(&args.binary).with_context(|| format!("read {}", args.binary.display()))?;
+    let simulator = Simulator::new(Bytes::from(binary), args.max_cycles);
+    let (mock, lock) = load_mock(&args)?;
+    let code_hash = args.code_hash.map(|hash| hash.pack());
+    let outcomes = simulator.run(&mock, &mut RpcLoader::new(&args.rpc), |script| {
+        match (&code_hash, &lock) {
+            (Some(code_hash), _) => script.code_hash() == *code_hash,
+            (None, Some(lock)) => script == lock,
+            (None, None) => simulator.runs(script),
+        }
+    })?;
+    if outcomes.is_empty() {
+        bail!("no input is locked by the contract, pass its code hash with --code-hash");
+    }
+
+    let mut failed = false;
+    for outcome in &outcomes {
+        failed |= outcome.result.is_err();
+        let value = json!({
+            "script_hash": format!("{:#x}", outcome.script_hash),
+            "inputs": outcome.inputs,
+            "debug": outcome.debug,
+            "cycles": outcome.result.as_ref().ok(),
+            "exit_code": outcome.exit_code(),
+            "error": outcome.result.as_ref().err().map(ToString::to_string),
+        });
+        emit(value, || {
+            println!(
+                "lock {:#x}, inputs {:?}",
+                outcome.script_hash, outcome.inputs
+            );
+            for line in &outcome.debug {
+                println!("  debug: {}", line);
+            }
+            match &outcome.result {
+                Ok(cycles) => println!("  ok, {} cycles", cycles),
+                Err(err) => match outcome.exit_code() {
+                    Some(code) => println!("  failed with exit code {}: {}", code, err),
+                    None => println!("  failed: {}", err),
+                },
+            }
+        });
+    }
+    if failed {
+        bail!("the transaction doesn't pass the contract");
+    }
+    Ok(())
+}
+
+/// The mock transaction of the file, and the lock of a proposal.
+fn load_mock(args: &SimulateArgs) -> Result<(MockTransaction, Option<Script>)> {
+    let path = &args.tx;
+    let content = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let file: Value =
+        serde_json::from_str(&content).with_context(|| format!("parse {}", path.display()))?;
+    if file.get("mock_info").is_some() {
+        let mock: ReprMockTransaction = serde_json::from_value(file)
+            .with_context(|| format!("invalid mock transaction in {}", path.display()))?;
+        return Ok((mock.into(), None));
+    }
+    let (tx, lock) = match SigningRequest::from_json(&content) {
+        Ok(request) => (request.tx, Some(request.script_group.script)),
+        Err(_) => (load_tx(path)?, None),
+    };
+    Ok((mock_tx(tx), lock))
+}
+
+/// A mock transaction without any cell, they are all loaded.
+pub fn mock_tx(tx: TransactionView) -> MockTransaction {
+    MockTransaction {
+        mock_info: Default::default(),
+        tx: tx.data(),
+    }
+}
+
+/// The cells and headers of the node.
+pub struct RpcLoader {
+    client: CkbRpcClient,
+}
+
+impl RpcLoader {
+    pub fn new(url: &str) -> Self {
+        RpcLoader {
+            client: CkbRpcClient::new(url),
+        }
+    }
+}
+
+impl MockResourceLoader for RpcLoader {
+    fn get_header(&mut self, hash: H256) -> Result<Option<HeaderView>, String> {
+        self.client
+            .get_header(hash)
+            .map(|header| header.map(Into::into))
+            .map_err(|err| err.to_string())
+    }
+
+    fn get_live_cell(
+        &mut self,
+        out_point: OutPoint,
+    ) -> Result<Option<(CellOutput, Bytes, Option<Byte32>)>, String> {
+        let cell = self
+            .client
+            .get_live_cell(out_point.into(), true)
+            .map_err(|err| err.to_string())?
+            .cell;
+        Ok(cell.map(|cell| {
+            let data = cell
+                .data
+                .map(|data| data.content.into_bytes())
+                .unwrap_or_default();
+            (cell.output.into(), data, None)
+        }))
+    }
+}
+
+/// The result of a lock group.
+pub struct Outcome {
+    pub script_hash: Byte32,
+    pub inputs: Vec<usize>,
+    /// The cycles consumed.
+    pub result: Result<Cycle, ScriptError>,
+    /// What the script printed with `debug!`.
+    pub debug: Vec<String>,
+}
+
+impl Outcome {
+    pub fn exit_code(&self) -> Option<i8> {
+        match &self.result {
+            Err(ScriptError::ValidationFailure(_, code)) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// Runs a contract binary on transactions, with every hardfork active.
+pub struct Simulator {
+    binary: Bytes,
+    max_cycles: Cycle,
+    consensus: Arc<Consensus>,
+    env: Arc<TxVerifyEnv>,
+}
+
+impl Simulator {
+    pub fn new(binary: Bytes, max_cycles: Cycle) -> Self {
+        let hardforks = HardForks {
+            ckb2021: CKB2021::new_dev_default(),
+            ckb2023: CKB2023::new_dev_default(),
+        };
+        let consensus = ConsensusBuilder::default()
+            .hardfork_switch(hardforks)
+            .build();
+        let header = HeaderView::new_advanced_builder()
+            .epoch(EpochNumberWithFraction::new(0, 0, 1).pack())
+            .build();
+        Simulator {
+            binary,
+            max_cycles,
+            consensus: Arc::new(consensus),
+            env: Arc::new(TxVerifyEnv::new_commit(&header)),
+        }
+    }
+
+    /// The data hash of the binary.
+    pub fn code_hash(&self) -> Byte32 {
+        CellOutput::calc_data_hash(&self.binary)
+    }
+
+    pub fn max_block_cycles(&self) -> Cycle {
+        self.consensus.max_block_cycles()
+    }
+
+    /// Whether the script references the binary by its data hash.
+    pub fn runs(&self, script: &Script) -> bool {
+        script.code_hash() == self.code_hash() && script.hash_type() != ScriptHashType::Type.into()
+    }
+
+    /// Run the binary for the inputs whose lock is `selected`, each lock in
+    /// its own group. The locks of the other inputs are not run.
+    pub fn run<L, F>(
+        &self,
+        mock: &MockTransaction,
+        loader: &mut L,
+        selected: F,
+    ) -> Result<Vec<Outcome>>
+    where
+        L: MockResourceLoader,
+        F: Fn(&Script) -> bool,
+    {
+        let resource = Resource::from_both(mock, loader).map_err(|err| anyhow!(err))?;
+        let mut rtx = resolve_transaction(
+            mock.core_transaction(),
+            &mut HashSet::new(),
+            &resource,
+            &resource,
+        )
+        .map_err(|err| anyhow!("resolve the transaction: {}", err))?;
+
+        let groups = self.replace_locks(&mut rtx, selected);
+        let debug = Arc::new(Mutex::new(Vec::new()));
+        let printer = {
+            let debug = Arc::clone(&debug);
+            Arc::new(move |_: &Byte32, message: &str| {
+                debug
+                    .lock()
+                    .expect("poisoned lock")
+                    .push(message.to_string());
+            })
+        };
+        let verifier = TransactionScriptsVerifier::new_with_debug_printer(
+            Arc::new(rtx),
+            resource,
+            Arc::clone(&self.consensus),
+            Arc::clone(&self.env),
+            printer,
+        );
+        Ok(groups
+            .into_iter()
+            .map(|(script_hash, inputs)| {
+                let result =
+                    verifier.verify_single(ScriptGroupType::Lock, &script_hash, self.max_cycles);
+                let debug = std::mem::take(&mut *debug.lock().expect("poisoned lock"));
+                Outcome {
+                    script_hash,
+                    inputs,
+                    result,
+                    debug,
+                }
+            })
+            .collect())
+    }
+
+    /// Point the selected input locks at the binary, added as the last cell
+    /// dep. Returns the new lock groups with their inputs.
+    fn replace_locks<F>(
+        &self,
+        rtx: &mut ResolvedTransaction,
+        selected: F,
+    ) -> BTreeMap<Byte32, Vec<usize>>
+    where
+        F: Fn(&Script) -> bool,
+    {
+        let code_hash = self.code_hash();
+        let mut groups: BTreeMap<Byte32, Vec<usize>> = BTreeMap::new();
+        for (i, input) in rtx.resolved_inputs.iter_mut().enumerate() {
+            let lock = input.cell_output.lock();
+            if !selected(&lock) {
+                continue;
+            }
+            let lock = lock
+                .as_builder()
+                .code_hash(code_hash.clone())
+                .hash_type(ScriptHashType::Data1)
+                .build();
+            groups.entry(lock.calc_script_hash()).or_default().push(i);
+            input.cell_output = input.cell_output.clone().as_builder().lock(lock).build();
+        }
+        let output = packed::CellOutput::new_builder().build();
+        rtx.resolved_cell_deps
+            .push(CellMetaBuilder::from_cell_output(output, self.binary.clone()).build());
+        groups
+    }
+}
@@ -0,0 +1,265 @@
+//! `ckb-multisig verify`: check a signed transaction offline.
+//!
+//! ```text
+//! ckb-multisig verify --tx signed.json --config config.toml
+//! ckb-multisig verify --tx tx.json --config config.toml --input 0 --input 1
+//! ```
+//!
+//! Everything comes from the files, for air-gapped machines: the signing
+//! message is computed again from the transaction, each signature must
+//! recover to a different member, the threshold and the first members must
+//! be met, and the group inputs must satisfy the since of the lock args.
+//! Under strict witnesses the other witnesses of the group must be empty,
+//! and a config spending all its cells at once must declare as many cells as
+//! the group spends. Under legacy witnesses the lock field may follow
+//! placeholders, as older wallets lay it out. The inputs of the group are
+//! those of a proposal, or given with `--input`.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use ckb_multisig_sdk::{
+    constants::BLAKE160_SIZE,
+    digest::{generate_legacy_message, generate_message},
+    signer::recover_pubkey,
+    since::{format_since, merge_since, SinceSpec},
+    witness::{
+        legacy_lock_index, legacy_stuffed_witnesses, spend_all_total, stuffed_witnesses,
+        witness_args,
+    },
+    MultisigConfig, MultisigLock,
+};
+use ckb_types::{core::TransactionView, prelude::*};
+use clap::Args;
+use serde_json::json;
+
+use crate::util::{emit, load_config, load_request, load_tx};
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// Proposal, or transaction file in the node RPC or `ckb-cli tx` format
+    #[arg(long)]
+    tx: PathBuf,
+
+    /// Config file the transaction must be signed for
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Input of the script group, can be repeated, those of the proposal by
+    /// default
+    #[arg(long = "input")]
+    inputs: Vec<usize>,
+}
+
+/// The outcome of the checks, printed as they are made.
+#[derive(Default)]
+struct Report {
+    failed: bool,
+}
+
+impl Report {
+    fn check(&mut self, passed: bool, what: &str) {
+        let value = json!({ "kind": "check", "passed": passed, "what": what });
+        emit(value, || {
+            println!("{} {}", if passed { "ok  " } else { "FAIL" }, what)
+        });
+        self.failed |= !passed;
+    }
+
+    /// Something learnt on the way, e.g. the signing message.
+    fn note(&self, what: &str) {
+        let value = json!({ "kind": "note", "what": what });
+        emit(value, || println!("     {}", what));
+    }
+}
+
+pub fn run(args: VerifyArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    let mut report = Report::default();
+    let (tx, inputs) = match load_request(&args.tx) {
+        Ok(request) => {
+            let lock_args = request.script_group.script.args().raw_data();
+            report.check(
+                lock_args == config.lock_args(),
+                &format!("lock args 0x{} of the proposal", hex::encode(&lock_args)),
+            );
+            let inputs = if args.inputs.is_empty() {
+                request.script_group.input_indices.clone()
+            } else {
+                args.inputs.clone()
+            };
+            (request.tx, inputs)
+        }
+        Err(_) if args.inputs.is_empty() => bail!(
+            "{} is not a proposal, pass the inputs of the group with --input",
+            args.tx.display()
+        ),
+        Err(_) => (load_tx(&args.tx)?, args.inputs.clone()),
+    };
+
+    verify(&mut report, &tx, &inputs, &config)?;
+    if report.failed {
+        bail!("the transaction doesn't unlock the config");
+    }
+    let value = json!({ "kind": "result", "unlocks": true });
+    emit(value, || println!("the transaction unlocks the config"));
+    Ok(())
+}
+
+fn verify(
+    report: &mut Report,
+    tx: &TransactionView,
+    inputs: &[usize],
+    config: &MultisigConfig,
+) -> Result<()> {
+    if let Some(index) = inputs.iter().find(|index| **index >= tx.inputs().len()) {
+        bail!("input #{} out of range", index);
+    }
+    let lock_index = if config.legacy_witnesses() {
+        legacy_lock_index(tx, inputs).unwrap_or(inputs[0])
+    } else {
+        inputs[0]
+    };
+    let lock = match witness_args(tx, lock_index)?.lock().to_opt() {
+        Some(lock) => MultisigLock::parse(&lock.raw_data())?,
+        None => bail!("the witness of input #{} has no lock field", lock_index),
+    };
+    report.check(
+        lock.config().multisig_script() == config.multisig_script(),
+        "the witness holds the multisig script of the config",
+    );
+
+    let message = if config.legacy_witnesses() {
+        generate_legacy_message(tx, inputs)?
+    } else {
+        generate_message(tx, inputs)?
+    };
+    report.note(&format!("signing message 0x{}", hex::encode(message)));
+    let mut signers: Vec<[u8; BLAKE160_SIZE]> = Vec::new();
+    for (slot, signature) in lock.filled().enumerate() {
+        let identity = match recover_pubkey(&message, signature) {
+            Ok(pubkey) => config.identity(&pubkey),
+            Err(err) => {
+                report.check(false, &format!("signature #{}: {}", slot, err));
+                continue;
+            }
+        };
+        let what = match config.position(&identity) {
+            None => format!(
+                "signature #{} by 0x{}, not a member",
+                slot,
+                hex::encode(identity)
+            ),
+            Some(_) if signers.contains(&identity) => {
+                format!(
+                    "signature #{} by 0x{}, duplicated",
+                    slot,
+                    hex::encode(identity)
+                )
+            }
+            Some(position) => format!(
+                "signature #{} by member #{} 0x{}",
+                slot,
+                position,
+                hex::encode(identity)
+            ),
+        };
+        report.check(
+            config.position(&identity).is_some() && !signers.contains(&identity),
+            &what,
+        );
+        signers.push(identity);
+    }
+
+    let threshold = usize::from(config.threshold());
+    report.check(
+        lock.filled_count() == threshold,
+        &format!(
+            "{} signatures, threshold {}",
+            lock.filled_count(),
+            threshold
+        ),
+    );
+    let first_n = usize::from(config.require_first_n());
+    if first_n > 0 {
+        let missing: Vec<_> = (0..first_n)
+            .filter(|i| !signers.contains(&config.pubkey_hashes()[*i]))
+            .map(|i| format!("#{}", i))
+            .collect();
+        let what = if missing.is_empty() {
+            format!("the first {} members signed", first_n)
+        } else {
+            format!(
+                "the first {} members must sign, missing {}",
+                first_n,
+                missing.join(", ")
+            )
+        };
+        report.check(missing.is_empty(), &what);
+    }
+    // the checks above should agree with the contract
+    if !report.failed {
+        if let Err(err) = lock.verify(&message) {
+            report.check(false, &err.to_string());
+        }
+    }
+
+    for index in inputs {
+        let since: u64 = tx.inputs().get(*index).expect("checked").since().unpack();
+        if since != 0 && SinceSpec::from_value(since).is_err() {
+            report.check(false, &format!("input #{}: {}", index, format_since(since)));
+            continue;
+        }
+        match config.since() {
+            Some(lock_since) if lock_since != 0 => {
+                let satisfied = merge_since(Some(lock_since), since).ok() == Some(since);
+                report.check(
+                    satisfied,
+                    &format!(
+                        "input #{} since 0x{:016x}, the lock args require {}",
+                        index,
+                        since,
+                        format_since(lock_since)
+                    ),
+                );
+            }
+            _ if since != 0 => {
+                report.note(&format!("input #{} {}", index, format_since(since)));
+            }
+            _ => {}
+        }
+    }
+    if config.strict_witnesses() {
+        let stuffed = if config.legacy_witnesses() {
+            legacy_stuffed_witnesses(tx, inputs)
+        } else {
+            stuffed_witnesses(tx, inputs)
+        };
+        let what = if stuffed.is_empty() {
+            "the other witnesses of the group are empty".to_string()
+        } else {
+            let stuffed: Vec<_> = stuffed.iter().map(|i| format!("#{}", i)).collect();
+            format!(
+                "the witnesses of the group must be empty, but that of the lock field, not {}",
+                stuffed.join(", ")
+            )
+        };
+        report.check(stuffed.is_empty(), &what);
+    }
+    if config.spend_all() {
+        let declared = spend_all_total(tx, inputs[0])?;
+        let what = match declared {
+            Some(total) => format!(
+                "the witness declares {} live cells, the group spends {}",
+                total,
+                inputs.len()
+            ),
+            None => "the witness must declare the count of live cells".to_string(),
+        };
+        report.check(
+            declared.map(|total| total as usize) == Some(inputs.len()),
+            &what,
+        );
+    }
+    Ok(())
+}
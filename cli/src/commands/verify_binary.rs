@@ -0,0 +1,124 @@
+//! `ckb-multisig verify-binary`: the deployed contract is the audited code.
+//!
+//! ```text
+//! ckb-multisig verify-binary --deployments deployments.toml --network mainnet --build
+//! ckb-multisig verify-binary --cell-dep <tx hash>:0 --artifact ckb-multisig
+//! ```
+//!
+//! Compares the data hash of the code cell on chain with a local binary,
+//! either an artifact given or the one `capsule build --release` rebuilds
+//! from the sources in `--workspace`, the audited tag checked out there. The
+//! cell is the one of the registry entry, its dep group member matching the
+//! code hash, or the cell dep given, its dep group member holding the
+//! artifact. A mismatch fails the command.
+//!
+//! The contracts build in the capsule docker image, whose toolchain is
+//! pinned in `capsule.toml`, so that a rebuild yields the same bytes.
+
+use std::{fs, path::PathBuf, process::Command};
+
+use anyhow::{bail, Context, Result};
+use ckb_multisig_sdk::{
+    deploy::{verify_cell_dep, verify_deployed},
+    deployment::Network,
+};
+use ckb_sdk::CkbRpcClient;
+use ckb_types::packed::CellDep;
+use clap::Args;
+use serde_json::json;
+
+use crate::util::{emit, load_registry, parse_cell_dep};
+
+#[derive(Args)]
+pub struct VerifyBinaryArgs {
+    /// RPC of a CKB node
+    #[arg(long, default_value = "http://127.0.0.1:8114")]
+    rpc: String,
+
+    /// Registry file of the deployments of the lock, TOML or JSON
+    #[arg(long, requires = "network", required_unless_present = "cell_dep")]
+    deployments: Option<PathBuf>,
+
+    /// Network to look up in the registry: mainnet, testnet or devnet
+    #[arg(long, requires = "deployments")]
+    network: Option<Network>,
+
+    /// Cell dep of the contract, as `tx_hash:index` or
+    /// `tx_hash:index:dep_group`, instead of the registry
+    #[arg(long, value_parser = parse_cell_dep, conflicts_with = "deployments")]
+    cell_dep: Option<CellDep>,
+
+    /// Local build of the contract
+    #[arg(long, required_unless_present = "build", conflicts_with = "build")]
+    artifact: Option<PathBuf>,
+
+    /// Rebuild the contract with `capsule build --release` instead
+    #[arg(long)]
+    build: bool,
+
+    /// Directory of `capsule.toml`, where the contract is rebuilt
+    #[arg(long, default_value = ".")]
+    workspace: PathBuf,
+
+    /// Contract to rebuild, as named in `capsule.toml`
+    #[arg(long, default_value = "ckb-multisig")]
+    contract: String,
+}
+
+pub fn run(args: VerifyBinaryArgs) -> Result<()> {
+    let path = match &args.artifact {
+        Some(path) => path.clone(),
+        None => build(&args)?,
+    };
+    let artifact = fs::read(&path).with_context(|| format!("read {}", path.display()))?;
+    let client = CkbRpcClient::new(&args.rpc);
+    let (hash, source) = match (&args.cell_dep, &args.deployments, args.network) {
+        (Some(cell_dep), _, _) => (
+            verify_cell_dep(&client, cell_dep, &artifact)?,
+            format!("cell dep {}", cell_dep.out_point()),
+        ),
+        (None, Some(deployments), Some(network)) => {
+            let registry = load_registry(deployments)?;
+            let deployment = registry.get(network)?;
+            (
+                verify_deployed(&client, deployment, &artifact)?,
+                format!(
+                    "{} deployment of code hash {:#x}",
+                    network, deployment.code_hash
+                ),
+            )
+        }
+        _ => bail!("pass --cell-dep or --deployments and --network"),
+    };
+    let value = json!({
+        "source": source,
+        "artifact": path,
+        "data_hash": format!("{:#x}", hash),
+    });
+    emit(value, || {
+        println!(
+            "the {} holds {}, data hash {:#x}",
+            source,
+            path.display(),
+            hash
+        )
+    });
+    Ok(())
+}
+
+/// Rebuild the contract, returning the path of its binary.
+fn build(args: &VerifyBinaryArgs) -> Result<PathBuf> {
+    let status = Command::new("capsule")
+        .args(["build", "--release"])
+        .current_dir(&args.workspace)
+        .status()
+        .context("run capsule, is it installed?")?;
+    if !status.success() {
+        bail!("capsule build failed: {}", status);
+    }
+    Ok(args
+        .workspace
+        .join("build")
+        .join("release")
+        .join(&args.contract))
+}
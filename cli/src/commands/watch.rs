@@ -0,0 +1,220 @@
+//! `ckb-multisig watch`: follow the configs on chain, continuously.
+//!
+//! ```text
+//! ckb-multisig watch --config treasury.toml --proposals proposals/ --state watch.json --deployments ...
+//! ```
+//!
+//! Every confirmed deposit to a config and every spend of its cells is
+//! printed, and recorded in the state file, a JSON database of the events by
+//! lock script hash and of the next block to watch, so a restart resumes
+//! where the last poll ended. A spend is expected when its transaction is
+//! one of the proposals given, files or directories of proposal files such
+//! as the `--dir` of the coordination server, read again on every poll. Any
+//! other spend is reported on stderr as unexpected: a key may have leaked.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use ckb_multisig_sdk::watcher::{
+    Activity, Deposit, DepositWatcher, Spend, DEFAULT_CONFIRMATIONS, DEFAULT_INTERVAL,
+};
+use ckb_types::{prelude::*, H256};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::util::{emit, format_ckb, load_config, load_proposals, ChainArgs};
+
+#[derive(Args)]
+pub struct WatchArgs {
+    #[command(flatten)]
+    chain: ChainArgs,
+
+    /// Config file to watch, can be repeated
+    #[arg(long = "config", required = true)]
+    configs: Vec<PathBuf>,
+
+    /// Proposal file, or directory of proposal files, whose transactions
+    /// are expected spends, can be repeated
+    #[arg(long = "proposals")]
+    proposals: Vec<PathBuf>,
+
+    /// State file, JSON, created when missing
+    #[arg(long)]
+    state: PathBuf,
+
+    /// Blocks on top of a transaction before it is reported
+    #[arg(long, default_value_t = DEFAULT_CONFIRMATIONS)]
+    confirmations: u64,
+
+    /// Seconds between two polls
+    #[arg(long, default_value_t = DEFAULT_INTERVAL.as_secs())]
+    interval: u64,
+
+    /// Poll once and exit, e.g. from cron
+    #[arg(long)]
+    once: bool,
+}
+
+/// The state file.
+#[derive(Default, Serialize, Deserialize)]
+struct WatchState {
+    /// By `0x` lock script hash.
+    locks: BTreeMap<String, LockState>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct LockState {
+    config: PathBuf,
+    next_block: u64,
+    events: Vec<Event>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Event {
+    Deposit {
+        tx_hash: H256,
+        index: u32,
+        block_number: u64,
+        capacity: u64,
+    },
+    Spend {
+        tx_hash: H256,
+        block_number: u64,
+        inputs: usize,
+        outgoing: u64,
+        expected: bool,
+    },
+}
+
+impl WatchState {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(WatchState::default());
+        }
+        let content =
+            fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("parse {}", path.display()))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .and_then(|_| fs::rename(&tmp, path))
+            .with_context(|| format!("write {}", path.display()))
+    }
+}
+
+pub fn run(args: WatchArgs) -> Result<()> {
+    let chain = args.chain.resolve()?;
+    let mut state = WatchState::load(&args.state)?;
+    let mut watchers = Vec::new();
+    for path in &args.configs {
+        let config = load_config(path)?;
+        let script_hash: H256 = chain.lock_script(&config).calc_script_hash().unpack();
+        let key = format!("{:#x}", script_hash);
+        let lock = state.locks.entry(key.clone()).or_default();
+        lock.config = path.clone();
+        // a light client syncs the lock from the first block to watch on
+        chain
+            .connect()
+            .watch(&chain.lock_script(&config), lock.next_block)?;
+        let watcher = DepositWatcher::with_backend(
+            chain.connect(),
+            &config,
+            &chain.code_hash,
+            chain.hash_type,
+        )
+        .from_block(lock.next_block)
+        .confirmations(args.confirmations);
+        watchers.push((key, path.display().to_string(), watcher));
+    }
+    loop {
+        let proposals: BTreeSet<H256> = load_proposals(&args.proposals)?
+            .iter()
+            .map(|request| request.tx.hash().unpack())
+            .collect();
+        for (key, name, watcher) in &mut watchers {
+            let activity = watcher.poll_activity()?;
+            let lock = state.locks.get_mut(key).expect("inserted above");
+            for activity in activity {
+                let event = match activity {
+                    Activity::Deposit(deposit) => report_deposit(name, &deposit),
+                    Activity::Spend(spend) => report_spend(name, &spend, &proposals),
+                };
+                lock.events.push(event);
+            }
+            lock.next_block = watcher.next_block();
+        }
+        state.save(&args.state)?;
+        if args.once {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(args.interval));
+    }
+}
+
+fn report_deposit(name: &str, deposit: &Deposit) -> Event {
+    let tx_hash: H256 = deposit.out_point.tx_hash().unpack();
+    let index: u32 = deposit.out_point.index().unpack();
+    let event = Event::Deposit {
+        tx_hash: tx_hash.clone(),
+        index,
+        block_number: deposit.block_number,
+        capacity: deposit.capacity(),
+    };
+    emit(event_value(name, &event), || {
+        println!(
+            "{}: deposit {:#x}:{} of {} at block {}",
+            name,
+            tx_hash,
+            index,
+            format_ckb(deposit.capacity()),
+            deposit.block_number
+        )
+    });
+    event
+}
+
+fn report_spend(name: &str, spend: &Spend, proposals: &BTreeSet<H256>) -> Event {
+    let tx_hash = spend.tx_hash();
+    let expected = proposals.contains(&tx_hash);
+    let line = format!(
+        "{}: spend {:#x} of {} cells, {} out, at block {}",
+        name,
+        tx_hash,
+        spend.inputs,
+        format_ckb(spend.outgoing),
+        spend.block_number
+    );
+    if !expected {
+        eprintln!("unexpected {}, not from any proposal", line);
+    }
+    let event = Event::Spend {
+        tx_hash,
+        block_number: spend.block_number,
+        inputs: spend.inputs,
+        outgoing: spend.outgoing,
+        expected,
+    };
+    emit(event_value(name, &event), || {
+        if expected {
+            println!("{}", line);
+        }
+    });
+    event
+}
+
+/// `event` as recorded in the state file, with the config it is of.
+fn event_value(name: &str, event: &Event) -> Value {
+    let mut value = serde_json::to_value(event).expect("plain fields");
+    value["config"] = json!(name);
+    value
+}
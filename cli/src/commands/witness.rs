@@ -0,0 +1,189 @@
+//! `ckb-multisig decode-witness`: what the contract sees in a witness.
+//!
+//! ```text
+//! ckb-multisig decode-witness --witness 0x...
+//! ckb-multisig decode-witness --tx tx.json --input 0 --input 1
+//! ```
+//!
+//! Prints the flags, the members and the signature slots of the lock field.
+//! With the transaction, the signing message is computed too and each
+//! signature is matched against the members, as the contract would.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Result};
+use ckb_multisig_sdk::{
+    constants::{BLAKE160_SIZE, FLAGS_SIZE},
+    digest::generate_message,
+    signer::recover_pubkey,
+    MultisigLock,
+};
+use ckb_types::{bytes::Bytes, packed, prelude::*};
+use clap::{ArgGroup, Args};
+use serde_json::json;
+
+use crate::util::{emit, load_tx};
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("source").required(true).args(["witness", "tx"])))]
+pub struct DecodeWitnessArgs {
+    /// Witness in hex, a `WitnessArgs` or a bare lock field
+    #[arg(long)]
+    witness: Option<String>,
+
+    /// Transaction file in the node RPC format, a `ckb-cli tx` file or a proposal
+    #[arg(long)]
+    tx: Option<PathBuf>,
+
+    /// Input of the script group, can be repeated, the first carries the lock field
+    #[arg(long = "input", default_value = "0")]
+    inputs: Vec<usize>,
+}
+
+pub fn run(args: DecodeWitnessArgs) -> Result<()> {
+    let (witness, tx) = match (&args.witness, &args.tx) {
+        (Some(witness), _) => (parse_hex(witness)?, None),
+        (None, Some(path)) => {
+            let tx = load_tx(path)?;
+            let index = args.inputs[0];
+            if index >= tx.inputs().len() {
+                bail!("input #{} out of range", index);
+            }
+            let witness = tx
+                .witnesses()
+                .get(index)
+                .ok_or_else(|| anyhow!("input #{} has no witness", index))?
+                .raw_data();
+            (witness, Some(tx))
+        }
+        (None, None) => unreachable!("required argument group"),
+    };
+
+    let lock = lock_field(&witness)?;
+    if lock.len() < FLAGS_SIZE {
+        bail!(
+            "lock field of {} bytes, too short for the flags",
+            lock.len()
+        );
+    }
+    let flags = [lock[0], lock[1], lock[2], lock[3]];
+    let lock = MultisigLock::parse(&lock)?;
+    let config = lock.config();
+    let pubkey_hashes: Vec<_> = config
+        .pubkey_hashes()
+        .iter()
+        .map(|hash| format!("0x{}", hex::encode(hash)))
+        .collect();
+
+    let message = match &tx {
+        Some(tx) => Some(generate_message(tx, &args.inputs)?),
+        None => None,
+    };
+    let mut signers = Vec::new();
+    let mut slots = Vec::new();
+    for signature in lock.signatures() {
+        if signature.iter().all(|b| *b == 0) {
+            slots.push((None, None));
+            continue;
+        }
+        let status = message
+            .as_ref()
+            .map(|message| match recover_pubkey(message, signature) {
+                Ok(pubkey) => signer_status(&lock, config.identity(&pubkey), &mut signers),
+                Err(err) => format!("unrecoverable: {}", err),
+            });
+        slots.push((Some(format!("0x{}", hex::encode(signature))), status));
+    }
+    let verification = message
+        .as_ref()
+        .map(|message| lock.verify(message).map_err(|err| err.to_string()));
+
+    let value = json!({
+        "key_format": flags[0],
+        "require_first_n": flags[1],
+        "threshold": flags[2],
+        "pubkeys": flags[3],
+        "multisig_hash": format!("0x{}", hex::encode(config.hash160())),
+        "pubkey_hashes": pubkey_hashes,
+        "filled": lock.filled_count(),
+        "slots": slots
+            .iter()
+            .map(|(signature, status)| json!({ "signature": signature, "signer": status }))
+            .collect::<Vec<_>>(),
+        "message": message.as_ref().map(|message| format!("0x{}", hex::encode(message))),
+        "verified": verification.as_ref().map(Result::is_ok),
+        "error": verification.as_ref().and_then(|result| result.as_ref().err()),
+    });
+    emit(value, || {
+        println!(
+            "flags: key format {}, require_first_n {}, threshold {}, pubkeys {}",
+            flags[0], flags[1], flags[2], flags[3]
+        );
+        println!(
+            "lock args: 0x{}, followed by the since if any",
+            hex::encode(config.hash160())
+        );
+        for (i, hash) in pubkey_hashes.iter().enumerate() {
+            println!("pubkey #{}: {}", i, hash);
+        }
+        println!(
+            "signatures: {} of {} slots filled",
+            lock.filled_count(),
+            lock.signatures().len()
+        );
+        for (slot, (signature, status)) in slots.iter().enumerate() {
+            match signature {
+                Some(signature) => println!("slot #{}: {}", slot, signature),
+                None => println!("slot #{}: empty", slot),
+            }
+            if let Some(status) = status {
+                println!("  {}", status);
+            }
+        }
+        if let (Some(message), Some(verification)) = (&message, &verification) {
+            println!("message: 0x{}", hex::encode(message));
+            match verification {
+                Ok(()) => println!("verification: ok"),
+                Err(err) => println!("verification: failed, {}", err),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Who produced a signature, `signers` are the members matched by the
+/// previous slots.
+fn signer_status(
+    lock: &MultisigLock,
+    identity: [u8; BLAKE160_SIZE],
+    signers: &mut Vec<[u8; BLAKE160_SIZE]>,
+) -> String {
+    let hex = hex::encode(identity);
+    match lock.config().position(&identity) {
+        None => format!("signed by 0x{}, not a member", hex),
+        Some(_) if signers.contains(&identity) => format!("signed by 0x{}, duplicated", hex),
+        Some(position) => {
+            signers.push(identity);
+            format!("signed by pubkey #{} 0x{}", position, hex)
+        }
+    }
+}
+
+/// The lock field of a `WitnessArgs`, or the bytes as is when they don't
+/// parse as one.
+fn lock_field(witness: &[u8]) -> Result<Bytes> {
+    match packed::WitnessArgs::from_slice(witness) {
+        Ok(args) => args
+            .lock()
+            .to_opt()
+            .map(|lock| lock.raw_data())
+            .ok_or_else(|| anyhow!("the witness has no lock field")),
+        Err(_) => Ok(Bytes::copy_from_slice(witness)),
+    }
+}
+
+fn parse_hex(s: &str) -> Result<Bytes> {
+    hex::decode(s.trim_start_matches("0x"))
+        .map(Bytes::from)
+        .map_err(|err| anyhow!("invalid hex: {}", err))
+}
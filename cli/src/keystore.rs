@@ -0,0 +1,562 @@
+//! Encrypted keys of the cosigners, in the keystore format of ckb-cli.
+//!
+//! A directory of JSON files, one per key:
+//!
+//! ```json
+//! {
+//!   "id": "<uuid>",
+//!   "version": 3,
+//!   "crypto": {
+//!     "cipher": "aes-128-ctr",
+//!     "cipherparams": { "iv": "<hex>" },
+//!     "ciphertext": "<hex>",
+//!     "kdf": "scrypt",
+//!     "kdfparams": { "dklen": 32, "n": 262144, "p": 1, "r": 8, "salt": "<hex>" },
+//!     "mac": "<hex>"
+//!   },
+//!   "hash160": "<hex>",
+//!   "label": "alice",
+//!   "configs": ["0x..."]
+//! }
+//! ```
+//!
+//! The scrypt key of the password is split in two: the first half encrypts
+//! the private key with AES-128-CTR, the keccak256 of the second half and
+//! the ciphertext is the MAC. Like the master keys of ckb-cli, the plaintext
+//! is the private key followed by a chain code, a bare private key is read
+//! too. `hash160` is the pubkey hash of the key, as ckb-cli writes it.
+//! `label` and `configs` are ours, other tools ignore them: the configs are
+//! the blake160 of the multisig scripts the key is a member of.
+//!
+//! The store also holds the configs its keys sign for, in TOML under
+//! `configs/<name>.toml`, so one store serves an operator sitting on several
+//! treasuries. Registering a config marks the keys of the store which are
+//! members of it, the other members are the external cosigners, named by the
+//! labels of the stored config file.
+
+use std::{
+    convert::TryInto,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use aes::cipher::{KeyIvInit, StreamCipher};
+use anyhow::{anyhow, bail, Context, Result};
+use ckb_multisig_sdk::{
+    config_file::{ConfigFile, KeyEntry},
+    constants::BLAKE160_SIZE,
+    MultisigConfig, SecpSigner, Signer,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use subtle::ConstantTimeEq;
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+/// Read instead of prompting for the password when set, for scripts.
+pub const PASSWORD_ENV: &str = "CKB_MULTISIG_PASSWORD";
+
+const KEYSTORE_VERSION: u32 = 3;
+const CIPHER: &str = "aes-128-ctr";
+const KDF: &str = "scrypt";
+const DKLEN: usize = 32;
+// the "standard" parameters of ckb-cli
+const SCRYPT_LOG_N: u8 = 18;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyFile {
+    pub id: String,
+    pub version: u32,
+    pub crypto: Crypto,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash160: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configs: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Crypto {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    pub ciphertext: String,
+    pub kdf: String,
+    pub kdfparams: KdfParams,
+    pub mac: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub dklen: usize,
+    pub n: u64,
+    pub p: u32,
+    pub r: u32,
+    pub salt: String,
+}
+
+impl KeyFile {
+    /// Encrypt a new random key.
+    pub fn generate(password: &str) -> Result<Self> {
+        loop {
+            // out of the curve order once in 2^128
+            let secret = random::<32>();
+            if SecpSigner::from_slice(&secret).is_ok() {
+                return KeyFile::encrypt(&secret, password);
+            }
+        }
+    }
+
+    /// Encrypt a private key, with a random chain code after it.
+    pub fn encrypt(secret: &[u8], password: &str) -> Result<Self> {
+        KeyFile::encrypt_with(secret, password, SCRYPT_LOG_N)
+    }
+
+    /// `encrypt` at a scrypt cost of `2^log_n`, lighter ones for the tests.
+    pub(crate) fn encrypt_with(secret: &[u8], password: &str, log_n: u8) -> Result<Self> {
+        let signer = SecpSigner::from_slice(secret)?;
+        let mut plaintext = secret.to_vec();
+        plaintext.extend_from_slice(&random::<32>());
+
+        let salt = random::<32>();
+        let iv = random::<16>();
+        let params = scrypt::Params::new(log_n, SCRYPT_R, SCRYPT_P, DKLEN)
+            .map_err(|err| anyhow!("scrypt parameters: {}", err))?;
+        let key = derive_key(password, &salt, &params)?;
+        let mut ciphertext = plaintext;
+        Aes128Ctr::new(key[..16].into(), (&iv).into()).apply_keystream(&mut ciphertext);
+
+        Ok(KeyFile {
+            id: uuid::Uuid::new_v4().to_string(),
+            version: KEYSTORE_VERSION,
+            crypto: Crypto {
+                cipher: CIPHER.to_string(),
+                cipherparams: CipherParams {
+                    iv: hex::encode(iv),
+                },
+                mac: hex::encode(mac(&key, &ciphertext)),
+                ciphertext: hex::encode(ciphertext),
+                kdf: KDF.to_string(),
+                kdfparams: KdfParams {
+                    dklen: DKLEN,
+                    n: 1 << log_n,
+                    p: SCRYPT_P,
+                    r: SCRYPT_R,
+                    salt: hex::encode(salt),
+                },
+            },
+            hash160: Some(hex::encode(signer.identity()?)),
+            label: None,
+            configs: vec![],
+        })
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<SecpSigner> {
+        let crypto = &self.crypto;
+        if crypto.cipher != CIPHER || crypto.kdf != KDF {
+            bail!(
+                "unsupported cipher {} with {}, expected {} with {}",
+                crypto.cipher,
+                crypto.kdf,
+                CIPHER,
+                KDF
+            );
+        }
+        let kdf = &crypto.kdfparams;
+        if !kdf.n.is_power_of_two() || kdf.dklen != DKLEN {
+            bail!(
+                "unsupported scrypt parameters n {}, dklen {}",
+                kdf.n,
+                kdf.dklen
+            );
+        }
+        let params = scrypt::Params::new(kdf.n.trailing_zeros() as u8, kdf.r, kdf.p, kdf.dklen)
+            .map_err(|err| anyhow!("scrypt parameters: {}", err))?;
+        let key = derive_key(password, &parse_hex(&kdf.salt)?, &params)?;
+        let mut plaintext = parse_hex(&crypto.ciphertext)?;
+        let expected = parse_hex(&crypto.mac)?;
+        if !bool::from(mac(&key, &plaintext)[..].ct_eq(&expected[..])) {
+            bail!("wrong password");
+        }
+        let iv = parse_hex(&crypto.cipherparams.iv)?;
+        if iv.len() != 16 {
+            bail!("invalid iv of {} bytes", iv.len());
+        }
+        Aes128Ctr::new(key[..16].into(), iv[..].into()).apply_keystream(&mut plaintext);
+        match plaintext.len() {
+            32 | 64 => Ok(SecpSigner::from_slice(&plaintext[..32])?),
+            len => bail!("invalid key of {} bytes", len),
+        }
+    }
+
+    /// Whether the key is marked as a member of the config.
+    pub fn is_member(&self, config: &MultisigConfig) -> bool {
+        self.configs.contains(&config_id(config))
+    }
+
+    /// The identity of the key, the pubkey hash.
+    pub fn identity(&self) -> Result<[u8; BLAKE160_SIZE]> {
+        let hash = self
+            .hash160
+            .as_deref()
+            .ok_or_else(|| anyhow!("key {} without hash160", self.id))?;
+        parse_hex(hash)?
+            .try_into()
+            .map_err(|_| anyhow!("invalid hash160 {}", hash))
+    }
+
+    /// The label, or else the pubkey hash.
+    pub fn name(&self) -> String {
+        match (&self.label, &self.hash160) {
+            (Some(label), _) => label.clone(),
+            (None, Some(hash)) => format!("0x{}", hash.trim_start_matches("0x")),
+            (None, None) => self.id.clone(),
+        }
+    }
+}
+
+/// How the configs of a key are referred to.
+pub fn config_id(config: &MultisigConfig) -> String {
+    format!("0x{}", hex::encode(config.hash160()))
+}
+
+/// A config registered in the store.
+#[derive(Clone, Debug)]
+pub struct StoredConfig {
+    pub name: String,
+    pub path: PathBuf,
+    pub file: ConfigFile,
+}
+
+impl StoredConfig {
+    pub fn config(&self) -> Result<MultisigConfig> {
+        self.file
+            .to_config()
+            .with_context(|| format!("config {}", self.name))
+    }
+
+    /// The id the keys refer to the config by, see `config_id`.
+    pub fn id(&self) -> Result<String> {
+        Ok(config_id(&self.config()?))
+    }
+}
+
+/// A member of a stored config, in the config order.
+#[derive(Clone, Debug)]
+pub struct Cosigner {
+    pub pubkey_hash: [u8; BLAKE160_SIZE],
+    /// The entry of the config file, with the label of an external cosigner.
+    pub entry: KeyEntry,
+    /// The key of the store, `None` for an external cosigner.
+    pub key: Option<KeyFile>,
+}
+
+impl Cosigner {
+    pub fn is_local(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// The label of the local key, or else of the config file, or else the
+    /// pubkey hash.
+    pub fn name(&self) -> String {
+        match (&self.key, &self.entry.label) {
+            (Some(key), _) => key.name(),
+            (None, Some(label)) => label.clone(),
+            (None, None) => format!("0x{}", hex::encode(self.pubkey_hash)),
+        }
+    }
+}
+
+/// A directory of key files.
+pub struct Keystore {
+    dir: PathBuf,
+}
+
+impl Keystore {
+    pub fn new(dir: PathBuf) -> Self {
+        Keystore { dir }
+    }
+
+    /// `--keystore`, or else `~/.ckb-multisig/keystore`.
+    pub fn open(dir: Option<&Path>) -> Result<Self> {
+        match dir {
+            Some(dir) => Ok(Keystore::new(dir.to_path_buf())),
+            None => {
+                let home = env::var_os("HOME").ok_or_else(|| anyhow!("no home directory"))?;
+                Ok(Keystore::new(
+                    Path::new(&home).join(".ckb-multisig").join("keystore"),
+                ))
+            }
+        }
+    }
+
+    /// The key files sorted by path, none when the directory doesn't exist.
+    pub fn list(&self) -> Result<Vec<(PathBuf, KeyFile)>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut paths = Vec::new();
+        for entry in
+            fs::read_dir(&self.dir).with_context(|| format!("read {}", self.dir.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| {
+                let file = load(&path)?;
+                Ok((path, file))
+            })
+            .collect()
+    }
+
+    /// The key of a label or a pubkey hash.
+    pub fn find(&self, key: &str) -> Result<(PathBuf, KeyFile)> {
+        let hash = key.trim_start_matches("0x");
+        let mut found = self.list()?.into_iter().filter(|(_, file)| {
+            file.label.as_deref() == Some(key)
+                || file
+                    .hash160
+                    .as_deref()
+                    .is_some_and(|other| other.trim_start_matches("0x") == hash)
+        });
+        match (found.next(), found.next()) {
+            (Some(key), None) => Ok(key),
+            (Some(_), Some(_)) => bail!("several keys match `{}`, use the pubkey hash", key),
+            (None, _) => bail!("no key `{}` in {}", key, self.dir.display()),
+        }
+    }
+
+    /// Write a new key, named after its pubkey hash.
+    pub fn add(&self, file: &KeyFile) -> Result<PathBuf> {
+        let name = file.hash160.as_deref().unwrap_or(&file.id);
+        let path = self
+            .dir
+            .join(format!("{}.json", name.trim_start_matches("0x")));
+        if path.exists() {
+            bail!("the key is already in {}", path.display());
+        }
+        if let Some(label) = &file.label {
+            if self
+                .list()?
+                .iter()
+                .any(|(_, other)| other.label.as_ref() == Some(label))
+            {
+                bail!("another key is labeled `{}`", label);
+            }
+        }
+        fs::create_dir_all(&self.dir).with_context(|| format!("create {}", self.dir.display()))?;
+        save(&path, file)?;
+        Ok(path)
+    }
+
+    fn configs_dir(&self) -> PathBuf {
+        self.dir.join("configs")
+    }
+
+    /// The stored configs sorted by name.
+    pub fn configs(&self) -> Result<Vec<StoredConfig>> {
+        let dir = self.configs_dir();
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&dir).with_context(|| format!("read {}", dir.display()))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "toml") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths.into_iter().map(|path| load_stored(&path)).collect()
+    }
+
+    /// The stored config of a name or an id.
+    pub fn find_config(&self, name: &str) -> Result<StoredConfig> {
+        for stored in self.configs()? {
+            if stored.name == name || stored.id()? == name {
+                return Ok(stored);
+            }
+        }
+        bail!("no config `{}` in {}", name, self.configs_dir().display())
+    }
+
+    /// Store a config under `name`, and mark the keys of the store which are
+    /// members of it.
+    pub fn add_config(&self, name: &str, file: &ConfigFile) -> Result<StoredConfig> {
+        check_config_name(name)?;
+        let config = file.to_config()?;
+        let id = config_id(&config);
+        for other in self.configs()? {
+            if other.name == name {
+                bail!("another config is named `{}`", name);
+            }
+            if other.id()? == id {
+                bail!("the config is already stored as `{}`", other.name);
+            }
+        }
+        let dir = self.configs_dir();
+        fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+        let stored = StoredConfig {
+            name: name.to_string(),
+            path: dir.join(format!("{}.toml", name)),
+            file: file.clone(),
+        };
+        self.save_config(&stored)?;
+        self.mark_members(&config)?;
+        Ok(stored)
+    }
+
+    pub fn save_config(&self, stored: &StoredConfig) -> Result<()> {
+        fs::write(&stored.path, stored.file.to_toml()?)
+            .with_context(|| format!("write {}", stored.path.display()))
+    }
+
+    /// Forget a stored config, and unmark its keys.
+    pub fn remove_config(&self, name: &str) -> Result<StoredConfig> {
+        let stored = self.find_config(name)?;
+        let id = stored.id()?;
+        for (path, mut file) in self.list()? {
+            if file.configs.contains(&id) {
+                file.configs.retain(|config| *config != id);
+                save(&path, &file)?;
+            }
+        }
+        fs::remove_file(&stored.path)
+            .with_context(|| format!("remove {}", stored.path.display()))?;
+        Ok(stored)
+    }
+
+    /// Mark every key of the store which is a member of `config`, and return
+    /// them.
+    pub fn mark_members(&self, config: &MultisigConfig) -> Result<Vec<KeyFile>> {
+        let id = config_id(config);
+        let mut members = Vec::new();
+        for (path, mut file) in self.list()? {
+            let is_member = file
+                .identity()
+                .is_ok_and(|identity| config.position(&identity).is_some());
+            if !is_member {
+                continue;
+            }
+            if !file.configs.contains(&id) {
+                file.configs.push(id.clone());
+                save(&path, &file)?;
+            }
+            members.push(file);
+        }
+        Ok(members)
+    }
+
+    /// The members of a stored config, each with the key of the store when
+    /// there is one.
+    pub fn cosigners(&self, stored: &StoredConfig) -> Result<Vec<Cosigner>> {
+        let config = stored.config()?;
+        let keys = self.list()?;
+        Ok(config
+            .pubkey_hashes()
+            .iter()
+            .zip(&stored.file.keys)
+            .map(|(hash, entry)| Cosigner {
+                pubkey_hash: *hash,
+                entry: entry.clone(),
+                key: keys
+                    .iter()
+                    .map(|(_, file)| file)
+                    .find(|file| file.identity().is_ok_and(|identity| identity == *hash))
+                    .cloned(),
+            })
+            .collect())
+    }
+}
+
+fn load_stored(path: &Path) -> Result<StoredConfig> {
+    let content = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let file =
+        ConfigFile::from_toml(&content).with_context(|| format!("parse {}", path.display()))?;
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow!("invalid config name {}", path.display()))?;
+    Ok(StoredConfig {
+        name: name.to_string(),
+        path: path.to_path_buf(),
+        file,
+    })
+}
+
+/// Names become file names: letters, digits, `-` and `_` only.
+fn check_config_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!(
+            "invalid config name `{}`, use letters, digits, - and _",
+            name
+        );
+    }
+    if name.starts_with("0x") {
+        bail!("config names can't start with 0x, the ids do");
+    }
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<KeyFile> {
+    let content = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("parse key {}", path.display()))
+}
+
+pub fn save(path: &Path, file: &KeyFile) -> Result<()> {
+    let json = serde_json::to_string_pretty(file)?;
+    fs::write(path, json).with_context(|| format!("write {}", path.display()))
+}
+
+/// The password of `name`, from `PASSWORD_ENV` or the terminal.
+pub fn read_password(name: &str, confirm: bool) -> Result<String> {
+    if let Ok(password) = env::var(PASSWORD_ENV) {
+        return Ok(password);
+    }
+    let password = rpassword::prompt_password(format!("Password of {}: ", name))?;
+    if confirm && rpassword::prompt_password("Repeat the password: ")? != password {
+        bail!("the passwords differ");
+    }
+    Ok(password)
+}
+
+fn derive_key(password: &str, salt: &[u8], params: &scrypt::Params) -> Result<[u8; DKLEN]> {
+    let mut key = [0u8; DKLEN];
+    scrypt::scrypt(password.as_bytes(), salt, params, &mut key)
+        .map_err(|err| anyhow!("scrypt: {}", err))?;
+    Ok(key)
+}
+
+fn mac(key: &[u8; DKLEN], ciphertext: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(&key[16..]);
+    hasher.update(ciphertext);
+    hasher.finalize().into()
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>> {
+    hex::decode(s.trim_start_matches("0x")).map_err(|err| anyhow!("invalid hex: {}", err))
+}
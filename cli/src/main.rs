@@ -0,0 +1,124 @@
+//! Command line tool of the ckb-multisig lock.
+//!
+//! See `commands/` for the subcommands, `util.rs` for the arguments they
+//! share, the `--json` output included, and `keystore.rs` for the encrypted
+//! keys of the cosigners.
+
+mod commands;
+mod keystore;
+mod terminal;
+mod util;
+
+#[cfg(test)]
+mod tests;
+
+use clap::{Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(
+    name = "ckb-multisig",
+    version,
+    about = "Manage cells guarded by the ckb-multisig lock"
+)]
+struct Cli {
+    /// Print the results as JSON lines for scripts, errors included
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write, convert and rotate config files
+    #[command(subcommand)]
+    Config(commands::config::ConfigCommand),
+    /// Lock args, lock script and address of a config
+    Address(commands::address::AddressArgs),
+    /// Create the proposal of a payment from the cells of a config
+    Propose(commands::proposal::ProposeArgs),
+    /// Summary of a proposal for the cosigners to read before signing
+    InspectTx(commands::inspect::InspectTxArgs),
+    /// Add the signatures of local or Ledger keys to a proposal, or to a
+    /// directory of them
+    Sign(commands::proposal::SignArgs),
+    /// Merge the signatures of copies of a proposal signed separately
+    Combine(commands::proposal::CombineArgs),
+    /// Send a completely signed proposal
+    Send(commands::proposal::SendArgs),
+    /// Sign or reject the pending proposals on an interactive screen
+    Review(commands::review::ReviewArgs),
+    /// Check a signed transaction against a config, offline
+    Verify(commands::verify::VerifyArgs),
+    /// Check the deployed contract is a local or rebuilt binary
+    VerifyBinary(commands::verify_binary::VerifyBinaryArgs),
+    /// Re-verify an audit log of the approvals, offline
+    Audit(commands::audit::AuditArgs),
+    /// Write a proposal as a node RPC transaction or a `ckb-cli tx` file
+    Export(commands::interop::ExportArgs),
+    /// Make a proposal of a transaction file of another tool
+    Import(commands::interop::ImportArgs),
+    /// Print the lock field of a witness and check its signatures
+    DecodeWitness(commands::witness::DecodeWitnessArgs),
+    /// Run the contract binary on a transaction locally
+    Simulate(commands::simulate::SimulateArgs),
+    /// Cycles of verifying transactions of a config shape
+    EstimateCycles(commands::cycles::EstimateCyclesArgs),
+    /// Manage the encrypted keys of the cosigners
+    #[command(subcommand)]
+    Keystore(commands::keystore::KeystoreCommand),
+    /// Move the cells of an old config under a new one
+    #[command(subcommand)]
+    Migrate(commands::migrate::MigrateCommand),
+    /// Earn the Nervos DAO compensation on the capacity of a config
+    #[command(subcommand)]
+    Dao(commands::dao::DaoCommand),
+    /// Transactions of a config with the cosigners who approved the spends
+    History(commands::history::HistoryArgs),
+    /// Report the deposits and spends of configs as they are confirmed
+    Watch(commands::watch::WatchArgs),
+    /// Print the completion script of a shell
+    Completions(commands::completions::CompletionsArgs),
+    /// Check the node, the indexer, the deployment and the keystore
+    Doctor(commands::doctor::DoctorArgs),
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    util::set_json_output(cli.json);
+    let result = run(cli.command);
+    if let (true, Err(err)) = (cli.json, &result) {
+        println!("{}", serde_json::json!({ "error": format!("{:#}", err) }));
+        std::process::exit(1);
+    }
+    result
+}
+
+fn run(command: Command) -> anyhow::Result<()> {
+    match command {
+        Command::Config(command) => commands::config::run(command),
+        Command::Address(args) => commands::address::run(args),
+        Command::Propose(args) => commands::proposal::propose(args),
+        Command::InspectTx(args) => commands::inspect::run(args),
+        Command::Sign(args) => commands::proposal::sign(args),
+        Command::Combine(args) => commands::proposal::combine(args),
+        Command::Send(args) => commands::proposal::send(args),
+        Command::Review(args) => commands::review::run(args),
+        Command::Verify(args) => commands::verify::run(args),
+        Command::VerifyBinary(args) => commands::verify_binary::run(args),
+        Command::Audit(args) => commands::audit::run(args),
+        Command::Export(args) => commands::interop::export(args),
+        Command::Import(args) => commands::interop::import(args),
+        Command::DecodeWitness(args) => commands::witness::run(args),
+        Command::Simulate(args) => commands::simulate::run(args),
+        Command::EstimateCycles(args) => commands::cycles::run(args),
+        Command::Keystore(command) => commands::keystore::run(command),
+        Command::Migrate(command) => commands::migrate::run(command),
+        Command::Dao(command) => commands::dao::run(command),
+        Command::History(args) => commands::history::run(args),
+        Command::Watch(args) => commands::watch::run(args),
+        Command::Completions(args) => commands::completions::run(args),
+        Command::Doctor(args) => commands::doctor::run(args),
+    }
+}
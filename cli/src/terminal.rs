@@ -0,0 +1,143 @@
+//! The bare terminal handling of the `review` screen, with ANSI escapes and
+//! termios: no dependency for a single screen.
+//!
+//! `Screen` switches to the alternate screen for its lifetime. Keys are read
+//! one at a time with the terminal in raw mode only while waiting for them,
+//! so prompts such as the keystore passwords work as usual in between.
+
+use std::io::{self, Read, Write};
+
+use anyhow::{bail, Result};
+
+const ENTER_ALTERNATE: &str = "\x1b[?1049h\x1b[?25l";
+const LEAVE_ALTERNATE: &str = "\x1b[?25h\x1b[?1049l";
+const CLEAR: &str = "\x1b[H\x1b[2J";
+
+/// A key pressed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Char(char),
+    /// Ctrl-C, the signals being off in raw mode.
+    Interrupt,
+    Other,
+}
+
+/// The alternate screen, left when dropped.
+pub struct Screen {
+    _private: (),
+}
+
+impl Screen {
+    pub fn enter() -> Result<Self> {
+        if !is_terminal() {
+            bail!("stdin and stdout must be a terminal");
+        }
+        print!("{}", ENTER_ALTERNATE);
+        io::stdout().flush()?;
+        Ok(Screen { _private: () })
+    }
+
+    /// Replace the screen with `lines`.
+    pub fn draw(&self, lines: &[String]) -> Result<()> {
+        let mut out = io::stdout().lock();
+        write!(out, "{}", CLEAR)?;
+        for line in lines {
+            writeln!(out, "{}", line)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// The next key pressed.
+    pub fn read_key(&self) -> Result<Key> {
+        let _raw = RawMode::enter()?;
+        let mut stdin = io::stdin().lock();
+        let mut byte = [0u8; 1];
+        stdin.read_exact(&mut byte)?;
+        Ok(match byte[0] {
+            0x03 => Key::Interrupt,
+            0x1b => {
+                let mut sequence = [0u8; 2];
+                stdin.read_exact(&mut sequence)?;
+                match sequence {
+                    [b'[', b'A'] => Key::Up,
+                    [b'[', b'B'] => Key::Down,
+                    _ => Key::Other,
+                }
+            }
+            byte if byte.is_ascii_graphic() => Key::Char(byte as char),
+            _ => Key::Other,
+        })
+    }
+
+    /// Clear the screen, before a prompt.
+    pub fn clear(&self) -> Result<()> {
+        print!("{}", CLEAR);
+        io::stdout().flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        print!("{}", LEAVE_ALTERNATE);
+        let _ = io::stdout().flush();
+    }
+}
+
+#[cfg(unix)]
+fn is_terminal() -> bool {
+    // SAFETY: isatty only reads the descriptors
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+fn is_terminal() -> bool {
+    false
+}
+
+/// Stdin without echo, line buffering nor signals, restored when dropped.
+#[cfg(unix)]
+struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enter() -> Result<Self> {
+        // SAFETY: termios is plain data, filled by tcgetattr before use
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(RawMode { original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the attributes read in `enter`
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+#[cfg(not(unix))]
+struct RawMode;
+
+#[cfg(not(unix))]
+impl RawMode {
+    fn enter() -> Result<Self> {
+        bail!("raw terminal mode is only supported on unix");
+    }
+}
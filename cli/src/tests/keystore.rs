@@ -0,0 +1,84 @@
+use ckb_multisig_sdk::{SecpSigner, Signer};
+use serde_json::json;
+
+use crate::keystore::KeyFile;
+
+const SECRET: [u8; 32] = [7; 32];
+
+/// A key file of ckb-cli, the crypto from the tests of its keystore: a bare
+/// private key under the light scrypt parameters.
+fn ckb_cli_key_file() -> KeyFile {
+    serde_json::from_value(json!({
+        "id": "c5f2c3b2-5a35-4a85-a8c2-4a0c7b1e7f3d",
+        "version": 3,
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "ciphertext": "253397209cae86474e368720f9baa30f448767047d2cc5a7672ef121861974ed",
+            "cipherparams": {
+                "iv": "8bd8523e0048db3a4ae2534aec6d303a"
+            },
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32,
+                "n": 4096,
+                "p": 6,
+                "r": 8,
+                "salt": "be3d86c99f4895f99d1a0048afb61a34153fa83d5edd033fc914de2c502f57e7"
+            },
+            "mac": "4453cf5d4f6ec43d0664c3895c4ab9b1c9bcd2d02c7abb190c84375a42739099"
+        }
+    }))
+    .unwrap()
+}
+
+fn identity(secret: &[u8]) -> [u8; 20] {
+    SecpSigner::from_slice(secret).unwrap().identity().unwrap()
+}
+
+#[test]
+fn test_key_file_round_trip() {
+    let file = KeyFile::encrypt_with(&SECRET, "alice", 10).unwrap();
+    assert_eq!(file.version, 3);
+    assert_eq!(file.crypto.kdfparams.n, 1 << 10);
+    assert_eq!(file.identity().unwrap(), identity(&SECRET));
+    // the private key then a chain code
+    assert_eq!(file.crypto.ciphertext.len(), 2 * 64);
+
+    let signer = file.decrypt("alice").unwrap();
+    assert_eq!(signer.identity().unwrap(), identity(&SECRET));
+    assert!(file.decrypt("bob").is_err());
+
+    // through JSON, the MAC in any case
+    let mut file: KeyFile = serde_json::from_str(&serde_json::to_string(&file).unwrap()).unwrap();
+    file.crypto.mac = format!("0x{}", file.crypto.mac.to_uppercase());
+    assert_eq!(
+        file.decrypt("alice").unwrap().identity().unwrap(),
+        identity(&SECRET)
+    );
+}
+
+#[test]
+fn test_ckb_cli_key_file() {
+    let file = ckb_cli_key_file();
+    let secret =
+        hex::decode("8c8a06804785c73adf91b53a1174f6ee7280101bd30d0f3260cfb4449ed1e2ca").unwrap();
+    assert_eq!(
+        file.decrypt("123").unwrap().identity().unwrap(),
+        identity(&secret)
+    );
+    assert!(file.decrypt("1234").is_err());
+}
+
+#[test]
+fn test_key_file_mac() {
+    // a MAC altered or cut short is a wrong password
+    let mut file = ckb_cli_key_file();
+    file.crypto.mac.replace_range(..2, "00");
+    assert!(file.decrypt("123").is_err());
+    let mut file = ckb_cli_key_file();
+    file.crypto.mac.truncate(62);
+    assert!(file.decrypt("123").is_err());
+    let mut file = ckb_cli_key_file();
+    file.crypto.mac = "not hex".to_string();
+    assert!(file.decrypt("123").is_err());
+}
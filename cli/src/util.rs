@@ -0,0 +1,588 @@
+use std::{
+    convert::TryInto,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ckb_jsonrpc_types as json;
+use ckb_multisig_sdk::{
+    approval::{MAINNET_GENESIS_HASH, TESTNET_GENESIS_HASH},
+    backend::{BackendKind, BoxedBackend},
+    config_file::ConfigFile,
+    deployment::{find_secp256k1_data, Network, Registry},
+    kms::{AwsCredentials, AwsKms, GcpKms, KmsSigner, VaultTransit},
+    policy::{Context as PolicyContext, Policy, SpendLedger, Violation},
+    request::SigningRequest,
+    since::{parse_since, SinceSpec},
+    unlock::BoxedSigner,
+    MultisigConfig, SecpSigner, Signer,
+};
+use ckb_sdk::{Address, NetworkType};
+use ckb_types::{
+    core::{DepType, ScriptHashType, TransactionView},
+    packed::{self, CellDep, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use clap::Args;
+
+use crate::keystore::{self, Keystore};
+use serde_json::Value;
+
+#[cfg(any(feature = "ledger", feature = "trezor"))]
+use ckb_multisig_sdk::ledger::DerivationPath;
+#[cfg(feature = "ledger")]
+use ckb_multisig_sdk::ledger::{hid::HidTransport, LedgerSigner};
+#[cfg(feature = "trezor")]
+use ckb_multisig_sdk::trezor::{hid::HidTransport as TrezorHidTransport, TrezorSigner};
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Switch the output of every command to JSON, from the global `--json`.
+pub fn set_json_output(enabled: bool) {
+    JSON_OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Print a result of a command: `value` on a line of its own under `--json`,
+/// `text` printing it for a reader otherwise. Commands with several results,
+/// e.g. one per cell, emit each as it comes, JSON lines, the `kind` field
+/// telling them apart. Hashes and bytes are `0x` hex, amounts in shannons.
+pub fn emit(value: Value, text: impl FnOnce()) {
+    if json_output() {
+        println!("{}", value);
+    } else {
+        text();
+    }
+}
+
+/// Where the lock is deployed and how to reach the chain.
+#[derive(Args)]
+pub struct ChainArgs {
+    /// RPC of a CKB node with the indexer module enabled
+    #[arg(long, default_value = "http://127.0.0.1:8114")]
+    pub rpc: String,
+
+    /// What --rpc reads the cells and transactions from: indexer,
+    /// rich-indexer or light-client, the RPC of a CKB light client
+    #[arg(long, default_value_t)]
+    pub backend: BackendKind,
+
+    /// Registry file of the deployments of the lock, TOML or JSON
+    #[arg(long, requires = "network")]
+    pub deployments: Option<PathBuf>,
+
+    /// Network to look up in the registry: mainnet, testnet or devnet
+    #[arg(long, requires = "deployments")]
+    pub network: Option<Network>,
+
+    /// Code hash of the deployed contract, overrides the registry
+    #[arg(long, value_parser = parse_h256)]
+    pub code_hash: Option<H256>,
+
+    /// Hash type of the lock script: data, type, data1 or data2, overrides
+    /// the registry, type by default
+    #[arg(long, value_parser = parse_hash_type)]
+    pub hash_type: Option<ScriptHashType>,
+
+    /// Cell dep of the contract, as `tx_hash:index` or `tx_hash:index:dep_group`,
+    /// replaces the one of the registry
+    #[arg(long = "cell-dep", value_parser = parse_cell_dep)]
+    pub cell_deps: Vec<CellDep>,
+}
+
+impl ChainArgs {
+    /// The deployment of the registry with the overrides of the flags. The
+    /// `secp256k1_data` cell of a devnet is found in its genesis block.
+    pub fn resolve(&self) -> Result<Chain> {
+        let deployment = match (&self.deployments, self.network) {
+            (Some(path), Some(network)) => {
+                let mut registry = load_registry(path)?;
+                let deployment = registry.get(network)?.clone();
+                if network == Network::Devnet
+                    && deployment.secp256k1_data
+                    && self.cell_deps.is_empty()
+                {
+                    let genesis = self.backend.connect(&self.rpc).genesis()?;
+                    let dep = find_secp256k1_data(&genesis)
+                        .context("no secp256k1_data cell in the genesis block")?;
+                    registry.set_secp256k1_data(network, dep);
+                }
+                Some((deployment, registry.cell_deps(network)?))
+            }
+            _ => None,
+        };
+        let code_hash = match (&self.code_hash, &deployment) {
+            (Some(code_hash), _) => code_hash.clone(),
+            (None, Some((deployment, _))) => deployment.code_hash.clone(),
+            (None, None) => bail!("no code hash, pass --code-hash or --deployments"),
+        };
+        let hash_type = self
+            .hash_type
+            .or_else(|| {
+                deployment
+                    .as_ref()
+                    .map(|(deployment, _)| deployment.hash_type)
+            })
+            .unwrap_or(ScriptHashType::Type);
+        let cell_deps = match deployment {
+            Some((_, cell_deps)) if self.cell_deps.is_empty() => cell_deps,
+            _ => self.cell_deps.clone(),
+        };
+        Ok(Chain {
+            rpc: self.rpc.clone(),
+            backend: self.backend,
+            code_hash,
+            hash_type,
+            cell_deps,
+        })
+    }
+}
+
+/// The resolved `ChainArgs`.
+pub struct Chain {
+    pub rpc: String,
+    pub backend: BackendKind,
+    pub code_hash: H256,
+    pub hash_type: ScriptHashType,
+    pub cell_deps: Vec<CellDep>,
+}
+
+impl Chain {
+    /// The backend the reads of the chain go through.
+    pub fn connect(&self) -> BoxedBackend {
+        self.backend.connect(&self.rpc)
+    }
+
+    pub fn lock_script(&self, config: &MultisigConfig) -> Script {
+        config.lock_script(&self.code_hash, self.hash_type)
+    }
+
+    /// The cell deps of a transaction spending cells of the lock, refusing
+    /// to build one which can't run.
+    pub fn cell_deps(&self) -> Result<Vec<CellDep>> {
+        if self.cell_deps.is_empty() {
+            bail!("no cell dep for the lock, pass --cell-dep or --deployments");
+        }
+        Ok(self.cell_deps.clone())
+    }
+
+    /// The hash of the genesis block, naming the chain in the proposals.
+    pub fn genesis_hash(&self) -> Result<H256> {
+        Ok(self.connect().genesis()?.hash().unpack())
+    }
+}
+
+/// The keys a cosigner signs with.
+#[derive(Args)]
+pub struct SignerArgs {
+    /// Private key file of a cosigner, can be repeated
+    #[arg(long = "privkey-path")]
+    pub privkey_paths: Vec<PathBuf>,
+
+    /// Label or pubkey hash of a key of the keystore, can be repeated
+    #[arg(long = "key")]
+    pub keys: Vec<String>,
+
+    /// Every key of the keystore marked as a member of the config
+    #[arg(long)]
+    pub marked: bool,
+
+    #[command(flatten)]
+    pub keystore: KeystoreArgs,
+
+    /// Derivation path of a key on the connected Ledger, e.g. m/44'/309'/0'/0/0
+    #[cfg(feature = "ledger")]
+    #[arg(long)]
+    pub ledger_path: Option<DerivationPath>,
+
+    /// Derivation path of a key on the connected Trezor or OneKey
+    #[cfg(feature = "trezor")]
+    #[arg(long)]
+    pub trezor_path: Option<DerivationPath>,
+
+    /// Sign blind when the Trezor or OneKey firmware can't display the
+    /// cobuild message
+    #[cfg(feature = "trezor")]
+    #[arg(long)]
+    pub trezor_blind: bool,
+
+    /// Id, ARN or alias of an AWS KMS key, with the credentials of the
+    /// AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN
+    /// environment variables
+    #[arg(long, requires = "aws_region")]
+    pub aws_kms_key: Option<String>,
+
+    /// Region of the AWS KMS key
+    #[arg(long)]
+    pub aws_region: Option<String>,
+
+    /// Resource name of a GCP Cloud KMS key version, with the access token of
+    /// the GCP_ACCESS_TOKEN environment variable
+    #[arg(long)]
+    pub gcp_kms_key: Option<String>,
+
+    /// Name of a Vault transit key, with the VAULT_ADDR and VAULT_TOKEN
+    /// environment variables
+    #[arg(long)]
+    pub vault_key: Option<String>,
+}
+
+impl SignerArgs {
+    /// Whether any key to sign with is given.
+    pub fn any(&self) -> bool {
+        #[allow(unused_mut)]
+        let mut any = !self.privkey_paths.is_empty()
+            || !self.keys.is_empty()
+            || self.marked
+            || self.aws_kms_key.is_some()
+            || self.gcp_kms_key.is_some()
+            || self.vault_key.is_some();
+        #[cfg(feature = "ledger")]
+        {
+            any |= self.ledger_path.is_some();
+        }
+        #[cfg(feature = "trezor")]
+        {
+            any |= self.trezor_path.is_some();
+        }
+        any
+    }
+
+    /// The keys to sign for `config` with, the passwords of the keystore
+    /// keys are asked for. `display` is the cobuild message the hardware
+    /// wallets show, when there is a single transaction to sign.
+    #[cfg_attr(not(feature = "trezor"), allow(unused_variables))]
+    pub fn load(
+        &self,
+        config: &MultisigConfig,
+        display: Option<Vec<u8>>,
+    ) -> Result<Vec<BoxedSigner>> {
+        let mut signers = self
+            .privkey_paths
+            .iter()
+            .map(|path| load_signer(path))
+            .collect::<Result<Vec<_>>>()?;
+        if !self.keys.is_empty() || self.marked {
+            let keystore = self.keystore.open()?;
+            let mut files = self
+                .keys
+                .iter()
+                .map(|key| keystore.find(key).map(|(_, file)| file))
+                .collect::<Result<Vec<_>>>()?;
+            if self.marked {
+                files.extend(
+                    keystore
+                        .list()?
+                        .into_iter()
+                        .map(|(_, file)| file)
+                        .filter(|file| file.is_member(config)),
+                );
+            }
+            for file in files {
+                let password = keystore::read_password(&file.name(), false)?;
+                signers.push(Box::new(file.decrypt(&password)?));
+            }
+        }
+        #[cfg(feature = "ledger")]
+        if let Some(path) = &self.ledger_path {
+            let transport = HidTransport::new()?;
+            signers.push(Box::new(LedgerSigner::new(transport, path.clone())?));
+        }
+        #[cfg(feature = "trezor")]
+        if let Some(path) = &self.trezor_path {
+            let mut signer = TrezorSigner::new(TrezorHidTransport::new()?, path.clone())?
+                .allow_blind(self.trezor_blind);
+            if let Some(message) = display {
+                signer = signer.display(message);
+            }
+            signers.push(Box::new(signer));
+        }
+        if let (Some(key), Some(region)) = (&self.aws_kms_key, &self.aws_region) {
+            let kms = AwsKms::new(key, region, AwsCredentials::from_env()?);
+            signers.push(Box::new(KmsSigner::new(kms)?));
+        }
+        if let Some(name) = &self.gcp_kms_key {
+            let token = env_var("GCP_ACCESS_TOKEN")?;
+            signers.push(Box::new(KmsSigner::new(GcpKms::new(name, &token))?));
+        }
+        if let Some(key) = &self.vault_key {
+            let vault = VaultTransit::new(&env_var("VAULT_ADDR")?, &env_var("VAULT_TOKEN")?, key);
+            signers.push(Box::new(KmsSigner::new(vault)?));
+        }
+        if signers.is_empty() {
+            bail!("no key to sign with");
+        }
+        Ok(signers)
+    }
+}
+
+fn env_var(name: &str) -> Result<String> {
+    std::env::var(name).with_context(|| format!("{} not set", name))
+}
+
+/// Where the encrypted keys are.
+#[derive(Args)]
+pub struct KeystoreArgs {
+    /// Keystore directory, ~/.ckb-multisig/keystore by default
+    #[arg(long)]
+    pub keystore: Option<PathBuf>,
+}
+
+impl KeystoreArgs {
+    pub fn open(&self) -> Result<Keystore> {
+        Keystore::open(self.keystore.as_deref())
+    }
+}
+
+/// The off-chain policy a request must satisfy, see `policy.rs` of the SDK.
+#[derive(Args)]
+pub struct PolicyArgs {
+    /// Policy file, TOML; the spends counted against its daily limit are
+    /// kept next to it, in `<policy>.spends.json`
+    #[arg(long)]
+    pub policy: Option<PathBuf>,
+}
+
+impl PolicyArgs {
+    pub fn load(&self) -> Result<Option<PolicyCheck>> {
+        let Some(path) = &self.policy else {
+            return Ok(None);
+        };
+        let content =
+            fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        let policy =
+            Policy::from_toml(&content).with_context(|| format!("parse {}", path.display()))?;
+        let ledger = SpendLedger::open(&path.with_extension("spends.json"))?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("system time before 1970")?
+            .as_secs();
+        Ok(Some(PolicyCheck {
+            policy,
+            ledger,
+            now,
+        }))
+    }
+}
+
+/// A policy and the spends signed under it, as of now.
+pub struct PolicyCheck {
+    policy: Policy,
+    ledger: SpendLedger,
+    now: u64,
+}
+
+impl PolicyCheck {
+    /// The rules `request` breaks, `signers` being about to sign it.
+    pub fn violations(
+        &self,
+        request: &SigningRequest,
+        signers: &[BoxedSigner],
+    ) -> Result<Vec<Violation>> {
+        let context = PolicyContext {
+            now: self.now,
+            spent: self.ledger.spent(self.now),
+            signers: signers
+                .iter()
+                .map(|signer| signer.identity())
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(self.policy.evaluate(request, &context)?)
+    }
+
+    /// Count `request` as signed against the daily limit.
+    pub fn record(&mut self, request: &SigningRequest) -> Result<()> {
+        self.ledger.record(request, self.now);
+        Ok(self.ledger.save(self.now)?)
+    }
+}
+
+/// 32 bytes hex, optionally 0x prefixed.
+pub fn parse_h256(s: &str) -> Result<H256> {
+    H256::from_str(s.trim_start_matches("0x"))
+        .map_err(|err| anyhow!("invalid hash `{}`: {}", s, err))
+}
+
+/// `N` bytes hex, optionally 0x prefixed.
+pub fn parse_hex<const N: usize>(s: &str) -> Result<[u8; N]> {
+    hex::decode(s.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow!("expected {} bytes hex, got `{}`", N, s))
+}
+
+/// A since readable like "after epoch 180", or a raw 0x prefixed value.
+pub fn parse_since_arg(s: &str) -> Result<u64> {
+    match s.strip_prefix("0x") {
+        Some(raw) => {
+            let since =
+                u64::from_str_radix(raw, 16).map_err(|err| anyhow!("invalid since: {}", err))?;
+            SinceSpec::from_value(since)?;
+            Ok(since)
+        }
+        None => Ok(parse_since(s)?),
+    }
+}
+
+pub fn parse_address(s: &str) -> Result<Address> {
+    Address::from_str(s).map_err(|err| anyhow!("invalid address `{}`: {}", s, err))
+}
+
+/// mainnet, testnet or the 0x genesis hash of another chain.
+pub fn parse_chain(s: &str) -> Result<H256> {
+    match s {
+        "mainnet" => Ok(MAINNET_GENESIS_HASH),
+        "testnet" => Ok(TESTNET_GENESIS_HASH),
+        _ => parse_h256(s),
+    }
+}
+
+pub fn parse_network(s: &str) -> Result<NetworkType> {
+    match s {
+        "mainnet" => Ok(NetworkType::Mainnet),
+        "testnet" => Ok(NetworkType::Testnet),
+        _ => bail!("unknown network `{}`, expected mainnet or testnet", s),
+    }
+}
+
+pub fn parse_hash_type(s: &str) -> Result<ScriptHashType> {
+    match s {
+        "data" => Ok(ScriptHashType::Data),
+        "type" => Ok(ScriptHashType::Type),
+        "data1" => Ok(ScriptHashType::Data1),
+        "data2" => Ok(ScriptHashType::Data2),
+        _ => bail!("unknown hash type `{}`", s),
+    }
+}
+
+pub fn parse_cell_dep(s: &str) -> Result<CellDep> {
+    let mut parts = s.split(':');
+    let tx_hash = parse_h256(parts.next().unwrap_or_default())?;
+    let index: u32 = parts
+        .next()
+        .ok_or_else(|| anyhow!("missing the index of cell dep `{}`", s))?
+        .parse()?;
+    let dep_type = match parts.next() {
+        None | Some("code") => DepType::Code,
+        Some("dep_group") => DepType::DepGroup,
+        Some(other) => bail!("unknown dep type `{}`", other),
+    };
+    Ok(CellDep::new_builder()
+        .out_point(OutPoint::new(tx_hash.pack(), index))
+        .dep_type(dep_type)
+        .build())
+}
+
+/// A config file, TOML unless the extension is `.json`.
+pub fn load_config(path: &Path) -> Result<MultisigConfig> {
+    Ok(load_config_file(path)?.to_config()?)
+}
+
+/// A config file, JSON by its extension or else TOML.
+pub fn load_config_file(path: &Path) -> Result<ConfigFile> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("read config {}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "json") {
+        Ok(ConfigFile::from_json(&content)?)
+    } else {
+        Ok(ConfigFile::from_toml(&content)?)
+    }
+}
+
+/// A registry of deployments, JSON by its extension or else TOML.
+pub fn load_registry(path: &Path) -> Result<Registry> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("read registry {}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "json") {
+        Ok(Registry::from_json(&content)?)
+    } else {
+        Ok(Registry::from_toml(&content)?)
+    }
+}
+
+/// A private key file in the ckb-cli format: the hex key on the first line.
+pub fn load_signer(path: &Path) -> Result<BoxedSigner> {
+    Ok(Box::new(SecpSigner::from_slice(&read_privkey(path)?)?))
+}
+
+/// The bytes of a private key file, see `load_signer`.
+pub fn read_privkey(path: &Path) -> Result<Vec<u8>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("read key {}", path.display()))?;
+    let line = content.lines().next().unwrap_or_default().trim();
+    hex::decode(line.trim_start_matches("0x"))
+        .with_context(|| format!("invalid key {}", path.display()))
+}
+
+pub fn load_request(path: &Path) -> Result<SigningRequest> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("read request {}", path.display()))?;
+    SigningRequest::from_json(&content).with_context(|| format!("parse {}", path.display()))
+}
+
+/// The proposal files, and the `.json` files of the directories, e.g. the
+/// `--dir` of the coordination server.
+pub fn load_proposals(paths: &[PathBuf]) -> Result<Vec<SigningRequest>> {
+    proposal_files(paths)?
+        .iter()
+        .map(|path| load_request(path))
+        .collect()
+}
+
+/// The paths of the proposals of `load_proposals`, those of a directory by
+/// name.
+pub fn proposal_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let entries = fs::read_dir(path).with_context(|| format!("read {}", path.display()))?;
+        let mut found = Vec::new();
+        for entry in entries {
+            let file = entry?.path();
+            if file.extension().is_some_and(|ext| ext == "json") {
+                found.push(file);
+            }
+        }
+        found.sort();
+        files.extend(found);
+    }
+    Ok(files)
+}
+
+pub fn save_request(path: &Path, request: &SigningRequest) -> Result<()> {
+    fs::write(path, request.to_json()?).with_context(|| format!("write {}", path.display()))
+}
+
+/// A transaction in the node RPC format, or wrapped by a `ckb-cli tx` file or
+/// a proposal.
+pub fn load_tx(path: &Path) -> Result<TransactionView> {
+    let content = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let file: Value =
+        serde_json::from_str(&content).with_context(|| format!("parse {}", path.display()))?;
+    let tx = file
+        .get("transaction")
+        .or_else(|| file.get("tx"))
+        .unwrap_or(&file);
+    let tx: json::Transaction = serde_json::from_value(tx.clone())
+        .with_context(|| format!("invalid transaction in {}", path.display()))?;
+    Ok(packed::Transaction::from(tx).into_view())
+}
+
+pub fn format_ckb(shannons: u64) -> String {
+    format!(
+        "{}.{:08} CKB",
+        shannons / 100_000_000,
+        shannons % 100_000_000
+    )
+}
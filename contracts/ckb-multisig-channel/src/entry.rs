@@ -0,0 +1,237 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::{convert::TryInto, result::Result};
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    debug,
+    error::SysError,
+    high_level::{
+        load_cell_capacity, load_cell_data, load_cell_lock_hash, load_script, load_script_hash,
+        load_witness_args, QueryIter,
+    },
+};
+
+use crate::error::Error;
+
+use blake2b_ref::Blake2bBuilder;
+use ckb_multisig_core::{
+    blake2b_256, BLAKE160_SIZE, BLAKE2B_BLOCK_SIZE, CKB_HASH_PERSONALIZATION, U64_SIZE,
+};
+
+/// The args: `2 of 2 multisig hash | dispute delay | channel id`, the delay a
+/// relative since value as little endian u64, the id 32 bytes the parties
+/// pick so that their states are only valid for this channel.
+const DELAY_OFFSET: usize = BLAKE160_SIZE;
+const CHANNEL_ID_OFFSET: usize = DELAY_OFFSET + U64_SIZE;
+const ARGS_SIZE: usize = CHANNEL_ID_OFFSET + BLAKE2B_BLOCK_SIZE;
+
+/// A state: `sequence | amount a | amount b | lock hash a | lock hash b |
+/// revocation hash a | revocation hash b`, the numbers little endian u64.
+/// A closing cell holds a state followed by the index of the party who
+/// closed.
+const STATE_SIZE: usize = 3 * U64_SIZE + 4 * BLAKE2B_BLOCK_SIZE;
+const CLOSING_SIZE: usize = STATE_SIZE + 1;
+
+/// The actions, the first byte of the input type field of the witness.
+const UPDATE: u8 = 0;
+const SETTLE: u8 = 1;
+const PUNISH: u8 = 2;
+
+/// A channel between two parties funded in a cell of this lock.
+///
+/// The parties spend the cell together with the 2 of 2 signatures in the
+/// lock field, as for the multisig lock. Off chain they sign states instead,
+/// each with a higher sequence, and reveal the revocation secret of their
+/// previous state. Alone, a party closes the channel with the last state:
+/// the cell becomes a closing cell, which the other party can update with a
+/// state of a higher sequence, or take entirely with the revocation secret
+/// of the closer if the state was revoked. After the dispute delay, anyone
+/// settles the closing cell, paying the amounts of its state.
+pub fn main() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    debug!("script args is {:?}", args);
+
+    if args.len() != ARGS_SIZE {
+        return Err(Error::ArgumentsLen);
+    }
+    let multisig_hash = &args[0..BLAKE160_SIZE];
+    let witness = match load_witness_args(0, Source::GroupInput) {
+        Ok(witness) => witness,
+        Err(SysError::IndexOutOfBound) => return Err(Error::InvalidAction),
+        Err(err) => return Err(err.into()),
+    };
+    if witness.lock().to_opt().is_some() {
+        ckb_multisig_core::verify(multisig_hash, 0)?;
+        return Ok(());
+    }
+
+    if load_cell_data(1, Source::GroupInput).is_ok() {
+        return Err(Error::InvalidState);
+    }
+    let input = load_cell_data(0, Source::GroupInput)?;
+    let closing = match input.len() {
+        0 => None,
+        CLOSING_SIZE => Some(State::parse(&input)),
+        _ => return Err(Error::InvalidState),
+    };
+    let action = witness
+        .input_type()
+        .to_opt()
+        .map(|field| field.raw_data())
+        .ok_or(Error::InvalidAction)?;
+    match (action.first(), closing) {
+        (Some(&UPDATE), closing) => {
+            let channel_id = &args[CHANNEL_ID_OFFSET..];
+            update(multisig_hash, channel_id, &action[1..], closing)
+        }
+        (Some(&SETTLE), Some(closing)) => {
+            // the same since check as the multisig lock, relative to the close
+            ckb_multisig_core::check_since(read_u64(&args, DELAY_OFFSET))?;
+            settle(&closing)
+        }
+        (Some(&PUNISH), Some(closing)) => punish(&closing, &action[1..]),
+        _ => Err(Error::InvalidAction),
+    }
+}
+
+/// Close with a state signed by both parties, or replace the state of a
+/// closing cell by a newer one.
+fn update(
+    multisig_hash: &[u8],
+    channel_id: &[u8],
+    lock_field: &[u8],
+    closing: Option<State>,
+) -> Result<(), Error> {
+    let script_hash = load_script_hash()?;
+    let mut outputs = QueryIter::new(load_cell_lock_hash, Source::Output)
+        .enumerate()
+        .filter(|(_, lock_hash)| *lock_hash == script_hash)
+        .map(|(i, _)| i);
+    // the closing cell, alone
+    let index = match (outputs.next(), outputs.next()) {
+        (Some(index), None) => index,
+        _ => return Err(Error::InvalidState),
+    };
+    let data = load_cell_data(index, Source::Output)?;
+    if data.len() != CLOSING_SIZE {
+        return Err(Error::InvalidState);
+    }
+    let state = State::parse(&data);
+    if let Some(closing) = closing {
+        if state.sequence <= closing.sequence {
+            return Err(Error::StaleSequence);
+        }
+    }
+
+    let closer = state.lock(state.closer).ok_or(Error::Closer)?;
+    if !QueryIter::new(load_cell_lock_hash, Source::Input).any(|hash| hash == closer) {
+        return Err(Error::Closer);
+    }
+    let capacity = load_cell_capacity(index, Source::Output)?;
+    if capacity < load_cell_capacity(0, Source::GroupInput)? || state.total()? > capacity {
+        return Err(Error::Payout);
+    }
+
+    // both parties signed the state for this channel
+    let mut message = [0u8; BLAKE2B_BLOCK_SIZE];
+    let mut blake2b = Blake2bBuilder::new(BLAKE2B_BLOCK_SIZE)
+        .personal(CKB_HASH_PERSONALIZATION)
+        .build();
+    blake2b.update(channel_id);
+    blake2b.update(&data[..STATE_SIZE]);
+    blake2b.finalize(&mut message);
+    ckb_multisig_core::verify_message(multisig_hash, lock_field, &message)?;
+    Ok(())
+}
+
+/// Pay the amounts of the state of the closing cell.
+fn settle(closing: &State) -> Result<(), Error> {
+    if closing.lock_a == closing.lock_b {
+        return check_paid(&closing.lock_a, closing.total()?);
+    }
+    check_paid(&closing.lock_a, closing.amount_a)?;
+    check_paid(&closing.lock_b, closing.amount_b)
+}
+
+/// Pay everything to the other party when the closer revoked the state.
+fn punish(closing: &State, secret: &[u8]) -> Result<(), Error> {
+    let revocation = match closing.closer {
+        0 => &closing.revocation_a,
+        _ => &closing.revocation_b,
+    };
+    if blake2b_256(secret) != *revocation {
+        return Err(Error::Revocation);
+    }
+    let other = closing.lock(1 - closing.closer).ok_or(Error::Closer)?;
+    check_paid(&other, closing.total()?)
+}
+
+fn check_paid(lock_hash: &[u8; BLAKE2B_BLOCK_SIZE], amount: u64) -> Result<(), Error> {
+    let mut paid: u64 = 0;
+    for (i, hash) in QueryIter::new(load_cell_lock_hash, Source::Output).enumerate() {
+        if hash == *lock_hash {
+            paid = paid
+                .checked_add(load_cell_capacity(i, Source::Output)?)
+                .ok_or(Error::Overflow)?;
+        }
+    }
+    if paid < amount {
+        return Err(Error::Payout);
+    }
+    Ok(())
+}
+
+struct State {
+    sequence: u64,
+    amount_a: u64,
+    amount_b: u64,
+    lock_a: [u8; BLAKE2B_BLOCK_SIZE],
+    lock_b: [u8; BLAKE2B_BLOCK_SIZE],
+    revocation_a: [u8; BLAKE2B_BLOCK_SIZE],
+    revocation_b: [u8; BLAKE2B_BLOCK_SIZE],
+    closer: u8,
+}
+
+impl State {
+    /// The state of closing cell data, of `CLOSING_SIZE` bytes.
+    fn parse(data: &[u8]) -> Self {
+        let u64_at = |i: usize| read_u64(data, i * U64_SIZE);
+        let hash_at = |i: usize| -> [u8; BLAKE2B_BLOCK_SIZE] {
+            data[3 * U64_SIZE + i * BLAKE2B_BLOCK_SIZE..][..BLAKE2B_BLOCK_SIZE]
+                .try_into()
+                .unwrap()
+        };
+        State {
+            sequence: u64_at(0),
+            amount_a: u64_at(1),
+            amount_b: u64_at(2),
+            lock_a: hash_at(0),
+            lock_b: hash_at(1),
+            revocation_a: hash_at(2),
+            revocation_b: hash_at(3),
+            closer: data[STATE_SIZE],
+        }
+    }
+
+    fn lock(&self, party: u8) -> Option<[u8; BLAKE2B_BLOCK_SIZE]> {
+        match party {
+            0 => Some(self.lock_a),
+            1 => Some(self.lock_b),
+            _ => None,
+        }
+    }
+
+    fn total(&self) -> Result<u64, Error> {
+        self.amount_a
+            .checked_add(self.amount_b)
+            .ok_or(Error::Overflow)
+    }
+}
+
+fn read_u64(args: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(args[offset..offset + U64_SIZE].try_into().unwrap())
+}
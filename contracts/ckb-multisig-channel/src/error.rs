@@ -0,0 +1,47 @@
+use ckb_multisig_core::error::Error as MultisigError;
+use ckb_std::error::SysError;
+
+/// Error
+pub enum Error {
+    /// The 2 of 2 signatures of the transaction or of a state failed, or the
+    /// dispute delay isn't over, or a syscall.
+    Multisig(MultisigError),
+    // Add customized errors here...
+    ArgumentsLen,
+    InvalidAction,
+    InvalidState,
+    StaleSequence,
+    Closer,
+    Payout,
+    Revocation,
+    Overflow,
+}
+
+impl Error {
+    /// The exit code, those of the multisig lock for its errors.
+    pub fn code(self) -> i8 {
+        match self {
+            Self::Multisig(err) => err as i8,
+            Self::ArgumentsLen => -1,
+            Self::InvalidAction => -101,
+            Self::InvalidState => -102,
+            Self::StaleSequence => -103,
+            Self::Closer => -104,
+            Self::Payout => -105,
+            Self::Revocation => -106,
+            Self::Overflow => -107,
+        }
+    }
+}
+
+impl From<MultisigError> for Error {
+    fn from(err: MultisigError) -> Self {
+        Self::Multisig(err)
+    }
+}
+
+impl From<SysError> for Error {
+    fn from(err: SysError) -> Self {
+        Self::Multisig(err.into())
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::Path;
+use std::{env, fs};
+
+/// The cap on the keys of a multisig script, 255 when not set. Deployments
+/// set it to bound the witness size and the cycles of the verification.
+const MAX_PUBKEYS_ENV: &str = "CKB_MULTISIG_MAX_PUBKEYS";
+
+fn main() {
+    let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    // `make all-embedded` builds the library with the tables compiled in
+    let build = if env::var("CARGO_FEATURE_EMBEDDED_TABLES").is_ok() {
+        "build-embedded"
+    } else {
+        "build"
+    };
+    println!("cargo:rustc-link-search=native={}", Path::new(&dir).join("../ckb-multisig/ckb-lib-secp256k1").join(build).display());
+    println!("cargo:rustc-link-lib=static=ckb-lib-secp256k1");
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed={}", MAX_PUBKEYS_ENV);
+    let max_pubkeys: u8 = match env::var(MAX_PUBKEYS_ENV) {
+        Ok(value) => match value.parse() {
+            Ok(max) if max > 0 => max,
+            _ => panic!("{} must be between 1 and 255, got {}", MAX_PUBKEYS_ENV, value),
+        },
+        Err(_) => u8::MAX,
+    };
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("max_pubkeys.rs"),
+        format!("pub const MAX_PUBKEYS_CNT: u8 = {};\n", max_pubkeys),
+    )
+    .unwrap();
+}
@@ -0,0 +1,144 @@
+//! Detached approvals: the members sign an action days before the
+//! transaction carrying it is built, bound to a nonce cell of
+//! `ckb-multisig-nonce` so the approval is good for one transaction only.
+//!
+//! The lock field of the first witness of the group is
+//!
+//! ```text
+//! APPROVAL | nonce type hash | genesis hash | max fee | multisig script | signatures
+//! ```
+//!
+//! the max fee a little endian u64. The signatures are over the blake2b of
+//! `multisig hash | genesis hash | action hash | nonce type hash | nonce`,
+//! where the genesis hash is the one of the chain, so an approval collected
+//! on a testnet can't be replayed on the mainnet, and the action
+//! hash is the blake2b of the max fee followed by every output which isn't
+//! the nonce cell, each cell followed by its data prefixed by its length as
+//! a little endian u64, and the nonce is the one of the nonce cell spent by
+//! the transaction, a little endian u64. The transaction may pay at most the
+//! max fee, the inputs being unknown when the members approve, and carry
+//! the genesis header as a header dep.
+
+use core::{convert::TryInto, result::Result};
+
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::prelude::*,
+    high_level::{
+        load_cell, load_cell_capacity, load_cell_data, load_cell_type_hash, load_header, QueryIter,
+    },
+};
+
+use blake2b_ref::Blake2bBuilder;
+
+use crate::{
+    blake2b_256, check_since, error::Error, verify_message, BLAKE160_SIZE, BLAKE2B_BLOCK_SIZE,
+    CKB_HASH_PERSONALIZATION, U64_SIZE,
+};
+
+/// The first byte of an approval lock field, next to `CONFIG_V2` and
+/// `CONFIG_PROOF` above every key format a multisig lock field starts with.
+pub const APPROVAL: u8 = 0x82;
+
+const NONCE_TYPE_HASH_OFFSET: usize = 1;
+const GENESIS_HASH_OFFSET: usize = NONCE_TYPE_HASH_OFFSET + BLAKE2B_BLOCK_SIZE;
+const MAX_FEE_OFFSET: usize = GENESIS_HASH_OFFSET + BLAKE2B_BLOCK_SIZE;
+const MULTISIG_OFFSET: usize = MAX_FEE_OFFSET + U64_SIZE;
+
+/// Whether `lock_bytes` is an approval lock field.
+pub fn is_approval(lock_bytes: &[u8]) -> bool {
+    lock_bytes.first() == Some(&APPROVAL)
+}
+
+/// Verify the approval lock field `lock_bytes` of the script group: the
+/// multisig script must hash to `multisig_hash` and the group inputs must
+/// satisfy `since` as in `check_since`.
+pub fn verify(multisig_hash: &[u8], since: u64, lock_bytes: &[u8]) -> Result<(), Error> {
+    if lock_bytes.len() < MULTISIG_OFFSET || lock_bytes[0] != APPROVAL {
+        return Err(Error::WitnessSize);
+    }
+    let nonce_type_hash: [u8; BLAKE2B_BLOCK_SIZE] = lock_bytes
+        [NONCE_TYPE_HASH_OFFSET..GENESIS_HASH_OFFSET]
+        .try_into()
+        .unwrap();
+    let genesis_hash = &lock_bytes[GENESIS_HASH_OFFSET..MAX_FEE_OFFSET];
+    let max_fee = &lock_bytes[MAX_FEE_OFFSET..MULTISIG_OFFSET];
+    check_since(since)?;
+    check_chain(genesis_hash)?;
+    check_fee(u64::from_le_bytes(max_fee.try_into().unwrap()))?;
+
+    let mut message = [0u8; BLAKE2B_BLOCK_SIZE];
+    let mut blake2b = new_blake2b();
+    blake2b.update(&multisig_hash[..BLAKE160_SIZE]);
+    blake2b.update(genesis_hash);
+    blake2b.update(&action_hash(&nonce_type_hash, max_fee)?);
+    blake2b.update(&nonce_type_hash);
+    blake2b.update(&nonce(&nonce_type_hash)?.to_le_bytes());
+    blake2b.finalize(&mut message);
+    verify_message(multisig_hash, &lock_bytes[MULTISIG_OFFSET..], &message)
+}
+
+/// The transaction is on the chain of `genesis_hash`: its block 0 is a
+/// header dep, which the node only accepts when it has the block.
+fn check_chain(genesis_hash: &[u8]) -> Result<(), Error> {
+    let genesis = QueryIter::new(load_header, Source::HeaderDep).any(|header| {
+        Unpack::<u64>::unpack(&header.raw().number()) == 0
+            && blake2b_256(header.as_slice())[..] == genesis_hash[..]
+    });
+    if !genesis {
+        return Err(Error::Chain);
+    }
+    Ok(())
+}
+
+/// The nonce of the nonce cell spent by the transaction, its type script
+/// increments it in the outputs.
+fn nonce(nonce_type_hash: &[u8; BLAKE2B_BLOCK_SIZE]) -> Result<u64, Error> {
+    let index = QueryIter::new(load_cell_type_hash, Source::Input)
+        .position(|type_hash| type_hash.as_ref() == Some(nonce_type_hash))
+        .ok_or(Error::NonceCell)?;
+    let data = load_cell_data(index, Source::Input)?;
+    let nonce: [u8; U64_SIZE] = data[..].try_into().map_err(|_| Error::NonceCell)?;
+    Ok(u64::from_le_bytes(nonce))
+}
+
+fn action_hash(
+    nonce_type_hash: &[u8; BLAKE2B_BLOCK_SIZE],
+    max_fee: &[u8],
+) -> Result<[u8; BLAKE2B_BLOCK_SIZE], Error> {
+    let mut blake2b = new_blake2b();
+    blake2b.update(max_fee);
+    for (i, output) in QueryIter::new(load_cell, Source::Output).enumerate() {
+        if load_cell_type_hash(i, Source::Output)?.as_ref() == Some(nonce_type_hash) {
+            continue;
+        }
+        let data = load_cell_data(i, Source::Output)?;
+        blake2b.update(output.as_slice());
+        blake2b.update(&(data.len() as u64).to_le_bytes());
+        blake2b.update(&data);
+    }
+    let mut hash = [0u8; BLAKE2B_BLOCK_SIZE];
+    blake2b.finalize(&mut hash);
+    Ok(hash)
+}
+
+/// The capacity of the inputs not paid to the outputs is at most `max_fee`.
+pub fn check_fee(max_fee: u64) -> Result<(), Error> {
+    let mut fee: u64 = 0;
+    for capacity in QueryIter::new(load_cell_capacity, Source::Input) {
+        fee = fee.checked_add(capacity).ok_or(Error::Fee)?;
+    }
+    for capacity in QueryIter::new(load_cell_capacity, Source::Output) {
+        fee = fee.checked_sub(capacity).ok_or(Error::Fee)?;
+    }
+    if fee > max_fee {
+        return Err(Error::Fee);
+    }
+    Ok(())
+}
+
+fn new_blake2b() -> blake2b_ref::Blake2b {
+    Blake2bBuilder::new(BLAKE2B_BLOCK_SIZE)
+        .personal(CKB_HASH_PERSONALIZATION)
+        .build()
+}
@@ -0,0 +1,63 @@
+//! The multisig verification as an exec callee, for other scripts embedding
+//! m of n authorization without reimplementing it.
+//!
+//! The caller execs the multisig lock binary from a cell dep with `ARGC`
+//! arguments, hex encoded without prefix since argv holds C strings:
+//!
+//! * `argv[0]`: the blake160 of the multisig script, 20 bytes, usually taken
+//!   from the args of the caller;
+//! * `argv[1]`: the message the members signed, 32 bytes;
+//! * `argv[2]`: the multisig lock field `multisig script | signatures`, laid
+//!   out as in the witness of the multisig lock.
+//!
+//! The callee exits with 0 when the signatures reach the threshold of the
+//! multisig script, otherwise with the error code of the multisig lock. The
+//! since of the lock args is not checked, it is up to the caller. Exec
+//! replaces the caller, so it is the last thing the caller does and the exit
+//! code of the callee is the one of the script.
+
+use alloc::vec::Vec;
+use core::result::Result;
+
+use ckb_std::{
+    ckb_types::core::ScriptHashType,
+    cstr_core::{CStr, CString},
+    error::SysError,
+    high_level::exec_cell,
+};
+
+use crate::{error::Error, verify_message, BLAKE160_SIZE, BLAKE2B_BLOCK_SIZE};
+
+/// The number of arguments of the callee.
+pub const ARGC: usize = 3;
+
+/// Exec the multisig lock deployed at `code_hash` to verify `lock_bytes`
+/// over `message`, only returns when the binary isn't in the cell deps.
+pub fn exec(
+    code_hash: &[u8],
+    hash_type: ScriptHashType,
+    multisig_hash: &[u8],
+    message: &[u8; BLAKE2B_BLOCK_SIZE],
+    lock_bytes: &[u8],
+) -> Result<u64, SysError> {
+    let args: Vec<CString> = [multisig_hash, &message[..], lock_bytes]
+        .iter()
+        .map(|arg| CString::new(hex::encode(arg)).expect("no nul in hex"))
+        .collect();
+    let argv: Vec<&CStr> = args.iter().map(|arg| arg.as_c_str()).collect();
+    exec_cell(code_hash, hash_type, 0, 0, &argv)
+}
+
+/// The callee side: verify the arguments of an exec, see the module
+/// documentation.
+pub fn verify_argv(argv: &[&[u8]]) -> Result<(), Error> {
+    if argv.len() != ARGC {
+        return Err(Error::ArgumentsLen);
+    }
+    let mut multisig_hash = [0u8; BLAKE160_SIZE];
+    let mut message = [0u8; BLAKE2B_BLOCK_SIZE];
+    hex::decode_to_slice(argv[0], &mut multisig_hash).map_err(|_| Error::Encoding)?;
+    hex::decode_to_slice(argv[1], &mut message).map_err(|_| Error::Encoding)?;
+    let lock_bytes = hex::decode(argv[2]).map_err(|_| Error::Encoding)?;
+    verify_message(&multisig_hash, &lock_bytes, &message)
+}
@@ -0,0 +1,53 @@
+use ckb_std::error::SysError;
+
+/// Error
+#[repr(i8)]
+pub enum Error {
+    IndexOutOfBound = 1,
+    ItemMissing,
+    LengthNotEnough,
+    Encoding,
+    /// A syscall error unknown to ckb-std, e.g. of a newer VM.
+    Unknown,
+    // Add customized errors here...
+    ArgumentsLen = -1,
+    WitnessSize = -22,
+    IncorrectSinceFlags = -23,
+    IncorrectSinceValue = -24,
+    InvalidSinceFlags = -25,
+    IncorrectSinceRelative = -26,
+    InvalidSinceEpoch = -27,
+    HeaderDeps = -28,
+    GroupWitness = -29,
+    // PubkeyBlake160Hash = -31,
+    InvalidReserveField = -41,
+    InvalidPubkeysCnt = -42,
+    InvalidThreshold = -43,
+    InvalidRequireFirstN = -44,
+    PubkeysCap = -45,
+    ConfigProof = -46,
+    MultsigScriptHash = -51,
+    Verification = -52,
+    NetPosition = -53,
+    TypedCell = -54,
+    NonceCell = -55,
+    Fee = -56,
+    SpendAll = -57,
+    Successor = -58,
+    Chain = -59,
+    TooManyCells = -61,
+    TypeId = -62,
+}
+
+impl From<SysError> for Error {
+    fn from(err: SysError) -> Self {
+        use SysError::*;
+        match err {
+            IndexOutOfBound => Self::IndexOutOfBound,
+            ItemMissing => Self::ItemMissing,
+            LengthNotEnough(_) => Self::LengthNotEnough,
+            Encoding => Self::Encoding,
+            Unknown(_) => Self::Unknown,
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! The witness layouts of older wallets, accepted under the legacy witness
+//! flag of the lock args so that their users aren't locked out of migrated
+//! cells:
+//!
+//! * the other witnesses of the group hold an empty `WitnessArgs`, 16 bytes,
+//!   instead of nothing, which `check_group_witnesses` refuses;
+//! * the lock field sits in a later witness of the group, those before it
+//!   being placeholders, empty or empty `WitnessArgs`, left for inputs the
+//!   wallet signed in another pass.
+//!
+//! The message is that of `verify` with the witness of the lock field first,
+//! the other witnesses of the group following in order. Moving the lock
+//! field between placeholders doesn't change it, witnesses aren't part of
+//! the transaction hash anyway.
+
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+use ckb_std::ckb_constants::Source;
+
+use crate::{error::Error, witnesses};
+
+/// A `WitnessArgs` without any field: its total size and the offsets of its
+/// 3 fields, all 16.
+const EMPTY_WITNESS_ARGS: [u8; 16] = [16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0];
+
+/// An empty witness, or an empty `WitnessArgs`.
+pub fn is_placeholder(witness: &[u8]) -> bool {
+    witness.is_empty() || witness == EMPTY_WITNESS_ARGS
+}
+
+/// `crate::verify` with the lock field in the first witness of the group
+/// which isn't a placeholder.
+pub fn verify(multisig_hash: &[u8], since: u64) -> Result<(), Error> {
+    crate::verify_witness(lock_witness()?, multisig_hash, since)
+}
+
+/// `crate::check_group_witnesses` with placeholders taken as empty: all the
+/// witnesses of the group but that of the lock field are placeholders.
+pub fn check_group_witnesses() -> Result<(), Error> {
+    let index = lock_witness()?;
+    for (i, witness) in witnesses(Source::GroupInput).enumerate() {
+        if i != index && !is_placeholder(&witness?) {
+            return Err(Error::GroupWitness);
+        }
+    }
+    Ok(())
+}
+
+/// The index in the group of the witness holding the lock field.
+fn lock_witness() -> Result<usize, Error> {
+    for (i, witness) in witnesses(Source::GroupInput).enumerate() {
+        if !is_placeholder(&witness?) {
+            return Ok(i);
+        }
+    }
+    Err(Error::WitnessSize)
+}
@@ -0,0 +1,412 @@
+//! The multisig verification shared by the contracts: the lock field of the
+//! first witness of the script group carries the multisig script and the
+//! signatures, see `verify`. See `auth.rs` for the verification exec-ed by
+//! other scripts, `approval.rs` for the approvals signed before the
+//! transaction, `median_time.rs` for the timestamp since checked against
+//! header deps, `legacy.rs` for the witness layouts of older wallets and
+//! `type_id.rs` for the type id of the singleton cells.
+//!
+//! The secp256k1 library is linked from `ckb-multisig/ckb-lib-secp256k1`,
+//! see `build.rs`.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod approval;
+pub mod auth;
+pub mod error;
+pub mod legacy;
+mod lock_field;
+pub mod median_time;
+mod secp256k1_helper;
+pub mod type_id;
+
+use alloc::vec::Vec;
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::{ops::Range, result::Result};
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    error::SysError,
+    high_level::{load_input_since, load_tx_hash, load_witness},
+    syscalls,
+};
+
+use crate::{
+    error::Error,
+    lock_field::{lock_range, LockField},
+};
+
+use blake2b_ref::Blake2bBuilder;
+
+pub const BLAKE160_SIZE: usize = 20;
+pub const U64_SIZE: usize = 8;
+pub const FLAGS_SIZE: usize = 4;
+pub const SIGNATURE_SIZE: usize = 65;
+pub const BLAKE2B_BLOCK_SIZE: usize = 32;
+pub const CKB_HASH_PERSONALIZATION: &[u8] = b"ckb-default-hash";
+
+/// How the keys are hashed in the multisig script, its first byte: the
+/// blake160 of the 33 bytes compressed public key, of the 65 bytes
+/// uncompressed one for keys only exported that way, or of either.
+pub const KEY_FORMAT_COMPRESSED: u8 = 0;
+pub const KEY_FORMAT_UNCOMPRESSED: u8 = 1;
+pub const KEY_FORMAT_EITHER: u8 = 2;
+
+/// The first byte of a lock field whose config is molecule encoded, see
+/// `lock_field.rs`, above every key format.
+pub const CONFIG_V2: u8 = 0x80;
+/// The first byte of a lock field proving its config is a leaf of the Merkle
+/// root of the lock args, see `lock_field.rs`, followed by the proof then the
+/// lock field of the config.
+pub const CONFIG_PROOF: u8 = 0x81;
+/// At most 256 alternative configs under a root.
+pub const MAX_PROOF_DEPTH: usize = 8;
+
+// `MAX_PUBKEYS_CNT`, the cap on `pubkeys_cnt` set by the deployment when
+// building, see `build.rs`.
+include!(concat!(env!("OUT_DIR"), "/max_pubkeys.rs"));
+
+/// Verify the multisig lock field of the script group: the multisig script
+/// must hash to `multisig_hash`, the group inputs must satisfy `since` as in
+/// `check_since`, and the signatures must reach the threshold of the script.
+pub fn verify(multisig_hash: &[u8], since: u64) -> Result<(), Error> {
+    verify_witness(0, multisig_hash, since)
+}
+
+/// `verify` with the lock field in the witness `index` of the group, see
+/// `legacy.rs`.
+fn verify_witness(index: usize, multisig_hash: &[u8], since: u64) -> Result<(), Error> {
+    let witness = load_witness(index, Source::GroupInput)?;
+    let range = lock_range(&witness)?;
+    let lock = LockField::parse(multisig_hash, &witness[range.clone()])?;
+    check_since(since)?;
+
+    // the signatures end the lock field
+    let zeroed = range.end - lock.signatures().len()..range.end;
+    let message = group_message(index, &witness, zeroed)?;
+    validate(&lock, &message)
+}
+
+/// Verify the single signature of the fee key in the lock field of the
+/// script group, over the same message as the multisig signatures, the
+/// group inputs must satisfy `since` as in `check_since`. What the fee key
+/// may do is up to the caller.
+pub fn verify_fee_key(fee_key_hash: &[u8], since: u64) -> Result<(), Error> {
+    let witness = load_witness(0, Source::GroupInput)?;
+    let range = lock_range(&witness)?;
+    if range.len() != SIGNATURE_SIZE {
+        return Err(Error::WitnessSize);
+    }
+    check_since(since)?;
+    let message = group_message(0, &witness, range.clone())?;
+    verify_signature(fee_key_hash, &message, &witness[range])
+}
+
+/// Verify a multisig lock field signing `message` instead of the transaction,
+/// e.g. a state agreed off chain.
+pub fn verify_message(
+    multisig_hash: &[u8],
+    lock_bytes: &[u8],
+    message: &[u8; BLAKE2B_BLOCK_SIZE],
+) -> Result<(), Error> {
+    let lock = LockField::parse(multisig_hash, lock_bytes)?;
+    validate(&lock, message)
+}
+
+/// Check the head of a lock field without its signatures, e.g. a config
+/// published on chain: a multisig script or `CONFIG_V2` and a molecule
+/// config, either after a `CONFIG_PROOF`, well formed as `verify` parses it
+/// and hashing to `multisig_hash`. Returns the pubkey hashes.
+pub fn check_multisig_script<'a>(
+    multisig_hash: &[u8],
+    script: &'a [u8],
+) -> Result<&'a [u8], Error> {
+    Ok(LockField::parse_unsigned(multisig_hash, script)?.pubkey_hashes())
+}
+
+/// Check the signatures of a parsed lock field. Many users start with 1 of
+/// 1 before adding keys, so that configuration recovers its single
+/// signature and compares the hash of the key, as the sighash lock, skipping
+/// the threshold and `require_first_n` bookkeeping.
+fn validate(lock: &LockField, message: &[u8]) -> Result<(), Error> {
+    if lock.threshold() == 1 && lock.pubkeys_cnt() == 1 {
+        return secp256k1_helper::validate_secp256k1_signature(
+            lock.key_format(),
+            message,
+            lock.signatures(),
+            lock.pubkey_hashes(),
+        );
+    }
+    secp256k1_helper::validate_secp256k1_multisignautre(
+        lock.key_format(),
+        lock.require_first_n(),
+        lock.threshold(),
+        lock.pubkeys_cnt(),
+        message,
+        lock.pubkey_hashes(),
+        lock.signatures(),
+    )
+}
+
+/// Verify a single signature of `message` by the key of `pubkey_hash`, as
+/// the lock field of a 1 of 1 multisig script.
+pub fn verify_signature(
+    pubkey_hash: &[u8],
+    message: &[u8; BLAKE2B_BLOCK_SIZE],
+    signature: &[u8],
+) -> Result<(), Error> {
+    verify_key_signature(KEY_FORMAT_COMPRESSED, pubkey_hash, message, signature)
+}
+
+/// `verify_signature` for a key hashed in `key_format`, e.g. a member of a
+/// multisig script whose first byte is `key_format`.
+pub fn verify_key_signature(
+    key_format: u8,
+    pubkey_hash: &[u8],
+    message: &[u8; BLAKE2B_BLOCK_SIZE],
+    signature: &[u8],
+) -> Result<(), Error> {
+    if key_format > KEY_FORMAT_EITHER {
+        return Err(Error::InvalidReserveField);
+    }
+    if pubkey_hash.len() != BLAKE160_SIZE || signature.len() != SIGNATURE_SIZE {
+        return Err(Error::WitnessSize);
+    }
+    secp256k1_helper::validate_secp256k1_signature(key_format, message, signature, pubkey_hash)
+}
+
+/// The witnesses of the group but the first must be empty or missing. They
+/// are signed anyway, this refuses transactions whose size, thus fee, was
+/// inflated before the signatures were collected.
+pub fn check_group_witnesses() -> Result<(), Error> {
+    for i in 1.. {
+        match load_input_since(i, Source::GroupInput) {
+            Ok(_) => {}
+            Err(SysError::IndexOutOfBound) => break,
+            Err(err) => return Err(err.into()),
+        }
+        // only the length is loaded
+        match syscalls::load_witness(&mut [], 0, i, Source::GroupInput) {
+            Ok(_) | Err(SysError::IndexOutOfBound) => {}
+            Err(SysError::LengthNotEnough(_)) => return Err(Error::GroupWitness),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+/// The hash of CKB, blake2b 256 with the CKB personalization.
+pub fn blake2b_256(data: &[u8]) -> [u8; BLAKE2B_BLOCK_SIZE] {
+    let mut blake2b = Blake2bBuilder::new(BLAKE2B_BLOCK_SIZE)
+        .personal(CKB_HASH_PERSONALIZATION)
+        .build();
+    blake2b.update(data);
+    let mut hash = [0; BLAKE2B_BLOCK_SIZE];
+    blake2b.finalize(&mut hash);
+    hash
+}
+
+/// The message signed for the script group: the tx hash, then the witness
+/// `index` of the group holding the lock field, the first but in legacy
+/// layouts, with its bytes in `zeroed` set to 0, the other witnesses of the
+/// group and the witnesses beyond the inputs, each prefixed by its length.
+fn group_message(
+    index: usize,
+    witness: &[u8],
+    zeroed: Range<usize>,
+) -> Result<[u8; BLAKE2B_BLOCK_SIZE], Error> {
+    let mut blake2b = Blake2bBuilder::new(BLAKE2B_BLOCK_SIZE)
+        .personal(CKB_HASH_PERSONALIZATION)
+        .build();
+    blake2b.update(&load_tx_hash()?);
+    blake2b.update(&(witness.len() as u64).to_le_bytes());
+    blake2b.update(&witness[..zeroed.start]);
+    let zeros = [0u8; SIGNATURE_SIZE];
+    let mut remaining = zeroed.len();
+    while remaining > 0 {
+        let len = core::cmp::min(remaining, SIGNATURE_SIZE);
+        blake2b.update(&zeros[..len]);
+        remaining -= len;
+    }
+    blake2b.update(&witness[zeroed.end..]);
+
+    for (i, data) in witnesses(Source::GroupInput).enumerate() {
+        let data = data?;
+        if i != index {
+            blake2b.update(&(data.len() as u64).to_le_bytes());
+            blake2b.update(&data);
+        }
+    }
+    // For safety consideration, this lock script will also hash and guard all witnesses that
+    // have index values equal to or larger than the number of input cells. It assumes all
+    // witnesses that do have an input cell with the same index, will be guarded by the lock
+    // script of the input cell.
+    //
+    // For convenience reason, we provide a utility function here to calculate the number of
+    // input cells in a transaction
+    let i = calculate_inputs_len()?;
+    for data in witnesses(Source::Input).skip(i) {
+        let data = data?;
+        blake2b.update(&(data.len() as u64).to_le_bytes());
+        blake2b.update(&data);
+    }
+    let mut tmp = [0; BLAKE2B_BLOCK_SIZE];
+    blake2b.finalize(&mut tmp);
+    Ok(tmp)
+}
+
+/// The witnesses of `source` in order, up to the first missing one. Unlike
+/// `QueryIter`, which panics on any other error of the syscall, the error is
+/// returned, for the caller to fail with.
+pub(crate) fn witnesses(source: Source) -> impl Iterator<Item = Result<Vec<u8>, Error>> {
+    (0..)
+        .map(move |i| load_witness(i, source))
+        .take_while(|witness| !matches!(witness, Err(SysError::IndexOutOfBound)))
+        .map(|witness| witness.map_err(Error::from))
+}
+
+/* calculate inputs length */
+fn calculate_inputs_len() -> Result<usize, Error> {
+    /* lower bound, at least tx has one input */
+    let mut lo = 0;
+    /* higher bound */
+    let mut hi = 4;
+    /* try to load input until failing to increase lo and hi */
+    while has_input(hi)? {
+        lo = hi;
+        hi *= 2;
+    }
+
+    /* now we get our lower bound and higher bound,
+    count number of inputs by binary search */
+    while lo + 1 != hi {
+        let i = (lo + hi) / 2;
+        if has_input(i)? {
+            lo = i;
+        } else {
+            hi = i;
+        }
+    }
+    /* now lo is last input index and hi is length of inputs */
+    Ok(hi)
+}
+
+/// Whether the transaction has an input `i`. Only a missing input ends it,
+/// any other error is returned rather than miscounting the inputs, thus
+/// leaving witnesses out of the message.
+fn has_input(i: usize) -> Result<bool, Error> {
+    match load_input_since(i, Source::Input) {
+        Ok(_) => Ok(true),
+        Err(SysError::IndexOutOfBound) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Every input of the group must be locked at least until `since`, with the
+/// same flags. A since with reserved flag bits, the reserved metric or an
+/// epoch fraction not less than 1 is refused, no input could satisfy it the
+/// way it was meant.
+pub fn check_since(since: u64) -> Result<(), Error> {
+    const SINCE_VALUE_BITS: usize = 56;
+    const SINCE_VALUE_MASK: u64 = 0x00ffffffffffffff;
+    const SINCE_RELATIVE_FLAG: u64 = 0b10000000;
+    const SINCE_METRIC_MASK: u64 = 0b01100000;
+    const SINCE_RESERVED_MASK: u64 = 0b00011111;
+    const SINCE_EPOCH_FRACTION_FLAG: u64 = 0b00100000;
+    const SINCE_RESERVED_METRIC: u64 = 0b01100000;
+
+    let since_flags = since >> SINCE_VALUE_BITS;
+    let since_value = since & SINCE_VALUE_MASK;
+    let since_metric = since_flags & SINCE_METRIC_MASK;
+    if since_flags & SINCE_RESERVED_MASK != 0 || since_metric == SINCE_RESERVED_METRIC {
+        return Err(Error::InvalidSinceFlags);
+    }
+    if since_metric == SINCE_EPOCH_FRACTION_FLAG && !epoch_is_well_formed(since_value) {
+        return Err(Error::InvalidSinceEpoch);
+    }
+
+    for i in 0.. {
+        match load_input_since(i, Source::GroupInput) {
+            Ok(input_since) => {
+                let input_since_flags = input_since >> SINCE_VALUE_BITS;
+                let input_since_value = input_since & SINCE_VALUE_MASK;
+                if since_flags & SINCE_RELATIVE_FLAG != input_since_flags & SINCE_RELATIVE_FLAG {
+                    return Err(Error::IncorrectSinceRelative);
+                } else if since_flags != input_since_flags {
+                    return Err(Error::IncorrectSinceFlags);
+                } else if since_metric == SINCE_EPOCH_FRACTION_FLAG {
+                    // relative epochs are fractions too
+                    let ret = epoch_number_with_fraction_cmp(input_since_value, since_value);
+                    if ret < 0 {
+                        return Err(Error::IncorrectSinceValue);
+                    }
+                } else if input_since_value < since_value {
+                    return Err(Error::IncorrectSinceValue);
+                }
+            }
+            Err(SysError::IndexOutOfBound) => break,
+            Err(err) => return Err(err.into()),
+        };
+    }
+    Ok(())
+}
+
+/// The fraction of an epoch since value is less than 1, or 0 / 0.
+fn epoch_is_well_formed(value: u64) -> bool {
+    let index = (value >> 24) & 0xffff;
+    let length = (value >> 40) & 0xffff;
+    index < length || (index == 0 && length == 0)
+}
+
+/* a and b are since value,
+return 0 if a is equals to b,
+return -1 if a is less than b,
+return 1 if a is greater than b */
+fn epoch_number_with_fraction_cmp(a: u64, b: u64) -> i32 {
+    let number_offset = 0;
+    let number_bits = 24;
+    let number_maximum_value = 1 << number_bits;
+    let number_mask = number_maximum_value - 1;
+    let index_offset = number_bits;
+    let index_bits = 16;
+    let index_maximum_value = 1 << index_bits;
+    let index_mask = index_maximum_value - 1;
+    let length_offset = number_bits + index_bits;
+    let length_bits = 16;
+    let length_maximum_value = 1 << length_bits;
+    let length_mask = length_maximum_value - 1;
+
+    /* extract a epoch */
+    let a_epoch = (a >> number_offset) & number_mask;
+    let a_index = (a >> index_offset) & index_mask;
+    let a_len = (a >> length_offset) & length_mask;
+
+    /* extract b epoch */
+    let b_epoch = (b >> number_offset) & number_mask;
+    let b_index = (b >> index_offset) & index_mask;
+    let b_len = (b >> length_offset) & length_mask;
+
+    if a_epoch < b_epoch {
+        return -1;
+    } else if a_epoch > b_epoch {
+        return 1;
+    } else {
+        /* a and b is in the same epoch,
+          compare a_index / a_len <=> b_index / b_len
+        */
+        let a_block = a_index * b_len;
+        let b_block = b_index * a_len;
+        /* compare block */
+        if a_block < b_block {
+            return -1;
+        } else if a_block > b_block {
+            return 1;
+        } else {
+            return 0;
+        }
+    }
+}
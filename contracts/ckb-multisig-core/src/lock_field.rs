@@ -0,0 +1,289 @@
+//! Zero-copy reads of the lock field: the witness is loaded once as raw
+//! bytes, its molecule structure verified once, and everything else is read
+//! at fixed offsets from checked lengths, without rebuilding `WitnessArgs`.
+
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::{ops::Range, result::Result};
+
+use ckb_std::ckb_types::{packed::WitnessArgsReader, prelude::*};
+
+use blake2b_ref::Blake2bBuilder;
+
+use crate::{
+    blake2b_256, error::Error, BLAKE160_SIZE, BLAKE2B_BLOCK_SIZE, CKB_HASH_PERSONALIZATION,
+    CONFIG_PROOF, CONFIG_V2, FLAGS_SIZE, KEY_FORMAT_EITHER, MAX_PROOF_DEPTH, MAX_PUBKEYS_CNT,
+    SIGNATURE_SIZE,
+};
+
+/// The molecule header of a `WitnessArgs`: its total size and the offsets of
+/// its lock, input type and output type fields, little endian u32.
+const LOCK_OFFSET_OFFSET: usize = 4;
+const INPUT_TYPE_OFFSET_OFFSET: usize = 8;
+/// The length header of the molecule `Bytes` of the lock field.
+const BYTES_HEADER_SIZE: usize = 4;
+
+/// The range of the raw lock field in the `WitnessArgs` of `witness`,
+/// `Error::WitnessSize` when it has none.
+pub(crate) fn lock_range(witness: &[u8]) -> Result<Range<usize>, Error> {
+    WitnessArgsReader::verify(witness, false).map_err(|_| Error::Encoding)?;
+    // a verified table has its header, the lock is its first field
+    let start = read_u32(witness, LOCK_OFFSET_OFFSET);
+    let end = read_u32(witness, INPUT_TYPE_OFFSET_OFFSET);
+    if start == end {
+        return Err(Error::WitnessSize);
+    }
+    Ok(start + BYTES_HEADER_SIZE..end)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ]) as usize
+}
+
+/// A multisig lock field, either `multisig script | signatures` with the
+/// multisig script `key format | require_first_n | threshold | pubkeys_cnt |
+/// pubkey hashes`, or `CONFIG_V2 | config | signatures` with the config the
+/// canonical molecule encoding of
+///
+/// ```text
+/// array Byte20 [byte; 20];
+/// vector Byte20Vec <Byte20>;
+/// table MultisigConfigV2 {
+///     key_format: byte,
+///     require_first_n: byte,
+///     threshold: byte,
+///     pubkey_hashes: Byte20Vec,
+/// }
+/// ```
+///
+/// The multisig hash of the lock args is the blake160 of the multisig
+/// script, or of the config. Either can follow `CONFIG_PROOF | depth | path |
+/// siblings` instead, the multisig hash is then a Merkle root over several
+/// configs agreed on beforehand, see `ConfigProof`. Checked once by `parse`,
+/// then read from the fields.
+pub(crate) struct LockField<'a> {
+    key_format: u8,
+    require_first_n: u8,
+    threshold: u8,
+    pubkey_hashes: &'a [u8],
+    signatures: &'a [u8],
+}
+
+impl<'a> LockField<'a> {
+    /// Check the flags and the length of a multisig lock field, and that its
+    /// multisig script or config hashes to `multisig_hash`.
+    pub(crate) fn parse(multisig_hash: &[u8], bytes: &'a [u8]) -> Result<Self, Error> {
+        Self::parse_signed(multisig_hash, bytes, true)
+    }
+
+    /// `parse` of the head of a lock field alone, without any signature.
+    pub(crate) fn parse_unsigned(multisig_hash: &[u8], bytes: &'a [u8]) -> Result<Self, Error> {
+        Self::parse_signed(multisig_hash, bytes, false)
+    }
+
+    fn parse_signed(multisig_hash: &[u8], bytes: &'a [u8], signed: bool) -> Result<Self, Error> {
+        let (proof, bytes) = match bytes.first() {
+            Some(&CONFIG_PROOF) => {
+                let (proof, bytes) = ConfigProof::parse(bytes)?;
+                (Some(proof), bytes)
+            }
+            _ => (None, bytes),
+        };
+        let (committed, lock) = match bytes.first() {
+            Some(&CONFIG_V2) => parse_config_v2(&bytes[1..])?,
+            _ => parse_multisig_script(bytes)?,
+        };
+        if lock.key_format > KEY_FORMAT_EITHER {
+            return Err(Error::InvalidReserveField);
+        }
+        if lock.threshold == 0 {
+            return Err(Error::InvalidThreshold);
+        }
+        if lock.pubkeys_cnt() == 0 {
+            return Err(Error::InvalidPubkeysCnt);
+        }
+        if lock.pubkeys_cnt() > MAX_PUBKEYS_CNT {
+            return Err(Error::PubkeysCap);
+        }
+        if lock.threshold > lock.pubkeys_cnt() {
+            return Err(Error::InvalidThreshold);
+        }
+        if lock.require_first_n > lock.threshold {
+            return Err(Error::InvalidRequireFirstN);
+        }
+        let signatures = if signed {
+            usize::from(lock.threshold)
+        } else {
+            0
+        };
+        if lock.signatures.len() != SIGNATURE_SIZE * signatures {
+            return Err(Error::WitnessSize);
+        }
+        let hash = match proof {
+            Some(proof) => proof.root(committed),
+            None => blake2b_256(committed),
+        };
+        if multisig_hash != &hash[0..BLAKE160_SIZE] {
+            return Err(Error::MultsigScriptHash);
+        }
+        Ok(lock)
+    }
+
+    pub(crate) fn key_format(&self) -> u8 {
+        self.key_format
+    }
+
+    pub(crate) fn require_first_n(&self) -> u8 {
+        self.require_first_n
+    }
+
+    pub(crate) fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// At most 255, checked by `parse`.
+    pub(crate) fn pubkeys_cnt(&self) -> u8 {
+        (self.pubkey_hashes.len() / BLAKE160_SIZE) as u8
+    }
+
+    pub(crate) fn pubkey_hashes(&self) -> &'a [u8] {
+        self.pubkey_hashes
+    }
+
+    /// The signatures, which end the lock field.
+    pub(crate) fn signatures(&self) -> &'a [u8] {
+        self.signatures
+    }
+}
+
+/// The multisig script at the head of `bytes` and the lock field it starts.
+fn parse_multisig_script(bytes: &[u8]) -> Result<(&[u8], LockField), Error> {
+    if bytes.len() < FLAGS_SIZE {
+        return Err(Error::WitnessSize);
+    }
+    let script_len = FLAGS_SIZE + BLAKE160_SIZE * usize::from(bytes[3]);
+    if bytes.len() < script_len {
+        return Err(Error::WitnessSize);
+    }
+    let lock = LockField {
+        key_format: bytes[0],
+        require_first_n: bytes[1],
+        threshold: bytes[2],
+        pubkey_hashes: &bytes[FLAGS_SIZE..script_len],
+        signatures: &bytes[script_len..],
+    };
+    Ok((&bytes[..script_len], lock))
+}
+
+/// The table header of `MultisigConfigV2`: its total size and the offsets of
+/// its 4 fields, the first 3 a byte each, then the `Byte20Vec` item count.
+const CONFIG_V2_HEADER_SIZE: usize = 5 * 4;
+const CONFIG_V2_HASHES_OFFSET: usize = CONFIG_V2_HEADER_SIZE + 3;
+const CONFIG_V2_MIN_SIZE: usize = CONFIG_V2_HASHES_OFFSET + 4;
+
+/// The `MultisigConfigV2` at the head of `bytes` and the lock field it
+/// starts. A table of this schema has a single valid encoding, so the header
+/// is checked word by word, and unknown fields are refused as molecule does
+/// when not in compatible mode.
+fn parse_config_v2(bytes: &[u8]) -> Result<(&[u8], LockField), Error> {
+    if bytes.len() < CONFIG_V2_MIN_SIZE {
+        return Err(Error::WitnessSize);
+    }
+    let pubkeys_cnt = read_u32(bytes, CONFIG_V2_HASHES_OFFSET);
+    if pubkeys_cnt > usize::from(u8::MAX) {
+        return Err(Error::InvalidPubkeysCnt);
+    }
+    let config_len = CONFIG_V2_MIN_SIZE + BLAKE160_SIZE * pubkeys_cnt;
+    if bytes.len() < config_len {
+        return Err(Error::WitnessSize);
+    }
+    let header = [
+        config_len,
+        CONFIG_V2_HEADER_SIZE,
+        CONFIG_V2_HEADER_SIZE + 1,
+        CONFIG_V2_HEADER_SIZE + 2,
+        CONFIG_V2_HASHES_OFFSET,
+    ];
+    for (i, word) in header.iter().enumerate() {
+        if read_u32(bytes, i * 4) != *word {
+            return Err(Error::Encoding);
+        }
+    }
+    let lock = LockField {
+        key_format: bytes[CONFIG_V2_HEADER_SIZE],
+        require_first_n: bytes[CONFIG_V2_HEADER_SIZE + 1],
+        threshold: bytes[CONFIG_V2_HEADER_SIZE + 2],
+        pubkey_hashes: &bytes[CONFIG_V2_MIN_SIZE..config_len],
+        signatures: &bytes[config_len..],
+    };
+    Ok((&bytes[..config_len], lock))
+}
+
+/// The domain separators of the leaves and of the inner nodes of the tree
+/// of configs, so that neither can pass for the other.
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// The path from a config to the Merkle root of the lock args: bit `i` of
+/// `path` is set when the `i`th sibling, 32 bytes each from the leaf up, is
+/// on the left. Unused bits must be 0, a proof has a single encoding.
+struct ConfigProof<'a> {
+    path: u8,
+    siblings: &'a [u8],
+}
+
+impl<'a> ConfigProof<'a> {
+    /// The proof at the head of `bytes`, `CONFIG_PROOF | depth | path |
+    /// siblings`, and the lock field following it.
+    fn parse(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Error> {
+        if bytes.len() < 3 {
+            return Err(Error::WitnessSize);
+        }
+        let depth = usize::from(bytes[1]);
+        let path = bytes[2];
+        if depth > MAX_PROOF_DEPTH || (depth < MAX_PROOF_DEPTH && path >> depth != 0) {
+            return Err(Error::ConfigProof);
+        }
+        let end = 3 + BLAKE2B_BLOCK_SIZE * depth;
+        if bytes.len() < end {
+            return Err(Error::WitnessSize);
+        }
+        let proof = ConfigProof {
+            path,
+            siblings: &bytes[3..end],
+        };
+        Ok((proof, &bytes[end..]))
+    }
+
+    /// The root of the tree holding the leaf `committed`, the multisig
+    /// script or molecule config of the lock field: a leaf is the hash of
+    /// `LEAF_PREFIX | config`, a node the hash of `NODE_PREFIX | left |
+    /// right`.
+    fn root(&self, committed: &[u8]) -> [u8; BLAKE2B_BLOCK_SIZE] {
+        let mut node = prefixed_hash(LEAF_PREFIX, committed, &[]);
+        for (i, sibling) in self.siblings.chunks(BLAKE2B_BLOCK_SIZE).enumerate() {
+            node = if self.path >> i & 1 == 1 {
+                prefixed_hash(NODE_PREFIX, sibling, &node)
+            } else {
+                prefixed_hash(NODE_PREFIX, &node, sibling)
+            };
+        }
+        node
+    }
+}
+
+fn prefixed_hash(prefix: u8, left: &[u8], right: &[u8]) -> [u8; BLAKE2B_BLOCK_SIZE] {
+    let mut hash = [0u8; BLAKE2B_BLOCK_SIZE];
+    let mut blake2b = Blake2bBuilder::new(BLAKE2B_BLOCK_SIZE)
+        .personal(CKB_HASH_PERSONALIZATION)
+        .build();
+    blake2b.update(&[prefix]);
+    blake2b.update(left);
+    blake2b.update(right);
+    blake2b.finalize(&mut hash);
+    hash
+}
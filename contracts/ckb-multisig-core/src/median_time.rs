@@ -0,0 +1,94 @@
+//! Timestamp since checked against header deps instead of the since of the
+//! inputs, for wall clock timelocks wallets can reason about precisely.
+//!
+//! The transaction carries the headers of `MEDIAN_TIME_BLOCK_COUNT`
+//! consecutive blocks as header deps, the last ones of the deps by number,
+//! and their median timestamp is the time proved to be reached, as the
+//! median time CKB compares the since with. An absolute since is reached
+//! when the median is not before it. A relative one also needs the header of
+//! the block committing each group input, and is reached when the median is
+//! not before the timestamp of that block plus the since.
+
+use alloc::vec::Vec;
+use core::result::Result;
+
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{packed::Header, prelude::*},
+    error::SysError,
+    high_level::{load_header, QueryIter},
+};
+
+use crate::{blake2b_256, error::Error};
+
+/// The blocks the median time is taken over, as in CKB.
+pub const MEDIAN_TIME_BLOCK_COUNT: usize = 37;
+
+const SINCE_VALUE_BITS: usize = 56;
+const SINCE_VALUE_MASK: u64 = 0x00ffffffffffffff;
+const SINCE_RELATIVE_FLAG: u64 = 0b10000000;
+const SINCE_METRIC_MASK: u64 = 0b01100000;
+const SINCE_TIMESTAMP_FLAG: u64 = 0b01000000;
+
+/// Whether `since` is checked by `check_since` here rather than by the since
+/// of the inputs.
+pub fn is_timestamp(since: u64) -> bool {
+    (since >> SINCE_VALUE_BITS) & SINCE_METRIC_MASK == SINCE_TIMESTAMP_FLAG
+}
+
+/// Check the timestamp `since` against the median time of the header deps.
+pub fn check_since(since: u64) -> Result<(), Error> {
+    let flags = since >> SINCE_VALUE_BITS;
+    if flags & !SINCE_RELATIVE_FLAG != SINCE_TIMESTAMP_FLAG {
+        return Err(Error::InvalidSinceFlags);
+    }
+    // in seconds, the header timestamps in milliseconds
+    let median = median_time()? / 1000;
+    let value = since & SINCE_VALUE_MASK;
+    if flags & SINCE_RELATIVE_FLAG == 0 {
+        if median < value {
+            return Err(Error::IncorrectSinceValue);
+        }
+        return Ok(());
+    }
+    for i in 0.. {
+        let commit = match load_header(i, Source::GroupInput) {
+            Ok(header) => timestamp(&header) / 1000,
+            Err(SysError::IndexOutOfBound) => break,
+            Err(SysError::ItemMissing) => return Err(Error::HeaderDeps),
+            Err(err) => return Err(err.into()),
+        };
+        if median < commit.saturating_add(value) {
+            return Err(Error::IncorrectSinceValue);
+        }
+    }
+    Ok(())
+}
+
+/// The median timestamp of the last `MEDIAN_TIME_BLOCK_COUNT` header deps
+/// by number, which must be consecutive blocks.
+fn median_time() -> Result<u64, Error> {
+    let mut headers: Vec<Header> = QueryIter::new(load_header, Source::HeaderDep).collect();
+    if headers.len() < MEDIAN_TIME_BLOCK_COUNT {
+        return Err(Error::HeaderDeps);
+    }
+    headers.sort_by_key(number);
+    let headers = &headers[headers.len() - MEDIAN_TIME_BLOCK_COUNT..];
+    for pair in headers.windows(2) {
+        let parent_hash = pair[1].raw().parent_hash();
+        if parent_hash.as_slice() != &blake2b_256(pair[0].as_slice())[..] {
+            return Err(Error::HeaderDeps);
+        }
+    }
+    let mut timestamps: Vec<u64> = headers.iter().map(timestamp).collect();
+    timestamps.sort_unstable();
+    Ok(timestamps[MEDIAN_TIME_BLOCK_COUNT / 2])
+}
+
+fn number(header: &Header) -> u64 {
+    header.raw().number().unpack()
+}
+
+fn timestamp(header: &Header) -> u64 {
+    header.raw().timestamp().unpack()
+}
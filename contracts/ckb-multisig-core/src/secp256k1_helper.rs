@@ -0,0 +1,67 @@
+use crate::error::Error;
+
+#[link(name = "ckb-lib-secp256k1", kind = "static")]
+extern "C" {
+    fn ckb_secp256k1_verify(
+        key_format: u8,
+        require_first_n: u8,
+        threshold: u8,
+        pubkeys_cnt: u8,
+        message: *const u8,
+        pubkey_hashes: *const u8,
+        signatures: *const u8,
+    ) -> i32;
+    fn ckb_secp256k1_verify_single(
+        key_format: u8,
+        message: *const u8,
+        signature: *const u8,
+        pubkey_hash: *const u8,
+    ) -> i32;
+}
+
+pub(crate) fn validate_secp256k1_multisignautre(
+    key_format: u8,
+    require_first_n: u8,
+    threshold: u8,
+    pubkeys_cnt: u8,
+    message: &[u8],
+    pubkey_hashes: &[u8],
+    signatures: &[u8],
+) -> Result<(), Error> {
+    let ret = unsafe {
+        ckb_secp256k1_verify(
+            key_format,
+            require_first_n,
+            threshold,
+            pubkeys_cnt,
+            message.as_ptr(),
+            pubkey_hashes.as_ptr(),
+            signatures.as_ptr(),
+        )
+    };
+    if ret != 0 {
+        return Err(Error::Verification);
+    }
+    Ok(())
+}
+
+/// A single signature by the key of `pubkey_hash`, the 1 of 1 fast path.
+pub(crate) fn validate_secp256k1_signature(
+    key_format: u8,
+    message: &[u8],
+    signature: &[u8],
+    pubkey_hash: &[u8],
+) -> Result<(), Error> {
+    let ret = unsafe {
+        ckb_secp256k1_verify_single(
+            key_format,
+            message.as_ptr(),
+            signature.as_ptr(),
+            pubkey_hash.as_ptr(),
+        )
+    };
+    if ret != 0 {
+        return Err(Error::Verification);
+    }
+    Ok(())
+}
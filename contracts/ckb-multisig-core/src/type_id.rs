@@ -0,0 +1,49 @@
+//! The type id rules of the singleton cells of the contracts, the config
+//! registry and the nonce cell: the args of the type script start with an
+//! id no other cell can take.
+
+use core::result::Result;
+
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::prelude::*,
+    high_level::{load_cell_data, load_cell_type_hash, load_input, load_script_hash, QueryIter},
+};
+
+use blake2b_ref::Blake2bBuilder;
+
+use crate::{error::Error, BLAKE2B_BLOCK_SIZE, CKB_HASH_PERSONALIZATION};
+
+/// The size of a type id.
+pub const TYPE_ID_SIZE: usize = 32;
+
+/// The type id rules: at most one cell in and out of the group, and a new
+/// cell takes the id derived from the first input of the transaction and its
+/// output index.
+pub fn check_type_id(type_id: &[u8]) -> Result<(), Error> {
+    if load_cell_data(1, Source::GroupInput).is_ok()
+        || load_cell_data(1, Source::GroupOutput).is_ok()
+    {
+        return Err(Error::TooManyCells);
+    }
+    if load_cell_data(0, Source::GroupInput).is_ok() {
+        return Ok(());
+    }
+
+    let script_hash = load_script_hash()?;
+    let index = QueryIter::new(load_cell_type_hash, Source::Output)
+        .position(|type_hash| type_hash == Some(script_hash))
+        .ok_or(Error::ItemMissing)?;
+    let first_input = load_input(0, Source::Input)?;
+    let mut expected = [0; BLAKE2B_BLOCK_SIZE];
+    let mut blake2b = Blake2bBuilder::new(BLAKE2B_BLOCK_SIZE)
+        .personal(CKB_HASH_PERSONALIZATION)
+        .build();
+    blake2b.update(first_input.as_slice());
+    blake2b.update(&(index as u64).to_le_bytes());
+    blake2b.finalize(&mut expected);
+    if type_id != &expected[..] {
+        return Err(Error::TypeId);
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::{convert::TryInto, result::Result};
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    debug,
+    error::SysError,
+    high_level::{
+        load_cell_capacity, load_cell_data, load_cell_lock_hash, load_script, load_witness_args,
+        QueryIter,
+    },
+};
+
+use crate::error::Error;
+
+use ckb_multisig_core::{BLAKE160_SIZE, BLAKE2B_BLOCK_SIZE, U64_SIZE};
+
+/// The args: `campaign multisig hash | goal | deadline`, the goal in
+/// shannons and the deadline an absolute since value, as little endian u64.
+const GOAL_OFFSET: usize = BLAKE160_SIZE;
+const DEADLINE_OFFSET: usize = GOAL_OFFSET + U64_SIZE;
+const ARGS_SIZE: usize = DEADLINE_OFFSET + U64_SIZE;
+
+/// Contributions pooled by a campaign: each cell holds the lock hash its
+/// contributor is refunded to in its data. The campaign multisig claims the
+/// contributions once they reach the goal together, signing in the lock
+/// field as for the multisig lock. Otherwise every contribution goes back to
+/// its contributor from the deadline on.
+pub fn main() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    debug!("script args is {:?}", args);
+
+    if args.len() != ARGS_SIZE {
+        return Err(Error::ArgumentsLen);
+    }
+    let goal = read_u64(&args, GOAL_OFFSET);
+    let deadline = read_u64(&args, DEADLINE_OFFSET);
+
+    let claim = match load_witness_args(0, Source::GroupInput) {
+        Ok(witness) => witness.lock().to_opt().is_some(),
+        Err(SysError::IndexOutOfBound) => false,
+        Err(err) => return Err(err.into()),
+    };
+    if claim {
+        ckb_multisig_core::verify(&args[0..BLAKE160_SIZE], 0)?;
+        let mut raised: u64 = 0;
+        for capacity in QueryIter::new(load_cell_capacity, Source::GroupInput) {
+            raised = raised.checked_add(capacity).ok_or(Error::Overflow)?;
+        }
+        if raised < goal {
+            return Err(Error::GoalNotReached);
+        }
+        return Ok(());
+    }
+
+    // a refund, the same since check as the multisig lock
+    ckb_multisig_core::check_since(deadline)?;
+    for (i, data) in QueryIter::new(load_cell_data, Source::GroupInput).enumerate() {
+        let refund_lock = refund_lock(&data)?;
+        // each contributor once, at their first contribution
+        let counted = QueryIter::new(load_cell_data, Source::GroupInput)
+            .take(i)
+            .any(|other| other[..] == data[..]);
+        if counted {
+            continue;
+        }
+        let contributed = sum_capacity(Source::GroupInput, |index| {
+            Ok(load_cell_data(index, Source::GroupInput)?[..] == data[..])
+        })?;
+        let refunded = sum_capacity(Source::Output, |index| {
+            Ok(load_cell_lock_hash(index, Source::Output)? == refund_lock)
+        })?;
+        if refunded < contributed {
+            return Err(Error::Refund);
+        }
+    }
+    Ok(())
+}
+
+/// The lock hash of the contributor, the data of a contribution.
+fn refund_lock(data: &[u8]) -> Result<[u8; BLAKE2B_BLOCK_SIZE], Error> {
+    data.try_into().map_err(|_| Error::InvalidContribution)
+}
+
+/// The capacity of the cells of `source` selected by their index.
+fn sum_capacity<F>(source: Source, selected: F) -> Result<u64, Error>
+where
+    F: Fn(usize) -> Result<bool, Error>,
+{
+    let mut sum: u64 = 0;
+    for (index, capacity) in QueryIter::new(load_cell_capacity, source).enumerate() {
+        if selected(index)? {
+            sum = sum.checked_add(capacity).ok_or(Error::Overflow)?;
+        }
+    }
+    Ok(sum)
+}
+
+fn read_u64(args: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(args[offset..offset + U64_SIZE].try_into().unwrap())
+}
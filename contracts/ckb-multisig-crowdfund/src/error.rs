@@ -0,0 +1,41 @@
+use ckb_multisig_core::error::Error as MultisigError;
+use ckb_std::error::SysError;
+
+/// Error
+pub enum Error {
+    /// The claim by the campaign multisig failed, or the deadline of a refund
+    /// isn't reached, or a syscall.
+    Multisig(MultisigError),
+    // Add customized errors here...
+    ArgumentsLen,
+    GoalNotReached,
+    InvalidContribution,
+    Refund,
+    Overflow,
+}
+
+impl Error {
+    /// The exit code, those of the multisig lock for its errors.
+    pub fn code(self) -> i8 {
+        match self {
+            Self::Multisig(err) => err as i8,
+            Self::ArgumentsLen => -1,
+            Self::GoalNotReached => -81,
+            Self::InvalidContribution => -82,
+            Self::Refund => -83,
+            Self::Overflow => -84,
+        }
+    }
+}
+
+impl From<MultisigError> for Error {
+    fn from(err: MultisigError) -> Self {
+        Self::Multisig(err)
+    }
+}
+
+impl From<SysError> for Error {
+    fn from(err: SysError) -> Self {
+        Self::Multisig(err.into())
+    }
+}
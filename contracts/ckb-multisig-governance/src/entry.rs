@@ -0,0 +1,177 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::{convert::TryInto, result::Result};
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, packed::Script, prelude::*},
+    debug,
+    error::SysError,
+    high_level::{
+        load_cell, load_cell_data, load_cell_lock_hash, load_cell_type_hash, load_input,
+        load_script, load_script_hash, load_witness_args, QueryIter,
+    },
+};
+
+use crate::error::Error;
+
+use blake2b_ref::Blake2bBuilder;
+use ckb_multisig_core::{
+    approval::check_fee, blake2b_256, verify_key_signature, BLAKE160_SIZE, BLAKE2B_BLOCK_SIZE,
+    CKB_HASH_PERSONALIZATION, FLAGS_SIZE, SIGNATURE_SIZE, U64_SIZE,
+};
+
+/// The args of the treasury: `multisig hash | quorum | weights`, the quorum a
+/// little endian u16 and a weight byte per member in the config order. A
+/// ballot type script has the args of the treasury followed by the hash of
+/// the proposal voted on.
+const QUORUM_OFFSET: usize = BLAKE160_SIZE;
+const WEIGHTS_OFFSET: usize = QUORUM_OFFSET + 2;
+
+const NO: u8 = 0;
+const YES: u8 = 1;
+
+/// The same binary is the treasury lock and the type script of the ballots.
+///
+/// A ballot cell holds `multisig script | voter | choice | signature`: the
+/// voter is the index of a member of the config, who signs the hash of the
+/// proposal and the choice. The treasury spends either with the signatures
+/// of the quorum of the config, as the multisig lock, or with the ballots of
+/// its proposal as cell deps, once the members voting yes weigh the quorum.
+/// A proposal spends given treasury cells, so its ballots are good for one
+/// transaction only: once it is committed, they match no proposal anymore.
+pub fn main() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    debug!("script args is {:?}", args);
+
+    if args.len() <= WEIGHTS_OFFSET {
+        return Err(Error::ArgumentsLen);
+    }
+    let script_hash = load_script_hash()?;
+    match load_cell_lock_hash(0, Source::GroupInput) {
+        Ok(lock_hash) if lock_hash == script_hash => treasury(&script, &args),
+        Ok(_) | Err(SysError::IndexOutOfBound) => check_ballots(&args),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn treasury(script: &Script, args: &[u8]) -> Result<(), Error> {
+    let witness = match load_witness_args(0, Source::GroupInput) {
+        Ok(witness) => Some(witness),
+        Err(SysError::IndexOutOfBound) => None,
+        Err(err) => return Err(err.into()),
+    };
+    if witness
+        .as_ref()
+        .map_or(false, |witness| witness.lock().to_opt().is_some())
+    {
+        ckb_multisig_core::verify(&args[0..BLAKE160_SIZE], 0)?;
+        return Ok(());
+    }
+
+    // the max fee of the proposal, in the input type field
+    let max_fee: [u8; U64_SIZE] = witness
+        .and_then(|witness| witness.input_type().to_opt())
+        .and_then(|max_fee| max_fee.raw_data()[..].try_into().ok())
+        .ok_or(Error::MaxFee)?;
+    check_fee(u64::from_le_bytes(max_fee))?;
+
+    let quorum = u16::from_le_bytes(args[QUORUM_OFFSET..WEIGHTS_OFFSET].try_into().unwrap());
+    let weights = &args[WEIGHTS_OFFSET..];
+    let mut ballot_args = args.to_vec();
+    ballot_args.extend_from_slice(&proposal_hash(&max_fee)?);
+    let ballot_type = script
+        .clone()
+        .as_builder()
+        .args(ballot_args.as_slice().pack())
+        .build();
+    let ballot_type_hash = blake2b_256(ballot_type.as_slice());
+
+    let mut voted = [false; 256];
+    let mut weight: u32 = 0;
+    for (i, type_hash) in QueryIter::new(load_cell_type_hash, Source::CellDep).enumerate() {
+        if type_hash != Some(ballot_type_hash) {
+            continue;
+        }
+        // checked by the type script when created
+        let data = load_cell_data(i, Source::CellDep)?;
+        let (voter, choice) = parse_ballot(&data, weights.len())?;
+        if choice == YES && !voted[voter] {
+            voted[voter] = true;
+            weight += u32::from(weights[voter]);
+        }
+    }
+    if weight < u32::from(quorum) {
+        return Err(Error::NotPassed);
+    }
+    Ok(())
+}
+
+/// The ballots created in the transaction: each one by a member, signed.
+/// Ballots are destroyed freely, by their lock.
+fn check_ballots(args: &[u8]) -> Result<(), Error> {
+    if args.len() <= WEIGHTS_OFFSET + BLAKE2B_BLOCK_SIZE {
+        return Err(Error::ArgumentsLen);
+    }
+    let proposal = &args[args.len() - BLAKE2B_BLOCK_SIZE..];
+    let members = args.len() - BLAKE2B_BLOCK_SIZE - WEIGHTS_OFFSET;
+    for data in QueryIter::new(load_cell_data, Source::GroupOutput) {
+        let (voter, choice) = parse_ballot(&data, members)?;
+        let multisig_script = &data[0..FLAGS_SIZE + BLAKE160_SIZE * members];
+        if usize::from(multisig_script[3]) != members
+            || blake2b_256(multisig_script)[0..BLAKE160_SIZE] != args[0..BLAKE160_SIZE]
+        {
+            return Err(Error::InvalidBallot);
+        }
+        let pubkey_hash = &multisig_script[FLAGS_SIZE + BLAKE160_SIZE * voter..][..BLAKE160_SIZE];
+        let signature = &data[data.len() - SIGNATURE_SIZE..];
+        let mut message = [0u8; BLAKE2B_BLOCK_SIZE];
+        let mut blake2b = Blake2bBuilder::new(BLAKE2B_BLOCK_SIZE)
+            .personal(CKB_HASH_PERSONALIZATION)
+            .build();
+        blake2b.update(proposal);
+        blake2b.update(&[choice]);
+        blake2b.finalize(&mut message);
+        verify_key_signature(multisig_script[0], pubkey_hash, &message, signature)?;
+    }
+    Ok(())
+}
+
+/// The voter and the choice of a ballot of a config of `members` keys.
+fn parse_ballot(data: &[u8], members: usize) -> Result<(usize, u8), Error> {
+    let voter_offset = FLAGS_SIZE + BLAKE160_SIZE * members;
+    if data.len() != voter_offset + 2 + SIGNATURE_SIZE {
+        return Err(Error::InvalidBallot);
+    }
+    let voter = usize::from(data[voter_offset]);
+    let choice = data[voter_offset + 1];
+    if voter >= members || (choice != NO && choice != YES) {
+        return Err(Error::InvalidBallot);
+    }
+    Ok((voter, choice))
+}
+
+/// What a proposal of the treasury commits to: its max fee, the out points
+/// of the treasury cells it spends, then the outputs of the spending
+/// transaction, each cell followed by its data prefixed by its length as a
+/// little endian u64.
+fn proposal_hash(max_fee: &[u8; U64_SIZE]) -> Result<[u8; BLAKE2B_BLOCK_SIZE], Error> {
+    let mut blake2b = Blake2bBuilder::new(BLAKE2B_BLOCK_SIZE)
+        .personal(CKB_HASH_PERSONALIZATION)
+        .build();
+    blake2b.update(max_fee);
+    for input in QueryIter::new(load_input, Source::GroupInput) {
+        blake2b.update(input.previous_output().as_slice());
+    }
+    for (i, output) in QueryIter::new(load_cell, Source::Output).enumerate() {
+        let data = load_cell_data(i, Source::Output)?;
+        blake2b.update(output.as_slice());
+        blake2b.update(&(data.len() as u64).to_le_bytes());
+        blake2b.update(&data);
+    }
+    let mut hash = [0u8; BLAKE2B_BLOCK_SIZE];
+    blake2b.finalize(&mut hash);
+    Ok(hash)
+}
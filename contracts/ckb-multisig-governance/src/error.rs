@@ -0,0 +1,39 @@
+use ckb_multisig_core::error::Error as MultisigError;
+use ckb_std::error::SysError;
+
+/// Error
+pub enum Error {
+    /// The multisig path of the treasury or a ballot signature failed, or a
+    /// syscall.
+    Multisig(MultisigError),
+    // Add customized errors here...
+    ArgumentsLen,
+    InvalidBallot,
+    NotPassed,
+    MaxFee,
+}
+
+impl Error {
+    /// The exit code, those of the multisig lock for its errors.
+    pub fn code(self) -> i8 {
+        match self {
+            Self::Multisig(err) => err as i8,
+            Self::ArgumentsLen => -1,
+            Self::InvalidBallot => -91,
+            Self::NotPassed => -92,
+            Self::MaxFee => -93,
+        }
+    }
+}
+
+impl From<MultisigError> for Error {
+    fn from(err: MultisigError) -> Self {
+        Self::Multisig(err)
+    }
+}
+
+impl From<SysError> for Error {
+    fn from(err: SysError) -> Self {
+        Self::Multisig(err.into())
+    }
+}
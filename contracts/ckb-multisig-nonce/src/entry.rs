@@ -0,0 +1,57 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::{convert::TryInto, result::Result};
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    debug,
+    error::SysError,
+    high_level::{load_cell_data, load_script},
+};
+
+use crate::error::Error;
+
+use ckb_multisig_core::{
+    type_id::{check_type_id, TYPE_ID_SIZE},
+    U64_SIZE,
+};
+
+/// A nonce cell holds a little endian u64 in its data. It is unique by its
+/// type id, created with the nonce 0, and every transaction spending it must
+/// recreate it with the nonce incremented by one, so an approval signed for a
+/// nonce is consumed by the first transaction using it and can't be
+/// replayed. Who may spend the cell is up to its lock.
+pub fn main() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    debug!("script args is {:?}", args);
+
+    // the args: the type id of the nonce cell
+    if args.len() != TYPE_ID_SIZE {
+        return Err(Error::ArgumentsLen);
+    }
+    check_type_id(&args)?;
+
+    let output = match load_cell_data(0, Source::GroupOutput) {
+        Ok(data) => read_nonce(&data)?,
+        // destroying the cell is up to its lock
+        Err(SysError::IndexOutOfBound) => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let expected = match load_cell_data(0, Source::GroupInput) {
+        Ok(data) => read_nonce(&data)?.checked_add(1).ok_or(Error::Nonce)?,
+        Err(SysError::IndexOutOfBound) => 0,
+        Err(err) => return Err(err.into()),
+    };
+    if output != expected {
+        return Err(Error::Nonce);
+    }
+    Ok(())
+}
+
+fn read_nonce(data: &[u8]) -> Result<u64, Error> {
+    let nonce: [u8; U64_SIZE] = data.try_into().map_err(|_| Error::NonceSize)?;
+    Ok(u64::from_le_bytes(nonce))
+}
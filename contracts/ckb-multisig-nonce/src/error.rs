@@ -0,0 +1,47 @@
+use ckb_multisig_core::error::Error as CoreError;
+use ckb_std::error::SysError;
+
+/// Error
+#[repr(i8)]
+pub enum Error {
+    IndexOutOfBound = 1,
+    ItemMissing,
+    LengthNotEnough,
+    Encoding,
+    /// A syscall error unknown to ckb-std, e.g. of a newer VM.
+    Unknown,
+    // Add customized errors here...
+    ArgumentsLen = -1,
+    TooManyCells = -61,
+    TypeId = -62,
+    NonceSize = -111,
+    Nonce = -112,
+}
+
+impl From<SysError> for Error {
+    fn from(err: SysError) -> Self {
+        use SysError::*;
+        match err {
+            IndexOutOfBound => Self::IndexOutOfBound,
+            ItemMissing => Self::ItemMissing,
+            LengthNotEnough(_) => Self::LengthNotEnough,
+            Encoding => Self::Encoding,
+            Unknown(_) => Self::Unknown,
+        }
+    }
+}
+
+/// The errors of `ckb_multisig_core::type_id::check_type_id`.
+impl From<CoreError> for Error {
+    fn from(err: CoreError) -> Self {
+        match err {
+            CoreError::IndexOutOfBound => Self::IndexOutOfBound,
+            CoreError::ItemMissing => Self::ItemMissing,
+            CoreError::LengthNotEnough => Self::LengthNotEnough,
+            CoreError::Encoding => Self::Encoding,
+            CoreError::TooManyCells => Self::TooManyCells,
+            CoreError::TypeId => Self::TypeId,
+            _ => Self::Unknown,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    debug,
+    error::SysError,
+    high_level::{load_cell_data, load_cell_lock, load_script},
+};
+
+use crate::error::Error;
+
+use ckb_multisig_core::{
+    type_id::{check_type_id, TYPE_ID_SIZE},
+    BLAKE160_SIZE, BLAKE2B_BLOCK_SIZE, U64_SIZE,
+};
+
+/// The args: the type id of the registry cell, then the code hash and hash
+/// type of the multisig lock which must guard it.
+const ARGS_SIZE: usize = TYPE_ID_SIZE + BLAKE2B_BLOCK_SIZE + 1;
+
+/// A config cell holds in its data the head of the lock field of the
+/// multisig lock, its signatures left out: the multisig script `S | R | M |
+/// N | pubkey hashes` of any key format, or `CONFIG_V2` and the molecule
+/// config, either after the proof of a config tree. It is unique by its type
+/// id, and locked by the multisig lock of the config it holds, so only the
+/// quorum of the current config can update or destroy it.
+pub fn main() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    debug!("script args is {:?}", args);
+
+    if args.len() != ARGS_SIZE {
+        return Err(Error::ArgumentsLen);
+    }
+    check_type_id(&args[0..TYPE_ID_SIZE])?;
+
+    let config = match load_cell_data(0, Source::GroupOutput) {
+        Ok(config) => config,
+        // destroying the cell is up to its lock
+        Err(SysError::IndexOutOfBound) => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let lock = load_cell_lock(0, Source::GroupOutput)?;
+    let lock_args = lock.args().raw_data();
+    if lock.code_hash().as_slice() != &args[TYPE_ID_SIZE..TYPE_ID_SIZE + BLAKE2B_BLOCK_SIZE]
+        || lock.hash_type().as_slice()[0] != args[ARGS_SIZE - 1]
+    {
+        return Err(Error::LockScript);
+    }
+    if !is_lock_args_len(lock_args.len()) {
+        return Err(Error::LockScript);
+    }
+    check_config(&lock_args[0..BLAKE160_SIZE], &config)
+}
+
+/// The lock args of the multisig lock: `multisig hash | since | fee key hash
+/// | flags | successor`, see its `entry.rs`, whose flags the lock checks.
+fn is_lock_args_len(len: usize) -> bool {
+    const SINCE_ARGS_SIZE: usize = BLAKE160_SIZE + U64_SIZE;
+    const FEE_KEY_ARGS_SIZE: usize = SINCE_ARGS_SIZE + BLAKE160_SIZE;
+    const SUCCESSOR_SIZE: usize = BLAKE160_SIZE + BLAKE2B_BLOCK_SIZE;
+    match len {
+        BLAKE160_SIZE | SINCE_ARGS_SIZE | FEE_KEY_ARGS_SIZE => true,
+        _ => [SINCE_ARGS_SIZE, FEE_KEY_ARGS_SIZE]
+            .iter()
+            .any(|size| len == size + 1 || len == size + 1 + SUCCESSOR_SIZE),
+    }
+}
+
+/// The config is well formed the way the multisig lock parses it, hashes to
+/// `multisig_hash` and lists no key twice.
+fn check_config(multisig_hash: &[u8], config: &[u8]) -> Result<(), Error> {
+    let pubkeys = ckb_multisig_core::check_multisig_script(multisig_hash, config)?;
+    for (i, pubkey) in pubkeys.chunks(BLAKE160_SIZE).enumerate() {
+        if pubkeys[(i + 1) * BLAKE160_SIZE..]
+            .chunks(BLAKE160_SIZE)
+            .any(|other| other == pubkey)
+        {
+            return Err(Error::DuplicatedPubkey);
+        }
+    }
+    Ok(())
+}
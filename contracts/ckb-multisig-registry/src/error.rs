@@ -0,0 +1,65 @@
+use ckb_multisig_core::error::Error as CoreError;
+use ckb_std::error::SysError;
+
+/// Error
+#[repr(i8)]
+pub enum Error {
+    IndexOutOfBound = 1,
+    ItemMissing,
+    LengthNotEnough,
+    Encoding,
+    /// A syscall error unknown to ckb-std, e.g. of a newer VM.
+    Unknown,
+    // Add customized errors here...
+    ArgumentsLen = -1,
+    InvalidReserveField = -41,
+    InvalidPubkeysCnt = -42,
+    InvalidThreshold = -43,
+    InvalidRequireFirstN = -44,
+    ConfigSize = -45,
+    DuplicatedPubkey = -46,
+    PubkeysCap = -47,
+    ConfigProof = -48,
+    MultsigScriptHash = -51,
+    TooManyCells = -61,
+    TypeId = -62,
+    LockScript = -63,
+}
+
+impl From<SysError> for Error {
+    fn from(err: SysError) -> Self {
+        use SysError::*;
+        match err {
+            IndexOutOfBound => Self::IndexOutOfBound,
+            ItemMissing => Self::ItemMissing,
+            LengthNotEnough(_) => Self::LengthNotEnough,
+            Encoding => Self::Encoding,
+            Unknown(_) => Self::Unknown,
+        }
+    }
+}
+
+/// The errors of `ckb_multisig_core::check_multisig_script`, a lock field
+/// too short or too long being a config of the wrong size, and of
+/// `check_type_id`.
+impl From<CoreError> for Error {
+    fn from(err: CoreError) -> Self {
+        match err {
+            CoreError::IndexOutOfBound => Self::IndexOutOfBound,
+            CoreError::ItemMissing => Self::ItemMissing,
+            CoreError::LengthNotEnough => Self::LengthNotEnough,
+            CoreError::Encoding => Self::Encoding,
+            CoreError::WitnessSize => Self::ConfigSize,
+            CoreError::InvalidReserveField => Self::InvalidReserveField,
+            CoreError::InvalidPubkeysCnt => Self::InvalidPubkeysCnt,
+            CoreError::InvalidThreshold => Self::InvalidThreshold,
+            CoreError::InvalidRequireFirstN => Self::InvalidRequireFirstN,
+            CoreError::PubkeysCap => Self::PubkeysCap,
+            CoreError::ConfigProof => Self::ConfigProof,
+            CoreError::MultsigScriptHash => Self::MultsigScriptHash,
+            CoreError::TooManyCells => Self::TooManyCells,
+            CoreError::TypeId => Self::TypeId,
+            _ => Self::Unknown,
+        }
+    }
+}
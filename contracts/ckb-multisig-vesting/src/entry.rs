@@ -0,0 +1,119 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::{convert::TryInto, result::Result};
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    debug,
+    error::SysError,
+    high_level::{
+        load_cell_capacity, load_cell_lock_hash, load_input_since, load_script, load_script_hash,
+        load_witness_args, QueryIter,
+    },
+};
+
+use crate::error::Error;
+
+use ckb_multisig_core::{BLAKE160_SIZE, BLAKE2B_BLOCK_SIZE, U64_SIZE};
+
+/// The args: `admin multisig hash | beneficiary lock hash | total | start |
+/// period | tranches`, the amounts in shannons and the times in seconds, as
+/// little endian u64, the tranches in a byte.
+const BENEFICIARY_OFFSET: usize = BLAKE160_SIZE;
+const TOTAL_OFFSET: usize = BENEFICIARY_OFFSET + BLAKE2B_BLOCK_SIZE;
+const START_OFFSET: usize = TOTAL_OFFSET + U64_SIZE;
+const PERIOD_OFFSET: usize = START_OFFSET + U64_SIZE;
+const TRANCHES_OFFSET: usize = PERIOD_OFFSET + U64_SIZE;
+const ARGS_SIZE: usize = TRANCHES_OFFSET + 1;
+
+const SINCE_VALUE_BITS: usize = 56;
+const SINCE_VALUE_MASK: u64 = 0x00ffffffffffffff;
+/// Absolute since in median timestamp seconds.
+const SINCE_TIMESTAMP_FLAG: u64 = 0b01000000;
+
+/// A grant vesting in tranches: every `period` from `start` another of the
+/// `tranches` of `total` is vested. The beneficiary withdraws what is vested
+/// by spending a cell of their lock along, the rest stays in cells of this
+/// lock. The admin multisig claws back anything at any time, signing in the
+/// lock field as for the multisig lock.
+pub fn main() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    debug!("script args is {:?}", args);
+
+    if args.len() != ARGS_SIZE {
+        return Err(Error::ArgumentsLen);
+    }
+    let clawback = match load_witness_args(0, Source::GroupInput) {
+        Ok(witness) => witness.lock().to_opt().is_some(),
+        Err(SysError::IndexOutOfBound) => false,
+        Err(err) => return Err(err.into()),
+    };
+    if clawback {
+        ckb_multisig_core::verify(&args[0..BLAKE160_SIZE], 0)?;
+        return Ok(());
+    }
+
+    let beneficiary = &args[BENEFICIARY_OFFSET..TOTAL_OFFSET];
+    if !QueryIter::new(load_cell_lock_hash, Source::Input).any(|hash| hash[..] == beneficiary[..]) {
+        return Err(Error::Beneficiary);
+    }
+
+    let total = read_u64(&args, TOTAL_OFFSET);
+    let start = read_u64(&args, START_OFFSET);
+    let period = read_u64(&args, PERIOD_OFFSET);
+    let tranches = u64::from(args[TRANCHES_OFFSET]);
+    let vested = vested(total, start, period, tranches, now()?)?;
+
+    let mut remaining: u64 = 0;
+    for capacity in QueryIter::new(load_cell_capacity, Source::GroupInput) {
+        remaining = remaining.checked_add(capacity).ok_or(Error::Overflow)?;
+    }
+    // what is already withdrawn is vested too
+    let unvested = remaining.min(total - vested);
+
+    let script_hash = load_script_hash()?;
+    let mut kept: u64 = 0;
+    for (i, hash) in QueryIter::new(load_cell_lock_hash, Source::Output).enumerate() {
+        if hash == script_hash {
+            kept = kept
+                .checked_add(load_cell_capacity(i, Source::Output)?)
+                .ok_or(Error::Overflow)?;
+        }
+    }
+    if kept < unvested {
+        return Err(Error::Unvested);
+    }
+    Ok(())
+}
+
+/// The earliest time the group inputs can be committed, they must all be
+/// locked by an absolute timestamp since.
+fn now() -> Result<u64, Error> {
+    let mut now = u64::MAX;
+    for since in QueryIter::new(load_input_since, Source::GroupInput) {
+        if since >> SINCE_VALUE_BITS != SINCE_TIMESTAMP_FLAG {
+            return Err(Error::IncorrectSinceFlags);
+        }
+        now = now.min(since & SINCE_VALUE_MASK);
+    }
+    Ok(now)
+}
+
+fn vested(total: u64, start: u64, period: u64, tranches: u64, now: u64) -> Result<u64, Error> {
+    if tranches == 0 || period == 0 {
+        return Err(Error::InvalidSchedule);
+    }
+    if now < start {
+        return Ok(0);
+    }
+    let elapsed = ((now - start) / period).min(tranches);
+    let vested = u128::from(total) * u128::from(elapsed) / u128::from(tranches);
+    Ok(vested as u64)
+}
+
+fn read_u64(args: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(args[offset..offset + U64_SIZE].try_into().unwrap())
+}
@@ -0,0 +1,42 @@
+use ckb_multisig_core::error::Error as MultisigError;
+use ckb_std::error::SysError;
+
+/// Error
+pub enum Error {
+    /// The clawback by the admin multisig failed, or a syscall.
+    Multisig(MultisigError),
+    // Add customized errors here...
+    ArgumentsLen,
+    IncorrectSinceFlags,
+    Beneficiary,
+    Unvested,
+    Overflow,
+    InvalidSchedule,
+}
+
+impl Error {
+    /// The exit code, those of the multisig lock for its errors.
+    pub fn code(self) -> i8 {
+        match self {
+            Self::Multisig(err) => err as i8,
+            Self::ArgumentsLen => -1,
+            Self::IncorrectSinceFlags => -23,
+            Self::Beneficiary => -71,
+            Self::Unvested => -72,
+            Self::Overflow => -73,
+            Self::InvalidSchedule => -74,
+        }
+    }
+}
+
+impl From<MultisigError> for Error {
+    fn from(err: MultisigError) -> Self {
+        Self::Multisig(err)
+    }
+}
+
+impl From<SysError> for Error {
+    fn from(err: SysError) -> Self {
+        Self::Multisig(err.into())
+    }
+}
@@ -1,8 +0,0 @@
-use std::path::Path;
-use std::env;
-
-fn main() {
-    let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
-    println!("cargo:rustc-link-search=native={}", Path::new(&dir).join("ckb-lib-secp256k1/build").display());
-    println!("cargo:rustc-link-lib=static=ckb-lib-secp256k1");
-}
\ No newline at end of file
@@ -3,9 +3,38 @@
 #include "secp256k1_helper.h"
 #include "secp256k1_lock.h"
 
+/*
+ * The recovery splits the scalar multiplications with the GLV endomorphism,
+ * which the Makefile configures, saving a good part of the cycles of every
+ * signature. Refuse to build without it rather than silently slowing down.
+ */
+#ifndef USE_ENDOMORPHISM
+#error "secp256k1 must be configured with --enable-endomorphism, see the Makefile"
+#endif
+
 /* 32 KB */
 #define ONE_BATCH_SIZE 32768
 
+#ifdef CKB_SECP256K1_EMBEDDED_DATA
+/*
+ * The tables compiled in by `make all-embedded`, as `secp256k1_data` and
+ * `secp256k1_data_len`: the context points into them, neither a secp256k1
+ * data cell nor a copy on the stack is needed.
+ */
+#include "secp256k1_data.h"
+
+#define DECLARE_SECP256K1_DATA(name) uint8_t *name = secp256k1_data
+
+int ckb_secp256k1_custom_load_data(void *data) {
+  (void)ckb_secp256k1_data_hash;
+  if (data != secp256k1_data || secp256k1_data_len != CKB_SECP256K1_DATA_SIZE) {
+    return CKB_SECP256K1_HELPER_ERROR_LOADING_DATA;
+  }
+  return CKB_SUCCESS;
+}
+#else
+#define DECLARE_SECP256K1_DATA(name) uint8_t name[CKB_SECP256K1_DATA_SIZE]
+
 /*
  * data should at least be CKB_SECP256K1_DATA_SIZE big
  * so as to hold all loaded data.
@@ -24,6 +53,7 @@ int ckb_secp256k1_custom_load_data(void *data) {
   }
   return CKB_SUCCESS;
 }
+#endif
 
 int load_and_hash_witness(blake2b_state *ctx, size_t index, size_t source) {
   uint8_t temp[ONE_BATCH_SIZE];
@@ -127,7 +157,7 @@ int validate_secp256k1_blake2b_sighash_all(uint8_t *output_public_key_hash) {
 
   // Load signature
   secp256k1_context context;
-  uint8_t secp_data[CKB_SECP256K1_DATA_SIZE];
+  DECLARE_SECP256K1_DATA(secp_data);
   ret = ckb_secp256k1_custom_load_data(secp_data);
   if (ret != 0) {
     return ret;
@@ -167,14 +197,63 @@ int validate_secp256k1_blake2b_sighash_all(uint8_t *output_public_key_hash) {
 
 
 
-#define FLAGS_SIZE 4
 #define TEMP_SIZE 32768
 #define ERROR_VERIFICATION -52
+#define UNCOMPRESSED_PUBKEY_SIZE 65
+
+// How the keys are hashed in the multisig script, its first byte: blake160
+// of the compressed public key, of the uncompressed one, or of either.
+#define KEY_FORMAT_COMPRESSED 0
+#define KEY_FORMAT_UNCOMPRESSED 1
+#define KEY_FORMAT_EITHER 2
+
+// The blake2b of the public key serialized with `flags`.
+static int hash_pubkey(secp256k1_context *context, secp256k1_pubkey *pubkey,
+                       unsigned int flags, size_t size, unsigned char *hash) {
+  unsigned char serialized[UNCOMPRESSED_PUBKEY_SIZE];
+  size_t pubkey_size = size;
+  if (secp256k1_ec_pubkey_serialize(context, serialized, &pubkey_size, pubkey,
+                                    flags) != 1) {
+    return ERROR_SECP_SERIALIZE_PUBKEY;
+  }
+  blake2b_state blake2b_ctx;
+  blake2b_init(&blake2b_ctx, BLAKE2B_BLOCK_SIZE);
+  blake2b_update(&blake2b_ctx, serialized, pubkey_size);
+  blake2b_final(&blake2b_ctx, hash, BLAKE2B_BLOCK_SIZE);
+  return 0;
+}
 
-int32_t ckb_secp256k1_verify(uint8_t require_first_n, uint8_t threshold, uint8_t pubkeys_cnt,
-                         unsigned char * message, unsigned char * lock_bytes, size_t multisig_script_len) {
+// The hashes of the public key the key format accepts, 1 or 2 of them.
+static int hash_pubkey_formats(secp256k1_context *context, secp256k1_pubkey *pubkey,
+                               uint8_t key_format,
+                               unsigned char hashes[2][BLAKE2B_BLOCK_SIZE],
+                               size_t *hashes_cnt) {
+  int ret = 0;
+  *hashes_cnt = 0;
+  if (key_format != KEY_FORMAT_UNCOMPRESSED) {
+    ret = hash_pubkey(context, pubkey, SECP256K1_EC_COMPRESSED, PUBKEY_SIZE,
+                      hashes[*hashes_cnt]);
+    if (ret != 0) {
+      return ret;
+    }
+    *hashes_cnt += 1;
+  }
+  if (key_format != KEY_FORMAT_COMPRESSED) {
+    ret = hash_pubkey(context, pubkey, SECP256K1_EC_UNCOMPRESSED,
+                      UNCOMPRESSED_PUBKEY_SIZE, hashes[*hashes_cnt]);
+    if (ret != 0) {
+      return ret;
+    }
+    *hashes_cnt += 1;
+  }
+  return 0;
+}
+
+int32_t ckb_secp256k1_verify(uint8_t key_format, uint8_t require_first_n, uint8_t threshold,
+                         uint8_t pubkeys_cnt, unsigned char * message,
+                         const unsigned char * pubkey_hashes,
+                         const unsigned char * signatures) {
 
-  unsigned char temp[TEMP_SIZE];
   // Verify threshold signatures, threshold is a uint8_t, at most it is
   // 255, meaning this array will definitely have a reasonable upper bound.
   // Also this code uses C99's new feature to allocate a variable length array.
@@ -187,7 +266,7 @@ int32_t ckb_secp256k1_verify(uint8_t require_first_n, uint8_t threshold, uint8_t
   // you don't have to wait for the foundation to ship a new cryptographic algorithm. You
   // can just build and ship your own.
   secp256k1_context context;
-  uint8_t secp_data[CKB_SECP256K1_DATA_SIZE];
+  DECLARE_SECP256K1_DATA(secp_data);
   int32_t ret = ckb_secp256k1_custom_verify_only_initialize(&context, secp_data);
   if (ret != 0) {
     return ret;
@@ -197,10 +276,10 @@ int32_t ckb_secp256k1_verify(uint8_t require_first_n, uint8_t threshold, uint8_t
   for (size_t i = 0; i < threshold; i++) {
     // Load signature
     secp256k1_ecdsa_recoverable_signature signature;
-    size_t signature_offset = multisig_script_len + i * SIGNATURE_SIZE;
+    size_t signature_offset = i * SIGNATURE_SIZE;
     if (secp256k1_ecdsa_recoverable_signature_parse_compact(
-            &context, &signature, &lock_bytes[signature_offset],
-            lock_bytes[signature_offset + RECID_INDEX]) == 0) {
+            &context, &signature, &signatures[signature_offset],
+            signatures[signature_offset + RECID_INDEX]) == 0) {
       return ERROR_SECP_PARSE_SIGNATURE;
     }
 
@@ -210,32 +289,29 @@ int32_t ckb_secp256k1_verify(uint8_t require_first_n, uint8_t threshold, uint8_t
       return ERROR_SECP_RECOVER_PUBKEY;
     }
 
-    // Calculate the blake160 hash of the derived public key
-    size_t pubkey_size = PUBKEY_SIZE;
-    if (secp256k1_ec_pubkey_serialize(&context, temp, &pubkey_size, &pubkey,
-                                      SECP256K1_EC_COMPRESSED) != 1) {
-      return ERROR_SECP_SERIALIZE_PUBKEY;
+    // Calculate the blake160 hashes of the derived public key
+    unsigned char calculated_pubkey_hashes[2][BLAKE2B_BLOCK_SIZE];
+    size_t hashes_cnt = 0;
+    ret = hash_pubkey_formats(&context, &pubkey, key_format, calculated_pubkey_hashes,
+                              &hashes_cnt);
+    if (ret != 0) {
+      return ret;
     }
 
-    unsigned char calculated_pubkey_hash[BLAKE2B_BLOCK_SIZE];
-    blake2b_state blake2b_ctx;
-    blake2b_init(&blake2b_ctx, BLAKE2B_BLOCK_SIZE);
-    blake2b_update(&blake2b_ctx, temp, PUBKEY_SIZE);
-    blake2b_final(&blake2b_ctx, calculated_pubkey_hash, BLAKE2B_BLOCK_SIZE);
-
     // Check if this signature is signed with one of the provided public key.
     uint8_t matched = 0;
-    for (size_t i = 0; i < pubkeys_cnt; i++) {
+    for (size_t i = 0; i < pubkeys_cnt && matched == 0; i++) {
       if (used_signatures[i] == 1) {
         continue;
       }
-      if (memcmp(&lock_bytes[FLAGS_SIZE + i * BLAKE160_SIZE],
-                 calculated_pubkey_hash, BLAKE160_SIZE) != 0) {
-        continue;
+      for (size_t h = 0; h < hashes_cnt; h++) {
+        if (memcmp(&pubkey_hashes[i * BLAKE160_SIZE],
+                   calculated_pubkey_hashes[h], BLAKE160_SIZE) == 0) {
+          matched = 1;
+          used_signatures[i] = 1;
+          break;
+        }
       }
-      matched = 1;
-      used_signatures[i] = 1;
-      break;
     }
 
     // If the signature doesn't match any of the provided public key, the script
@@ -257,4 +333,42 @@ int32_t ckb_secp256k1_verify(uint8_t require_first_n, uint8_t threshold, uint8_t
     }
   }
   return 0;
-}
\ No newline at end of file
+}
+
+// The 1 of 1 configuration: a single signature recovered and its public key
+// hash compared with the only one of the multisig script, without the
+// bookkeeping of the threshold and required_first_n above.
+int32_t ckb_secp256k1_verify_single(uint8_t key_format, unsigned char * message,
+                                    unsigned char * signature, unsigned char * pubkey_hash) {
+  secp256k1_context context;
+  DECLARE_SECP256K1_DATA(secp_data);
+  int32_t ret = ckb_secp256k1_custom_verify_only_initialize(&context, secp_data);
+  if (ret != 0) {
+    return ret;
+  }
+
+  secp256k1_ecdsa_recoverable_signature recoverable;
+  if (secp256k1_ecdsa_recoverable_signature_parse_compact(
+          &context, &recoverable, signature, signature[RECID_INDEX]) == 0) {
+    return ERROR_SECP_PARSE_SIGNATURE;
+  }
+
+  secp256k1_pubkey pubkey;
+  if (secp256k1_ecdsa_recover(&context, &pubkey, &recoverable, message) != 1) {
+    return ERROR_SECP_RECOVER_PUBKEY;
+  }
+
+  unsigned char calculated_pubkey_hashes[2][BLAKE2B_BLOCK_SIZE];
+  size_t hashes_cnt = 0;
+  ret = hash_pubkey_formats(&context, &pubkey, key_format, calculated_pubkey_hashes,
+                            &hashes_cnt);
+  if (ret != 0) {
+    return ret;
+  }
+  for (size_t h = 0; h < hashes_cnt; h++) {
+    if (memcmp(pubkey_hash, calculated_pubkey_hashes[h], BLAKE160_SIZE) == 0) {
+      return 0;
+    }
+  }
+  return ERROR_VERIFICATION;
+}
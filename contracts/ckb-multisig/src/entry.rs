@@ -5,251 +5,230 @@ use core::{convert::TryInto, result::Result};
 // https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
 use ckb_std::{
     ckb_constants::Source,
-    ckb_types::{bytes::Bytes, packed::WitnessArgs, prelude::*},
+    ckb_types::{bytes::Bytes, prelude::*},
     debug,
-    error::SysError,
-    high_level::{load_input_since, load_script, load_tx_hash, load_witness_args, QueryIter},
+    high_level::{
+        load_cell_capacity, load_cell_lock_hash, load_cell_type_hash, load_input_since,
+        load_script, load_script_hash, load_witness, load_witness_args, QueryIter,
+    },
 };
 
 use crate::error::Error;
 
-use blake2b_ref::Blake2bBuilder;
+use ckb_multisig_core::{
+    approval, legacy, median_time, BLAKE160_SIZE, BLAKE2B_BLOCK_SIZE, SIGNATURE_SIZE, U64_SIZE,
+};
 
-const BLAKE160_SIZE: usize = 20;
-const U64_SIZE: usize = 8;
-const FLAGS_SIZE: usize = 4;
-const SIGNATURE_SIZE: usize = 65;
-const BLAKE2B_BLOCK_SIZE: usize = 32;
-const CKB_HASH_PERSONALIZATION: &[u8] = b"ckb-default-hash";
+/// The args: `multisig hash | since | fee key hash | flags | successor`, all
+/// but the multisig hash optional, the since 0 when only the fee key or the
+/// flags are set, the successor present with its flag.
+const SINCE_OFFSET: usize = BLAKE160_SIZE;
+const FEE_KEY_OFFSET: usize = SINCE_OFFSET + U64_SIZE;
+const FEE_KEY_ARGS_SIZE: usize = FEE_KEY_OFFSET + BLAKE160_SIZE;
+/// The successor: `multisig hash | type hash`, the reduced quorum and the
+/// type of the only cell it may move the funds into.
+const SUCCESSOR_SIZE: usize = BLAKE160_SIZE + BLAKE2B_BLOCK_SIZE;
+
+/// Check a timestamp since against the median time of the header deps, see
+/// `ckb_multisig_core::median_time`.
+const HEADER_TIME_FLAG: u8 = 0b00000001;
+/// Refuse non empty witnesses at the other inputs of the group, see
+/// `ckb_multisig_core::check_group_witnesses`.
+const STRICT_WITNESS_FLAG: u8 = 0b00000010;
+/// Spend every live cell of the lock together, see `check_spend_all`.
+const SPEND_ALL_FLAG: u8 = 0b00000100;
+/// Let a reduced quorum move the funds into the successor cell, see
+/// `check_successor`.
+const SUCCESSOR_FLAG: u8 = 0b00001000;
+/// Accept the witness layouts of older wallets, see
+/// `ckb_multisig_core::legacy`.
+const LEGACY_WITNESS_FLAG: u8 = 0b00010000;
 
 pub fn main() -> Result<(), Error> {
     let script = load_script()?;
     let args: Bytes = script.args().unpack();
     debug!("script args is {:?}", args);
 
-    if args.len() != BLAKE160_SIZE && args.len() != BLAKE160_SIZE + U64_SIZE {
+    let with_flags = |len: usize| args.len() == len + 1 || args.len() == len + 1 + SUCCESSOR_SIZE;
+    let (fee_key, flags_offset) = match args.len() {
+        BLAKE160_SIZE | FEE_KEY_OFFSET => (None, None),
+        FEE_KEY_ARGS_SIZE => (Some(&args[FEE_KEY_OFFSET..FEE_KEY_ARGS_SIZE]), None),
+        _ if with_flags(FEE_KEY_OFFSET) => (None, Some(FEE_KEY_OFFSET)),
+        _ if with_flags(FEE_KEY_ARGS_SIZE) => (
+            Some(&args[FEE_KEY_OFFSET..FEE_KEY_ARGS_SIZE]),
+            Some(FEE_KEY_ARGS_SIZE),
+        ),
+        _ => return Err(Error::ArgumentsLen),
+    };
+    let flags = flags_offset.map_or(0, |offset| args[offset]);
+    let successor = flags_offset.map_or(&[][..], |offset| &args[offset + 1..]);
+    let known = HEADER_TIME_FLAG
+        | STRICT_WITNESS_FLAG
+        | SPEND_ALL_FLAG
+        | SUCCESSOR_FLAG
+        | LEGACY_WITNESS_FLAG;
+    if flags & !known != 0 || (flags & SUCCESSOR_FLAG != 0) != (successor.len() == SUCCESSOR_SIZE) {
         return Err(Error::ArgumentsLen);
     }
-    let since = if args.len() == BLAKE160_SIZE + U64_SIZE {
-        u64::from_le_bytes(args[BLAKE160_SIZE..BLAKE160_SIZE + 8].try_into().unwrap())
+    let legacy = flags & LEGACY_WITNESS_FLAG != 0;
+    let verify = if legacy {
+        legacy::verify
     } else {
-        0
+        ckb_multisig_core::verify
     };
-
-    let witness = load_witness_args(0, Source::GroupInput)?;
-    let lock_bytes = {
-        let lock_opt = witness.lock();
-        if lock_opt.is_none() {
-            return Err(Error::WitnessSize);
-        }
-        let lock_bytes = lock_opt.to_opt().unwrap().raw_data();
-        if lock_bytes.len() < FLAGS_SIZE {
-            return Err(Error::WitnessSize);
+    if flags & STRICT_WITNESS_FLAG != 0 {
+        if legacy {
+            legacy::check_group_witnesses()?;
+        } else {
+            ckb_multisig_core::check_group_witnesses()?;
         }
-        lock_bytes
-    };
-
-    if lock_bytes[0] != 0u8 {
-        return Err(Error::InvalidReserveField);
-    }
-    let require_first_n: u8 = lock_bytes[1];
-
-    let threshold = lock_bytes[2];
-    if threshold == 0 {
-        return Err(Error::InvalidThreshold);
-    }
-    let pubkeys_cnt: u8 = lock_bytes[3];
-    if pubkeys_cnt == 0 {
-        return Err(Error::InvalidPubkeysCnt);
     }
-    if threshold > pubkeys_cnt {
-        return Err(Error::InvalidThreshold);
+    if flags & SPEND_ALL_FLAG != 0 {
+        check_spend_all()?;
     }
-    if require_first_n > threshold {
-        return Err(Error::InvalidRequireFirstN);
+    let mut since = if args.len() > BLAKE160_SIZE {
+        u64::from_le_bytes(args[SINCE_OFFSET..FEE_KEY_OFFSET].try_into().unwrap())
+    } else {
+        0
+    };
+    if flags & HEADER_TIME_FLAG != 0 && median_time::is_timestamp(since) {
+        median_time::check_since(since)?;
+        // the inputs need no since then
+        since = 0;
     }
 
-    let multisig_script_len = FLAGS_SIZE + BLAKE160_SIZE * usize::from(pubkeys_cnt);
-    let signatures_len = SIGNATURE_SIZE * usize::from(threshold);
-    let required_lock_len = multisig_script_len + signatures_len;
-    if lock_bytes.len() != required_lock_len {
-        return Err(Error::WitnessSize);
+    let multisig_hash = &args[0..BLAKE160_SIZE];
+    match (fee_key, lock_field(legacy)?) {
+        // a single signature can't be a multisig lock field, which starts
+        // with the multisig script
+        (Some(fee_key), Some(lock)) if lock.len() == SIGNATURE_SIZE => {
+            ckb_multisig_core::verify_fee_key(fee_key, since)?;
+            check_net_position()
+        }
+        (_, Some(lock)) if approval::is_approval(&lock) => {
+            approval::verify(multisig_hash, since, &lock)
+        }
+        // the full quorum first, the lock field says which one signed
+        _ if flags & SUCCESSOR_FLAG != 0 => match verify(multisig_hash, since) {
+            Err(Error::MultsigScriptHash) => {
+                verify(&successor[..BLAKE160_SIZE], since)?;
+                check_successor(&successor[BLAKE160_SIZE..])
+            }
+            result => result,
+        },
+        _ => verify(multisig_hash, since),
     }
+}
 
-    {
-        // check multisig args hash
-        let mut tmp = [0; BLAKE2B_BLOCK_SIZE];
-        let mut blake2b = Blake2bBuilder::new(BLAKE2B_BLOCK_SIZE)
-            .personal(CKB_HASH_PERSONALIZATION)
-            .build();
-        blake2b.update(&lock_bytes[0..multisig_script_len]);
-        blake2b.finalize(&mut tmp);
+/// The lock field of the first witness of the group, none in a legacy layout
+/// whose first witness is a placeholder.
+fn lock_field(legacy: bool) -> Result<Option<Bytes>, Error> {
+    if legacy && legacy::is_placeholder(&load_witness(0, Source::GroupInput)?) {
+        return Ok(None);
+    }
+    let witness = load_witness_args(0, Source::GroupInput)?;
+    Ok(witness.lock().to_opt().map(|lock| lock.raw_data()))
+}
 
-        if &args.as_ref()[0..BLAKE160_SIZE] != &tmp[0..BLAKE160_SIZE] {
-            return Err(Error::MultsigScriptHash);
+/// The live cells of the lock are spent all at once, never drained a part at
+/// a time: the input type field of the first witness of the group declares
+/// how many there are, a little endian u32 covered by the signatures with the
+/// rest of the witness, and the group must have as many inputs. The script
+/// can't see the live cells, the signers check the count.
+fn check_spend_all() -> Result<(), Error> {
+    let witness = load_witness_args(0, Source::GroupInput)?;
+    let total = match witness.input_type().to_opt() {
+        Some(field) if field.raw_data().len() == 4 => {
+            u32::from_le_bytes(field.raw_data()[..].try_into().unwrap())
         }
+        _ => return Err(Error::SpendAll),
+    };
+    let inputs = QueryIter::new(load_input_since, Source::GroupInput).count();
+    if inputs != total as usize {
+        return Err(Error::SpendAll);
     }
-    check_since(since)?;
-
-    let message = {
-        let mut blake2b = Blake2bBuilder::new(BLAKE2B_BLOCK_SIZE)
-            .personal(CKB_HASH_PERSONALIZATION)
-            .build();
-        blake2b.update(&load_tx_hash()?);
-        blake2b.update(&(witness.total_size() as u64).to_le_bytes());
+    Ok(())
+}
 
-        {
-            let mut zero_lock = lock_bytes.to_vec();
-            zero_lock[multisig_script_len..multisig_script_len + signatures_len].fill(0);
-            let init_witness = WitnessArgs::from_slice(witness.as_slice())
-                .unwrap()
-                .as_builder()
-                .lock(Some(Bytes::from(zero_lock)).pack())
-                .build();
-            blake2b.update(init_witness.as_slice());
+/// What the reduced quorum of the successor may sign: the plain cells of the
+/// group, and the single cell of the successor type spent alongside, all go
+/// into the single output of that type, under the lock of the cell spent,
+/// the fee being paid by the other inputs. Automated rebalancing thus never
+/// needs the full quorum, nor can it send the funds anywhere else.
+///
+/// The type alone doesn't bind the successor: a type id only keeps its cell
+/// unique, whoever creates the cell or spends it with its own lock picks the
+/// lock of the output. The successor cell is thus spent, so a new one can't
+/// be made up, and its lock is kept.
+fn check_successor(type_hash: &[u8]) -> Result<(), Error> {
+    let mut moved: u64 = 0;
+    for (i, capacity) in QueryIter::new(load_cell_capacity, Source::GroupInput).enumerate() {
+        if load_cell_type_hash(i, Source::GroupInput)?.is_some() {
+            return Err(Error::TypedCell);
         }
-
-        QueryIter::new(load_witness_args, Source::GroupInput)
-            .skip(1)
-            .for_each(|data| {
-                blake2b.update(&(data.total_size() as u64).to_le_bytes());
-                blake2b.update(data.as_slice());
-            });
-        // For safety consideration, this lock script will also hash and guard all witnesses that
-        // have index values equal to or larger than the number of input cells. It assumes all
-        // witnesses that do have an input cell with the same index, will be guarded by the lock
-        // script of the input cell.
-        //
-        // For convenience reason, we provide a utility function here to calculate the number of
-        // input cells in a transaction
-        let i = calculate_inputs_len();
-        QueryIter::new(load_witness_args, Source::Input)
-            .skip(i)
-            .for_each(|data| {
-                blake2b.update(&(data.total_size() as u64).to_le_bytes());
-                blake2b.update(data.as_slice());
-            });
-        let mut tmp = [0; BLAKE2B_BLOCK_SIZE];
-        blake2b.finalize(&mut tmp);
-        tmp
+        moved = moved.checked_add(capacity).ok_or(Error::Successor)?;
+    }
+    let is_successor = |hash: &Option<[u8; BLAKE2B_BLOCK_SIZE]>| {
+        hash.as_ref().map(|hash| &hash[..]) == Some(type_hash)
     };
-
-    crate::secp256k1_helper::validate_secp256k1_multisignautre(
-        require_first_n,
-        threshold,
-        pubkeys_cnt,
-        &message,
-        &(*lock_bytes),
-        multisig_script_len,
-    )
+    let mut inputs = QueryIter::new(load_cell_type_hash, Source::Input)
+        .enumerate()
+        .filter(|(_, hash)| is_successor(hash))
+        .map(|(i, _)| i);
+    let input = match (inputs.next(), inputs.next()) {
+        (Some(index), None) => index,
+        _ => return Err(Error::Successor),
+    };
+    moved = moved
+        .checked_add(load_cell_capacity(input, Source::Input)?)
+        .ok_or(Error::Successor)?;
+
+    let mut outputs = QueryIter::new(load_cell_type_hash, Source::Output)
+        .enumerate()
+        .filter(|(_, hash)| is_successor(hash))
+        .map(|(i, _)| i);
+    let index = match (outputs.next(), outputs.next()) {
+        (Some(index), None) => index,
+        _ => return Err(Error::Successor),
+    };
+    if load_cell_lock_hash(index, Source::Output)? != load_cell_lock_hash(input, Source::Input)? {
+        return Err(Error::Successor);
+    }
+    if load_cell_capacity(index, Source::Output)? < moved {
+        return Err(Error::Successor);
+    }
+    Ok(())
 }
 
-/* calculate inputs length */
-fn calculate_inputs_len() -> usize {
-    /* lower bound, at least tx has one input */
-    let mut lo = 0;
-    /* higher bound */
-    let mut hi = 4;
-    /* try to load input until failing to increase lo and hi */
-    loop {
-        if let Ok(_since) = load_input_since(hi, Source::Input) {
-            lo = hi;
-            hi *= 2;
-        } else {
-            break;
+/// What the fee key may sign: the cells of the group are plain, and the
+/// plain outputs of this lock hold at least their capacity, the fee being
+/// paid by the other inputs. A type script could trap the capacity.
+fn check_net_position() -> Result<(), Error> {
+    let mut spent: u64 = 0;
+    for (i, capacity) in QueryIter::new(load_cell_capacity, Source::GroupInput).enumerate() {
+        if load_cell_type_hash(i, Source::GroupInput)?.is_some() {
+            return Err(Error::TypedCell);
         }
+        spent = spent.checked_add(capacity).ok_or(Error::NetPosition)?;
     }
 
-    /* now we get our lower bound and higher bound,
-    count number of inputs by binary search */
-    while lo + 1 != hi {
-        let i = (lo + hi) / 2;
-        if let Ok(_since) = load_input_since(i, Source::Input) {
-            lo = i;
-        } else {
-            hi = i;
+    let script_hash = load_script_hash()?;
+    let mut kept: u64 = 0;
+    for (i, hash) in QueryIter::new(load_cell_lock_hash, Source::Output).enumerate() {
+        if hash == script_hash && load_cell_type_hash(i, Source::Output)?.is_none() {
+            kept = kept
+                .checked_add(load_cell_capacity(i, Source::Output)?)
+                .ok_or(Error::NetPosition)?;
         }
     }
-    /* now lo is last input index and hi is length of inputs */
-    hi
-}
-
-fn check_since(since: u64) -> Result<(), Error> {
-    const SINCE_VALUE_BITS: usize = 56;
-    const SINCE_VALUE_MASK: u64 = 0x00ffffffffffffff;
-    const SINCE_EPOCH_FRACTION_FLAG: u64 = 0b00100000;
-
-    let since_flags = since >> SINCE_VALUE_BITS;
-    let since_value = since & SINCE_VALUE_MASK;
-
-    for i in 0.. {
-        match load_input_since(i, Source::GroupInput) {
-            Ok(input_since) => {
-                let input_since_flags = input_since >> SINCE_VALUE_BITS;
-                let input_since_value = input_since & SINCE_VALUE_MASK;
-                if since_flags != input_since_flags {
-                    return Err(Error::IncorrectSinceFlags);
-                } else if input_since_flags == SINCE_EPOCH_FRACTION_FLAG {
-                    let ret = epoch_number_with_fraction_cmp(input_since_value, since_value);
-                    if ret < 0 {
-                        return Err(Error::IncorrectSinceValue);
-                    }
-                } else if input_since_value < since_value {
-                    return Err(Error::IncorrectSinceValue);
-                }
-            }
-            Err(SysError::IndexOutOfBound) => break,
-            Err(err) => return Err(err.into()),
-        };
+    if kept < spent {
+        return Err(Error::NetPosition);
     }
     Ok(())
 }
 
-/* a and b are since value,
-return 0 if a is equals to b,
-return -1 if a is less than b,
-return 1 if a is greater than b */
-fn epoch_number_with_fraction_cmp(a: u64, b: u64) -> i32 {
-    let number_offset = 0;
-    let number_bits = 24;
-    let number_maximum_value = 1 << number_bits;
-    let number_mask = number_maximum_value - 1;
-    let index_offset = number_bits;
-    let index_bits = 16;
-    let index_maximum_value = 1 << index_bits;
-    let index_mask = index_maximum_value - 1;
-    let length_offset = number_bits + index_bits;
-    let length_bits = 16;
-    let length_maximum_value = 1 << length_bits;
-    let length_mask = length_maximum_value - 1;
-
-    /* extract a epoch */
-    let a_epoch = (a >> number_offset) & number_mask;
-    let a_index = (a >> index_offset) & index_mask;
-    let a_len = (a >> length_offset) & length_mask;
-
-    /* extract b epoch */
-    let b_epoch = (b >> number_offset) & number_mask;
-    let b_index = (b >> index_offset) & index_mask;
-    let b_len = (b >> length_offset) & length_mask;
-
-    if a_epoch < b_epoch {
-        return -1;
-    } else if a_epoch > b_epoch {
-        return 1;
-    } else {
-        /* a and b is in the same epoch,
-          compare a_index / a_len <=> b_index / b_len
-        */
-        let a_block = a_index * b_len;
-        let b_block = b_index * a_len;
-        /* compare block */
-        if a_block < b_block {
-            return -1;
-        } else if a_block > b_block {
-            return 1;
-        } else {
-            return 0;
-        }
-    }
+/// The entry of the exec callee, `argv` as defined in
+/// `ckb_multisig_core::auth`.
+pub fn auth(argv: &[&[u8]]) -> Result<(), Error> {
+    ckb_multisig_core::auth::verify_argv(argv)
 }
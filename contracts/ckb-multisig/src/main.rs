@@ -14,10 +14,10 @@
 // define modules
 mod entry;
 mod error;
-mod secp256k1_helper;
 
-use ckb_std::default_alloc;
-use core::arch::asm;
+use alloc::vec::Vec;
+use ckb_std::{cstr_core::CStr, default_alloc};
+use core::{arch::asm, slice};
 
 ckb_std::entry!(program_entry);
 default_alloc!();
@@ -25,9 +25,19 @@ default_alloc!();
 /// program entry
 ///
 ///  Both `argc` and `argv` can be omitted.
-fn program_entry(_argc: u64, _argv: *const *const u8) -> i8 {
+fn program_entry(argc: u64, argv: *const *const u8) -> i8 {
     // Call main function and return error code
-    match entry::main() {
+    let result = if argc == 0 {
+        entry::main()
+    } else {
+        // exec-ed by another script, see `ckb_multisig_core::auth`
+        let argv: Vec<&[u8]> = unsafe { slice::from_raw_parts(argv, argc as usize) }
+            .iter()
+            .map(|arg| unsafe { CStr::from_ptr(*arg as *const _) }.to_bytes())
+            .collect();
+        entry::auth(&argv)
+    };
+    match result {
         Ok(_) => 0,
         Err(err) => err as i8,
     }
@@ -0,0 +1,297 @@
+//! The HTTP endpoints, JSON in and out:
+//!
+//! ```text
+//! POST /users/<user>/address                                   -> address
+//! GET  /users/<user>                                           -> account
+//! POST /withdrawals               {"user", "to", "amount"}     -> summary
+//! GET  /withdrawals/<id>                                       -> signing request
+//! POST /withdrawals/<id>/signatures  {"signatures": [..]}      -> summary
+//! GET  /metrics                                                -> Prometheus metrics
+//! ```
+//!
+//! `<user>` is the name the exchange knows the user by, `<id>` the
+//! transaction hash of a withdrawal, hex. The address of a user is created on
+//! the first call and stays the same. `amount` is in CKB, up to 8 decimals,
+//! `to` an address of the network of the service.
+//!
+//! The cosigners sign the `message` of the summary, or the signing request
+//! with the CLI, and post their signatures: the transaction is sent as soon
+//! as the threshold is reached, posting no signature retries a failed send.
+//! Errors are `{"error": "..."}` with a 4xx status, 502 when the node
+//! refuses the transaction. See `metrics.rs` for the metrics.
+
+use std::{convert::TryInto, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use ckb_multisig_sdk::{
+    batch::{parse_ckb, BatchTransfer, Payment},
+    constants::SIGNATURE_SIZE,
+};
+use ckb_sdk::Address;
+use ckb_types::{packed::Script, H256};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chain::Chain,
+    metrics::Metrics,
+    settings::Settings,
+    store::{Credit, Error, Store, Summary, Withdrawal},
+};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub store: Arc<Store>,
+    pub settings: Arc<Settings>,
+    pub chain: Arc<dyn Chain>,
+    pub metrics: Arc<Metrics>,
+}
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/users/{user}/address", post(address))
+        .route("/users/{user}", get(account))
+        .route("/withdrawals", post(withdraw))
+        .route("/withdrawals/{id}", get(withdrawal))
+        .route("/withdrawals/{id}/signatures", post(add_signatures))
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+pub struct ApiError(pub StatusCode, pub String);
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        let status = match err {
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::BadRequest(_) | Error::Invalid(_) => StatusCode::BAD_REQUEST,
+            Error::Chain(_) => StatusCode::BAD_GATEWAY,
+            Error::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, err.to_string())
+    }
+}
+
+impl From<ckb_multisig_sdk::Error> for ApiError {
+    fn from(err: ckb_multisig_sdk::Error) -> Self {
+        Error::from(err).into()
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+fn bad_request(message: String) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, message)
+}
+
+#[derive(Serialize)]
+struct AddressInfo {
+    user: String,
+    index: u32,
+    address: String,
+    lock_args: String,
+}
+
+#[derive(Serialize)]
+struct AccountInfo {
+    user: String,
+    index: u32,
+    address: String,
+    /// In shannons.
+    balance: u64,
+    /// In shannons.
+    withdrawn: u64,
+    deposits: Vec<Credit>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WithdrawalRequest {
+    user: String,
+    to: String,
+    amount: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Signatures {
+    signatures: Vec<String>,
+}
+
+async fn address(
+    State(state): State<AppState>,
+    Path(user): Path<String>,
+) -> Result<Json<AddressInfo>, ApiError> {
+    let account = state.store.open_account(&user)?;
+    let config = state.settings.config(account.index)?;
+    Ok(Json(AddressInfo {
+        user,
+        index: account.index,
+        address: state.settings.address(&state.settings.lock_script(&config)),
+        lock_args: format!("0x{}", hex::encode(config.lock_args())),
+    }))
+}
+
+async fn account(
+    State(state): State<AppState>,
+    Path(user): Path<String>,
+) -> Result<Json<AccountInfo>, ApiError> {
+    let account = state.store.account(&user)?;
+    let config = state.settings.config(account.index)?;
+    Ok(Json(AccountInfo {
+        user,
+        index: account.index,
+        address: state.settings.address(&state.settings.lock_script(&config)),
+        balance: account.balance(),
+        withdrawn: account.withdrawn,
+        deposits: account.deposits,
+    }))
+}
+
+async fn withdraw(
+    State(state): State<AppState>,
+    Json(body): Json<WithdrawalRequest>,
+) -> Result<(StatusCode, Json<Summary>), ApiError> {
+    let to = parse_address(&state.settings, &body.to)?;
+    let amount = parse_ckb(&body.amount).map_err(bad_request)?;
+    let account = state.store.account(&body.user)?;
+    if account.balance() < amount {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            format!(
+                "the balance of `{}` is {} shannons, {} requested",
+                body.user,
+                account.balance(),
+                amount
+            ),
+        ));
+    }
+
+    let treasury = state.settings.config(0)?;
+    let (cells, genesis_hash) = {
+        let chain = Arc::clone(&state.chain);
+        let treasury = treasury.clone();
+        tokio::task::spawn_blocking(move || {
+            Ok::<_, String>((chain.spendable(&treasury)?, chain.genesis_hash()?))
+        })
+        .await
+        .map_err(|err| Error::Chain(err.to_string()))?
+        .map_err(Error::Chain)?
+    };
+    let settings = &state.settings;
+    let builder = BatchTransfer::new(
+        treasury.clone(),
+        settings.lock_script(&treasury),
+        settings.cell_deps.clone(),
+        settings.fee_rate,
+    );
+    let payment = Payment {
+        to,
+        capacity: amount,
+    };
+    let mut requests = builder.build(vec![payment], &cells)?;
+    if requests.len() != 1 {
+        return Err(bad_request(
+            "the withdrawal doesn't fit in one transaction".to_string(),
+        ));
+    }
+    let mut request = requests.remove(0);
+    request.genesis_hash = Some(genesis_hash);
+    let summary = state.store.withdraw(Withdrawal {
+        user: body.user,
+        to: body.to,
+        amount,
+        request,
+        sent: None,
+    })?;
+    state.metrics.withdrawal_proposed(&summary.id);
+    Ok((StatusCode::CREATED, Json(summary)))
+}
+
+async fn withdrawal(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let withdrawal = state.store.withdrawal(&parse_id(&id)?)?;
+    let json = withdrawal.request.to_json()?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], json).into_response())
+}
+
+async fn add_signatures(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(body): Json<Signatures>,
+) -> Result<Json<Summary>, ApiError> {
+    let id = parse_id(&id)?;
+    let signatures = body
+        .signatures
+        .iter()
+        .map(|signature| parse_hex::<SIGNATURE_SIZE>(signature, "signature"))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut withdrawal = state.store.add_signatures(&id, &signatures)?;
+    state.metrics.signatures_added(signatures.len());
+    if withdrawal.sent.is_none() && withdrawal.request.is_complete()? {
+        // the store refuses signatures once complete, this counts once
+        if !signatures.is_empty() {
+            state.metrics.threshold_reached(&id);
+        }
+        let chain = Arc::clone(&state.chain);
+        let request = withdrawal.request.clone();
+        let sent = tokio::task::spawn_blocking(move || chain.send(&request))
+            .await
+            .map_err(|err| Error::Chain(err.to_string()))
+            .and_then(|sent| sent.map_err(Error::Chain));
+        state.metrics.broadcast(&id, sent.is_ok());
+        let tx_hash = sent?;
+        withdrawal = state.store.mark_sent(&id, tx_hash)?;
+    }
+    Ok(Json(withdrawal.summary()?))
+}
+
+async fn metrics(State(state): State<AppState>) -> Response {
+    let metrics = state.metrics.render(&state.store);
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics,
+    )
+        .into_response()
+}
+
+fn parse_address(settings: &Settings, address: &str) -> Result<Script, ApiError> {
+    let parsed = Address::from_str(address)
+        .map_err(|err| bad_request(format!("invalid address `{}`: {}", address, err)))?;
+    if parsed.network() != settings.address_network() {
+        return Err(bad_request(format!(
+            "{} is not an address of {}",
+            address, settings.network
+        )));
+    }
+    Ok(Script::from(&parsed))
+}
+
+fn parse_id(id: &str) -> Result<H256, ApiError> {
+    H256::from_str(id.trim_start_matches("0x"))
+        .map_err(|err| bad_request(format!("invalid withdrawal id `{}`: {}", id, err)))
+}
+
+fn parse_hex<const N: usize>(s: &str, what: &str) -> Result<[u8; N], ApiError> {
+    hex::decode(s.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            bad_request(format!(
+                "invalid {} `{}`, expected {} bytes hex",
+                what, s, N
+            ))
+        })
+}
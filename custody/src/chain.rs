@@ -0,0 +1,84 @@
+//! What the service reads from and sends to the chain, behind a trait so
+//! that an exchange can plug its own node access, and tests a mock.
+
+use ckb_multisig_sdk::{
+    request::SigningRequest,
+    scanner::Scanner,
+    watcher::{Deposit, DepositWatcher},
+    MultisigConfig,
+};
+use ckb_sdk::{traits::LiveCell, CkbRpcClient};
+use ckb_types::{core::ScriptHashType, H256};
+
+/// The chain, every method called on a blocking thread.
+pub trait Chain: Send + Sync {
+    /// The cells of `config` the withdrawals can spend.
+    fn spendable(&self, config: &MultisigConfig) -> Result<Vec<LiveCell>, String>;
+
+    /// The deposits to `config` confirmed from `from_block` on, and the
+    /// first block not looked at yet.
+    fn deposits(
+        &self,
+        config: &MultisigConfig,
+        from_block: u64,
+    ) -> Result<(Vec<Deposit>, u64), String>;
+
+    /// The hash of the genesis block, naming the chain in the withdrawals.
+    fn genesis_hash(&self) -> Result<H256, String>;
+
+    /// The hash of the sent transaction.
+    fn send(&self, request: &SigningRequest) -> Result<H256, String>;
+}
+
+/// The RPC of a node with the indexer module enabled.
+pub struct RpcChain {
+    url: String,
+    code_hash: H256,
+    hash_type: ScriptHashType,
+    confirmations: u64,
+}
+
+impl RpcChain {
+    pub fn new(url: &str, code_hash: H256, hash_type: ScriptHashType, confirmations: u64) -> Self {
+        RpcChain {
+            url: url.to_string(),
+            code_hash,
+            hash_type,
+            confirmations,
+        }
+    }
+}
+
+impl Chain for RpcChain {
+    fn spendable(&self, config: &MultisigConfig) -> Result<Vec<LiveCell>, String> {
+        let cells = Scanner::new(&self.url, config, &self.code_hash, self.hash_type)
+            .scan()
+            .map_err(|err| err.to_string())?;
+        Ok(cells.spendable())
+    }
+
+    fn deposits(
+        &self,
+        config: &MultisigConfig,
+        from_block: u64,
+    ) -> Result<(Vec<Deposit>, u64), String> {
+        let mut watcher = DepositWatcher::new(&self.url, config, &self.code_hash, self.hash_type)
+            .from_block(from_block)
+            .confirmations(self.confirmations);
+        let deposits = watcher.poll().map_err(|err| err.to_string())?;
+        Ok((deposits, watcher.next_block()))
+    }
+
+    fn genesis_hash(&self) -> Result<H256, String> {
+        CkbRpcClient::new(&self.url)
+            .get_block_hash(0.into())
+            .map_err(|err| err.to_string())?
+            .ok_or_else(|| "no genesis block".to_string())
+    }
+
+    fn send(&self, request: &SigningRequest) -> Result<H256, String> {
+        request
+            .send(&CkbRpcClient::new(&self.url))
+            .map_err(|err| err.to_string())
+    }
+}
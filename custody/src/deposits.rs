@@ -0,0 +1,50 @@
+//! Deposit detection: the address of every account is polled for the
+//! deposits confirmed since the last poll, see `watcher.rs` of the SDK, and
+//! each new one is credited once to the account.
+
+use std::sync::Arc;
+
+use crate::{
+    api::AppState,
+    store::{Credit, Error},
+};
+
+/// Credit the new deposits of every account, returning them by user.
+pub async fn poll(state: &AppState) -> Result<Vec<(String, Credit)>, Error> {
+    let mut credited = Vec::new();
+    for (user, account) in state.store.accounts() {
+        let config = state.settings.config(account.index)?;
+        let chain = Arc::clone(&state.chain);
+        let (deposits, next_block) =
+            tokio::task::spawn_blocking(move || chain.deposits(&config, account.next_block))
+                .await
+                .map_err(|err| Error::Chain(err.to_string()))?
+                .map_err(Error::Chain)?;
+        let credits = state.store.credit(&user, &deposits, next_block)?;
+        state.metrics.deposits_credited(credits.len());
+        for credit in credits {
+            credited.push((user.clone(), credit));
+        }
+    }
+    Ok(credited)
+}
+
+/// Poll every `poll_interval` of the settings, forever, logging the
+/// deposits and the errors.
+pub async fn run(state: AppState) {
+    let mut interval = tokio::time::interval(state.settings.poll_interval);
+    loop {
+        interval.tick().await;
+        match poll(&state).await {
+            Ok(credited) => {
+                for (user, credit) in credited {
+                    println!(
+                        "credited {} shannons to {} from {:#x}:{}",
+                        credit.capacity, user, credit.tx_hash, credit.index
+                    );
+                }
+            }
+            Err(err) => eprintln!("deposit poll failed: {}", err),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Reference custody backend of the ckb-multisig lock, for exchanges to
+//! adapt rather than building from the raw SDK calls.
+//!
+//! Every user gets a deposit address of its own, a multisig config derived
+//! from the xpubs of the cosigners. The deposits to those addresses are
+//! detected and credited to the users, who withdraw from the treasury
+//! config: the service builds the withdrawal transaction, the cosigners post
+//! their signatures, and it is sent to the node once the threshold is
+//! reached. Sweeping the deposit addresses into the treasury is left to the
+//! `sweep` flow of the SDK.
+//!
+//! See `settings.rs` for the settings file, `api.rs` for the endpoints,
+//! `deposits.rs` for the deposit detection, `chain.rs` for the node access,
+//! `metrics.rs` for the Prometheus metrics and `store.rs` for the accounts
+//! and withdrawals on disk.
+
+mod api;
+mod chain;
+mod deposits;
+mod metrics;
+mod settings;
+mod store;
+#[cfg(test)]
+mod tests;
+
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(
+    name = "ckb-multisig-custody",
+    version,
+    about = "Custody backend of ckb-multisig deposit addresses and withdrawals"
+)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8121")]
+    listen: SocketAddr,
+
+    /// Directory the accounts and withdrawals are kept in
+    #[arg(long)]
+    dir: PathBuf,
+
+    /// Settings file, see `settings.rs`
+    #[arg(long)]
+    settings: PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let path = args.settings.clone();
+    let settings = tokio::task::spawn_blocking(move || settings::Settings::load(&path)).await??;
+    let store = store::Store::open(&args.dir)
+        .with_context(|| format!("open accounts in {}", args.dir.display()))?;
+    let chain = chain::RpcChain::new(
+        &settings.rpc,
+        settings.deployment.code_hash.clone(),
+        settings.deployment.hash_type,
+        settings.confirmations,
+    );
+    let state = api::AppState {
+        store: Arc::new(store),
+        settings: Arc::new(settings),
+        chain: Arc::new(chain),
+        metrics: Arc::default(),
+    };
+    let listener = tokio::net::TcpListener::bind(args.listen)
+        .await
+        .with_context(|| format!("listen on {}", args.listen))?;
+    tokio::spawn(deposits::run(state.clone()));
+    axum::serve(listener, api::router(state)).await?;
+    Ok(())
+}
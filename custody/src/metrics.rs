@@ -0,0 +1,195 @@
+//! Metrics of the deposits and withdrawals for Prometheus, in its text
+//! format at `GET /metrics`:
+//!
+//! ```text
+//! ckb_multisig_custody_withdrawals_pending        gauge, withdrawals not sent yet
+//! ckb_multisig_custody_oldest_pending_seconds     gauge, age of the oldest of them
+//! ckb_multisig_custody_withdrawals_total          counter, withdrawals proposed
+//! ckb_multisig_custody_signatures_total           counter, signatures accepted
+//! ckb_multisig_custody_threshold_seconds          histogram, from proposal to threshold
+//! ckb_multisig_custody_broadcasts_total           counter, transactions sent to the node
+//! ckb_multisig_custody_broadcast_failures_total   counter
+//! ckb_multisig_custody_deposits_total             counter, deposits credited
+//! ```
+//!
+//! Alert on `ckb_multisig_custody_oldest_pending_seconds` for the stalled
+//! withdrawals, missing signatures or refused by the node. The ages count
+//! from the proposal of a withdrawal by this process, from the first scrape
+//! for the withdrawals loaded from the directory at start.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use ckb_types::H256;
+
+use crate::store::Store;
+
+/// Upper bounds of the buckets of the time to threshold, in seconds: a
+/// minute, 5 and 15 minutes, an hour, 4 hours, a day, 3 days and a week.
+pub const THRESHOLD_BUCKETS: [u64; 8] = [60, 300, 900, 3600, 14400, 86400, 259200, 604800];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; THRESHOLD_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(THRESHOLD_BUCKETS.iter()) {
+            if seconds <= *bound as f64 {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    withdrawals: AtomicU64,
+    signatures: AtomicU64,
+    broadcasts: AtomicU64,
+    broadcast_failures: AtomicU64,
+    deposits: AtomicU64,
+    threshold: Mutex<Histogram>,
+    /// When the withdrawals not sent yet were proposed.
+    started: Mutex<HashMap<H256, Instant>>,
+}
+
+impl Metrics {
+    pub fn withdrawal_proposed(&self, id: &H256) {
+        self.withdrawals.fetch_add(1, Ordering::Relaxed);
+        self.started
+            .lock()
+            .expect("poisoned lock")
+            .insert(id.clone(), Instant::now());
+    }
+
+    pub fn signatures_added(&self, count: usize) {
+        self.signatures.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn threshold_reached(&self, id: &H256) {
+        let started = self.started.lock().expect("poisoned lock").get(id).copied();
+        if let Some(started) = started {
+            self.threshold
+                .lock()
+                .expect("poisoned lock")
+                .observe(started.elapsed().as_secs_f64());
+        }
+    }
+
+    pub fn broadcast(&self, id: &H256, sent: bool) {
+        if sent {
+            self.broadcasts.fetch_add(1, Ordering::Relaxed);
+            self.started.lock().expect("poisoned lock").remove(id);
+        } else {
+            self.broadcast_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn deposits_credited(&self, count: usize) {
+        self.deposits.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// The metrics in the text format of Prometheus.
+    pub fn render(&self, store: &Store) -> String {
+        let pending = store.unsent();
+        let oldest = {
+            let mut started = self.started.lock().expect("poisoned lock");
+            let now = Instant::now();
+            started.retain(|id, _| pending.contains(id));
+            pending
+                .iter()
+                .map(|id| *started.entry(id.clone()).or_insert(now))
+                .min()
+                .map_or(0.0, |started| started.elapsed().as_secs_f64())
+        };
+
+        let mut out = String::new();
+        metric(
+            &mut out,
+            "ckb_multisig_custody_withdrawals_pending",
+            "gauge",
+            "Withdrawals not sent yet.",
+            pending.len(),
+        );
+        metric(
+            &mut out,
+            "ckb_multisig_custody_oldest_pending_seconds",
+            "gauge",
+            "Age of the oldest withdrawal not sent yet.",
+            oldest,
+        );
+        metric(
+            &mut out,
+            "ckb_multisig_custody_withdrawals_total",
+            "counter",
+            "Withdrawals proposed.",
+            self.withdrawals.load(Ordering::Relaxed),
+        );
+        metric(
+            &mut out,
+            "ckb_multisig_custody_signatures_total",
+            "counter",
+            "Signatures accepted.",
+            self.signatures.load(Ordering::Relaxed),
+        );
+        let threshold = self.threshold.lock().expect("poisoned lock");
+        histogram(
+            &mut out,
+            "ckb_multisig_custody_threshold_seconds",
+            "Time from the proposal of a withdrawal to its threshold.",
+            &threshold,
+        );
+        metric(
+            &mut out,
+            "ckb_multisig_custody_broadcasts_total",
+            "counter",
+            "Withdrawal transactions sent to the node.",
+            self.broadcasts.load(Ordering::Relaxed),
+        );
+        metric(
+            &mut out,
+            "ckb_multisig_custody_broadcast_failures_total",
+            "counter",
+            "Withdrawal transactions the node refused.",
+            self.broadcast_failures.load(Ordering::Relaxed),
+        );
+        metric(
+            &mut out,
+            "ckb_multisig_custody_deposits_total",
+            "counter",
+            "Deposits credited to the users.",
+            self.deposits.load(Ordering::Relaxed),
+        );
+        out
+    }
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    for (bound, count) in THRESHOLD_BUCKETS.iter().zip(histogram.buckets.iter()) {
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+    }
+    let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, histogram.count);
+    let _ = writeln!(out, "{}_sum {}", name, histogram.sum);
+    let _ = writeln!(out, "{}_count {}", name, histogram.count);
+}
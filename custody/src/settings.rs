@@ -0,0 +1,138 @@
+//! The settings file of the service, TOML:
+//!
+//! ```toml
+//! rpc = "http://127.0.0.1:8114"
+//! network = "testnet"
+//! # the registry of the deployments of the lock, see `deployment.rs` of the SDK
+//! deployments = "deployments.toml"
+//! # the account xpubs of the cosigners, see `xpub.rs` of the SDK
+//! xpubs = ["xpub...", "xpub...", "xpub..."]
+//! require_first_n = 0
+//! threshold = 2
+//! # optional, in shannons per 1000 bytes
+//! fee_rate = 1000
+//! # optional, blocks on top of a deposit before it is credited
+//! confirmations = 24
+//! # optional, seconds between two polls of the deposits
+//! poll_interval = 8
+//! ```
+//!
+//! The config at index 0 of the xpubs is the treasury the withdrawals are
+//! paid from, the users get the configs from index 1 on as deposit
+//! addresses.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use ckb_multisig_sdk::{
+    deployment::{find_secp256k1_data, Deployment, Network, Registry},
+    hd::ExtendedPubKey,
+    watcher::{DEFAULT_CONFIRMATIONS, DEFAULT_INTERVAL},
+    xpub::XpubConfig,
+    MultisigConfig,
+};
+use ckb_sdk::{Address, AddressPayload, CkbRpcClient, NetworkType};
+use ckb_types::packed::{CellDep, Script};
+use serde::Deserialize;
+
+const DEFAULT_FEE_RATE: u64 = 1000;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsFile {
+    rpc: String,
+    network: Network,
+    deployments: PathBuf,
+    xpubs: Vec<String>,
+    require_first_n: u8,
+    threshold: u8,
+    fee_rate: Option<u64>,
+    confirmations: Option<u64>,
+    poll_interval: Option<u64>,
+}
+
+/// The settings, resolved.
+pub struct Settings {
+    pub rpc: String,
+    pub network: Network,
+    pub deployment: Deployment,
+    pub cell_deps: Vec<CellDep>,
+    pub xpubs: XpubConfig,
+    pub fee_rate: u64,
+    pub confirmations: u64,
+    pub poll_interval: Duration,
+}
+
+impl Settings {
+    /// Read the settings at `path`, the registry relative to it. The
+    /// `secp256k1_data` cell of a devnet is looked up on the node.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        let file: SettingsFile =
+            toml::from_str(&content).with_context(|| format!("parse {}", path.display()))?;
+        let registry_path = path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(&file.deployments);
+        let registry = fs::read_to_string(&registry_path)
+            .with_context(|| format!("read {}", registry_path.display()))?;
+        let mut registry = Registry::from_toml(&registry)?;
+        let deployment = registry.get(file.network)?.clone();
+        if file.network == Network::Devnet && deployment.secp256k1_data {
+            let genesis = CkbRpcClient::new(&file.rpc)
+                .get_block_by_number(0.into())?
+                .context("no genesis block")?;
+            let dep = find_secp256k1_data(&genesis.into())
+                .context("no secp256k1_data cell in the genesis block")?;
+            registry.set_secp256k1_data(file.network, dep);
+        }
+        let xpubs = file
+            .xpubs
+            .iter()
+            .map(|xpub| xpub.parse())
+            .collect::<Result<Vec<ExtendedPubKey>, _>>()?;
+        Ok(Settings {
+            rpc: file.rpc,
+            network: file.network,
+            cell_deps: registry.cell_deps(file.network)?,
+            deployment,
+            xpubs: XpubConfig::new(xpubs, file.require_first_n, file.threshold)?,
+            fee_rate: file.fee_rate.unwrap_or(DEFAULT_FEE_RATE),
+            confirmations: file.confirmations.unwrap_or(DEFAULT_CONFIRMATIONS),
+            poll_interval: file
+                .poll_interval
+                .map_or(DEFAULT_INTERVAL, Duration::from_secs),
+        })
+    }
+
+    /// The config at `index` of the xpubs, 0 being the treasury.
+    pub fn config(&self, index: u32) -> Result<MultisigConfig, ckb_multisig_sdk::Error> {
+        self.xpubs.config(index)
+    }
+
+    pub fn lock_script(&self, config: &MultisigConfig) -> Script {
+        self.deployment.lock_script(config)
+    }
+
+    pub fn address_network(&self) -> NetworkType {
+        match self.network {
+            Network::Mainnet => NetworkType::Mainnet,
+            Network::Testnet => NetworkType::Testnet,
+            Network::Devnet => NetworkType::Dev,
+        }
+    }
+
+    pub fn address(&self, lock: &Script) -> String {
+        Address::new(
+            self.address_network(),
+            AddressPayload::from(lock.clone()),
+            true,
+        )
+        .to_string()
+    }
+}
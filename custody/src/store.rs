@@ -0,0 +1,402 @@
+//! The accounts of the users and the withdrawals, kept in memory and
+//! written to a directory: `accounts.json` for the accounts, and one
+//! `withdrawals/<tx hash>.json` file per withdrawal, holding its signing
+//! request in the format of the SDK.
+//!
+//! A user owns the config at the index of its account: every deposit to its
+//! address is credited to it once, by out point, and every withdrawal is
+//! debited when proposed, the fee being paid by the treasury.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use ckb_multisig_sdk::{constants::SIGNATURE_SIZE, request::SigningRequest, watcher::Deposit};
+use ckb_types::{prelude::*, H256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error(transparent)]
+    Invalid(#[from] ckb_multisig_sdk::Error),
+
+    #[error("chain error: {0}")]
+    Chain(String),
+
+    #[error("storage error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// A deposit credited to an account.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credit {
+    pub tx_hash: H256,
+    pub index: u32,
+    pub block_number: u64,
+    /// In shannons.
+    pub capacity: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Account {
+    /// The index of the config of the account among the xpub configs.
+    pub index: u32,
+    /// The first block whose deposits are not credited yet.
+    pub next_block: u64,
+    pub deposits: Vec<Credit>,
+    /// In shannons.
+    pub withdrawn: u64,
+}
+
+impl Account {
+    /// In shannons.
+    pub fn balance(&self) -> u64 {
+        let deposited: u64 = self.deposits.iter().map(|credit| credit.capacity).sum();
+        deposited.saturating_sub(self.withdrawn)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Accounts {
+    /// The index of the next account, 0 being the treasury.
+    next_index: u32,
+    accounts: BTreeMap<String, Account>,
+}
+
+/// A withdrawal of a user.
+#[derive(Clone, Debug)]
+pub struct Withdrawal {
+    pub user: String,
+    /// Address of the recipient.
+    pub to: String,
+    /// In shannons.
+    pub amount: u64,
+    pub request: SigningRequest,
+    /// Hash of the transaction once sent.
+    pub sent: Option<H256>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WithdrawalFile {
+    user: String,
+    to: String,
+    amount: u64,
+    request: Value,
+    sent: Option<H256>,
+}
+
+/// What the cosigners need to know about a withdrawal.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub id: H256,
+    pub user: String,
+    pub to: String,
+    /// In shannons.
+    pub amount: u64,
+    /// In shannons.
+    pub fee: u64,
+    /// The digest the cosigners sign, hex.
+    pub message: String,
+    pub threshold: u8,
+    /// Identities of the cosigners who signed, hex.
+    pub signed: Vec<String>,
+    pub complete: bool,
+    pub sent: Option<H256>,
+}
+
+impl Withdrawal {
+    pub fn id(&self) -> H256 {
+        self.request.tx.hash().unpack()
+    }
+
+    pub fn summary(&self) -> Result<Summary, Error> {
+        let request = &self.request;
+        Ok(Summary {
+            id: self.id(),
+            user: self.user.clone(),
+            to: self.to.clone(),
+            amount: self.amount,
+            fee: request.fee,
+            message: format!("0x{}", hex::encode(request.message()?)),
+            threshold: request.config.threshold(),
+            signed: request
+                .signed()?
+                .iter()
+                .map(|identity| format!("0x{}", hex::encode(identity)))
+                .collect(),
+            complete: request.is_complete()?,
+            sent: self.sent.clone(),
+        })
+    }
+
+    fn to_json(&self) -> Result<String, Error> {
+        let file = WithdrawalFile {
+            user: self.user.clone(),
+            to: self.to.clone(),
+            amount: self.amount,
+            request: serde_json::from_str(&self.request.to_json()?)
+                .expect("signing request is JSON"),
+            sent: self.sent.clone(),
+        };
+        Ok(serde_json::to_string_pretty(&file).expect("serializable withdrawal"))
+    }
+
+    fn from_json(json: &str) -> Result<Self, Error> {
+        let file: WithdrawalFile = serde_json::from_str(json)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(Withdrawal {
+            user: file.user,
+            to: file.to,
+            amount: file.amount,
+            request: SigningRequest::from_json(&file.request.to_string())?,
+            sent: file.sent,
+        })
+    }
+}
+
+const ACCOUNTS: &str = "accounts.json";
+const WITHDRAWALS: &str = "withdrawals";
+
+pub struct Store {
+    dir: PathBuf,
+    accounts: Mutex<Accounts>,
+    withdrawals: Mutex<BTreeMap<H256, Withdrawal>>,
+}
+
+impl Store {
+    /// Load the accounts and withdrawals of `dir`, created when missing.
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(dir.join(WITHDRAWALS))?;
+        let accounts = match fs::read_to_string(dir.join(ACCOUNTS)) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Accounts {
+                next_index: 1,
+                accounts: BTreeMap::new(),
+            },
+            Err(err) => return Err(err.into()),
+        };
+        let mut withdrawals = BTreeMap::new();
+        for entry in fs::read_dir(dir.join(WITHDRAWALS))? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let withdrawal = Withdrawal::from_json(&fs::read_to_string(&path)?)?;
+            withdrawals.insert(withdrawal.id(), withdrawal);
+        }
+        Ok(Store {
+            dir: dir.to_path_buf(),
+            accounts: Mutex::new(accounts),
+            withdrawals: Mutex::new(withdrawals),
+        })
+    }
+
+    /// The account of `user`, opened with the next index when new.
+    pub fn open_account(&self, user: &str) -> Result<Account, Error> {
+        check_user(user)?;
+        let mut accounts = self.accounts.lock().expect("poisoned lock");
+        if let Some(account) = accounts.accounts.get(user) {
+            return Ok(account.clone());
+        }
+        let account = Account {
+            index: accounts.next_index,
+            next_block: 0,
+            deposits: Vec::new(),
+            withdrawn: 0,
+        };
+        accounts.next_index += 1;
+        accounts.accounts.insert(user.to_string(), account.clone());
+        self.save_accounts(&accounts)?;
+        Ok(account)
+    }
+
+    pub fn account(&self, user: &str) -> Result<Account, Error> {
+        self.accounts
+            .lock()
+            .expect("poisoned lock")
+            .accounts
+            .get(user)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("unknown user `{}`", user)))
+    }
+
+    pub fn accounts(&self) -> Vec<(String, Account)> {
+        let accounts = self.accounts.lock().expect("poisoned lock");
+        accounts
+            .accounts
+            .iter()
+            .map(|(user, account)| (user.clone(), account.clone()))
+            .collect()
+    }
+
+    /// Credit the deposits found up to `next_block`, skipping those already
+    /// credited, and return the new ones.
+    pub fn credit(
+        &self,
+        user: &str,
+        deposits: &[Deposit],
+        next_block: u64,
+    ) -> Result<Vec<Credit>, Error> {
+        let mut accounts = self.accounts.lock().expect("poisoned lock");
+        let account = accounts
+            .accounts
+            .get_mut(user)
+            .ok_or_else(|| Error::NotFound(format!("unknown user `{}`", user)))?;
+        let mut credited = Vec::new();
+        for deposit in deposits {
+            let credit = Credit {
+                tx_hash: deposit.out_point.tx_hash().unpack(),
+                index: deposit.out_point.index().unpack(),
+                block_number: deposit.block_number,
+                capacity: deposit.capacity(),
+            };
+            let known = account
+                .deposits
+                .iter()
+                .any(|known| known.tx_hash == credit.tx_hash && known.index == credit.index);
+            if !known {
+                account.deposits.push(credit.clone());
+                credited.push(credit);
+            }
+        }
+        account.next_block = account.next_block.max(next_block);
+        self.save_accounts(&accounts)?;
+        Ok(credited)
+    }
+
+    /// Keep the withdrawal and debit its amount from the account of its
+    /// user.
+    pub fn withdraw(&self, withdrawal: Withdrawal) -> Result<Summary, Error> {
+        let mut accounts = self.accounts.lock().expect("poisoned lock");
+        let mut withdrawals = self.withdrawals.lock().expect("poisoned lock");
+        let id = withdrawal.id();
+        if withdrawals.contains_key(&id) {
+            return Err(Error::Conflict(format!(
+                "withdrawal {:#x} already exists",
+                id
+            )));
+        }
+        let account = accounts
+            .accounts
+            .get_mut(&withdrawal.user)
+            .ok_or_else(|| Error::NotFound(format!("unknown user `{}`", withdrawal.user)))?;
+        if account.balance() < withdrawal.amount {
+            return Err(Error::Conflict(format!(
+                "the balance of `{}` is {} shannons, {} requested",
+                withdrawal.user,
+                account.balance(),
+                withdrawal.amount
+            )));
+        }
+        let summary = withdrawal.summary()?;
+        account.withdrawn += withdrawal.amount;
+        self.save_withdrawal(&withdrawal)?;
+        self.save_accounts(&accounts)?;
+        withdrawals.insert(id, withdrawal);
+        Ok(summary)
+    }
+
+    pub fn withdrawal(&self, id: &H256) -> Result<Withdrawal, Error> {
+        self.withdrawals
+            .lock()
+            .expect("poisoned lock")
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("unknown withdrawal {:#x}", id)))
+    }
+
+    /// The ids of the withdrawals not sent yet.
+    pub fn unsent(&self) -> Vec<H256> {
+        let withdrawals = self.withdrawals.lock().expect("poisoned lock");
+        withdrawals
+            .iter()
+            .filter(|(_, withdrawal)| withdrawal.sent.is_none())
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    /// Add the signatures, all or none: each must come from a member who
+    /// hasn't signed yet.
+    pub fn add_signatures(
+        &self,
+        id: &H256,
+        signatures: &[[u8; SIGNATURE_SIZE]],
+    ) -> Result<Withdrawal, Error> {
+        let mut withdrawals = self.withdrawals.lock().expect("poisoned lock");
+        let mut withdrawal = withdrawals
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("unknown withdrawal {:#x}", id)))?;
+        for signature in signatures {
+            if withdrawal.request.is_complete()? {
+                return Err(Error::Conflict(format!(
+                    "withdrawal {:#x} is already completely signed",
+                    id
+                )));
+            }
+            withdrawal.request.add_signature(*signature)?;
+        }
+        self.save_withdrawal(&withdrawal)?;
+        withdrawals.insert(id.clone(), withdrawal.clone());
+        Ok(withdrawal)
+    }
+
+    /// Record the transaction of the withdrawal as sent.
+    pub fn mark_sent(&self, id: &H256, tx_hash: H256) -> Result<Withdrawal, Error> {
+        let mut withdrawals = self.withdrawals.lock().expect("poisoned lock");
+        let withdrawal = withdrawals
+            .get_mut(id)
+            .ok_or_else(|| Error::NotFound(format!("unknown withdrawal {:#x}", id)))?;
+        withdrawal.sent = Some(tx_hash);
+        let withdrawal = withdrawal.clone();
+        self.save_withdrawal(&withdrawal)?;
+        Ok(withdrawal)
+    }
+
+    fn save_accounts(&self, accounts: &Accounts) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(accounts).expect("serializable accounts");
+        write_atomic(&self.dir.join(ACCOUNTS), &json)
+    }
+
+    fn save_withdrawal(&self, withdrawal: &Withdrawal) -> Result<(), Error> {
+        let path = self
+            .dir
+            .join(WITHDRAWALS)
+            .join(format!("{:x}.json", withdrawal.id()));
+        write_atomic(&path, &withdrawal.to_json()?)
+    }
+}
+
+/// Names of 1 to 64 ASCII letters, digits, `-` or `_`.
+fn check_user(user: &str) -> Result<(), Error> {
+    let valid = !user.is_empty()
+        && user.len() <= 64
+        && user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(Error::BadRequest(format!("invalid user name `{}`", user)));
+    }
+    Ok(())
+}
+
+fn write_atomic(path: &Path, content: &str) -> Result<(), Error> {
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,450 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use ckb_multisig_sdk::{
+    deployment::{Deployment, Network},
+    hd::{ckb_account_path, ckb_path, ExtendedPrivKey, Mnemonic},
+    request::SigningRequest,
+    watcher::Deposit,
+    xpub::XpubConfig,
+    MultisigConfig, SecpSigner, Signer,
+};
+use ckb_sdk::{traits::LiveCell, Address, AddressPayload, NetworkType};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, DepType, ScriptHashType},
+    packed::{CellDep, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use http_body_util::BodyExt;
+use secp256k1::rand;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use crate::{
+    api::{router, AppState},
+    chain::Chain,
+    deposits,
+    settings::Settings,
+    store::Store,
+};
+
+const ONE_CKB: u64 = 100_000_000;
+const GENESIS_HASH: H256 = H256([7; 32]);
+
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "ckb-multisig-custody-{}",
+        hex::encode(rand::random::<[u8; 8]>())
+    ))
+}
+
+/// The chain of a test: the live cells and deposits by lock.
+#[derive(Default)]
+struct MockChain {
+    cells: Mutex<HashMap<Script, Vec<LiveCell>>>,
+    deposits: Mutex<HashMap<Script, Vec<Deposit>>>,
+    sent: Mutex<Vec<H256>>,
+    refuse: Mutex<bool>,
+    deployment: Option<Deployment>,
+}
+
+impl MockChain {
+    fn lock(&self, config: &MultisigConfig) -> Script {
+        self.deployment.as_ref().unwrap().lock_script(config)
+    }
+
+    fn live_cell(lock: &Script, capacity: u64) -> LiveCell {
+        LiveCell {
+            output: CellOutput::new_builder()
+                .capacity(Capacity::shannons(capacity).pack())
+                .lock(lock.clone())
+                .build(),
+            output_data: Bytes::new(),
+            out_point: OutPoint::new(rand::random::<[u8; 32]>().pack(), 0),
+            block_number: 1,
+            tx_index: 1,
+        }
+    }
+
+    fn fund(&self, lock: &Script, capacity: u64) {
+        self.cells
+            .lock()
+            .unwrap()
+            .entry(lock.clone())
+            .or_default()
+            .push(Self::live_cell(lock, capacity));
+    }
+
+    fn deposit(&self, lock: &Script, capacity: u64, block_number: u64) {
+        let cell = Self::live_cell(lock, capacity);
+        self.deposits
+            .lock()
+            .unwrap()
+            .entry(lock.clone())
+            .or_default()
+            .push(Deposit {
+                out_point: cell.out_point,
+                block_number,
+                output: cell.output,
+                output_data: cell.output_data,
+            });
+    }
+}
+
+impl Chain for MockChain {
+    fn spendable(&self, config: &MultisigConfig) -> Result<Vec<LiveCell>, String> {
+        let cells = self.cells.lock().unwrap();
+        Ok(cells.get(&self.lock(config)).cloned().unwrap_or_default())
+    }
+
+    fn deposits(
+        &self,
+        config: &MultisigConfig,
+        from_block: u64,
+    ) -> Result<(Vec<Deposit>, u64), String> {
+        let deposits = self.deposits.lock().unwrap();
+        let deposits: Vec<_> = deposits
+            .get(&self.lock(config))
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|deposit| deposit.block_number >= from_block)
+            .collect();
+        // everything up to block 100 is confirmed
+        Ok((deposits, 100))
+    }
+
+    fn genesis_hash(&self) -> Result<H256, String> {
+        Ok(GENESIS_HASH)
+    }
+
+    fn send(&self, request: &SigningRequest) -> Result<H256, String> {
+        if *self.refuse.lock().unwrap() {
+            return Err("the node is down".to_string());
+        }
+        request
+            .check_chain(&GENESIS_HASH)
+            .map_err(|err| err.to_string())?;
+        let tx_hash: H256 = request.tx.hash().unpack();
+        self.sent.lock().unwrap().push(tx_hash.clone());
+        Ok(tx_hash)
+    }
+}
+
+/// Three cosigners with their mnemonics, 2 of 3.
+struct Setup {
+    mnemonics: Vec<Mnemonic>,
+    state: AppState,
+    chain: Arc<MockChain>,
+    app: Router,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let mnemonics: Vec<_> = (0..3).map(|_| Mnemonic::generate(12).unwrap()).collect();
+        let xpubs = mnemonics
+            .iter()
+            .enumerate()
+            .map(|(account, mnemonic)| {
+                ExtendedPrivKey::from_seed(&mnemonic.to_seed(""))
+                    .unwrap()
+                    .derive(&ckb_account_path(account as u32).unwrap())
+                    .unwrap()
+                    .to_public()
+            })
+            .collect();
+        let deployment = Deployment {
+            code_hash: H256([0x42; 32]),
+            hash_type: ScriptHashType::Type,
+            cell_dep: CellDep::new_builder()
+                .out_point(OutPoint::new(H256([0x43; 32]).pack(), 0))
+                .dep_type(DepType::DepGroup)
+                .build(),
+            secp256k1_data: false,
+        };
+        let settings = Settings {
+            rpc: String::new(),
+            network: Network::Testnet,
+            cell_deps: vec![deployment.cell_dep.clone()],
+            deployment: deployment.clone(),
+            xpubs: XpubConfig::new(xpubs, 0, 2).unwrap(),
+            fee_rate: 1000,
+            confirmations: 24,
+            poll_interval: Duration::from_secs(1),
+        };
+        let chain = Arc::new(MockChain {
+            deployment: Some(deployment),
+            ..MockChain::default()
+        });
+        let state = AppState {
+            store: Arc::new(Store::open(&temp_dir()).unwrap()),
+            settings: Arc::new(settings),
+            chain: Arc::clone(&chain) as Arc<dyn Chain>,
+            metrics: Arc::default(),
+        };
+        let app = router(state.clone());
+        Setup {
+            mnemonics,
+            state,
+            chain,
+            app,
+        }
+    }
+
+    /// The key of cosigner `account` for the config at `index`.
+    fn signer(&self, account: usize, index: u32) -> SecpSigner {
+        let path = ckb_path(account as u32, index).unwrap();
+        SecpSigner::from_mnemonic(&self.mnemonics[account], "", &path).unwrap()
+    }
+
+    fn lock(&self, index: u32) -> Script {
+        let config = self.state.settings.config(index).unwrap();
+        self.state.settings.lock_script(&config)
+    }
+
+    async fn call(&self, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+        let (status, body) = self.call_text(method, uri, body).await;
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    async fn call_text(&self, method: &str, uri: &str, body: Value) -> (StatusCode, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(if body.is_null() {
+                String::new()
+            } else {
+                body.to_string()
+            }))
+            .unwrap();
+        let response = self.app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    async fn metric(&self, name: &str) -> f64 {
+        let (_, metrics) = self.call_text("GET", "/metrics", Value::Null).await;
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("no metric {}", name))
+            .parse()
+            .unwrap()
+    }
+}
+
+fn recipient() -> String {
+    let lock = Script::new_builder()
+        .code_hash(H256([0x99; 32]).pack())
+        .hash_type(ScriptHashType::Type)
+        .args(Bytes::from(vec![1u8; 20]).pack())
+        .build();
+    Address::new(NetworkType::Testnet, AddressPayload::from(lock), true).to_string()
+}
+
+#[tokio::test]
+async fn test_addresses() {
+    let setup = Setup::new();
+    let (status, alice) = setup
+        .call("POST", "/users/alice/address", Value::Null)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(alice["index"], 1);
+    assert!(alice["address"].as_str().unwrap().starts_with("ckt1"));
+    let config = setup.state.settings.config(1).unwrap();
+    assert_eq!(
+        alice["lock_args"],
+        format!("0x{}", hex::encode(config.lock_args()))
+    );
+
+    // the same address on every call, a new one per user
+    let (_, again) = setup
+        .call("POST", "/users/alice/address", Value::Null)
+        .await;
+    assert_eq!(again, alice);
+    let (_, bob) = setup.call("POST", "/users/bob/address", Value::Null).await;
+    assert_eq!(bob["index"], 2);
+    assert_ne!(bob["address"], alice["address"]);
+
+    let (status, _) = setup
+        .call("POST", "/users/not%20valid/address", Value::Null)
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = setup.call("GET", "/users/carol", Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_deposits() {
+    let setup = Setup::new();
+    setup
+        .call("POST", "/users/alice/address", Value::Null)
+        .await;
+    setup.call("POST", "/users/bob/address", Value::Null).await;
+    setup.chain.deposit(&setup.lock(1), 500 * ONE_CKB, 10);
+    setup.chain.deposit(&setup.lock(1), 70 * ONE_CKB, 12);
+    setup.chain.deposit(&setup.lock(2), 80 * ONE_CKB, 12);
+    // to the treasury, nobody's
+    setup.chain.deposit(&setup.lock(0), 90 * ONE_CKB, 12);
+
+    let credited = deposits::poll(&setup.state).await.unwrap();
+    assert_eq!(credited.len(), 3);
+    assert!(deposits::poll(&setup.state).await.unwrap().is_empty());
+
+    let (status, alice) = setup.call("GET", "/users/alice", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(alice["balance"], 570 * ONE_CKB);
+    assert_eq!(alice["deposits"].as_array().unwrap().len(), 2);
+    let (_, bob) = setup.call("GET", "/users/bob", Value::Null).await;
+    assert_eq!(bob["balance"], 80 * ONE_CKB);
+    assert_eq!(setup.state.store.account("alice").unwrap().next_block, 100);
+}
+
+#[tokio::test]
+async fn test_withdrawal() {
+    let setup = Setup::new();
+    setup
+        .call("POST", "/users/alice/address", Value::Null)
+        .await;
+    setup.chain.deposit(&setup.lock(1), 500 * ONE_CKB, 10);
+    deposits::poll(&setup.state).await.unwrap();
+    setup.chain.fund(&setup.lock(0), 10_000 * ONE_CKB);
+
+    let (status, _) = setup
+        .call(
+            "POST",
+            "/withdrawals",
+            json!({ "user": "alice", "to": recipient(), "amount": "501" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let mainnet = Address::new(
+        NetworkType::Mainnet,
+        AddressPayload::from(setup.lock(1)),
+        true,
+    );
+    let (status, _) = setup
+        .call(
+            "POST",
+            "/withdrawals",
+            json!({ "user": "alice", "to": mainnet.to_string(), "amount": "1" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, summary) = setup
+        .call(
+            "POST",
+            "/withdrawals",
+            json!({ "user": "alice", "to": recipient(), "amount": "300" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(summary["amount"], 300 * ONE_CKB);
+    assert_eq!(summary["complete"], false);
+    let id = summary["id"].as_str().unwrap().to_string();
+    let (_, alice) = setup.call("GET", "/users/alice", Value::Null).await;
+    assert_eq!(alice["balance"], 200 * ONE_CKB);
+
+    let (status, request) = setup
+        .call("GET", &format!("/withdrawals/{}", id), Value::Null)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let request = SigningRequest::from_json(&request.to_string()).unwrap();
+    assert_eq!(request.config, setup.state.settings.config(0).unwrap());
+    assert_eq!(request.genesis_hash, Some(GENESIS_HASH));
+    let message = request.message().unwrap();
+    assert_eq!(summary["message"], format!("0x{}", hex::encode(message)));
+
+    let signature = |account| {
+        format!(
+            "0x{}",
+            hex::encode(setup.signer(account, 0).sign(&message).unwrap())
+        )
+    };
+    let uri = format!("/withdrawals/{}/signatures", id);
+    let (status, summary) = setup
+        .call("POST", &uri, json!({ "signatures": [signature(0)] }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["complete"], false);
+    assert!(setup.chain.sent.lock().unwrap().is_empty());
+
+    // the node refuses the complete transaction, retried without signatures
+    *setup.chain.refuse.lock().unwrap() = true;
+    let (status, _) = setup
+        .call("POST", &uri, json!({ "signatures": [signature(2)] }))
+        .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    *setup.chain.refuse.lock().unwrap() = false;
+    let (status, summary) = setup.call("POST", &uri, json!({ "signatures": [] })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["complete"], true);
+    assert_eq!(summary["sent"], id);
+    assert_eq!(setup.chain.sent.lock().unwrap().len(), 1);
+
+    let (status, _) = setup
+        .call("POST", &uri, json!({ "signatures": [signature(1)] }))
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    assert_eq!(
+        setup.metric("ckb_multisig_custody_withdrawals_total").await,
+        1.0
+    );
+    assert_eq!(
+        setup
+            .metric("ckb_multisig_custody_withdrawals_pending")
+            .await,
+        0.0
+    );
+    assert_eq!(
+        setup.metric("ckb_multisig_custody_signatures_total").await,
+        2.0
+    );
+    assert_eq!(
+        setup
+            .metric("ckb_multisig_custody_threshold_seconds_count")
+            .await,
+        1.0
+    );
+    assert_eq!(
+        setup.metric("ckb_multisig_custody_broadcasts_total").await,
+        1.0
+    );
+    assert_eq!(
+        setup
+            .metric("ckb_multisig_custody_broadcast_failures_total")
+            .await,
+        1.0
+    );
+    assert_eq!(
+        setup.metric("ckb_multisig_custody_deposits_total").await,
+        1.0
+    );
+}
+
+#[tokio::test]
+async fn test_store_reopens() {
+    let dir = temp_dir();
+    let store = Store::open(&dir).unwrap();
+    let alice = store.open_account("alice").unwrap();
+    store.open_account("bob").unwrap();
+    drop(store);
+    let store = Store::open(&dir).unwrap();
+    assert_eq!(store.account("alice").unwrap(), alice);
+    assert_eq!(store.open_account("carol").unwrap().index, 3);
+}
@@ -0,0 +1,139 @@
+#ifndef CKB_MULTISIG_H
+#define CKB_MULTISIG_H
+
+/* Generated by cbindgen from ffi/src/lib.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Bumped on every incompatible change of the interface.
+#define CKB_MULTISIG_ABI_VERSION 1
+
+typedef enum CkbMultisigStatus {
+  CKB_MULTISIG_STATUS_OK = 0,
+  // A required pointer is null.
+  CKB_MULTISIG_STATUS_NULL_POINTER = 1,
+  // `out_len` holds the required length.
+  CKB_MULTISIG_STATUS_BUFFER_TOO_SMALL = 2,
+  CKB_MULTISIG_STATUS_INVALID_CONFIG = 3,
+  CKB_MULTISIG_STATUS_INVALID_TRANSACTION = 4,
+  CKB_MULTISIG_STATUS_INVALID_WITNESS = 5,
+  CKB_MULTISIG_STATUS_INVALID_SIGNATURE = 6,
+  CKB_MULTISIG_STATUS_TOO_MANY_SIGNATURES = 7,
+  // The signatures don't unlock the script group.
+  CKB_MULTISIG_STATUS_VERIFICATION_FAILED = 8,
+  CKB_MULTISIG_STATUS_INVALID_PARAMETER = 9,
+} CkbMultisigStatus;
+
+// The version of the interface implemented by the library.
+uint32_t ckb_multisig_abi_version(void);
+
+// Copy the message of the last failure of the calling thread into `out`, NUL
+// terminated and truncated to `out_len` bytes. Returns the length of the
+// whole message, without the NUL.
+//
+// # Safety
+//
+// `out` must be null or valid for `out_len` bytes.
+size_t ckb_multisig_last_error(char *out, size_t out_len);
+
+// The multisig script of a config, `S | R | M | N | pubkey hashes`.
+//
+// # Safety
+//
+// `pubkey_hashes` must be valid for `keys * 20` bytes, `out` for `*out_len`
+// bytes.
+enum CkbMultisigStatus ckb_multisig_multisig_script(const uint8_t *pubkey_hashes,
+                                                    size_t keys,
+                                                    uint8_t require_first_n,
+                                                    uint8_t threshold,
+                                                    uint8_t *out,
+                                                    size_t *out_len);
+
+// The lock args of a config, with the 8 bytes little endian `since` when it
+// isn't null.
+//
+// # Safety
+//
+// `pubkey_hashes` must be valid for `keys * 20` bytes, `since` null or
+// valid, `out` valid for `*out_len` bytes.
+enum CkbMultisigStatus ckb_multisig_lock_args(const uint8_t *pubkey_hashes,
+                                              size_t keys,
+                                              uint8_t require_first_n,
+                                              uint8_t threshold,
+                                              const uint64_t *since,
+                                              uint8_t *out,
+                                              size_t *out_len);
+
+// The unsigned lock field of the config of `multisig_script`, to put in the
+// first witness of the script group before computing the digest.
+//
+// # Safety
+//
+// `multisig_script` must be valid for `script_len` bytes, `out` for
+// `*out_len` bytes.
+enum CkbMultisigStatus ckb_multisig_placeholder_lock(const uint8_t *multisig_script,
+                                                     size_t script_len,
+                                                     uint8_t *out,
+                                                     size_t *out_len);
+
+// The 32 bytes message the cosigners sign, the first witness of the script
+// group must carry a lock field with the multisig script.
+//
+// # Safety
+//
+// `tx` must be valid for `tx_len` bytes, `input_indices` for `indices_len`
+// indices and `out` for 32 bytes.
+enum CkbMultisigStatus ckb_multisig_digest(const uint8_t *tx,
+                                           size_t tx_len,
+                                           const size_t *input_indices,
+                                           size_t indices_len,
+                                           uint8_t *out);
+
+// The lock field of the config of `multisig_script` holding `signatures`,
+// the remaining slots are left empty.
+//
+// # Safety
+//
+// `multisig_script` must be valid for `script_len` bytes, `signatures` for
+// `signatures_count * 65` bytes, `out` for `*out_len` bytes.
+enum CkbMultisigStatus ckb_multisig_assemble_lock(const uint8_t *multisig_script,
+                                                  size_t script_len,
+                                                  const uint8_t *signatures,
+                                                  size_t signatures_count,
+                                                  uint8_t *out,
+                                                  size_t *out_len);
+
+// The transaction with `lock` as the lock field of witness `index`, the
+// other fields of the witness are kept.
+//
+// # Safety
+//
+// `tx` must be valid for `tx_len` bytes, `lock` for `lock_len` bytes and
+// `out` for `*out_len` bytes.
+enum CkbMultisigStatus ckb_multisig_set_lock(const uint8_t *tx,
+                                             size_t tx_len,
+                                             size_t index,
+                                             const uint8_t *lock,
+                                             size_t lock_len,
+                                             uint8_t *out,
+                                             size_t *out_len);
+
+// Check the script group is unlocked by the config of `multisig_script`,
+// the way the contract does. Returns `CKB_MULTISIG_STATUS_OK` when it is.
+//
+// # Safety
+//
+// `tx` must be valid for `tx_len` bytes, `input_indices` for `indices_len`
+// indices and `multisig_script` for `script_len` bytes.
+enum CkbMultisigStatus ckb_multisig_verify(const uint8_t *tx,
+                                           size_t tx_len,
+                                           const size_t *input_indices,
+                                           size_t indices_len,
+                                           const uint8_t *multisig_script,
+                                           size_t script_len);
+
+#endif  /* CKB_MULTISIG_H */
@@ -0,0 +1,357 @@
+//! `extern "C"` interface of the SDK core operations, for custody systems in
+//! other languages linking against the reference implementation.
+//!
+//! The header is `include/ckb_multisig.h`, regenerate it after changing this
+//! file with:
+//!
+//! ``` sh
+//! cbindgen --config ffi/cbindgen.toml --crate ckb-multisig-ffi --output ffi/include/ckb_multisig.h
+//! ```
+//!
+//! Conventions:
+//!
+//! * transactions are molecule serialized `Transaction`s, as in the `tx`
+//!   field of a block;
+//! * pubkey hashes are the 20 bytes blake160 of the compressed public keys,
+//!   concatenated in the order of the multisig script, signatures the 65
+//!   bytes `r | s | recid`, concatenated as well;
+//! * output buffers come with `out_len`, the buffer capacity on entry and the
+//!   written length on return. When the buffer is too small the call returns
+//!   `CKB_MULTISIG_STATUS_BUFFER_TOO_SMALL` with the required length in
+//!   `out_len`, so a first call with a zero capacity gives the size;
+//! * every call returns a status, `ckb_multisig_last_error` describes the
+//!   last failure of the calling thread.
+//!
+//! The interface is versioned by `ckb_multisig_abi_version`, a change to an
+//! existing signature or status bumps it.
+
+use std::{cell::RefCell, os::raw::c_char, slice};
+
+use ckb_multisig_sdk::{
+    constants::{BLAKE160_SIZE, DIGEST_SIZE, SIGNATURE_SIZE},
+    digest::generate_message,
+    witness::{set_witness_lock, witness_args},
+    Error, MultisigConfig, MultisigLock,
+};
+use ckb_types::{core::TransactionView, packed, prelude::*};
+
+#[cfg(test)]
+mod tests;
+
+/// Bumped on every incompatible change of the interface.
+pub const CKB_MULTISIG_ABI_VERSION: u32 = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CkbMultisigStatus {
+    Ok = 0,
+    /// A required pointer is null.
+    NullPointer = 1,
+    /// `out_len` holds the required length.
+    BufferTooSmall = 2,
+    InvalidConfig = 3,
+    InvalidTransaction = 4,
+    InvalidWitness = 5,
+    InvalidSignature = 6,
+    TooManySignatures = 7,
+    /// The signatures don't unlock the script group.
+    VerificationFailed = 8,
+    InvalidParameter = 9,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+struct Failure(CkbMultisigStatus, String);
+
+impl From<Error> for Failure {
+    fn from(err: Error) -> Self {
+        let status = match err {
+            Error::InvalidConfig(_) => CkbMultisigStatus::InvalidConfig,
+            Error::InvalidWitness(_) => CkbMultisigStatus::InvalidWitness,
+            Error::Secp256k1(_) | Error::SignatureMismatch => CkbMultisigStatus::InvalidSignature,
+            Error::TooManySignatures => CkbMultisigStatus::TooManySignatures,
+            Error::Verification(_) => CkbMultisigStatus::VerificationFailed,
+            _ => CkbMultisigStatus::InvalidParameter,
+        };
+        Failure(status, err.to_string())
+    }
+}
+
+/// The version of the interface implemented by the library.
+#[no_mangle]
+pub extern "C" fn ckb_multisig_abi_version() -> u32 {
+    CKB_MULTISIG_ABI_VERSION
+}
+
+/// Copy the message of the last failure of the calling thread into `out`, NUL
+/// terminated and truncated to `out_len` bytes. Returns the length of the
+/// whole message, without the NUL.
+///
+/// # Safety
+///
+/// `out` must be null or valid for `out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ckb_multisig_last_error(out: *mut c_char, out_len: usize) -> usize {
+    LAST_ERROR.with(|last| {
+        let last = last.borrow();
+        if !out.is_null() && out_len > 0 {
+            let len = last.len().min(out_len - 1);
+            std::ptr::copy_nonoverlapping(last.as_ptr() as *const c_char, out, len);
+            *out.add(len) = 0;
+        }
+        last.len()
+    })
+}
+
+/// The multisig script of a config, `S | R | M | N | pubkey hashes`.
+///
+/// # Safety
+///
+/// `pubkey_hashes` must be valid for `keys * 20` bytes, `out` for `*out_len`
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ckb_multisig_multisig_script(
+    pubkey_hashes: *const u8,
+    keys: usize,
+    require_first_n: u8,
+    threshold: u8,
+    out: *mut u8,
+    out_len: *mut usize,
+) -> CkbMultisigStatus {
+    run(|| {
+        let config = config(pubkey_hashes, keys, require_first_n, threshold)?;
+        write(&config.multisig_script(), out, out_len)
+    })
+}
+
+/// The lock args of a config, with the 8 bytes little endian `since` when it
+/// isn't null.
+///
+/// # Safety
+///
+/// `pubkey_hashes` must be valid for `keys * 20` bytes, `since` null or
+/// valid, `out` valid for `*out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ckb_multisig_lock_args(
+    pubkey_hashes: *const u8,
+    keys: usize,
+    require_first_n: u8,
+    threshold: u8,
+    since: *const u64,
+    out: *mut u8,
+    out_len: *mut usize,
+) -> CkbMultisigStatus {
+    run(|| {
+        let since = if since.is_null() { None } else { Some(*since) };
+        let config = config(pubkey_hashes, keys, require_first_n, threshold)?.with_since(since);
+        write(&config.lock_args(), out, out_len)
+    })
+}
+
+/// The unsigned lock field of the config of `multisig_script`, to put in the
+/// first witness of the script group before computing the digest.
+///
+/// # Safety
+///
+/// `multisig_script` must be valid for `script_len` bytes, `out` for
+/// `*out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ckb_multisig_placeholder_lock(
+    multisig_script: *const u8,
+    script_len: usize,
+    out: *mut u8,
+    out_len: *mut usize,
+) -> CkbMultisigStatus {
+    run(|| {
+        let config = MultisigConfig::from_multisig_script(input(multisig_script, script_len)?)?;
+        write(&config.placeholder_lock(), out, out_len)
+    })
+}
+
+/// The 32 bytes message the cosigners sign, the first witness of the script
+/// group must carry a lock field with the multisig script.
+///
+/// # Safety
+///
+/// `tx` must be valid for `tx_len` bytes, `input_indices` for `indices_len`
+/// indices and `out` for 32 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ckb_multisig_digest(
+    tx: *const u8,
+    tx_len: usize,
+    input_indices: *const usize,
+    indices_len: usize,
+    out: *mut u8,
+) -> CkbMultisigStatus {
+    run(|| {
+        let tx = transaction(tx, tx_len)?;
+        let message = generate_message(&tx, input(input_indices, indices_len)?)?;
+        let mut len = DIGEST_SIZE;
+        write(&message, out, &mut len)
+    })
+}
+
+/// The lock field of the config of `multisig_script` holding `signatures`,
+/// the remaining slots are left empty.
+///
+/// # Safety
+///
+/// `multisig_script` must be valid for `script_len` bytes, `signatures` for
+/// `signatures_count * 65` bytes, `out` for `*out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ckb_multisig_assemble_lock(
+    multisig_script: *const u8,
+    script_len: usize,
+    signatures: *const u8,
+    signatures_count: usize,
+    out: *mut u8,
+    out_len: *mut usize,
+) -> CkbMultisigStatus {
+    run(|| {
+        let config = MultisigConfig::from_multisig_script(input(multisig_script, script_len)?)?;
+        let mut lock = MultisigLock::new(config);
+        let signatures = input(signatures, signatures_count * SIGNATURE_SIZE)?;
+        for chunk in signatures.chunks(SIGNATURE_SIZE) {
+            let mut signature = [0u8; SIGNATURE_SIZE];
+            signature.copy_from_slice(chunk);
+            lock.add_signature(signature)?;
+        }
+        write(&lock.to_bytes(), out, out_len)
+    })
+}
+
+/// The transaction with `lock` as the lock field of witness `index`, the
+/// other fields of the witness are kept.
+///
+/// # Safety
+///
+/// `tx` must be valid for `tx_len` bytes, `lock` for `lock_len` bytes and
+/// `out` for `*out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ckb_multisig_set_lock(
+    tx: *const u8,
+    tx_len: usize,
+    index: usize,
+    lock: *const u8,
+    lock_len: usize,
+    out: *mut u8,
+    out_len: *mut usize,
+) -> CkbMultisigStatus {
+    run(|| {
+        let tx = transaction(tx, tx_len)?;
+        let lock = MultisigLock::parse(input(lock, lock_len)?)?;
+        let tx = set_witness_lock(&tx, index, &lock)?;
+        write(tx.data().as_slice(), out, out_len)
+    })
+}
+
+/// Check the script group is unlocked by the config of `multisig_script`,
+/// the way the contract does. Returns `CKB_MULTISIG_STATUS_OK` when it is.
+///
+/// # Safety
+///
+/// `tx` must be valid for `tx_len` bytes, `input_indices` for `indices_len`
+/// indices and `multisig_script` for `script_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ckb_multisig_verify(
+    tx: *const u8,
+    tx_len: usize,
+    input_indices: *const usize,
+    indices_len: usize,
+    multisig_script: *const u8,
+    script_len: usize,
+) -> CkbMultisigStatus {
+    run(|| {
+        let tx = transaction(tx, tx_len)?;
+        let input_indices = input(input_indices, indices_len)?;
+        let config = MultisigConfig::from_multisig_script(input(multisig_script, script_len)?)?;
+        let first = *input_indices.first().ok_or_else(|| {
+            Failure(
+                CkbMultisigStatus::InvalidParameter,
+                "empty script group".to_string(),
+            )
+        })?;
+        let lock = witness_args(&tx, first)?.lock().to_opt().ok_or_else(|| {
+            Error::InvalidWitness(format!("witness #{} has no lock field", first))
+        })?;
+        let lock = MultisigLock::parse(&lock.raw_data())?;
+        if lock.config().multisig_script() != config.multisig_script() {
+            return Err(
+                Error::InvalidWitness("the witness belongs to another config".to_string()).into(),
+            );
+        }
+        lock.verify(&generate_message(&tx, input_indices)?)?;
+        Ok(())
+    })
+}
+
+fn run<F: FnOnce() -> Result<(), Failure>>(f: F) -> CkbMultisigStatus {
+    match f() {
+        Ok(()) => CkbMultisigStatus::Ok,
+        Err(Failure(status, message)) => {
+            LAST_ERROR.with(|last| *last.borrow_mut() = message);
+            status
+        }
+    }
+}
+
+unsafe fn input<'a, T>(ptr: *const T, len: usize) -> Result<&'a [T], Failure> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if ptr.is_null() {
+        return Err(null_pointer());
+    }
+    Ok(slice::from_raw_parts(ptr, len))
+}
+
+unsafe fn write(data: &[u8], out: *mut u8, out_len: *mut usize) -> Result<(), Failure> {
+    if out_len.is_null() {
+        return Err(null_pointer());
+    }
+    let capacity = *out_len;
+    *out_len = data.len();
+    if capacity < data.len() {
+        return Err(Failure(
+            CkbMultisigStatus::BufferTooSmall,
+            format!("{} bytes needed, the buffer has {}", data.len(), capacity),
+        ));
+    }
+    if out.is_null() {
+        return Err(null_pointer());
+    }
+    std::ptr::copy_nonoverlapping(data.as_ptr(), out, data.len());
+    Ok(())
+}
+
+unsafe fn config(
+    pubkey_hashes: *const u8,
+    keys: usize,
+    require_first_n: u8,
+    threshold: u8,
+) -> Result<MultisigConfig, Failure> {
+    let hashes = input(pubkey_hashes, keys * BLAKE160_SIZE)?
+        .chunks(BLAKE160_SIZE)
+        .map(|chunk| {
+            let mut hash = [0u8; BLAKE160_SIZE];
+            hash.copy_from_slice(chunk);
+            hash
+        })
+        .collect();
+    Ok(MultisigConfig::new(hashes, require_first_n, threshold)?)
+}
+
+unsafe fn transaction(tx: *const u8, tx_len: usize) -> Result<TransactionView, Failure> {
+    let tx = packed::Transaction::from_slice(input(tx, tx_len)?)
+        .map_err(|err| Failure(CkbMultisigStatus::InvalidTransaction, err.to_string()))?;
+    Ok(tx.into_view())
+}
+
+fn null_pointer() -> Failure {
+    Failure(
+        CkbMultisigStatus::NullPointer,
+        "a required pointer is null".to_string(),
+    )
+}
@@ -0,0 +1,150 @@
+use ckb_multisig_sdk::{SecpSigner, Signer};
+use ckb_types::{
+    bytes::Bytes,
+    core::TransactionBuilder,
+    packed::{CellInput, CellOutput, OutPoint},
+};
+use secp256k1::{rand, SecretKey};
+
+use super::*;
+
+fn signers(count: usize) -> Vec<SecpSigner> {
+    (0..count)
+        .map(|_| SecpSigner::new(SecretKey::new(&mut rand::thread_rng())))
+        .collect()
+}
+
+fn hashes(signers: &[SecpSigner]) -> Vec<u8> {
+    signers
+        .iter()
+        .flat_map(|signer| signer.identity().unwrap().to_vec())
+        .collect()
+}
+
+/// Call `f` with a zero capacity first to learn the length.
+fn output<F: Fn(*mut u8, *mut usize) -> CkbMultisigStatus>(f: F) -> Vec<u8> {
+    let mut len = 0;
+    assert_eq!(
+        f(std::ptr::null_mut(), &mut len),
+        CkbMultisigStatus::BufferTooSmall
+    );
+    let mut out = vec![0u8; len];
+    assert_eq!(f(out.as_mut_ptr(), &mut len), CkbMultisigStatus::Ok);
+    out
+}
+
+fn last_error() -> String {
+    let mut buf = [0 as c_char; 256];
+    let len = unsafe { ckb_multisig_last_error(buf.as_mut_ptr(), buf.len()) };
+    let bytes: Vec<u8> = buf[..len.min(255)].iter().map(|c| *c as u8).collect();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn test_sign_and_verify() {
+    let signers = signers(3);
+    let hashes = hashes(&signers);
+    let script = output(|out, len| unsafe {
+        ckb_multisig_multisig_script(hashes.as_ptr(), 3, 0, 2, out, len)
+    });
+    let since = 42u64;
+    let args = output(|out, len| unsafe {
+        ckb_multisig_lock_args(hashes.as_ptr(), 3, 0, 2, &since, out, len)
+    });
+    assert_eq!(args.len(), 28);
+    assert_eq!(args[20..], since.to_le_bytes());
+
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new([1u8; 32].pack(), 0), 0))
+        .output(CellOutput::new_builder().build())
+        .output_data(Bytes::new().pack())
+        .build();
+    let tx = tx.data().as_slice().to_vec();
+    let placeholder = output(|out, len| unsafe {
+        ckb_multisig_placeholder_lock(script.as_ptr(), script.len(), out, len)
+    });
+    let tx = output(|out, len| unsafe {
+        ckb_multisig_set_lock(
+            tx.as_ptr(),
+            tx.len(),
+            0,
+            placeholder.as_ptr(),
+            placeholder.len(),
+            out,
+            len,
+        )
+    });
+    let indices = [0usize];
+    let mut digest = [0u8; DIGEST_SIZE];
+    let status = unsafe {
+        ckb_multisig_digest(
+            tx.as_ptr(),
+            tx.len(),
+            indices.as_ptr(),
+            1,
+            digest.as_mut_ptr(),
+        )
+    };
+    assert_eq!(status, CkbMultisigStatus::Ok);
+
+    let signatures: Vec<u8> = signers[1..]
+        .iter()
+        .flat_map(|signer| signer.sign(&digest).unwrap().to_vec())
+        .collect();
+    let lock = output(|out, len| unsafe {
+        ckb_multisig_assemble_lock(
+            script.as_ptr(),
+            script.len(),
+            signatures.as_ptr(),
+            2,
+            out,
+            len,
+        )
+    });
+    let unsigned = tx.clone();
+    let tx = output(|out, len| unsafe {
+        ckb_multisig_set_lock(
+            tx.as_ptr(),
+            tx.len(),
+            0,
+            lock.as_ptr(),
+            lock.len(),
+            out,
+            len,
+        )
+    });
+    let verify = |tx: &[u8]| unsafe {
+        ckb_multisig_verify(
+            tx.as_ptr(),
+            tx.len(),
+            indices.as_ptr(),
+            1,
+            script.as_ptr(),
+            script.len(),
+        )
+    };
+    assert_eq!(verify(&tx), CkbMultisigStatus::Ok);
+    assert_eq!(verify(&unsigned), CkbMultisigStatus::VerificationFailed);
+    assert!(last_error().contains("0 of 2 signatures"));
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(ckb_multisig_abi_version(), CKB_MULTISIG_ABI_VERSION);
+    let mut len = 64;
+    let mut out = [0u8; 64];
+    let status = unsafe {
+        ckb_multisig_multisig_script(std::ptr::null(), 2, 0, 1, out.as_mut_ptr(), &mut len)
+    };
+    assert_eq!(status, CkbMultisigStatus::NullPointer);
+    let hashes = [0u8; 40];
+    let status = unsafe {
+        ckb_multisig_multisig_script(hashes.as_ptr(), 2, 0, 3, out.as_mut_ptr(), &mut len)
+    };
+    assert_eq!(status, CkbMultisigStatus::InvalidConfig);
+    let mut digest = [0u8; DIGEST_SIZE];
+    let status = unsafe {
+        ckb_multisig_digest([1u8].as_ptr(), 1, [0usize].as_ptr(), 1, digest.as_mut_ptr())
+    };
+    assert_eq!(status, CkbMultisigStatus::InvalidTransaction);
+}
@@ -1,6 +1,7 @@
 //mod dao;
 mod secp256k1_blake160_multisig_all;
 //mod secp256k1_blake160_sighash_all;
+mod spec;
 
 use ckb_crypto::secp::Privkey;
 use ckb_script::DataLoader;
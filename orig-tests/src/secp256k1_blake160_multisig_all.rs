@@ -14,19 +14,31 @@ use ckb_types::{
     prelude::*,
     H256,
 };
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+use std::collections::HashMap;
 
 const SIGNATURE_SIZE: usize = 65;
+const CONFIG_V2: u8 = 0x80;
+const CONFIG_PROOF: u8 = 0x81;
 
+const ERROR_ENCODING: i8 = 4;
 const ERROR_WITNESS_SIZE: i8 = -22;
 const ERROR_INVALID_PUBKEYS_CNT: i8 = -42;
 const ERROR_INVALID_THRESHOLD: i8 = -43;
 const ERROR_INVALID_REQUIRE_FIRST_N: i8 = -44;
+const ERROR_CONFIG_PROOF: i8 = -46;
 const ERROR_MULTSIG_SCRIPT_HASH: i8 = -51;
 const ERROR_VERIFICATION: i8 = -52;
 const ERROR_INCORRECT_SINCE_FLAG: i8 = -23;
 const ERROR_INCORRECT_SINCE_VALUE: i8 = -24;
 
+/// Cycles limit of a mainnet block, which bounds a transaction too: the
+/// transactions over the 70M cycles verified at once by the pool are
+/// verified in the background up to it.
+const MAX_BLOCK_CYCLES: u64 = 3_500_000_000;
+/// The deepest config tree, 256 configs.
+const MAX_PROOF_DEPTH: usize = 8;
+
 #[test]
 fn test_multisig_script_hash() {
     let mut data_loader = DummyDataLoader::new();
@@ -253,6 +265,57 @@ fn test_multisig_1_2_3_with_multiple_inputs_unlock() {
     }
 }
 
+#[test]
+fn test_multisig_legacy_witness_layout() {
+    let keys = generate_keys(3);
+    let multi_sign_script = gen_multi_sign_script(&keys, 2, 1);
+    let legacy_args = |flags: u8| {
+        let mut args = blake160(&multi_sign_script).to_vec();
+        args.extend_from_slice(&[0; 8]);
+        args.push(flags);
+        Bytes::from(args)
+    };
+    // signed the usual way, the lock field first, then moved after the
+    // empty witness as older wallets lay it out: the message is the same
+    let layouts = |data_loader: &mut DummyDataLoader, args: Bytes| {
+        let tx = gen_tx_with_extra_inputs(data_loader, args, 1)
+            .as_advanced_builder()
+            .set_witnesses(vec![
+                WitnessArgs::new_builder().build().as_bytes().pack(),
+                Bytes::new().pack(),
+            ])
+            .build();
+        let tx = multi_sign_tx(tx, &multi_sign_script, &[&keys[0], &keys[1]]);
+        let moved = tx
+            .as_advanced_builder()
+            .set_witnesses(vec![Bytes::new().pack(), tx.witnesses().get(0).unwrap()])
+            .build();
+        (tx, moved)
+    };
+    {
+        let mut data_loader = DummyDataLoader::new();
+        let (tx, moved) = layouts(&mut data_loader, legacy_args(0b10000));
+        verify(&data_loader, &tx).expect("pass verification");
+        verify(&data_loader, &moved).expect("pass verification");
+    }
+    {
+        // under strict witnesses too, the empty witness being a placeholder
+        let mut data_loader = DummyDataLoader::new();
+        let (_, moved) = layouts(&mut data_loader, legacy_args(0b10010));
+        verify(&data_loader, &moved).expect("pass verification");
+    }
+    {
+        let mut data_loader = DummyDataLoader::new();
+        let (tx, moved) = layouts(&mut data_loader, legacy_args(0));
+        verify(&data_loader, &tx).expect("pass verification");
+        let verify_result = verify(&data_loader, &moved);
+        assert_error_eq!(
+            verify_result.unwrap_err(),
+            ScriptError::ValidationFailure(ERROR_ENCODING),
+        );
+    }
+}
+
 #[test]
 fn test_multisig_0_1_1_unlock() {
     let mut data_loader = DummyDataLoader::new();
@@ -297,6 +360,381 @@ fn test_multisig_0_2_2_unlock() {
     }
 }
 
+#[test]
+fn test_multisig_config_v2_unlock() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let config = gen_config_v2(&keys, 2, 1);
+    let args = blake160(&config);
+    let raw_tx = gen_tx(&mut data_loader, args);
+    let mut lock_prefix = vec![CONFIG_V2];
+    lock_prefix.extend_from_slice(&config);
+    let lock_prefix = Bytes::from(lock_prefix);
+    {
+        let tx = multi_sign_tx(raw_tx.clone(), &lock_prefix, &[&keys[2], &keys[0]]);
+        verify(&data_loader, &tx).expect("pass verification");
+    }
+    {
+        let tx = multi_sign_tx(raw_tx.clone(), &lock_prefix, &[&keys[1], &keys[2]]);
+        let verify_result = verify(&data_loader, &tx);
+        assert_error_eq!(
+            verify_result.unwrap_err(),
+            ScriptError::ValidationFailure(ERROR_VERIFICATION),
+        );
+    }
+    // the multisig script of the same config doesn't match the args
+    {
+        let script = gen_multi_sign_script(&keys, 2, 1);
+        let tx = multi_sign_tx(raw_tx.clone(), &script, &[&keys[0], &keys[1]]);
+        let verify_result = verify(&data_loader, &tx);
+        assert_error_eq!(
+            verify_result.unwrap_err(),
+            ScriptError::ValidationFailure(ERROR_MULTSIG_SCRIPT_HASH),
+        );
+    }
+    // a table header which isn't canonical is refused before hashing
+    {
+        let mut lock_prefix = lock_prefix.to_vec();
+        lock_prefix[1 + 4] = 24;
+        let tx = multi_sign_tx(raw_tx, &lock_prefix.into(), &[&keys[0], &keys[1]]);
+        let verify_result = verify(&data_loader, &tx);
+        assert_error_eq!(
+            verify_result.unwrap_err(),
+            ScriptError::ValidationFailure(ERROR_ENCODING),
+        );
+    }
+}
+
+#[test]
+fn test_multisig_config_tree_unlock() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(4);
+    let ops = gen_multi_sign_script(&keys[..3], 2, 0);
+    let recovery = gen_multi_sign_script(&keys[3..], 1, 0);
+    let (ops_leaf, recovery_leaf) = (tree_hash(0, &[&ops[..]]), tree_hash(0, &[&recovery[..]]));
+    let root = tree_hash(1, &[&ops_leaf, &recovery_leaf]);
+    let raw_tx = gen_tx(&mut data_loader, Bytes::copy_from_slice(&root[..20]));
+    let with_proof = |path: u8, sibling: &[u8], script: &Bytes| -> Bytes {
+        let mut lock = vec![CONFIG_PROOF, 1, path];
+        lock.extend_from_slice(sibling);
+        lock.extend_from_slice(script);
+        lock.into()
+    };
+    {
+        let lock_prefix = with_proof(0, &recovery_leaf, &ops);
+        let tx = multi_sign_tx(raw_tx.clone(), &lock_prefix, &[&keys[0], &keys[1]]);
+        verify(&data_loader, &tx).expect("pass verification");
+    }
+    {
+        let lock_prefix = with_proof(1, &ops_leaf, &recovery);
+        let tx = multi_sign_tx(raw_tx.clone(), &lock_prefix, &[&keys[3]]);
+        verify(&data_loader, &tx).expect("pass verification");
+    }
+    // the wrong side
+    {
+        let lock_prefix = with_proof(1, &recovery_leaf, &ops);
+        let tx = multi_sign_tx(raw_tx.clone(), &lock_prefix, &[&keys[0], &keys[1]]);
+        let verify_result = verify(&data_loader, &tx);
+        assert_error_eq!(
+            verify_result.unwrap_err(),
+            ScriptError::ValidationFailure(ERROR_MULTSIG_SCRIPT_HASH),
+        );
+    }
+    // a path bit beyond the depth
+    {
+        let lock_prefix = with_proof(0b10, &recovery_leaf, &ops);
+        let tx = multi_sign_tx(raw_tx, &lock_prefix, &[&keys[0], &keys[1]]);
+        let verify_result = verify(&data_loader, &tx);
+        assert_error_eq!(
+            verify_result.unwrap_err(),
+            ScriptError::ValidationFailure(ERROR_CONFIG_PROOF),
+        );
+    }
+}
+
+/// The cycles of n of n configs, to compare builds of the secp256k1 library,
+/// e.g. with and without the endomorphism:
+///
+/// ```text
+/// cargo test test_cycles_per_signature -- --nocapture
+/// ```
+#[test]
+fn test_cycles_per_signature() {
+    let mut previous = None;
+    for n in 1..=5 {
+        let mut data_loader = DummyDataLoader::new();
+        let keys = generate_keys(n);
+        let multi_sign_script = gen_multi_sign_script(&keys, n as u8, 0);
+        let args = blake160(&multi_sign_script);
+        let raw_tx = gen_tx(&mut data_loader, args);
+        let signers: Vec<_> = keys.iter().collect();
+        let tx = multi_sign_tx(raw_tx, &multi_sign_script, &signers);
+        let cycles = verify(&data_loader, &tx).expect("pass verification");
+        match previous {
+            Some(previous) => {
+                assert!(cycles > previous);
+                println!(
+                    "{} of {}: {} cycles, {} for the last signature",
+                    n,
+                    n,
+                    cycles,
+                    cycles - previous
+                );
+            }
+            None => println!("{} of {}: {} cycles", n, n, cycles),
+        }
+        previous = Some(cycles);
+    }
+}
+
+/// The trade-off of the `embedded-tables` feature: the size of the binary
+/// against the cycles of a 2 of 3 signature. `MAX_BINARY_SIZE` and
+/// `MAX_2_OF_3_CYCLES` turn the figures into regression checks, e.g. with the
+/// ones of the last release of the deployed build.
+#[test]
+fn test_binary_size_and_cycles() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let multi_sign_script = gen_multi_sign_script(&keys, 2, 0);
+    let args = blake160(&multi_sign_script);
+    let raw_tx = gen_tx(&mut data_loader, args);
+    let tx = multi_sign_tx(raw_tx, &multi_sign_script, &[&keys[0], &keys[2]]);
+    let cycles = verify(&data_loader, &tx).expect("pass verification");
+    let size = MULTISIG_ALL_BIN.len();
+    println!("binary: {} bytes, 2 of 3: {} cycles", size, cycles);
+
+    let budget = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .map(|value| value.parse::<u64>().expect(name))
+    };
+    if let Some(max) = budget("MAX_BINARY_SIZE") {
+        assert!(size as u64 <= max, "binary of {} bytes over {}", size, max);
+    }
+    if let Some(max) = budget("MAX_2_OF_3_CYCLES") {
+        assert!(cycles <= max, "{} cycles over {}", cycles, max);
+    }
+}
+
+/// The largest configs the format allows are spendable: 255 of 255 keys,
+/// every one of them required, as a multisig script and as a molecule
+/// config at the bottom of the deepest tree, the largest lock field. Their
+/// cycles must fit in a mainnet block.
+#[test]
+fn test_max_configs_under_block_cycles() {
+    let keys = generate_keys(usize::from(u8::MAX));
+    let signers: Vec<_> = keys.iter().collect();
+    let run = |lock_args: Bytes, lock_prefix: &Bytes| {
+        let mut data_loader = DummyDataLoader::new();
+        let raw_tx = gen_tx(&mut data_loader, lock_args);
+        let tx = multi_sign_tx(raw_tx, lock_prefix, &signers);
+        let witness_size = tx.witnesses().get(0).unwrap().raw_data().len();
+        let cycles = verify(&data_loader, &tx).expect("pass verification");
+        (witness_size, cycles)
+    };
+
+    let script = gen_multi_sign_script(&keys, u8::MAX, u8::MAX);
+    let (witness_size, cycles) = run(blake160(&script), &script);
+    println!(
+        "255 of 255: {} bytes of witness, {} cycles",
+        witness_size, cycles
+    );
+    assert!(
+        cycles <= MAX_BLOCK_CYCLES,
+        "{} cycles over {}",
+        cycles,
+        MAX_BLOCK_CYCLES
+    );
+
+    let config = gen_config_v2(&keys, u8::MAX, u8::MAX);
+    let mut leaf_field = vec![CONFIG_V2];
+    leaf_field.extend_from_slice(&config);
+    let mut node = tree_hash(0, &[&config[..]]);
+    let mut lock_prefix = vec![CONFIG_PROOF, MAX_PROOF_DEPTH as u8, 0];
+    for _ in 0..MAX_PROOF_DEPTH {
+        let mut sibling = [0u8; 32];
+        thread_rng().fill(&mut sibling);
+        node = tree_hash(1, &[&node, &sibling]);
+        lock_prefix.extend_from_slice(&sibling);
+    }
+    lock_prefix.extend_from_slice(&leaf_field);
+    let (witness_size, cycles) = run(
+        Bytes::copy_from_slice(&node[..20]),
+        &Bytes::from(lock_prefix),
+    );
+    println!(
+        "255 of 255, molecule config in a tree of depth {}: {} bytes of witness, {} cycles",
+        MAX_PROOF_DEPTH, witness_size, cycles
+    );
+    assert!(
+        cycles <= MAX_BLOCK_CYCLES,
+        "{} cycles over {}",
+        cycles,
+        MAX_BLOCK_CYCLES
+    );
+}
+
+/// The exact cycles of a fixed set of scenarios, checked against
+/// `cycles.snapshot` so that the impact of a change on every path shows in
+/// review. The keys and the out points are fixed, the same build gives the
+/// same figures. After a change explaining them, record the new ones and
+/// commit them with it:
+///
+/// ```text
+/// UPDATE_CYCLES=1 cargo test test_golden_cycles
+/// ```
+///
+/// A snapshot without figures, or none at all, is recorded the same way on
+/// the first run instead of failing, to be committed.
+#[test]
+fn test_golden_cycles() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/cycles.snapshot");
+    let measured = golden_cycles();
+    let snapshot = std::fs::read_to_string(path).unwrap_or_default();
+    let recorded: HashMap<&str, u64> = snapshot
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .map(|line| {
+            let colon = line.rfind(": ").expect("`<scenario>: <cycles>`");
+            let cycles = line[colon + 2..].trim().parse().expect("cycles");
+            (&line[..colon], cycles)
+        })
+        .collect();
+    if recorded.is_empty() || std::env::var("UPDATE_CYCLES").is_ok() {
+        let mut content = String::from(SNAPSHOT_HEADER);
+        measured.iter().for_each(|(name, cycles)| {
+            content.push_str(&format!("{}: {}\n", name, cycles));
+        });
+        std::fs::write(path, content).expect("write the snapshot");
+        println!("recorded the cycles in {}", path);
+        return;
+    }
+
+    let mut changes = Vec::new();
+    for (name, cycles) in &measured {
+        match recorded.get(name) {
+            Some(previous) if previous == cycles => {}
+            Some(previous) => changes.push(format!(
+                "{}: {} -> {} ({:+})",
+                name,
+                previous,
+                cycles,
+                *cycles as i64 - *previous as i64
+            )),
+            None => changes.push(format!("{}: {}, not recorded", name, cycles)),
+        }
+    }
+    for name in recorded.keys() {
+        if !measured.iter().any(|(measured, _)| measured == name) {
+            changes.push(format!("{}: recorded, no longer measured", name));
+        }
+    }
+    assert!(
+        changes.is_empty(),
+        "the cycles changed, record them with UPDATE_CYCLES=1 if expected:\n{}",
+        changes.join("\n")
+    );
+}
+
+const SNAPSHOT_HEADER: &str = "\
+# Cycles of the scenarios of `test_golden_cycles`, with the contract build
+# linked into `specs/cells`, recorded by
+# `UPDATE_CYCLES=1 cargo test test_golden_cycles`.
+";
+
+/// The scenarios of `test_golden_cycles` and their cycles.
+fn golden_cycles() -> Vec<(&'static str, u64)> {
+    let keys: Vec<_> = (1..=5u8).map(|i| Privkey::from_slice(&[i; 32])).collect();
+    let run = |lock_args: Bytes, extra_inputs: u32, lock_prefix: &Bytes, signers: &[&Privkey]| {
+        let mut data_loader = DummyDataLoader::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        let raw_tx = gen_tx_with_rng(&mut data_loader, lock_args, extra_inputs, &mut rng);
+        let tx = multi_sign_tx(raw_tx, lock_prefix, signers);
+        verify(&data_loader, &tx).expect("pass verification")
+    };
+    let script = |n: usize, threshold: u8, require_first_n: u8| {
+        gen_multi_sign_script(&keys[..n], threshold, require_first_n)
+    };
+
+    let one_of_one = script(1, 1, 0);
+    let two_of_three = script(3, 2, 0);
+    let three_of_five = script(5, 3, 2);
+    let five_of_five = script(5, 5, 0);
+    let config_v2 = gen_config_v2(&keys[..3], 2, 0);
+    let mut v2_prefix = vec![CONFIG_V2];
+    v2_prefix.extend_from_slice(&config_v2);
+    let recovery = gen_multi_sign_script(&keys[3..4], 1, 0);
+    let (ops_leaf, recovery_leaf) = (
+        tree_hash(0, &[&two_of_three[..]]),
+        tree_hash(0, &[&recovery[..]]),
+    );
+    let root = tree_hash(1, &[&ops_leaf, &recovery_leaf]);
+    let mut tree_prefix = vec![CONFIG_PROOF, 1, 0];
+    tree_prefix.extend_from_slice(&recovery_leaf);
+    tree_prefix.extend_from_slice(&two_of_three);
+
+    vec![
+        (
+            "1 of 1",
+            run(blake160(&one_of_one), 0, &one_of_one, &[&keys[0]]),
+        ),
+        (
+            "2 of 3",
+            run(
+                blake160(&two_of_three),
+                0,
+                &two_of_three,
+                &[&keys[0], &keys[2]],
+            ),
+        ),
+        (
+            "3 of 5, first 2",
+            run(
+                blake160(&three_of_five),
+                0,
+                &three_of_five,
+                &[&keys[0], &keys[1], &keys[4]],
+            ),
+        ),
+        (
+            "5 of 5",
+            run(
+                blake160(&five_of_five),
+                0,
+                &five_of_five,
+                &keys.iter().collect::<Vec<_>>(),
+            ),
+        ),
+        (
+            "2 of 3, 3 inputs",
+            run(
+                blake160(&two_of_three),
+                2,
+                &two_of_three,
+                &[&keys[0], &keys[2]],
+            ),
+        ),
+        (
+            "2 of 3, molecule config",
+            run(
+                blake160(&config_v2),
+                0,
+                &Bytes::from(v2_prefix),
+                &[&keys[0], &keys[2]],
+            ),
+        ),
+        (
+            "2 of 3, config tree",
+            run(
+                Bytes::copy_from_slice(&root[..20]),
+                0,
+                &Bytes::from(tree_prefix),
+                &[&keys[0], &keys[2]],
+            ),
+        ),
+    ]
+}
+
 fn multi_sign_tx(
     tx: TransactionView,
     multi_sign_script: &Bytes,
@@ -653,7 +1091,7 @@ fn test_genesis_time_locked_cell() {
     }
 }
 
-fn gen_multi_sign_script(keys: &[Privkey], threshold: u8, require_first_n: u8) -> Bytes {
+pub(crate) fn gen_multi_sign_script(keys: &[Privkey], threshold: u8, require_first_n: u8) -> Bytes {
     let pubkeys = keys
         .iter()
         .map(|key| key.pubkey().unwrap())
@@ -665,13 +1103,48 @@ fn gen_multi_sign_script(keys: &[Privkey], threshold: u8, require_first_n: u8) -
     script.into()
 }
 
-fn gen_tx_with_extra_inputs(
+/// A leaf (prefix 0) or a node (prefix 1) of a tree of configs.
+fn tree_hash(prefix: u8, parts: &[&[u8]]) -> [u8; 32] {
+    let mut blake2b = ckb_hash::new_blake2b();
+    blake2b.update(&[prefix]);
+    parts.iter().for_each(|part| blake2b.update(part));
+    let mut hash = [0u8; 32];
+    blake2b.finalize(&mut hash);
+    hash
+}
+
+/// The canonical molecule encoding of `MultisigConfigV2`.
+fn gen_config_v2(keys: &[Privkey], threshold: u8, require_first_n: u8) -> Bytes {
+    let total = 27 + 20 * keys.len() as u32;
+    let mut config = Vec::new();
+    for word in &[total, 20, 21, 22, 23] {
+        config.extend_from_slice(&word.to_le_bytes());
+    }
+    config.extend_from_slice(&[0u8, require_first_n, threshold]);
+    config.extend_from_slice(&(keys.len() as u32).to_le_bytes());
+    keys.iter().for_each(|key| {
+        config.extend_from_slice(&blake160(&key.pubkey().unwrap().serialize()));
+    });
+    config.into()
+}
+
+pub(crate) fn gen_tx_with_extra_inputs(
+    dummy: &mut DummyDataLoader,
+    lock_args: Bytes,
+    extra_inputs: u32,
+) -> TransactionView {
+    gen_tx_with_rng(dummy, lock_args, extra_inputs, &mut thread_rng())
+}
+
+/// The transaction of `gen_tx_with_extra_inputs`, its out points drawn from
+/// `rng`.
+fn gen_tx_with_rng<R: Rng>(
     dummy: &mut DummyDataLoader,
     lock_args: Bytes,
     extra_inputs: u32,
+    rng: &mut R,
 ) -> TransactionView {
     let previous_tx_hash = {
-        let mut rng = thread_rng();
         let mut buf = [0u8; 32];
         rng.fill(&mut buf);
         buf.pack()
@@ -680,7 +1153,6 @@ fn gen_tx_with_extra_inputs(
     let capacity = Capacity::shannons(42);
     let previous_out_point = OutPoint::new(previous_tx_hash.clone(), previous_index);
     let contract_tx_hash = {
-        let mut rng = thread_rng();
         let mut buf = [0u8; 32];
         rng.fill(&mut buf);
         buf.pack()
@@ -703,7 +1175,6 @@ fn gen_tx_with_extra_inputs(
     // secp256k1 data
     let secp256k1_data_out_point = {
         let tx_hash = {
-            let mut rng = thread_rng();
             let mut buf = [0u8; 32];
             rng.fill(&mut buf);
             buf.pack()
@@ -755,7 +1226,6 @@ fn gen_tx_with_extra_inputs(
         let mut extra_inputs_tx_builder = tx_builder;
         extra_inputs_tx_builder =
             extra_inputs_tx_builder.witness(WitnessArgs::new_builder().build().as_bytes().pack());
-        let mut rng = thread_rng();
         for i in 1..=extra_inputs {
             let extra_out_point = OutPoint::new(previous_tx_hash.clone(), i);
             dummy.cells.insert(
@@ -782,7 +1252,7 @@ fn gen_tx_with_extra_inputs(
     }
 }
 
-fn gen_tx(dummy: &mut DummyDataLoader, lock_args: Bytes) -> TransactionView {
+pub(crate) fn gen_tx(dummy: &mut DummyDataLoader, lock_args: Bytes) -> TransactionView {
     gen_tx_with_extra_inputs(dummy, lock_args, 0)
 }
 
@@ -817,7 +1287,7 @@ fn build_resolved_tx(data_loader: &DummyDataLoader, tx: &TransactionView) -> Res
     }
 }
 
-fn generate_keys(n: usize) -> Vec<Privkey> {
+pub(crate) fn generate_keys(n: usize) -> Vec<Privkey> {
     let mut keys = Vec::with_capacity(n);
     for _ in 0..n {
         keys.push(Generator::random_privkey());
@@ -826,7 +1296,7 @@ fn generate_keys(n: usize) -> Vec<Privkey> {
     keys
 }
 
-fn verify(data_loader: &DummyDataLoader, tx: &TransactionView) -> Result<u64, Error> {
+pub(crate) fn verify(data_loader: &DummyDataLoader, tx: &TransactionView) -> Result<u64, Error> {
     let resolved_tx = build_resolved_tx(&data_loader, &tx);
     let mut verfier = TransactionScriptsVerifier::new(&resolved_tx, data_loader);
     verfier.set_debug_printer(|hash, message| println!("{}", message));
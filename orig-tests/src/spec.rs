@@ -0,0 +1,469 @@
+//! The normative statements of the multisig lock in the Nervos RFC 0024,
+//! "CKB Genesis Script List", and of the reference implementation of
+//! ckb-system-scripts, one named test each: `cargo test spec_` checks them
+//! all, a failure naming the statement the contract diverges from.
+//!
+//! The known divergences are tests ignored with the reason, listed by
+//! `cargo test spec_ -- --ignored`.
+
+use super::secp256k1_blake160_multisig_all::{
+    gen_multi_sign_script, gen_tx, gen_tx_with_extra_inputs, generate_keys, verify,
+};
+use super::{blake160, DummyDataLoader, SIGNATURE_SIZE};
+use ckb_crypto::secp::{Generator, Privkey};
+use ckb_error::assert_error_eq;
+use ckb_script::ScriptError;
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, EpochNumberWithFraction, TransactionView},
+    packed::{self, CellInput, CellOutput, WitnessArgs},
+    prelude::*,
+    H256,
+};
+
+const ERROR_ARGUMENTS_LEN: i8 = -1;
+const ERROR_WITNESS_SIZE: i8 = -22;
+const ERROR_INCORRECT_SINCE_FLAGS: i8 = -23;
+const ERROR_INCORRECT_SINCE_VALUE: i8 = -24;
+const ERROR_INCORRECT_SINCE_RELATIVE: i8 = -26;
+const ERROR_INVALID_RESERVE_FIELD: i8 = -41;
+const ERROR_INVALID_PUBKEYS_CNT: i8 = -42;
+const ERROR_INVALID_THRESHOLD: i8 = -43;
+const ERROR_INVALID_REQUIRE_FIRST_N: i8 = -44;
+const ERROR_MULTSIG_SCRIPT_HASH: i8 = -51;
+const ERROR_VERIFICATION: i8 = -52;
+
+const SINCE_RELATIVE_FLAG: u64 = 0x8000_0000_0000_0000;
+const SINCE_EPOCH_FLAG: u64 = 0x2000_0000_0000_0000;
+
+/// The digest of the spec: blake2b, with the CKB personalization, of
+/// * the transaction hash;
+/// * the first witness of the group, `zeroed` its lock field with every
+///   signature set to 0;
+/// * the other witnesses of the group;
+/// * the witnesses beyond the inputs;
+/// each witness prefixed by its length, a little endian u64, unless
+/// `prefixed` is false. Every input is of the group in these tests.
+fn spec_digest(tx: &TransactionView, zeroed: &[u8], prefixed: bool) -> H256 {
+    let group = tx.inputs().len();
+    let witnesses: Vec<Bytes> = tx
+        .witnesses()
+        .into_iter()
+        .map(|witness| witness.unpack())
+        .collect();
+    let hashed = std::iter::once(zeroed)
+        .chain(witnesses[1..group].iter().map(|witness| &witness[..]))
+        .chain(witnesses[group..].iter().map(|witness| &witness[..]));
+    let mut blake2b = ckb_hash::new_blake2b();
+    blake2b.update(&tx.hash().raw_data());
+    for witness in hashed {
+        if prefixed {
+            blake2b.update(&(witness.len() as u64).to_le_bytes());
+        }
+        blake2b.update(witness);
+    }
+    let mut message = [0u8; 32];
+    blake2b.finalize(&mut message);
+    H256::from(message)
+}
+
+/// The lock field of the spec, `multisig_script | Signature1 | ...`, signed
+/// by `keys` over `spec_digest`, into the first witness.
+fn spec_sign_with(
+    tx: TransactionView,
+    multisig_script: &Bytes,
+    keys: &[&Privkey],
+    prefixed: bool,
+) -> TransactionView {
+    let witness = WitnessArgs::new_unchecked(tx.witnesses().get(0).unwrap().unpack());
+    let mut lock = multisig_script.to_vec();
+    lock.resize(multisig_script.len() + keys.len() * SIGNATURE_SIZE, 0);
+    let zeroed = witness
+        .clone()
+        .as_builder()
+        .lock(Bytes::from(lock.clone()).pack())
+        .build();
+    let message = spec_digest(&tx, &zeroed.as_bytes(), prefixed);
+    for (i, key) in keys.iter().enumerate() {
+        let offset = multisig_script.len() + i * SIGNATURE_SIZE;
+        let signature = key.sign_recoverable(&message).expect("sign").serialize();
+        lock[offset..offset + SIGNATURE_SIZE].copy_from_slice(&signature);
+    }
+    let witness = witness.as_builder().lock(Bytes::from(lock).pack()).build();
+    set_witness(tx, 0, witness.as_bytes())
+}
+
+fn spec_sign(tx: TransactionView, multisig_script: &Bytes, keys: &[&Privkey]) -> TransactionView {
+    spec_sign_with(tx, multisig_script, keys, true)
+}
+
+fn set_witness(tx: TransactionView, index: usize, witness: Bytes) -> TransactionView {
+    let mut witnesses: Vec<packed::Bytes> = tx.witnesses().into_iter().collect();
+    witnesses[index] = witness.pack();
+    tx.as_advanced_builder().set_witnesses(witnesses).build()
+}
+
+/// `tx` with the since of each input in turn.
+fn set_sinces(tx: TransactionView, sinces: &[u64]) -> TransactionView {
+    let inputs: Vec<CellInput> = tx
+        .inputs()
+        .into_iter()
+        .zip(sinces)
+        .map(|(input, since)| input.as_builder().since(since.pack()).build())
+        .collect();
+    tx.as_advanced_builder().set_inputs(inputs).build()
+}
+
+fn args_with_since(multisig_script: &Bytes, since: u64) -> Bytes {
+    let mut args = blake160(multisig_script).to_vec();
+    args.extend_from_slice(&since.to_le_bytes());
+    args.into()
+}
+
+fn assert_refused(data_loader: &DummyDataLoader, tx: &TransactionView, code: i8) {
+    assert_error_eq!(
+        verify(data_loader, tx).unwrap_err(),
+        ScriptError::ValidationFailure(code),
+    );
+}
+
+/// A multisig script with the flags `S | R | M | N` given as they are.
+fn raw_multisig_script(flags: [u8; 4], keys: &[Privkey]) -> Bytes {
+    let mut script = flags.to_vec();
+    for key in keys {
+        script.extend_from_slice(&blake160(&key.pubkey().unwrap().serialize()));
+    }
+    script.into()
+}
+
+// The lock args
+
+/// The lock args are the blake160 of the multisig script, 20 bytes.
+#[test]
+fn spec_args_are_blake160_of_multisig_script() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    verify(
+        &data_loader,
+        &spec_sign(tx.clone(), &script, &[&keys[0], &keys[1]]),
+    )
+    .expect("pass verification");
+    let other = gen_multi_sign_script(&keys, 2, 1);
+    let tx = spec_sign(tx, &other, &[&keys[0], &keys[1]]);
+    assert_refused(&data_loader, &tx, ERROR_MULTSIG_SCRIPT_HASH);
+}
+
+/// The lock args may append a since to the hash, a little endian u64, 28
+/// bytes in all; other lengths are refused.
+#[test]
+fn spec_args_of_20_or_28_bytes() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let tx = gen_tx(&mut data_loader, args_with_since(&script, 0));
+    verify(&data_loader, &spec_sign(tx, &script, &[&keys[0], &keys[1]]))
+        .expect("pass verification");
+    for len in [21, 27].iter() {
+        let mut args = args_with_since(&script, 0).to_vec();
+        args.resize(*len, 0);
+        let tx = gen_tx(&mut data_loader, args.into());
+        let tx = spec_sign(tx, &script, &[&keys[0], &keys[1]]);
+        assert_refused(&data_loader, &tx, ERROR_ARGUMENTS_LEN);
+    }
+}
+
+// The multisig script, `S | R | M | N | PubKeyHash1 | ... | PubKeyHashN`
+
+/// S, the first byte, is reserved: the values the contract doesn't know are
+/// refused.
+#[test]
+fn spec_reserved_byte_refused() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = raw_multisig_script([3, 0, 2, 3], &keys);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    let tx = spec_sign(tx, &script, &[&keys[0], &keys[1]]);
+    assert_refused(&data_loader, &tx, ERROR_INVALID_RESERVE_FIELD);
+}
+
+/// S is 0 in the spec: any other value is refused.
+#[test]
+#[ignore = "divergence: S selects the key format, 1 and 2 are accepted, see Key format in the README"]
+fn spec_reserved_byte_is_zero() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    // either format, the members listed as compressed keys
+    let script = raw_multisig_script([2, 0, 2, 3], &keys);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    let tx = spec_sign(tx, &script, &[&keys[0], &keys[1]]);
+    assert_refused(&data_loader, &tx, ERROR_INVALID_RESERVE_FIELD);
+}
+
+/// N, the count of pubkey hashes, is at least 1.
+#[test]
+fn spec_pubkeys_cnt_not_zero() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(1);
+    let script = raw_multisig_script([0, 0, 1, 0], &[]);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    let tx = spec_sign(tx, &script, &[&keys[0]]);
+    assert_refused(&data_loader, &tx, ERROR_INVALID_PUBKEYS_CNT);
+}
+
+/// M, the threshold, is from 1 to N.
+#[test]
+fn spec_threshold_within_pubkeys_cnt() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    for threshold in [0u8, 4].iter() {
+        let script = raw_multisig_script([0, 0, *threshold, 3], &keys);
+        let tx = gen_tx(&mut data_loader, blake160(&script));
+        let signers: Vec<_> = keys.iter().take(usize::from(*threshold)).collect();
+        let tx = spec_sign(tx, &script, &signers);
+        assert_refused(&data_loader, &tx, ERROR_INVALID_THRESHOLD);
+    }
+}
+
+/// R, the count of first pubkeys required, is at most M.
+#[test]
+fn spec_require_first_n_within_threshold() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = raw_multisig_script([0, 3, 2, 3], &keys);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    let tx = spec_sign(tx, &script, &[&keys[0], &keys[1]]);
+    assert_refused(&data_loader, &tx, ERROR_INVALID_REQUIRE_FIRST_N);
+}
+
+// The signatures, `multisig_script | Signature1 | ... | SignatureM` in the
+// lock field of the first witness of the group
+
+/// The lock field holds exactly M signatures.
+#[test]
+fn spec_lock_holds_threshold_signatures() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    for signers in [vec![&keys[0]], vec![&keys[0], &keys[1], &keys[2]]].iter() {
+        let tx = spec_sign(tx.clone(), &script, signers);
+        assert_refused(&data_loader, &tx, ERROR_WITNESS_SIZE);
+    }
+}
+
+/// The signatures may come in any order.
+#[test]
+fn spec_signatures_in_any_order() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 3, 0);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    for signers in [[2, 0, 1], [1, 2, 0], [0, 1, 2]].iter() {
+        let signers: Vec<_> = signers.iter().map(|i| &keys[*i]).collect();
+        verify(&data_loader, &spec_sign(tx.clone(), &script, &signers)).expect("pass verification");
+    }
+}
+
+/// Each signature recovers a distinct pubkey of the multisig script.
+#[test]
+fn spec_signatures_from_distinct_pubkeys() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let stranger = Generator::random_privkey();
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    for signers in [[&keys[0], &keys[0]], [&keys[0], &stranger]].iter() {
+        let tx = spec_sign(tx.clone(), &script, signers);
+        assert_refused(&data_loader, &tx, ERROR_VERIFICATION);
+    }
+}
+
+/// The first R pubkeys of the multisig script all sign.
+#[test]
+fn spec_first_r_pubkeys_sign() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 2);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    verify(
+        &data_loader,
+        &spec_sign(tx.clone(), &script, &[&keys[1], &keys[0]]),
+    )
+    .expect("pass verification");
+    let tx = spec_sign(tx, &script, &[&keys[0], &keys[2]]);
+    assert_refused(&data_loader, &tx, ERROR_VERIFICATION);
+}
+
+// The digest, see `spec_digest`
+
+/// The digest starts with the transaction hash: the signatures cover the
+/// transaction.
+#[test]
+fn spec_digest_covers_tx_hash() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    let tx = spec_sign(tx, &script, &[&keys[0], &keys[1]]);
+    let output = CellOutput::new_builder()
+        .capacity(Capacity::shannons(41).pack())
+        .build();
+    let tx = tx.as_advanced_builder().set_outputs(vec![output]).build();
+    assert_refused(&data_loader, &tx, ERROR_VERIFICATION);
+}
+
+/// The first witness of the group is hashed whole, its signatures zeroed:
+/// its other fields are covered.
+#[test]
+fn spec_digest_covers_first_witness() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    let tx = spec_sign(tx, &script, &[&keys[0], &keys[1]]);
+    let witness = WitnessArgs::new_unchecked(tx.witnesses().get(0).unwrap().unpack())
+        .as_builder()
+        .extra(Bytes::from(vec![1u8]).pack())
+        .build();
+    let tx = set_witness(tx, 0, witness.as_bytes());
+    assert_refused(&data_loader, &tx, ERROR_VERIFICATION);
+}
+
+/// The other witnesses of the group follow.
+#[test]
+fn spec_digest_covers_group_witnesses() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let tx = gen_tx_with_extra_inputs(&mut data_loader, blake160(&script), 2);
+    let tx = spec_sign(tx, &script, &[&keys[0], &keys[1]]);
+    verify(&data_loader, &tx).expect("pass verification");
+    let tx = set_witness(tx, 2, Bytes::from(vec![1u8, 2, 3]));
+    assert_refused(&data_loader, &tx, ERROR_VERIFICATION);
+}
+
+/// The witnesses beyond the inputs follow.
+#[test]
+fn spec_digest_covers_witnesses_beyond_inputs() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let tx = gen_tx(&mut data_loader, blake160(&script));
+    let tx = tx
+        .as_advanced_builder()
+        .witness(Bytes::from(vec![1u8, 2, 3]).pack())
+        .build();
+    let tx = spec_sign(tx, &script, &[&keys[0], &keys[1]]);
+    verify(&data_loader, &tx).expect("pass verification");
+    let tx = set_witness(tx, 1, Bytes::from(vec![3u8, 2, 1]));
+    assert_refused(&data_loader, &tx, ERROR_VERIFICATION);
+}
+
+/// Each witness hashed is prefixed by its length, a little endian u64.
+#[test]
+fn spec_digest_prefixes_lengths() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let tx = gen_tx_with_extra_inputs(&mut data_loader, blake160(&script), 1);
+    let tx = spec_sign_with(tx, &script, &[&keys[0], &keys[1]], false);
+    assert_refused(&data_loader, &tx, ERROR_VERIFICATION);
+}
+
+// The since of the lock args
+
+/// Every input of the group has a since not below that of the lock args.
+#[test]
+fn spec_since_not_below_args() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let since = 100;
+    let tx = gen_tx_with_extra_inputs(&mut data_loader, args_with_since(&script, since), 1);
+    for sinces in [[since, since], [since + 1, since]].iter() {
+        let tx = spec_sign(
+            set_sinces(tx.clone(), sinces),
+            &script,
+            &[&keys[0], &keys[1]],
+        );
+        verify(&data_loader, &tx).expect("pass verification");
+    }
+    // the second input alone is below
+    let tx = spec_sign(
+        set_sinces(tx, &[since, since - 1]),
+        &script,
+        &[&keys[0], &keys[1]],
+    );
+    assert_refused(&data_loader, &tx, ERROR_INCORRECT_SINCE_VALUE);
+}
+
+/// The since of every input of the group has the flags of that of the lock
+/// args, its metric and whether it is relative.
+#[test]
+fn spec_since_same_flags() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let since = 100;
+    let tx = gen_tx(&mut data_loader, args_with_since(&script, since));
+    let cases = [
+        (SINCE_EPOCH_FLAG | since, ERROR_INCORRECT_SINCE_FLAGS),
+        // refused with a code of its own
+        (SINCE_RELATIVE_FLAG | since, ERROR_INCORRECT_SINCE_RELATIVE),
+    ];
+    for (input_since, code) in cases.iter() {
+        let tx = spec_sign(
+            set_sinces(tx.clone(), &[*input_since]),
+            &script,
+            &[&keys[0], &keys[1]],
+        );
+        assert_refused(&data_loader, &tx, *code);
+    }
+}
+
+/// Epoch sinces compare as an epoch number and a fraction of the next.
+#[test]
+fn spec_since_epoch_fraction() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    let epoch = |number, index, length| {
+        SINCE_EPOCH_FLAG | EpochNumberWithFraction::new(number, index, length).full_value()
+    };
+    let tx = gen_tx(
+        &mut data_loader,
+        args_with_since(&script, epoch(200, 5, 100)),
+    );
+    for input_since in [epoch(200, 1, 10), epoch(200, 1, 20), epoch(201, 0, 1)].iter() {
+        let tx = spec_sign(
+            set_sinces(tx.clone(), &[*input_since]),
+            &script,
+            &[&keys[0], &keys[1]],
+        );
+        verify(&data_loader, &tx).expect("pass verification");
+    }
+    let tx = spec_sign(
+        set_sinces(tx, &[epoch(200, 4, 100)]),
+        &script,
+        &[&keys[0], &keys[1]],
+    );
+    assert_refused(&data_loader, &tx, ERROR_INCORRECT_SINCE_VALUE);
+}
+
+/// Without a since in the lock args, or a zero one, the reference
+/// implementation skips the check: the inputs may carry any since.
+#[test]
+#[ignore = "divergence: the since of the inputs is checked against 0, their flags must be 0 too"]
+fn spec_zero_since_puts_no_constraint() {
+    let mut data_loader = DummyDataLoader::new();
+    let keys = generate_keys(3);
+    let script = gen_multi_sign_script(&keys, 2, 0);
+    for args in [blake160(&script), args_with_since(&script, 0)].iter() {
+        let tx = gen_tx(&mut data_loader, args.clone());
+        let tx = set_sinces(tx, &[SINCE_RELATIVE_FLAG | 10]);
+        verify(&data_loader, &spec_sign(tx, &script, &[&keys[0], &keys[1]]))
+            .expect("pass verification");
+    }
+}
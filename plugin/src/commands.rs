@@ -0,0 +1,326 @@
+//! `ckb-cli multisig ...`, the sub commands of the plugin:
+//!
+//! ```text
+//! multisig address --pubkey-hash <hash> ... --threshold 2 --code-hash <hash>
+//! multisig digest --tx-file tx.json --config config.toml --code-hash <hash>
+//! multisig add-signatures --tx-file tx.json --config config.toml --code-hash <hash> --signature <sig>
+//! ```
+//!
+//! `--tx-file` is a file of `ckb-cli tx`, whose `transaction` is read and
+//! updated in place, or a bare transaction in the node RPC format. The
+//! inputs of the config are given with `--input` or looked up through
+//! `--rpc`. Each command answers a JSON value ckb-cli prints.
+
+use std::{convert::TryInto, fs, path::PathBuf, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ckb_jsonrpc_types as json;
+use ckb_multisig_sdk::{
+    blake160,
+    config_file::ConfigFile,
+    constants::{BLAKE160_SIZE, SIGNATURE_SIZE},
+    request::SigningRequest,
+    since::parse_since,
+    unlock::{BoxedSigner, MultisigScriptSigner},
+    MultisigConfig, SecpSigner,
+};
+use ckb_sdk::{types::ScriptGroup, Address, AddressPayload, CkbRpcClient, NetworkType};
+use ckb_types::{
+    core::ScriptHashType,
+    packed::{self, Script},
+    prelude::*,
+    H256,
+};
+use clap::{error::ErrorKind, Args, Parser, Subcommand};
+use serde_json::{json, Value};
+
+#[derive(Parser)]
+#[command(name = "multisig", about = "The ckb-multisig lock")]
+struct MultisigCommand {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lock args, lock script and address of a config
+    Address(AddressArgs),
+    /// Signing digest of the inputs of a config
+    Digest(GroupArgs),
+    /// Put signatures into the witness of the inputs of a config
+    AddSignatures(AddSignaturesArgs),
+}
+
+#[derive(Args)]
+struct LockArgs {
+    /// Code hash of the deployed contract
+    #[arg(long, value_parser = parse_h256)]
+    code_hash: H256,
+
+    /// Hash type of the lock script: data, type, data1 or data2
+    #[arg(long, default_value = "type", value_parser = parse_hash_type)]
+    hash_type: ScriptHashType,
+}
+
+#[derive(Args)]
+struct AddressArgs {
+    /// blake160 of a member public key, in the multisig script order
+    #[arg(long = "pubkey-hash", value_parser = parse_hex::<BLAKE160_SIZE>)]
+    pubkey_hashes: Vec<[u8; BLAKE160_SIZE]>,
+
+    /// Compressed public key of a member, listed after the `--pubkey-hash` ones
+    #[arg(long = "pubkey", value_parser = parse_hex::<33>)]
+    pubkeys: Vec<[u8; 33]>,
+
+    #[arg(long)]
+    threshold: u8,
+
+    #[arg(long, default_value_t = 0)]
+    require_first_n: u8,
+
+    /// Since of the lock args, readable like `after epoch 180` or raw `0x...`
+    #[arg(long, value_parser = parse_since_arg)]
+    since: Option<u64>,
+
+    #[command(flatten)]
+    lock: LockArgs,
+
+    /// mainnet or testnet
+    #[arg(long, default_value = "mainnet", value_parser = parse_network)]
+    network: NetworkType,
+}
+
+#[derive(Args)]
+struct GroupArgs {
+    /// Transaction file of `ckb-cli tx`, or a transaction in the RPC format
+    #[arg(long)]
+    tx_file: PathBuf,
+
+    /// Config file of the lock, TOML unless the extension is `.json`
+    #[arg(long)]
+    config: PathBuf,
+
+    #[command(flatten)]
+    lock: LockArgs,
+
+    /// Index of an input locked by the config, found through `--rpc` when omitted
+    #[arg(long = "input")]
+    inputs: Vec<usize>,
+
+    /// RPC of a CKB node
+    #[arg(long, default_value = "http://127.0.0.1:8114")]
+    rpc: String,
+}
+
+#[derive(Args)]
+struct AddSignaturesArgs {
+    #[command(flatten)]
+    group: GroupArgs,
+
+    /// Signature of the digest, 65 bytes hex, can be repeated
+    #[arg(long = "signature", value_parser = parse_hex::<SIGNATURE_SIZE>)]
+    signatures: Vec<[u8; SIGNATURE_SIZE]>,
+
+    /// Private key file of a member, can be repeated
+    #[arg(long = "privkey-path")]
+    privkey_paths: Vec<PathBuf>,
+}
+
+/// Run the command line ckb-cli passed, help is answered as a string.
+pub fn run(args: &str) -> Result<Value> {
+    let argv = std::iter::once("multisig").chain(args.split_whitespace());
+    let command = match MultisigCommand::try_parse_from(argv) {
+        Ok(command) => command,
+        Err(err)
+            if matches!(
+                err.kind(),
+                ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+            ) =>
+        {
+            return Ok(Value::String(err.to_string()))
+        }
+        Err(err) => bail!("{}", err),
+    };
+    match command.command {
+        Command::Address(args) => address(args),
+        Command::Digest(args) => digest(args),
+        Command::AddSignatures(args) => add_signatures(args),
+    }
+}
+
+fn address(args: AddressArgs) -> Result<Value> {
+    let mut hashes = args.pubkey_hashes;
+    hashes.extend(args.pubkeys.iter().map(|pubkey| blake160(pubkey)));
+    let config =
+        MultisigConfig::new(hashes, args.require_first_n, args.threshold)?.with_since(args.since);
+    let script = config.lock_script(&args.lock.code_hash, args.lock.hash_type);
+    let address = Address::new(args.network, AddressPayload::from(script.clone()), true);
+    Ok(json!({
+        "lock_args": format!("0x{}", hex::encode(config.lock_args())),
+        "lock_script": json::Script::from(script),
+        "address": address.to_string(),
+    }))
+}
+
+fn digest(args: GroupArgs) -> Result<Value> {
+    let (_, request) = load_group(&args)?;
+    let lock = request.lock()?;
+    Ok(json!({
+        "inputs": request.script_group.input_indices,
+        "digest": format!("0x{}", hex::encode(request.message()?)),
+        "signed": identities(&request)?,
+        "missing": lock.signatures().len() - lock.filled_count(),
+    }))
+}
+
+fn add_signatures(args: AddSignaturesArgs) -> Result<Value> {
+    if args.signatures.is_empty() && args.privkey_paths.is_empty() {
+        bail!("nothing to add, pass --signature or --privkey-path");
+    }
+    let (mut file, mut request) = load_group(&args.group)?;
+    for signature in &args.signatures {
+        request.add_signature(*signature)?;
+    }
+    if !args.privkey_paths.is_empty() {
+        let signers = args
+            .privkey_paths
+            .iter()
+            .map(|path| load_signer(path))
+            .collect::<Result<Vec<_>>>()?;
+        request.sign(&MultisigScriptSigner::new(request.config.clone(), signers))?;
+    }
+    let tx = json::Transaction::from(request.tx.data());
+    match file.get_mut("transaction") {
+        Some(transaction) => *transaction = serde_json::to_value(tx)?,
+        None => file = serde_json::to_value(tx)?,
+    }
+    let path = &args.group.tx_file;
+    fs::write(path, serde_json::to_string_pretty(&file)?)
+        .with_context(|| format!("write {}", path.display()))?;
+    Ok(json!({
+        "inputs": request.script_group.input_indices,
+        "signed": identities(&request)?,
+        "complete": request.is_complete()?,
+    }))
+}
+
+/// The tx file as read and the request of the config inputs.
+fn load_group(args: &GroupArgs) -> Result<(Value, SigningRequest)> {
+    let path = &args.tx_file;
+    let content = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let file: Value =
+        serde_json::from_str(&content).with_context(|| format!("parse {}", path.display()))?;
+    let tx: json::Transaction =
+        serde_json::from_value(file.get("transaction").unwrap_or(&file).clone())
+            .with_context(|| format!("invalid transaction in {}", path.display()))?;
+    let tx = packed::Transaction::from(tx).into_view();
+
+    let config = load_config(&args.config)?;
+    let lock = config.lock_script(&args.lock.code_hash, args.lock.hash_type);
+    let input_indices = if args.inputs.is_empty() {
+        find_inputs(&args.rpc, tx.inputs(), &lock)?
+    } else {
+        args.inputs.clone()
+    };
+    if input_indices.is_empty() {
+        bail!("no input of {} is locked by the config", path.display());
+    }
+    if let Some(index) = input_indices.iter().find(|i| **i >= tx.inputs().len()) {
+        bail!("input #{} out of range", index);
+    }
+    let mut script_group = ScriptGroup::from_lock_script(&lock);
+    script_group.input_indices = input_indices;
+    Ok((
+        file,
+        SigningRequest {
+            config,
+            tx,
+            script_group,
+            fee: 0,
+            genesis_hash: None,
+        },
+    ))
+}
+
+fn find_inputs(rpc: &str, inputs: packed::CellInputVec, lock: &Script) -> Result<Vec<usize>> {
+    let client = CkbRpcClient::new(rpc);
+    let mut indices = Vec::new();
+    for (i, input) in inputs.into_iter().enumerate() {
+        let cell = client
+            .get_live_cell(input.previous_output().into(), false)?
+            .cell
+            .ok_or_else(|| anyhow!("input #{} is not a live cell", i))?;
+        if Script::from(cell.output.lock) == *lock {
+            indices.push(i);
+        }
+    }
+    Ok(indices)
+}
+
+fn identities(request: &SigningRequest) -> Result<Vec<String>> {
+    Ok(request
+        .signed()?
+        .iter()
+        .map(|identity| format!("0x{}", hex::encode(identity)))
+        .collect())
+}
+
+fn load_config(path: &PathBuf) -> Result<MultisigConfig> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("read config {}", path.display()))?;
+    let file = if path.extension().is_some_and(|ext| ext == "json") {
+        ConfigFile::from_json(&content)?
+    } else {
+        ConfigFile::from_toml(&content)?
+    };
+    Ok(file.to_config()?)
+}
+
+/// A private key file in the ckb-cli format: the hex key on the first line.
+fn load_signer(path: &PathBuf) -> Result<BoxedSigner> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("read key {}", path.display()))?;
+    let line = content.lines().next().unwrap_or_default().trim();
+    let key = hex::decode(line.trim_start_matches("0x"))
+        .with_context(|| format!("invalid key {}", path.display()))?;
+    Ok(Box::new(SecpSigner::from_slice(&key)?))
+}
+
+fn parse_h256(s: &str) -> Result<H256> {
+    H256::from_str(s.trim_start_matches("0x"))
+        .map_err(|err| anyhow!("invalid hash `{}`: {}", s, err))
+}
+
+fn parse_hex<const N: usize>(s: &str) -> Result<[u8; N]> {
+    hex::decode(s.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow!("expected {} bytes hex, got `{}`", N, s))
+}
+
+fn parse_hash_type(s: &str) -> Result<ScriptHashType> {
+    match s {
+        "data" => Ok(ScriptHashType::Data),
+        "type" => Ok(ScriptHashType::Type),
+        "data1" => Ok(ScriptHashType::Data1),
+        "data2" => Ok(ScriptHashType::Data2),
+        _ => bail!("unknown hash type `{}`", s),
+    }
+}
+
+fn parse_network(s: &str) -> Result<NetworkType> {
+    match s {
+        "mainnet" => Ok(NetworkType::Mainnet),
+        "testnet" => Ok(NetworkType::Testnet),
+        _ => bail!("unknown network `{}`, expected mainnet or testnet", s),
+    }
+}
+
+/// A since without spaces, as the command line is split on whitespace:
+/// `0x...`, or a readable one with `_` in place of spaces.
+fn parse_since_arg(s: &str) -> Result<u64> {
+    match s.strip_prefix("0x") {
+        Some(raw) => u64::from_str_radix(raw, 16).map_err(|err| anyhow!("invalid since: {}", err)),
+        None => Ok(parse_since(&s.replace('_', " "))?),
+    }
+}
@@ -0,0 +1,79 @@
+//! ckb-cli plugin of the ckb-multisig lock, adds `ckb-cli multisig` to
+//! derive addresses, compute signing digests and put signatures into the
+//! transaction files of `ckb-cli tx`.
+//!
+//! ```text
+//! ckb-cli plugin add --binary-path ./ckb-multisig-plugin
+//! ckb-cli multisig digest --tx-file tx.json --config config.toml --code-hash <hash>
+//! ```
+//!
+//! See `protocol.rs` for the messages exchanged with ckb-cli and
+//! `commands.rs` for the sub commands.
+
+mod commands;
+mod protocol;
+#[cfg(test)]
+mod tests;
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+use protocol::{JsonrpcRequest, JsonrpcResponse};
+
+/// Answer a request line, `None` when ckb-cli asks to quit.
+fn handle(line: &str) -> Option<JsonrpcResponse> {
+    let request: JsonrpcRequest = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(err) => {
+            return Some(JsonrpcResponse::error(
+                Value::Null,
+                protocol::INVALID_PARAMS,
+                format!("invalid request: {}", err),
+            ))
+        }
+    };
+    let id = request.id;
+    let response = match request.method.as_str() {
+        protocol::QUIT => return None,
+        protocol::GET_CONFIG => JsonrpcResponse::ok(id, protocol::plugin_config()),
+        protocol::SUB_COMMAND => match request.params.first().and_then(Value::as_str) {
+            Some(args) => match commands::run(args) {
+                Ok(value) => JsonrpcResponse::ok(id, protocol::json_value(value)),
+                Err(err) => {
+                    JsonrpcResponse::error(id, protocol::INVALID_PARAMS, format!("{:#}", err))
+                }
+            },
+            None => JsonrpcResponse::error(
+                id,
+                protocol::INVALID_PARAMS,
+                "missing the command line".to_string(),
+            ),
+        },
+        method => JsonrpcResponse::error(
+            id,
+            protocol::METHOD_NOT_FOUND,
+            format!("unsupported method `{}`", method),
+        ),
+    };
+    Some(response)
+}
+
+fn main() -> anyhow::Result<()> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match handle(&line) {
+            Some(response) => {
+                writeln!(stdout, "{}", serde_json::to_string(&response)?)?;
+                stdout.flush()?;
+            }
+            None => break,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,100 @@
+//! The part of the ckb-cli plugin protocol a sub command plugin speaks.
+//!
+//! ckb-cli starts the plugin and writes one JSON-RPC request per line on its
+//! stdin, the plugin answers one response per line on stdout:
+//!
+//! * `get_config`, answered with the plugin config, which registers the
+//!   `multisig` sub command;
+//! * `sub_command`, with the rest of the command line as the only param,
+//!   answered with the JSON value ckb-cli prints;
+//! * `quit`, no answer.
+//!
+//! Results are tagged as `{"type": "plugin_config", "content": ...}`, the
+//! serialization of the `PluginResponse` enum of `ckb-cli-plugin-protocol`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const JSONRPC_VERSION: &str = "2.0";
+
+pub const QUIT: &str = "quit";
+pub const GET_CONFIG: &str = "get_config";
+pub const SUB_COMMAND: &str = "sub_command";
+
+/// Name of the sub command, `ckb-cli multisig ...`.
+pub const SUB_COMMAND_NAME: &str = "multisig";
+
+// JSON-RPC error codes
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonrpcError {
+    pub code: i32,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonrpcRequest {
+    pub jsonrpc: String,
+    pub id: Value,
+    pub method: String,
+    pub params: Vec<Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonrpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonrpcError>,
+}
+
+impl JsonrpcResponse {
+    pub fn ok(id: Value, result: Value) -> Self {
+        JsonrpcResponse {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn error(id: Value, code: i32, message: String) -> Self {
+        JsonrpcResponse {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: None,
+            error: Some(JsonrpcError {
+                code,
+                message,
+                data: None,
+            }),
+        }
+    }
+}
+
+/// The `get_config` result: a short lived plugin adding one sub command.
+pub fn plugin_config() -> Value {
+    json!({
+        "type": "plugin_config",
+        "content": {
+            "name": SUB_COMMAND_NAME,
+            "description": "Addresses, signing digests and signatures of the ckb-multisig lock",
+            "daemon": false,
+            "roles": [{ "role": "sub_command", "name": SUB_COMMAND_NAME }],
+        },
+    })
+}
+
+/// A `sub_command` result ckb-cli prints as is.
+pub fn json_value(value: Value) -> Value {
+    json!({ "type": "json_value", "content": value })
+}
@@ -0,0 +1,167 @@
+use std::{convert::TryInto, path::PathBuf};
+
+use ckb_jsonrpc_types as json;
+use ckb_multisig_sdk::{
+    config_file::ConfigFile, since::parse_since, MultisigConfig, SecpSigner, Signer,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType, TransactionBuilder},
+    packed::{CellInput, CellOutput, OutPoint},
+    prelude::*,
+    H256,
+};
+use secp256k1::{rand, SecretKey};
+use serde_json::{json, Value};
+
+use super::handle;
+
+const CODE_HASH: H256 = H256([0x42; 32]);
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "ckb-multisig-plugin-{}-{}",
+        hex::encode(rand::random::<[u8; 8]>()),
+        name
+    ))
+}
+
+fn call(method: &str, params: Value) -> Value {
+    let request = json!({ "jsonrpc": "2.0", "id": 7, "method": method, "params": params });
+    let response = handle(&request.to_string()).unwrap();
+    assert_eq!(response.id, json!(7));
+    serde_json::to_value(response).unwrap()
+}
+
+fn sub_command(args: &str) -> Value {
+    let response = call("sub_command", json!([args]));
+    assert_eq!(response["result"]["type"], "json_value", "{}", response);
+    response["result"]["content"].clone()
+}
+
+#[test]
+fn test_protocol() {
+    let config = call("get_config", json!([]));
+    assert_eq!(config["result"]["type"], "plugin_config");
+    assert_eq!(
+        config["result"]["content"]["roles"],
+        json!([{ "role": "sub_command", "name": "multisig" }])
+    );
+    assert!(handle(r#"{"jsonrpc":"2.0","id":1,"method":"quit","params":[]}"#).is_none());
+    assert!(call("keystore_list_account", json!([]))["error"]["code"] == -32601);
+    assert!(call("sub_command", json!(["address --threshold 0"]))["error"].is_object());
+    assert!(sub_command("--help").as_str().unwrap().contains("digest"));
+}
+
+#[test]
+fn test_address() {
+    let signers: Vec<_> = (0..2)
+        .map(|_| SecpSigner::new(SecretKey::new(&mut rand::thread_rng())))
+        .collect();
+    let hashes: Vec<_> = signers.iter().map(|s| s.identity().unwrap()).collect();
+    let config = MultisigConfig::new(hashes.clone(), 1, 2)
+        .unwrap()
+        .with_since(Some(parse_since("after epoch 180").unwrap()));
+    let args = format!(
+        "address --pubkey-hash 0x{} --pubkey-hash 0x{} --threshold 2 --require-first-n 1 \
+         --since after_epoch_180 --code-hash {:#x} --network testnet",
+        hex::encode(hashes[0]),
+        hex::encode(hashes[1]),
+        CODE_HASH
+    );
+    let result = sub_command(&args);
+    assert_eq!(
+        result["lock_args"],
+        format!("0x{}", hex::encode(config.lock_args()))
+    );
+    let script = config.lock_script(&CODE_HASH, ScriptHashType::Type);
+    assert_eq!(
+        result["lock_script"],
+        serde_json::to_value(json::Script::from(script)).unwrap()
+    );
+    assert!(result["address"].as_str().unwrap().starts_with("ckt1"));
+}
+
+#[test]
+fn test_digest_and_signatures() {
+    let keys: Vec<_> = (0..3)
+        .map(|_| SecretKey::new(&mut rand::thread_rng()))
+        .collect();
+    let signers: Vec<_> = keys.iter().map(|key| SecpSigner::new(*key)).collect();
+    let hashes = signers.iter().map(|s| s.identity().unwrap()).collect();
+    let config = MultisigConfig::new(hashes, 0, 2).unwrap();
+    let config_path = temp_path("config.toml");
+    std::fs::write(&config_path, ConfigFile::from(&config).to_toml().unwrap()).unwrap();
+
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(H256([1; 32]).pack(), 0), 0))
+        .input(CellInput::new(OutPoint::new(H256([2; 32]).pack(), 0), 0))
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::shannons(42).pack())
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .build();
+    // a file of `ckb-cli tx`, the other fields are kept
+    let tx_path = temp_path("tx.json");
+    let file = json!({
+        "transaction": json::Transaction::from(tx.data()),
+        "multisig_configs": {},
+        "signatures": {},
+    });
+    std::fs::write(&tx_path, file.to_string()).unwrap();
+
+    let group = format!(
+        "--tx-file {} --config {} --code-hash {:#x} --input 0 --input 1",
+        tx_path.display(),
+        config_path.display(),
+        CODE_HASH
+    );
+    let digest = sub_command(&format!("digest {}", group));
+    assert_eq!(digest["inputs"], json!([0, 1]));
+    assert_eq!(digest["missing"], 2);
+    let message: [u8; 32] = hex::decode(&digest["digest"].as_str().unwrap()[2..])
+        .unwrap()
+        .try_into()
+        .unwrap();
+
+    let signature = hex::encode(signers[1].sign(&message).unwrap());
+    let result = sub_command(&format!(
+        "add-signatures {} --signature 0x{}",
+        group, signature
+    ));
+    assert_eq!(result["complete"], false);
+    assert_eq!(
+        result["signed"],
+        json!([format!("0x{}", hex::encode(signers[1].identity().unwrap()))])
+    );
+
+    // signing doesn't change the digest
+    let digest = sub_command(&format!("digest {}", group));
+    assert_eq!(digest["missing"], 1);
+    assert_eq!(digest["digest"], format!("0x{}", hex::encode(message)));
+
+    let key_path = temp_path("key");
+    std::fs::write(
+        &key_path,
+        format!("{}\n", hex::encode(keys[2].secret_bytes())),
+    )
+    .unwrap();
+    let result = sub_command(&format!(
+        "add-signatures {} --privkey-path {}",
+        group,
+        key_path.display()
+    ));
+    assert_eq!(result["complete"], true);
+
+    let file: Value = serde_json::from_str(&std::fs::read_to_string(&tx_path).unwrap()).unwrap();
+    assert_eq!(file["multisig_configs"], json!({}));
+    assert_eq!(
+        file["transaction"]["witnesses"].as_array().unwrap().len(),
+        1
+    );
+    for path in [config_path, tx_path, key_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
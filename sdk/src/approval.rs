@@ -0,0 +1,180 @@
+//! Detached approvals, signed before the transaction carrying them is built.
+//!
+//! The members sign the blake2b of `multisig hash | genesis hash | action
+//! hash | nonce type hash | nonce` instead of the transaction: the genesis
+//! hash binds the approval to a chain, the action hash commits to the max fee
+//! and the outputs, leaving out the nonce cell, and the nonce is the one of
+//! the nonce cell the transaction spends, see `nonce.rs`. The lock field is
+//! then
+//!
+//! ```text
+//! APPROVAL | nonce type hash | genesis hash | max fee | multisig script | signatures
+//! ```
+//!
+//! and the transaction carries the genesis header as a header dep, see
+//! `Approval::header_dep`.
+//!
+//! Mirrors `ckb-multisig-core/src/approval.rs`.
+
+use ckb_hash::new_blake2b;
+use ckb_types::{
+    bytes::Bytes,
+    core::TransactionView,
+    h256,
+    packed::{Byte32, CellOutput, Script},
+    prelude::*,
+    H256,
+};
+
+use crate::{
+    config::MultisigConfig,
+    constants::{BLAKE160_SIZE, DIGEST_SIZE, SIGNATURE_SIZE},
+    error::Error,
+    signer::{recover_pubkey, Signer},
+    witness::MultisigLock,
+};
+
+/// The first byte of an approval lock field, next to `CONFIG_V2` and
+/// `CONFIG_PROOF` above every key format a multisig lock field starts with.
+pub const APPROVAL: u8 = 0x82;
+
+/// The genesis hash of the mainnet, Lina.
+pub const MAINNET_GENESIS_HASH: H256 =
+    h256!("0x92b197aa1fba0f63633922c61c92375c9c074a93e85963554f5499fe1450d0e5");
+/// The genesis hash of the testnet, Aggron.
+pub const TESTNET_GENESIS_HASH: H256 =
+    h256!("0x10639e0895502b5688a6be8cf69460d76541bfa4821629d86d62ba0aae3f9606");
+
+/// An approval of an action by the members of a config, for the transaction
+/// spending the nonce cell of `nonce_type_hash` at `nonce` on the chain of
+/// `genesis_hash`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Approval {
+    pub nonce_type_hash: [u8; DIGEST_SIZE],
+    pub genesis_hash: [u8; DIGEST_SIZE],
+    pub nonce: u64,
+    pub max_fee: u64,
+    pub action_hash: [u8; DIGEST_SIZE],
+    pub lock: MultisigLock,
+}
+
+impl Approval {
+    /// An unsigned approval of paying `outputs` with at most `max_fee`, on the
+    /// chain of `genesis_hash`.
+    pub fn new(
+        config: MultisigConfig,
+        outputs: &[(CellOutput, Bytes)],
+        nonce_type_hash: [u8; DIGEST_SIZE],
+        nonce: u64,
+        max_fee: u64,
+        genesis_hash: &H256,
+    ) -> Self {
+        Approval {
+            nonce_type_hash,
+            genesis_hash: genesis_hash.0,
+            nonce,
+            max_fee,
+            action_hash: action_hash(outputs, &nonce_type_hash, max_fee),
+            lock: MultisigLock::new(config),
+        }
+    }
+
+    /// The message the members sign.
+    pub fn message(&self) -> [u8; DIGEST_SIZE] {
+        let mut blake2b = new_blake2b();
+        blake2b.update(&self.lock.config().hash160());
+        blake2b.update(&self.genesis_hash);
+        blake2b.update(&self.action_hash);
+        blake2b.update(&self.nonce_type_hash);
+        blake2b.update(&self.nonce.to_le_bytes());
+        let mut message = [0u8; DIGEST_SIZE];
+        blake2b.finalize(&mut message);
+        message
+    }
+
+    /// Sign with a member of the config who hasn't signed yet.
+    pub fn sign(&mut self, signer: &dyn Signer) -> Result<[u8; BLAKE160_SIZE], Error> {
+        let signature = signer.sign(&self.message())?;
+        self.add_signature(signature)
+    }
+
+    /// Add a signature produced elsewhere, it must come from a member of the
+    /// config who hasn't signed yet. Returns the identity of the signer.
+    pub fn add_signature(
+        &mut self,
+        signature: [u8; SIGNATURE_SIZE],
+    ) -> Result<[u8; BLAKE160_SIZE], Error> {
+        let message = self.message();
+        let identity = self
+            .lock
+            .config()
+            .identity(&recover_pubkey(&message, &signature)?);
+        if self.lock.config().position(&identity).is_none() {
+            return Err(Error::Verification(format!(
+                "0x{} is not a member of the config",
+                hex::encode(identity)
+            )));
+        }
+        if self.lock.signed_identities(&message)?.contains(&identity) {
+            return Err(Error::Verification(format!(
+                "0x{} already signed",
+                hex::encode(identity)
+            )));
+        }
+        self.lock.add_signature(signature)?;
+        Ok(identity)
+    }
+
+    /// Check the signatures the way the contract does.
+    pub fn verify(&self) -> Result<(), Error> {
+        self.lock.verify(&self.message())
+    }
+
+    /// Whether `tx` carries the approved action, the nonce cell aside.
+    pub fn matches(&self, tx: &TransactionView) -> bool {
+        let outputs: Vec<_> = tx.outputs_with_data_iter().collect();
+        action_hash(&outputs, &self.nonce_type_hash, self.max_fee) == self.action_hash
+    }
+
+    /// The header dep the transaction must carry, the genesis header of the
+    /// chain.
+    pub fn header_dep(&self) -> Byte32 {
+        self.genesis_hash.pack()
+    }
+
+    /// The lock field of the first witness of the script group.
+    pub fn to_bytes(&self) -> Bytes {
+        let mut lock = vec![APPROVAL];
+        lock.extend_from_slice(&self.nonce_type_hash);
+        lock.extend_from_slice(&self.genesis_hash);
+        lock.extend_from_slice(&self.max_fee.to_le_bytes());
+        lock.extend_from_slice(&self.lock.to_bytes());
+        lock.into()
+    }
+}
+
+/// The action hash: the max fee followed by every output but the nonce cell,
+/// each cell followed by its data prefixed by its length.
+pub fn action_hash(
+    outputs: &[(CellOutput, Bytes)],
+    nonce_type_hash: &[u8; DIGEST_SIZE],
+    max_fee: u64,
+) -> [u8; DIGEST_SIZE] {
+    let mut blake2b = new_blake2b();
+    blake2b.update(&max_fee.to_le_bytes());
+    for (output, data) in outputs {
+        let type_hash = output
+            .type_()
+            .to_opt()
+            .map(|script: Script| script.calc_script_hash());
+        if type_hash.as_ref().map(|hash| hash.as_slice()) == Some(&nonce_type_hash[..]) {
+            continue;
+        }
+        blake2b.update(output.as_slice());
+        blake2b.update(&(data.len() as u64).to_le_bytes());
+        blake2b.update(data);
+    }
+    let mut hash = [0u8; DIGEST_SIZE];
+    blake2b.finalize(&mut hash);
+    hash
+}
@@ -0,0 +1,280 @@
+//! Append-only audit trail of the approvals: every signature a cosigner
+//! gave, with the digest it signs, the identity of the signer and when it was
+//! received, in a file of JSON lines:
+//!
+//! ```json
+//! {"seq":0,"timestamp":1718000000,"proposal":"0x..","lock_args":"0x..","digest":"0x..","signature":"0x..","signer":"0x..","prev_hash":"0x00..","hash":"0x.."}
+//! ```
+//!
+//! `hash` is the blake2b of the entry, see `AuditEntry::hash`, and
+//! `prev_hash` the one of the entry before, zero for the first entry: an
+//! entry can't be changed, removed or reordered without breaking the chain.
+//! Anyone holding the file re-verifies it with `verify_log`, which checks the
+//! chain and recovers every signature to its signer, no key and no node
+//! needed. `AuditEntry::check_member` also ties an entry to a config.
+//!
+//! The chain makes tampering evident, it doesn't prevent rewriting the whole
+//! file: auditors should keep the `hash` of the last entry they checked, and
+//! check the next export extends it.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use ckb_hash::blake2b_256;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{KeyFormat, MultisigConfig},
+    constants::{BLAKE160_SIZE, DIGEST_SIZE, SIGNATURE_SIZE},
+    error::Error,
+    signer::recover_pubkey,
+};
+
+/// A signature given by a cosigner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Approval {
+    /// The transaction hash of the proposal.
+    pub proposal: [u8; 32],
+    pub lock_args: Vec<u8>,
+    pub digest: [u8; DIGEST_SIZE],
+    pub signature: [u8; SIGNATURE_SIZE],
+    pub signer: [u8; BLAKE160_SIZE],
+    /// Unix time in seconds the approval was received.
+    pub timestamp: u64,
+}
+
+impl Approval {
+    /// The signature recovers to `signer`, compressed or uncompressed.
+    pub fn verify(&self) -> Result<(), Error> {
+        let pubkey = recover_pubkey(&self.digest, &self.signature)?;
+        if !KeyFormat::Either.identities(&pubkey).contains(&self.signer) {
+            return Err(Error::SignatureMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// An approval in the log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub prev_hash: [u8; 32],
+    pub approval: Approval,
+}
+
+impl AuditEntry {
+    /// blake2b of `seq | timestamp | proposal | lock args length | lock args
+    /// | digest | signature | signer | prev_hash`, the integers little endian
+    /// u64.
+    pub fn hash(&self) -> [u8; 32] {
+        let approval = &self.approval;
+        let mut data = Vec::new();
+        data.extend_from_slice(&self.seq.to_le_bytes());
+        data.extend_from_slice(&approval.timestamp.to_le_bytes());
+        data.extend_from_slice(&approval.proposal);
+        data.extend_from_slice(&(approval.lock_args.len() as u64).to_le_bytes());
+        data.extend_from_slice(&approval.lock_args);
+        data.extend_from_slice(&approval.digest);
+        data.extend_from_slice(&approval.signature);
+        data.extend_from_slice(&approval.signer);
+        data.extend_from_slice(&self.prev_hash);
+        blake2b_256(data)
+    }
+
+    /// The approval is for a proposal of `config`, by one of its members.
+    pub fn check_member(&self, config: &MultisigConfig) -> Result<(), Error> {
+        let approval = &self.approval;
+        if approval.lock_args != config.lock_args().as_ref() {
+            return Err(Error::Verification(format!(
+                "entry {} is for another config",
+                self.seq
+            )));
+        }
+        if config.position(&approval.signer).is_none() {
+            return Err(Error::Verification(format!(
+                "entry {}: 0x{} is not a member of the config",
+                self.seq,
+                hex::encode(approval.signer)
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let approval = &self.approval;
+        let line = EntryLine {
+            seq: self.seq,
+            timestamp: approval.timestamp,
+            proposal: to_hex(&approval.proposal),
+            lock_args: to_hex(&approval.lock_args),
+            digest: to_hex(&approval.digest),
+            signature: to_hex(&approval.signature),
+            signer: to_hex(&approval.signer),
+            prev_hash: to_hex(&self.prev_hash),
+            hash: to_hex(&self.hash()),
+        };
+        serde_json::to_string(&line).expect("serializable entry")
+    }
+
+    /// An entry whose `hash` matches its content.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let line: EntryLine =
+            serde_json::from_str(json).map_err(|err| audit_err(err.to_string()))?;
+        let entry = AuditEntry {
+            seq: line.seq,
+            prev_hash: from_hex(&line.prev_hash)?,
+            approval: Approval {
+                proposal: from_hex(&line.proposal)?,
+                lock_args: decode_hex(&line.lock_args)?,
+                digest: from_hex(&line.digest)?,
+                signature: from_hex(&line.signature)?,
+                signer: from_hex(&line.signer)?,
+                timestamp: line.timestamp,
+            },
+        };
+        if to_hex(&entry.hash()) != line.hash {
+            return Err(audit_err(format!(
+                "entry {} doesn't match its hash",
+                entry.seq
+            )));
+        }
+        Ok(entry)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EntryLine {
+    seq: u64,
+    timestamp: u64,
+    proposal: String,
+    lock_args: String,
+    digest: String,
+    signature: String,
+    signer: String,
+    prev_hash: String,
+    hash: String,
+}
+
+/// The entries of a log file content, one JSON object per line.
+pub fn parse_log(content: &str) -> Result<Vec<AuditEntry>, Error> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(AuditEntry::from_json)
+        .collect()
+}
+
+/// The entries follow each other from the first one, and every signature
+/// recovers to its signer.
+pub fn verify_log(entries: &[AuditEntry]) -> Result<(), Error> {
+    let mut prev_hash = [0u8; 32];
+    for (seq, entry) in entries.iter().enumerate() {
+        if entry.seq != seq as u64 || entry.prev_hash != prev_hash {
+            return Err(audit_err(format!(
+                "entry {} breaks the chain at line {}",
+                entry.seq,
+                seq + 1
+            )));
+        }
+        entry
+            .approval
+            .verify()
+            .map_err(|err| Error::Verification(format!("entry {}: {}", entry.seq, err)))?;
+        prev_hash = entry.hash();
+    }
+    Ok(())
+}
+
+/// A log file, appended to.
+pub struct AuditLog {
+    path: PathBuf,
+    next_seq: u64,
+    last_hash: [u8; 32],
+}
+
+impl AuditLog {
+    /// Open the log at `path`, created by the first append. The existing entries
+    /// must verify.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let entries = if path.exists() {
+            parse_log(&fs::read_to_string(path).map_err(|err| io_err(path, err))?)?
+        } else {
+            Vec::new()
+        };
+        verify_log(&entries)?;
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            next_seq: entries.len() as u64,
+            last_hash: entries.last().map_or([0u8; 32], AuditEntry::hash),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The hash of the last entry, zero when empty.
+    pub fn last_hash(&self) -> [u8; 32] {
+        self.last_hash
+    }
+
+    /// Verify the approval and write it at the end of the log.
+    pub fn append(&mut self, approval: Approval) -> Result<AuditEntry, Error> {
+        approval.verify()?;
+        let entry = AuditEntry {
+            seq: self.next_seq,
+            prev_hash: self.last_hash,
+            approval,
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|err| io_err(&self.path, err))?;
+        writeln!(file, "{}", entry.to_json())
+            .and_then(|_| file.sync_data())
+            .map_err(|err| io_err(&self.path, err))?;
+        self.next_seq += 1;
+        self.last_hash = entry.hash();
+        Ok(entry)
+    }
+
+    /// The entries written so far.
+    pub fn entries(&self) -> Result<Vec<AuditEntry>, Error> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        parse_log(&fs::read_to_string(&self.path).map_err(|err| io_err(&self.path, err))?)
+    }
+}
+
+fn audit_err(message: String) -> Error {
+    Error::InvalidAuditLog(message)
+}
+
+fn io_err(path: &Path, err: std::io::Error) -> Error {
+    audit_err(format!("{}: {}", path.display(), err))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(s.trim_start_matches("0x"))
+        .map_err(|err| audit_err(format!("invalid hex `{}`: {}", s, err)))
+}
+
+fn from_hex<const N: usize>(s: &str) -> Result<[u8; N], Error> {
+    let bytes = decode_hex(s)?;
+    let mut array = [0u8; N];
+    if bytes.len() != N {
+        return Err(audit_err(format!("`{}` is not {} bytes", s, N)));
+    }
+    array.copy_from_slice(&bytes);
+    Ok(array)
+}
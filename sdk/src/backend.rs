@@ -0,0 +1,349 @@
+//! Chain data backends: where the watch-only modules read the cells and the
+//! transactions of a lock from, so the scanner, the balances, the watcher
+//! and the history run on whichever one an operator runs.
+//!
+//! * `NodeIndexer`: the indexer module built in a CKB node, `Indexer` in
+//!   the `[rpc]` modules of ckb.toml.
+//! * `RichIndexer`: the rich indexer of a CKB node, `RichIndexer` in the
+//!   modules, serving the same RPC from SQL. The lock is matched exactly
+//!   rather than by prefix, an index lookup instead of a scan there.
+//! * `LightClient`: a CKB light client syncing only the watched locks, see
+//!   `light_client.rs`.
+//!
+//! `BackendKind` names them, e.g. for a command line flag. The transaction
+//! builders built on ckb-sdk read the chain through a backend too, see
+//! `providers.rs`, as do the DAO cells and the lookups of the deployed and
+//! nonce cells. Only checking that a cell is live, `verify_deployed` and
+//! co. of `deploy.rs`, and sending a transaction need the RPC of a node.
+
+use std::{fmt, str::FromStr};
+
+use ckb_jsonrpc_types::{self as json, JsonBytes};
+use ckb_sdk::{
+    rpc::ckb_indexer::{Cell, CellType, Order, Pagination, SearchKey, SearchMode, Tx},
+    traits::LiveCell,
+    CkbRpcClient,
+};
+use ckb_types::{
+    core::{BlockView, HeaderView, TransactionView},
+    packed::Script,
+    H256,
+};
+
+use crate::{
+    error::Error, light_client::LightClient, scanner::BlockInfo, watcher::fetch_transaction,
+};
+
+/// A transaction of a lock, from `ChainBackend::transactions`.
+#[derive(Clone, Debug)]
+pub struct LockTx {
+    pub tx_hash: H256,
+    pub block_number: u64,
+    /// The cells of the lock it spends.
+    pub inputs: usize,
+    /// The transaction, when the backend gives it along.
+    pub tx: Option<TransactionView>,
+}
+
+/// Read access to the chain, for the locks the backend indexes.
+pub trait ChainBackend: Send + Sync {
+    /// Make the cells and transactions of `lock_script` from `from_block`
+    /// on available, nothing to do for the indexers of a node.
+    fn watch(&self, _lock_script: &Script, _from_block: u64) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The header of the tip.
+    fn tip_header(&self) -> Result<HeaderView, Error>;
+
+    /// The tip of the chain.
+    fn tip(&self) -> Result<BlockInfo, Error> {
+        Ok(BlockInfo::from(&self.tip_header()?))
+    }
+
+    /// The header of the block `block_hash`.
+    fn header(&self, block_hash: &H256) -> Result<HeaderView, Error>;
+
+    /// The header of block `number`, an error on a backend looking blocks up
+    /// by hash only, see `commit_header` instead.
+    fn header_by_number(&self, number: u64) -> Result<HeaderView, Error>;
+
+    /// The hash of the block committing `tx_hash`.
+    fn transaction_block(&self, tx_hash: &H256) -> Result<H256, Error>;
+
+    /// The header of the block committing `tx_hash`.
+    fn commit_header(&self, tx_hash: &H256) -> Result<HeaderView, Error> {
+        self.header(&self.transaction_block(tx_hash)?)
+    }
+
+    /// The genesis block, whose cells hold the system scripts.
+    fn genesis(&self) -> Result<BlockView, Error>;
+
+    /// The last block the cells and transactions of `lock_script` are known
+    /// at, an error when the backend doesn't index it.
+    fn indexed_block(&self, lock_script: &Script) -> Result<u64, Error>;
+
+    /// At most `limit` live cells of `search_key` after `cursor`, oldest
+    /// first.
+    fn cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Result<Pagination<Cell>, Error>;
+
+    /// The block committing `cell`.
+    fn commit_block(&self, cell: &LiveCell) -> Result<BlockInfo, Error>;
+
+    /// At most `limit` transactions of `search_key` grouped, after `cursor`
+    /// in chain order, and the cursor of the next page.
+    fn transactions(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Result<(Vec<LockTx>, JsonBytes), Error>;
+
+    /// The committed transaction `tx_hash`.
+    fn transaction(&self, tx_hash: &H256) -> Result<TransactionView, Error>;
+
+    /// The transaction of `tx`, looked up when not given along.
+    fn lock_transaction(&self, tx: LockTx) -> Result<TransactionView, Error> {
+        match tx.tx {
+            Some(view) => Ok(view),
+            None => self.transaction(&tx.tx_hash),
+        }
+    }
+}
+
+pub type BoxedBackend = Box<dyn ChainBackend>;
+
+/// The indexer built in a CKB node.
+pub struct NodeIndexer {
+    client: CkbRpcClient,
+}
+
+impl NodeIndexer {
+    /// `url` is the RPC of a CKB node with the indexer module enabled.
+    pub fn new(url: &str) -> Self {
+        NodeIndexer {
+            client: CkbRpcClient::new(url),
+        }
+    }
+}
+
+impl ChainBackend for NodeIndexer {
+    fn tip_header(&self) -> Result<HeaderView, Error> {
+        Ok(self.client.get_tip_header()?.into())
+    }
+
+    fn header(&self, block_hash: &H256) -> Result<HeaderView, Error> {
+        Ok(self
+            .client
+            .get_header(block_hash.clone())?
+            .ok_or_else(|| Error::Rpc(format!("block {:#x} not found", block_hash)))?
+            .into())
+    }
+
+    fn header_by_number(&self, number: u64) -> Result<HeaderView, Error> {
+        Ok(self
+            .client
+            .get_header_by_number(number.into())?
+            .ok_or_else(|| Error::Rpc(format!("block #{} not found", number)))?
+            .into())
+    }
+
+    fn transaction_block(&self, tx_hash: &H256) -> Result<H256, Error> {
+        self.client
+            .get_transaction(tx_hash.clone())?
+            .and_then(|tx| tx.tx_status.block_hash)
+            .ok_or_else(|| Error::Rpc(format!("transaction {:#x} is not committed", tx_hash)))
+    }
+
+    fn genesis(&self) -> Result<BlockView, Error> {
+        Ok(self
+            .client
+            .get_block_by_number(0.into())?
+            .ok_or_else(|| Error::Rpc("no genesis block".to_string()))?
+            .into())
+    }
+
+    fn indexed_block(&self, _lock_script: &Script) -> Result<u64, Error> {
+        Ok(self
+            .client
+            .get_indexer_tip()?
+            .ok_or_else(|| Error::Rpc("the indexer has no tip yet".to_string()))?
+            .block_number
+            .value())
+    }
+
+    fn cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Result<Pagination<Cell>, Error> {
+        Ok(self
+            .client
+            .get_cells(search_key, Order::Asc, limit.into(), cursor)?)
+    }
+
+    fn commit_block(&self, cell: &LiveCell) -> Result<BlockInfo, Error> {
+        Ok(BlockInfo::from(&self.header_by_number(cell.block_number)?))
+    }
+
+    fn transactions(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Result<(Vec<LockTx>, JsonBytes), Error> {
+        let page = self
+            .client
+            .get_transactions(search_key, Order::Asc, limit.into(), cursor)?;
+        let txs = page
+            .objects
+            .into_iter()
+            .map(|tx| match tx {
+                Tx::Grouped(tx) => Ok(LockTx {
+                    tx_hash: tx.tx_hash,
+                    block_number: tx.block_number.value(),
+                    inputs: count_inputs(&tx.cells),
+                    tx: None,
+                }),
+                Tx::Ungrouped(_) => Err(Error::Rpc("expected transactions grouped".to_string())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((txs, page.last_cursor))
+    }
+
+    fn transaction(&self, tx_hash: &H256) -> Result<TransactionView, Error> {
+        fetch_transaction(&self.client, tx_hash.clone())
+    }
+}
+
+/// The rich indexer of a CKB node, the lock scripts matched exactly.
+pub struct RichIndexer {
+    indexer: NodeIndexer,
+}
+
+impl RichIndexer {
+    /// `url` is the RPC of a CKB node with the rich indexer module enabled.
+    pub fn new(url: &str) -> Self {
+        RichIndexer {
+            indexer: NodeIndexer::new(url),
+        }
+    }
+}
+
+fn exact(mut search_key: SearchKey) -> SearchKey {
+    search_key.script_search_mode = Some(SearchMode::Exact);
+    search_key
+}
+
+impl ChainBackend for RichIndexer {
+    fn tip_header(&self) -> Result<HeaderView, Error> {
+        self.indexer.tip_header()
+    }
+
+    fn header(&self, block_hash: &H256) -> Result<HeaderView, Error> {
+        self.indexer.header(block_hash)
+    }
+
+    fn header_by_number(&self, number: u64) -> Result<HeaderView, Error> {
+        self.indexer.header_by_number(number)
+    }
+
+    fn transaction_block(&self, tx_hash: &H256) -> Result<H256, Error> {
+        self.indexer.transaction_block(tx_hash)
+    }
+
+    fn genesis(&self) -> Result<BlockView, Error> {
+        self.indexer.genesis()
+    }
+
+    fn indexed_block(&self, lock_script: &Script) -> Result<u64, Error> {
+        self.indexer.indexed_block(lock_script)
+    }
+
+    fn cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Result<Pagination<Cell>, Error> {
+        self.indexer.cells(exact(search_key), limit, cursor)
+    }
+
+    fn commit_block(&self, cell: &LiveCell) -> Result<BlockInfo, Error> {
+        self.indexer.commit_block(cell)
+    }
+
+    fn transactions(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Result<(Vec<LockTx>, JsonBytes), Error> {
+        self.indexer.transactions(exact(search_key), limit, cursor)
+    }
+
+    fn transaction(&self, tx_hash: &H256) -> Result<TransactionView, Error> {
+        self.indexer.transaction(tx_hash)
+    }
+}
+
+/// The cells a grouped transaction spends.
+pub(crate) fn count_inputs(cells: &[(CellType, json::Uint32)]) -> usize {
+    cells
+        .iter()
+        .filter(|(io_type, _)| matches!(io_type, CellType::Input))
+        .count()
+}
+
+/// A kind of backend.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    #[default]
+    Indexer,
+    RichIndexer,
+    LightClient,
+}
+
+impl BackendKind {
+    /// The backend of this kind at `url`.
+    pub fn connect(self, url: &str) -> BoxedBackend {
+        match self {
+            BackendKind::Indexer => Box::new(NodeIndexer::new(url)),
+            BackendKind::RichIndexer => Box::new(RichIndexer::new(url)),
+            BackendKind::LightClient => Box::new(LightClient::new(url)),
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BackendKind::Indexer => "indexer",
+            BackendKind::RichIndexer => "rich-indexer",
+            BackendKind::LightClient => "light-client",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "indexer" => Ok(BackendKind::Indexer),
+            "rich-indexer" => Ok(BackendKind::RichIndexer),
+            "light-client" => Ok(BackendKind::LightClient),
+            _ => Err(Error::InvalidParameter(format!(
+                "unknown backend `{}`, expected indexer, rich-indexer or light-client",
+                s
+            ))),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Balances of a config: what it holds, what it can spend at the tip and
+//! what the since of the lock args still locks.
+//!
+//! Select the cells with the `TypeFilter` of the scanner, `NoType` for the
+//! CKB holdings or the type script of a UDT for its holdings. UDT amounts
+//! are read as the little endian u128 at the head of the cell data, the
+//! layout of sUDT and xUDT.
+
+use std::{convert::TryInto, ops::AddAssign};
+
+use crate::{
+    error::Error,
+    scanner::{BlockInfo, CellSet, MultisigCell, Scanner},
+};
+
+const UDT_AMOUNT_SIZE: usize = 16;
+const PAGE_SIZE: u32 = 256;
+
+/// Sums split by maturity, `total == spendable + locked`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Amounts<T> {
+    pub total: T,
+    /// Held by mature cells.
+    pub spendable: T,
+    /// Held by cells the since of the lock args doesn't allow to spend yet.
+    pub locked: T,
+}
+
+impl<T: AddAssign + Copy> Amounts<T> {
+    fn add(&mut self, amount: T, mature: bool) {
+        self.total += amount;
+        if mature {
+            self.spendable += amount;
+        } else {
+            self.locked += amount;
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Balance {
+    pub tip: BlockInfo,
+    pub cells: usize,
+    /// In shannons.
+    pub capacity: Amounts<u64>,
+    /// UDT amounts of the cells with a type script and at least 16 bytes of
+    /// data, only meaningful when the scan is filtered on one UDT.
+    pub udt: Amounts<u128>,
+}
+
+impl Balance {
+    pub fn new(tip: BlockInfo) -> Self {
+        Balance {
+            tip,
+            cells: 0,
+            capacity: Amounts::default(),
+            udt: Amounts::default(),
+        }
+    }
+
+    pub fn add(&mut self, cell: &MultisigCell) {
+        let mature = cell.maturity.is_mature();
+        self.cells += 1;
+        self.capacity.add(cell.capacity(), mature);
+        if let Some(amount) = udt_amount(cell) {
+            self.udt.add(amount, mature);
+        }
+    }
+}
+
+impl From<&CellSet> for Balance {
+    fn from(set: &CellSet) -> Self {
+        let mut balance = Balance::new(set.tip);
+        for cell in &set.cells {
+            balance.add(cell);
+        }
+        balance
+    }
+}
+
+/// The UDT amount of a typed cell, `None` for cells without type script or
+/// with less than 16 bytes of data.
+pub fn udt_amount(cell: &MultisigCell) -> Option<u128> {
+    if cell.cell.output.type_().is_none() {
+        return None;
+    }
+    let data = cell.cell.output_data.get(..UDT_AMOUNT_SIZE)?;
+    Some(u128::from_le_bytes(data.try_into().expect("16 bytes")))
+}
+
+impl Scanner {
+    /// The balance at the current tip, summed page by page without holding
+    /// every cell, for configs with large cell sets.
+    pub fn balance(&self) -> Result<Balance, Error> {
+        let mut balance = Balance::new(self.tip()?);
+        let mut cursor = None;
+        loop {
+            let page = self.page(&balance.tip, PAGE_SIZE, cursor)?;
+            for cell in &page.cells {
+                balance.add(cell);
+            }
+            match page.cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(balance),
+            }
+        }
+    }
+}
@@ -0,0 +1,438 @@
+//! Batch transfers: pay many recipients from the cells of a config, e.g. a
+//! payroll or grant disbursement read from a CSV file.
+//!
+//! ```text
+//! address,amount
+//! ckb1qz...,1000
+//! ckb1qz...,61.5
+//! ```
+//!
+//! Amounts are in CKB with up to 8 decimals, the header line is optional,
+//! empty lines and lines starting with `#` are skipped.
+//!
+//! Payments are packed in order into as few transactions as the size and
+//! outputs limits allow. Each transaction takes the cells it needs in the
+//! given order and sends the rest back to the change lock, when the rest is
+//! too small for a change cell it is left to the fee.
+
+use std::{ops::Range, str::FromStr};
+
+use ckb_sdk::{traits::LiveCell, types::ScriptGroup, Address, NetworkType};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, TransactionBuilder, TransactionView},
+    packed::{CellDep, CellInput, CellOutput, Script},
+    prelude::*,
+};
+
+use crate::{
+    config::MultisigConfig,
+    error::Error,
+    fee::{witness_size, FeeEstimator},
+    request::SigningRequest,
+    since::apply_since,
+    sweep::DEFAULT_MAX_TX_SIZE,
+};
+
+const SHANNONS_PER_CKB: u64 = 100_000_000;
+
+/// Serialized size of one more input, only the first input of the group has
+/// a witness.
+const INPUT_SIZE: u64 = 44;
+
+/// Capacity sent to a lock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Payment {
+    pub to: Script,
+    /// In shannons.
+    pub capacity: u64,
+}
+
+/// Parse the `address,amount` lines of a CSV file, refusing the addresses of
+/// another network than `network` when given.
+pub fn parse_csv(csv: &str, network: Option<NetworkType>) -> Result<Vec<Payment>, Error> {
+    let mut payments = Vec::new();
+    for (i, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |msg: String| Error::InvalidCsv(format!("line {}: {}", i + 1, msg));
+        let (address, amount) = line
+            .split_once(',')
+            .ok_or_else(|| invalid("expected `address,amount`".to_string()))?;
+        let (address, amount) = (address.trim(), amount.trim());
+        if payments.is_empty() && address.eq_ignore_ascii_case("address") {
+            continue;
+        }
+        let address = Address::from_str(address).map_err(invalid)?;
+        if let Some(network) = network {
+            if address.network() != network {
+                return Err(invalid(format!(
+                    "address of {}, expected {}",
+                    address.network().to_str(),
+                    network.to_str()
+                )));
+            }
+        }
+        payments.push(Payment {
+            to: Script::from(&address),
+            capacity: parse_ckb(amount).map_err(invalid)?,
+        });
+    }
+    Ok(payments)
+}
+
+/// An amount of CKB with up to 8 decimals, in shannons.
+pub fn parse_ckb(amount: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid amount `{}`", amount);
+    let (int, frac) = amount.split_once('.').unwrap_or((amount, ""));
+    if int.is_empty() || frac.len() > 8 {
+        return Err(invalid());
+    }
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if !digits(int) || !digits(frac) {
+        return Err(invalid());
+    }
+    let int: u64 = int.parse().map_err(|_| invalid())?;
+    let frac: u64 = format!("{:0<8}", frac).parse().map_err(|_| invalid())?;
+    int.checked_mul(SHANNONS_PER_CKB)
+        .and_then(|shannons| shannons.checked_add(frac))
+        .ok_or_else(invalid)
+}
+
+pub struct BatchTransfer {
+    config: MultisigConfig,
+    lock_script: Script,
+    cell_deps: Vec<CellDep>,
+    fee_rate: u64,
+    change: Option<Script>,
+    max_tx_size: u64,
+    max_outputs: Option<usize>,
+}
+
+impl BatchTransfer {
+    /// `lock_script` is the lock of the config cells, `cell_deps` are the
+    /// cell deps needed to run it and `fee_rate` is in shannons per 1000
+    /// bytes.
+    pub fn new(
+        config: MultisigConfig,
+        lock_script: Script,
+        cell_deps: Vec<CellDep>,
+        fee_rate: u64,
+    ) -> Self {
+        BatchTransfer {
+            config,
+            lock_script,
+            cell_deps,
+            fee_rate,
+            change: None,
+            max_tx_size: DEFAULT_MAX_TX_SIZE,
+            max_outputs: None,
+        }
+    }
+
+    /// Send the change to another lock, the config lock by default.
+    pub fn change(mut self, change: Script) -> Self {
+        self.change = Some(change);
+        self
+    }
+
+    /// Size limit of each transaction once signed.
+    pub fn max_tx_size(mut self, max_tx_size: u64) -> Self {
+        self.max_tx_size = max_tx_size;
+        self
+    }
+
+    /// Payments per transaction, the change output aside.
+    pub fn max_outputs(mut self, max_outputs: usize) -> Self {
+        self.max_outputs = Some(max_outputs);
+        self
+    }
+
+    /// Build the transactions paying every payment from `cells`, e.g.
+    /// `CellSet::spendable`.
+    pub fn build<I>(&self, payments: I, cells: &[LiveCell]) -> Result<Vec<SigningRequest>, Error>
+    where
+        I: IntoIterator<Item = Payment>,
+    {
+        if let Some(cell) = cells
+            .iter()
+            .find(|cell| cell.output.lock() != self.lock_script)
+        {
+            return Err(Error::InvalidParameter(format!(
+                "cell {} is not guarded by the config",
+                cell.out_point
+            )));
+        }
+        let costs = Costs {
+            base_size: self
+                .tx(&[], &[])
+                .data()
+                .as_reader()
+                .serialized_size_in_block() as u64
+                + witness_size(&self.config) as u64,
+            change_size: output_size(&self.change_output()),
+            change_occupied: occupied(&self.change_output()),
+            estimator: FeeEstimator::new(self.fee_rate),
+        };
+        let mut outputs = Vec::new();
+        let mut requests = Vec::new();
+        let mut batch = Batch::default();
+        for (i, payment) in payments.into_iter().enumerate() {
+            let output = self.payment_output(&payment, i)?;
+            let size = output_size(&output);
+            outputs.push(output);
+            let mut candidate = batch.with(size, payment.capacity, i)?;
+            costs.fund(&mut candidate, cells, i)?;
+            if !self.fits(&candidate, &costs) && batch.outputs > 0 {
+                // close the batch, the payment opens the next one
+                requests.push(
+                    self.build_one(&outputs[batch.output_range()], &cells[batch.cell_range()])?,
+                );
+                candidate = batch.next().with(size, payment.capacity, i)?;
+                costs.fund(&mut candidate, cells, i)?;
+            }
+            if !self.fits(&candidate, &costs) {
+                return Err(Error::InvalidParameter(format!(
+                    "payment #{} doesn't fit in a transaction",
+                    i + 1
+                )));
+            }
+            batch = candidate;
+        }
+        if batch.outputs > 0 {
+            requests
+                .push(self.build_one(&outputs[batch.output_range()], &cells[batch.cell_range()])?);
+        }
+        Ok(requests)
+    }
+
+    fn fits(&self, batch: &Batch, costs: &Costs) -> bool {
+        costs.size(batch, true) <= self.max_tx_size
+            && self.max_outputs.is_none_or(|max| batch.outputs <= max)
+    }
+
+    fn change_output(&self) -> CellOutput {
+        CellOutput::new_builder()
+            .lock(
+                self.change
+                    .clone()
+                    .unwrap_or_else(|| self.lock_script.clone()),
+            )
+            .build()
+    }
+
+    fn payment_output(&self, payment: &Payment, index: usize) -> Result<CellOutput, Error> {
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(payment.capacity).pack())
+            .lock(payment.to.clone())
+            .build();
+        if payment.capacity < occupied(&output) {
+            return Err(Error::InvalidParameter(format!(
+                "payment #{} of {} shannons is below the {} shannons of a cell",
+                index + 1,
+                payment.capacity,
+                occupied(&output)
+            )));
+        }
+        Ok(output)
+    }
+
+    fn tx(&self, outputs: &[CellOutput], cells: &[LiveCell]) -> TransactionView {
+        TransactionBuilder::default()
+            .cell_deps(self.cell_deps.clone())
+            .inputs(
+                cells
+                    .iter()
+                    .map(|cell| CellInput::new(cell.out_point.clone(), 0)),
+            )
+            .outputs(outputs.to_vec())
+            .outputs_data(outputs.iter().map(|_| Bytes::new().pack()))
+            .build()
+    }
+
+    fn build_one(
+        &self,
+        outputs: &[CellOutput],
+        cells: &[LiveCell],
+    ) -> Result<SigningRequest, Error> {
+        let mut script_group = ScriptGroup::from_lock_script(&self.lock_script);
+        script_group.input_indices = (0..cells.len()).collect();
+        let total = checked_sum(cells.iter().map(capacity))?;
+        let paid = checked_sum(outputs.iter().map(output_capacity))?;
+        let groups = [(script_group.clone(), self.config.clone())];
+        let estimator = FeeEstimator::new(self.fee_rate);
+
+        let mut with_change = outputs.to_vec();
+        with_change.push(self.change_output());
+        let tx = apply_since(
+            &self.tx(&with_change, cells),
+            &script_group,
+            self.config.since(),
+        )?;
+        let fee = estimator.estimate(&tx, &groups, None)?.fee;
+        let change = total.checked_sub(checked_sum([paid, fee])?);
+        let (tx, fee) = match change {
+            Some(change) if change >= occupied(&self.change_output()) => {
+                let mut outputs: Vec<_> = tx.outputs().into_iter().collect();
+                let last = outputs.len() - 1;
+                outputs[last] = outputs[last].clone().as_builder().capacity(change).build();
+                (tx.as_advanced_builder().set_outputs(outputs).build(), fee)
+            }
+            _ => {
+                // too little left for a change cell, it goes to the fee
+                let tx = apply_since(&self.tx(outputs, cells), &script_group, self.config.since())?;
+                let fee = estimator.estimate(&tx, &groups, None)?.fee;
+                if total < checked_sum([paid, fee])? {
+                    return Err(Error::InsufficientCapacity(format!(
+                        "{} shannons can't pay {} and {} fee",
+                        total, paid, fee
+                    )));
+                }
+                (tx, total - paid)
+            }
+        };
+        Ok(SigningRequest {
+            config: self.config.clone(),
+            tx,
+            script_group,
+            fee,
+            genesis_hash: None,
+        })
+    }
+}
+
+/// The payments of the transaction being filled and the cells taken for
+/// them so far, as ranges of the payments and of the cells.
+#[derive(Clone, Copy, Default)]
+struct Batch {
+    first_output: usize,
+    outputs: usize,
+    /// Serialized size of the outputs and their empty data.
+    size: u64,
+    paid: u64,
+    first_cell: usize,
+    inputs: usize,
+    total: u64,
+}
+
+impl Batch {
+    /// The batch with the payment #`index + 1` of `capacity` added.
+    fn with(&self, size: u64, capacity: u64, index: usize) -> Result<Batch, Error> {
+        let paid = self
+            .paid
+            .checked_add(capacity)
+            .ok_or_else(|| overflow(index))?;
+        Ok(Batch {
+            outputs: self.outputs + 1,
+            size: self.size + size,
+            paid,
+            ..*self
+        })
+    }
+
+    /// An empty batch after this one.
+    fn next(&self) -> Batch {
+        Batch {
+            first_output: self.first_output + self.outputs,
+            first_cell: self.first_cell + self.inputs,
+            ..Batch::default()
+        }
+    }
+
+    fn output_range(&self) -> Range<usize> {
+        self.first_output..self.first_output + self.outputs
+    }
+
+    fn cell_range(&self) -> Range<usize> {
+        self.first_cell..self.first_cell + self.inputs
+    }
+}
+
+/// What the planning of the batches needs to size and price them.
+struct Costs {
+    /// The transaction without inputs and outputs but with the witness.
+    base_size: u64,
+    change_size: u64,
+    change_occupied: u64,
+    estimator: FeeEstimator,
+}
+
+impl Costs {
+    fn size(&self, batch: &Batch, with_change: bool) -> u64 {
+        let change = if with_change { self.change_size } else { 0 };
+        self.base_size + batch.inputs as u64 * INPUT_SIZE + batch.size + change
+    }
+
+    fn fee(&self, batch: &Batch, with_change: bool) -> u64 {
+        self.estimator
+            .estimate_with_size(self.size(batch, with_change), None)
+            .fee
+    }
+
+    /// Take cells until the payments, the fee and a change cell are covered,
+    /// or at least the payments and the fee once the cells run out.
+    fn fund(&self, batch: &mut Batch, cells: &[LiveCell], index: usize) -> Result<(), Error> {
+        let needed = |batch: &Batch, with_change: bool| {
+            let change = if with_change { self.change_occupied } else { 0 };
+            checked_sum([batch.paid, self.fee(batch, with_change), change])
+                .map_err(|_| overflow(index))
+        };
+        while batch.total < needed(batch, true)? {
+            match cells.get(batch.first_cell + batch.inputs) {
+                Some(cell) => {
+                    batch.total = batch
+                        .total
+                        .checked_add(capacity(cell))
+                        .ok_or_else(|| overflow(index))?;
+                    batch.inputs += 1;
+                }
+                None if batch.total >= needed(batch, false)? => break,
+                None => {
+                    return Err(Error::InsufficientCapacity(format!(
+                        "{} cells can't pay the payment #{} and the ones before it",
+                        cells.len(),
+                        index + 1
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Size an output adds to a transaction: itself, its empty data and their
+/// offsets.
+fn output_size(output: &CellOutput) -> u64 {
+    (output.as_slice().len() + 4 + Bytes::new().pack().as_slice().len() + 4) as u64
+}
+
+fn overflow(index: usize) -> Error {
+    Error::InvalidParameter(format!(
+        "payment #{} overflows the capacity of a transaction",
+        index + 1
+    ))
+}
+
+fn checked_sum<I: IntoIterator<Item = u64>>(values: I) -> Result<u64, Error> {
+    values
+        .into_iter()
+        .try_fold(0u64, |sum, value| sum.checked_add(value))
+        .ok_or_else(|| Error::InvalidParameter("capacity overflow".to_string()))
+}
+
+fn occupied(output: &CellOutput) -> u64 {
+    output
+        .occupied_capacity(Capacity::zero())
+        .expect("occupied capacity")
+        .as_u64()
+}
+
+fn capacity(cell: &LiveCell) -> u64 {
+    output_capacity(&cell.output)
+}
+
+fn output_capacity(output: &CellOutput) -> u64 {
+    Unpack::<u64>::unpack(&output.capacity())
+}
@@ -0,0 +1,200 @@
+//! Fee bump of a stuck transaction, replace-by-fee style.
+//!
+//! The replacement spends the same inputs, so the node swaps it for the
+//! original once it pays at least the original fee plus the minimal RBF rate
+//! on its own size. The structure is kept: only the capacity of the change
+//! output goes down, and when the change can't pay for it, cells of the
+//! config are appended as inputs. Every other input, output and witness
+//! field stays as it was, so the cosigners only have to check the listed
+//! changes before signing again.
+//!
+//! The signing message covers the transaction hash, any change invalidates
+//! the signatures, the replacement comes back unsigned.
+
+use ckb_sdk::traits::LiveCell;
+use ckb_types::{
+    core::{Capacity, FeeRate},
+    packed::{CellInput, CellOutput, OutPoint},
+    prelude::*,
+};
+
+use crate::{
+    error::Error, fee::FeeEstimator, request::SigningRequest, since::apply_since, unlock::set_lock,
+    witness::MultisigLock,
+};
+
+/// The default `min_rbf_rate` of the CKB tx pool, in shannons per 1000 bytes.
+pub const DEFAULT_MIN_RBF_RATE: u64 = 1500;
+
+/// A difference between the original transaction and its replacement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// The capacity of an output went down, in shannons.
+    Output { index: usize, from: u64, to: u64 },
+    /// A cell of the config was added as input.
+    Input { out_point: OutPoint, capacity: u64 },
+}
+
+/// The unsigned replacement and how it differs from the original.
+#[derive(Clone, Debug)]
+pub struct FeeBump {
+    pub request: SigningRequest,
+    pub changes: Vec<Change>,
+}
+
+pub struct FeeBumper {
+    fee_rate: u64,
+    min_rbf_rate: u64,
+    change_index: Option<usize>,
+}
+
+impl FeeBumper {
+    /// `fee_rate` is the rate of the replacement in shannons per 1000 bytes,
+    /// the fee is raised further when the RBF rule asks for more.
+    pub fn new(fee_rate: u64) -> Self {
+        FeeBumper {
+            fee_rate,
+            min_rbf_rate: DEFAULT_MIN_RBF_RATE,
+            change_index: None,
+        }
+    }
+
+    /// The `min_rbf_rate` of the tx pool, when the node isn't on the default.
+    pub fn min_rbf_rate(mut self, min_rbf_rate: u64) -> Self {
+        self.min_rbf_rate = min_rbf_rate;
+        self
+    }
+
+    /// The output paying the fee, the last output of the config lock by
+    /// default.
+    pub fn change_index(mut self, index: usize) -> Self {
+        self.change_index = Some(index);
+        self
+    }
+
+    /// Rebuild `request` with a higher fee, `extra` are live cells of the
+    /// config taken in order when the change output is too small.
+    pub fn bump(&self, request: &SigningRequest, extra: &[LiveCell]) -> Result<FeeBump, Error> {
+        let lock_script = &request.script_group.script;
+        let tx = &request.tx;
+        let change_index = match self.change_index {
+            Some(index) if index < tx.outputs().len() => index,
+            Some(index) => {
+                return Err(Error::InvalidParameter(format!(
+                    "missing output #{}",
+                    index
+                )))
+            }
+            None => tx
+                .outputs()
+                .into_iter()
+                .enumerate()
+                .filter(|(_, output)| output.lock() == *lock_script)
+                .map(|(index, _)| index)
+                .last()
+                .ok_or_else(|| {
+                    Error::InvalidParameter("no output of the config lock to pay from".to_string())
+                })?,
+        };
+        let change = tx
+            .output(change_index)
+            .ok_or_else(|| Error::InvalidParameter(format!("missing output #{}", change_index)))?;
+        let change_capacity: u64 = change.capacity().unpack();
+        let data_len = tx
+            .outputs_data()
+            .get(change_index)
+            .map(|data| data.raw_data().len())
+            .unwrap_or_default();
+        let occupied = Capacity::bytes(data_len)
+            .and_then(|data| change.occupied_capacity(data))
+            .map_err(|err| Error::InvalidParameter(err.to_string()))?
+            .as_u64();
+        if !extra.is_empty() && tx.witnesses().len() > tx.inputs().len() {
+            // the witnesses beyond the inputs would become the witnesses of
+            // the new inputs
+            return Err(Error::InvalidParameter(
+                "can't add inputs to a transaction with extra witnesses".to_string(),
+            ));
+        }
+
+        let estimator = FeeEstimator::new(self.fee_rate);
+        let mut script_group = request.script_group.clone();
+        let mut inputs: Vec<CellInput> = tx.inputs().into_iter().collect();
+        let mut added = 0;
+        let mut added_capacity = 0;
+        loop {
+            let unsigned = tx.as_advanced_builder().set_inputs(inputs.clone()).build();
+            let unsigned = apply_since(&unsigned, &script_group, request.config.since())?;
+            let unsigned = set_lock(
+                &unsigned,
+                &script_group,
+                &MultisigLock::new(request.config.clone()),
+            )?;
+            let groups = [(script_group.clone(), request.config.clone())];
+            let estimate = estimator.estimate(&unsigned, &groups, None)?;
+            let rbf_fee = request
+                .fee
+                .checked_add(
+                    FeeRate::from_u64(self.min_rbf_rate)
+                        .fee(estimate.size)
+                        .as_u64(),
+                )
+                .ok_or_else(overflow)?;
+            let fee = estimate.fee.max(rbf_fee);
+            let available = change_capacity
+                .checked_add(added_capacity)
+                .ok_or_else(overflow)?;
+            let spendable = available.checked_add(request.fee).ok_or_else(overflow)?;
+            match spendable.checked_sub(fee) {
+                Some(new_capacity) if new_capacity >= occupied => {
+                    let mut outputs: Vec<CellOutput> = unsigned.outputs().into_iter().collect();
+                    outputs[change_index] =
+                        change.clone().as_builder().capacity(new_capacity).build();
+                    let mut changes = vec![Change::Output {
+                        index: change_index,
+                        from: change_capacity,
+                        to: new_capacity,
+                    }];
+                    changes.extend(extra[..added].iter().map(|cell| Change::Input {
+                        out_point: cell.out_point.clone(),
+                        capacity: cell.output.capacity().unpack(),
+                    }));
+                    return Ok(FeeBump {
+                        request: SigningRequest {
+                            config: request.config.clone(),
+                            tx: unsigned.as_advanced_builder().set_outputs(outputs).build(),
+                            script_group,
+                            fee,
+                            genesis_hash: request.genesis_hash.clone(),
+                        },
+                        changes,
+                    });
+                }
+                _ => {
+                    let cell = extra.get(added).ok_or_else(|| {
+                        Error::InsufficientCapacity(format!(
+                            "the change output #{} of {} shannons can't pay a fee of {}",
+                            change_index, available, fee
+                        ))
+                    })?;
+                    if cell.output.lock() != *lock_script {
+                        return Err(Error::InvalidParameter(format!(
+                            "cell {} is not guarded by the config",
+                            cell.out_point
+                        )));
+                    }
+                    script_group.input_indices.push(inputs.len());
+                    inputs.push(CellInput::new(cell.out_point.clone(), 0));
+                    added_capacity = Unpack::<u64>::unpack(&cell.output.capacity())
+                        .checked_add(added_capacity)
+                        .ok_or_else(overflow)?;
+                    added += 1;
+                }
+            }
+        }
+    }
+}
+
+fn overflow() -> Error {
+    Error::InvalidParameter("the capacity of the replacement overflows".to_string())
+}
@@ -0,0 +1,244 @@
+//! Cobuild messages describing what a transaction of a config does, so a
+//! hardware wallet can show "send 1,000 CKB to ckb1..." instead of a bare
+//! hash.
+//!
+//! The message holds one `Action` for the multisig lock, its data is a
+//! `MultisigAction` listing the outputs leaving the config:
+//!
+//! ```text
+//! option Byte32Opt (Byte32);
+//! table MultisigTransfer { to: Script, capacity: Uint64, type_hash: Byte32Opt }
+//! vector MultisigTransferVec <MultisigTransfer>;
+//! table MultisigAction { multisig_hash: Byte20, transfers: MultisigTransferVec }
+//! ```
+//!
+//! The message and the `SighashAll` witness layout are encoded as in the
+//! cobuild `basic.mol` schema, and `signing_message_hash` is the cobuild
+//! sighash. The multisig lock itself still verifies the legacy message of
+//! `digest::generate_message`: the cobuild message only tells the device what
+//! to display, and `check_message` lets it refuse a message that doesn't
+//! match the transaction it is asked to sign.
+
+use std::convert::TryInto;
+
+use ckb_hash::{blake2b_256, Blake2bBuilder};
+use ckb_sdk::{Address, AddressPayload, NetworkType};
+use ckb_types::{
+    bytes::Bytes,
+    core::TransactionView,
+    packed::{CellOutput, Script},
+    prelude::*,
+};
+
+use crate::{config::MultisigConfig, error::Error};
+
+pub const SIGHASH_ALL_PERSONALIZATION: &[u8] = b"ckb-tcob-sighash";
+pub const SIGHASH_ALL_ONLY_PERSONALIZATION: &[u8] = b"ckb-tcob-sgohash";
+
+/// Union ids of `WitnessLayout`.
+pub const WITNESS_LAYOUT_SIGHASH_ALL: u32 = 4278190081;
+pub const WITNESS_LAYOUT_SIGHASH_ALL_ONLY: u32 = 4278190082;
+
+pub const SCRIPT_NAME: &str = "ckb-multisig";
+pub const SCRIPT_URL: &str = "https://github.com/liuck8080/ckb-multisig";
+pub const MESSAGE_TYPE: &str = "MultisigAction";
+pub const ACTION_SCHEMA: &str = "option Byte32Opt (Byte32); \
+table MultisigTransfer { to: Script, capacity: Uint64, type_hash: Byte32Opt } \
+vector MultisigTransferVec <MultisigTransfer>; \
+table MultisigAction { multisig_hash: Byte20, transfers: MultisigTransferVec }";
+
+/// Capacity leaving the config, one per output of another lock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    pub to: Script,
+    /// In shannons.
+    pub capacity: u64,
+    pub type_hash: Option<[u8; 32]>,
+}
+
+impl Transfer {
+    /// The outputs of `tx` not locked by `lock_script`, change goes back to
+    /// the config and isn't listed.
+    pub fn from_tx(tx: &TransactionView, lock_script: &Script) -> Vec<Transfer> {
+        tx.outputs()
+            .into_iter()
+            .filter(|output| &output.lock() != lock_script)
+            .map(|output| Transfer::from_output(&output))
+            .collect()
+    }
+
+    fn from_output(output: &CellOutput) -> Transfer {
+        Transfer {
+            to: output.lock(),
+            capacity: output.capacity().unpack(),
+            type_hash: output.type_().to_opt().map(|script| {
+                script
+                    .calc_script_hash()
+                    .raw_data()
+                    .as_ref()
+                    .try_into()
+                    .expect("byte32")
+            }),
+        }
+    }
+
+    /// "send 1,000 CKB to ckb1...".
+    pub fn describe(&self, network: NetworkType) -> String {
+        let address = Address::new(network, AddressPayload::from(self.to.clone()), true);
+        let mut line = format!("send {} to {}", format_capacity(self.capacity), address);
+        if let Some(type_hash) = &self.type_hash {
+            line.push_str(&format!(" (type 0x{})", hex::encode(type_hash)));
+        }
+        line
+    }
+
+    fn to_molecule(&self) -> Vec<u8> {
+        let type_hash = self.type_hash.map(|hash| hash.to_vec()).unwrap_or_default();
+        table(&[self.to.as_slice(), &self.capacity.to_le_bytes(), &type_hash])
+    }
+}
+
+/// The descriptions of `transfers`, one line each.
+pub fn describe(transfers: &[Transfer], network: NetworkType) -> Vec<String> {
+    transfers
+        .iter()
+        .map(|transfer| transfer.describe(network))
+        .collect()
+}
+
+/// The `ScriptInfo` of the multisig lock, as encoded in molecule.
+pub fn script_info(lock_script: &Script) -> Vec<u8> {
+    table(&[
+        &bytes(SCRIPT_NAME.as_bytes()),
+        &bytes(SCRIPT_URL.as_bytes()),
+        lock_script.calc_script_hash().as_slice(),
+        &bytes(ACTION_SCHEMA.as_bytes()),
+        &bytes(MESSAGE_TYPE.as_bytes()),
+    ])
+}
+
+/// The `MultisigAction` of `tx`, the action data.
+pub fn action_data(config: &MultisigConfig, tx: &TransactionView, lock_script: &Script) -> Vec<u8> {
+    let transfers: Vec<_> = Transfer::from_tx(tx, lock_script)
+        .iter()
+        .map(Transfer::to_molecule)
+        .collect();
+    let transfers: Vec<&[u8]> = transfers.iter().map(Vec::as_slice).collect();
+    table(&[&config.hash160(), &table(&transfers)])
+}
+
+/// The cobuild `Message` of `tx`, holding the action of the multisig lock.
+pub fn message(config: &MultisigConfig, tx: &TransactionView, lock_script: &Script) -> Vec<u8> {
+    let action = table(&[
+        &blake2b_256(script_info(lock_script)),
+        lock_script.calc_script_hash().as_slice(),
+        &bytes(&action_data(config, tx, lock_script)),
+    ]);
+    table(&[&table(&[&action])])
+}
+
+/// Refuse a `message` which isn't the one of `tx`.
+pub fn check_message(
+    config: &MultisigConfig,
+    tx: &TransactionView,
+    lock_script: &Script,
+    message: &[u8],
+) -> Result<(), Error> {
+    if message != self::message(config, tx, lock_script).as_slice() {
+        return Err(Error::Verification(format!(
+            "the cobuild message doesn't describe transaction {:#x}",
+            tx.hash()
+        )));
+    }
+    Ok(())
+}
+
+/// The `WitnessLayout::SighashAll` witness carrying `message` and `seal`.
+pub fn sighash_all_witness(message: &[u8], seal: &[u8]) -> Bytes {
+    let mut witness = WITNESS_LAYOUT_SIGHASH_ALL.to_le_bytes().to_vec();
+    witness.extend_from_slice(&table(&[&bytes(seal), message]));
+    witness.into()
+}
+
+/// The cobuild signing message of `tx`, `inputs` are the resolved input
+/// cells and their data in the input order. Without `message` this is the
+/// `SighashAllOnly` hash.
+pub fn signing_message_hash(
+    tx: &TransactionView,
+    inputs: &[(CellOutput, Bytes)],
+    message: Option<&[u8]>,
+) -> Result<[u8; 32], Error> {
+    if inputs.len() != tx.inputs().len() {
+        return Err(Error::InvalidParameter(format!(
+            "{} resolved inputs for {} inputs",
+            inputs.len(),
+            tx.inputs().len()
+        )));
+    }
+    let personalization = match message {
+        Some(_) => SIGHASH_ALL_PERSONALIZATION,
+        None => SIGHASH_ALL_ONLY_PERSONALIZATION,
+    };
+    let mut hasher = Blake2bBuilder::new(32).personal(personalization).build();
+    if let Some(message) = message {
+        hasher.update(message);
+    }
+    hasher.update(tx.hash().as_slice());
+    for (output, data) in inputs {
+        hasher.update(output.as_slice());
+        hasher.update(&(data.len() as u32).to_le_bytes());
+        hasher.update(data);
+    }
+    for witness in tx.witnesses().into_iter().skip(inputs.len()) {
+        let witness = witness.raw_data();
+        hasher.update(&(witness.len() as u32).to_le_bytes());
+        hasher.update(&witness);
+    }
+    let mut hash = [0u8; 32];
+    hasher.finalize(&mut hash);
+    Ok(hash)
+}
+
+/// Shannons as CKB with thousands separators and without trailing zeros,
+/// e.g. "1,000 CKB" or "0.5 CKB".
+pub fn format_capacity(shannons: u64) -> String {
+    let whole = (shannons / 100_000_000).to_string();
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    let fraction = shannons % 100_000_000;
+    if fraction != 0 {
+        let fraction = format!("{:08}", fraction);
+        grouped.push('.');
+        grouped.push_str(fraction.trim_end_matches('0'));
+    }
+    format!("{} CKB", grouped)
+}
+
+/// A molecule table, or dynvec, of already encoded fields.
+fn table(fields: &[&[u8]]) -> Vec<u8> {
+    let header = 4 * (fields.len() + 1);
+    let total = header + fields.iter().map(|field| field.len()).sum::<usize>();
+    let mut out = Vec::with_capacity(total);
+    out.extend_from_slice(&(total as u32).to_le_bytes());
+    let mut offset = header;
+    for field in fields {
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    for field in fields {
+        out.extend_from_slice(field);
+    }
+    out
+}
+
+/// Molecule `Bytes`.
+fn bytes(data: &[u8]) -> Vec<u8> {
+    let mut out = (data.len() as u32).to_le_bytes().to_vec();
+    out.extend_from_slice(data);
+    out
+}
@@ -0,0 +1,203 @@
+//! A ckb-sdk `CellCollector` honoring the since of the lock args: the cells
+//! of this lock are only collected once their since is mature at the tip, so
+//! the ckb-sdk builders and balancers never pick a cell the contract would
+//! reject. The cells left out are kept, with their maturity, in
+//! `MaturedCellCollector::immature`.
+//!
+//! ```ignore
+//! let collector = MaturedCellCollector::new(
+//!     BackendCellCollector::new(backend.clone()),
+//!     BackendProvider::new(backend),
+//!     &code_hash,
+//!     ScriptHashType::Type,
+//! );
+//! ```
+//!
+//! Cells of other locks, and queries by type script, go to the inner
+//! collector as is.
+
+use std::sync::Arc;
+
+use ckb_sdk::{
+    traits::{CellCollector, CellCollectorError, CellQueryOptions, LiveCell, PrimaryScriptType},
+    types::Since,
+    CkbRpcClient,
+};
+use ckb_types::{
+    core::{HeaderView, ScriptHashType},
+    packed::{OutPoint, Script, Transaction},
+    prelude::*,
+    H256,
+};
+
+use crate::{
+    constants::BLAKE160_SIZE,
+    error::Error,
+    scanner::{BlockInfo, Maturity, MultisigCell},
+};
+
+/// The tip and the blocks the maturity of a cell is evaluated against.
+pub trait BlockInfoProvider: Send + Sync {
+    fn tip(&self) -> Result<BlockInfo, Error>;
+    fn block(&self, number: u64) -> Result<BlockInfo, Error>;
+}
+
+impl BlockInfoProvider for CkbRpcClient {
+    fn tip(&self) -> Result<BlockInfo, Error> {
+        let tip: HeaderView = self.get_tip_header()?.into();
+        Ok(BlockInfo::from(&tip))
+    }
+
+    fn block(&self, number: u64) -> Result<BlockInfo, Error> {
+        let header: HeaderView = self
+            .get_header_by_number(number.into())?
+            .ok_or_else(|| Error::Rpc(format!("block #{} not found", number)))?
+            .into();
+        Ok(BlockInfo::from(&header))
+    }
+}
+
+/// The since of lock args `hash | since | ...`, `None` when the args carry
+/// the multisig hash alone.
+pub fn lock_args_since(args: &[u8]) -> Option<u64> {
+    let since = args.get(BLAKE160_SIZE..BLAKE160_SIZE + 8)?;
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(since);
+    Some(u64::from_le_bytes(bytes))
+}
+
+#[derive(Clone)]
+pub struct MaturedCellCollector<C> {
+    inner: C,
+    blocks: Arc<dyn BlockInfoProvider>,
+    code_hash: H256,
+    hash_type: ScriptHashType,
+    immature: Vec<MultisigCell>,
+}
+
+impl<C: CellCollector + Clone> MaturedCellCollector<C> {
+    /// `code_hash` and `hash_type` are those of the deployed contract, the
+    /// locks whose since is honored.
+    pub fn new<B>(inner: C, blocks: B, code_hash: &H256, hash_type: ScriptHashType) -> Self
+    where
+        B: BlockInfoProvider + 'static,
+    {
+        MaturedCellCollector {
+            inner,
+            blocks: Arc::new(blocks),
+            code_hash: code_hash.clone(),
+            hash_type,
+            immature: Vec::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// The cells the last collect of a lock with a since left out, with
+    /// when they mature.
+    pub fn immature(&self) -> &[MultisigCell] {
+        &self.immature
+    }
+
+    /// The since of `lock` when it is this contract, a since of 0 being none.
+    fn since_of(&self, lock: &Script) -> Option<u64> {
+        if lock.code_hash() != self.code_hash.pack() || lock.hash_type() != self.hash_type.into() {
+            return None;
+        }
+        lock_args_since(&lock.args().raw_data()).filter(|since| *since != 0)
+    }
+
+    /// The mature cells among `cells` until `min_total_capacity` is reached,
+    /// the immature ones are recorded.
+    fn select(
+        &mut self,
+        since: u64,
+        cells: Vec<LiveCell>,
+        min_total_capacity: u64,
+        tip: &BlockInfo,
+    ) -> Result<(Vec<LiveCell>, u64), Error> {
+        self.immature.clear();
+        // only relative since values need the commit block
+        let relative = Since::from_raw_value(since).is_relative();
+        let mut selected = Vec::new();
+        let mut total = 0u64;
+        for cell in cells {
+            let commit = if relative {
+                self.blocks.block(cell.block_number)?
+            } else {
+                *tip
+            };
+            let maturity = Maturity::evaluate(Some(since), &commit, tip);
+            if !maturity.is_mature() {
+                self.immature.push(MultisigCell { cell, maturity });
+            } else if total < min_total_capacity {
+                total += Unpack::<u64>::unpack(&cell.output.capacity());
+                selected.push(cell);
+            }
+        }
+        Ok((selected, total))
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: CellCollector + Clone> CellCollector for MaturedCellCollector<C> {
+    async fn collect_live_cells_async(
+        &mut self,
+        query: &CellQueryOptions,
+        apply_changes: bool,
+    ) -> Result<(Vec<LiveCell>, u64), CellCollectorError> {
+        let since = match query.primary_type {
+            PrimaryScriptType::Lock => self.since_of(&query.primary_script),
+            PrimaryScriptType::Type => None,
+        };
+        let since = match since {
+            Some(since) => since,
+            None => {
+                return self
+                    .inner
+                    .collect_live_cells_async(query, apply_changes)
+                    .await
+            }
+        };
+        // every cell of the query, the immature ones must not count
+        let mut all = query.clone();
+        all.min_total_capacity = u64::MAX;
+        let (cells, _) = self.inner.collect_live_cells_async(&all, false).await?;
+        let tip = self
+            .blocks
+            .tip()
+            .map_err(|err| CellCollectorError::Other(err.into()))?;
+        let (cells, total) = self
+            .select(since, cells, query.min_total_capacity, &tip)
+            .map_err(|err| CellCollectorError::Other(err.into()))?;
+        if apply_changes {
+            for cell in &cells {
+                self.inner.lock_cell(cell.out_point.clone(), tip.number)?;
+            }
+        }
+        Ok((cells, total))
+    }
+
+    fn lock_cell(
+        &mut self,
+        out_point: OutPoint,
+        tip_block_number: u64,
+    ) -> Result<(), CellCollectorError> {
+        self.inner.lock_cell(out_point, tip_block_number)
+    }
+
+    fn apply_tx(
+        &mut self,
+        tx: Transaction,
+        tip_block_number: u64,
+    ) -> Result<(), CellCollectorError> {
+        self.inner.apply_tx(tx, tip_block_number)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.immature.clear();
+    }
+}
@@ -0,0 +1,574 @@
+use std::collections::HashSet;
+
+use ckb_types::{bytes::Bytes, core::ScriptHashType, packed::Script, prelude::*, H256};
+use secp256k1::PublicKey;
+
+use crate::{
+    blake160,
+    config_tree::ConfigProof,
+    constants::{BLAKE160_SIZE, FLAGS_SIZE, SIGNATURE_SIZE, U64_SIZE},
+    error::Error,
+    signer::{pubkey_identity, uncompressed_pubkey_identity},
+    successor::Successor,
+};
+
+/// The flag of the lock args checking a timestamp since against header deps.
+pub const HEADER_TIME_FLAG: u8 = 0b0000_0001;
+/// The flag of the lock args refusing non empty witnesses at the inputs of a
+/// script group but the first.
+pub const STRICT_WITNESS_FLAG: u8 = 0b0000_0010;
+/// The flag of the lock args requiring every live cell of the lock to be
+/// spent together, see `witness::set_spend_all_total`.
+pub const SPEND_ALL_FLAG: u8 = 0b0000_0100;
+/// The flag of the lock args followed by a `Successor`.
+pub const SUCCESSOR_FLAG: u8 = 0b0000_1000;
+/// The flag of the lock args accepting the witness layouts of older wallets,
+/// see `witness::legacy_lock_index`.
+pub const LEGACY_WITNESS_FLAG: u8 = 0b0001_0000;
+
+/// The first byte of a lock field whose config is molecule encoded, above
+/// every key format.
+pub const CONFIG_V2: u8 = 0x80;
+
+/// The first byte of a lock field proving its config is one of the Merkle
+/// root of the lock args, see `config_tree.rs`.
+pub const CONFIG_PROOF: u8 = 0x81;
+
+/// Size of the table header of `MultisigConfigV2`: its total size and the
+/// offsets of its 4 fields.
+const CONFIG_V2_HEADER_SIZE: usize = 5 * 4;
+/// Offset of the item count of the pubkey hashes, after 3 byte fields.
+const CONFIG_V2_HASHES_OFFSET: usize = CONFIG_V2_HEADER_SIZE + 3;
+const CONFIG_V2_MIN_SIZE: usize = CONFIG_V2_HASHES_OFFSET + 4;
+
+/// What the multisig hash of the lock args commits to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ConfigEncoding {
+    /// The multisig script, its fields concatenated at fixed offsets.
+    #[default]
+    Script,
+    /// The canonical molecule encoding of the config, see
+    /// `MultisigConfig::molecule_config`. The lock field starts with
+    /// `CONFIG_V2` followed by it.
+    Molecule,
+}
+
+/// How the keys are hashed in the multisig script, its first byte. Some
+/// legacy HSMs only export uncompressed keys, the contract then hashes the
+/// 65 bytes serialization of the keys it recovers, or tries both.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum KeyFormat {
+    /// blake160 of the 33 bytes compressed public key.
+    #[default]
+    Compressed = 0,
+    /// blake160 of the 65 bytes uncompressed public key.
+    Uncompressed = 1,
+    /// Either of them, each member listed in its own format.
+    Either = 2,
+}
+
+impl KeyFormat {
+    pub fn from_u8(value: u8) -> Result<Self, Error> {
+        match value {
+            0 => Ok(KeyFormat::Compressed),
+            1 => Ok(KeyFormat::Uncompressed),
+            2 => Ok(KeyFormat::Either),
+            _ => Err(Error::InvalidConfig(format!(
+                "key format must be 0, 1 or 2, got {}",
+                value
+            ))),
+        }
+    }
+
+    /// The hashes of `pubkey` the contract accepts in this format, the
+    /// compressed one first.
+    pub fn identities(self, pubkey: &PublicKey) -> Vec<[u8; BLAKE160_SIZE]> {
+        match self {
+            KeyFormat::Compressed => vec![pubkey_identity(pubkey)],
+            KeyFormat::Uncompressed => vec![uncompressed_pubkey_identity(pubkey)],
+            KeyFormat::Either => vec![
+                pubkey_identity(pubkey),
+                uncompressed_pubkey_identity(pubkey),
+            ],
+        }
+    }
+}
+
+/// A multisig configuration, the off-chain counterpart of the multisig script
+/// carried in the witness and committed to by the lock args.
+///
+/// The multisig script is laid out as:
+///
+/// ```text
+/// key format | require_first_n | threshold | pubkeys_cnt | blake160(pubkey_1) | ... | blake160(pubkey_n)
+/// ```
+///
+/// the key format 0 unless keys are hashed uncompressed, see `KeyFormat`,
+/// and the lock args are `blake160(multisig script)`, optionally followed by a
+/// little endian since value, the blake160 of a fee key, a flags byte and a
+/// `Successor`, the since 0 when only the fee key or the flags are set.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MultisigConfig {
+    pubkey_hashes: Vec<[u8; BLAKE160_SIZE]>,
+    require_first_n: u8,
+    threshold: u8,
+    since: Option<u64>,
+    fee_key: Option<[u8; BLAKE160_SIZE]>,
+    header_time: bool,
+    strict_witnesses: bool,
+    spend_all: bool,
+    successor: Option<Successor>,
+    legacy_witnesses: bool,
+    key_format: KeyFormat,
+    encoding: ConfigEncoding,
+    proof: Option<ConfigProof>,
+}
+
+impl MultisigConfig {
+    /// Same rules as the contract, and in addition duplicated keys are
+    /// refused: a key listed twice could fill two signature slots alone.
+    pub fn new(
+        pubkey_hashes: Vec<[u8; BLAKE160_SIZE]>,
+        require_first_n: u8,
+        threshold: u8,
+    ) -> Result<Self, Error> {
+        if pubkey_hashes.is_empty() || pubkey_hashes.len() > usize::from(u8::MAX) {
+            return Err(Error::InvalidConfig(format!(
+                "pubkeys count {} out of range 1..=255",
+                pubkey_hashes.len()
+            )));
+        }
+        if threshold == 0 || usize::from(threshold) > pubkey_hashes.len() {
+            return Err(Error::InvalidConfig(format!(
+                "threshold {} out of range 1..={}",
+                threshold,
+                pubkey_hashes.len()
+            )));
+        }
+        if require_first_n > threshold {
+            return Err(Error::InvalidConfig(format!(
+                "require_first_n {} is greater than threshold {}",
+                require_first_n, threshold
+            )));
+        }
+        let mut seen = HashSet::with_capacity(pubkey_hashes.len());
+        if let Some(dup) = pubkey_hashes.iter().find(|hash| !seen.insert(**hash)) {
+            return Err(Error::InvalidConfig(format!(
+                "duplicated pubkey hash 0x{}",
+                hex::encode(dup)
+            )));
+        }
+        Ok(MultisigConfig {
+            pubkey_hashes,
+            require_first_n,
+            threshold,
+            since: None,
+            fee_key: None,
+            header_time: false,
+            strict_witnesses: false,
+            spend_all: false,
+            successor: None,
+            legacy_witnesses: false,
+            key_format: KeyFormat::Compressed,
+            encoding: ConfigEncoding::Script,
+            proof: None,
+        })
+    }
+
+    /// Parse the multisig script part of a witness lock field, or
+    /// `CONFIG_V2` followed by a molecule config, either of them after a
+    /// config proof.
+    pub fn from_multisig_script(script: &[u8]) -> Result<Self, Error> {
+        if script.first() == Some(&CONFIG_PROOF) {
+            let (proof, script) = ConfigProof::parse(script)?;
+            if script.first() == Some(&CONFIG_PROOF) {
+                return Err(Error::InvalidConfig("nested config proof".to_string()));
+            }
+            return Ok(Self::from_multisig_script(script)?.with_proof(Some(proof)));
+        }
+        if script.first() == Some(&CONFIG_V2) {
+            return Self::from_molecule_config(&script[1..]);
+        }
+        if script.len() < FLAGS_SIZE {
+            return Err(Error::InvalidConfig(
+                "multisig script too short".to_string(),
+            ));
+        }
+        let key_format = KeyFormat::from_u8(script[0])?;
+        let pubkeys_cnt = usize::from(script[3]);
+        if script.len() != FLAGS_SIZE + BLAKE160_SIZE * pubkeys_cnt {
+            return Err(Error::InvalidConfig(format!(
+                "multisig script length {} doesn't match {} pubkeys",
+                script.len(),
+                pubkeys_cnt
+            )));
+        }
+        let pubkey_hashes = script[FLAGS_SIZE..]
+            .chunks(BLAKE160_SIZE)
+            .map(|chunk| {
+                let mut hash = [0u8; BLAKE160_SIZE];
+                hash.copy_from_slice(chunk);
+                hash
+            })
+            .collect();
+        Ok(Self::new(pubkey_hashes, script[1], script[2])?.with_key_format(key_format))
+    }
+
+    /// Parse a config in its canonical molecule encoding, any other encoding
+    /// of the same table is refused as the contract does.
+    pub fn from_molecule_config(config: &[u8]) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidConfig(format!("molecule config {}", reason));
+        if config.len() < CONFIG_V2_MIN_SIZE {
+            return Err(invalid("too short"));
+        }
+        let pubkeys_cnt = read_u32(config, CONFIG_V2_HASHES_OFFSET);
+        if pubkeys_cnt > usize::from(u8::MAX) {
+            return Err(invalid(&format!("with {} pubkeys", pubkeys_cnt)));
+        }
+        if config.len() != CONFIG_V2_MIN_SIZE + BLAKE160_SIZE * pubkeys_cnt {
+            return Err(invalid(&format!(
+                "length {} doesn't match {} pubkeys",
+                config.len(),
+                pubkeys_cnt
+            )));
+        }
+        if config[..CONFIG_V2_HEADER_SIZE] != config_v2_header(pubkeys_cnt) {
+            return Err(invalid("header isn't canonical"));
+        }
+        let pubkey_hashes = config[CONFIG_V2_MIN_SIZE..]
+            .chunks(BLAKE160_SIZE)
+            .map(|chunk| {
+                let mut hash = [0u8; BLAKE160_SIZE];
+                hash.copy_from_slice(chunk);
+                hash
+            })
+            .collect();
+        let fields = &config[CONFIG_V2_HEADER_SIZE..];
+        Ok(Self::new(pubkey_hashes, fields[1], fields[2])?
+            .with_key_format(KeyFormat::from_u8(fields[0])?)
+            .with_encoding(ConfigEncoding::Molecule))
+    }
+
+    /// Lock the cells with an absolute or relative since value.
+    pub fn with_since(mut self, since: Option<u64>) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Let the key of `fee_key` alone sign transactions paying fees from
+    /// other inputs, which keep the capacity of the cells of this config in
+    /// plain cells of the same lock.
+    pub fn with_fee_key(mut self, fee_key: Option<[u8; BLAKE160_SIZE]>) -> Self {
+        self.fee_key = fee_key;
+        self
+    }
+
+    /// Check a timestamp since against the median time of header deps
+    /// rather than the since of the inputs, see `since::add_median_time_headers`.
+    pub fn with_header_time(mut self, header_time: bool) -> Self {
+        self.header_time = header_time;
+        self
+    }
+
+    /// Refuse transactions carrying data in the witnesses of the inputs of a
+    /// script group but the first, see `witness::stuffed_witnesses`.
+    pub fn with_strict_witnesses(mut self, strict_witnesses: bool) -> Self {
+        self.strict_witnesses = strict_witnesses;
+        self
+    }
+
+    /// Spend all the live cells of the lock at once, the first witness of the
+    /// group declaring their count, see `witness::set_spend_all_total`.
+    pub fn with_spend_all(mut self, spend_all: bool) -> Self {
+        self.spend_all = spend_all;
+        self
+    }
+
+    /// Let the reduced quorum of `successor` move the funds into the
+    /// successor cell, the full quorum keeps its powers.
+    pub fn with_successor(mut self, successor: Option<Successor>) -> Self {
+        self.successor = successor;
+        self
+    }
+
+    /// Accept the lock field in a later witness of the group, after
+    /// placeholders, and empty `WitnessArgs` as empty witnesses, as older
+    /// wallets lay them out.
+    pub fn with_legacy_witnesses(mut self, legacy_witnesses: bool) -> Self {
+        self.legacy_witnesses = legacy_witnesses;
+        self
+    }
+
+    /// How the keys are hashed, part of the multisig script.
+    pub fn with_key_format(mut self, key_format: KeyFormat) -> Self {
+        self.key_format = key_format;
+        self
+    }
+
+    /// What the multisig hash commits to, part of the lock args.
+    pub fn with_encoding(mut self, encoding: ConfigEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Prove the config is one of the Merkle root of the lock args, see
+    /// `ConfigTree::config`.
+    pub fn with_proof(mut self, proof: Option<ConfigProof>) -> Self {
+        self.proof = proof;
+        self
+    }
+
+    pub fn pubkey_hashes(&self) -> &[[u8; BLAKE160_SIZE]] {
+        &self.pubkey_hashes
+    }
+
+    pub fn require_first_n(&self) -> u8 {
+        self.require_first_n
+    }
+
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    pub fn since(&self) -> Option<u64> {
+        self.since
+    }
+
+    pub fn fee_key(&self) -> Option<&[u8; BLAKE160_SIZE]> {
+        self.fee_key.as_ref()
+    }
+
+    pub fn header_time(&self) -> bool {
+        self.header_time
+    }
+
+    pub fn strict_witnesses(&self) -> bool {
+        self.strict_witnesses
+    }
+
+    pub fn spend_all(&self) -> bool {
+        self.spend_all
+    }
+
+    pub fn successor(&self) -> Option<&Successor> {
+        self.successor.as_ref()
+    }
+
+    pub fn legacy_witnesses(&self) -> bool {
+        self.legacy_witnesses
+    }
+
+    pub fn key_format(&self) -> KeyFormat {
+        self.key_format
+    }
+
+    pub fn encoding(&self) -> ConfigEncoding {
+        self.encoding
+    }
+
+    pub fn proof(&self) -> Option<&ConfigProof> {
+        self.proof.as_ref()
+    }
+
+    /// Position of the key in the config, if it is a member.
+    pub fn position(&self, pubkey_hash: &[u8; BLAKE160_SIZE]) -> Option<usize> {
+        self.pubkey_hashes
+            .iter()
+            .position(|hash| hash == pubkey_hash)
+    }
+
+    /// The identity of `pubkey` in this config: the hash listed for it when
+    /// it is a member, else its hash in the first format the config accepts.
+    pub fn identity(&self, pubkey: &PublicKey) -> [u8; BLAKE160_SIZE] {
+        let identities = self.key_format.identities(pubkey);
+        identities
+            .iter()
+            .find(|identity| self.position(identity).is_some())
+            .unwrap_or(&identities[0])
+            .to_owned()
+    }
+
+    /// The multisig script put at the head of the witness lock field, or
+    /// `CONFIG_V2` followed by the molecule config, after the config proof
+    /// if any.
+    pub fn multisig_script(&self) -> Bytes {
+        let mut script = Vec::with_capacity(self.multisig_script_len());
+        if let Some(proof) = &self.proof {
+            script.extend_from_slice(&proof.to_bytes());
+        }
+        if self.encoding == ConfigEncoding::Molecule {
+            script.push(CONFIG_V2);
+        }
+        script.extend_from_slice(&self.committed_bytes());
+        script.into()
+    }
+
+    /// What the multisig hash commits to, or the leaf of a config tree: the
+    /// multisig script without proof, or the molecule config.
+    pub(crate) fn committed_bytes(&self) -> Bytes {
+        if self.encoding == ConfigEncoding::Molecule {
+            return self.molecule_config();
+        }
+        let mut script = Vec::with_capacity(FLAGS_SIZE + BLAKE160_SIZE * self.pubkey_hashes.len());
+        script.extend_from_slice(&[
+            self.key_format as u8,
+            self.require_first_n,
+            self.threshold,
+            self.pubkey_hashes.len() as u8,
+        ]);
+        for hash in &self.pubkey_hashes {
+            script.extend_from_slice(hash);
+        }
+        script.into()
+    }
+
+    pub fn multisig_script_len(&self) -> usize {
+        self.proof.as_ref().map_or(0, ConfigProof::encoded_len)
+            + match self.encoding {
+                ConfigEncoding::Script => FLAGS_SIZE + BLAKE160_SIZE * self.pubkey_hashes.len(),
+                ConfigEncoding::Molecule => {
+                    1 + CONFIG_V2_MIN_SIZE + BLAKE160_SIZE * self.pubkey_hashes.len()
+                }
+            }
+    }
+
+    /// The canonical molecule encoding of the config, whatever its encoding:
+    ///
+    /// ```text
+    /// array Byte20 [byte; 20];
+    /// vector Byte20Vec <Byte20>;
+    /// table MultisigConfigV2 {
+    ///     key_format: byte,
+    ///     require_first_n: byte,
+    ///     threshold: byte,
+    ///     pubkey_hashes: Byte20Vec,
+    /// }
+    /// ```
+    pub fn molecule_config(&self) -> Bytes {
+        let pubkeys_cnt = self.pubkey_hashes.len();
+        let mut config = config_v2_header(pubkeys_cnt);
+        config.extend_from_slice(&[self.key_format as u8, self.require_first_n, self.threshold]);
+        config.extend_from_slice(&(pubkeys_cnt as u32).to_le_bytes());
+        for hash in &self.pubkey_hashes {
+            config.extend_from_slice(hash);
+        }
+        config.into()
+    }
+
+    /// Length of the complete witness lock field: script and all signatures.
+    pub fn lock_len(&self) -> usize {
+        self.multisig_script_len() + SIGNATURE_SIZE * usize::from(self.threshold)
+    }
+
+    /// blake160 of the multisig script, or of the molecule config, or the
+    /// root of the config tree given a proof.
+    pub fn hash160(&self) -> [u8; BLAKE160_SIZE] {
+        match &self.proof {
+            Some(proof) => proof.root(self),
+            None => blake160(&self.committed_bytes()),
+        }
+    }
+
+    pub fn lock_args(&self) -> Bytes {
+        let mut args = Vec::with_capacity(BLAKE160_SIZE + U64_SIZE + BLAKE160_SIZE + 1);
+        args.extend_from_slice(&self.hash160());
+        let flags = self.flags();
+        if self.since.is_some() || self.fee_key.is_some() || flags != 0 {
+            args.extend_from_slice(&self.since.unwrap_or(0).to_le_bytes());
+        }
+        if let Some(fee_key) = &self.fee_key {
+            args.extend_from_slice(fee_key);
+        }
+        if flags != 0 {
+            args.push(flags);
+        }
+        if let Some(successor) = &self.successor {
+            args.extend_from_slice(&successor.multisig_hash);
+            args.extend_from_slice(&successor.type_hash);
+        }
+        args.into()
+    }
+
+    /// The flags byte of the lock args, 0 when it is left out.
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.header_time {
+            flags |= HEADER_TIME_FLAG;
+        }
+        if self.strict_witnesses {
+            flags |= STRICT_WITNESS_FLAG;
+        }
+        if self.spend_all {
+            flags |= SPEND_ALL_FLAG;
+        }
+        if self.successor.is_some() {
+            flags |= SUCCESSOR_FLAG;
+        }
+        if self.legacy_witnesses {
+            flags |= LEGACY_WITNESS_FLAG;
+        }
+        flags
+    }
+
+    /// The lock script guarding cells of this config, `code_hash` and
+    /// `hash_type` identify the deployed contract.
+    pub fn lock_script(&self, code_hash: &H256, hash_type: ScriptHashType) -> Script {
+        Script::new_builder()
+            .code_hash(code_hash.pack())
+            .hash_type(hash_type)
+            .args(self.lock_args().pack())
+            .build()
+    }
+
+    /// Lock field with all the signature slots zero filled, used both to
+    /// reserve the witness size and to compute the signing message.
+    pub fn placeholder_lock(&self) -> Bytes {
+        let mut lock = self.multisig_script().to_vec();
+        lock.resize(self.lock_len(), 0);
+        lock.into()
+    }
+}
+
+/// The table header of a `MultisigConfigV2` of `pubkeys_cnt` keys.
+fn config_v2_header(pubkeys_cnt: usize) -> Vec<u8> {
+    let total = CONFIG_V2_MIN_SIZE + BLAKE160_SIZE * pubkeys_cnt;
+    [
+        total,
+        CONFIG_V2_HEADER_SIZE,
+        CONFIG_V2_HEADER_SIZE + 1,
+        CONFIG_V2_HEADER_SIZE + 2,
+        CONFIG_V2_HASHES_OFFSET,
+    ]
+    .iter()
+    .flat_map(|word| (*word as u32).to_le_bytes())
+    .collect()
+}
+
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    let mut word = [0u8; 4];
+    word.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(word) as usize
+}
+
+/// Length of the multisig script, or of `CONFIG_V2` and the molecule
+/// config, at the head of a lock field, `None` when too short to tell.
+pub(crate) fn multisig_script_len_of(lock: &[u8]) -> Option<usize> {
+    if lock.first() == Some(&CONFIG_PROOF) {
+        let (proof, rest) = ConfigProof::parse(lock).ok()?;
+        if rest.first() == Some(&CONFIG_PROOF) {
+            return None;
+        }
+        return multisig_script_len_of(rest)?.checked_add(proof.encoded_len());
+    }
+    if lock.first() == Some(&CONFIG_V2) {
+        if lock.len() < 1 + CONFIG_V2_MIN_SIZE {
+            return None;
+        }
+        let pubkeys_cnt = read_u32(lock, 1 + CONFIG_V2_HASHES_OFFSET);
+        return BLAKE160_SIZE
+            .checked_mul(pubkeys_cnt)?
+            .checked_add(1 + CONFIG_V2_MIN_SIZE);
+    }
+    lock.get(3)
+        .map(|cnt| FLAGS_SIZE + BLAKE160_SIZE * usize::from(*cnt))
+}
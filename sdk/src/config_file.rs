@@ -0,0 +1,368 @@
+//! Versioned file format of `MultisigConfig`, as JSON or TOML.
+//!
+//! ```toml
+//! version = 1
+//! # or "secp256k1-blake160-uncompressed" for keys hashed uncompressed, or
+//! # "secp256k1-blake160-either" for a mix, see `config::KeyFormat`
+//! algorithm = "secp256k1-blake160"
+//! require_first_n = 1
+//! threshold = 2
+//! # optional, readable as in `since::SinceSpec` or a raw `0x` prefixed value
+//! since = "after epoch 180"
+//! # optional, the blake160 of the fee key
+//! fee_key = "0x..."
+//! # optional, check a timestamp since against the header deps
+//! header_time = true
+//! # optional, refuse data in the witnesses of the group but the first
+//! strict_witnesses = true
+//! # optional, spend all the live cells of the lock at once
+//! spend_all = true
+//! # optional, accept the witness layouts of older wallets
+//! legacy_witnesses = true
+//! # optional, "molecule" to hash the canonical molecule encoding of the
+//! # config instead of the multisig script, see `config::ConfigEncoding`
+//! encoding = "molecule"
+//! # optional, the proof of the config in a tree of configs, see
+//! # `config_tree::ConfigTree`, the lock args then hold the root
+//! proof = "0x81..."
+//!
+//! # optional, the reduced quorum allowed to move the funds into the single
+//! # cell of a type, see `successor::Successor`
+//! [successor]
+//! multisig_hash = "0x..."
+//! type_hash = "0x..."
+//!
+//! [[keys]]
+//! pubkey_hash = "0x..."
+//! # optional, checked against the hash when present
+//! pubkey = "0x..."
+//! # optional
+//! label = "alice"
+//! ```
+//!
+//! Keys are listed in the order of the multisig script, the first
+//! `require_first_n` of them must sign. Unknown fields are refused, so a
+//! config written by a newer tool is never read with parts silently ignored,
+//! and a `version` greater than `CONFIG_VERSION` is refused as well.
+
+use std::convert::TryInto;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{ConfigEncoding, KeyFormat, MultisigConfig},
+    config_tree::ConfigProof,
+    constants::BLAKE160_SIZE,
+    error::Error,
+    since::{format_since, parse_since, SinceSpec},
+    successor::Successor,
+};
+
+/// The version written by this SDK.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// How the keys are identified in the multisig script.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Algorithm {
+    /// blake160 of the compressed secp256k1 public key.
+    Secp256k1Blake160,
+    /// blake160 of the uncompressed secp256k1 public key.
+    Secp256k1Blake160Uncompressed,
+    /// blake160 of either serialization, key by key.
+    Secp256k1Blake160Either,
+}
+
+impl Algorithm {
+    pub fn key_format(self) -> KeyFormat {
+        match self {
+            Algorithm::Secp256k1Blake160 => KeyFormat::Compressed,
+            Algorithm::Secp256k1Blake160Uncompressed => KeyFormat::Uncompressed,
+            Algorithm::Secp256k1Blake160Either => KeyFormat::Either,
+        }
+    }
+}
+
+impl From<KeyFormat> for Algorithm {
+    fn from(key_format: KeyFormat) -> Self {
+        match key_format {
+            KeyFormat::Compressed => Algorithm::Secp256k1Blake160,
+            KeyFormat::Uncompressed => Algorithm::Secp256k1Blake160Uncompressed,
+            KeyFormat::Either => Algorithm::Secp256k1Blake160Either,
+        }
+    }
+}
+
+/// What the multisig hash of the lock args commits to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Encoding {
+    #[default]
+    Script,
+    Molecule,
+}
+
+impl Encoding {
+    fn is_script(&self) -> bool {
+        *self == Encoding::Script
+    }
+}
+
+impl From<Encoding> for ConfigEncoding {
+    fn from(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Script => ConfigEncoding::Script,
+            Encoding::Molecule => ConfigEncoding::Molecule,
+        }
+    }
+}
+
+impl From<ConfigEncoding> for Encoding {
+    fn from(encoding: ConfigEncoding) -> Self {
+        match encoding {
+            ConfigEncoding::Script => Encoding::Script,
+            ConfigEncoding::Molecule => Encoding::Molecule,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyEntry {
+    pub pubkey_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pubkey: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SuccessorEntry {
+    pub multisig_hash: String,
+    pub type_hash: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub version: u32,
+    pub algorithm: Algorithm,
+    pub require_first_n: u8,
+    pub threshold: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_key: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub header_time: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub strict_witnesses: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub spend_all: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub legacy_witnesses: bool,
+    #[serde(default, skip_serializing_if = "Encoding::is_script")]
+    pub encoding: Encoding,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub successor: Option<SuccessorEntry>,
+    pub keys: Vec<KeyEntry>,
+}
+
+impl ConfigFile {
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let file: ConfigFile =
+            serde_json::from_str(json).map_err(|err| Error::InvalidConfigFile(err.to_string()))?;
+        file.check_version()?;
+        Ok(file)
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|err| Error::InvalidConfigFile(err.to_string()))
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, Error> {
+        let file: ConfigFile =
+            toml::from_str(toml).map_err(|err| Error::InvalidConfigFile(err.to_string()))?;
+        file.check_version()?;
+        Ok(file)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string(self).map_err(|err| Error::InvalidConfigFile(err.to_string()))
+    }
+
+    /// Labels of the keys, in the config order.
+    pub fn labels(&self) -> Vec<Option<&str>> {
+        self.keys.iter().map(|key| key.label.as_deref()).collect()
+    }
+
+    /// The config, checked the same way as `MultisigConfig::new`.
+    pub fn to_config(&self) -> Result<MultisigConfig, Error> {
+        self.check_version()?;
+        let pubkey_hashes = self
+            .keys
+            .iter()
+            .map(|key| key.to_pubkey_hash(self.algorithm.key_format()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let since = self.since.as_deref().map(parse_since_field).transpose()?;
+        let fee_key = self.fee_key.as_deref().map(parse_fee_key).transpose()?;
+        let proof = self.proof.as_deref().map(parse_proof).transpose()?;
+        let successor = self
+            .successor
+            .as_ref()
+            .map(SuccessorEntry::to_successor)
+            .transpose()?;
+        Ok(
+            MultisigConfig::new(pubkey_hashes, self.require_first_n, self.threshold)?
+                .with_since(since)
+                .with_fee_key(fee_key)
+                .with_header_time(self.header_time)
+                .with_strict_witnesses(self.strict_witnesses)
+                .with_spend_all(self.spend_all)
+                .with_successor(successor)
+                .with_legacy_witnesses(self.legacy_witnesses)
+                .with_key_format(self.algorithm.key_format())
+                .with_encoding(self.encoding.into())
+                .with_proof(proof),
+        )
+    }
+
+    fn check_version(&self) -> Result<(), Error> {
+        if self.version == 0 || self.version > CONFIG_VERSION {
+            return Err(Error::InvalidConfigFile(format!(
+                "unsupported version {}, expected at most {}",
+                self.version, CONFIG_VERSION
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl From<&MultisigConfig> for ConfigFile {
+    fn from(config: &MultisigConfig) -> Self {
+        ConfigFile {
+            version: CONFIG_VERSION,
+            algorithm: config.key_format().into(),
+            require_first_n: config.require_first_n(),
+            threshold: config.threshold(),
+            since: config.since().map(format_since_field),
+            fee_key: config
+                .fee_key()
+                .map(|hash| format!("0x{}", hex::encode(hash))),
+            header_time: config.header_time(),
+            strict_witnesses: config.strict_witnesses(),
+            spend_all: config.spend_all(),
+            legacy_witnesses: config.legacy_witnesses(),
+            encoding: config.encoding().into(),
+            proof: config
+                .proof()
+                .map(|proof| format!("0x{}", hex::encode(proof.to_bytes()))),
+            successor: config.successor().map(|successor| SuccessorEntry {
+                multisig_hash: format!("0x{}", hex::encode(successor.multisig_hash)),
+                type_hash: format!("0x{}", hex::encode(successor.type_hash)),
+            }),
+            keys: config
+                .pubkey_hashes()
+                .iter()
+                .map(|hash| KeyEntry {
+                    pubkey_hash: format!("0x{}", hex::encode(hash)),
+                    pubkey: None,
+                    label: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl KeyEntry {
+    fn to_pubkey_hash(&self, key_format: KeyFormat) -> Result<[u8; BLAKE160_SIZE], Error> {
+        let hash = decode_hex(&self.pubkey_hash)?;
+        let hash: [u8; BLAKE160_SIZE] = hash.as_slice().try_into().map_err(|_| {
+            Error::InvalidConfigFile(format!(
+                "pubkey hash {} is not {} bytes",
+                self.pubkey_hash, BLAKE160_SIZE
+            ))
+        })?;
+        if let Some(pubkey) = &self.pubkey {
+            let key = secp256k1::PublicKey::from_slice(&decode_hex(pubkey)?)?;
+            if !key_format.identities(&key).contains(&hash) {
+                return Err(Error::InvalidConfigFile(format!(
+                    "pubkey {} doesn't match the hash {}",
+                    pubkey, self.pubkey_hash
+                )));
+            }
+        }
+        Ok(hash)
+    }
+}
+
+impl SuccessorEntry {
+    fn to_successor(&self) -> Result<Successor, Error> {
+        Ok(Successor {
+            multisig_hash: decode_array(&self.multisig_hash, "successor multisig hash")?,
+            type_hash: decode_array(&self.type_hash, "successor type hash")?,
+        })
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn parse_fee_key(fee_key: &str) -> Result<[u8; BLAKE160_SIZE], Error> {
+    decode_hex(fee_key)?.as_slice().try_into().map_err(|_| {
+        Error::InvalidConfigFile(format!(
+            "fee key {} is not {} bytes",
+            fee_key, BLAKE160_SIZE
+        ))
+    })
+}
+
+fn parse_proof(proof: &str) -> Result<ConfigProof, Error> {
+    let bytes = decode_hex(proof)?;
+    match ConfigProof::parse(&bytes) {
+        Ok((proof, [])) => Ok(proof),
+        Ok(_) => Err(Error::InvalidConfigFile(format!(
+            "proof {} has trailing bytes",
+            proof
+        ))),
+        Err(err) => Err(Error::InvalidConfigFile(err.to_string())),
+    }
+}
+
+fn decode_array<const N: usize>(s: &str, name: &str) -> Result<[u8; N], Error> {
+    decode_hex(s)?
+        .as_slice()
+        .try_into()
+        .map_err(|_| Error::InvalidConfigFile(format!("{} {} is not {} bytes", name, s, N)))
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, Error> {
+    let digits = s
+        .strip_prefix("0x")
+        .ok_or_else(|| Error::InvalidConfigFile(format!("{} is not 0x prefixed", s)))?;
+    hex::decode(digits).map_err(|err| Error::InvalidConfigFile(format!("{}: {}", s, err)))
+}
+
+fn parse_since_field(since: &str) -> Result<u64, Error> {
+    match since.strip_prefix("0x") {
+        Some(digits) => {
+            let value = u64::from_str_radix(digits, 16)
+                .map_err(|err| Error::InvalidConfigFile(format!("since {}: {}", since, err)))?;
+            SinceSpec::from_value(value)?;
+            Ok(value)
+        }
+        None => parse_since(since),
+    }
+}
+
+/// The readable form when possible, the raw value otherwise so it is kept.
+fn format_since_field(since: u64) -> String {
+    match parse_since(&format_since(since)) {
+        Ok(value) if value == since => format_since(since),
+        _ => format!("0x{:016x}", since),
+    }
+}
@@ -0,0 +1,191 @@
+//! Several configs agreed on beforehand under a single lock, e.g. normal
+//! operations, emergency and recovery.
+//!
+//! The multisig hash of the lock args is the Merkle root of the configs, and
+//! the lock field proves the config signing is one of them:
+//!
+//! ```text
+//! CONFIG_PROOF | depth | path | siblings | multisig script | signatures
+//! ```
+//!
+//! A leaf is the blake2b of `0 | multisig script`, or of the molecule config,
+//! and a node the blake2b of `1 | left | right`. Bit `i` of `path` is set when
+//! the `i`th sibling, from the leaf up, is on the left. A level with an odd
+//! node carries it up as is, so its proof skips that level.
+//!
+//! Mirrors `ConfigProof` in `ckb-multisig-core/src/lock_field.rs`.
+
+use ckb_hash::new_blake2b;
+
+use crate::{
+    config::{MultisigConfig, CONFIG_PROOF},
+    constants::{BLAKE160_SIZE, DIGEST_SIZE},
+    error::Error,
+};
+
+/// At most 256 configs under a root, the path being a byte.
+pub const MAX_PROOF_DEPTH: usize = 8;
+pub const MAX_TREE_CONFIGS: usize = 1 << MAX_PROOF_DEPTH;
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// The path from a config to the root of its tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ConfigProof {
+    path: u8,
+    siblings: Vec<[u8; DIGEST_SIZE]>,
+}
+
+impl ConfigProof {
+    /// Parse the proof at the head of a lock field, `CONFIG_PROOF` included,
+    /// returning the rest of the lock field.
+    pub fn parse(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let invalid = |reason: &str| Error::InvalidConfig(format!("config proof {}", reason));
+        if bytes.len() < 3 || bytes[0] != CONFIG_PROOF {
+            return Err(invalid("too short"));
+        }
+        let depth = usize::from(bytes[1]);
+        let path = bytes[2];
+        if depth > MAX_PROOF_DEPTH || (depth < MAX_PROOF_DEPTH && path >> depth != 0) {
+            return Err(invalid(&format!(
+                "depth {} with path {:#010b}",
+                depth, path
+            )));
+        }
+        let end = 3 + DIGEST_SIZE * depth;
+        if bytes.len() < end {
+            return Err(invalid("too short"));
+        }
+        let siblings = bytes[3..end]
+            .chunks(DIGEST_SIZE)
+            .map(|chunk| {
+                let mut sibling = [0u8; DIGEST_SIZE];
+                sibling.copy_from_slice(chunk);
+                sibling
+            })
+            .collect();
+        Ok((ConfigProof { path, siblings }, &bytes[end..]))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![CONFIG_PROOF, self.siblings.len() as u8, self.path];
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling);
+        }
+        bytes
+    }
+
+    /// Length of `to_bytes`.
+    pub fn encoded_len(&self) -> usize {
+        3 + DIGEST_SIZE * self.siblings.len()
+    }
+
+    /// The root of the tree holding `config`, truncated as in the lock args.
+    pub fn root(&self, config: &MultisigConfig) -> [u8; BLAKE160_SIZE] {
+        let mut node = leaf_hash(config);
+        for (i, sibling) in self.siblings.iter().enumerate() {
+            node = if self.path >> i & 1 == 1 {
+                node_hash(sibling, &node)
+            } else {
+                node_hash(&node, sibling)
+            };
+        }
+        let mut root = [0u8; BLAKE160_SIZE];
+        root.copy_from_slice(&node[..BLAKE160_SIZE]);
+        root
+    }
+}
+
+/// The configs under one lock, in a fixed order: the root depends on it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigTree {
+    configs: Vec<MultisigConfig>,
+}
+
+impl ConfigTree {
+    /// The configs must agree on the since, fee key and flags of the lock
+    /// args, only the multisig hash is shared.
+    pub fn new(configs: Vec<MultisigConfig>) -> Result<Self, Error> {
+        if configs.is_empty() || configs.len() > MAX_TREE_CONFIGS {
+            return Err(Error::InvalidConfig(format!(
+                "{} configs out of range 1..={}",
+                configs.len(),
+                MAX_TREE_CONFIGS
+            )));
+        }
+        let args = |config: &MultisigConfig| config.lock_args()[BLAKE160_SIZE..].to_vec();
+        if let Some(i) = configs.iter().position(|c| args(c) != args(&configs[0])) {
+            return Err(Error::InvalidConfig(format!(
+                "config #{} has other lock args than the hash",
+                i
+            )));
+        }
+        let configs: Vec<_> = configs
+            .into_iter()
+            .map(|config| config.with_proof(None))
+            .collect();
+        if let Some(i) = (1..configs.len()).find(|i| configs[..*i].contains(&configs[*i])) {
+            return Err(Error::InvalidConfig(format!("config #{} is duplicated", i)));
+        }
+        Ok(ConfigTree { configs })
+    }
+
+    pub fn configs(&self) -> &[MultisigConfig] {
+        &self.configs
+    }
+
+    /// The config at `index` with its proof, whose lock args are those of
+    /// the tree.
+    pub fn config(&self, index: usize) -> Option<MultisigConfig> {
+        let config = self.configs.get(index)?;
+        Some(config.clone().with_proof(Some(self.proof(index))))
+    }
+
+    /// The root, the multisig hash of the lock args.
+    pub fn root(&self) -> [u8; BLAKE160_SIZE] {
+        self.proof(0).root(&self.configs[0])
+    }
+
+    fn proof(&self, mut index: usize) -> ConfigProof {
+        let mut level: Vec<_> = self.configs.iter().map(leaf_hash).collect();
+        let mut proof = ConfigProof::default();
+        while level.len() > 1 {
+            let sibling = index ^ 1;
+            if sibling < level.len() {
+                if sibling < index {
+                    proof.path |= 1 << proof.siblings.len();
+                }
+                proof.siblings.push(level[sibling]);
+            }
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            index /= 2;
+        }
+        proof
+    }
+}
+
+fn leaf_hash(config: &MultisigConfig) -> [u8; DIGEST_SIZE] {
+    prefixed_hash(LEAF_PREFIX, &config.committed_bytes(), &[])
+}
+
+fn node_hash(left: &[u8], right: &[u8]) -> [u8; DIGEST_SIZE] {
+    prefixed_hash(NODE_PREFIX, left, right)
+}
+
+fn prefixed_hash(prefix: u8, left: &[u8], right: &[u8]) -> [u8; DIGEST_SIZE] {
+    let mut blake2b = new_blake2b();
+    blake2b.update(&[prefix]);
+    blake2b.update(left);
+    blake2b.update(right);
+    let mut hash = [0u8; DIGEST_SIZE];
+    blake2b.finalize(&mut hash);
+    hash
+}
@@ -0,0 +1,10 @@
+/// Size of a public key identity in the multisig script.
+pub const BLAKE160_SIZE: usize = 20;
+/// Size of a recoverable secp256k1 signature: `r | s | recid`.
+pub const SIGNATURE_SIZE: usize = 65;
+/// Size of a signing digest.
+pub const DIGEST_SIZE: usize = 32;
+/// Size of the flags at the head of the multisig script.
+pub const FLAGS_SIZE: usize = 4;
+/// Size of the optional since value in the lock args.
+pub const U64_SIZE: usize = 8;
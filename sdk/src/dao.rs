@@ -0,0 +1,271 @@
+//! Nervos DAO flows for the capacity held by a config, on top of the ckb-sdk
+//! DAO builders.
+//!
+//! * deposit: `MultisigDao::deposit`
+//! * withdraw phase 1: `MultisigDao::prepare`
+//! * withdraw phase 2: `MultisigDao::withdraw`
+//!
+//! The ckb-sdk builders resolve the deposit and prepare headers and put them
+//! in the header deps. Phase 2 inputs must carry an absolute epoch since not
+//! earlier than the DAO unlock point, a config locked by since can only be
+//! withdrawn when its since is an absolute epoch too, the inputs then use the
+//! later of the two. Call `MultisigDao::apply_since` on the balanced
+//! transaction before signing, so the inputs the balancer added also satisfy
+//! the lock args.
+//!
+//! `MultisigDao::cells` lists the DAO cells of the config, deposited or
+//! prepared, with the compensation they accrued and the epoch phase 2 can
+//! claim them at.
+
+use std::{cmp::Ordering, convert::TryFrom};
+
+use ckb_sdk::{
+    constants::DAO_TYPE_HASH,
+    traits::{LiveCell, TransactionDependencyProvider},
+    tx_builder::{
+        dao::{
+            DaoDepositBuilder, DaoDepositReceiver, DaoPrepareBuilder, DaoPrepareItem,
+            DaoWithdrawBuilder, DaoWithdrawItem, DaoWithdrawReceiver,
+        },
+        gen_script_groups,
+    },
+    types::{Since, SinceType},
+    util::{calculate_dao_maximum_withdraw4, minimal_unlock_point},
+};
+use ckb_types::{
+    core::{
+        Capacity, EpochNumberWithFraction, FeeRate, HeaderView, ScriptHashType, TransactionView,
+    },
+    packed::{CellInput, OutPoint, Script, WitnessArgs},
+    prelude::*,
+    H256,
+};
+
+use crate::{backend::ChainBackend, config::MultisigConfig, error::Error, since};
+
+/// The since flags of an absolute epoch.
+const EPOCH_FRACTION_FLAGS: u64 = 0b0010_0000;
+
+/// The type script of the Nervos DAO cells.
+pub fn dao_type_script() -> Script {
+    Script::new_builder()
+        .code_hash(DAO_TYPE_HASH.pack())
+        .hash_type(ScriptHashType::Type)
+        .build()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DaoPhase {
+    Deposited,
+    /// Through phase 1, the cell data holds the deposit block number.
+    Prepared,
+}
+
+/// A DAO cell of the config.
+#[derive(Clone, Debug)]
+pub struct DaoCell {
+    pub cell: LiveCell,
+    pub phase: DaoPhase,
+    /// Shannons earned on top of the capacity, until the tip for a deposit,
+    /// until phase 1 for a prepared cell.
+    pub compensation: u64,
+    /// The earliest epoch of phase 2, for a deposit should phase 1 be at the
+    /// tip.
+    pub claimable_epoch: EpochNumberWithFraction,
+}
+
+impl DaoCell {
+    /// `deposit` is the header of the deposit block, `end` the header of
+    /// the phase 1 block for a prepared cell, of the tip for a deposit.
+    pub fn new(cell: LiveCell, deposit: &HeaderView, end: &HeaderView) -> Result<Self, Error> {
+        let phase = match cell.output_data.as_ref() {
+            [0, 0, 0, 0, 0, 0, 0, 0] => DaoPhase::Deposited,
+            data if data.len() == 8 => DaoPhase::Prepared,
+            _ => {
+                return Err(Error::InvalidParameter(format!(
+                    "cell {} has {} bytes of data, not a DAO cell",
+                    cell.out_point,
+                    cell.output_data.len()
+                )))
+            }
+        };
+        let occupied = cell
+            .output
+            .occupied_capacity(Capacity::bytes(cell.output_data.len()).expect("8 bytes"))
+            .map_err(|err| Error::InvalidParameter(err.to_string()))?
+            .as_u64();
+        let maximum = calculate_dao_maximum_withdraw4(deposit, end, &cell.output, occupied);
+        let capacity: u64 = cell.output.capacity().unpack();
+        Ok(DaoCell {
+            phase,
+            compensation: maximum.saturating_sub(capacity),
+            claimable_epoch: minimal_unlock_point(deposit, end),
+            cell,
+        })
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.cell.output.capacity().unpack()
+    }
+
+    /// Whether phase 2 can claim the cell at the epoch `tip`.
+    pub fn is_claimable(&self, tip: EpochNumberWithFraction) -> bool {
+        self.phase == DaoPhase::Prepared
+            && since::since_value_cmp(
+                EPOCH_FRACTION_FLAGS,
+                tip.full_value(),
+                self.claimable_epoch.full_value(),
+            ) != Ordering::Less
+    }
+}
+
+pub struct MultisigDao {
+    config: MultisigConfig,
+    lock_script: Script,
+}
+
+impl MultisigDao {
+    pub fn new(config: MultisigConfig, code_hash: &H256, hash_type: ScriptHashType) -> Self {
+        let lock_script = config.lock_script(code_hash, hash_type);
+        MultisigDao {
+            config,
+            lock_script,
+        }
+    }
+
+    pub fn config(&self) -> &MultisigConfig {
+        &self.config
+    }
+
+    pub fn lock_script(&self) -> &Script {
+        &self.lock_script
+    }
+
+    /// Deposit cells of the given capacities, guarded by the config.
+    pub fn deposit(&self, capacities: &[u64]) -> DaoDepositBuilder {
+        let receivers = capacities
+            .iter()
+            .map(|capacity| DaoDepositReceiver::new(self.lock_script.clone(), *capacity))
+            .collect();
+        DaoDepositBuilder::new(receivers)
+    }
+
+    /// Phase 1, the prepared cells keep the lock of the deposits.
+    pub fn prepare(&self, deposits: Vec<OutPoint>) -> DaoPrepareBuilder {
+        let since = self.config.since().unwrap_or(0);
+        let items = deposits
+            .into_iter()
+            .map(|out_point| DaoPrepareItem::from(CellInput::new(out_point, since)))
+            .collect();
+        DaoPrepareBuilder::new(items)
+    }
+
+    /// Phase 2, the withdrawn capacity goes to `receiver`, the config lock by
+    /// default. With `fee_rate` the fee is paid from the withdrawn capacity.
+    pub fn withdraw(
+        &self,
+        prepared: Vec<OutPoint>,
+        receiver: Option<Script>,
+        fee_rate: Option<u64>,
+    ) -> Result<DaoWithdrawBuilder, Error> {
+        if let Some(lock_since) = self.config.since().filter(|since| *since != 0) {
+            let since = Since::from_raw_value(lock_since);
+            let absolute_epoch = !since.is_relative()
+                && matches!(
+                    since.extract_metric(),
+                    Some((SinceType::EpochNumberWithFraction, _))
+                );
+            if !absolute_epoch {
+                return Err(Error::InvalidSince(format!(
+                    "dao withdraw requires an absolute epoch since, the lock args have 0x{:016x}",
+                    lock_since
+                )));
+            }
+        }
+        let init_witness = WitnessArgs::new_builder()
+            .lock(Some(self.config.placeholder_lock()).pack())
+            .build();
+        let mut items: Vec<_> = prepared
+            .into_iter()
+            .map(|out_point| DaoWithdrawItem::new(out_point, None))
+            .collect();
+        // only the first witness of the group carries the lock field
+        if let Some(first) = items.first_mut() {
+            first.init_witness = Some(init_witness);
+        }
+        let receiver = DaoWithdrawReceiver::LockScript {
+            script: receiver.unwrap_or_else(|| self.lock_script.clone()),
+            fee_rate: fee_rate.map(FeeRate::from_u64),
+        };
+        Ok(DaoWithdrawBuilder::new(items, receiver))
+    }
+
+    /// The DAO cells among `cells` of the config, e.g. of a `Scanner` with
+    /// `TypeFilter::Script(dao_type_script())`, their headers from `backend`.
+    /// The headers are looked up by the transactions committed in them, the
+    /// deposit of a withdrawing cell through the input of its transaction,
+    /// so that a light client resolves them too.
+    pub fn cells(
+        &self,
+        backend: &dyn ChainBackend,
+        cells: Vec<LiveCell>,
+    ) -> Result<Vec<DaoCell>, Error> {
+        let tip = backend.tip_header()?;
+        let mut dao_cells = Vec::new();
+        for cell in cells {
+            if cell.output.type_().to_opt() != Some(dao_type_script()) {
+                continue;
+            }
+            let data = cell.output_data.as_ref();
+            let deposit_number = match <[u8; 8]>::try_from(data) {
+                Ok(bytes) => u64::from_le_bytes(bytes),
+                Err(_) => continue,
+            };
+            let tx_hash: H256 = cell.out_point.tx_hash().unpack();
+            let commit = backend.commit_header(&tx_hash)?;
+            let dao_cell = if deposit_number == 0 {
+                DaoCell::new(cell.clone(), &commit, &tip)?
+            } else {
+                // the prepare transaction spends the deposit at the index of
+                // the withdrawing cell
+                let index: u32 = cell.out_point.index().unpack();
+                let input = backend
+                    .transaction(&tx_hash)?
+                    .inputs()
+                    .get(index as usize)
+                    .ok_or_else(|| {
+                        Error::InvalidParameter(format!(
+                            "the withdrawing cell {} has no deposit input",
+                            cell.out_point
+                        ))
+                    })?;
+                let deposit = backend.commit_header(&input.previous_output().tx_hash().unpack())?;
+                if deposit.number() != deposit_number {
+                    return Err(Error::InvalidParameter(format!(
+                        "the withdrawing cell {} records the deposit block #{}, not #{}",
+                        cell.out_point,
+                        deposit_number,
+                        deposit.number()
+                    )));
+                }
+                DaoCell::new(cell.clone(), &deposit, &commit)?
+            };
+            dao_cells.push(dao_cell);
+        }
+        Ok(dao_cells)
+    }
+
+    /// Merge the lock args since into the since of every input of the config.
+    pub fn apply_since(
+        &self,
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<TransactionView, Error> {
+        let groups = gen_script_groups(tx, tx_dep_provider)
+            .map_err(|err| Error::TransactionDependency(err.to_string()))?;
+        match groups.lock_groups.get(&self.lock_script.calc_script_hash()) {
+            Some(group) => since::apply_since(tx, group, self.config.since()),
+            None => Ok(tx.clone()),
+        }
+    }
+}
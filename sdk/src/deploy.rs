@@ -0,0 +1,283 @@
+//! Deployment of the contract binaries under a type id.
+//!
+//! The binary is the data of a cell whose type script is the type id one,
+//! the lock scripts refer to it by the hash of that type script with the
+//! hash type `type`. An upgrade spends the cell and recreates it with the new
+//! binary and the same type script: the code hash, and so every lock of
+//! every config, stay the same, only the out point of the cell dep moves.
+//!
+//! `deployment` records the cell of a deploy or upgrade transaction in the
+//! `deployment::Registry` format and `verify_deployed` checks a deployed
+//! cell holds a local build artifact, before trusting a registry entry,
+//! `verify_cell_dep` before trusting a cell dep alone. `find_code` finds the
+//! cell of a code hash among the cell deps of a lock.
+
+use ckb_hash::blake2b_256;
+use ckb_sdk::{
+    constants::TYPE_ID_CODE_HASH,
+    traits::{CellQueryOptions, LiveCell},
+    CkbRpcClient,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, DepType, ScriptHashType, TransactionView},
+    packed::{CellDep, CellInput, CellOutput, OutPoint, OutPointVec, Script},
+    prelude::*,
+    H256,
+};
+
+use crate::{backend::ChainBackend, deployment::Deployment, error::Error, nonce::type_id};
+
+/// The type id type script of `type_id`.
+pub fn type_id_script(type_id: &[u8; 32]) -> Script {
+    Script::new_builder()
+        .code_hash(TYPE_ID_CODE_HASH.pack())
+        .hash_type(ScriptHashType::Type)
+        .args(Bytes::copy_from_slice(type_id).pack())
+        .build()
+}
+
+/// Add the cell of `binary` under a new type id, guarded by `lock`, as the
+/// last output of `tx`, returning its type script. The type id is derived
+/// from the first input, so `tx` must already have its inputs. The cell
+/// holds the capacity it occupies, the caller balances the transaction.
+pub fn deploy(
+    tx: &TransactionView,
+    lock: Script,
+    binary: &[u8],
+) -> Result<(TransactionView, Script), Error> {
+    let first_input = tx.inputs().get(0).ok_or_else(|| {
+        Error::InvalidParameter("the transaction deploying a binary has no input".to_string())
+    })?;
+    let type_script = type_id_script(&type_id(&first_input, tx.outputs().len() as u64));
+    let output = code_output(lock, type_script.clone(), binary)?;
+    let tx = tx
+        .as_advanced_builder()
+        .output(output)
+        .output_data(Bytes::copy_from_slice(binary).pack())
+        .build();
+    Ok((tx, type_script))
+}
+
+/// Spend the deployed `cell` in `tx` and recreate it with `binary` as the
+/// last output, with the same type id and the lock `lock`, the one of the
+/// cell when `None`. The new cell holds the capacity it occupies, the caller
+/// balances the transaction.
+pub fn upgrade(
+    tx: &TransactionView,
+    cell: &LiveCell,
+    lock: Option<Script>,
+    binary: &[u8],
+) -> Result<TransactionView, Error> {
+    let type_script = cell
+        .output
+        .type_()
+        .to_opt()
+        .filter(is_type_id)
+        .ok_or_else(|| Error::InvalidParameter("the upgraded cell has no type id".to_string()))?;
+    let lock = lock.unwrap_or_else(|| cell.output.lock());
+    let output = code_output(lock, type_script, binary)?;
+    Ok(tx
+        .as_advanced_builder()
+        .input(CellInput::new(cell.out_point.clone(), 0))
+        .output(output)
+        .output_data(Bytes::copy_from_slice(binary).pack())
+        .build())
+}
+
+/// The deployment of the binary at `output_index` of a deploy or upgrade
+/// transaction, referred to by its type id with a code cell dep. Clear
+/// `secp256k1_data` for a binary built with the embedded tables.
+pub fn deployment(tx: &TransactionView, output_index: u32) -> Result<Deployment, Error> {
+    let output = tx.outputs().get(output_index as usize).ok_or_else(|| {
+        Error::InvalidParameter(format!("the transaction has no output #{}", output_index))
+    })?;
+    let type_script = output.type_().to_opt().filter(is_type_id).ok_or_else(|| {
+        Error::InvalidParameter(format!("output #{} has no type id", output_index))
+    })?;
+    Ok(Deployment {
+        code_hash: type_script.calc_script_hash().unpack(),
+        hash_type: ScriptHashType::Type,
+        cell_dep: CellDep::new_builder()
+            .out_point(OutPoint::new(tx.hash(), output_index))
+            .dep_type(DepType::Code)
+            .build(),
+        secp256k1_data: true,
+    })
+}
+
+/// The live cell of the binary deployed under `type_id`.
+pub fn find(backend: &dyn ChainBackend, type_id: &[u8; 32]) -> Result<Option<LiveCell>, Error> {
+    let type_script = type_id_script(type_id);
+    let query = CellQueryOptions::new_type(type_script.clone());
+    let page = backend.cells(query.into(), 2, None)?;
+    // the indexer matches the type script by prefix
+    Ok(page
+        .objects
+        .into_iter()
+        .map(LiveCell::from)
+        .find(|cell| cell.output.type_().to_opt().as_ref() == Some(&type_script)))
+}
+
+/// The binary `deployment` refers to is `artifact`, a local build of the
+/// contract, returning its data hash. The cell of a dep group is the member
+/// matching the code hash.
+pub fn verify_deployed(
+    client: &CkbRpcClient,
+    deployment: &Deployment,
+    artifact: &[u8],
+) -> Result<H256, Error> {
+    let out_point = deployment.cell_dep.out_point();
+    let code_cell = if deployment.cell_dep.dep_type() == DepType::DepGroup.into() {
+        let (_, data) = live_cell(client, &out_point)?;
+        let members = OutPointVec::from_slice(&data).map_err(|err| {
+            Error::Verification(format!("invalid dep group {}: {}", out_point, err))
+        })?;
+        let mut found = None;
+        for member in members.into_iter() {
+            let (output, data) = live_cell(client, &member)?;
+            if code_hash(&output, &data, deployment.hash_type) == deployment.code_hash {
+                found = Some((output, data));
+                break;
+            }
+        }
+        found.ok_or_else(|| {
+            Error::Verification(format!(
+                "no cell of code hash {:#x} in the dep group",
+                deployment.code_hash
+            ))
+        })?
+    } else {
+        live_cell(client, &out_point)?
+    };
+    let (output, data) = code_cell;
+    if code_hash(&output, &data, deployment.hash_type) != deployment.code_hash {
+        return Err(Error::Verification(format!(
+            "the cell dep doesn't match the code hash {:#x}",
+            deployment.code_hash
+        )));
+    }
+    verify_binary(&data, artifact)
+}
+
+/// The cell of `cell_dep` holds `artifact`, returning its data hash. The
+/// cell of a dep group is the member holding it.
+pub fn verify_cell_dep(
+    client: &CkbRpcClient,
+    cell_dep: &CellDep,
+    artifact: &[u8],
+) -> Result<H256, Error> {
+    let out_point = cell_dep.out_point();
+    let (_, data) = live_cell(client, &out_point)?;
+    if cell_dep.dep_type() != DepType::DepGroup.into() {
+        return verify_binary(&data, artifact);
+    }
+    let members = OutPointVec::from_slice(&data)
+        .map_err(|err| Error::Verification(format!("invalid dep group {}: {}", out_point, err)))?;
+    for member in members.into_iter() {
+        let (_, data) = live_cell(client, &member)?;
+        if let Ok(hash) = verify_binary(&data, artifact) {
+            return Ok(hash);
+        }
+    }
+    Err(Error::Verification(format!(
+        "no cell of the dep group {} holds the artifact of data hash 0x{}",
+        out_point,
+        hex::encode(blake2b_256(artifact))
+    )))
+}
+
+/// The cell of `cell_deps` holding the code `hash` refers to with
+/// `hash_type`, the members of the dep groups included. Every cell dep must
+/// be live.
+pub fn find_code(
+    client: &CkbRpcClient,
+    cell_deps: &[CellDep],
+    hash: &H256,
+    hash_type: ScriptHashType,
+) -> Result<OutPoint, Error> {
+    let mut found = None;
+    for cell_dep in cell_deps {
+        let out_point = cell_dep.out_point();
+        let (output, data) = live_cell(client, &out_point)?;
+        let mut cells = vec![(out_point.clone(), output, data.clone())];
+        if cell_dep.dep_type() == DepType::DepGroup.into() {
+            let members = OutPointVec::from_slice(&data).map_err(|err| {
+                Error::Verification(format!("invalid dep group {}: {}", out_point, err))
+            })?;
+            cells.clear();
+            for member in members.into_iter() {
+                let (output, data) = live_cell(client, &member)?;
+                cells.push((member, output, data));
+            }
+        }
+        for (out_point, output, data) in cells {
+            if found.is_none() && code_hash(&output, &data, hash_type) == *hash {
+                found = Some(out_point);
+            }
+        }
+    }
+    found.ok_or_else(|| {
+        Error::Verification(format!(
+            "no cell dep holds the code of code hash {:#x}",
+            hash
+        ))
+    })
+}
+
+/// `data` is `artifact`, returning its data hash.
+pub fn verify_binary(data: &[u8], artifact: &[u8]) -> Result<H256, Error> {
+    let deployed = blake2b_256(data);
+    let local = blake2b_256(artifact);
+    if deployed != local {
+        return Err(Error::Verification(format!(
+            "the deployed binary has the data hash 0x{}, the artifact 0x{}",
+            hex::encode(deployed),
+            hex::encode(local)
+        )));
+    }
+    Ok(H256(deployed))
+}
+
+fn is_type_id(script: &Script) -> bool {
+    script.code_hash() == TYPE_ID_CODE_HASH.pack()
+        && script.hash_type() == ScriptHashType::Type.into()
+        && script.args().raw_data().len() == 32
+}
+
+/// The code hash `hash_type` refers to a cell with.
+fn code_hash(output: &CellOutput, data: &[u8], hash_type: ScriptHashType) -> H256 {
+    match hash_type {
+        ScriptHashType::Type => output
+            .type_()
+            .to_opt()
+            .map(|script| script.calc_script_hash().unpack())
+            .unwrap_or_default(),
+        _ => H256(blake2b_256(data)),
+    }
+}
+
+fn live_cell(client: &CkbRpcClient, out_point: &OutPoint) -> Result<(CellOutput, Bytes), Error> {
+    let cell = client
+        .get_live_cell(out_point.clone().into(), true)?
+        .cell
+        .ok_or_else(|| Error::Verification(format!("{} is not a live cell", out_point)))?;
+    let data = cell
+        .data
+        .map(|data| data.content.into_bytes())
+        .unwrap_or_default();
+    Ok((cell.output.into(), data))
+}
+
+fn code_output(lock: Script, type_script: Script, binary: &[u8]) -> Result<CellOutput, Error> {
+    let output = CellOutput::new_builder()
+        .lock(lock)
+        .type_(Some(type_script).pack())
+        .build();
+    let occupied = output
+        .occupied_capacity(Capacity::bytes(binary.len()).map_err(|err| {
+            Error::InvalidParameter(format!("binary of {} bytes: {}", binary.len(), err))
+        })?)
+        .map_err(|err| Error::InsufficientCapacity(err.to_string()))?;
+    Ok(output.as_builder().capacity(occupied.pack()).build())
+}
@@ -0,0 +1,369 @@
+//! Registry of where the lock is deployed on each network: the code hash and
+//! hash type of its scripts and the cell dep bringing in its binary, so
+//! builders look them up instead of hard-coding out points that rot with
+//! every redeployment.
+//!
+//! The registry file, TOML or JSON, holds one table per network:
+//!
+//! ```toml
+//! [testnet]
+//! code_hash = "0x..."
+//! # optional, "type" by default, or "data", "data1" or "data2"
+//! hash_type = "type"
+//! tx_hash = "0x..."
+//! index = 0
+//! # optional, "dep_group" by default, or "code"
+//! dep_type = "dep_group"
+//! # optional, whether the genesis secp256k1_data cell is added next to the
+//! # cell dep, by default only for a code cell dep
+//! secp256k1_data = false
+//! ```
+//!
+//! The contract loads the secp256k1 precomputed tables from the
+//! `secp256k1_data` cell of the genesis block, unless its dep group carries
+//! it or it is built with the embedded tables. `Registry::cell_deps` gives the
+//! cell deps a transaction of the lock needs, the genesis cell included when
+//! required: known on mainnet and testnet, found in the genesis block with
+//! `find_secp256k1_data` on a devnet.
+//!
+//! `Deployment::from_capsule_migration` reads the migration file capsule
+//! writes on deploying. Entries are overridden with `Registry::set` or merged
+//! from another registry with `Registry::merge`, e.g. a local file over the
+//! shared one.
+
+use std::{collections::BTreeMap, convert::TryFrom, fmt, str::FromStr};
+
+use ckb_jsonrpc_types as json;
+use ckb_types::{
+    core::{BlockView, DepType, ScriptHashType},
+    h256,
+    packed::{CellDep, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::MultisigConfig, error::Error};
+
+/// Data hash of the secp256k1 precomputed tables.
+pub const SECP256K1_DATA_HASH: H256 =
+    h256!("0x9799bee251b975b82c45a02154ce28cec89c5853ecc14d12b7b8cccfc19e0af4");
+/// The first transaction of the mainnet genesis block, whose output 3 is the
+/// `secp256k1_data` cell.
+pub const MAINNET_GENESIS_TX: H256 =
+    h256!("0x71a7ba8fc96349fea0ed3a5c47992e3b4084b031a42264a018e0072e8172e46c");
+/// The first transaction of the testnet genesis block, whose output 3 is the
+/// `secp256k1_data` cell.
+pub const TESTNET_GENESIS_TX: H256 =
+    h256!("0x8f8c79eb6671709633fe6a46de93c0fedc9c1b8a6527a18d3983879542635c9f");
+const SECP256K1_DATA_INDEX: u32 = 3;
+
+/// The code cell dep of the `secp256k1_data` cell of `genesis`, the genesis
+/// block of a devnet.
+pub fn find_secp256k1_data(genesis: &BlockView) -> Option<CellDep> {
+    let data_hash = SECP256K1_DATA_HASH.pack();
+    genesis.transactions().iter().find_map(|tx| {
+        tx.outputs_data()
+            .into_iter()
+            .position(|data| CellOutput::calc_data_hash(&data.raw_data()) == data_hash)
+            .map(|index| cell_dep(&tx.hash().unpack(), index as u32, DepType::Code))
+    })
+}
+
+/// A CKB network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    /// A local dev chain.
+    Devnet,
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Devnet => "devnet",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Network {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "devnet" => Ok(Network::Devnet),
+            _ => Err(Error::InvalidDeployment(format!(
+                "unknown network `{}`, expected mainnet, testnet or devnet",
+                s
+            ))),
+        }
+    }
+}
+
+/// Where the lock is deployed on a network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deployment {
+    pub code_hash: H256,
+    pub hash_type: ScriptHashType,
+    pub cell_dep: CellDep,
+    /// The genesis `secp256k1_data` cell is needed next to `cell_dep`.
+    pub secp256k1_data: bool,
+}
+
+impl Deployment {
+    /// The lock script of `config` on this deployment.
+    pub fn lock_script(&self, config: &MultisigConfig) -> Script {
+        config.lock_script(&self.code_hash, self.hash_type)
+    }
+
+    /// The deployment from the migration file of capsule, `cell` being the
+    /// name of the contract cell and `dep_group` the one of its dep group,
+    /// if any. The code hash is the type id when the cell has one, the data
+    /// hash of the binary under data1 otherwise. A dep group is expected to
+    /// carry the `secp256k1_data` cell.
+    pub fn from_capsule_migration(
+        json: &str,
+        cell: &str,
+        dep_group: Option<&str>,
+    ) -> Result<Self, Error> {
+        let migration: CapsuleMigration =
+            serde_json::from_str(json).map_err(|err| deployment_err(err.to_string()))?;
+        let recipe = migration
+            .cell_recipes
+            .iter()
+            .find(|recipe| recipe.name == cell)
+            .ok_or_else(|| deployment_err(format!("no cell `{}` in the migration", cell)))?;
+        let (code_hash, hash_type) = match &recipe.type_id {
+            Some(type_id) => (type_id.clone(), ScriptHashType::Type),
+            None => (recipe.data_hash.clone(), ScriptHashType::Data1),
+        };
+        let (tx_hash, index, dep_type) = match dep_group {
+            Some(name) => {
+                let group = migration
+                    .dep_group_recipes
+                    .iter()
+                    .find(|group| group.name == name)
+                    .ok_or_else(|| {
+                        deployment_err(format!("no dep group `{}` in the migration", name))
+                    })?;
+                (group.tx_hash.clone(), group.index, DepType::DepGroup)
+            }
+            None => (recipe.tx_hash.clone(), recipe.index, DepType::Code),
+        };
+        Ok(Deployment {
+            code_hash,
+            hash_type,
+            cell_dep: cell_dep(&tx_hash, index, dep_type),
+            secp256k1_data: dep_type == DepType::Code,
+        })
+    }
+
+    fn from_entry(network: Network, entry: Entry) -> Result<Self, Error> {
+        let hash_type = match entry.hash_type {
+            Some(hash_type) => hash_type.into(),
+            None => ScriptHashType::Type,
+        };
+        let dep_type = match entry.dep_type {
+            Some(dep_type) => dep_type.into(),
+            None => DepType::DepGroup,
+        };
+        if entry.code_hash == H256::default() {
+            return Err(deployment_err(format!("{}: zero code hash", network)));
+        }
+        Ok(Deployment {
+            code_hash: entry.code_hash,
+            hash_type,
+            cell_dep: cell_dep(&entry.tx_hash, entry.index, dep_type),
+            secp256k1_data: entry.secp256k1_data.unwrap_or(dep_type == DepType::Code),
+        })
+    }
+
+    fn to_entry(&self) -> Entry {
+        let out_point = self.cell_dep.out_point();
+        Entry {
+            code_hash: self.code_hash.clone(),
+            hash_type: Some(self.hash_type.into()),
+            tx_hash: out_point.tx_hash().unpack(),
+            index: out_point.index().unpack(),
+            dep_type: Some(
+                DepType::try_from(self.cell_dep.dep_type())
+                    .expect("valid dep type")
+                    .into(),
+            ),
+            secp256k1_data: Some(self.secp256k1_data),
+        }
+    }
+}
+
+/// The deployments of the lock, by network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Registry {
+    deployments: BTreeMap<Network, Deployment>,
+    secp256k1_data: BTreeMap<Network, CellDep>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let secp256k1_data = [
+            (Network::Mainnet, MAINNET_GENESIS_TX),
+            (Network::Testnet, TESTNET_GENESIS_TX),
+        ]
+        .iter()
+        .map(|(network, tx_hash)| {
+            (
+                *network,
+                cell_dep(tx_hash, SECP256K1_DATA_INDEX, DepType::Code),
+            )
+        })
+        .collect();
+        Registry {
+            deployments: BTreeMap::new(),
+            secp256k1_data,
+        }
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, Error> {
+        let entries: BTreeMap<Network, Entry> =
+            toml::from_str(toml).map_err(|err| deployment_err(err.to_string()))?;
+        Registry::from_entries(entries)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string(&self.entries()).map_err(|err| deployment_err(err.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let entries: BTreeMap<Network, Entry> =
+            serde_json::from_str(json).map_err(|err| deployment_err(err.to_string()))?;
+        Registry::from_entries(entries)
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(&self.entries()).map_err(|err| deployment_err(err.to_string()))
+    }
+
+    /// The deployment on `network`.
+    pub fn get(&self, network: Network) -> Result<&Deployment, Error> {
+        self.deployments
+            .get(&network)
+            .ok_or_else(|| deployment_err(format!("the lock is not deployed on {}", network)))
+    }
+
+    /// The cell deps of a transaction spending cells of the lock on
+    /// `network`: the one of the deployment, and the `secp256k1_data` cell
+    /// when the deployment needs it.
+    pub fn cell_deps(&self, network: Network) -> Result<Vec<CellDep>, Error> {
+        let deployment = self.get(network)?;
+        let mut cell_deps = vec![deployment.cell_dep.clone()];
+        if deployment.secp256k1_data {
+            let data = self.secp256k1_data.get(&network).ok_or_else(|| {
+                deployment_err(format!(
+                    "the secp256k1_data cell of {} is unknown, find it in the genesis block",
+                    network
+                ))
+            })?;
+            cell_deps.push(data.clone());
+        }
+        Ok(cell_deps)
+    }
+
+    /// Set the cell dep of the `secp256k1_data` cell on `network`, see
+    /// `find_secp256k1_data`.
+    pub fn set_secp256k1_data(&mut self, network: Network, cell_dep: CellDep) {
+        self.secp256k1_data.insert(network, cell_dep);
+    }
+
+    /// Set or override the deployment on `network`.
+    pub fn set(&mut self, network: Network, deployment: Deployment) -> Option<Deployment> {
+        self.deployments.insert(network, deployment)
+    }
+
+    /// The deployments of `other` override these.
+    pub fn merge(&mut self, other: Registry) {
+        self.deployments.extend(other.deployments);
+        self.secp256k1_data.extend(other.secp256k1_data);
+    }
+
+    pub fn networks(&self) -> impl Iterator<Item = Network> + '_ {
+        self.deployments.keys().copied()
+    }
+
+    fn from_entries(entries: BTreeMap<Network, Entry>) -> Result<Self, Error> {
+        let deployments = entries
+            .into_iter()
+            .map(|(network, entry)| Ok((network, Deployment::from_entry(network, entry)?)))
+            .collect::<Result<_, Error>>()?;
+        Ok(Registry {
+            deployments,
+            ..Registry::default()
+        })
+    }
+
+    fn entries(&self) -> BTreeMap<Network, Entry> {
+        self.deployments
+            .iter()
+            .map(|(network, deployment)| (*network, deployment.to_entry()))
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    code_hash: H256,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash_type: Option<json::ScriptHashType>,
+    tx_hash: H256,
+    index: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dep_type: Option<json::DepType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secp256k1_data: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct CapsuleMigration {
+    cell_recipes: Vec<CellRecipe>,
+    #[serde(default)]
+    dep_group_recipes: Vec<DepGroupRecipe>,
+}
+
+#[derive(Deserialize)]
+struct CellRecipe {
+    name: String,
+    tx_hash: H256,
+    index: u32,
+    data_hash: H256,
+    type_id: Option<H256>,
+}
+
+#[derive(Deserialize)]
+struct DepGroupRecipe {
+    name: String,
+    tx_hash: H256,
+    index: u32,
+}
+
+fn cell_dep(tx_hash: &H256, index: u32, dep_type: DepType) -> CellDep {
+    CellDep::new_builder()
+        .out_point(OutPoint::new(tx_hash.pack(), index))
+        .dep_type(dep_type)
+        .build()
+}
+
+fn deployment_err(message: String) -> Error {
+    Error::InvalidDeployment(message)
+}
@@ -0,0 +1,186 @@
+//! Descriptors: a config on a single line, for backups that are copied and
+//! pasted or written down, in the manner of the Bitcoin output descriptors:
+//!
+//! ```text
+//! multi(2,1,<pubkey hash>,<pubkey hash>,<pubkey hash>)#<checksum>
+//! since(0x2000000000000b40,multi(2,0,<pubkey hash>,<pubkey hash>))#<checksum>
+//! ```
+//!
+//! `multi` takes the threshold, `require_first_n` and the pubkey hashes in
+//! hex, in the multisig script order; `multi_uncompressed` and
+//! `multi_either` stand for the other key formats, see `KeyFormat`. `since`
+//! wraps it when the lock args hold a since, its raw value in hex.
+//!
+//! The checksum is the one of BIP-380, 8 characters catching up to 4 errors,
+//! and is required: a descriptor without one is refused, the error giving
+//! the checksum to append to a descriptor written by hand. Configs holding
+//! anything else, e.g. a fee key, flags or a successor, have no descriptor.
+
+use crate::{
+    config::{KeyFormat, MultisigConfig},
+    constants::BLAKE160_SIZE,
+    error::Error,
+};
+
+/// The characters a descriptor may hold, by groups of 32.
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const CHECKSUM_SIZE: usize = 8;
+
+/// The descriptor of `config`, with its checksum.
+pub fn encode(config: &MultisigConfig) -> Result<String, Error> {
+    let plain = MultisigConfig::new(
+        config.pubkey_hashes().to_vec(),
+        config.require_first_n(),
+        config.threshold(),
+    )?
+    .with_since(config.since())
+    .with_key_format(config.key_format());
+    if config.clone().with_proof(None) != plain {
+        return Err(invalid(
+            "the config holds more than a quorum, a key format and a since".to_string(),
+        ));
+    }
+    let name = match config.key_format() {
+        KeyFormat::Compressed => "multi",
+        KeyFormat::Uncompressed => "multi_uncompressed",
+        KeyFormat::Either => "multi_either",
+    };
+    let mut descriptor = format!(
+        "{}({},{}",
+        name,
+        config.threshold(),
+        config.require_first_n()
+    );
+    for hash in config.pubkey_hashes() {
+        descriptor.push(',');
+        descriptor.push_str(&hex::encode(hash));
+    }
+    descriptor.push(')');
+    if let Some(since) = config.since() {
+        descriptor = format!("since({:#x},{})", since, descriptor);
+    }
+    let checksum = checksum(&descriptor)?;
+    Ok(format!("{}#{}", descriptor, checksum))
+}
+
+/// The config of `descriptor`, its checksum checked.
+pub fn decode(descriptor: &str) -> Result<MultisigConfig, Error> {
+    let descriptor = descriptor.trim();
+    let (body, given) = match descriptor.rsplit_once('#') {
+        Some((body, given)) => (body, given),
+        None => {
+            return Err(invalid(format!(
+                "without checksum, append #{} if it is right",
+                checksum(descriptor)?
+            )))
+        }
+    };
+    let expected = checksum(body)?;
+    if given != expected {
+        return Err(invalid(format!(
+            "checksum mismatch, #{} given, #{} computed: the descriptor is mistyped",
+            given, expected
+        )));
+    }
+
+    let (since, multi) = match call(body, "since") {
+        Some(args) => {
+            let (since, multi) = args
+                .split_once(',')
+                .ok_or_else(|| invalid("expected `since(<since>,multi(...))`".to_string()))?;
+            let since = since
+                .strip_prefix("0x")
+                .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| invalid(format!("invalid since `{}`", since)))?;
+            (Some(since), multi)
+        }
+        None => (None, body),
+    };
+    let formats = [
+        ("multi", KeyFormat::Compressed),
+        ("multi_uncompressed", KeyFormat::Uncompressed),
+        ("multi_either", KeyFormat::Either),
+    ];
+    let (key_format, args) = formats
+        .iter()
+        .find_map(|(name, format)| call(multi, name).map(|args| (*format, args)))
+        .ok_or_else(|| invalid("expected `multi(<threshold>,<first n>,<hashes>)`".to_string()))?;
+    let mut args = args.split(',');
+    let mut number = |field: &str| {
+        args.next()
+            .and_then(|arg| arg.parse::<u8>().ok())
+            .ok_or_else(|| invalid(format!("invalid {}", field)))
+    };
+    let threshold = number("threshold")?;
+    let require_first_n = number("require_first_n")?;
+    let hashes = args
+        .map(|arg| {
+            let mut hash = [0u8; BLAKE160_SIZE];
+            hex::decode_to_slice(arg, &mut hash)
+                .map_err(|_| invalid(format!("invalid pubkey hash `{}`", arg)))?;
+            Ok(hash)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(MultisigConfig::new(hashes, require_first_n, threshold)?
+        .with_since(since)
+        .with_key_format(key_format))
+}
+
+/// The BIP-380 checksum of `descriptor`, without the `#`.
+pub fn checksum(descriptor: &str) -> Result<String, Error> {
+    let mut c = 1u64;
+    let mut class = 0u64;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET
+            .find(ch)
+            .ok_or_else(|| invalid(format!("invalid character `{}`", ch)))?
+            as u64;
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..CHECKSUM_SIZE {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    Ok((0..CHECKSUM_SIZE)
+        .map(|i| CHECKSUM_CHARSET[((c >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect())
+}
+
+fn polymod(c: u64, value: u64) -> u64 {
+    const GENERATORS: [u64; 5] = [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ];
+    let top = c >> 35;
+    let mut c = ((c & 0x7_ffff_ffff) << 5) ^ value;
+    for (i, generator) in GENERATORS.iter().enumerate() {
+        if top >> i & 1 == 1 {
+            c ^= generator;
+        }
+    }
+    c
+}
+
+/// The arguments of `name(...)` when `s` is that call.
+fn call<'a>(s: &'a str, name: &str) -> Option<&'a str> {
+    s.strip_prefix(name)?.strip_prefix('(')?.strip_suffix(')')
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidConfig(format!("descriptor {}", message))
+}
@@ -0,0 +1,164 @@
+use ckb_hash::new_blake2b;
+use ckb_types::{bytes::Bytes, core::TransactionView, packed::WitnessArgs, prelude::*};
+
+use crate::{
+    config::{multisig_script_len_of, MultisigConfig},
+    constants::{DIGEST_SIZE, SIGNATURE_SIZE},
+    error::Error,
+    witness::{legacy_lock_index, witness_args, MultisigLock},
+};
+
+/// Compute the message the contract verifies signatures against.
+///
+/// `input_indices` are the inputs of the script group, the first one carries
+/// the multisig lock field. Mirrors `entry.rs`:
+///
+/// * the transaction hash
+/// * the first group witness, with every signature slot zeroed
+/// * the rest witnesses of the group
+/// * the witnesses beyond the number of inputs
+///
+/// each witness is prefixed by its length as a little endian u64.
+pub fn generate_message(
+    tx: &TransactionView,
+    input_indices: &[usize],
+) -> Result<[u8; DIGEST_SIZE], Error> {
+    let first = first_index(input_indices)?;
+    let witness = tx
+        .witnesses()
+        .get(first)
+        .ok_or_else(|| Error::InvalidWitness(format!("missing witness #{}", first)))?;
+    let zero_witness = zero_signatures(&witness.raw_data())
+        .map_err(|err| Error::InvalidWitness(format!("witness #{}: {}", first, err)))?;
+    Ok(hash_group(tx, input_indices, &zero_witness))
+}
+
+/// `generate_message` under a config `with_legacy_witnesses`, whose lock
+/// field may sit in a later witness of the group, see
+/// `witness::legacy_lock_index`: that witness is hashed first, the other
+/// witnesses of the group following in order.
+pub fn generate_legacy_message(
+    tx: &TransactionView,
+    input_indices: &[usize],
+) -> Result<[u8; DIGEST_SIZE], Error> {
+    let lock_index = legacy_lock_index(tx, input_indices).ok_or_else(|| {
+        Error::InvalidWitness("no lock field in the witnesses of the group".to_string())
+    })?;
+    let mut indices = vec![lock_index];
+    indices.extend(input_indices.iter().filter(|i| **i != lock_index));
+    generate_message(tx, &indices)
+}
+
+/// Compute the sighash of the script group of `config`, the digest its
+/// cosigners sign, for validating signers implemented elsewhere against
+/// the contract. See `src/tests/vectors/sighash.json` for test vectors.
+///
+/// Unlike `generate_message` the lock field doesn't have to be set yet: a
+/// missing first witness or lock field is taken as the unsigned lock of
+/// `config`, the one the signatures go into. A lock field already there
+/// must carry the multisig script of `config`, its signatures are ignored.
+pub fn compute_sighash(
+    tx: &TransactionView,
+    group_indices: &[usize],
+    config: &MultisigConfig,
+) -> Result<[u8; DIGEST_SIZE], Error> {
+    let first = first_index(group_indices)?;
+    if first >= tx.inputs().len() {
+        return Err(Error::InvalidWitness(format!(
+            "input #{} out of range",
+            first
+        )));
+    }
+    let witness = witness_args(tx, first)?;
+    if let Some(lock) = witness.lock().to_opt() {
+        let lock = MultisigLock::parse(&lock.raw_data())?;
+        if lock.config().multisig_script() != config.multisig_script() {
+            return Err(Error::InvalidWitness(format!(
+                "witness #{} is locked by another config",
+                first
+            )));
+        }
+    }
+    let zero_witness = witness
+        .as_builder()
+        .lock(Some(config.placeholder_lock()).pack())
+        .build()
+        .as_bytes();
+    Ok(hash_group(tx, group_indices, &zero_witness))
+}
+
+/// Compute the sighash the fee key of the lock args signs alone: the same
+/// message as the multisig signatures, with the 65 bytes signature of the
+/// fee key in the lock field instead of the multisig one.
+pub fn compute_fee_sighash(
+    tx: &TransactionView,
+    group_indices: &[usize],
+) -> Result<[u8; DIGEST_SIZE], Error> {
+    let first = first_index(group_indices)?;
+    if first >= tx.inputs().len() {
+        return Err(Error::InvalidWitness(format!(
+            "input #{} out of range",
+            first
+        )));
+    }
+    let zero_witness = witness_args(tx, first)?
+        .as_builder()
+        .lock(Some(Bytes::from(vec![0u8; SIGNATURE_SIZE])).pack())
+        .build()
+        .as_bytes();
+    Ok(hash_group(tx, group_indices, &zero_witness))
+}
+
+fn first_index(input_indices: &[usize]) -> Result<usize, Error> {
+    input_indices
+        .first()
+        .copied()
+        .ok_or_else(|| Error::InvalidWitness("empty script group".to_string()))
+}
+
+/// The hash of the message once the first group witness is zeroed.
+fn hash_group(
+    tx: &TransactionView,
+    input_indices: &[usize],
+    zero_witness: &[u8],
+) -> [u8; DIGEST_SIZE] {
+    let mut blake2b = new_blake2b();
+    blake2b.update(tx.hash().as_slice());
+    blake2b.update(&(zero_witness.len() as u64).to_le_bytes());
+    blake2b.update(zero_witness);
+    let rest = input_indices[1..]
+        .iter()
+        .map_while(|i| tx.witnesses().get(*i))
+        .chain(tx.witnesses().into_iter().skip(tx.inputs().len()));
+    for witness in rest {
+        let data = witness.raw_data();
+        blake2b.update(&(data.len() as u64).to_le_bytes());
+        blake2b.update(&data);
+    }
+    let mut message = [0u8; DIGEST_SIZE];
+    blake2b.finalize(&mut message);
+    message
+}
+
+/// The witness with every signature slot of its lock field zeroed, after
+/// the multisig script of any encoding and the proof of a config tree
+/// heading it, see `config::multisig_script_len_of`.
+pub fn zero_signatures(witness: &[u8]) -> Result<Bytes, String> {
+    let witness = WitnessArgs::from_slice(witness).map_err(|err| err.to_string())?;
+    let mut lock = witness
+        .lock()
+        .to_opt()
+        .ok_or_else(|| "lock field is missing".to_string())?
+        .raw_data()
+        .to_vec();
+    let script_len = match multisig_script_len_of(&lock) {
+        Some(script_len) if lock.len() >= script_len => script_len,
+        _ => return Err("lock field too short".to_string()),
+    };
+    lock[script_len..].fill(0);
+    Ok(witness
+        .as_builder()
+        .lock(Some(Bytes::from(lock)).pack())
+        .build()
+        .as_bytes())
+}
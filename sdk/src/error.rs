@@ -0,0 +1,99 @@
+use thiserror::Error;
+
+/// Error
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("secp256k1 error: `{0}`")]
+    Secp256k1(#[from] secp256k1::Error),
+
+    #[error("invalid multisig config: `{0}`")]
+    InvalidConfig(String),
+
+    #[error("invalid config file: `{0}`")]
+    InvalidConfigFile(String),
+
+    #[error("invalid signing request: `{0}`")]
+    InvalidRequest(String),
+
+    #[error("invalid CSV: `{0}`")]
+    InvalidCsv(String),
+
+    #[error("invalid deployment: `{0}`")]
+    InvalidDeployment(String),
+
+    #[error("invalid policy: `{0}`")]
+    InvalidPolicy(String),
+
+    #[error("invalid audit log: `{0}`")]
+    InvalidAuditLog(String),
+
+    #[error("invalid QR payload: `{0}`")]
+    InvalidPayload(String),
+
+    #[error("invalid witness: `{0}`")]
+    InvalidWitness(String),
+
+    #[error("all the signature slots are already filled")]
+    TooManySignatures,
+
+    #[error("verification failed: `{0}`")]
+    Verification(String),
+
+    #[error("invalid parameter: `{0}`")]
+    InvalidParameter(String),
+
+    #[error("insufficient capacity: `{0}`")]
+    InsufficientCapacity(String),
+
+    #[error("invalid since: `{0}`")]
+    InvalidSince(String),
+
+    #[error("transaction dependency error: `{0}`")]
+    TransactionDependency(String),
+
+    #[error("rpc error: `{0}`")]
+    Rpc(String),
+
+    #[error("webhook error: `{0}`")]
+    Webhook(String),
+
+    #[error("signature does not match the public key of the signer")]
+    SignatureMismatch,
+
+    #[error("invalid mnemonic: `{0}`")]
+    InvalidMnemonic(String),
+
+    #[error("invalid derivation path: `{0}`")]
+    InvalidDerivationPath(String),
+
+    #[error("ledger transport error: `{0}`")]
+    LedgerTransport(String),
+
+    #[error("ledger app returned status word 0x{0:04x}")]
+    LedgerStatus(u16),
+
+    #[error("unexpected ledger response: `{0}`")]
+    LedgerResponse(String),
+
+    #[error("trezor transport error: `{0}`")]
+    TrezorTransport(String),
+
+    #[error("trezor failure {0}: `{1}`")]
+    TrezorFailure(u64, String),
+
+    #[error("unexpected trezor response: `{0}`")]
+    TrezorResponse(String),
+
+    #[error("kms error: `{0}`")]
+    Kms(String),
+
+    #[error("unsupported by the hardware wallet: `{0}`")]
+    HardwareUnsupported(String),
+}
+
+#[cfg(feature = "chain")]
+impl From<ckb_sdk::RpcError> for Error {
+    fn from(err: ckb_sdk::RpcError) -> Self {
+        Error::Rpc(err.to_string())
+    }
+}
@@ -0,0 +1,102 @@
+//! Fee estimation of multisig transactions.
+//!
+//! The fee is paid before the cosigners sign, so the transaction is measured
+//! with the complete lock field of every config: the multisig script plus
+//! `threshold` signatures. Unsigned and partially signed proposals therefore
+//! estimate the same fee as the final transaction.
+
+use ckb_sdk::{tx_builder::bytes_per_cycle, types::ScriptGroup};
+use ckb_types::{
+    bytes::Bytes,
+    core::{FeeRate, TransactionView},
+    packed::WitnessArgs,
+    prelude::*,
+};
+
+use crate::{config::MultisigConfig, error::Error, unlock::set_lock, witness::MultisigLock};
+
+/// Size of a witness carrying only the complete lock field of `config`, and
+/// the count of cells of a config `with_spend_all`, as serialized in the
+/// witnesses of a transaction.
+pub fn witness_size(config: &MultisigConfig) -> usize {
+    let total = Some(Bytes::from(vec![0u8; 4])).filter(|_| config.spend_all());
+    let witness = WitnessArgs::new_builder()
+        .lock(Some(config.placeholder_lock()).pack())
+        .input_type(total.pack())
+        .build();
+    // the offset in the witnesses vector and the bytes header
+    4 + witness.as_bytes().pack().as_slice().len()
+}
+
+/// Weight of a transaction as the tx pool computes it: the serialized size,
+/// or the size equivalent of the cycles when they dominate.
+pub fn weight(size: u64, cycles: Option<u64>) -> u64 {
+    match cycles {
+        Some(cycles) => size.max((cycles as f64 * bytes_per_cycle()) as u64),
+        None => size,
+    }
+}
+
+/// Result of an estimation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Estimate {
+    /// Serialized size in block once fully signed.
+    pub size: u64,
+    pub weight: u64,
+    /// In shannons.
+    pub fee: u64,
+}
+
+pub struct FeeEstimator {
+    fee_rate: FeeRate,
+}
+
+impl FeeEstimator {
+    /// `fee_rate` in shannons per 1000 bytes.
+    pub fn new(fee_rate: u64) -> Self {
+        FeeEstimator {
+            fee_rate: FeeRate::from_u64(fee_rate),
+        }
+    }
+
+    pub fn fee_rate(&self) -> u64 {
+        self.fee_rate.as_u64()
+    }
+
+    /// `tx` with the first witness of each group replaced by the complete
+    /// lock field of its config, other witness fields are kept.
+    pub fn sized_tx(
+        tx: &TransactionView,
+        groups: &[(ScriptGroup, MultisigConfig)],
+    ) -> Result<TransactionView, Error> {
+        groups.iter().try_fold(tx.clone(), |tx, (group, config)| {
+            set_lock(&tx, group, &MultisigLock::new(config.clone()))
+        })
+    }
+
+    /// Estimate the fee of `tx` once every group in `groups` is signed.
+    ///
+    /// `cycles` are the verification cycles of the whole transaction when
+    /// measured, e.g. by a dry run, leave `None` to price by size only.
+    pub fn estimate(
+        &self,
+        tx: &TransactionView,
+        groups: &[(ScriptGroup, MultisigConfig)],
+        cycles: Option<u64>,
+    ) -> Result<Estimate, Error> {
+        let size = Self::sized_tx(tx, groups)?
+            .data()
+            .as_reader()
+            .serialized_size_in_block() as u64;
+        Ok(self.estimate_with_size(size, cycles))
+    }
+
+    pub fn estimate_with_size(&self, size: u64, cycles: Option<u64>) -> Estimate {
+        let weight = weight(size, cycles);
+        Estimate {
+            size,
+            weight,
+            fee: self.fee_rate.fee(weight).as_u64(),
+        }
+    }
+}
@@ -0,0 +1,525 @@
+//! Hierarchical deterministic keys of the software signer: BIP-39 mnemonics
+//! and BIP-32 derivation, so that cosigners can be set up and recovered from
+//! seed phrases.
+//!
+//! CKB wallets, ckb-cli and Neuron among them, derive their keys at the
+//! BIP-44 paths `m/44'/309'/account'/0/index`, see `ckb_path`, the first
+//! being `ledger::DEFAULT_PATH`. A coordinator given the extended public key
+//! of an account, serialized as a BIP-32 `xpub`, derives the identities of
+//! its keys without any secret, see `ExtendedPubKey::identities`.
+//!
+//! Only the English wordlist is supported. Passphrases are used as given, a
+//! non ASCII one must already be in NFKD form to match other wallets.
+
+use std::{fmt, ops::Range, str::FromStr};
+
+use hmac::{Hmac, Mac};
+use secp256k1::{
+    hashes::{hash160, Hash},
+    PublicKey, Scalar, SecretKey, SECP256K1,
+};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{
+    constants::BLAKE160_SIZE,
+    error::Error,
+    ledger::{DerivationPath, HARDENED},
+    signer::{pubkey_identity, SecpSigner},
+};
+
+/// The BIP-39 English wordlist, sorted.
+const WORDLIST: &str = include_str!("bip39_english.txt");
+const BITS_PER_WORD: usize = 11;
+const PBKDF2_ROUNDS: u32 = 2048;
+
+/// Version bytes of the BIP-32 serialization of mainnet and testnet extended
+/// public keys, `xpub` and `tpub`.
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TPUB_VERSION: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+const XPUB_SIZE: usize = 78;
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Registered coin type of CKB in SLIP-44.
+pub const CKB_COIN_TYPE: u32 = 309;
+/// Word counts of a mnemonic, from 128 to 256 bits of entropy.
+pub const WORD_COUNTS: [usize; 5] = [12, 15, 18, 21, 24];
+
+/// A BIP-39 mnemonic, its words single space separated. Debug output hides
+/// the words.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Mnemonic {
+    phrase: String,
+    entropy: Vec<u8>,
+}
+
+impl Mnemonic {
+    /// A new mnemonic of `word_count` words out of the randomness of the
+    /// operating system.
+    pub fn generate(word_count: usize) -> Result<Self, Error> {
+        if !WORD_COUNTS.contains(&word_count) {
+            return Err(Error::InvalidMnemonic(format!(
+                "{} words, expected one of {:?}",
+                word_count, WORD_COUNTS
+            )));
+        }
+        let mut entropy = vec![0u8; word_count * 4 / 3];
+        getrandom::getrandom(&mut entropy)
+            .map_err(|err| Error::InvalidMnemonic(format!("no randomness: {}", err)))?;
+        Self::from_entropy(&entropy)
+    }
+
+    /// The mnemonic of 16 to 32 bytes of entropy, a multiple of 4.
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, Error> {
+        if entropy.len() < 16 || entropy.len() > 32 || !entropy.len().is_multiple_of(4) {
+            return Err(Error::InvalidMnemonic(format!(
+                "{} bytes of entropy",
+                entropy.len()
+            )));
+        }
+        let checksum = Sha256::digest(entropy);
+        let bit = |i: usize| {
+            let byte = match entropy.get(i / 8) {
+                Some(byte) => *byte,
+                None => checksum[i / 8 - entropy.len()],
+            };
+            usize::from(byte >> (7 - i % 8) & 1)
+        };
+        // a checksum bit every 32 bits of entropy
+        let bits = entropy.len() * 8 + entropy.len() / 4;
+        let words: Vec<_> = WORDLIST.lines().collect();
+        let phrase = (0..bits / BITS_PER_WORD)
+            .map(|word| {
+                let index = (0..BITS_PER_WORD)
+                    .fold(0, |index, j| index << 1 | bit(word * BITS_PER_WORD + j));
+                words[index]
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(Mnemonic {
+            phrase,
+            entropy: entropy.to_vec(),
+        })
+    }
+
+    /// Recover a mnemonic written down, its checksum checked. Words are
+    /// separated by any whitespace, in any case.
+    pub fn from_phrase(phrase: &str) -> Result<Self, Error> {
+        let words: Vec<_> = WORDLIST.lines().collect();
+        let indices = phrase
+            .split_whitespace()
+            .map(|word| {
+                words
+                    .binary_search(&word.to_lowercase().as_str())
+                    .map_err(|_| Error::InvalidMnemonic(format!("unknown word {}", word)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !WORD_COUNTS.contains(&indices.len()) {
+            return Err(Error::InvalidMnemonic(format!(
+                "{} words, expected one of {:?}",
+                indices.len(),
+                WORD_COUNTS
+            )));
+        }
+        let mut entropy = vec![0u8; indices.len() * 4 / 3];
+        for i in 0..entropy.len() * 8 {
+            let bit = indices[i / BITS_PER_WORD] >> (BITS_PER_WORD - 1 - i % BITS_PER_WORD) & 1;
+            entropy[i / 8] |= (bit as u8) << (7 - i % 8);
+        }
+        let mnemonic = Self::from_entropy(&entropy)?;
+        let expected = mnemonic
+            .phrase
+            .split(' ')
+            .map(|word| words.binary_search(&word));
+        if !expected.eq(indices.into_iter().map(Ok)) {
+            return Err(Error::InvalidMnemonic("checksum mismatch".to_string()));
+        }
+        Ok(mnemonic)
+    }
+
+    pub fn phrase(&self) -> &str {
+        &self.phrase
+    }
+
+    pub fn entropy(&self) -> &[u8] {
+        &self.entropy
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.phrase.split(' ').count()
+    }
+
+    /// The 64 bytes seed of BIP-39, the mnemonic stretched with the salt
+    /// `mnemonic` followed by `passphrase`, empty when there is none.
+    pub fn to_seed(&self, passphrase: &str) -> [u8; 64] {
+        let mut seed = [0u8; 64];
+        let salt = format!("mnemonic{}", passphrase);
+        pbkdf2::pbkdf2_hmac::<Sha512>(
+            self.phrase.as_bytes(),
+            salt.as_bytes(),
+            PBKDF2_ROUNDS,
+            &mut seed,
+        );
+        seed
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_phrase(s)
+    }
+}
+
+impl fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mnemonic({} words)", self.word_count())
+    }
+}
+
+/// The path of key `index` of `account` in CKB wallets,
+/// `m/44'/309'/account'/0/index`.
+pub fn ckb_path(account: u32, index: u32) -> Result<DerivationPath, Error> {
+    if account >= HARDENED || index >= HARDENED {
+        return Err(Error::InvalidDerivationPath(format!(
+            "account {} index {}",
+            account, index
+        )));
+    }
+    Ok(DerivationPath::new(vec![
+        44 | HARDENED,
+        CKB_COIN_TYPE | HARDENED,
+        account | HARDENED,
+        0,
+        index,
+    ]))
+}
+
+/// The path of the extended public key of `account`, `m/44'/309'/account'`.
+pub fn ckb_account_path(account: u32) -> Result<DerivationPath, Error> {
+    Ok(DerivationPath::new(
+        ckb_path(account, 0)?.indices()[..3].to_vec(),
+    ))
+}
+
+/// Where a key sits in its tree: its depth, the fingerprint of its parent
+/// and its index under it, all 0 for a master key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Origin {
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+}
+
+impl Origin {
+    fn child(&self, parent: &PublicKey, index: u32) -> Result<Self, Error> {
+        let depth = self
+            .depth
+            .checked_add(1)
+            .ok_or_else(|| Error::InvalidDerivationPath("deeper than 255 levels".to_string()))?;
+        Ok(Origin {
+            depth,
+            parent_fingerprint: fingerprint(parent),
+            child_number: index,
+        })
+    }
+}
+
+/// A BIP-32 extended private key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ExtendedPrivKey {
+    key: SecretKey,
+    chain_code: [u8; 32],
+    origin: Origin,
+}
+
+impl ExtendedPrivKey {
+    /// The master key of a seed, e.g. `Mnemonic::to_seed`.
+    pub fn from_seed(seed: &[u8]) -> Result<Self, Error> {
+        if seed.len() < 16 || seed.len() > 64 {
+            return Err(Error::InvalidParameter(format!(
+                "seed of {} bytes, expected 16 to 64",
+                seed.len()
+            )));
+        }
+        let (key, chain_code) = split(hmac_sha512(b"Bitcoin seed", &[seed]));
+        Ok(ExtendedPrivKey {
+            key: SecretKey::from_slice(&key)?,
+            chain_code,
+            origin: Origin::default(),
+        })
+    }
+
+    /// The key at `path` under this one, `m` standing for this key.
+    pub fn derive(&self, path: &DerivationPath) -> Result<Self, Error> {
+        path.indices()
+            .iter()
+            .try_fold(self.clone(), |key, index| key.child(*index))
+    }
+
+    fn child(&self, index: u32) -> Result<Self, Error> {
+        let data = if index >= HARDENED {
+            let mut data = vec![0u8];
+            data.extend_from_slice(&self.key.secret_bytes());
+            data
+        } else {
+            self.public_key().serialize().to_vec()
+        };
+        let (tweak, chain_code) = split(hmac_sha512(
+            &self.chain_code,
+            &[&data, &index.to_be_bytes()],
+        ));
+        Ok(ExtendedPrivKey {
+            key: self.key.add_tweak(&scalar(tweak)?)?,
+            chain_code,
+            origin: self.origin.child(&self.public_key(), index)?,
+        })
+    }
+
+    pub fn secret_key(&self) -> SecretKey {
+        self.key
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from_secret_key(SECP256K1, &self.key)
+    }
+
+    pub fn chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+
+    /// The extended public key, for deriving identities without the secret.
+    pub fn to_public(&self) -> ExtendedPubKey {
+        ExtendedPubKey {
+            key: self.public_key(),
+            chain_code: self.chain_code,
+            origin: self.origin,
+        }
+    }
+
+    pub fn identity(&self) -> [u8; BLAKE160_SIZE] {
+        pubkey_identity(&self.public_key())
+    }
+
+    pub fn signer(&self) -> SecpSigner {
+        SecpSigner::new(self.key)
+    }
+}
+
+impl fmt::Debug for ExtendedPrivKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ExtendedPrivKey(0x{})", hex::encode(self.identity()))
+    }
+}
+
+/// A BIP-32 extended public key, deriving the public keys of its normal,
+/// non hardened, children. Parsed from and displayed as an `xpub`, a `tpub`
+/// is read as well.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtendedPubKey {
+    key: PublicKey,
+    chain_code: [u8; 32],
+    origin: Origin,
+}
+
+impl ExtendedPubKey {
+    /// A master key, the root of its tree.
+    pub fn new(key: PublicKey, chain_code: [u8; 32]) -> Self {
+        ExtendedPubKey {
+            key,
+            chain_code,
+            origin: Origin::default(),
+        }
+    }
+
+    /// The key at `path` under this one, which can't hold hardened indices.
+    pub fn derive(&self, path: &DerivationPath) -> Result<Self, Error> {
+        path.indices()
+            .iter()
+            .try_fold(self.clone(), |key, index| key.child(*index))
+    }
+
+    fn child(&self, index: u32) -> Result<Self, Error> {
+        if index >= HARDENED {
+            return Err(Error::InvalidDerivationPath(format!(
+                "hardened index {}' under a public key",
+                index - HARDENED
+            )));
+        }
+        let (tweak, chain_code) = split(hmac_sha512(
+            &self.chain_code,
+            &[&self.key.serialize(), &index.to_be_bytes()],
+        ));
+        Ok(ExtendedPubKey {
+            key: self.key.add_exp_tweak(SECP256K1, &scalar(tweak)?)?,
+            chain_code,
+            origin: self.origin.child(&self.key, index)?,
+        })
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.key
+    }
+
+    pub fn chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+
+    pub fn identity(&self) -> [u8; BLAKE160_SIZE] {
+        pubkey_identity(&self.key)
+    }
+
+    /// Depth in the tree, 3 for the key of an account.
+    pub fn depth(&self) -> u8 {
+        self.origin.depth
+    }
+
+    /// The identities of the keys `indices` of an account, this being the
+    /// key at `ckb_account_path`, as listed in a config.
+    pub fn identities(&self, indices: Range<u32>) -> Result<Vec<[u8; BLAKE160_SIZE]>, Error> {
+        let external = self.child(0)?;
+        indices
+            .map(|index| Ok(external.child(index)?.identity()))
+            .collect()
+    }
+}
+
+impl FromStr for ExtendedPubKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| Error::InvalidParameter(format!("xpub {}: {}", s, reason));
+        let data = base58check_decode(s).ok_or_else(|| invalid("bad base58 checksum"))?;
+        if data.len() != XPUB_SIZE {
+            return Err(invalid("wrong length"));
+        }
+        if data[..4] != XPUB_VERSION && data[..4] != TPUB_VERSION {
+            return Err(invalid("not an extended public key"));
+        }
+        let mut parent_fingerprint = [0u8; 4];
+        parent_fingerprint.copy_from_slice(&data[5..9]);
+        let mut child_number = [0u8; 4];
+        child_number.copy_from_slice(&data[9..13]);
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&data[13..45]);
+        let origin = Origin {
+            depth: data[4],
+            parent_fingerprint,
+            child_number: u32::from_be_bytes(child_number),
+        };
+        if origin.depth == 0 && origin != Origin::default() {
+            return Err(invalid("master key with a parent"));
+        }
+        Ok(ExtendedPubKey {
+            key: PublicKey::from_slice(&data[45..])?,
+            chain_code,
+            origin,
+        })
+    }
+}
+
+impl fmt::Display for ExtendedPubKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut data = Vec::with_capacity(XPUB_SIZE);
+        data.extend_from_slice(&XPUB_VERSION);
+        data.push(self.origin.depth);
+        data.extend_from_slice(&self.origin.parent_fingerprint);
+        data.extend_from_slice(&self.origin.child_number.to_be_bytes());
+        data.extend_from_slice(&self.chain_code);
+        data.extend_from_slice(&self.key.serialize());
+        f.write_str(&base58check_encode(&data))
+    }
+}
+
+/// The first 4 bytes of the hash160, ripemd160 of sha256, of a key.
+fn fingerprint(key: &PublicKey) -> [u8; 4] {
+    let hash = hash160::Hash::hash(&key.serialize()).to_byte_array();
+    let mut fingerprint = [0u8; 4];
+    fingerprint.copy_from_slice(&hash[..4]);
+    fingerprint
+}
+
+fn base58check_encode(payload: &[u8]) -> String {
+    let mut data = payload.to_vec();
+    data.extend_from_slice(&double_sha256(payload)[..4]);
+    // base 58 digits, least significant first
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in &data {
+        let mut carry = u32::from(*byte);
+        for digit in digits.iter_mut() {
+            carry += u32::from(*digit) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = data.iter().take_while(|byte| **byte == 0).count();
+    std::iter::repeat_n('1', zeros)
+        .chain(
+            digits
+                .iter()
+                .rev()
+                .map(|digit| char::from(BASE58_ALPHABET[usize::from(*digit)])),
+        )
+        .collect()
+}
+
+fn base58check_decode(s: &str) -> Option<Vec<u8>> {
+    // base 256 digits, least significant first
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|digit| *digit == c)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += u32::from(*byte) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = s.bytes().take_while(|c| *c == b'1').count();
+    let mut data = vec![0u8; zeros];
+    data.extend(bytes.iter().rev());
+    if data.len() < 4 {
+        return None;
+    }
+    let (payload, checksum) = data.split_at(data.len() - 4);
+    if double_sha256(payload)[..4] != *checksum {
+        return None;
+    }
+    Some(payload.to_vec())
+}
+
+fn double_sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    let mut output = [0u8; 64];
+    output.copy_from_slice(&mac.finalize().into_bytes());
+    output
+}
+
+fn split(output: [u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&output[..32]);
+    right.copy_from_slice(&output[32..]);
+    (left, right)
+}
+
+/// A tweak out of the curve order, with a probability below 2^-127, makes
+/// the index invalid, BIP-32 moves to the next one.
+fn scalar(tweak: [u8; 32]) -> Result<Scalar, Error> {
+    Scalar::from_be_bytes(tweak)
+        .map_err(|_| Error::InvalidDerivationPath("invalid child, use the next index".to_string()))
+}
@@ -0,0 +1,199 @@
+//! Transaction history of a config: every transaction touching its lock,
+//! from the indexer, or another backend, see `backend.rs`, summed up as what
+//! it moved in or out of the config.
+//!
+//! A transaction spending cells of the config is outgoing, the capacity it
+//! sends to other locks and those locks being its amount and counterparties,
+//! the change back to the config aside. Any other is incoming, the capacity
+//! paid to the config and the locks of the cells it spends being its amount
+//! and counterparties.
+
+use ckb_sdk::rpc::ckb_indexer::{ScriptType, SearchKey};
+use ckb_types::{
+    core::{ScriptHashType, TransactionView},
+    packed::Script,
+    prelude::*,
+    H256,
+};
+
+use crate::{
+    backend::{BoxedBackend, NodeIndexer},
+    config::MultisigConfig,
+    error::Error,
+};
+
+const PAGE_SIZE: u32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub tx_hash: H256,
+    pub block_number: u64,
+    pub direction: Direction,
+    /// Shannons paid to the config when incoming, sent to other locks when
+    /// outgoing.
+    pub amount: u64,
+    /// The locks paying the config when incoming, the locks paid when
+    /// outgoing, each once, in order.
+    pub counterparties: Vec<Script>,
+}
+
+impl HistoryEntry {
+    /// The entry of `tx` spending cells of `lock_script`.
+    pub fn outgoing(tx: &TransactionView, block_number: u64, lock_script: &Script) -> Self {
+        let mut amount = 0;
+        let mut counterparties = Vec::new();
+        for output in tx.outputs() {
+            let lock = output.lock();
+            if lock == *lock_script {
+                continue;
+            }
+            amount += Unpack::<u64>::unpack(&output.capacity());
+            push_once(&mut counterparties, lock);
+        }
+        HistoryEntry {
+            tx_hash: tx.hash().unpack(),
+            block_number,
+            direction: Direction::Out,
+            amount,
+            counterparties,
+        }
+    }
+
+    /// The entry of `tx` paying `lock_script`, `senders` the locks of the
+    /// cells it spends.
+    pub fn incoming(
+        tx: &TransactionView,
+        block_number: u64,
+        lock_script: &Script,
+        senders: Vec<Script>,
+    ) -> Self {
+        let amount = tx
+            .outputs()
+            .into_iter()
+            .filter(|output| output.lock() == *lock_script)
+            .map(|output| Unpack::<u64>::unpack(&output.capacity()))
+            .sum();
+        let mut counterparties = Vec::new();
+        for sender in senders {
+            push_once(&mut counterparties, sender);
+        }
+        HistoryEntry {
+            tx_hash: tx.hash().unpack(),
+            block_number,
+            direction: Direction::In,
+            amount,
+            counterparties,
+        }
+    }
+}
+
+/// The history of one config.
+pub struct History {
+    backend: BoxedBackend,
+    lock_script: Script,
+}
+
+impl History {
+    /// `url` is the RPC of a CKB node with the indexer module enabled.
+    pub fn new(
+        url: &str,
+        config: &MultisigConfig,
+        code_hash: &H256,
+        hash_type: ScriptHashType,
+    ) -> Self {
+        Self::with_backend(
+            Box::new(NodeIndexer::new(url)),
+            config,
+            code_hash,
+            hash_type,
+        )
+    }
+
+    /// Read the chain from `backend`, see `backend.rs`.
+    pub fn with_backend(
+        backend: BoxedBackend,
+        config: &MultisigConfig,
+        code_hash: &H256,
+        hash_type: ScriptHashType,
+    ) -> Self {
+        History {
+            backend,
+            lock_script: config.lock_script(code_hash, hash_type),
+        }
+    }
+
+    pub fn lock_script(&self) -> &Script {
+        &self.lock_script
+    }
+
+    /// Every transaction touching the lock, in chain order. The cells spent
+    /// by the incoming ones are looked up for their senders.
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>, Error> {
+        self.backend.indexed_block(&self.lock_script)?;
+        let search_key = SearchKey {
+            script: self.lock_script.clone().into(),
+            script_type: ScriptType::Lock,
+            script_search_mode: None,
+            filter: None,
+            with_data: None,
+            group_by_transaction: Some(true),
+        };
+        let mut entries = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (txs, next) = self
+                .backend
+                .transactions(search_key.clone(), PAGE_SIZE, cursor)?;
+            let last = txs.len() < PAGE_SIZE as usize;
+            for tx in txs {
+                let block_number = tx.block_number;
+                let spends = tx.inputs > 0;
+                let view = self.backend.lock_transaction(tx)?;
+                entries.push(if spends {
+                    HistoryEntry::outgoing(&view, block_number, &self.lock_script)
+                } else {
+                    let senders = self.senders(&view)?;
+                    HistoryEntry::incoming(&view, block_number, &self.lock_script, senders)
+                });
+            }
+            if last {
+                break;
+            }
+            cursor = Some(next);
+        }
+        Ok(entries)
+    }
+
+    /// The locks of the cells `tx` spends, none for a cellbase.
+    fn senders(&self, tx: &TransactionView) -> Result<Vec<Script>, Error> {
+        let mut senders = Vec::new();
+        for out_point in tx.input_pts_iter() {
+            if out_point.is_null() {
+                continue;
+            }
+            let previous = self.backend.transaction(&out_point.tx_hash().unpack())?;
+            let index: u32 = out_point.index().unpack();
+            let output = previous.output(index as usize).ok_or_else(|| {
+                Error::Rpc(format!(
+                    "transaction {:#x} has no output {}",
+                    previous.hash(),
+                    index
+                ))
+            })?;
+            senders.push(output.lock());
+        }
+        Ok(senders)
+    }
+}
+
+fn push_once(locks: &mut Vec<Script>, lock: Script) {
+    if !locks.contains(&lock) {
+        locks.push(lock);
+    }
+}
@@ -0,0 +1,465 @@
+//! Cloud KMS and HSM backends of the `Signer` trait: AWS KMS, GCP Cloud KMS
+//! and Vault transit, for cosigners whose key never leaves a managed HSM.
+//!
+//! The services only hold the key: `KmsKey` fetches its public key, a DER
+//! `SubjectPublicKeyInfo`, and signs a 32 bytes digest into a DER ECDSA
+//! signature. `KmsSigner` turns that into the 65 bytes signature the
+//! contract recovers: the s is lowered and the recovery id, which the
+//! services don't return, is found by trying every candidate against the
+//! public key, see `signer::to_recoverable`.
+//!
+//! The digest is handed to the services as a SHA-256 one, which it is not
+//! but has the size of: they sign it as is.
+//!
+//! * `AwsKms`: a `ECC_SECG_P256K1` key, the requests signed with SigV4 from
+//!   the access key of the caller;
+//! * `GcpKms`: a `EC_SIGN_SECP256K1_SHA256` key version, with an OAuth
+//!   access token, e.g. `gcloud auth print-access-token`;
+//! * `VaultTransit`: a transit key of a secp256k1 type, e.g. a managed key
+//!   backed by an HSM, with a Vault token.
+
+use std::{
+    convert::TryInto,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::{Hmac, Mac};
+use secp256k1::{ecdsa::Signature, PublicKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    constants::{BLAKE160_SIZE, DIGEST_SIZE, SIGNATURE_SIZE},
+    error::Error,
+    signer::{pubkey_identity, to_recoverable, Signer},
+};
+
+/// The algorithm identifier of secp256k1 keys in a `SubjectPublicKeyInfo`:
+/// id-ecPublicKey with the secp256k1 curve.
+const SECP256K1_SPKI_PREFIX: [u8; 23] = [
+    0x30, 0x56, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b,
+    0x81, 0x04, 0x00, 0x0a, 0x03, 0x42, 0x00,
+];
+
+/// A key held by a KMS.
+pub trait KmsKey {
+    /// The DER `SubjectPublicKeyInfo` of the key.
+    fn public_key_der(&self) -> Result<Vec<u8>, Error>;
+
+    /// A DER ECDSA signature of `digest`.
+    fn sign_der(&self, digest: &[u8; DIGEST_SIZE]) -> Result<Vec<u8>, Error>;
+}
+
+/// The public key of a DER `SubjectPublicKeyInfo`, which must be a
+/// secp256k1 one.
+pub fn parse_spki(der: &[u8]) -> Result<PublicKey, Error> {
+    match der.strip_prefix(&SECP256K1_SPKI_PREFIX[..]) {
+        Some(point) => Ok(PublicKey::from_slice(point)?),
+        None => Err(kms_err("not a secp256k1 public key")),
+    }
+}
+
+/// The DER content of a PEM block.
+pub fn parse_pem(pem: &str) -> Result<Vec<u8>, Error> {
+    let content: String = pem
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect();
+    BASE64
+        .decode(content)
+        .map_err(|err| kms_err(&format!("invalid PEM: {}", err)))
+}
+
+/// A key of a KMS as a member of a config.
+pub struct KmsSigner<K> {
+    key: K,
+    pubkey: PublicKey,
+}
+
+impl<K: KmsKey> KmsSigner<K> {
+    /// The public key is fetched once and cached.
+    pub fn new(key: K) -> Result<Self, Error> {
+        let pubkey = parse_spki(&key.public_key_der()?)?;
+        Ok(KmsSigner { key, pubkey })
+    }
+
+    pub fn pubkey(&self) -> &PublicKey {
+        &self.pubkey
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<K: KmsKey> Signer for KmsSigner<K> {
+    fn identity(&self) -> Result<[u8; BLAKE160_SIZE], Error> {
+        Ok(pubkey_identity(&self.pubkey))
+    }
+
+    fn sign(&self, digest: &[u8; DIGEST_SIZE]) -> Result<[u8; SIGNATURE_SIZE], Error> {
+        let signature = Signature::from_der(&self.key.sign_der(digest)?)?;
+        to_recoverable(digest, &signature, &self.pubkey)
+    }
+}
+
+/// The credentials of an AWS caller.
+#[derive(Clone, Debug)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// With temporary credentials.
+    pub session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    /// From `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN`.
+    pub fn from_env() -> Result<Self, Error> {
+        let var = |name| std::env::var(name).map_err(|_| kms_err(&format!("{} not set", name)));
+        Ok(AwsCredentials {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+/// A key of AWS KMS.
+pub struct AwsKms {
+    key_id: String,
+    region: String,
+    endpoint: String,
+    credentials: AwsCredentials,
+    client: reqwest::blocking::Client,
+}
+
+impl AwsKms {
+    /// `key_id` is the id, the ARN or an alias of the key.
+    pub fn new(key_id: &str, region: &str, credentials: AwsCredentials) -> Self {
+        AwsKms {
+            key_id: key_id.to_string(),
+            region: region.to_string(),
+            endpoint: format!("https://kms.{}.amazonaws.com", region),
+            credentials,
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+
+    /// Another endpoint than the one of the region, e.g. a VPC endpoint.
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.trim_end_matches('/').to_string();
+        self
+    }
+
+    fn call(&self, target: &str, body: Value) -> Result<Value, Error> {
+        let body = body.to_string();
+        let host = self
+            .endpoint
+            .split("://")
+            .nth(1)
+            .unwrap_or(&self.endpoint)
+            .to_string();
+        let date = amz_date(unix_time());
+        let target = format!("TrentService.{}", target);
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host),
+            ("x-amz-date", date.clone()),
+            ("x-amz-target", target),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let authorization = sigv4_authorization(
+            &SigV4Request {
+                method: "POST",
+                path: "/",
+                headers: &headers,
+                payload: body.as_bytes(),
+            },
+            &date,
+            &self.region,
+            "kms",
+            &self.credentials,
+        );
+        let mut request = self
+            .client
+            .post(format!("{}/", self.endpoint))
+            .header("authorization", authorization)
+            .body(body);
+        for (name, value) in &headers {
+            if *name != "host" {
+                request = request.header(*name, value);
+            }
+        }
+        send(request)
+    }
+}
+
+impl KmsKey for AwsKms {
+    fn public_key_der(&self) -> Result<Vec<u8>, Error> {
+        let answer = self.call("GetPublicKey", json!({ "KeyId": self.key_id }))?;
+        decode_base64(&answer["PublicKey"])
+    }
+
+    fn sign_der(&self, digest: &[u8; DIGEST_SIZE]) -> Result<Vec<u8>, Error> {
+        let answer = self.call(
+            "Sign",
+            json!({
+                "KeyId": self.key_id,
+                "Message": BASE64.encode(digest),
+                "MessageType": "DIGEST",
+                "SigningAlgorithm": "ECDSA_SHA_256",
+            }),
+        )?;
+        decode_base64(&answer["Signature"])
+    }
+}
+
+/// A key version of GCP Cloud KMS.
+pub struct GcpKms {
+    /// `projects/*/locations/*/keyRings/*/cryptoKeys/*/cryptoKeyVersions/*`.
+    name: String,
+    token: String,
+    endpoint: String,
+    client: reqwest::blocking::Client,
+}
+
+impl GcpKms {
+    pub fn new(name: &str, token: &str) -> Self {
+        GcpKms {
+            name: name.to_string(),
+            token: token.to_string(),
+            endpoint: "https://cloudkms.googleapis.com".to_string(),
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.trim_end_matches('/').to_string();
+        self
+    }
+}
+
+impl KmsKey for GcpKms {
+    fn public_key_der(&self) -> Result<Vec<u8>, Error> {
+        let url = format!("{}/v1/{}/publicKey", self.endpoint, self.name);
+        let answer = send(self.client.get(url).bearer_auth(&self.token))?;
+        parse_pem(answer["pem"].as_str().unwrap_or_default())
+    }
+
+    fn sign_der(&self, digest: &[u8; DIGEST_SIZE]) -> Result<Vec<u8>, Error> {
+        let url = format!("{}/v1/{}:asymmetricSign", self.endpoint, self.name);
+        let body = json!({ "digest": { "sha256": BASE64.encode(digest) } });
+        let answer = send(self.client.post(url).bearer_auth(&self.token).json(&body))?;
+        decode_base64(&answer["signature"])
+    }
+}
+
+/// A key of the Vault transit secrets engine.
+pub struct VaultTransit {
+    addr: String,
+    token: String,
+    /// Where the engine is mounted, `transit` by default.
+    mount: String,
+    key: String,
+    /// The key version, the latest when missing.
+    version: Option<u32>,
+    client: reqwest::blocking::Client,
+}
+
+impl VaultTransit {
+    /// `addr` as `VAULT_ADDR`, e.g. `https://vault:8200`.
+    pub fn new(addr: &str, token: &str, key: &str) -> Self {
+        VaultTransit {
+            addr: addr.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            mount: "transit".to_string(),
+            key: key.to_string(),
+            version: None,
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+
+    pub fn mount(mut self, mount: &str) -> Self {
+        self.mount = mount.trim_matches('/').to_string();
+        self
+    }
+
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = Some(version);
+        self
+    }
+}
+
+impl KmsKey for VaultTransit {
+    fn public_key_der(&self) -> Result<Vec<u8>, Error> {
+        let url = format!("{}/v1/{}/keys/{}", self.addr, self.mount, self.key);
+        let answer = send(self.client.get(url).header("X-Vault-Token", &self.token))?;
+        let data = &answer["data"];
+        let version = match self.version {
+            Some(version) => version.to_string(),
+            None => data["latest_version"].to_string(),
+        };
+        let pem = data["keys"][version.as_str()]["public_key"]
+            .as_str()
+            .ok_or_else(|| kms_err(&format!("no public key of version {}", version)))?;
+        parse_pem(pem)
+    }
+
+    fn sign_der(&self, digest: &[u8; DIGEST_SIZE]) -> Result<Vec<u8>, Error> {
+        let url = format!("{}/v1/{}/sign/{}", self.addr, self.mount, self.key);
+        let mut body = json!({
+            "input": BASE64.encode(digest),
+            "prehashed": true,
+            "hash_algorithm": "sha2-256",
+            "marshaling_algorithm": "asn1",
+        });
+        if let Some(version) = self.version {
+            body["key_version"] = json!(version);
+        }
+        let answer = send(
+            self.client
+                .post(url)
+                .header("X-Vault-Token", &self.token)
+                .json(&body),
+        )?;
+        parse_vault_signature(answer["data"]["signature"].as_str().unwrap_or_default())
+    }
+}
+
+/// The DER of a `vault:v<version>:<base64>` signature.
+pub fn parse_vault_signature(signature: &str) -> Result<Vec<u8>, Error> {
+    match signature.splitn(3, ':').collect::<Vec<_>>().as_slice() {
+        ["vault", version, data] if version.starts_with('v') => BASE64
+            .decode(data)
+            .map_err(|err| kms_err(&format!("invalid signature: {}", err))),
+        _ => Err(kms_err(&format!("invalid signature `{}`", signature))),
+    }
+}
+
+/// A request to sign with SigV4.
+pub struct SigV4Request<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    /// Lowercase names, every one of them is signed.
+    pub headers: &'a [(&'a str, String)],
+    pub payload: &'a [u8],
+}
+
+/// The `Authorization` header of `request` with AWS Signature Version 4,
+/// `date` as in `x-amz-date`.
+pub fn sigv4_authorization(
+    request: &SigV4Request,
+    date: &str,
+    region: &str,
+    service: &str,
+    credentials: &AwsCredentials,
+) -> String {
+    let mut headers: Vec<_> = request.headers.iter().collect();
+    headers.sort_by_key(|(name, _)| *name);
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        request.method,
+        request.path,
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(request.payload))
+    );
+    let day = &date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", day, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = format!("AWS4{}", credentials.secret_access_key);
+    let key = hmac_sha256(key.as_bytes(), day.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    let key = hmac_sha256(&key, b"aws4_request");
+    let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed_headers, signature
+    )
+}
+
+/// `YYYYMMDD'T'HHMMSS'Z'` of a unix time.
+pub fn amz_date(unix_time: u64) -> String {
+    // days to civil date, from Howard Hinnant's algorithm
+    let days = (unix_time / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let seconds = unix_time % 86400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("any key size");
+    mac.update(data);
+    mac.finalize().into_bytes().as_slice().try_into().unwrap()
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Send the request, and read the JSON answer of a success.
+fn send(request: reqwest::blocking::RequestBuilder) -> Result<Value, Error> {
+    let response = request.send().map_err(|err| kms_err(&err.to_string()))?;
+    let status = response.status();
+    let body = response.text().map_err(|err| kms_err(&err.to_string()))?;
+    if !status.is_success() {
+        return Err(kms_err(&format!("{}: {}", status, body)));
+    }
+    serde_json::from_str(&body).map_err(|err| kms_err(&format!("invalid answer: {}", err)))
+}
+
+fn decode_base64(value: &Value) -> Result<Vec<u8>, Error> {
+    let data = value
+        .as_str()
+        .ok_or_else(|| kms_err(&format!("expected base64, got {}", value)))?;
+    BASE64
+        .decode(data)
+        .map_err(|err| kms_err(&format!("invalid base64: {}", err)))
+}
+
+fn kms_err(message: &str) -> Error {
+    Error::Kms(message.to_string())
+}
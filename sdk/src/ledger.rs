@@ -0,0 +1,261 @@
+//! Ledger backend of the `Signer` trait.
+//!
+//! Talks to the Nervos CKB Ledger app, the APDU layout follows the app's
+//! `docs/apdu.md`. Since the digest of this lock is not something the app can
+//! display, signing goes through the "sign message hash" instruction, which
+//! requires blind signing to be enabled in the app settings.
+
+use std::{convert::TryFrom, str::FromStr};
+
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    PublicKey,
+};
+
+use crate::{
+    constants::{BLAKE160_SIZE, DIGEST_SIZE, SIGNATURE_SIZE},
+    error::Error,
+    signer::{pubkey_identity, recover_pubkey, serialize_signature, Signer},
+};
+
+pub const CLA: u8 = 0x80;
+
+pub const INS_GET_APP_VERSION: u8 = 0x00;
+pub const INS_GET_PUBLIC_KEY: u8 = 0x02;
+pub const INS_SIGN_MESSAGE_HASH: u8 = 0x07;
+
+pub const P1_FIRST: u8 = 0x00;
+pub const P1_NEXT: u8 = 0x01;
+pub const P1_LAST_MARKER: u8 = 0x80;
+
+pub const SW_OK: u16 = 0x9000;
+
+/// Default derivation path of CKB keys, `m/44'/309'/0'/0/0`.
+pub const DEFAULT_PATH: &str = "m/44'/309'/0'/0/0";
+
+pub(crate) const HARDENED: u32 = 0x8000_0000;
+const MAX_PATH_DEPTH: usize = 10;
+
+pub struct ApduCommand {
+    pub cla: u8,
+    pub ins: u8,
+    pub p1: u8,
+    pub p2: u8,
+    pub data: Vec<u8>,
+}
+
+pub struct ApduAnswer {
+    pub data: Vec<u8>,
+    pub status: u16,
+}
+
+/// Transport delivering APDUs to a device, USB HID for real devices, or a
+/// mock in tests.
+pub trait LedgerTransport {
+    fn exchange(&self, command: &ApduCommand) -> Result<ApduAnswer, Error>;
+}
+
+/// BIP32 derivation path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    pub fn new(indices: Vec<u32>) -> Self {
+        DerivationPath(indices)
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.0
+    }
+
+    /// Path serialization of the app: depth, then every index in big endian.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(1 + self.0.len() * 4);
+        buf.push(self.0.len() as u8);
+        for index in &self.0 {
+            buf.extend_from_slice(&index.to_be_bytes());
+        }
+        buf
+    }
+}
+
+impl Default for DerivationPath {
+    fn default() -> Self {
+        DEFAULT_PATH.parse().expect("default path")
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidDerivationPath(s.to_string());
+        let mut parts = s.split('/');
+        if parts.next() != Some("m") {
+            return Err(invalid());
+        }
+        let indices = parts
+            .map(|part| {
+                let (number, hardened) = match part.strip_suffix('\'') {
+                    Some(number) => (number, true),
+                    None => (part, false),
+                };
+                let index: u32 = number.parse().map_err(|_| invalid())?;
+                if index >= HARDENED {
+                    return Err(invalid());
+                }
+                Ok(if hardened { index | HARDENED } else { index })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if indices.len() > MAX_PATH_DEPTH {
+            return Err(invalid());
+        }
+        Ok(DerivationPath(indices))
+    }
+}
+
+/// A key held by a Ledger device at a given derivation path.
+pub struct LedgerSigner<T> {
+    transport: T,
+    path: DerivationPath,
+    pubkey: PublicKey,
+}
+
+impl<T: LedgerTransport> LedgerSigner<T> {
+    /// Connect to the key at `path`, the public key is fetched once and cached.
+    pub fn new(transport: T, path: DerivationPath) -> Result<Self, Error> {
+        let pubkey = get_public_key(&transport, &path)?;
+        Ok(LedgerSigner {
+            transport,
+            path,
+            pubkey,
+        })
+    }
+
+    pub fn path(&self) -> &DerivationPath {
+        &self.path
+    }
+
+    pub fn pubkey(&self) -> &PublicKey {
+        &self.pubkey
+    }
+
+    /// Version of the CKB app, `(major, minor, patch)`.
+    pub fn app_version(&self) -> Result<(u8, u8, u8), Error> {
+        let data = send(&self.transport, INS_GET_APP_VERSION, P1_FIRST, Vec::new())?;
+        match data.as_slice() {
+            [major, minor, patch, ..] => Ok((*major, *minor, *patch)),
+            _ => Err(Error::LedgerResponse("app version too short".to_string())),
+        }
+    }
+}
+
+impl<T: LedgerTransport> Signer for LedgerSigner<T> {
+    fn identity(&self) -> Result<[u8; BLAKE160_SIZE], Error> {
+        Ok(pubkey_identity(&self.pubkey))
+    }
+
+    fn sign(&self, digest: &[u8; DIGEST_SIZE]) -> Result<[u8; SIGNATURE_SIZE], Error> {
+        send(
+            &self.transport,
+            INS_SIGN_MESSAGE_HASH,
+            P1_FIRST,
+            self.path.serialize(),
+        )?;
+        let data = send(
+            &self.transport,
+            INS_SIGN_MESSAGE_HASH,
+            P1_LAST_MARKER | P1_NEXT,
+            digest.to_vec(),
+        )?;
+        if data.len() != SIGNATURE_SIZE {
+            return Err(Error::LedgerResponse(format!(
+                "signature length {}",
+                data.len()
+            )));
+        }
+        let recid = RecoveryId::try_from(i32::from(data[64]))?;
+        let signature =
+            serialize_signature(&RecoverableSignature::from_compact(&data[..64], recid)?);
+        // never hand out a signature the contract would reject
+        if recover_pubkey(digest, &signature)? != self.pubkey {
+            return Err(Error::SignatureMismatch);
+        }
+        Ok(signature)
+    }
+}
+
+fn get_public_key<T: LedgerTransport>(
+    transport: &T,
+    path: &DerivationPath,
+) -> Result<PublicKey, Error> {
+    let data = send(transport, INS_GET_PUBLIC_KEY, P1_FIRST, path.serialize())?;
+    // response: key length, followed by the (uncompressed) key
+    let len = *data
+        .first()
+        .ok_or_else(|| Error::LedgerResponse("empty public key response".to_string()))?
+        as usize;
+    let key = data
+        .get(1..1 + len)
+        .ok_or_else(|| Error::LedgerResponse("public key response too short".to_string()))?;
+    Ok(PublicKey::from_slice(key)?)
+}
+
+fn send<T: LedgerTransport>(
+    transport: &T,
+    ins: u8,
+    p1: u8,
+    data: Vec<u8>,
+) -> Result<Vec<u8>, Error> {
+    let answer = transport.exchange(&ApduCommand {
+        cla: CLA,
+        ins,
+        p1,
+        p2: 0,
+        data,
+    })?;
+    if answer.status != SW_OK {
+        return Err(Error::LedgerStatus(answer.status));
+    }
+    Ok(answer.data)
+}
+
+#[cfg(feature = "ledger-hid")]
+pub mod hid {
+    use ledger_transport::APDUCommand;
+    use ledger_transport_hid::{hidapi::HidApi, TransportNativeHID};
+
+    use super::{ApduAnswer, ApduCommand, LedgerTransport};
+    use crate::error::Error;
+
+    /// USB HID transport of the first Ledger device found.
+    pub struct HidTransport(TransportNativeHID);
+
+    impl HidTransport {
+        pub fn new() -> Result<Self, Error> {
+            let api = HidApi::new().map_err(|err| Error::LedgerTransport(err.to_string()))?;
+            let transport = TransportNativeHID::new(&api)
+                .map_err(|err| Error::LedgerTransport(err.to_string()))?;
+            Ok(HidTransport(transport))
+        }
+    }
+
+    impl LedgerTransport for HidTransport {
+        fn exchange(&self, command: &ApduCommand) -> Result<ApduAnswer, Error> {
+            let answer = self
+                .0
+                .exchange(&APDUCommand {
+                    cla: command.cla,
+                    ins: command.ins,
+                    p1: command.p1,
+                    p2: command.p2,
+                    data: command.data.as_slice(),
+                })
+                .map_err(|err| Error::LedgerTransport(err.to_string()))?;
+            Ok(ApduAnswer {
+                data: answer.data().to_vec(),
+                status: answer.retcode(),
+            })
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Host side SDK of the ckb-multisig lock.
+//!
+//! See `config.rs` for `MultisigConfig`, the multisig script and lock args.
+//! See `config_file.rs` for the JSON and TOML file format of configs and
+//! `config_tree.rs` for several configs under one lock, `lock_policy.rs` for
+//! the readable policies compiled into them, `descriptor.rs` for the single
+//! line backups of configs.
+//! See `witness.rs` and `digest.rs` for the witness layout and signing message,
+//! `compute_sighash` is the reference for external signers.
+//! See `signer.rs` for the `Signer` abstraction and its software backend.
+//! See `ledger.rs` for the Ledger hardware backend, `trezor.rs` for the
+//! Trezor and OneKey one and `kms.rs` for the cloud KMS ones.
+//! See `deployment.rs` for the registry of the deployments of the lock and
+//! `deploy.rs` for deploying and upgrading the binaries under a type id.
+//! See `unlock.rs` for the ckb-sdk `ScriptUnlocker` integration and
+//! `validate.rs` for the run of the scripts under ckb-vm before broadcasting.
+//! See `scanner.rs` for the watch-only scanner of live cells and `balance.rs`
+//! for the balances summed from them, `collector.rs` for the cell collector
+//! honoring the since, `watcher.rs` for the deposit watcher and `history.rs`
+//! for the transaction history of a config, and `backend.rs` for the chain
+//! data they read, from the indexer of a node, its rich indexer or a light
+//! client, see `light_client.rs`, and `providers.rs` for the ckb-sdk
+//! builders reading it.
+//! See `fee.rs` for the fee estimation and `bump.rs` for the fee bump of stuck
+//! transactions.
+//! See `since.rs` for the since of inputs and `dao.rs` for the Nervos DAO flows.
+//! See `request.rs` for the signing requests passed between cosigners,
+//! `audit.rs` for the verifiable log of the signatures they gave and
+//! `cobuild.rs` for the cobuild messages shown by hardware wallets.
+//! See `qr.rs` for the QR frames moving requests to and from offline signers
+//! and `safe.rs` for the proposals in the shape of the Safe transaction
+//! service.
+//! See `policy.rs` for the off-chain policy a signing machine checks before
+//! signing.
+//! See `nonce.rs` for the nonce cells protecting approvals from replays and
+//! `approval.rs` for the approvals signed before the transaction.
+//! See `sweep.rs` for the consolidation of small cells, `batch.rs` for the
+//! payments to many recipients, `nft.rs` for the transfers of NFTs, `udt.rs`
+//! for the transfers of sUDT and xUDT tokens and `migrate.rs` for the key
+//! rotation.
+//! See `mixed.rs` for the transactions funded by a sighash fee payer and a
+//! config together.
+//! See `error.rs` for the `Error` type.
+//!
+//! Everything built on ckb-sdk is behind the default `chain` feature, without
+//! it the crate is the config, witness, digest and signer logic alone, which
+//! also builds for wasm32.
+
+pub mod approval;
+#[cfg(feature = "chain")]
+pub mod audit;
+#[cfg(feature = "chain")]
+pub mod backend;
+#[cfg(feature = "chain")]
+pub mod balance;
+#[cfg(feature = "chain")]
+pub mod batch;
+#[cfg(feature = "chain")]
+pub mod bump;
+#[cfg(feature = "chain")]
+pub mod cobuild;
+#[cfg(feature = "chain")]
+pub mod collector;
+pub mod config;
+#[cfg(feature = "chain")]
+pub mod config_file;
+pub mod config_tree;
+pub mod constants;
+#[cfg(feature = "chain")]
+pub mod dao;
+#[cfg(feature = "chain")]
+pub mod deploy;
+#[cfg(feature = "chain")]
+pub mod deployment;
+pub mod descriptor;
+pub mod digest;
+pub mod error;
+#[cfg(feature = "chain")]
+pub mod fee;
+pub mod hd;
+#[cfg(feature = "chain")]
+pub mod history;
+#[cfg(feature = "chain")]
+pub mod kms;
+pub mod ledger;
+#[cfg(feature = "chain")]
+pub mod light_client;
+#[cfg(feature = "chain")]
+pub mod lock_policy;
+#[cfg(feature = "chain")]
+pub mod migrate;
+#[cfg(feature = "chain")]
+pub mod mixed;
+#[cfg(feature = "chain")]
+pub mod nft;
+#[cfg(feature = "chain")]
+pub mod nonce;
+#[cfg(feature = "chain")]
+pub mod policy;
+#[cfg(feature = "chain")]
+pub mod providers;
+#[cfg(feature = "chain")]
+pub mod qr;
+#[cfg(feature = "chain")]
+pub mod request;
+#[cfg(feature = "chain")]
+pub mod safe;
+#[cfg(feature = "chain")]
+pub mod scanner;
+pub mod signer;
+#[cfg(feature = "chain")]
+pub mod since;
+pub mod successor;
+#[cfg(feature = "chain")]
+pub mod sweep;
+pub mod trezor;
+#[cfg(feature = "chain")]
+pub mod udt;
+#[cfg(feature = "chain")]
+pub mod unlock;
+#[cfg(feature = "chain")]
+pub mod validate;
+#[cfg(feature = "chain")]
+pub mod watcher;
+pub mod witness;
+pub mod xpub;
+
+pub use config::MultisigConfig;
+pub use digest::compute_sighash;
+pub use error::Error;
+pub use signer::{SecpSigner, Signer};
+#[cfg(feature = "chain")]
+pub use unlock::{MultisigScriptSigner, MultisigUnlocker};
+pub use witness::MultisigLock;
+
+#[cfg(all(test, feature = "chain"))]
+mod tests;
+
+/// blake160 is the first 20 bytes of the ckb flavored blake2b hash.
+pub fn blake160(message: &[u8]) -> [u8; constants::BLAKE160_SIZE] {
+    let mut hash = [0u8; constants::BLAKE160_SIZE];
+    hash.copy_from_slice(&ckb_hash::blake2b_256(message)[..constants::BLAKE160_SIZE]);
+    hash
+}
@@ -0,0 +1,295 @@
+//! Watch-only monitoring through a CKB light client, for the laptops and
+//! mobile bridges which can't host a full node and its indexer.
+//!
+//! The light client follows the headers of the chain, verified by proof of
+//! work and the MMR of the chain root, and downloads only the blocks its
+//! compact filters match for the scripts registered with it. The lock of a
+//! config is registered with `LightClient::watch`, from the block the
+//! config was created at, and the client then syncs the script up to the
+//! tip: `LightClient::synced_block` tells how far.
+//!
+//! `LightClient` is a `ChainBackend`, see `backend.rs`: `Scanner`,
+//! `DepositWatcher` and `History` run on it instead of a full node, e.g.
+//! with `Scanner::light_client`, the same cells, deposits and spends at the
+//! block the script is synced to. The watcher waits for its confirmations
+//! on top of that block rather than on top of the tip. A lock the client
+//! doesn't watch is refused, as is a type script, e.g. of a nonce cell,
+//! until `LightClient::watch_type`. The client looks blocks up by hash only:
+//! the header of a block is that of a transaction it commits.
+
+use ckb_jsonrpc_types::{self as json, JsonBytes};
+use ckb_sdk::{
+    rpc::{
+        ckb_indexer::{Cell, CellType, Order, Pagination, ScriptType, SearchKey},
+        ckb_light_client::{FetchStatus, ScriptStatus, SetScriptsCommand, Tx},
+        LightClientRpcClient,
+    },
+    traits::LiveCell,
+};
+use ckb_types::{
+    core::{BlockView, HeaderView, TransactionView},
+    packed::{self, Script},
+    prelude::*,
+    H256,
+};
+use serde::Deserialize;
+
+use crate::{
+    backend::{count_inputs, ChainBackend, LockTx},
+    error::Error,
+    scanner::BlockInfo,
+};
+
+/// A CKB light client, see `ckb-light-client`.
+pub struct LightClient {
+    client: LightClientRpcClient,
+}
+
+impl LightClient {
+    /// `url` is the RPC of the light client.
+    pub fn new(url: &str) -> Self {
+        LightClient {
+            client: LightClientRpcClient::new(url),
+        }
+    }
+
+    /// Register `lock_script` from `from_block`, e.g. the block its first
+    /// cell was created at, and return true. A script already registered
+    /// keeps its sync progress and false is returned.
+    pub fn watch(&self, lock_script: &Script, from_block: u64) -> Result<bool, Error> {
+        self.watch_script(lock_script, ScriptType::Lock, from_block)
+    }
+
+    /// `watch` for the cells of `type_script`.
+    pub fn watch_type(&self, type_script: &Script, from_block: u64) -> Result<bool, Error> {
+        self.watch_script(type_script, ScriptType::Type, from_block)
+    }
+
+    fn watch_script(
+        &self,
+        script: &Script,
+        script_type: ScriptType,
+        from_block: u64,
+    ) -> Result<bool, Error> {
+        if self.synced_script(script, &script_type)?.is_some() {
+            return Ok(false);
+        }
+        let status = ScriptStatus {
+            script: script.clone().into(),
+            script_type,
+            block_number: from_block.into(),
+        };
+        self.client
+            .set_scripts(vec![status], Some(SetScriptsCommand::Partial))?;
+        Ok(true)
+    }
+
+    /// Stop syncing `lock_script`.
+    pub fn unwatch(&self, lock_script: &Script) -> Result<(), Error> {
+        let status = ScriptStatus {
+            script: lock_script.clone().into(),
+            script_type: ScriptType::Lock,
+            block_number: 0.into(),
+        };
+        self.client
+            .set_scripts(vec![status], Some(SetScriptsCommand::Delete))?;
+        Ok(())
+    }
+
+    /// The block `lock_script` is synced to, `None` when it's not watched.
+    pub fn synced_block(&self, lock_script: &Script) -> Result<Option<u64>, Error> {
+        self.synced_script(lock_script, &ScriptType::Lock)
+    }
+
+    fn synced_script(
+        &self,
+        script: &Script,
+        script_type: &ScriptType,
+    ) -> Result<Option<u64>, Error> {
+        let script: json::Script = script.clone().into();
+        Ok(self
+            .client
+            .get_scripts()?
+            .into_iter()
+            .find(|status| {
+                status.script == script
+                    && matches!(
+                        (&status.script_type, script_type),
+                        (ScriptType::Lock, ScriptType::Lock) | (ScriptType::Type, ScriptType::Type)
+                    )
+            })
+            .map(|status| status.block_number.value()))
+    }
+
+    /// The transaction `tx_hash` and the hash of its block, when
+    /// committed.
+    fn transaction_with_status(
+        &self,
+        tx_hash: &H256,
+    ) -> Result<(json::TransactionView, Option<H256>), Error> {
+        let what = format!("transaction {:#x}", tx_hash);
+        let tx: LightTxWithStatus = match self.client.get_transaction(tx_hash.clone())? {
+            Some(tx) => convert(tx)?,
+            None => convert(fetched(
+                self.client.fetch_transaction(tx_hash.clone())?,
+                &what,
+            )?)?,
+        };
+        let view = tx
+            .transaction
+            .ok_or_else(|| Error::Rpc(format!("{} not found", what)))?;
+        Ok((view, tx.tx_status.block_hash))
+    }
+}
+
+/// `TransactionWithStatus` of the light client, whose fields are private
+/// in ckb-sdk.
+#[derive(Deserialize)]
+struct LightTxWithStatus {
+    transaction: Option<json::TransactionView>,
+    tx_status: json::TxStatus,
+}
+
+/// `TxWithCells` of the light client, whose fields are private in ckb-sdk.
+#[derive(Deserialize)]
+struct LightTx {
+    transaction: json::TransactionView,
+    block_number: json::BlockNumber,
+    cells: Vec<(CellType, json::Uint32)>,
+}
+
+fn convert<T, U>(value: T) -> Result<U, Error>
+where
+    T: serde::Serialize,
+    U: serde::de::DeserializeOwned,
+{
+    serde_json::to_value(value)
+        .and_then(serde_json::from_value)
+        .map_err(|err| Error::Rpc(format!("unexpected light client answer: {}", err)))
+}
+
+impl ChainBackend for LightClient {
+    fn watch(&self, lock_script: &Script, from_block: u64) -> Result<(), Error> {
+        LightClient::watch(self, lock_script, from_block)?;
+        Ok(())
+    }
+
+    /// The verified tip header.
+    fn tip_header(&self) -> Result<HeaderView, Error> {
+        Ok(self.client.get_tip_header()?.into())
+    }
+
+    fn header(&self, block_hash: &H256) -> Result<HeaderView, Error> {
+        let header = match self.client.get_header(block_hash.clone())? {
+            Some(header) => header,
+            // not downloaded with the matched blocks, ask the peers for it
+            None => fetched(
+                self.client.fetch_header(block_hash.clone())?,
+                &format!("block {:#x}", block_hash),
+            )?,
+        };
+        Ok(header.into())
+    }
+
+    fn header_by_number(&self, number: u64) -> Result<HeaderView, Error> {
+        Err(Error::Rpc(format!(
+            "the light client can't look block #{} up by number, only by hash",
+            number
+        )))
+    }
+
+    fn transaction_block(&self, tx_hash: &H256) -> Result<H256, Error> {
+        self.transaction_with_status(tx_hash)?
+            .1
+            .ok_or_else(|| Error::Rpc(format!("transaction {:#x} is not committed", tx_hash)))
+    }
+
+    fn genesis(&self) -> Result<BlockView, Error> {
+        Ok(self.client.get_genesis_block()?.into())
+    }
+
+    /// The block the lock is synced to.
+    fn indexed_block(&self, lock_script: &Script) -> Result<u64, Error> {
+        self.synced_block(lock_script)?.ok_or_else(|| {
+            Error::Rpc(format!(
+                "the light client doesn't watch the lock {}, see `LightClient::watch`",
+                lock_script
+            ))
+        })
+    }
+
+    fn cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Result<Pagination<Cell>, Error> {
+        let script: Script = search_key.script.clone().into();
+        if let ScriptType::Lock = search_key.script_type {
+            self.indexed_block(&script)?;
+        } else if self.synced_script(&script, &ScriptType::Type)?.is_none() {
+            return Err(Error::Rpc(format!(
+                "the light client doesn't watch the type {}, see `LightClient::watch_type`",
+                script
+            )));
+        }
+        Ok(self
+            .client
+            .get_cells(search_key, Order::Asc, limit.into(), cursor)?)
+    }
+
+    fn commit_block(&self, cell: &LiveCell) -> Result<BlockInfo, Error> {
+        let tx_hash: H256 = cell.out_point.tx_hash().unpack();
+        Ok(BlockInfo::from(&self.commit_header(&tx_hash)?))
+    }
+
+    /// Up to `indexed_block`, which checks the light client watches the
+    /// lock.
+    fn transactions(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Result<(Vec<LockTx>, JsonBytes), Error> {
+        let page = self
+            .client
+            .get_transactions(search_key, Order::Asc, limit.into(), cursor)?;
+        let txs = page
+            .objects
+            .into_iter()
+            .map(|tx| match tx {
+                Tx::Grouped(tx) => {
+                    let tx: LightTx = convert(tx)?;
+                    let view = packed::Transaction::from(tx.transaction.inner).into_view();
+                    Ok(LockTx {
+                        tx_hash: view.hash().unpack(),
+                        block_number: tx.block_number.value(),
+                        inputs: count_inputs(&tx.cells),
+                        tx: Some(view),
+                    })
+                }
+                Tx::Ungrouped(_) => Err(Error::Rpc("expected transactions grouped".to_string())),
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok((txs, page.last_cursor))
+    }
+
+    /// The transactions of the watched locks are stored by the client, the
+    /// others are fetched from its peers.
+    fn transaction(&self, tx_hash: &H256) -> Result<TransactionView, Error> {
+        let tx = self.transaction_with_status(tx_hash)?.0;
+        Ok(packed::Transaction::from(tx.inner).into_view())
+    }
+}
+
+/// `FetchStatus::Fetched` data, an error until then.
+fn fetched<T>(status: FetchStatus<T>, what: &str) -> Result<T, Error> {
+    match status {
+        FetchStatus::Fetched { data } => Ok(data),
+        FetchStatus::NotFound => Err(Error::Rpc(format!("{} not found", what))),
+        _ => Err(Error::Rpc(format!(
+            "{} is being fetched by the light client, retry",
+            what
+        ))),
+    }
+}
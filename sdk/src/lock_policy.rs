@@ -0,0 +1,296 @@
+//! Readable policies of the lock, compiled into its configs and decompiled
+//! back, so that what the cosigners review and the bytes on chain stay in
+//! sync:
+//!
+//! ```text
+//! 2 of [alice, bob, carol] including [alice]; 1 of [dave]
+//! after 90 days allow 2 of [alice, bob, carol]; after 90 days allow 1 of [dave]
+//! ```
+//!
+//! A clause is a quorum, `<threshold> of [<members>]`, optionally requiring
+//! the first members with `including [<members>]`, and optionally timelocked
+//! with `after <since> allow`, the since as in `SinceSpec`, `90d` standing
+//! for `90 days`. A member is a name looked up in the members given, or a
+//! pubkey hash in hex.
+//!
+//! A single clause compiles into a `MultisigConfig`, several into a
+//! `ConfigTree` of one config per clause, in order. The lock args hold one
+//! since for every config of a tree: the clauses of a policy must have the
+//! same `after`, a timelock on one clause alone is refused.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use ckb_types::bytes::Bytes;
+
+use crate::{
+    config::MultisigConfig,
+    config_tree::ConfigTree,
+    constants::BLAKE160_SIZE,
+    error::Error,
+    since::{format_since, parse_since},
+};
+
+/// A quorum of the policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Clause {
+    /// The raw since of `after`.
+    pub since: Option<u64>,
+    pub threshold: u8,
+    /// Names or `0x` pubkey hashes, in the multisig script order.
+    pub members: Vec<String>,
+    /// How many of the first members must sign.
+    pub require_first_n: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockPolicy {
+    pub clauses: Vec<Clause>,
+}
+
+/// The configs of a policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Compiled {
+    Config(MultisigConfig),
+    Tree(ConfigTree),
+}
+
+impl Compiled {
+    pub fn lock_args(&self) -> Bytes {
+        self.configs()[0].lock_args()
+    }
+
+    /// The config of every clause, in order, with its proof in a tree: the
+    /// configs the cosigners of each clause sign with.
+    pub fn configs(&self) -> Vec<MultisigConfig> {
+        match self {
+            Compiled::Config(config) => vec![config.clone()],
+            Compiled::Tree(tree) => (0..tree.configs().len())
+                .filter_map(|index| tree.config(index))
+                .collect(),
+        }
+    }
+}
+
+impl LockPolicy {
+    /// The configs of the policy, `members` naming the pubkey hashes.
+    pub fn compile(
+        &self,
+        members: &BTreeMap<String, [u8; BLAKE160_SIZE]>,
+    ) -> Result<Compiled, Error> {
+        let since = match self.clauses.first() {
+            Some(clause) => clause.since,
+            None => return Err(invalid("without any clause".to_string())),
+        };
+        let configs = self
+            .clauses
+            .iter()
+            .enumerate()
+            .map(|(i, clause)| {
+                if clause.since != since {
+                    return Err(invalid(format!(
+                        "clause #{} has another `after` than the first, the lock args hold a \
+                         single since for every clause",
+                        i
+                    )));
+                }
+                let hashes = clause
+                    .members
+                    .iter()
+                    .map(|member| resolve(member, members))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(
+                    MultisigConfig::new(hashes, clause.require_first_n, clause.threshold)?
+                        .with_since(since),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if configs.len() == 1 {
+            Ok(Compiled::Config(configs[0].clone()))
+        } else {
+            Ok(Compiled::Tree(ConfigTree::new(configs)?))
+        }
+    }
+
+    /// The policy of `compiled`, the members named after `members` when
+    /// listed there. The configs must hold nothing but the quorums and the
+    /// since, e.g. no fee key or flags.
+    pub fn decompile(
+        compiled: &Compiled,
+        members: &BTreeMap<String, [u8; BLAKE160_SIZE]>,
+    ) -> Result<Self, Error> {
+        let configs = match compiled {
+            Compiled::Config(config) => vec![config.clone()],
+            Compiled::Tree(tree) => tree.configs().to_vec(),
+        };
+        let clauses = configs
+            .iter()
+            .map(|config| {
+                let plain = MultisigConfig::new(
+                    config.pubkey_hashes().to_vec(),
+                    config.require_first_n(),
+                    config.threshold(),
+                )?
+                .with_since(config.since());
+                if config.clone().with_proof(None) != plain {
+                    return Err(invalid(
+                        "the config holds more than a quorum and a since".to_string(),
+                    ));
+                }
+                let name = |hash: &[u8; BLAKE160_SIZE]| {
+                    members
+                        .iter()
+                        .find(|(_, member)| *member == hash)
+                        .map(|(name, _)| name.clone())
+                        .unwrap_or_else(|| format!("0x{}", hex::encode(hash)))
+                };
+                Ok(Clause {
+                    since: config.since(),
+                    threshold: config.threshold(),
+                    members: config.pubkey_hashes().iter().map(name).collect(),
+                    require_first_n: config.require_first_n(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(LockPolicy { clauses })
+    }
+}
+
+impl FromStr for LockPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let clauses = s
+            .split(';')
+            .map(parse_clause)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(LockPolicy { clauses })
+    }
+}
+
+impl fmt::Display for LockPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, clause) in self.clauses.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", clause)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Clause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(since) = self.since {
+            write!(f, "{} allow ", format_since(since))?;
+        }
+        write!(f, "{} of [{}]", self.threshold, self.members.join(", "))?;
+        if self.require_first_n > 0 {
+            let first = &self.members[..usize::from(self.require_first_n)];
+            write!(f, " including [{}]", first.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_clause(clause: &str) -> Result<Clause, Error> {
+    let failed = |reason: &str| invalid(format!("clause `{}`: {}", clause.trim(), reason));
+    let mut rest = clause.trim();
+    let since = match rest.strip_prefix("after ") {
+        Some(after) => {
+            let (spec, quorum) = after
+                .split_once(" allow ")
+                .ok_or_else(|| failed("expected `after <since> allow <quorum>`"))?;
+            rest = quorum.trim();
+            Some(parse_since(&format!(
+                "after {}",
+                expand_duration(spec.trim())
+            ))?)
+        }
+        None => None,
+    };
+    let (threshold, rest) = rest
+        .split_once(" of ")
+        .ok_or_else(|| failed("expected `<threshold> of [<members>]`"))?;
+    let threshold = threshold
+        .trim()
+        .parse::<u8>()
+        .map_err(|_| failed("invalid threshold"))?;
+    let (members, rest) = parse_list(rest).ok_or_else(|| failed("invalid members"))?;
+    let rest = rest.trim();
+    let require_first_n = if rest.is_empty() {
+        0
+    } else {
+        let including = rest
+            .strip_prefix("including")
+            .and_then(parse_list)
+            .filter(|(_, rest)| rest.trim().is_empty())
+            .map(|(first, _)| first)
+            .ok_or_else(|| failed("expected `including [<members>]` after the members"))?;
+        if !members.starts_with(&including) {
+            return Err(failed("the members included must be the first ones"));
+        }
+        including.len() as u8
+    };
+    Ok(Clause {
+        since,
+        threshold,
+        members,
+        require_first_n,
+    })
+}
+
+/// `[a, b, c]` at the start of `s`, and what follows it.
+fn parse_list(s: &str) -> Option<(Vec<String>, &str)> {
+    let (list, rest) = s.trim_start().strip_prefix('[')?.split_once(']')?;
+    let items: Vec<_> = list
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .collect();
+    if items
+        .iter()
+        .any(|item| item.is_empty() || item.contains(char::is_whitespace))
+    {
+        return None;
+    }
+    Some((items, rest))
+}
+
+/// `90d` as `90 days`, and likewise for `w`, `h`, `m` and `s`.
+fn expand_duration(spec: &str) -> String {
+    let units = [
+        ('w', "weeks"),
+        ('d', "days"),
+        ('h', "hours"),
+        ('m', "minutes"),
+        ('s', "seconds"),
+    ];
+    for (suffix, unit) in units {
+        if let Some(count) = spec.strip_suffix(suffix) {
+            if !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()) {
+                return format!("{} {}", count, unit);
+            }
+        }
+    }
+    spec.to_string()
+}
+
+fn resolve(
+    member: &str,
+    members: &BTreeMap<String, [u8; BLAKE160_SIZE]>,
+) -> Result<[u8; BLAKE160_SIZE], Error> {
+    if let Some(hash) = members.get(member) {
+        return Ok(*hash);
+    }
+    let hex = member
+        .strip_prefix("0x")
+        .ok_or_else(|| invalid(format!("unknown member `{}`", member)))?;
+    let mut hash = [0u8; BLAKE160_SIZE];
+    hex::decode_to_slice(hex, &mut hash)
+        .map_err(|_| invalid(format!("invalid pubkey hash `{}`", member)))?;
+    Ok(hash)
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidConfig(format!("policy {}", message))
+}
@@ -0,0 +1,323 @@
+//! Key rotation: move the live cells of an old config under a new lock.
+//!
+//! Plain cells are merged into one output per transaction, which also pays
+//! the fee. Cells with a type script or data keep their capacity, type and
+//! data, only their lock changes. Cells still locked by the since of the old
+//! lock args are left for a later run, and Nervos DAO cells are skipped since
+//! the DAO script doesn't let them change lock outside of the withdraw phase
+//! 1, prepare them with `dao::MultisigDao` and the new lock instead.
+
+use ckb_sdk::{constants::DAO_TYPE_HASH, traits::LiveCell, types::ScriptGroup, CkbRpcClient};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType, TransactionBuilder},
+    packed::{CellDep, CellInput, CellOutput, Script},
+    prelude::*,
+    H256,
+};
+
+use crate::{
+    config::MultisigConfig,
+    constants::BLAKE160_SIZE,
+    error::Error,
+    fee::{witness_size, FeeEstimator},
+    request::SigningRequest,
+    scanner::{CellSet, MultisigCell},
+    since::apply_since,
+    sweep::DEFAULT_MAX_TX_SIZE,
+    unlock::MultisigScriptSigner,
+};
+
+const INPUT_SIZE: u64 = 44;
+
+/// The migration transactions and the cells they leave behind.
+#[derive(Clone, Debug)]
+pub struct MigrationPlan {
+    pub requests: Vec<SigningRequest>,
+    /// Locked by the since of the old lock args, to migrate once mature.
+    pub pending: Vec<MultisigCell>,
+    /// Nervos DAO cells.
+    pub skipped: Vec<MultisigCell>,
+}
+
+impl MigrationPlan {
+    /// Add the signatures of the local signers to every request.
+    pub fn sign(&mut self, signer: &MultisigScriptSigner) -> Result<(), Error> {
+        for request in &mut self.requests {
+            request.sign(signer)?;
+        }
+        Ok(())
+    }
+
+    /// Merge the signatures of a copy of the plan signed separately, request
+    /// by request, returning the identities added.
+    pub fn combine(&mut self, other: &MigrationPlan) -> Result<Vec<[u8; BLAKE160_SIZE]>, Error> {
+        if other.requests.len() != self.requests.len() {
+            return Err(Error::InvalidRequest(format!(
+                "a plan of {} transactions can't be merged into one of {}",
+                other.requests.len(),
+                self.requests.len()
+            )));
+        }
+        let mut added = Vec::new();
+        for (request, copy) in self.requests.iter_mut().zip(&other.requests) {
+            for identity in request.combine(copy)? {
+                if !added.contains(&identity) {
+                    added.push(identity);
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    pub fn is_complete(&self) -> Result<bool, Error> {
+        for request in &self.requests {
+            if !request.is_complete()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Send every request, they must all be completely signed.
+    pub fn send(&self, client: &CkbRpcClient) -> Result<Vec<H256>, Error> {
+        if !self.is_complete()? {
+            return Err(Error::Verification(
+                "the migration is not completely signed".to_string(),
+            ));
+        }
+        self.requests
+            .iter()
+            .map(|request| request.send(client))
+            .collect()
+    }
+}
+
+pub struct Migration {
+    old: MultisigConfig,
+    old_lock: Script,
+    new_lock: Script,
+    cell_deps: Vec<CellDep>,
+    fee_rate: u64,
+    max_tx_size: u64,
+}
+
+impl Migration {
+    /// `cell_deps` are the cell deps needed to run the old lock and the type
+    /// scripts of the migrated cells, `fee_rate` is in shannons per 1000
+    /// bytes.
+    pub fn new(
+        old: MultisigConfig,
+        old_lock: Script,
+        new_lock: Script,
+        cell_deps: Vec<CellDep>,
+        fee_rate: u64,
+    ) -> Self {
+        Migration {
+            old,
+            old_lock,
+            new_lock,
+            cell_deps,
+            fee_rate,
+            max_tx_size: DEFAULT_MAX_TX_SIZE,
+        }
+    }
+
+    /// Size limit of each transaction once signed.
+    pub fn max_tx_size(mut self, max_tx_size: u64) -> Self {
+        self.max_tx_size = max_tx_size;
+        self
+    }
+
+    /// Plan the migration of `cells`, the cell set of the old config.
+    pub fn plan(&self, cells: &CellSet) -> Result<MigrationPlan, Error> {
+        let mut plain = Vec::new();
+        let mut typed = Vec::new();
+        let mut pending = Vec::new();
+        let mut skipped = Vec::new();
+        for cell in &cells.cells {
+            if cell.cell.output.lock() != self.old_lock {
+                return Err(Error::InvalidParameter(format!(
+                    "cell {} is not guarded by the old config",
+                    cell.cell.out_point
+                )));
+            }
+            if !cell.maturity.is_mature() {
+                pending.push(cell.clone());
+            } else if is_dao(&cell.cell) {
+                skipped.push(cell.clone());
+            } else if cell.is_plain() {
+                plain.push(cell.cell.clone());
+            } else {
+                typed.push(cell.cell.clone());
+            }
+        }
+        let requests = self
+            .chunks(typed, plain)?
+            .iter()
+            .map(|chunk| self.build(chunk))
+            .collect::<Result<_, _>>()?;
+        Ok(MigrationPlan {
+            requests,
+            pending,
+            skipped,
+        })
+    }
+
+    /// Split the cells by transaction, typed cells first, every transaction
+    /// gets at least one plain cell to pay its fee.
+    fn chunks(
+        &self,
+        typed: Vec<LiveCell>,
+        plain: Vec<LiveCell>,
+    ) -> Result<Vec<Vec<Chunked>>, Error> {
+        let base = self.tx(&[]).data().as_reader().serialized_size_in_block() as u64
+            + witness_size(&self.old) as u64;
+        // leave room for a plain cell moved in later
+        let budget = self.max_tx_size.saturating_sub(INPUT_SIZE);
+        let mut chunks: Vec<Vec<Chunked>> = Vec::new();
+        let mut current: Vec<Chunked> = Vec::new();
+        let mut size = base;
+        let cells = typed
+            .into_iter()
+            .map(Chunked::Typed)
+            .chain(plain.into_iter().map(Chunked::Plain));
+        for cell in cells {
+            let extra = cell.size();
+            if base + extra > budget {
+                return Err(Error::InvalidParameter(format!(
+                    "cell {} doesn't fit in a transaction of {} bytes",
+                    cell.cell().out_point,
+                    self.max_tx_size
+                )));
+            }
+            if size + extra > budget {
+                chunks.push(std::mem::take(&mut current));
+                size = base;
+            }
+            size += extra;
+            current.push(cell);
+        }
+        if !current.is_empty() {
+            chunks.push(current);
+        }
+        for i in 0..chunks.len() {
+            if chunks[i].iter().any(Chunked::is_plain) {
+                continue;
+            }
+            let donor = chunks
+                .iter()
+                .rposition(|chunk| chunk.iter().filter(|cell| cell.is_plain()).count() > 1)
+                .ok_or_else(|| {
+                    Error::InsufficientCapacity(
+                        "not enough plain cells to pay the fee of moving the typed cells"
+                            .to_string(),
+                    )
+                })?;
+            let cell = chunks[donor].pop().expect("plain cells are last");
+            chunks[i].push(cell);
+        }
+        Ok(chunks)
+    }
+
+    fn tx(&self, cells: &[Chunked]) -> ckb_types::core::TransactionView {
+        let mut builder = TransactionBuilder::default().cell_deps(self.cell_deps.clone());
+        for cell in cells {
+            builder = builder.input(CellInput::new(cell.cell().out_point.clone(), 0));
+            if let Chunked::Typed(cell) = cell {
+                builder = builder
+                    .output(
+                        cell.output
+                            .clone()
+                            .as_builder()
+                            .lock(self.new_lock.clone())
+                            .build(),
+                    )
+                    .output_data(cell.output_data.pack());
+            }
+        }
+        builder
+            .output(
+                CellOutput::new_builder()
+                    .lock(self.new_lock.clone())
+                    .build(),
+            )
+            .output_data(Bytes::new().pack())
+            .build()
+    }
+
+    fn build(&self, cells: &[Chunked]) -> Result<SigningRequest, Error> {
+        let mut script_group = ScriptGroup::from_lock_script(&self.old_lock);
+        script_group.input_indices = (0..cells.len()).collect();
+        let tx = apply_since(&self.tx(cells), &script_group, self.old.since())?;
+        let fee = FeeEstimator::new(self.fee_rate)
+            .estimate(&tx, &[(script_group.clone(), self.old.clone())], None)?
+            .fee;
+        let total: u64 = cells
+            .iter()
+            .filter(|cell| cell.is_plain())
+            .map(|cell| Unpack::<u64>::unpack(&cell.cell().output.capacity()))
+            .sum();
+        let index = tx.outputs().len() - 1;
+        let output = tx.output(index).expect("merged output");
+        let occupied = output
+            .occupied_capacity(Capacity::zero())
+            .expect("occupied capacity")
+            .as_u64();
+        if total < occupied + fee {
+            return Err(Error::InsufficientCapacity(format!(
+                "{} shannons of plain cells can't pay {} for the output and {} fee",
+                total, occupied, fee
+            )));
+        }
+        let mut outputs: Vec<CellOutput> = tx.outputs().into_iter().collect();
+        outputs[index] = output.as_builder().capacity(total - fee).build();
+        Ok(SigningRequest {
+            config: self.old.clone(),
+            tx: tx.as_advanced_builder().set_outputs(outputs).build(),
+            script_group,
+            fee,
+            genesis_hash: None,
+        })
+    }
+}
+
+enum Chunked {
+    Typed(LiveCell),
+    Plain(LiveCell),
+}
+
+impl Chunked {
+    fn cell(&self) -> &LiveCell {
+        match self {
+            Chunked::Typed(cell) | Chunked::Plain(cell) => cell,
+        }
+    }
+
+    fn is_plain(&self) -> bool {
+        matches!(self, Chunked::Plain(_))
+    }
+
+    /// Serialized size added to the transaction.
+    fn size(&self) -> u64 {
+        match self {
+            Chunked::Plain(_) => INPUT_SIZE,
+            // the output and its data, each with its offset, and the data
+            // bytes header
+            Chunked::Typed(cell) => {
+                INPUT_SIZE
+                    + 4
+                    + cell.output.as_slice().len() as u64
+                    + 8
+                    + cell.output_data.len() as u64
+            }
+        }
+    }
+}
+
+fn is_dao(cell: &LiveCell) -> bool {
+    cell.output.type_().to_opt().is_some_and(|script| {
+        script.code_hash() == DAO_TYPE_HASH.pack()
+            && script.hash_type() == ScriptHashType::Type.into()
+    })
+}
@@ -0,0 +1,226 @@
+//! Transactions funded by two parties: the plain cells of a sighash fee
+//! payer, who pays the fee and takes the change, and the treasury cells of a
+//! config.
+//!
+//! `MixedFunding::balancer` is a ckb-sdk `CapacityBalancer` taking the cells
+//! of the fee payer first, then the treasury ones once the fee payer runs
+//! out. The first input each lock adds gets the placeholder witness of that
+//! lock, a 65 bytes sighash signature or the complete multisig lock field, so
+//! the fee holds once both groups are signed.
+//!
+//! Each party signs the message of its own script group, see
+//! `MixedFunding::digests`: the fee key the `secp256k1_blake160_sighash_all`
+//! message, the cosigners the multisig one. Neither covers the witnesses of
+//! the other group, so the parties sign in any order, but both cover the
+//! transaction hash: the transaction must be complete, outputs and change
+//! included, before anyone signs.
+
+use ckb_sdk::{
+    constants::SIGHASH_TYPE_HASH,
+    traits::TransactionDependencyProvider,
+    tx_builder::{gen_script_groups, CapacityBalancer, CapacityProvider, SinceSource},
+    types::ScriptGroup,
+    unlock::fill_witness_lock,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{ScriptHashType, TransactionView},
+    packed::{Script, WitnessArgs},
+    prelude::*,
+};
+
+use crate::{
+    config::MultisigConfig,
+    constants::{DIGEST_SIZE, SIGNATURE_SIZE},
+    digest::{compute_fee_sighash, compute_sighash},
+    error::Error,
+    request::SigningRequest,
+};
+
+/// Who signs a script group of the transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Party {
+    /// The sighash lock of the fee cells.
+    FeePayer,
+    /// The cosigners of the config.
+    Treasury,
+}
+
+/// The message a party signs for one of its script groups.
+#[derive(Clone, Debug)]
+pub struct GroupDigest {
+    pub party: Party,
+    pub script_group: ScriptGroup,
+    pub digest: [u8; DIGEST_SIZE],
+}
+
+pub struct MixedFunding {
+    config: MultisigConfig,
+    treasury: Script,
+    fee_payer: Script,
+}
+
+impl MixedFunding {
+    /// `treasury` is the lock of the config cells, `fee_payer` a
+    /// `secp256k1_blake160_sighash_all` lock.
+    pub fn new(config: MultisigConfig, treasury: Script, fee_payer: Script) -> Result<Self, Error> {
+        if fee_payer.code_hash() != SIGHASH_TYPE_HASH.pack()
+            || fee_payer.hash_type() != ScriptHashType::Type.into()
+        {
+            return Err(Error::InvalidParameter(
+                "the fee payer is not a sighash lock".to_string(),
+            ));
+        }
+        if config.spend_all() {
+            return Err(Error::InvalidParameter(
+                "the cells of a config with spend all can't be balanced a part at a time"
+                    .to_string(),
+            ));
+        }
+        Ok(MixedFunding {
+            config,
+            treasury,
+            fee_payer,
+        })
+    }
+
+    pub fn config(&self) -> &MultisigConfig {
+        &self.config
+    }
+
+    pub fn treasury(&self) -> &Script {
+        &self.treasury
+    }
+
+    pub fn fee_payer(&self) -> &Script {
+        &self.fee_payer
+    }
+
+    /// Balance with the fee payer cells, then the treasury ones, the change
+    /// going to the fee payer. `fee_rate` in shannons per 1000 bytes.
+    pub fn balancer(&self, fee_rate: u64) -> CapacityBalancer {
+        let provider = CapacityProvider::new(vec![
+            (
+                self.fee_payer.clone(),
+                self.placeholder_witness(Party::FeePayer),
+                SinceSource::default(),
+            ),
+            (
+                self.treasury.clone(),
+                self.placeholder_witness(Party::Treasury),
+                SinceSource::Value(self.config.since().unwrap_or(0)),
+            ),
+        ]);
+        let mut balancer = CapacityBalancer::new_with_provider(fee_rate, provider);
+        balancer.change_lock_script = Some(self.fee_payer.clone());
+        balancer
+    }
+
+    /// The witness of the first input of a group of `party` before signing.
+    pub fn placeholder_witness(&self, party: Party) -> WitnessArgs {
+        WitnessArgs::new_builder()
+            .lock(Some(self.placeholder_lock(party)).pack())
+            .build()
+    }
+
+    fn placeholder_lock(&self, party: Party) -> Bytes {
+        match party {
+            Party::FeePayer => Bytes::from(vec![0u8; SIGNATURE_SIZE]),
+            Party::Treasury => self.config.placeholder_lock(),
+        }
+    }
+
+    /// The party signing for `script_group`, `None` for a group of another
+    /// lock.
+    pub fn party(&self, script_group: &ScriptGroup) -> Option<Party> {
+        if script_group.script == self.fee_payer {
+            Some(Party::FeePayer)
+        } else if script_group.script == self.treasury {
+            Some(Party::Treasury)
+        } else {
+            None
+        }
+    }
+
+    /// The lock groups of the fee payer and of the treasury in `tx`, in that
+    /// order, the groups of other locks are left out.
+    pub fn script_groups(
+        &self,
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<Vec<ScriptGroup>, Error> {
+        let groups = gen_script_groups(tx, tx_dep_provider)
+            .map_err(|err| Error::TransactionDependency(err.to_string()))?;
+        Ok([&self.fee_payer, &self.treasury]
+            .iter()
+            .filter_map(|lock| groups.lock_groups.get(&lock.calc_script_hash()))
+            .cloned()
+            .collect())
+    }
+
+    /// Put the placeholder lock field into the groups which have none yet,
+    /// e.g. for the inputs added before balancing.
+    pub fn fill_placeholders(
+        &self,
+        tx: &TransactionView,
+        script_groups: &[ScriptGroup],
+    ) -> Result<TransactionView, Error> {
+        script_groups.iter().try_fold(tx.clone(), |tx, group| {
+            let party = self.party(group).ok_or_else(|| foreign_group(group))?;
+            fill_witness_lock(&tx, group, self.placeholder_lock(party))
+                .map_err(|err| Error::InvalidWitness(err.to_string()))
+        })
+    }
+
+    /// The message of each group, as its party signs it. The fee payer
+    /// message is the one of `compute_fee_sighash`, which zeroes a 65 bytes
+    /// lock field just like the sighash lock.
+    pub fn digests(
+        &self,
+        tx: &TransactionView,
+        script_groups: &[ScriptGroup],
+    ) -> Result<Vec<GroupDigest>, Error> {
+        script_groups
+            .iter()
+            .map(|group| {
+                let party = self.party(group).ok_or_else(|| foreign_group(group))?;
+                let digest = match party {
+                    Party::FeePayer => compute_fee_sighash(tx, &group.input_indices)?,
+                    Party::Treasury => compute_sighash(tx, &group.input_indices, &self.config)?,
+                };
+                Ok(GroupDigest {
+                    party,
+                    script_group: group.clone(),
+                    digest,
+                })
+            })
+            .collect()
+    }
+
+    /// The signing request of the treasury group, passed between the
+    /// cosigners. `fee` is the fee of the whole transaction, in shannons.
+    pub fn request(
+        &self,
+        tx: &TransactionView,
+        script_group: &ScriptGroup,
+        fee: u64,
+    ) -> Result<SigningRequest, Error> {
+        if self.party(script_group) != Some(Party::Treasury) {
+            return Err(foreign_group(script_group));
+        }
+        Ok(SigningRequest {
+            config: self.config.clone(),
+            tx: tx.clone(),
+            script_group: script_group.clone(),
+            fee,
+            genesis_hash: None,
+        })
+    }
+}
+
+fn foreign_group(script_group: &ScriptGroup) -> Error {
+    Error::InvalidParameter(format!(
+        "the group of {:#x} is neither the fee payer nor the treasury",
+        script_group.script.calc_script_hash()
+    ))
+}
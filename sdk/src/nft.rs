@@ -0,0 +1,319 @@
+//! Transfers of NFT cells held by a config, e.g. Spores, so a DAO custodies
+//! its NFTs under the same quorum as its CKB.
+//!
+//! An NFT here is a cell of a type script whose args are the token id, the
+//! layout of Spore, of Spore clusters and of the m-NFT cells alike: a
+//! transfer moves the cell to a new lock and keeps its type script and data
+//! byte for byte, which their type scripts check. The type scripts run in
+//! the transfer, so the cell deps of each `NftStandard` involved are added.
+//!
+//! The capacity of a token moves with it, topped up when the lock of the
+//! recipient is larger than the one of the config. The top ups and the fee
+//! are paid from plain cells of the config, the rest going back to the
+//! change lock, or to the fee when it's too small for a cell.
+//!
+//! A Spore of a cluster names the cluster in its data. The cluster cell is
+//! added as a cell dep when its out point is given with
+//! `NftTransfer::cluster`, for the Spore deployments which look it up on
+//! transfer.
+
+use std::{collections::BTreeMap, convert::TryInto};
+
+use ckb_sdk::{traits::LiveCell, types::ScriptGroup};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, DepType, ScriptHashType, TransactionBuilder, TransactionView},
+    packed::{CellDep, CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+
+use crate::{
+    config::MultisigConfig, error::Error, fee::FeeEstimator, request::SigningRequest,
+    since::apply_since,
+};
+
+const CLUSTER_ID_SIZE: usize = 32;
+
+/// The type script code of the tokens of a standard, and the cell deps
+/// running it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NftStandard {
+    /// E.g. `spore`.
+    pub name: String,
+    pub code_hash: H256,
+    pub hash_type: ScriptHashType,
+    pub cell_deps: Vec<CellDep>,
+}
+
+impl NftStandard {
+    /// Whether `type_script` is the one of a token of the standard.
+    pub fn is_token(&self, type_script: &Script) -> bool {
+        type_script.code_hash() == self.code_hash.pack()
+            && type_script.hash_type() == self.hash_type.into()
+    }
+}
+
+/// A token to send.
+#[derive(Clone, Debug)]
+pub struct NftTransferItem {
+    /// The cell of the token, guarded by the config.
+    pub cell: LiveCell,
+    pub to: Script,
+}
+
+pub struct NftTransfer {
+    config: MultisigConfig,
+    lock_script: Script,
+    cell_deps: Vec<CellDep>,
+    fee_rate: u64,
+    change: Option<Script>,
+    standards: Vec<NftStandard>,
+    clusters: BTreeMap<[u8; CLUSTER_ID_SIZE], OutPoint>,
+}
+
+impl NftTransfer {
+    /// `lock_script` is the lock of the config cells, `cell_deps` are the
+    /// cell deps needed to run it and `fee_rate` is in shannons per 1000
+    /// bytes.
+    pub fn new(
+        config: MultisigConfig,
+        lock_script: Script,
+        cell_deps: Vec<CellDep>,
+        fee_rate: u64,
+    ) -> Self {
+        NftTransfer {
+            config,
+            lock_script,
+            cell_deps,
+            fee_rate,
+            change: None,
+            standards: Vec::new(),
+            clusters: BTreeMap::new(),
+        }
+    }
+
+    /// Send the change to another lock, the config lock by default.
+    pub fn change(mut self, change: Script) -> Self {
+        self.change = Some(change);
+        self
+    }
+
+    /// Accept the tokens of `standard`, can be called once per standard.
+    pub fn standard(mut self, standard: NftStandard) -> Self {
+        self.standards.push(standard);
+        self
+    }
+
+    /// The cell of the Spore cluster `cluster_id`, added as a cell dep when
+    /// a Spore of the cluster is transferred.
+    pub fn cluster(mut self, cluster_id: [u8; CLUSTER_ID_SIZE], cell: OutPoint) -> Self {
+        self.clusters.insert(cluster_id, cell);
+        self
+    }
+
+    /// Build the transaction sending every token, paying the top ups and
+    /// the fee from `cells`, plain cells of the config, e.g. those of
+    /// `CellSet::spendable` without a type script, taken in order.
+    pub fn build(
+        &self,
+        items: &[NftTransferItem],
+        cells: &[LiveCell],
+    ) -> Result<SigningRequest, Error> {
+        if items.is_empty() {
+            return Err(Error::InvalidParameter("no token to transfer".to_string()));
+        }
+        let mut cell_deps = self.cell_deps.clone();
+        let mut outputs = Vec::new();
+        for item in items {
+            let (output, deps) = self.token_output(item)?;
+            for dep in deps {
+                if !cell_deps.contains(&dep) {
+                    cell_deps.push(dep);
+                }
+            }
+            outputs.push(output);
+        }
+        if let Some(cell) = cells
+            .iter()
+            .find(|cell| cell.output.lock() != self.lock_script || cell.output.type_().is_some())
+        {
+            return Err(Error::InvalidParameter(format!(
+                "cell {} is not a plain cell of the config",
+                cell.out_point
+            )));
+        }
+
+        let paid: u64 = outputs.iter().map(capacity).sum();
+        let change_output = CellOutput::new_builder()
+            .lock(
+                self.change
+                    .clone()
+                    .unwrap_or_else(|| self.lock_script.clone()),
+            )
+            .build();
+        let change_occupied = occupied(&change_output, 0);
+        let estimator = FeeEstimator::new(self.fee_rate);
+        let mut inputs: Vec<_> = items.iter().map(|item| item.cell.clone()).collect();
+        let mut next = cells.iter();
+        loop {
+            let total: u64 = inputs.iter().map(|cell| capacity(&cell.output)).sum();
+            let (tx, script_group) =
+                self.tx(&cell_deps, &inputs, &outputs, Some(&change_output))?;
+            let groups = [(script_group.clone(), self.config.clone())];
+            let fee = estimator.estimate(&tx, &groups, None)?.fee;
+            let change = total.checked_sub(paid + fee);
+            if let Some(change) = change.filter(|change| *change >= change_occupied) {
+                let mut tx_outputs: Vec<_> = tx.outputs().into_iter().collect();
+                let last = tx_outputs.len() - 1;
+                tx_outputs[last] = tx_outputs[last]
+                    .clone()
+                    .as_builder()
+                    .capacity(change)
+                    .build();
+                let tx = tx.as_advanced_builder().set_outputs(tx_outputs).build();
+                return Ok(self.request(tx, script_group, fee));
+            }
+            match next.next() {
+                Some(cell) => inputs.push(cell.clone()),
+                None => {
+                    // too little left for a change cell, it goes to the fee
+                    let (tx, script_group) = self.tx(&cell_deps, &inputs, &outputs, None)?;
+                    let fee = estimator
+                        .estimate(&tx, &[(script_group.clone(), self.config.clone())], None)?
+                        .fee;
+                    if total < paid + fee {
+                        return Err(Error::InsufficientCapacity(format!(
+                            "{} shannons can't pay the {} shannons of the tokens and {} fee",
+                            total, paid, fee
+                        )));
+                    }
+                    return Ok(self.request(tx, script_group, total - paid));
+                }
+            }
+        }
+    }
+
+    /// The output of the token sent to its recipient, and the cell deps its
+    /// type script needs.
+    fn token_output(&self, item: &NftTransferItem) -> Result<(CellOutput, Vec<CellDep>), Error> {
+        let cell = &item.cell;
+        if cell.output.lock() != self.lock_script {
+            return Err(Error::InvalidParameter(format!(
+                "token {} is not guarded by the config",
+                cell.out_point
+            )));
+        }
+        let type_script = cell.output.type_().to_opt().ok_or_else(|| {
+            Error::InvalidParameter(format!("cell {} has no type script", cell.out_point))
+        })?;
+        let standard = self
+            .standards
+            .iter()
+            .find(|standard| standard.is_token(&type_script))
+            .ok_or_else(|| {
+                Error::InvalidParameter(format!(
+                    "cell {} is not a token of a known standard, its type script code hash is {}",
+                    cell.out_point,
+                    type_script.code_hash()
+                ))
+            })?;
+        let mut deps = standard.cell_deps.clone();
+        if let Some(cluster_id) = spore_cluster_id(&cell.output_data) {
+            if let Some(out_point) = self.clusters.get(&cluster_id) {
+                deps.push(
+                    CellDep::new_builder()
+                        .out_point(out_point.clone())
+                        .dep_type(DepType::Code)
+                        .build(),
+                );
+            }
+        }
+        let output = cell
+            .output
+            .clone()
+            .as_builder()
+            .lock(item.to.clone())
+            .build();
+        let required = occupied(&output, cell.output_data.len());
+        let output = if capacity(&output) < required {
+            output.as_builder().capacity(required).build()
+        } else {
+            output
+        };
+        Ok((output, deps))
+    }
+
+    fn tx(
+        &self,
+        cell_deps: &[CellDep],
+        inputs: &[LiveCell],
+        outputs: &[CellOutput],
+        change: Option<&CellOutput>,
+    ) -> Result<(TransactionView, ScriptGroup), Error> {
+        let mut script_group = ScriptGroup::from_lock_script(&self.lock_script);
+        script_group.input_indices = (0..inputs.len()).collect();
+        let data = inputs
+            .iter()
+            .take(outputs.len())
+            .map(|cell| cell.output_data.pack());
+        let tx = TransactionBuilder::default()
+            .cell_deps(cell_deps.to_vec())
+            .inputs(
+                inputs
+                    .iter()
+                    .map(|cell| CellInput::new(cell.out_point.clone(), 0)),
+            )
+            .outputs(outputs.iter().chain(change).cloned())
+            .outputs_data(data.chain(change.map(|_| Bytes::new().pack())))
+            .build();
+        let tx = apply_since(&tx, &script_group, self.config.since())?;
+        Ok((tx, script_group))
+    }
+
+    fn request(&self, tx: TransactionView, script_group: ScriptGroup, fee: u64) -> SigningRequest {
+        SigningRequest {
+            config: self.config.clone(),
+            tx,
+            script_group,
+            fee,
+            genesis_hash: None,
+        }
+    }
+}
+
+/// The cluster id of the `SporeData` in `data`, a molecule table of the
+/// content type, the content and the optional cluster id. `None` for a
+/// Spore without cluster and for other data.
+pub fn spore_cluster_id(data: &[u8]) -> Option<[u8; CLUSTER_ID_SIZE]> {
+    let word = |at: usize| -> Option<usize> {
+        let bytes = data.get(at..at + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+    };
+    if word(0)? != data.len() {
+        return None;
+    }
+    let fields = (word(4)? / 4).checked_sub(1)?;
+    if fields < 3 {
+        return None;
+    }
+    let start = word(12)?;
+    let end = if fields > 3 { word(16)? } else { data.len() };
+    // `BytesOpt`: empty for none, else the bytes with their length
+    let field = data.get(start..end)?;
+    if field.is_empty() || word(start)? != field.len() - 4 {
+        return None;
+    }
+    field[4..].try_into().ok()
+}
+
+fn occupied(output: &CellOutput, data_size: usize) -> u64 {
+    output
+        .occupied_capacity(Capacity::bytes(data_size).expect("data size"))
+        .expect("occupied capacity")
+        .as_u64()
+}
+
+fn capacity(output: &CellOutput) -> u64 {
+    Unpack::<u64>::unpack(&output.capacity())
+}
@@ -0,0 +1,150 @@
+//! Nonce cells of `contracts/ckb-multisig-nonce`, for approvals signed off
+//! chain which must not be replayed.
+//!
+//! A nonce cell holds a little endian u64, is unique by its type id and is
+//! created with the nonce 0. Every transaction spending it recreates it with
+//! the nonce incremented by one: an approval naming the next nonce is valid
+//! for a single transaction, and bumping the nonce alone revokes the pending
+//! approvals.
+
+use std::convert::TryInto;
+
+use ckb_sdk::traits::{CellQueryOptions, LiveCell};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType, TransactionView},
+    packed::{CellInput, CellOutput, Script},
+    prelude::*,
+    H256,
+};
+
+use crate::{backend::ChainBackend, error::Error};
+
+pub const NONCE_SIZE: usize = 8;
+
+/// The nonce type script deployed at `code_hash`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NonceScript {
+    pub code_hash: H256,
+    pub hash_type: ScriptHashType,
+}
+
+impl NonceScript {
+    pub fn new(code_hash: H256, hash_type: ScriptHashType) -> Self {
+        NonceScript {
+            code_hash,
+            hash_type,
+        }
+    }
+
+    /// The type script of the nonce cell of `type_id`.
+    pub fn type_script(&self, type_id: &[u8; 32]) -> Script {
+        Script::new_builder()
+            .code_hash(self.code_hash.pack())
+            .hash_type(self.hash_type)
+            .args(Bytes::copy_from_slice(type_id).pack())
+            .build()
+    }
+
+    /// Add a new nonce cell of `lock` as the last output of `tx`, returning
+    /// its type script. The type id is derived from the first input, so `tx`
+    /// must already have its inputs.
+    pub fn create(
+        &self,
+        tx: &TransactionView,
+        lock: Script,
+        capacity: u64,
+    ) -> Result<(TransactionView, Script), Error> {
+        let first_input = tx.inputs().get(0).ok_or_else(|| {
+            Error::InvalidParameter("the transaction creating a nonce has no input".to_string())
+        })?;
+        let type_script = self.type_script(&type_id(&first_input, tx.outputs().len() as u64));
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(lock)
+            .type_(Some(type_script.clone()).pack())
+            .build();
+        check_capacity(&output)?;
+        let tx = tx
+            .as_advanced_builder()
+            .output(output)
+            .output_data(nonce_data(0).pack())
+            .build();
+        Ok((tx, type_script))
+    }
+
+    /// The live nonce cell of `type_id`, if not destroyed.
+    pub fn find(
+        &self,
+        backend: &dyn ChainBackend,
+        type_id: &[u8; 32],
+    ) -> Result<Option<LiveCell>, Error> {
+        let type_script = self.type_script(type_id);
+        let mut query = CellQueryOptions::new_type(type_script.clone());
+        query.with_data = Some(true);
+        let page = backend.cells(query.into(), 2, None)?;
+        // the indexer matches the type script by prefix
+        Ok(page
+            .objects
+            .into_iter()
+            .map(LiveCell::from)
+            .find(|cell| cell.output.type_().to_opt().as_ref() == Some(&type_script)))
+    }
+}
+
+/// The type id of the cell at `output_index` of a transaction whose first
+/// input is `first_input`.
+pub fn type_id(first_input: &CellInput, output_index: u64) -> [u8; 32] {
+    let mut blake2b = ckb_hash::new_blake2b();
+    blake2b.update(first_input.as_slice());
+    blake2b.update(&output_index.to_le_bytes());
+    let mut type_id = [0u8; 32];
+    blake2b.finalize(&mut type_id);
+    type_id
+}
+
+pub fn nonce_data(nonce: u64) -> Bytes {
+    Bytes::copy_from_slice(&nonce.to_le_bytes())
+}
+
+pub fn parse_nonce(data: &[u8]) -> Result<u64, Error> {
+    let nonce: [u8; NONCE_SIZE] = data.try_into().map_err(|_| {
+        Error::InvalidParameter(format!(
+            "nonce cell data is {} bytes, expected {}",
+            data.len(),
+            NONCE_SIZE
+        ))
+    })?;
+    Ok(u64::from_le_bytes(nonce))
+}
+
+/// Spend the nonce `cell` in `tx` and recreate it with the next nonce as the
+/// last output, returning the nonce the transaction consumes.
+pub fn bump(tx: &TransactionView, cell: &LiveCell) -> Result<(TransactionView, u64), Error> {
+    let nonce = parse_nonce(&cell.output_data)?;
+    let next = nonce
+        .checked_add(1)
+        .ok_or_else(|| Error::InvalidParameter("the nonce reached its maximum".to_string()))?;
+    let tx = tx
+        .as_advanced_builder()
+        .input(CellInput::new(cell.out_point.clone(), 0))
+        .output(cell.output.clone())
+        .output_data(nonce_data(next).pack())
+        .build();
+    Ok((tx, nonce))
+}
+
+fn check_capacity(output: &CellOutput) -> Result<(), Error> {
+    let occupied = output
+        .occupied_capacity(Capacity::bytes(NONCE_SIZE).expect("nonce size"))
+        .expect("occupied capacity")
+        .as_u64();
+    let capacity: u64 = output.capacity().unpack();
+    if capacity < occupied {
+        return Err(Error::InsufficientCapacity(format!(
+            "a nonce cell needs {} shannons, got {}",
+            occupied, capacity
+        )));
+    }
+    Ok(())
+}
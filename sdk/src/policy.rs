@@ -0,0 +1,355 @@
+//! Off-chain policy of a signing machine, checked before its keys sign a
+//! request: defense in depth for hot signers, the lock itself enforcing none
+//! of it. The policy file, TOML:
+//!
+//! ```toml
+//! # the outputs to other locks than the config may only go to these
+//! allowed_destinations = ["ckb1...", "ckb1..."]
+//! # CKB sent to other locks, per transaction and per rolling 24 hours
+//! max_per_transaction = "10000"
+//! daily_limit = "50000"
+//! # members who must have signed before, pubkey hashes
+//! required_approvers = ["0x..."]
+//!
+//! [business_hours]
+//! # minutes east of UTC, 0 by default
+//! utc_offset = 480
+//! days = ["mon", "tue", "wed", "thu", "fri"]
+//! start = "09:00"
+//! end = "18:00"
+//! ```
+//!
+//! Every rule is optional. `Policy::evaluate` lists the `Violation`s of a
+//! request, the signer refusing unless there are none. The daily limit
+//! counts the spends the machine signed, kept in a `SpendLedger` file.
+
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ckb_sdk::Address;
+use ckb_types::{packed::Script, prelude::*, H256};
+use serde::{Deserialize, Serialize};
+
+use crate::{batch::parse_ckb, constants::BLAKE160_SIZE, error::Error, request::SigningRequest};
+
+const DAY: u64 = 24 * 60 * 60;
+const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    allowed_destinations: Option<Vec<String>>,
+    max_per_transaction: Option<String>,
+    daily_limit: Option<String>,
+    #[serde(default)]
+    required_approvers: Vec<String>,
+    business_hours: Option<HoursFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HoursFile {
+    #[serde(default)]
+    utc_offset: i32,
+    days: Vec<String>,
+    start: String,
+    end: String,
+}
+
+/// The window the machine signs in, from `start` to `end` minutes of the
+/// local day, on `days`, 0 being Sunday.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusinessHours {
+    /// Minutes east of UTC.
+    pub utc_offset: i32,
+    pub days: Vec<u8>,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl BusinessHours {
+    /// Whether the unix time `now` is in the window.
+    pub fn contains(&self, now: u64) -> bool {
+        let local = now as i64 + i64::from(self.utc_offset) * 60;
+        // 1970-01-01 was a Thursday
+        let day = (local.div_euclid(DAY as i64) + 4).rem_euclid(7) as u8;
+        let minute = (local.rem_euclid(DAY as i64) / 60) as u32;
+        self.days.contains(&day) && self.start <= minute && minute < self.end
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// Any destination when `None`.
+    pub allowed_destinations: Option<Vec<Script>>,
+    /// In shannons.
+    pub max_per_transaction: Option<u64>,
+    /// In shannons.
+    pub daily_limit: Option<u64>,
+    pub required_approvers: Vec<[u8; BLAKE160_SIZE]>,
+    pub business_hours: Option<BusinessHours>,
+}
+
+/// A rule a request breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    Destination { lock: Script },
+    TransactionLimit { value: u64, limit: u64 },
+    DailyLimit { value: u64, spent: u64, limit: u64 },
+    OutsideBusinessHours,
+    MissingApprover { identity: [u8; BLAKE160_SIZE] },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::Destination { lock } => write!(
+                f,
+                "destination {:#x} is not allowed",
+                lock.calc_script_hash()
+            ),
+            Violation::TransactionLimit { value, limit } => write!(
+                f,
+                "sends {} shannons, over the limit of {} per transaction",
+                value, limit
+            ),
+            Violation::DailyLimit {
+                value,
+                spent,
+                limit,
+            } => write!(
+                f,
+                "sends {} shannons after {} in the last 24 hours, over the daily limit of {}",
+                value, spent, limit
+            ),
+            Violation::OutsideBusinessHours => write!(f, "outside of the business hours"),
+            Violation::MissingApprover { identity } => {
+                write!(f, "0x{} hasn't signed yet", hex::encode(identity))
+            }
+        }
+    }
+}
+
+/// What a request is evaluated against besides itself.
+#[derive(Clone, Debug, Default)]
+pub struct Context {
+    /// Unix time, in seconds.
+    pub now: u64,
+    /// Shannons signed away in the 24 hours before `now`, see `SpendLedger`.
+    pub spent: u64,
+    /// Identities of the keys about to sign, which needn't have signed.
+    pub signers: Vec<[u8; BLAKE160_SIZE]>,
+}
+
+impl Policy {
+    pub fn from_toml(content: &str) -> Result<Self, Error> {
+        let file: PolicyFile = toml::from_str(content).map_err(invalid)?;
+        let allowed_destinations = file
+            .allowed_destinations
+            .map(|addresses| {
+                addresses
+                    .iter()
+                    .map(|address| {
+                        Address::from_str(address)
+                            .map(|address| Script::from(&address))
+                            .map_err(|err| invalid(format!("address `{}`: {}", address, err)))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?;
+        let amount = |amount: Option<String>| amount.map(|a| parse_ckb(&a)).transpose();
+        let required_approvers = file
+            .required_approvers
+            .iter()
+            .map(|identity| {
+                hex::decode(identity.trim_start_matches("0x"))
+                    .ok()
+                    .and_then(|bytes| <[u8; BLAKE160_SIZE]>::try_from(bytes.as_slice()).ok())
+                    .ok_or_else(|| invalid(format!("invalid approver `{}`", identity)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let business_hours = file.business_hours.map(parse_hours).transpose()?;
+        Ok(Policy {
+            allowed_destinations,
+            max_per_transaction: amount(file.max_per_transaction).map_err(invalid)?,
+            daily_limit: amount(file.daily_limit).map_err(invalid)?,
+            required_approvers,
+            business_hours,
+        })
+    }
+
+    /// Every rule `request` breaks, none when it may be signed.
+    pub fn evaluate(
+        &self,
+        request: &SigningRequest,
+        context: &Context,
+    ) -> Result<Vec<Violation>, Error> {
+        let lock_script = &request.script_group.script;
+        let mut violations = Vec::new();
+        if let Some(allowed) = &self.allowed_destinations {
+            for output in request.tx.outputs() {
+                let lock = output.lock();
+                let violation = Violation::Destination { lock: lock.clone() };
+                if lock != *lock_script
+                    && !allowed.contains(&lock)
+                    && !violations.contains(&violation)
+                {
+                    violations.push(violation);
+                }
+            }
+        }
+        let value = outgoing(request);
+        if let Some(limit) = self.max_per_transaction {
+            if value > limit {
+                violations.push(Violation::TransactionLimit { value, limit });
+            }
+        }
+        if let Some(limit) = self.daily_limit {
+            if context.spent.saturating_add(value) > limit {
+                violations.push(Violation::DailyLimit {
+                    value,
+                    spent: context.spent,
+                    limit,
+                });
+            }
+        }
+        if let Some(hours) = &self.business_hours {
+            if !hours.contains(context.now) {
+                violations.push(Violation::OutsideBusinessHours);
+            }
+        }
+        let signed = request.signed()?;
+        for identity in &self.required_approvers {
+            if !signed.contains(identity) && !context.signers.contains(identity) {
+                violations.push(Violation::MissingApprover {
+                    identity: *identity,
+                });
+            }
+        }
+        Ok(violations)
+    }
+}
+
+/// The capacity `request` sends to other locks than its config, in
+/// shannons.
+pub fn outgoing(request: &SigningRequest) -> u64 {
+    let lock_script = &request.script_group.script;
+    request
+        .tx
+        .outputs()
+        .into_iter()
+        .filter(|output| output.lock() != *lock_script)
+        .map(|output| Unpack::<u64>::unpack(&output.capacity()))
+        .sum()
+}
+
+/// A spend signed by the machine.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Spend {
+    /// Unix time, in seconds.
+    pub timestamp: u64,
+    /// In shannons.
+    pub value: u64,
+}
+
+/// The spends signed by the machine for the daily limit, a JSON file of
+/// the spends by transaction hash. The spends older than a day are dropped
+/// on save.
+#[derive(Clone, Debug)]
+pub struct SpendLedger {
+    path: PathBuf,
+    spends: BTreeMap<H256, Spend>,
+}
+
+impl SpendLedger {
+    /// The ledger of `path`, empty when the file is missing.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let spends = if path.exists() {
+            let content = fs::read_to_string(path)
+                .map_err(|err| invalid(format!("{}: {}", path.display(), err)))?;
+            serde_json::from_str(&content)
+                .map_err(|err| invalid(format!("{}: {}", path.display(), err)))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(SpendLedger {
+            path: path.to_path_buf(),
+            spends,
+        })
+    }
+
+    /// Shannons spent in the 24 hours before `now`.
+    pub fn spent(&self, now: u64) -> u64 {
+        self.spends
+            .values()
+            .filter(|spend| spend.timestamp + DAY > now)
+            .map(|spend| spend.value)
+            .sum()
+    }
+
+    /// Record the spend of `request` at `now`, once per transaction.
+    pub fn record(&mut self, request: &SigningRequest, now: u64) {
+        self.spends
+            .entry(request.tx.hash().unpack())
+            .or_insert(Spend {
+                timestamp: now,
+                value: outgoing(request),
+            });
+    }
+
+    pub fn save(&mut self, now: u64) -> Result<(), Error> {
+        self.spends.retain(|_, spend| spend.timestamp + DAY > now);
+        let json = serde_json::to_string_pretty(&self.spends).expect("serializable spends");
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|err| invalid(format!("{}: {}", self.path.display(), err)))
+    }
+}
+
+fn parse_hours(file: HoursFile) -> Result<BusinessHours, Error> {
+    let days = file
+        .days
+        .iter()
+        .map(|day| {
+            DAYS.iter()
+                .position(|known| known == day)
+                .map(|position| position as u8)
+                .ok_or_else(|| invalid(format!("unknown day `{}`", day)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let (start, end) = (parse_time(&file.start)?, parse_time(&file.end)?);
+    if start >= end {
+        return Err(invalid(format!(
+            "business hours end at {} before they start at {}",
+            file.end, file.start
+        )));
+    }
+    if file.utc_offset.abs() > 14 * 60 {
+        return Err(invalid(format!("invalid utc_offset {}", file.utc_offset)));
+    }
+    Ok(BusinessHours {
+        utc_offset: file.utc_offset,
+        days,
+        start,
+        end,
+    })
+}
+
+/// `HH:MM` in minutes, `24:00` for the end of the day.
+fn parse_time(time: &str) -> Result<u32, Error> {
+    let parsed = time.split_once(':').and_then(|(hours, minutes)| {
+        let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+        (minutes < 60 && hours * 60 + minutes <= 24 * 60).then_some(hours * 60 + minutes)
+    });
+    parsed.ok_or_else(|| invalid(format!("invalid time `{}`, expected HH:MM", time)))
+}
+
+fn invalid(message: impl ToString) -> Error {
+    Error::InvalidPolicy(message.to_string())
+}
@@ -0,0 +1,304 @@
+//! The ckb-sdk traits its transaction builders and balancers take, over a
+//! `ChainBackend`, so that they run on the indexer of a node, its rich
+//! indexer or a light client alike:
+//!
+//! * `BackendCellCollector`, the `CellCollector` of the live cells;
+//! * `BackendProvider`, the `HeaderDepResolver`, the
+//!   `TransactionDependencyProvider` and the `BlockInfoProvider` of
+//!   `MaturedCellCollector`, keeping the headers and the transactions it
+//!   looked up.
+//!
+//! ```ignore
+//! let backend: Arc<dyn ChainBackend> = BackendKind::LightClient.connect(url).into();
+//! let provider = BackendProvider::new(backend.clone());
+//! let cell_dep_resolver = DefaultCellDepResolver::from_genesis(&backend.genesis()?)?;
+//! let mut collector = MaturedCellCollector::new(
+//!     BackendCellCollector::new(backend.clone()),
+//!     BackendProvider::new(backend),
+//!     &code_hash,
+//!     ScriptHashType::Type,
+//! );
+//! ```
+//!
+//! The light client looks blocks up by hash only: a header is resolved by
+//! number once resolved by a transaction of its block, as the DAO builders
+//! of ckb-sdk do when the lookup by number fails.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use ckb_sdk::{
+    rpc::ckb_indexer::SearchKey,
+    traits::{
+        CellCollector, CellCollectorError, CellQueryOptions, HeaderDepResolver, LiveCell,
+        TransactionDependencyError, TransactionDependencyProvider,
+    },
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{HeaderView, TransactionView},
+    packed::{Byte32, CellOutput, OutPoint, Transaction},
+    prelude::*,
+    H256,
+};
+
+use crate::{
+    backend::ChainBackend, collector::BlockInfoProvider, error::Error, scanner::BlockInfo,
+};
+
+/// The page size the collector starts from, doubled up to `MAX_LIMIT`.
+const LIMIT: u32 = 16;
+const MAX_LIMIT: u32 = 4096;
+
+/// The live cells of a backend, minus the cells locked and plus the outputs
+/// of the transactions applied, as the collectors of ckb-sdk. The backends
+/// page in ascending order whatever the order of the query. Cellbase
+/// outputs are never collected, their maturity being out of the backends'
+/// reach.
+#[derive(Clone)]
+pub struct BackendCellCollector {
+    backend: Arc<dyn ChainBackend>,
+    locked: HashSet<OutPoint>,
+    /// The outputs of the transactions applied.
+    applied: Vec<LiveCell>,
+}
+
+impl BackendCellCollector {
+    pub fn new(backend: Arc<dyn ChainBackend>) -> Self {
+        BackendCellCollector {
+            backend,
+            locked: HashSet::new(),
+            applied: Vec::new(),
+        }
+    }
+
+    /// The cells of `query` not locked, the outputs applied first, until
+    /// `min_total_capacity` is reached.
+    fn collect(&self, query: &CellQueryOptions) -> Result<(Vec<LiveCell>, u64), Error> {
+        let mut cells: Vec<LiveCell> = Vec::new();
+        let mut total = 0u64;
+        let add = |cell: LiveCell, cells: &mut Vec<LiveCell>, total: &mut u64| {
+            if *total < query.min_total_capacity
+                && !self.locked.contains(&cell.out_point)
+                && query.match_cell(&cell, 0)
+                && cells.iter().all(|other| other.out_point != cell.out_point)
+            {
+                *total += Unpack::<u64>::unpack(&cell.output.capacity());
+                cells.push(cell);
+            }
+        };
+        for cell in &self.applied {
+            add(cell.clone(), &mut cells, &mut total);
+        }
+        let search_key = SearchKey::from(query.clone());
+        let mut limit = query.limit.unwrap_or(LIMIT);
+        let mut cursor = None;
+        while total < query.min_total_capacity {
+            let page = self.backend.cells(search_key.clone(), limit, cursor)?;
+            if page.objects.is_empty() {
+                break;
+            }
+            for cell in page.objects {
+                add(LiveCell::from(cell), &mut cells, &mut total);
+            }
+            cursor = Some(page.last_cursor);
+            limit = (limit * 2).min(MAX_LIMIT);
+        }
+        Ok((cells, total))
+    }
+}
+
+#[async_trait::async_trait]
+impl CellCollector for BackendCellCollector {
+    async fn collect_live_cells_async(
+        &mut self,
+        query: &CellQueryOptions,
+        apply_changes: bool,
+    ) -> Result<(Vec<LiveCell>, u64), CellCollectorError> {
+        let (cells, total) = self
+            .collect(query)
+            .map_err(|err| CellCollectorError::Internal(err.into()))?;
+        if apply_changes {
+            for cell in &cells {
+                self.locked.insert(cell.out_point.clone());
+            }
+        }
+        Ok((cells, total))
+    }
+
+    fn lock_cell(
+        &mut self,
+        out_point: OutPoint,
+        _tip_number: u64,
+    ) -> Result<(), CellCollectorError> {
+        self.locked.insert(out_point);
+        Ok(())
+    }
+
+    fn apply_tx(&mut self, tx: Transaction, _tip_number: u64) -> Result<(), CellCollectorError> {
+        let tx = tx.into_view();
+        self.locked.extend(tx.input_pts_iter());
+        for (index, (output, data)) in tx.outputs_with_data_iter().enumerate() {
+            self.applied.push(LiveCell {
+                output,
+                output_data: data,
+                out_point: OutPoint::new(tx.hash(), index as u32),
+                block_number: 0,
+                tx_index: 0,
+            });
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.locked.clear();
+        self.applied.clear();
+    }
+}
+
+/// The headers and the transactions of a backend, kept once looked up.
+pub struct BackendProvider {
+    backend: Arc<dyn ChainBackend>,
+    headers: Mutex<HashMap<H256, HeaderView>>,
+    txs: Mutex<HashMap<H256, TransactionView>>,
+}
+
+impl BackendProvider {
+    pub fn new(backend: Arc<dyn ChainBackend>) -> Self {
+        BackendProvider {
+            backend,
+            headers: Mutex::new(HashMap::new()),
+            txs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn keep(&self, header: HeaderView) -> HeaderView {
+        self.headers
+            .lock()
+            .expect("poisoned lock")
+            .insert(header.hash().unpack(), header.clone());
+        header
+    }
+
+    fn header(&self, block_hash: &H256) -> Result<HeaderView, Error> {
+        if let Some(header) = self.headers.lock().expect("poisoned lock").get(block_hash) {
+            return Ok(header.clone());
+        }
+        Ok(self.keep(self.backend.header(block_hash)?))
+    }
+
+    /// The header of block `number`, from those kept first.
+    fn header_by_number(&self, number: u64) -> Result<HeaderView, Error> {
+        let kept = self
+            .headers
+            .lock()
+            .expect("poisoned lock")
+            .values()
+            .find(|header| header.number() == number)
+            .cloned();
+        match kept {
+            Some(header) => Ok(header),
+            None => Ok(self.keep(self.backend.header_by_number(number)?)),
+        }
+    }
+
+    fn transaction(&self, tx_hash: &H256) -> Result<TransactionView, Error> {
+        if let Some(tx) = self.txs.lock().expect("poisoned lock").get(tx_hash) {
+            return Ok(tx.clone());
+        }
+        let tx = self.backend.transaction(tx_hash)?;
+        self.txs
+            .lock()
+            .expect("poisoned lock")
+            .insert(tx_hash.clone(), tx.clone());
+        Ok(tx)
+    }
+
+    /// The output `out_point` refers to, and its data.
+    fn output(
+        &self,
+        out_point: &OutPoint,
+    ) -> Result<(CellOutput, Bytes), TransactionDependencyError> {
+        let tx = self
+            .transaction(&out_point.tx_hash().unpack())
+            .map_err(|err| TransactionDependencyError::Other(err.into()))?;
+        let index: u32 = out_point.index().unpack();
+        tx.output_with_data(index as usize)
+            .ok_or_else(|| TransactionDependencyError::NotFound(format!("cell {}", out_point)))
+    }
+}
+
+#[async_trait::async_trait]
+impl HeaderDepResolver for BackendProvider {
+    async fn resolve_by_tx_async(
+        &self,
+        tx_hash: &Byte32,
+    ) -> Result<Option<HeaderView>, anyhow::Error> {
+        let block_hash = self.backend.transaction_block(&tx_hash.unpack())?;
+        Ok(Some(self.header(&block_hash)?))
+    }
+
+    async fn resolve_by_number_async(
+        &self,
+        number: u64,
+    ) -> Result<Option<HeaderView>, anyhow::Error> {
+        self.header_by_number(number)
+            .map(Some)
+            .map_err(|err| anyhow!(err))
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionDependencyProvider for BackendProvider {
+    async fn get_transaction_async(
+        &self,
+        tx_hash: &Byte32,
+    ) -> Result<TransactionView, TransactionDependencyError> {
+        self.transaction(&tx_hash.unpack())
+            .map_err(|err| TransactionDependencyError::Other(err.into()))
+    }
+
+    async fn get_cell_async(
+        &self,
+        out_point: &OutPoint,
+    ) -> Result<CellOutput, TransactionDependencyError> {
+        Ok(self.output(out_point)?.0)
+    }
+
+    async fn get_cell_data_async(
+        &self,
+        out_point: &OutPoint,
+    ) -> Result<Bytes, TransactionDependencyError> {
+        Ok(self.output(out_point)?.1)
+    }
+
+    async fn get_header_async(
+        &self,
+        block_hash: &Byte32,
+    ) -> Result<HeaderView, TransactionDependencyError> {
+        self.header(&block_hash.unpack())
+            .map_err(|err| TransactionDependencyError::Other(err.into()))
+    }
+
+    async fn get_block_extension_async(
+        &self,
+        _block_hash: &Byte32,
+    ) -> Result<Option<ckb_types::packed::Bytes>, TransactionDependencyError> {
+        Err(TransactionDependencyError::NotFound(
+            "the block extensions are out of the backends' reach".to_string(),
+        ))
+    }
+}
+
+impl BlockInfoProvider for BackendProvider {
+    fn tip(&self) -> Result<BlockInfo, Error> {
+        self.backend.tip()
+    }
+
+    fn block(&self, number: u64) -> Result<BlockInfo, Error> {
+        Ok(BlockInfo::from(&self.header_by_number(number)?))
+    }
+}
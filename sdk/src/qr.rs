@@ -0,0 +1,429 @@
+//! Air-gapped payloads, signing requests and signatures moved between an
+//! online coordinator and an offline signer as a sequence of QR codes.
+//!
+//! A payload is a compact binary form, `version | kind | body`:
+//!
+//! * request: `u16 len | multisig script | u8 has_since | [u64 since] |
+//!   u32 len | lock script | u16 count | u32 input indices | u64 fee |
+//!   u8 has_genesis | [genesis hash] | transaction`, the scripts and the
+//!   transaction serialized in molecule, version 1 without the genesis hash;
+//! * signatures: `tx hash | u8 count | 65 bytes signatures`.
+//!
+//! Integers are little endian. The payload is split in frames of the form
+//! `CKBMS/<index>-<total>/<checksum>/<data>`, the index starts at 1, the
+//! checksum is the hex of the first 4 bytes of the payload ckb hash and the
+//! data is unpadded RFC 4648 base32. Every character is in the QR
+//! alphanumeric set, the densest QR mode. Frames are reassembled in any
+//! order, so a scanner can loop over them until it has them all.
+
+use std::convert::TryInto;
+
+use ckb_hash::blake2b_256;
+use ckb_sdk::types::ScriptGroup;
+use ckb_types::{packed, prelude::*, H256};
+
+use crate::{
+    config::MultisigConfig, constants::SIGNATURE_SIZE, error::Error, request::SigningRequest,
+    unlock::set_lock,
+};
+
+/// The version of the binary form written by this SDK, 2 adding the genesis
+/// hash.
+pub const PAYLOAD_VERSION: u8 = 2;
+
+pub const FRAME_PREFIX: &str = "CKBMS";
+
+/// Characters per frame, fits a QR code of version 13 at the low error
+/// correction level in alphanumeric mode.
+pub const DEFAULT_FRAME_SIZE: usize = 480;
+
+const KIND_REQUEST: u8 = 1;
+const KIND_SIGNATURES: u8 = 2;
+const CHECKSUM_SIZE: usize = 4;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Signatures added by a signer, to merge into the request on the
+/// coordinator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureSet {
+    pub tx_hash: H256,
+    pub signatures: Vec<[u8; SIGNATURE_SIZE]>,
+}
+
+impl SignatureSet {
+    /// The signatures filled in the lock of `request`.
+    pub fn from_request(request: &SigningRequest) -> Result<Self, Error> {
+        Ok(SignatureSet {
+            tx_hash: request.tx.hash().unpack(),
+            signatures: request.lock()?.filled().copied().collect(),
+        })
+    }
+
+    /// Add the signatures `request` doesn't have yet.
+    pub fn apply(&self, request: &mut SigningRequest) -> Result<(), Error> {
+        let tx_hash: H256 = request.tx.hash().unpack();
+        if tx_hash != self.tx_hash {
+            return Err(Error::InvalidPayload(format!(
+                "the signatures are for transaction {:#x}, not {:#x}",
+                self.tx_hash, tx_hash
+            )));
+        }
+        let mut lock = request.lock()?;
+        for signature in &self.signatures {
+            if !lock.filled().any(|filled| filled == signature) {
+                lock.add_signature(*signature)?;
+            }
+        }
+        request.tx = set_lock(&request.tx, &request.script_group, &lock)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Payload {
+    Request(Box<SigningRequest>),
+    Signatures(SignatureSet),
+}
+
+impl Payload {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![PAYLOAD_VERSION];
+        match self {
+            Payload::Request(request) => {
+                out.push(KIND_REQUEST);
+                let script = request.config.multisig_script();
+                out.extend_from_slice(&(script.len() as u16).to_le_bytes());
+                out.extend_from_slice(&script);
+                match request.config.since() {
+                    Some(since) => {
+                        out.push(1);
+                        out.extend_from_slice(&since.to_le_bytes());
+                    }
+                    None => out.push(0),
+                }
+                let lock = request.script_group.script.as_slice();
+                out.extend_from_slice(&(lock.len() as u32).to_le_bytes());
+                out.extend_from_slice(lock);
+                let indices = &request.script_group.input_indices;
+                out.extend_from_slice(&(indices.len() as u16).to_le_bytes());
+                for index in indices {
+                    out.extend_from_slice(&(*index as u32).to_le_bytes());
+                }
+                out.extend_from_slice(&request.fee.to_le_bytes());
+                match &request.genesis_hash {
+                    Some(genesis_hash) => {
+                        out.push(1);
+                        out.extend_from_slice(genesis_hash.as_bytes());
+                    }
+                    None => out.push(0),
+                }
+                out.extend_from_slice(request.tx.data().as_slice());
+            }
+            Payload::Signatures(set) => {
+                out.push(KIND_SIGNATURES);
+                out.extend_from_slice(set.tx_hash.as_bytes());
+                out.push(set.signatures.len() as u8);
+                for signature in &set.signatures {
+                    out.extend_from_slice(signature);
+                }
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader(bytes);
+        let version = reader.u8()?;
+        if version == 0 || version > PAYLOAD_VERSION {
+            return Err(Error::InvalidPayload(format!(
+                "unsupported version {}, expected at most {}",
+                version, PAYLOAD_VERSION
+            )));
+        }
+        let payload = match reader.u8()? {
+            KIND_REQUEST => {
+                let len = reader.u16()? as usize;
+                let config = MultisigConfig::from_multisig_script(reader.take(len)?)?;
+                let config = match reader.u8()? {
+                    0 => config,
+                    1 => config.with_since(Some(reader.u64()?)),
+                    flag => {
+                        return Err(Error::InvalidPayload(format!(
+                            "invalid since flag {}",
+                            flag
+                        )))
+                    }
+                };
+                let len = reader.u32()? as usize;
+                let lock = packed::Script::from_slice(reader.take(len)?)
+                    .map_err(|err| Error::InvalidPayload(format!("lock script: {}", err)))?;
+                if lock.args().raw_data() != config.lock_args() {
+                    return Err(Error::InvalidPayload(
+                        "the lock script args don't match the config".to_string(),
+                    ));
+                }
+                let count = reader.u16()? as usize;
+                let input_indices = (0..count)
+                    .map(|_| reader.u32().map(|index| index as usize))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let fee = reader.u64()?;
+                let genesis_hash = match version {
+                    1 => None,
+                    _ => match reader.u8()? {
+                        0 => None,
+                        1 => Some(H256::from_slice(reader.take(32)?).expect("32 bytes")),
+                        flag => {
+                            return Err(Error::InvalidPayload(format!(
+                                "invalid genesis flag {}",
+                                flag
+                            )))
+                        }
+                    },
+                };
+                let tx = packed::Transaction::from_slice(reader.rest())
+                    .map_err(|err| Error::InvalidPayload(format!("transaction: {}", err)))?
+                    .into_view();
+                if let Some(index) = input_indices
+                    .iter()
+                    .find(|index| **index >= tx.inputs().len())
+                {
+                    return Err(Error::InvalidPayload(format!("missing input #{}", index)));
+                }
+                let mut script_group = ScriptGroup::from_lock_script(&lock);
+                script_group.input_indices = input_indices;
+                Payload::Request(Box::new(SigningRequest {
+                    config,
+                    tx,
+                    script_group,
+                    fee,
+                    genesis_hash,
+                }))
+            }
+            KIND_SIGNATURES => {
+                let tx_hash = H256::from_slice(reader.take(32)?).expect("32 bytes");
+                let count = reader.u8()? as usize;
+                let signatures = (0..count)
+                    .map(|_| {
+                        reader
+                            .take(SIGNATURE_SIZE)
+                            .map(|sig| sig.try_into().expect("signature size"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if !reader.rest().is_empty() {
+                    return Err(Error::InvalidPayload("trailing bytes".to_string()));
+                }
+                Payload::Signatures(SignatureSet {
+                    tx_hash,
+                    signatures,
+                })
+            }
+            kind => return Err(Error::InvalidPayload(format!("unknown kind {}", kind))),
+        };
+        Ok(payload)
+    }
+
+    /// The frames of the payload, each at most `frame_size` characters.
+    pub fn to_frames(&self, frame_size: usize) -> Result<Vec<String>, Error> {
+        let bytes = self.to_bytes();
+        let checksum = hex::encode_upper(checksum(&bytes));
+        let data = base32_encode(&bytes);
+        // room for the header with the largest index, the number of frames
+        // only depends on the data length
+        let mut total = 1;
+        loop {
+            let header = format!("{}/{}-{}/{}/", FRAME_PREFIX, total, total, checksum).len();
+            let room = frame_size
+                .checked_sub(header)
+                .filter(|room| *room > 0)
+                .ok_or_else(|| {
+                    Error::InvalidParameter(format!(
+                        "frames of {} characters are too small",
+                        frame_size
+                    ))
+                })?;
+            let needed = data.len().div_ceil(room);
+            if needed <= total {
+                return Ok(data
+                    .as_bytes()
+                    .chunks(room)
+                    .enumerate()
+                    .map(|(i, chunk)| {
+                        format!(
+                            "{}/{}-{}/{}/{}",
+                            FRAME_PREFIX,
+                            i + 1,
+                            needed.max(1),
+                            checksum,
+                            std::str::from_utf8(chunk).expect("base32 is ascii")
+                        )
+                    })
+                    .collect());
+            }
+            total = needed;
+        }
+    }
+}
+
+/// Reassembles the frames of one payload, received in any order.
+#[derive(Clone, Debug, Default)]
+pub struct FrameDecoder {
+    checksum: Option<String>,
+    parts: Vec<Option<String>>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        FrameDecoder::default()
+    }
+
+    /// Received and expected frames.
+    pub fn progress(&self) -> (usize, usize) {
+        (
+            self.parts.iter().filter(|part| part.is_some()).count(),
+            self.parts.len(),
+        )
+    }
+
+    pub fn is_complete(&self) -> bool {
+        !self.parts.is_empty() && self.parts.iter().all(Option::is_some)
+    }
+
+    /// Add a scanned frame, returns the payload once every frame is in.
+    /// Frames received twice are ignored, frames of another payload
+    /// refused.
+    pub fn receive(&mut self, frame: &str) -> Result<Option<Payload>, Error> {
+        let invalid = || Error::InvalidPayload(format!("invalid frame `{}`", frame));
+        let mut fields = frame.trim().splitn(4, '/');
+        if !fields
+            .next()
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(FRAME_PREFIX))
+        {
+            return Err(invalid());
+        }
+        let (index, total) = fields
+            .next()
+            .and_then(|seq| seq.split_once('-'))
+            .and_then(|(index, total)| {
+                Some((index.parse::<usize>().ok()?, total.parse::<usize>().ok()?))
+            })
+            .filter(|(index, total)| *index >= 1 && index <= total)
+            .ok_or_else(invalid)?;
+        let checksum = fields.next().ok_or_else(invalid)?.to_ascii_uppercase();
+        let data = fields.next().ok_or_else(invalid)?.to_ascii_uppercase();
+        match &self.checksum {
+            Some(expected) if *expected != checksum || self.parts.len() != total => {
+                return Err(Error::InvalidPayload(format!(
+                    "frame of payload {} while reading payload {}",
+                    checksum, expected
+                )))
+            }
+            Some(_) => {}
+            None => {
+                self.checksum = Some(checksum.clone());
+                self.parts = vec![None; total];
+            }
+        }
+        self.parts[index - 1] = Some(data);
+        if !self.is_complete() {
+            return Ok(None);
+        }
+        let data: String = self.parts.iter().flatten().map(String::as_str).collect();
+        let bytes = base32_decode(&data)?;
+        if hex::encode_upper(self::checksum(&bytes)) != checksum {
+            return Err(Error::InvalidPayload(format!(
+                "checksum mismatch, expected {}",
+                checksum
+            )));
+        }
+        Payload::from_bytes(&bytes).map(Some)
+    }
+}
+
+fn checksum(bytes: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let hash = blake2b_256(bytes);
+    let mut checksum = [0u8; CHECKSUM_SIZE];
+    checksum.copy_from_slice(&hash[..CHECKSUM_SIZE]);
+    checksum
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(data: &str) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::with_capacity(data.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in data.bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .ok_or_else(|| {
+                Error::InvalidPayload(format!("invalid base32 character `{}`", c as char))
+            })?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    // the leftover bits are padding, only zeros are canonical
+    if bits >= 5 || buffer & ((1 << bits) - 1) != 0 {
+        return Err(Error::InvalidPayload("invalid base32 padding".to_string()));
+    }
+    Ok(out)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(Error::InvalidPayload("truncated payload".to_string()));
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.0)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(
+            self.take(2)?.try_into().expect("2 bytes"),
+        ))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("4 bytes"),
+        ))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("8 bytes"),
+        ))
+    }
+}
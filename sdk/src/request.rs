@@ -0,0 +1,236 @@
+//! Signing requests, the transactions passed between cosigners.
+//!
+//! The JSON form carries everything a cosigner needs to check and sign
+//! offline: the config, the lock script, the inputs of the script group and
+//! the transaction in the node RPC format. It names the chain the
+//! transaction is for by its genesis hash, so that a request of a testnet
+//! rehearsal is refused on the mainnet, see `SigningRequest::check_chain`.
+
+use ckb_jsonrpc_types as json;
+use ckb_sdk::{types::ScriptGroup, CkbRpcClient};
+use ckb_types::{core::TransactionView, packed, prelude::*, H256};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::MultisigConfig,
+    config_file::ConfigFile,
+    constants::{BLAKE160_SIZE, SIGNATURE_SIZE},
+    digest::generate_message,
+    error::Error,
+    signer::recover_pubkey,
+    unlock::{set_lock, MultisigScriptSigner},
+    witness::MultisigLock,
+};
+
+/// The version of the JSON form written by this SDK, 2 adding the genesis
+/// hash.
+pub const REQUEST_VERSION: u32 = 2;
+
+/// A transaction to be signed by the cosigners of a config.
+#[derive(Clone, Debug)]
+pub struct SigningRequest {
+    pub config: MultisigConfig,
+    pub tx: TransactionView,
+    pub script_group: ScriptGroup,
+    /// In shannons.
+    pub fee: u64,
+    /// The genesis hash of the chain of the transaction. The builders read
+    /// cells, not the chain: whoever proposes the request sets it.
+    pub genesis_hash: Option<H256>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RequestFile {
+    version: u32,
+    config: ConfigFile,
+    lock_script: json::Script,
+    input_indices: Vec<usize>,
+    fee: json::Capacity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    genesis_hash: Option<H256>,
+    tx: json::Transaction,
+}
+
+impl SigningRequest {
+    /// The lock field of the script group, unsigned when missing.
+    pub fn lock(&self) -> Result<MultisigLock, Error> {
+        MultisigScriptSigner::new(self.config.clone(), vec![])
+            .current_lock(&self.tx, &self.script_group)
+    }
+
+    /// The message the cosigners sign.
+    pub fn message(&self) -> Result<[u8; 32], Error> {
+        let tx = set_lock(
+            &self.tx,
+            &self.script_group,
+            &MultisigLock::new(self.config.clone()),
+        )?;
+        generate_message(&tx, &self.script_group.input_indices)
+    }
+
+    /// The request is for the chain of `genesis_hash`, refused when it names
+    /// another chain or none.
+    pub fn check_chain(&self, genesis_hash: &H256) -> Result<(), Error> {
+        match &self.genesis_hash {
+            Some(hash) if hash == genesis_hash => Ok(()),
+            Some(hash) => Err(Error::InvalidRequest(format!(
+                "the request is for the chain of genesis {:#x}, not {:#x}",
+                hash, genesis_hash
+            ))),
+            None => Err(Error::InvalidRequest(
+                "the request names no chain, propose it again".to_string(),
+            )),
+        }
+    }
+
+    /// Sign with the local signers of `signer`, which must use the request
+    /// config.
+    pub fn sign(&mut self, signer: &MultisigScriptSigner) -> Result<(), Error> {
+        if signer.config().multisig_script() != self.config.multisig_script() {
+            return Err(Error::InvalidParameter(
+                "the signer belongs to another config".to_string(),
+            ));
+        }
+        self.tx = signer.sign(&self.tx, &self.script_group)?;
+        Ok(())
+    }
+
+    /// Identities of the cosigners who already signed.
+    pub fn signed(&self) -> Result<Vec<[u8; BLAKE160_SIZE]>, Error> {
+        self.lock()?.signed_identities(&self.message()?)
+    }
+
+    /// Add a signature produced elsewhere, it must come from a member of the
+    /// config who hasn't signed yet. Returns the identity of the signer.
+    pub fn add_signature(
+        &mut self,
+        signature: [u8; SIGNATURE_SIZE],
+    ) -> Result<[u8; BLAKE160_SIZE], Error> {
+        let message = self.message()?;
+        let identity = self.config.identity(&recover_pubkey(&message, &signature)?);
+        if self.config.position(&identity).is_none() {
+            return Err(Error::Verification(format!(
+                "0x{} is not a member of the config",
+                hex::encode(identity)
+            )));
+        }
+        let mut lock = self.lock()?;
+        if lock.signed_identities(&message)?.contains(&identity) {
+            return Err(Error::Verification(format!(
+                "0x{} already signed",
+                hex::encode(identity)
+            )));
+        }
+        lock.add_signature(signature)?;
+        self.tx = set_lock(&self.tx, &self.script_group, &lock)?;
+        Ok(identity)
+    }
+
+    /// Merge the signatures of another copy of the request, e.g. the one a
+    /// cosigner signed offline. Signatures already there are skipped, the
+    /// identities of the added ones are returned.
+    pub fn combine(&mut self, other: &SigningRequest) -> Result<Vec<[u8; BLAKE160_SIZE]>, Error> {
+        if other.tx.hash() != self.tx.hash()
+            || other.script_group != self.script_group
+            || other.config != self.config
+            || other.genesis_hash != self.genesis_hash
+        {
+            return Err(Error::InvalidRequest(format!(
+                "{:#x} is another proposal than {:#x}",
+                other.tx.hash(),
+                self.tx.hash()
+            )));
+        }
+        let message = self.message()?;
+        let mut signed = self.signed()?;
+        let mut added = Vec::new();
+        for signature in other.lock()?.filled() {
+            let identity = self.config.identity(&recover_pubkey(&message, signature)?);
+            if signed.contains(&identity) {
+                continue;
+            }
+            if self.lock()?.is_complete() {
+                break;
+            }
+            self.add_signature(*signature)?;
+            signed.push(identity);
+            added.push(identity);
+        }
+        Ok(added)
+    }
+
+    /// All the signatures are there and verify.
+    pub fn is_complete(&self) -> Result<bool, Error> {
+        let lock = self.lock()?;
+        Ok(lock.is_complete() && lock.verify(&self.message()?).is_ok())
+    }
+
+    /// Send the transaction, it must be completely signed and for the chain
+    /// of the node.
+    pub fn send(&self, client: &CkbRpcClient) -> Result<H256, Error> {
+        let genesis_hash = client
+            .get_block_hash(0.into())?
+            .ok_or_else(|| Error::Rpc("no genesis block".to_string()))?;
+        self.check_chain(&genesis_hash)?;
+        if !self.is_complete()? {
+            return Err(Error::Verification(format!(
+                "transaction {:#x} is not completely signed",
+                self.tx.hash()
+            )));
+        }
+        // the lock isn't one of the well known scripts
+        Ok(client.send_transaction(
+            self.tx.data().into(),
+            Some(json::OutputsValidator::Passthrough),
+        )?)
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        let file = RequestFile {
+            version: REQUEST_VERSION,
+            config: ConfigFile::from(&self.config),
+            lock_script: self.script_group.script.clone().into(),
+            input_indices: self.script_group.input_indices.clone(),
+            fee: self.fee.into(),
+            genesis_hash: self.genesis_hash.clone(),
+            tx: self.tx.data().into(),
+        };
+        serde_json::to_string_pretty(&file).map_err(|err| Error::InvalidRequest(err.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let file: RequestFile =
+            serde_json::from_str(json).map_err(|err| Error::InvalidRequest(err.to_string()))?;
+        if file.version == 0 || file.version > REQUEST_VERSION {
+            return Err(Error::InvalidRequest(format!(
+                "unsupported version {}, expected at most {}",
+                file.version, REQUEST_VERSION
+            )));
+        }
+        let config = file.config.to_config()?;
+        let lock_script: packed::Script = file.lock_script.into();
+        if lock_script.args().raw_data() != config.lock_args() {
+            return Err(Error::InvalidRequest(
+                "the lock script args don't match the config".to_string(),
+            ));
+        }
+        let tx = packed::Transaction::from(file.tx).into_view();
+        if let Some(index) = file
+            .input_indices
+            .iter()
+            .find(|index| **index >= tx.inputs().len())
+        {
+            return Err(Error::InvalidRequest(format!("missing input #{}", index)));
+        }
+        let mut script_group = ScriptGroup::from_lock_script(&lock_script);
+        script_group.input_indices = file.input_indices;
+        Ok(SigningRequest {
+            config,
+            tx,
+            script_group,
+            fee: file.fee.into(),
+            genesis_hash: file.genesis_hash,
+        })
+    }
+}
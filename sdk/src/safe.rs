@@ -0,0 +1,290 @@
+//! Proposals in the shape of the multisig transactions of the Safe
+//! transaction service, for the treasury dashboards already reading them:
+//!
+//! ```json
+//! {
+//!   "safe": "0x<lock args>",
+//!   "nonce": 7,
+//!   "description": "Q3 payroll",
+//!   "safeTxHash": "0x<message signed by the cosigners>",
+//!   "transactionHash": "0x<tx hash>",
+//!   "value": "120000000000",
+//!   "fee": "100000",
+//!   "confirmationsRequired": 2,
+//!   "confirmations": [
+//!     {
+//!       "owner": "0x<pubkey hash>",
+//!       "ownerLabel": "alice",
+//!       "signature": "0x<65 bytes>",
+//!       "signatureType": "EOA"
+//!     }
+//!   ],
+//!   "config": { ... },
+//!   "lockScript": { ... },
+//!   "inputIndices": [0, 1],
+//!   "genesisHash": "0x<genesis hash of the chain>",
+//!   "transaction": { ... }
+//! }
+//! ```
+//!
+//! The CKB part is the one of a signing request: the config file, the lock
+//! script, the inputs of the script group, the genesis hash of the chain,
+//! when the request names one, and the transaction in the node RPC format,
+//! with the unsigned lock. The signatures are the confirmations
+//! alone, checked against their owner on import. `value` is the capacity of
+//! the outputs to other locks, `value` and `fee` in shannons as decimal
+//! strings. The `nonce` and the `description` are the dashboard's, the chain
+//! knows neither. Fields added by a dashboard are ignored.
+
+use std::convert::TryInto;
+
+use ckb_jsonrpc_types as json;
+use ckb_sdk::types::ScriptGroup;
+use ckb_types::{packed, prelude::*, H256};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config_file::ConfigFile,
+    constants::{BLAKE160_SIZE, SIGNATURE_SIZE},
+    error::Error,
+    request::SigningRequest,
+    signer::recover_pubkey,
+    unlock::set_lock,
+    witness::MultisigLock,
+};
+
+/// The `signatureType` of a signature by a member key.
+pub const EOA_SIGNATURE: &str = "EOA";
+
+/// A signing request with the bookkeeping of a dashboard.
+#[derive(Clone, Debug)]
+pub struct SafeProposal {
+    pub nonce: u64,
+    pub description: Option<String>,
+    pub request: SigningRequest,
+    /// Labels of the members in the config order, written into the config
+    /// and the confirmations. Empty when unknown.
+    pub labels: Vec<Option<String>>,
+}
+
+/// A signature of a member.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Confirmation {
+    pub owner: [u8; BLAKE160_SIZE],
+    pub signature: [u8; SIGNATURE_SIZE],
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SafeFile {
+    safe: json::JsonBytes,
+    nonce: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    safe_tx_hash: String,
+    transaction_hash: String,
+    value: String,
+    fee: String,
+    confirmations_required: u8,
+    #[serde(default)]
+    confirmations: Vec<ConfirmationFile>,
+    config: ConfigFile,
+    lock_script: json::Script,
+    input_indices: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    genesis_hash: Option<H256>,
+    transaction: json::Transaction,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfirmationFile {
+    owner: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner_label: Option<String>,
+    signature: String,
+    signature_type: String,
+}
+
+impl SafeProposal {
+    pub fn new(nonce: u64, request: SigningRequest) -> Self {
+        SafeProposal {
+            nonce,
+            description: None,
+            request,
+            labels: Vec::new(),
+        }
+    }
+
+    /// The signatures of the request with their signer, in the lock order.
+    pub fn confirmations(&self) -> Result<Vec<Confirmation>, Error> {
+        let request = &self.request;
+        let message = request.message()?;
+        request
+            .lock()?
+            .filled()
+            .map(|signature| {
+                let pubkey = recover_pubkey(&message, signature)?;
+                Ok(Confirmation {
+                    owner: request.config.identity(&pubkey),
+                    signature: *signature,
+                })
+            })
+            .collect()
+    }
+
+    /// The capacity of the outputs to other locks than the config one.
+    pub fn value(&self) -> u64 {
+        let lock_script = &self.request.script_group.script;
+        self.request
+            .tx
+            .outputs()
+            .into_iter()
+            .filter(|output| output.lock() != *lock_script)
+            .map(|output| Unpack::<u64>::unpack(&output.capacity()))
+            .sum()
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        let request = &self.request;
+        let config = &request.config;
+        let mut config_file = ConfigFile::from(config);
+        for (entry, label) in config_file.keys.iter_mut().zip(&self.labels) {
+            entry.label = label.clone();
+        }
+        let label_of = |owner: &[u8; BLAKE160_SIZE]| {
+            config
+                .position(owner)
+                .and_then(|position| self.labels.get(position).cloned().flatten())
+        };
+        let confirmations = self
+            .confirmations()?
+            .iter()
+            .map(|confirmation| ConfirmationFile {
+                owner: format!("0x{}", hex::encode(confirmation.owner)),
+                owner_label: label_of(&confirmation.owner),
+                signature: format!("0x{}", hex::encode(confirmation.signature)),
+                signature_type: EOA_SIGNATURE.to_string(),
+            })
+            .collect();
+        let unsigned = set_lock(
+            &request.tx,
+            &request.script_group,
+            &MultisigLock::new(config.clone()),
+        )?;
+        let file = SafeFile {
+            safe: json::JsonBytes::from_bytes(config.lock_args()),
+            nonce: self.nonce,
+            description: self.description.clone(),
+            safe_tx_hash: format!("0x{}", hex::encode(request.message()?)),
+            transaction_hash: format!("{:#x}", request.tx.hash()),
+            value: self.value().to_string(),
+            fee: request.fee.to_string(),
+            confirmations_required: config.threshold(),
+            confirmations,
+            config: config_file,
+            lock_script: request.script_group.script.clone().into(),
+            input_indices: request.script_group.input_indices.clone(),
+            genesis_hash: request.genesis_hash.clone(),
+            transaction: unsigned.data().into(),
+        };
+        serde_json::to_string_pretty(&file).map_err(|err| Error::InvalidRequest(err.to_string()))
+    }
+
+    /// Read a proposal back, its hashes and lock args must be those of the
+    /// transaction and config it carries, and every confirmation a valid
+    /// signature of its owner.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let file: SafeFile =
+            serde_json::from_str(json).map_err(|err| Error::InvalidRequest(err.to_string()))?;
+        let config = file.config.to_config()?;
+        if file.safe.as_bytes() != config.lock_args().as_ref() {
+            return Err(Error::InvalidRequest(
+                "the safe doesn't match the config lock args".to_string(),
+            ));
+        }
+        let lock_script: packed::Script = file.lock_script.into();
+        if lock_script.args().raw_data() != config.lock_args() {
+            return Err(Error::InvalidRequest(
+                "the lock script args don't match the config".to_string(),
+            ));
+        }
+        let tx = packed::Transaction::from(file.transaction).into_view();
+        if let Some(index) = file
+            .input_indices
+            .iter()
+            .find(|index| **index >= tx.inputs().len())
+        {
+            return Err(Error::InvalidRequest(format!("missing input #{}", index)));
+        }
+        if format!("{:#x}", tx.hash()) != file.transaction_hash {
+            return Err(Error::InvalidRequest(format!(
+                "transactionHash {} is not the hash of the transaction, {:#x}",
+                file.transaction_hash,
+                tx.hash()
+            )));
+        }
+        let fee = &file.fee;
+        let fee = fee
+            .parse()
+            .map_err(|_| Error::InvalidRequest(format!("invalid fee `{}`", fee)))?;
+        let mut script_group = ScriptGroup::from_lock_script(&lock_script);
+        script_group.input_indices = file.input_indices;
+        let tx = set_lock(&tx, &script_group, &MultisigLock::new(config.clone()))?;
+        let mut request = SigningRequest {
+            config,
+            tx,
+            script_group,
+            fee,
+            genesis_hash: file.genesis_hash,
+        };
+        let message = format!("0x{}", hex::encode(request.message()?));
+        if message != file.safe_tx_hash {
+            return Err(Error::InvalidRequest(format!(
+                "safeTxHash {} is not the message of the transaction, {}",
+                file.safe_tx_hash, message
+            )));
+        }
+        for confirmation in &file.confirmations {
+            if confirmation.signature_type != EOA_SIGNATURE {
+                return Err(Error::InvalidRequest(format!(
+                    "unsupported signature type {} of {}",
+                    confirmation.signature_type, confirmation.owner
+                )));
+            }
+            let signature = decode_hex(&confirmation.signature)?
+                .as_slice()
+                .try_into()
+                .map_err(|_| {
+                    Error::InvalidRequest(format!(
+                        "the signature of {} is not {} bytes",
+                        confirmation.owner, SIGNATURE_SIZE
+                    ))
+                })?;
+            let identity = request.add_signature(signature)?;
+            if decode_hex(&confirmation.owner)? != identity {
+                return Err(Error::Verification(format!(
+                    "the confirmation of {} is signed by 0x{}",
+                    confirmation.owner,
+                    hex::encode(identity)
+                )));
+            }
+        }
+        Ok(SafeProposal {
+            nonce: file.nonce,
+            description: file.description,
+            labels: file
+                .config
+                .labels()
+                .iter()
+                .map(|label| label.map(String::from))
+                .collect(),
+            request,
+        })
+    }
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(s.trim_start_matches("0x"))
+        .map_err(|err| Error::InvalidRequest(format!("invalid hex `{}`: {}", s, err)))
+}
@@ -0,0 +1,431 @@
+//! Watch-only scanner: lists the live cells of a config through the indexer
+//! RPC of a CKB node, or a light client, see `light_client.rs`, with the
+//! capacity and since maturity of each, all at once or page by page,
+//! optionally only those of a type script.
+
+use std::{collections::HashMap, fmt, sync::Mutex};
+
+use ckb_jsonrpc_types::JsonBytes;
+use ckb_sdk::{
+    traits::{CellQueryOptions, LiveCell, ValueRangeOption},
+    types::{Since, SinceType},
+};
+use ckb_types::{
+    core::{EpochNumberWithFraction, HeaderView, ScriptHashType},
+    packed::{CellOutput, Script},
+    H256,
+};
+
+use crate::{
+    backend::{BoxedBackend, NodeIndexer},
+    config::MultisigConfig,
+    error::Error,
+    light_client::LightClient,
+};
+
+const PAGE_SIZE: u32 = 256;
+
+/// The position of a block on the chain, in all the since metrics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub number: u64,
+    pub epoch: EpochNumberWithFraction,
+    /// Milliseconds since unix epoch.
+    pub timestamp: u64,
+}
+
+impl From<&HeaderView> for BlockInfo {
+    fn from(header: &HeaderView) -> Self {
+        BlockInfo {
+            number: header.number(),
+            epoch: header.epoch(),
+            timestamp: header.timestamp(),
+        }
+    }
+}
+
+/// A fractional epoch, which may not fit the 16 bits length of
+/// `EpochNumberWithFraction` after adding a relative since.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EpochTarget {
+    pub number: u64,
+    pub index: u64,
+    pub length: u64,
+}
+
+impl EpochTarget {
+    fn reached_by(&self, epoch: EpochNumberWithFraction) -> bool {
+        let (number, index, length) = normalize_epoch(epoch).unwrap_or((epoch.number(), 0, 1));
+        // compare number + index / length
+        let lhs =
+            (u128::from(number) * u128::from(length) + u128::from(index)) * u128::from(self.length);
+        let rhs = (u128::from(self.number) * u128::from(self.length) + u128::from(self.index))
+            * u128::from(length);
+        lhs >= rhs
+    }
+}
+
+impl fmt::Display for EpochTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.index == 0 {
+            write!(f, "epoch {}", self.number)
+        } else {
+            write!(f, "epoch {} + {}/{}", self.number, self.index, self.length)
+        }
+    }
+}
+
+/// When a cell can be spent given the since of the lock args.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Maturity {
+    Mature,
+    /// Spendable in blocks from this number on.
+    BlockNumber(u64),
+    /// Spendable in blocks from this epoch on.
+    Epoch(EpochTarget),
+    /// Spendable once the median time of the chain passes this, in seconds.
+    Timestamp(u64),
+    /// The since of the lock args can never be satisfied.
+    Unsatisfiable,
+}
+
+impl Maturity {
+    /// Evaluate the since of the lock args for a cell committed in `commit`,
+    /// against the chain `tip`.
+    ///
+    /// Timestamps are compared with the tip header time, the chain uses the
+    /// median time of the last 37 blocks which lags behind a little, a cell
+    /// reported mature by timestamp may still need a few more blocks.
+    pub fn evaluate(since: Option<u64>, commit: &BlockInfo, tip: &BlockInfo) -> Self {
+        let since = match since {
+            None | Some(0) => return Maturity::Mature,
+            Some(since) => Since::from_raw_value(since),
+        };
+        if !since.flags_is_valid() {
+            return Maturity::Unsatisfiable;
+        }
+        let (ty, value) = match since.extract_metric() {
+            Some(metric) => metric,
+            None => return Maturity::Unsatisfiable,
+        };
+        let relative = since.is_relative();
+        let maturity = match ty {
+            SinceType::BlockNumber => {
+                let base = if relative { commit.number } else { 0 };
+                Maturity::BlockNumber(base.saturating_add(value))
+            }
+            SinceType::EpochNumberWithFraction => {
+                let target = match normalize_epoch(EpochNumberWithFraction::from_full_value(value))
+                {
+                    Some(target) => target,
+                    None => return Maturity::Unsatisfiable,
+                };
+                let target = if relative {
+                    match normalize_epoch(commit.epoch) {
+                        Some(base) => add_epoch(base, target),
+                        None => return Maturity::Unsatisfiable,
+                    }
+                } else {
+                    EpochTarget {
+                        number: target.0,
+                        index: target.1,
+                        length: target.2,
+                    }
+                };
+                Maturity::Epoch(target)
+            }
+            SinceType::Timestamp => {
+                let base = if relative { commit.timestamp / 1000 } else { 0 };
+                Maturity::Timestamp(base.saturating_add(value))
+            }
+        };
+        if maturity.reached_by(tip) {
+            Maturity::Mature
+        } else {
+            maturity
+        }
+    }
+
+    pub fn is_mature(&self) -> bool {
+        *self == Maturity::Mature
+    }
+
+    fn reached_by(&self, tip: &BlockInfo) -> bool {
+        match self {
+            Maturity::Mature => true,
+            Maturity::BlockNumber(number) => tip.number >= *number,
+            Maturity::Epoch(target) => target.reached_by(tip.epoch),
+            Maturity::Timestamp(timestamp) => tip.timestamp / 1000 >= *timestamp,
+            Maturity::Unsatisfiable => false,
+        }
+    }
+}
+
+impl fmt::Display for Maturity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Maturity::Mature => write!(f, "mature"),
+            Maturity::BlockNumber(number) => write!(f, "locked until block {}", number),
+            Maturity::Epoch(target) => write!(f, "locked until {}", target),
+            Maturity::Timestamp(timestamp) => write!(f, "locked until timestamp {}", timestamp),
+            Maturity::Unsatisfiable => write!(f, "locked forever"),
+        }
+    }
+}
+
+/// `(number, index, length)` of a valid epoch fraction, `0/0` is read as `0/1`.
+fn normalize_epoch(epoch: EpochNumberWithFraction) -> Option<(u64, u64, u64)> {
+    match (epoch.index(), epoch.length()) {
+        (0, 0) => Some((epoch.number(), 0, 1)),
+        (index, length) if index < length => Some((epoch.number(), index, length)),
+        _ => None,
+    }
+}
+
+fn add_epoch(a: (u64, u64, u64), b: (u64, u64, u64)) -> EpochTarget {
+    let length = a.2 * b.2;
+    let index = a.1 * b.2 + b.1 * a.2;
+    let gcd = gcd(index, length);
+    EpochTarget {
+        number: a.0 + b.0 + index / length,
+        index: (index % length) / gcd,
+        length: length / gcd,
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
+/// A live cell guarded by the config.
+#[derive(Clone, Debug)]
+pub struct MultisigCell {
+    pub cell: LiveCell,
+    pub maturity: Maturity,
+}
+
+impl MultisigCell {
+    pub fn capacity(&self) -> u64 {
+        ckb_types::prelude::Unpack::<u64>::unpack(&self.cell.output.capacity())
+    }
+
+    /// No type script and no data, the cells usable to pay with capacity.
+    pub fn is_plain(&self) -> bool {
+        self.cell.output.type_().is_none() && self.cell.output_data.is_empty()
+    }
+}
+
+/// The live cells of a config at a given tip.
+#[derive(Clone, Debug)]
+pub struct CellSet {
+    pub tip: BlockInfo,
+    pub cells: Vec<MultisigCell>,
+}
+
+impl CellSet {
+    pub fn total_capacity(&self) -> u64 {
+        self.cells.iter().map(MultisigCell::capacity).sum()
+    }
+
+    pub fn mature_capacity(&self) -> u64 {
+        self.mature().map(MultisigCell::capacity).sum()
+    }
+
+    pub fn locked_capacity(&self) -> u64 {
+        self.total_capacity() - self.mature_capacity()
+    }
+
+    pub fn mature(&self) -> impl Iterator<Item = &MultisigCell> {
+        self.cells.iter().filter(|cell| cell.maturity.is_mature())
+    }
+
+    /// Mature cells without type script or data, as input candidates of
+    /// the transaction builder.
+    pub fn spendable(&self) -> Vec<LiveCell> {
+        self.mature()
+            .filter(|cell| cell.is_plain())
+            .map(|cell| cell.cell.clone())
+            .collect()
+    }
+}
+
+/// Which cells of the config a scan returns, by type script.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TypeFilter {
+    #[default]
+    Any,
+    /// Cells without type script, the CKB holdings.
+    NoType,
+    /// Cells of this exact type script, the holdings of a UDT for instance.
+    Script(Script),
+}
+
+impl TypeFilter {
+    pub fn matches(&self, output: &CellOutput) -> bool {
+        match self {
+            TypeFilter::Any => true,
+            TypeFilter::NoType => output.type_().is_none(),
+            TypeFilter::Script(script) => output.type_().to_opt().as_ref() == Some(script),
+        }
+    }
+}
+
+/// A page of cells, `cursor` resumes the scan after them and is `None` on
+/// the last page.
+#[derive(Clone, Debug)]
+pub struct CellPage {
+    pub cells: Vec<MultisigCell>,
+    pub cursor: Option<JsonBytes>,
+}
+
+/// Scans the cells of one config.
+pub struct Scanner {
+    backend: BoxedBackend,
+    lock_script: Script,
+    since: Option<u64>,
+    filter: TypeFilter,
+    /// Commit blocks already fetched, for relative since values.
+    headers: Mutex<HashMap<u64, BlockInfo>>,
+}
+
+impl Scanner {
+    /// `url` is the RPC of a CKB node with the indexer module enabled.
+    pub fn new(
+        url: &str,
+        config: &MultisigConfig,
+        code_hash: &H256,
+        hash_type: ScriptHashType,
+    ) -> Self {
+        Self::with_backend(
+            Box::new(NodeIndexer::new(url)),
+            config,
+            code_hash,
+            hash_type,
+        )
+    }
+
+    /// `url` is the RPC of a light client watching the lock of the config,
+    /// see `LightClient::watch`. The cells are those at the block the lock
+    /// is synced to.
+    pub fn light_client(
+        url: &str,
+        config: &MultisigConfig,
+        code_hash: &H256,
+        hash_type: ScriptHashType,
+    ) -> Self {
+        Self::with_backend(
+            Box::new(LightClient::new(url)),
+            config,
+            code_hash,
+            hash_type,
+        )
+    }
+
+    /// Read the chain from `backend`, see `backend.rs`.
+    pub fn with_backend(
+        backend: BoxedBackend,
+        config: &MultisigConfig,
+        code_hash: &H256,
+        hash_type: ScriptHashType,
+    ) -> Self {
+        Scanner {
+            backend,
+            lock_script: config.lock_script(code_hash, hash_type),
+            since: config.since(),
+            filter: TypeFilter::Any,
+            headers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_filter(mut self, filter: TypeFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn lock_script(&self) -> &Script {
+        &self.lock_script
+    }
+
+    pub fn tip(&self) -> Result<BlockInfo, Error> {
+        self.backend.tip()
+    }
+
+    /// Every cell at the current tip.
+    pub fn scan(&self) -> Result<CellSet, Error> {
+        let tip = self.tip()?;
+        let mut cells = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = self.page(&tip, PAGE_SIZE, cursor)?;
+            cells.extend(page.cells);
+            match page.cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        Ok(CellSet { tip, cells })
+    }
+
+    /// At most `limit` cells after `cursor`, oldest first, with their
+    /// maturity at `tip`.
+    pub fn page(
+        &self,
+        tip: &BlockInfo,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Result<CellPage, Error> {
+        let relative = self
+            .since
+            .is_some_and(|since| Since::from_raw_value(since).is_relative());
+        let mut query = CellQueryOptions::new_lock(self.lock_script.clone());
+        query.with_data = Some(true);
+        match &self.filter {
+            TypeFilter::Any => {}
+            TypeFilter::NoType => {
+                query.secondary_script_len_range = Some(ValueRangeOption::new_exact(0))
+            }
+            TypeFilter::Script(script) => query.secondary_script = Some(script.clone()),
+        }
+        let page = self.backend.cells(query.into(), limit, cursor)?;
+        let last = page.objects.len() < limit as usize;
+        let mut cells = Vec::with_capacity(page.objects.len());
+        for cell in page.objects {
+            let cell: LiveCell = cell.into();
+            // the indexer matches the type script by prefix
+            if !self.filter.matches(&cell.output) {
+                continue;
+            }
+            // only relative since values need the commit block
+            let commit = if relative {
+                self.block_info(&cell)?
+            } else {
+                *tip
+            };
+            let maturity = Maturity::evaluate(self.since, &commit, tip);
+            cells.push(MultisigCell { cell, maturity });
+        }
+        Ok(CellPage {
+            cells,
+            cursor: if last { None } else { Some(page.last_cursor) },
+        })
+    }
+
+    /// The commit block of `cell`.
+    fn block_info(&self, cell: &LiveCell) -> Result<BlockInfo, Error> {
+        let number = cell.block_number;
+        if let Some(info) = self.headers.lock().expect("poisoned lock").get(&number) {
+            return Ok(*info);
+        }
+        let info = self.backend.commit_block(cell)?;
+        self.headers
+            .lock()
+            .expect("poisoned lock")
+            .insert(number, info);
+        Ok(info)
+    }
+}
@@ -0,0 +1,142 @@
+use std::convert::TryFrom;
+
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId, Signature},
+    Message, PublicKey, SecretKey, SECP256K1,
+};
+
+use crate::{
+    blake160,
+    constants::{BLAKE160_SIZE, DIGEST_SIZE, SIGNATURE_SIZE},
+    error::Error,
+    hd::{ExtendedPrivKey, Mnemonic},
+    ledger::DerivationPath,
+};
+
+/// A key holder able to take part in the multisig signing flow.
+///
+/// The contract recovers the public key from every signature and looks its
+/// blake160 up in the multisig script, so a signer only has to provide those
+/// two things.
+pub trait Signer {
+    /// blake160 of the public key, as listed in the multisig script: of the
+    /// compressed key unless the config hashes uncompressed keys, see
+    /// `config::KeyFormat`.
+    fn identity(&self) -> Result<[u8; BLAKE160_SIZE], Error>;
+
+    /// Sign the digest, returns a recoverable signature laid out as
+    /// `r | s | recid`, which is the layout the contract expects.
+    fn sign(&self, digest: &[u8; DIGEST_SIZE]) -> Result<[u8; SIGNATURE_SIZE], Error>;
+}
+
+/// Identity of a public key, blake160 of its compressed serialization.
+pub fn pubkey_identity(pubkey: &PublicKey) -> [u8; BLAKE160_SIZE] {
+    blake160(&pubkey.serialize())
+}
+
+/// blake160 of the uncompressed serialization, for configs of
+/// `config::KeyFormat::Uncompressed` keys.
+pub fn uncompressed_pubkey_identity(pubkey: &PublicKey) -> [u8; BLAKE160_SIZE] {
+    blake160(&pubkey.serialize_uncompressed())
+}
+
+/// Serialize a recoverable signature into the 65 bytes layout used in witnesses.
+pub fn serialize_signature(signature: &RecoverableSignature) -> [u8; SIGNATURE_SIZE] {
+    let (recid, data) = signature.serialize_compact();
+    let mut buf = [0u8; SIGNATURE_SIZE];
+    buf[..64].copy_from_slice(&data);
+    buf[64] = i32::from(recid) as u8;
+    buf
+}
+
+/// Recover the public key from a 65 bytes `r | s | recid` signature.
+pub fn recover_pubkey(
+    digest: &[u8; DIGEST_SIZE],
+    signature: &[u8; SIGNATURE_SIZE],
+) -> Result<PublicKey, Error> {
+    let recid = RecoveryId::try_from(i32::from(signature[64]))?;
+    let signature = RecoverableSignature::from_compact(&signature[..64], recid)?;
+    Ok(SECP256K1.recover_ecdsa(&Message::from_digest(*digest), &signature)?)
+}
+
+/// Turn a plain ECDSA signature into a recoverable one.
+///
+/// Devices and services that only hand out DER or compact 64 bytes signatures
+/// don't tell the recovery id, it is found by trying every candidate against
+/// the known public key.
+pub fn to_recoverable(
+    digest: &[u8; DIGEST_SIZE],
+    signature: &Signature,
+    pubkey: &PublicKey,
+) -> Result<[u8; SIGNATURE_SIZE], Error> {
+    let mut signature = *signature;
+    // the contract only accepts low-s signatures
+    signature.normalize_s();
+    let data = signature.serialize_compact();
+    let message = Message::from_digest(*digest);
+    for id in 0..4 {
+        let recid = RecoveryId::try_from(id)?;
+        let candidate = RecoverableSignature::from_compact(&data, recid)?;
+        if SECP256K1.recover_ecdsa(&message, &candidate).ok().as_ref() == Some(pubkey) {
+            return Ok(serialize_signature(&candidate));
+        }
+    }
+    Err(Error::SignatureMismatch)
+}
+
+/// Software signer holding a raw secp256k1 private key.
+///
+/// The nonce of every signature is derived from the key and the digest as in
+/// RFC6979, the default of libsecp256k1: signing the same digest twice gives
+/// the same signature, and no signature depends on the entropy of the
+/// machine, so a weak random source can't leak the key through a reused
+/// nonce.
+pub struct SecpSigner {
+    key: SecretKey,
+}
+
+impl SecpSigner {
+    pub fn new(key: SecretKey) -> Self {
+        SecpSigner { key }
+    }
+
+    pub fn from_slice(key: &[u8]) -> Result<Self, Error> {
+        Ok(Self::new(SecretKey::from_slice(key)?))
+    }
+
+    /// The key at `path` of the seed of `mnemonic`, e.g. `hd::ckb_path` as
+    /// CKB wallets derive their keys.
+    pub fn from_mnemonic(
+        mnemonic: &Mnemonic,
+        passphrase: &str,
+        path: &DerivationPath,
+    ) -> Result<Self, Error> {
+        let master = ExtendedPrivKey::from_seed(&mnemonic.to_seed(passphrase))?;
+        Ok(master.derive(path)?.signer())
+    }
+
+    pub fn pubkey(&self) -> PublicKey {
+        PublicKey::from_secret_key(SECP256K1, &self.key)
+    }
+}
+
+impl Signer for SecpSigner {
+    fn identity(&self) -> Result<[u8; BLAKE160_SIZE], Error> {
+        Ok(pubkey_identity(&self.pubkey()))
+    }
+
+    fn sign(&self, digest: &[u8; DIGEST_SIZE]) -> Result<[u8; SIGNATURE_SIZE], Error> {
+        let signature = SECP256K1.sign_ecdsa_recoverable(&Message::from_digest(*digest), &self.key);
+        Ok(serialize_signature(&signature))
+    }
+}
+
+impl<T: Signer + ?Sized> Signer for Box<T> {
+    fn identity(&self) -> Result<[u8; BLAKE160_SIZE], Error> {
+        (**self).identity()
+    }
+
+    fn sign(&self, digest: &[u8; DIGEST_SIZE]) -> Result<[u8; SIGNATURE_SIZE], Error> {
+        (**self).sign(digest)
+    }
+}
@@ -0,0 +1,554 @@
+//! Since values of the inputs guarded by a config.
+//!
+//! When the lock args carry a since, the contract requires every input of the
+//! script group to use the same since flags, with a value not less than the
+//! one of the args. Other scripts may ask for a since of their own, e.g. the
+//! DAO withdraw phase 2, so both requirements are merged here.
+//!
+//! `SinceSpec` converts between raw since values and a readable form, e.g.
+//! `after block 12,000,000`, for the lock args and the inputs alike.
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use ckb_jsonrpc_types as json;
+use ckb_sdk::{
+    types::{ScriptGroup, Since, SinceType},
+    CkbRpcClient,
+};
+use ckb_types::{
+    core::{EpochNumberWithFraction, HeaderView, TransactionView},
+    packed::CellInput,
+    prelude::*,
+    H256,
+};
+
+use crate::error::Error;
+
+const SINCE_VALUE_BITS: u64 = 56;
+const SINCE_VALUE_MASK: u64 = 0x00ff_ffff_ffff_ffff;
+const SINCE_RELATIVE_FLAG: u64 = 0b1000_0000;
+const SINCE_METRIC_MASK: u64 = 0b0110_0000;
+const SINCE_EPOCH_FRACTION_FLAG: u64 = 0b0010_0000;
+
+/// Compare the values of two since with the same flags, the way the contract
+/// does: epochs, absolute or relative, are compared as fractions.
+pub fn since_value_cmp(flags: u64, a: u64, b: u64) -> Ordering {
+    if flags & SINCE_METRIC_MASK == SINCE_EPOCH_FRACTION_FLAG {
+        let a = EpochNumberWithFraction::from_full_value(a);
+        let b = EpochNumberWithFraction::from_full_value(b);
+        a.number().cmp(&b.number()).then_with(|| {
+            let lhs = u128::from(a.index()) * u128::from(b.length());
+            let rhs = u128::from(b.index()) * u128::from(a.length());
+            lhs.cmp(&rhs)
+        })
+    } else {
+        a.cmp(&b)
+    }
+}
+
+/// The since of an input satisfying both the lock args since and the since
+/// `input` asked by other scripts, 0 when none of them is required. Both
+/// must be valid as in `SinceSpec::from_value`, and have the same flags.
+pub fn merge_since(lock_since: Option<u64>, input: u64) -> Result<u64, Error> {
+    let lock_since = match lock_since {
+        None | Some(0) => return Ok(input),
+        Some(since) => since,
+    };
+    SinceSpec::from_value(lock_since)?;
+    if input == 0 {
+        return Ok(lock_since);
+    }
+    SinceSpec::from_value(input)?;
+    let flags = lock_since >> SINCE_VALUE_BITS;
+    if flags & SINCE_RELATIVE_FLAG != (input >> SINCE_VALUE_BITS) & SINCE_RELATIVE_FLAG {
+        return Err(Error::InvalidSince(format!(
+            "since 0x{:016x} and the since 0x{:016x} of the lock args aren't both relative or \
+             absolute",
+            input, lock_since
+        )));
+    }
+    if flags != input >> SINCE_VALUE_BITS {
+        return Err(Error::InvalidSince(format!(
+            "since 0x{:016x} conflicts with the since 0x{:016x} of the lock args",
+            input, lock_since
+        )));
+    }
+    let order = since_value_cmp(
+        flags,
+        input & SINCE_VALUE_MASK,
+        lock_since & SINCE_VALUE_MASK,
+    );
+    Ok(if order == Ordering::Less {
+        lock_since
+    } else {
+        input
+    })
+}
+
+/// Update the since of the script group inputs so the lock args since is
+/// satisfied, must be done before signing since it changes the tx hash.
+pub fn apply_since(
+    tx: &TransactionView,
+    script_group: &ScriptGroup,
+    lock_since: Option<u64>,
+) -> Result<TransactionView, Error> {
+    let mut inputs: Vec<CellInput> = tx.inputs().into_iter().collect();
+    for index in &script_group.input_indices {
+        let input = inputs
+            .get(*index)
+            .ok_or_else(|| Error::InvalidSince(format!("missing input #{}", index)))?;
+        let since = merge_since(lock_since, input.since().unpack())?;
+        inputs[*index] = input.clone().as_builder().since(since).build();
+    }
+    Ok(tx.as_advanced_builder().set_inputs(inputs).build())
+}
+
+/// The blocks the median time is taken over, as in CKB.
+pub const MEDIAN_TIME_BLOCK_COUNT: usize = 37;
+
+/// The median timestamp in milliseconds of `headers`, the median time the
+/// lock proves from header deps when its config is `with_header_time`: the
+/// last `MEDIAN_TIME_BLOCK_COUNT` of them by number, which must be
+/// consecutive blocks.
+pub fn median_time(headers: &[HeaderView]) -> Result<u64, Error> {
+    let mut headers = headers.to_vec();
+    if headers.len() < MEDIAN_TIME_BLOCK_COUNT {
+        return Err(Error::InvalidSince(format!(
+            "{} headers, the median time needs {}",
+            headers.len(),
+            MEDIAN_TIME_BLOCK_COUNT
+        )));
+    }
+    headers.sort_by_key(|header| header.number());
+    let headers = &headers[headers.len() - MEDIAN_TIME_BLOCK_COUNT..];
+    if let Some(pair) = headers
+        .windows(2)
+        .find(|pair| pair[1].parent_hash() != pair[0].hash())
+    {
+        return Err(Error::InvalidSince(format!(
+            "block #{} isn't the parent of block #{}",
+            pair[0].number(),
+            pair[1].number()
+        )));
+    }
+    let mut timestamps: Vec<u64> = headers.iter().map(|header| header.timestamp()).collect();
+    timestamps.sort_unstable();
+    Ok(timestamps[MEDIAN_TIME_BLOCK_COUNT / 2])
+}
+
+/// Add the header deps of a config `with_header_time`: the last
+/// `MEDIAN_TIME_BLOCK_COUNT` blocks up to the tip, and for a relative since
+/// `commit_blocks`, the blocks committing the group inputs. Returns the
+/// transaction and the median time it proves, in milliseconds.
+pub fn add_median_time_headers(
+    client: &CkbRpcClient,
+    tx: &TransactionView,
+    commit_blocks: &[H256],
+) -> Result<(TransactionView, u64), Error> {
+    let tip: u64 = client.get_tip_block_number()?.into();
+    let first = (tip + 1).saturating_sub(MEDIAN_TIME_BLOCK_COUNT as u64);
+    let mut headers = Vec::with_capacity(MEDIAN_TIME_BLOCK_COUNT);
+    for number in first..=tip {
+        let header: json::HeaderView = client
+            .get_header_by_number(number.into())?
+            .ok_or_else(|| Error::Rpc(format!("block #{} not found", number)))?;
+        headers.push(HeaderView::from(header));
+    }
+    let median = median_time(&headers)?;
+    let mut deps: Vec<_> = tx.header_deps().into_iter().collect();
+    for hash in headers
+        .iter()
+        .map(|header| header.hash())
+        .chain(commit_blocks.iter().map(|hash| hash.pack()))
+    {
+        if !deps.contains(&hash) {
+            deps.push(hash);
+        }
+    }
+    Ok((
+        tx.as_advanced_builder().set_header_deps(deps).build(),
+        median,
+    ))
+}
+
+const MAX_EPOCH_NUMBER: u64 = 0xff_ffff;
+const MAX_EPOCH_LENGTH: u64 = 0xffff;
+const SECONDS_PER_DAY: u64 = 86_400;
+const TIME_UNITS: [(&str, u64); 5] = [
+    ("week", 7 * SECONDS_PER_DAY),
+    ("day", SECONDS_PER_DAY),
+    ("hour", 3600),
+    ("minute", 60),
+    ("second", 1),
+];
+
+/// A since in readable form. The textual form is:
+///
+/// ```text
+/// after block 12,000,000         absolute block number
+/// after epoch 180 + 1/2          absolute epoch, the fraction is optional
+/// after 2024-06-01               absolute time in UTC, 2024-06-01T12:00:00Z
+///                                and 2024-06-01 12:00:00 are accepted too
+/// after timestamp 1717200000     absolute time in unix seconds
+/// after 1000 blocks              relative to the block committing the cell
+/// after 180 epochs               relative, 180 + 1/2 epochs is accepted too
+/// after 30 days                  relative, in weeks, days, hours, minutes or
+///                                seconds
+/// ```
+///
+/// Time is compared with the median time of the previous 37 blocks, which
+/// lags about 6 minutes behind the wall clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SinceSpec {
+    BlockNumber {
+        number: u64,
+        relative: bool,
+    },
+    Epoch {
+        number: u64,
+        index: u64,
+        length: u64,
+        relative: bool,
+    },
+    /// Unix seconds when absolute.
+    Timestamp {
+        seconds: u64,
+        relative: bool,
+    },
+}
+
+impl SinceSpec {
+    /// The raw since value.
+    pub fn value(&self) -> u64 {
+        let (ty, value, relative) = match *self {
+            SinceSpec::BlockNumber { number, relative } => {
+                (SinceType::BlockNumber, number, relative)
+            }
+            SinceSpec::Epoch {
+                number,
+                index,
+                length,
+                relative,
+            } => (
+                SinceType::EpochNumberWithFraction,
+                EpochNumberWithFraction::new_unchecked(number, index, length).full_value(),
+                relative,
+            ),
+            SinceSpec::Timestamp { seconds, relative } => (SinceType::Timestamp, seconds, relative),
+        };
+        Since::new(ty, value, relative).value()
+    }
+
+    /// Decode a raw since value, refusing the ones no block can satisfy.
+    pub fn from_value(since: u64) -> Result<Self, Error> {
+        let raw = Since::from_raw_value(since);
+        let metric = if raw.flags_is_valid() {
+            raw.extract_metric()
+        } else {
+            None
+        };
+        let relative = raw.is_relative();
+        let spec = match metric {
+            Some((SinceType::BlockNumber, number)) => SinceSpec::BlockNumber { number, relative },
+            Some((SinceType::EpochNumberWithFraction, value)) => {
+                let epoch = EpochNumberWithFraction::from_full_value(value);
+                SinceSpec::Epoch {
+                    number: epoch.number(),
+                    index: epoch.index(),
+                    length: epoch.length(),
+                    relative,
+                }
+            }
+            Some((SinceType::Timestamp, seconds)) => SinceSpec::Timestamp { seconds, relative },
+            None => {
+                return Err(Error::InvalidSince(format!(
+                    "invalid flags of since 0x{:016x}",
+                    since
+                )))
+            }
+        };
+        spec.check()?;
+        Ok(spec)
+    }
+
+    pub fn is_relative(&self) -> bool {
+        match *self {
+            SinceSpec::BlockNumber { relative, .. }
+            | SinceSpec::Epoch { relative, .. }
+            | SinceSpec::Timestamp { relative, .. } => relative,
+        }
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        match *self {
+            SinceSpec::BlockNumber { number: value, .. }
+            | SinceSpec::Timestamp { seconds: value, .. }
+                if value > SINCE_VALUE_MASK =>
+            {
+                Err(Error::InvalidSince(format!("{} overflows 56 bits", value)))
+            }
+            SinceSpec::Epoch {
+                number,
+                index,
+                length,
+                ..
+            } => {
+                if number > MAX_EPOCH_NUMBER || length > MAX_EPOCH_LENGTH {
+                    Err(Error::InvalidSince(format!(
+                        "epoch {} + {}/{} out of range",
+                        number, index, length
+                    )))
+                } else if index >= length && !(index == 0 && length == 0) {
+                    Err(Error::InvalidSince(format!(
+                        "epoch fraction {}/{} is not less than 1",
+                        index, length
+                    )))
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for SinceSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let words: Vec<&str> = lower.split_whitespace().collect();
+        let invalid = || Error::InvalidSince(format!("can't parse `{}`", s));
+        let words = match words.split_first() {
+            Some((&"after", rest)) => rest,
+            _ => return Err(invalid()),
+        };
+        let spec = match words {
+            ["block", number] => SinceSpec::BlockNumber {
+                number: parse_number(number)?,
+                relative: false,
+            },
+            ["epoch", number] => SinceSpec::Epoch {
+                number: parse_number(number)?,
+                index: 0,
+                length: 1,
+                relative: false,
+            },
+            ["epoch", number, "+", fraction] => {
+                let (index, length) = parse_fraction(fraction)?;
+                SinceSpec::Epoch {
+                    number: parse_number(number)?,
+                    index,
+                    length,
+                    relative: false,
+                }
+            }
+            ["timestamp", seconds] => SinceSpec::Timestamp {
+                seconds: parse_number(seconds)?,
+                relative: false,
+            },
+            [number, "block" | "blocks"] => SinceSpec::BlockNumber {
+                number: parse_number(number)?,
+                relative: true,
+            },
+            [number, "epoch" | "epochs"] => SinceSpec::Epoch {
+                number: parse_number(number)?,
+                index: 0,
+                length: 1,
+                relative: true,
+            },
+            [number, "+", fraction, "epoch" | "epochs"] => {
+                let (index, length) = parse_fraction(fraction)?;
+                SinceSpec::Epoch {
+                    number: parse_number(number)?,
+                    index,
+                    length,
+                    relative: true,
+                }
+            }
+            [count, unit] if time_unit(unit).is_some() => SinceSpec::Timestamp {
+                seconds: parse_number(count)?
+                    .checked_mul(time_unit(unit).unwrap_or(1))
+                    .ok_or_else(invalid)?,
+                relative: true,
+            },
+            [date] => SinceSpec::Timestamp {
+                seconds: parse_datetime(date, None).ok_or_else(invalid)?,
+                relative: false,
+            },
+            [date, time] => SinceSpec::Timestamp {
+                seconds: parse_datetime(date, Some(time)).ok_or_else(invalid)?,
+                relative: false,
+            },
+            _ => return Err(invalid()),
+        };
+        spec.check()?;
+        Ok(spec)
+    }
+}
+
+impl fmt::Display for SinceSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SinceSpec::BlockNumber {
+                number,
+                relative: false,
+            } => write!(f, "after block {}", number),
+            SinceSpec::BlockNumber {
+                number,
+                relative: true,
+            } => write!(f, "after {} {}", number, plural("block", number)),
+            SinceSpec::Epoch {
+                number,
+                index,
+                length,
+                relative,
+            } => {
+                let epoch = if index == 0 {
+                    number.to_string()
+                } else {
+                    format!("{} + {}/{}", number, index, length)
+                };
+                if relative {
+                    let count = if index == 0 { number } else { 2 };
+                    write!(f, "after {} {}", epoch, plural("epoch", count))
+                } else {
+                    write!(f, "after epoch {}", epoch)
+                }
+            }
+            SinceSpec::Timestamp {
+                seconds,
+                relative: false,
+            } => write!(f, "after {}", format_datetime(seconds)),
+            SinceSpec::Timestamp {
+                seconds,
+                relative: true,
+            } => {
+                let (unit, size) = TIME_UNITS
+                    .iter()
+                    .find(|(_, size)| seconds % size == 0)
+                    .copied()
+                    .unwrap_or(("second", 1));
+                let count = seconds / size;
+                write!(f, "after {} {}", count, plural(unit, count))
+            }
+        }
+    }
+}
+
+/// Raw since value of a readable since, see `SinceSpec`.
+pub fn parse_since(s: &str) -> Result<u64, Error> {
+    s.parse::<SinceSpec>().map(|spec| spec.value())
+}
+
+/// Readable form of a raw since value, see `SinceSpec`.
+pub fn format_since(since: u64) -> String {
+    match SinceSpec::from_value(since) {
+        Ok(spec) => spec.to_string(),
+        Err(_) => format!("invalid since 0x{:016x}", since),
+    }
+}
+
+fn plural(word: &str, count: u64) -> String {
+    if count == 1 {
+        word.to_string()
+    } else {
+        format!("{}s", word)
+    }
+}
+
+fn time_unit(unit: &str) -> Option<u64> {
+    let unit = unit.strip_suffix('s').unwrap_or(unit);
+    TIME_UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, size)| *size)
+}
+
+/// Digits with optional `,` or `_` separators.
+fn parse_number(s: &str) -> Result<u64, Error> {
+    let digits: String = s.chars().filter(|c| *c != ',' && *c != '_').collect();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Error::InvalidSince(format!("invalid number `{}`", s)));
+    }
+    digits
+        .parse()
+        .map_err(|_| Error::InvalidSince(format!("{} overflows 64 bits", s)))
+}
+
+fn parse_fraction(s: &str) -> Result<(u64, u64), Error> {
+    let (index, length) = s
+        .split_once('/')
+        .ok_or_else(|| Error::InvalidSince(format!("invalid epoch fraction `{}`", s)))?;
+    Ok((parse_number(index)?, parse_number(length)?))
+}
+
+/// `YYYY-MM-DD` with an optional `HH:MM:SS` time, either as a separate word
+/// or joined by `t` and ended by `z`, all in UTC.
+fn parse_datetime(date: &str, time: Option<&str>) -> Option<u64> {
+    let (date, time) = match (date.split_once('t'), time) {
+        (Some((date, time)), None) => (date, Some(time.strip_suffix('z').unwrap_or(time))),
+        (None, time) => (date, time),
+        _ => return None,
+    };
+    let mut fields = date.splitn(3, '-').map(|f| f.parse::<i64>().ok());
+    let (year, month, day) = (fields.next()??, fields.next()??, fields.next()??);
+    let days = days_from_civil(year, month, day);
+    if days < 0 || civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    let seconds = match time {
+        Some(time) => {
+            let mut fields = time.splitn(3, ':').map(|f| f.parse::<u64>().ok());
+            let (hour, minute, second) = (fields.next()??, fields.next()??, fields.next()??);
+            if hour > 23 || minute > 59 || second > 59 {
+                return None;
+            }
+            hour * 3600 + minute * 60 + second
+        }
+        None => 0,
+    };
+    Some(days as u64 * SECONDS_PER_DAY + seconds)
+}
+
+fn format_datetime(seconds: u64) -> String {
+    let (year, month, day) = civil_from_days((seconds / SECONDS_PER_DAY) as i64);
+    let time = seconds % SECONDS_PER_DAY;
+    if time == 0 {
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    } else {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            time / 3600,
+            time % 3600 / 60,
+            time % 60
+        )
+    }
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date, from
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = (if days >= 0 { days } else { days - 146_096 }) / 146_097;
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,91 @@
+//! A vault successor: a reduced quorum allowed to move the funds of a lock
+//! into the single cell of a type only, e.g. the type id of the vault
+//! succeeding it, so that automated rebalancing never needs the full quorum
+//! nor can send the funds anywhere else.
+//!
+//! The lock args end with `SUCCESSOR_FLAG` set in the flags byte, then the
+//! multisig hash of the reduced quorum and the type hash. The full quorum
+//! keeps its powers, the lock field says which one signed.
+//!
+//! A type id keeps the successor cell unique but doesn't choose its lock, so
+//! the successor cell is spent along and its lock kept by the output: the
+//! reduced quorum can neither make up a cell of the type nor change who
+//! holds it.
+//!
+//! Mirrors `check_successor` in `ckb-multisig/src/entry.rs`.
+
+use ckb_types::{core::TransactionView, packed::CellOutput, prelude::*};
+
+use crate::{
+    constants::{BLAKE160_SIZE, DIGEST_SIZE},
+    error::Error,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Successor {
+    /// `MultisigConfig::hash160` of the reduced quorum.
+    pub multisig_hash: [u8; BLAKE160_SIZE],
+    /// The type script hash of the successor cell.
+    pub type_hash: [u8; DIGEST_SIZE],
+}
+
+impl Successor {
+    /// Check what the reduced quorum signs before it signs, as the script
+    /// will: the cells of the group, `group` indexing `inputs`, the cells
+    /// spent by `tx`, are plain, and they go with the single input of the
+    /// successor type into its single output, under the lock of that input,
+    /// the fee being paid by the other inputs.
+    pub fn check_move(
+        &self,
+        tx: &TransactionView,
+        inputs: &[CellOutput],
+        group: &[usize],
+    ) -> Result<(), Error> {
+        let invalid = |reason: String| Error::InvalidParameter(format!("successor {}", reason));
+        let is_successor = |cell: &CellOutput| {
+            cell.type_()
+                .to_opt()
+                .is_some_and(|script| script.calc_script_hash().as_slice() == self.type_hash)
+        };
+        let capacity = |cell: &CellOutput| -> u64 { cell.capacity().unpack() };
+
+        let mut moved: u64 = 0;
+        for index in group {
+            let cell = inputs
+                .get(*index)
+                .ok_or_else(|| invalid(format!("input #{} is unknown", index)))?;
+            if cell.type_().is_some() {
+                return Err(invalid(format!("input #{} has a type script", index)));
+            }
+            moved = moved
+                .checked_add(capacity(cell))
+                .ok_or_else(|| invalid("capacity overflow".to_string()))?;
+        }
+        let spent: Vec<_> = inputs.iter().filter(|cell| is_successor(cell)).collect();
+        let spent = match spent.as_slice() {
+            [cell] => cell,
+            _ => return Err(invalid(format!("{} inputs of the type", spent.len()))),
+        };
+        moved = moved
+            .checked_add(capacity(spent))
+            .ok_or_else(|| invalid("capacity overflow".to_string()))?;
+
+        let outputs: Vec<_> = tx
+            .outputs()
+            .into_iter()
+            .filter(|cell| is_successor(cell))
+            .collect();
+        match outputs.as_slice() {
+            [output] if output.lock() != spent.lock() => {
+                Err(invalid("output is under another lock".to_string()))
+            }
+            [output] if capacity(output) >= moved => Ok(()),
+            [output] => Err(invalid(format!(
+                "output holds {} shannons out of {}",
+                capacity(output),
+                moved
+            ))),
+            _ => Err(invalid(format!("{} outputs of the type", outputs.len()))),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Consolidation of many small cells of a config into a few large ones.
+//!
+//! The cells are packed into as few transactions as the size limit allows,
+//! each transaction merges its inputs into a single output and pays its fee
+//! from them. Every transaction is returned as a signing request for the
+//! cosigners.
+
+use ckb_sdk::{traits::LiveCell, types::ScriptGroup};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, TransactionBuilder, TransactionView},
+    packed::{CellDep, CellInput, CellOutput, Script},
+    prelude::*,
+};
+
+use crate::{
+    config::MultisigConfig,
+    error::Error,
+    fee::{witness_size, FeeEstimator},
+    request::SigningRequest,
+    since::apply_since,
+    witness::set_spend_all_total,
+};
+
+/// Below the 597_000 bytes block size limit, with room for the block header
+/// and other transactions.
+pub const DEFAULT_MAX_TX_SIZE: u64 = 500_000;
+
+/// Serialized size of one more input, only the first input of the group has
+/// a witness.
+const INPUT_SIZE: u64 = 44;
+
+pub struct SweepBuilder {
+    config: MultisigConfig,
+    lock_script: Script,
+    cell_deps: Vec<CellDep>,
+    fee_rate: u64,
+    receiver: Option<Script>,
+    max_tx_size: u64,
+    max_inputs: Option<usize>,
+}
+
+impl SweepBuilder {
+    /// `lock_script` is the lock of the config cells, `cell_deps` are the
+    /// cell deps needed to run it and `fee_rate` is in shannons per 1000
+    /// bytes.
+    pub fn new(
+        config: MultisigConfig,
+        lock_script: Script,
+        cell_deps: Vec<CellDep>,
+        fee_rate: u64,
+    ) -> Self {
+        SweepBuilder {
+            config,
+            lock_script,
+            cell_deps,
+            fee_rate,
+            receiver: None,
+            max_tx_size: DEFAULT_MAX_TX_SIZE,
+            max_inputs: None,
+        }
+    }
+
+    /// Send the merged cells to another lock, the config lock by default.
+    pub fn receiver(mut self, receiver: Script) -> Self {
+        self.receiver = Some(receiver);
+        self
+    }
+
+    /// Size limit of each transaction once signed.
+    pub fn max_tx_size(mut self, max_tx_size: u64) -> Self {
+        self.max_tx_size = max_tx_size;
+        self
+    }
+
+    pub fn max_inputs(mut self, max_inputs: usize) -> Self {
+        self.max_inputs = Some(max_inputs);
+        self
+    }
+
+    /// Build the sweep transactions of `cells`, e.g. `CellSet::spendable`.
+    /// A config `with_spend_all` needs them all in a single transaction,
+    /// which declares their count.
+    ///
+    /// A last chunk left with a single cell is not swept, there is nothing
+    /// to merge it with.
+    pub fn build(&self, cells: &[LiveCell]) -> Result<Vec<SigningRequest>, Error> {
+        let base_size = self.tx(&[]).data().as_reader().serialized_size_in_block() as u64
+            + witness_size(&self.config) as u64
+            + INPUT_SIZE;
+        let per_tx = self
+            .max_tx_size
+            .checked_sub(base_size)
+            .map_or(0, |room| (room / INPUT_SIZE + 1) as usize);
+        let per_tx = self.max_inputs.map_or(per_tx, |max| max.min(per_tx));
+        if per_tx < 2 {
+            return Err(Error::InvalidParameter(
+                "the sweep transactions can't hold two inputs".to_string(),
+            ));
+        }
+        if self.config.spend_all() && cells.len() > per_tx {
+            return Err(Error::InvalidParameter(format!(
+                "the config spends its {} cells at once, a transaction holds {}",
+                cells.len(),
+                per_tx
+            )));
+        }
+        cells
+            .chunks(per_tx)
+            .filter(|chunk| chunk.len() > 1)
+            .map(|chunk| self.build_one(chunk))
+            .collect()
+    }
+
+    fn tx(&self, cells: &[LiveCell]) -> TransactionView {
+        let output = CellOutput::new_builder()
+            .lock(
+                self.receiver
+                    .clone()
+                    .unwrap_or_else(|| self.lock_script.clone()),
+            )
+            .build();
+        TransactionBuilder::default()
+            .cell_deps(self.cell_deps.clone())
+            .inputs(
+                cells
+                    .iter()
+                    .map(|cell| CellInput::new(cell.out_point.clone(), 0)),
+            )
+            .output(output)
+            .output_data(Bytes::new().pack())
+            .build()
+    }
+
+    fn build_one(&self, cells: &[LiveCell]) -> Result<SigningRequest, Error> {
+        if let Some(cell) = cells
+            .iter()
+            .find(|cell| cell.output.lock() != self.lock_script)
+        {
+            return Err(Error::InvalidParameter(format!(
+                "cell {} is not guarded by the config",
+                cell.out_point
+            )));
+        }
+        let mut script_group = ScriptGroup::from_lock_script(&self.lock_script);
+        script_group.input_indices = (0..cells.len()).collect();
+        let mut tx = apply_since(&self.tx(cells), &script_group, self.config.since())?;
+        if self.config.spend_all() {
+            tx = set_spend_all_total(&tx, 0, cells.len() as u32)?;
+        }
+        let fee = FeeEstimator::new(self.fee_rate)
+            .estimate(&tx, &[(script_group.clone(), self.config.clone())], None)?
+            .fee;
+        let total: u64 = cells
+            .iter()
+            .map(|cell| Unpack::<u64>::unpack(&cell.output.capacity()))
+            .sum();
+        let output = tx.output(0).expect("sweep output");
+        let occupied = output
+            .occupied_capacity(Capacity::zero())
+            .expect("occupied capacity")
+            .as_u64();
+        if total < occupied + fee {
+            return Err(Error::InsufficientCapacity(format!(
+                "{} shannons of {} cells can't pay {} for the output and {} fee",
+                total,
+                cells.len(),
+                occupied,
+                fee
+            )));
+        }
+        let output = output.as_builder().capacity(total - fee).build();
+        let tx = tx.as_advanced_builder().set_outputs(vec![output]).build();
+        Ok(SigningRequest {
+            config: self.config.clone(),
+            tx,
+            script_group,
+            fee,
+            genesis_hash: None,
+        })
+    }
+}
@@ -0,0 +1,129 @@
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType, TransactionBuilder},
+    packed::{CellOutput, Script},
+    prelude::*,
+    H256,
+};
+
+use super::{lock_script, random_config, random_signer};
+use crate::{
+    approval::{Approval, APPROVAL, MAINNET_GENESIS_HASH, TESTNET_GENESIS_HASH},
+    config::KeyFormat,
+    nonce::{nonce_data, NonceScript},
+    MultisigLock, Signer,
+};
+
+fn nonce_type() -> Script {
+    NonceScript::new(H256([0x24; 32]), ScriptHashType::Data1).type_script(&[3; 32])
+}
+
+fn output(lock: Script, capacity: u64, type_: Option<Script>) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock)
+        .type_(type_.pack())
+        .build()
+}
+
+#[test]
+fn test_approval() {
+    let (signers, config) = random_config(3, 1, 2);
+    let nonce_type = nonce_type();
+    let nonce_type_hash: [u8; 32] = nonce_type.calc_script_hash().unpack();
+    let outputs = vec![(
+        output(lock_script(&config), 500, None),
+        Bytes::from_static(b"data"),
+    )];
+    let mut approval = Approval::new(
+        config.clone(),
+        &outputs,
+        nonce_type_hash,
+        7,
+        1000,
+        &TESTNET_GENESIS_HASH,
+    );
+    assert!(approval.verify().is_err());
+
+    assert!(approval.sign(&random_signer()).is_err());
+    // the first key is required
+    approval.sign(&signers[1]).unwrap();
+    assert!(approval.sign(&signers[1]).is_err());
+    approval.sign(&signers[2]).unwrap();
+    assert!(approval.verify().is_err());
+
+    let mut approval = Approval::new(
+        config.clone(),
+        &outputs,
+        nonce_type_hash,
+        7,
+        1000,
+        &TESTNET_GENESIS_HASH,
+    );
+    approval.sign(&signers[0]).unwrap();
+    approval.sign(&signers[2]).unwrap();
+    approval.verify().unwrap();
+    let other_nonce = Approval {
+        nonce: 8,
+        ..approval.clone()
+    };
+    assert!(other_nonce.verify().is_err());
+    // testnet signatures don't replay on the mainnet
+    let mainnet = Approval {
+        genesis_hash: MAINNET_GENESIS_HASH.0,
+        ..approval.clone()
+    };
+    assert!(mainnet.verify().is_err());
+
+    let lock = approval.to_bytes();
+    assert_eq!(lock[0], APPROVAL);
+    // never the head of a multisig lock field
+    assert!(KeyFormat::from_u8(APPROVAL).is_err());
+    assert!(MultisigLock::parse(&lock).is_err());
+    assert_eq!(&lock[1..33], &nonce_type_hash);
+    assert_eq!(&lock[33..65], TESTNET_GENESIS_HASH.as_bytes());
+    assert_eq!(&lock[65..73], &1000u64.to_le_bytes());
+    assert_eq!(&lock[73..], &approval.lock.to_bytes()[..]);
+    assert_eq!(approval.header_dep(), TESTNET_GENESIS_HASH.pack());
+    assert_eq!(
+        approval
+            .lock
+            .signed_identities(&approval.message())
+            .unwrap(),
+        vec![
+            signers[0].identity().unwrap(),
+            signers[2].identity().unwrap()
+        ]
+    );
+}
+
+#[test]
+fn test_approval_matches() {
+    let (_, config) = random_config(2, 0, 2);
+    let nonce_type = nonce_type();
+    let nonce_type_hash: [u8; 32] = nonce_type.calc_script_hash().unpack();
+    let payment = output(lock_script(&config), 500, None);
+    let approval = Approval::new(
+        config.clone(),
+        &[(payment.clone(), Bytes::new())],
+        nonce_type_hash,
+        0,
+        1000,
+        &MAINNET_GENESIS_HASH,
+    );
+
+    let tx = TransactionBuilder::default()
+        .output(output(lock_script(&config), 200, Some(nonce_type)))
+        .output_data(nonce_data(1).pack())
+        .output(payment.clone())
+        .output_data(Bytes::new().pack())
+        .build();
+    assert!(approval.matches(&tx));
+
+    let tx = tx
+        .as_advanced_builder()
+        .output(payment)
+        .output_data(Bytes::new().pack())
+        .build();
+    assert!(!approval.matches(&tx));
+}
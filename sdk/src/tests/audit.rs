@@ -0,0 +1,90 @@
+use super::{random_config, random_digest, random_signer};
+use crate::{
+    audit::{parse_log, verify_log, Approval, AuditEntry, AuditLog},
+    SecpSigner, Signer,
+};
+
+fn approval(signer: &SecpSigner, lock_args: &[u8]) -> Approval {
+    let digest = random_digest();
+    Approval {
+        proposal: random_digest(),
+        lock_args: lock_args.to_vec(),
+        digest,
+        signature: signer.sign(&digest).unwrap(),
+        signer: signer.identity().unwrap(),
+        timestamp: 1_718_000_000,
+    }
+}
+
+fn temp_log() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!(
+        "ckb-multisig-audit-{}.jsonl",
+        hex::encode(random_digest())
+    ))
+}
+
+#[test]
+fn test_append_and_verify() {
+    let (signers, config) = random_config(3, 0, 2);
+    let lock_args = config.lock_args();
+    let path = temp_log();
+    let mut log = AuditLog::open(&path).unwrap();
+    for signer in &signers[..2] {
+        log.append(approval(signer, &lock_args)).unwrap();
+    }
+    let entries = log.entries().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].prev_hash, entries[0].hash());
+    assert_eq!(log.last_hash(), entries[1].hash());
+    verify_log(&entries).unwrap();
+    for entry in &entries {
+        entry.check_member(&config).unwrap();
+    }
+    let (_, other) = random_config(2, 0, 1);
+    assert!(entries[0].check_member(&other).is_err());
+
+    // reopened, the chain goes on
+    let mut log = AuditLog::open(&path).unwrap();
+    let entry = log.append(approval(&signers[2], &lock_args)).unwrap();
+    assert_eq!(entry.seq, 2);
+    assert_eq!(entry.prev_hash, entries[1].hash());
+    verify_log(&log.entries().unwrap()).unwrap();
+
+    // a signature by someone else than the signer is never written
+    let mut forged = approval(&signers[0], &lock_args);
+    forged.signer = random_signer().identity().unwrap();
+    assert!(log.append(forged).is_err());
+    assert_eq!(log.entries().unwrap().len(), 3);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_tampering() {
+    let signers: Vec<_> = (0..3).map(|_| random_signer()).collect();
+    let mut entries = Vec::new();
+    let mut prev_hash = [0u8; 32];
+    for (seq, signer) in signers.iter().enumerate() {
+        let entry = AuditEntry {
+            seq: seq as u64,
+            prev_hash,
+            approval: approval(signer, &[1u8; 20]),
+        };
+        prev_hash = entry.hash();
+        entries.push(entry);
+    }
+    let lines: Vec<_> = entries.iter().map(AuditEntry::to_json).collect();
+    assert_eq!(parse_log(&lines.join("\n")).unwrap(), entries);
+    verify_log(&entries).unwrap();
+
+    // an edited line no longer matches its hash
+    let edited = lines[1].replace("1718000000", "1718000001");
+    assert!(AuditEntry::from_json(&edited).is_err());
+    // removed or reordered entries break the chain
+    assert!(verify_log(&[entries[0].clone(), entries[2].clone()]).is_err());
+    assert!(verify_log(&[entries[1].clone(), entries[0].clone()]).is_err());
+    // a consistent rewrite still needs valid signatures
+    let mut rewritten = entries[0].clone();
+    rewritten.approval.timestamp += 1;
+    rewritten.approval.digest = random_digest();
+    assert!(verify_log(&[rewritten]).is_err());
+}
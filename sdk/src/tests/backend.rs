@@ -0,0 +1,64 @@
+use ckb_types::{core::ScriptHashType, H256};
+use serde_json::{json, Value};
+
+use super::{light_client::serve, random_config, CODE_HASH};
+use crate::{
+    backend::{BackendKind, NodeIndexer, RichIndexer},
+    history::History,
+};
+
+fn indexer_tip(block_number: u64) -> Value {
+    json!({
+        "block_hash": H256([0x1b; 32]),
+        "block_number": format!("{:#x}", block_number),
+    })
+}
+
+#[test]
+fn test_backend_kind_names() {
+    for kind in [
+        BackendKind::Indexer,
+        BackendKind::RichIndexer,
+        BackendKind::LightClient,
+    ] {
+        assert_eq!(kind.to_string().parse::<BackendKind>().unwrap(), kind);
+    }
+    assert_eq!(BackendKind::default(), BackendKind::Indexer);
+    assert_eq!(
+        "rich-indexer".parse::<BackendKind>().unwrap(),
+        BackendKind::RichIndexer
+    );
+    assert!("full-node".parse::<BackendKind>().is_err());
+}
+
+#[test]
+fn test_rich_indexer_matches_the_lock_exactly() {
+    let (_, config) = random_config(3, 0, 2);
+    let page = json!({ "objects": [], "last_cursor": "0x" });
+
+    let (url, handle) = serve(vec![indexer_tip(100), page.clone()]);
+    let history = History::with_backend(
+        Box::new(RichIndexer::new(&url)),
+        &config,
+        &CODE_HASH,
+        ScriptHashType::Data1,
+    );
+    assert!(history.entries().unwrap().is_empty());
+    let calls = handle.join().unwrap();
+    assert_eq!(calls[0]["method"], "get_indexer_tip");
+    assert_eq!(calls[1]["method"], "get_transactions");
+    assert_eq!(calls[1]["params"][0]["script_search_mode"], "exact");
+    assert_eq!(calls[1]["params"][0]["group_by_transaction"], true);
+
+    // by prefix on the indexer of a node, its default
+    let (url, handle) = serve(vec![indexer_tip(100), page]);
+    let history = History::with_backend(
+        Box::new(NodeIndexer::new(&url)),
+        &config,
+        &CODE_HASH,
+        ScriptHashType::Data1,
+    );
+    assert!(history.entries().unwrap().is_empty());
+    let calls = handle.join().unwrap();
+    assert!(calls[1]["params"][0]["script_search_mode"].is_null());
+}
@@ -0,0 +1,98 @@
+use ckb_sdk::traits::LiveCell;
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, EpochNumberWithFraction},
+    packed::{CellOutput, OutPoint, Script},
+    prelude::*,
+};
+
+use crate::{
+    balance::{udt_amount, Amounts, Balance},
+    scanner::{BlockInfo, CellSet, Maturity, MultisigCell, TypeFilter},
+};
+
+fn udt_script(byte: u8) -> Script {
+    Script::new_builder()
+        .code_hash([byte; 32].pack())
+        .args(Bytes::from(vec![byte; 32]).pack())
+        .build()
+}
+
+fn cell(capacity: u64, type_: Option<Script>, data: &[u8], maturity: Maturity) -> MultisigCell {
+    MultisigCell {
+        cell: LiveCell {
+            output: CellOutput::new_builder()
+                .capacity(Capacity::shannons(capacity).pack())
+                .type_(type_.pack())
+                .build(),
+            output_data: Bytes::copy_from_slice(data),
+            out_point: OutPoint::default(),
+            block_number: 0,
+            tx_index: 0,
+        },
+        maturity,
+    }
+}
+
+#[test]
+fn test_balance() {
+    let tip = BlockInfo {
+        number: 100,
+        epoch: EpochNumberWithFraction::new(1, 0, 1),
+        timestamp: 0,
+    };
+    let udt = udt_script(1);
+    let mut amount = 500u128.to_le_bytes().to_vec();
+    amount.extend_from_slice(b"extension");
+    let set = CellSet {
+        tip,
+        cells: vec![
+            cell(100, None, &[], Maturity::Mature),
+            cell(200, None, &[], Maturity::BlockNumber(200)),
+            cell(300, Some(udt.clone()), &amount, Maturity::Mature),
+            cell(
+                400,
+                Some(udt),
+                &7u128.to_le_bytes(),
+                Maturity::Unsatisfiable,
+            ),
+            // typed, but too short for an amount
+            cell(50, Some(udt_script(2)), &[1, 2], Maturity::Mature),
+        ],
+    };
+    let balance = Balance::from(&set);
+    assert_eq!(balance.cells, 5);
+    assert_eq!(
+        balance.capacity,
+        Amounts {
+            total: 1050,
+            spendable: 450,
+            locked: 600,
+        }
+    );
+    assert_eq!(
+        balance.udt,
+        Amounts {
+            total: 507,
+            spendable: 500,
+            locked: 7,
+        }
+    );
+    assert_eq!(balance.capacity.total, set.total_capacity());
+    assert_eq!(balance.capacity.spendable, set.mature_capacity());
+    assert_eq!(udt_amount(&set.cells[0]), None);
+    assert_eq!(udt_amount(&set.cells[4]), None);
+}
+
+#[test]
+fn test_type_filter() {
+    let plain = cell(100, None, &[], Maturity::Mature).cell.output;
+    let udt = cell(100, Some(udt_script(1)), &[], Maturity::Mature)
+        .cell
+        .output;
+    assert!(TypeFilter::Any.matches(&plain) && TypeFilter::Any.matches(&udt));
+    assert!(TypeFilter::NoType.matches(&plain) && !TypeFilter::NoType.matches(&udt));
+    assert!(TypeFilter::Script(udt_script(1)).matches(&udt));
+    assert!(!TypeFilter::Script(udt_script(1)).matches(&plain));
+    assert!(!TypeFilter::Script(udt_script(2)).matches(&udt));
+}
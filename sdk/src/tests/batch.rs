@@ -0,0 +1,195 @@
+use ckb_sdk::{traits::LiveCell, Address, AddressPayload, NetworkType};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType},
+    packed::{CellOutput, Script},
+    prelude::*,
+    H256,
+};
+
+use super::{capacity, live_cell, lock_script, random_config};
+use crate::{
+    batch::{parse_ckb, parse_csv, BatchTransfer, Payment},
+    error::Error,
+    fee::FeeEstimator,
+};
+
+const CKB: u64 = 100_000_000;
+
+fn recipient(byte: u8) -> Script {
+    Script::new_builder()
+        .code_hash(H256([byte; 32]).pack())
+        .hash_type(ScriptHashType::Type)
+        .args(Bytes::from(vec![byte; 20]).pack())
+        .build()
+}
+
+fn cells(lock: &Script, capacities: &[u64]) -> Vec<LiveCell> {
+    capacities
+        .iter()
+        .map(|capacity| {
+            let output = CellOutput::new_builder()
+                .capacity(Capacity::shannons(*capacity).pack())
+                .lock(lock.clone())
+                .build();
+            live_cell(output, Bytes::new())
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_ckb() {
+    assert_eq!(parse_ckb("1000").unwrap(), 1000 * CKB);
+    assert_eq!(parse_ckb("61.5").unwrap(), 61 * CKB + CKB / 2);
+    assert_eq!(parse_ckb("0.00000001").unwrap(), 1);
+    for invalid in [
+        "",
+        ".5",
+        "1.000000001",
+        "-1",
+        "1e3",
+        "1,000",
+        "184467440738",
+    ] {
+        assert!(parse_ckb(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_parse_csv() {
+    let address = |byte| {
+        Address::new(
+            NetworkType::Testnet,
+            AddressPayload::from(recipient(byte)),
+            true,
+        )
+        .to_string()
+    };
+    let csv = format!(
+        "address,amount\n# grants\n{},1000\n\n {} , 61.5 \n",
+        address(1),
+        address(2)
+    );
+    let payments = parse_csv(&csv, Some(NetworkType::Testnet)).unwrap();
+    assert_eq!(
+        payments,
+        vec![
+            Payment {
+                to: recipient(1),
+                capacity: 1000 * CKB,
+            },
+            Payment {
+                to: recipient(2),
+                capacity: 61 * CKB + CKB / 2,
+            },
+        ]
+    );
+    let err = parse_csv(&csv, Some(NetworkType::Mainnet)).unwrap_err();
+    assert!(err.to_string().contains("line 3"), "{}", err);
+    let err = parse_csv(&format!("{},abc", address(1)), None).unwrap_err();
+    assert!(err.to_string().contains("line 1"), "{}", err);
+    assert!(parse_csv("ckb1invalid,100", None).is_err());
+    assert!(parse_csv(&address(1), None).is_err());
+}
+
+#[test]
+fn test_batches() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let cells = cells(&lock, &[1000 * CKB; 8]);
+    let payments: Vec<_> = (0..25)
+        .map(|i| Payment {
+            to: recipient(i),
+            capacity: 100 * CKB + u64::from(i),
+        })
+        .collect();
+    let requests = BatchTransfer::new(config.clone(), lock.clone(), vec![], 1000)
+        .max_outputs(10)
+        .build(payments.clone(), &cells)
+        .unwrap();
+    assert_eq!(requests.len(), 3);
+
+    let mut paid = Vec::new();
+    let mut used = 0;
+    for request in &requests {
+        let tx = &request.tx;
+        let outputs: Vec<_> = tx.outputs().into_iter().collect();
+        let (change, payouts) = outputs.split_last().unwrap();
+        assert!(payouts.len() <= 10);
+        assert_eq!(change.lock(), lock);
+        paid.extend(payouts.iter().map(|output| Payment {
+            to: output.lock(),
+            capacity: capacity(output),
+        }));
+
+        let inputs: Vec<_> = cells[used..used + tx.inputs().len()].to_vec();
+        used += inputs.len();
+        let total: u64 = inputs.iter().map(|cell| capacity(&cell.output)).sum();
+        let spent: u64 = outputs.iter().map(capacity).sum();
+        assert_eq!(total, spent + request.fee);
+        let estimate = FeeEstimator::new(1000)
+            .estimate(tx, &[(request.script_group.clone(), config.clone())], None)
+            .unwrap();
+        assert_eq!(estimate.fee, request.fee);
+    }
+    assert_eq!(paid, payments);
+
+    // a size limit splits the batches further
+    let requests = BatchTransfer::new(config.clone(), lock.clone(), vec![], 1000)
+        .max_tx_size(1000)
+        .build(payments.clone(), &cells)
+        .unwrap();
+    assert!(requests.len() > 3);
+    for request in &requests {
+        let estimate = FeeEstimator::new(1000)
+            .estimate(
+                &request.tx,
+                &[(request.script_group.clone(), config.clone())],
+                None,
+            )
+            .unwrap();
+        assert!(estimate.size <= 1000);
+    }
+}
+
+#[test]
+fn test_change_and_errors() {
+    let (_, config) = random_config(2, 0, 1);
+    let lock = lock_script(&config);
+    let builder = BatchTransfer::new(config.clone(), lock.clone(), vec![], 1000);
+    let pay = |capacity| Payment {
+        to: recipient(1),
+        capacity,
+    };
+
+    // too little left for a change cell, the rest goes to the fee
+    let cells = cells(&lock, &[200 * CKB]);
+    let requests = builder.build(vec![pay(199 * CKB)], &cells).unwrap();
+    assert_eq!(requests[0].tx.outputs().len(), 1);
+    assert_eq!(requests[0].fee, CKB);
+
+    assert!(builder.build(vec![pay(200 * CKB)], &cells).is_err());
+    // below the capacity of a cell
+    assert!(builder.build(vec![pay(10 * CKB)], &cells).is_err());
+    // the cells must be of the config
+    let (_, other) = random_config(2, 0, 1);
+    let foreign = self::cells(&lock_script(&other), &[200 * CKB]);
+    assert!(builder.build(vec![pay(100 * CKB)], &foreign).is_err());
+    assert!(builder.build(vec![], &cells).unwrap().is_empty());
+}
+
+#[test]
+fn test_capacity_overflow() {
+    let (_, config) = random_config(2, 0, 1);
+    let lock = lock_script(&config);
+    let builder = BatchTransfer::new(config.clone(), lock.clone(), vec![], 1000);
+    let payments = (1..=2).map(|byte| Payment {
+        to: recipient(byte),
+        capacity: u64::MAX / 2 + 1,
+    });
+    let err = builder
+        .build(payments, &cells(&lock, &[u64::MAX]))
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidParameter(_)));
+    assert!(err.to_string().contains("payment #2 overflows"));
+}
@@ -0,0 +1,210 @@
+use ckb_sdk::traits::LiveCell;
+use ckb_types::{
+    bytes::Bytes,
+    core::Capacity,
+    packed::{CellOutput, Script},
+    prelude::*,
+};
+
+use super::{capacity, live_cell, lock_script, random_config};
+use crate::{
+    bump::{Change, FeeBumper},
+    fee::FeeEstimator,
+    request::SigningRequest,
+    sweep::SweepBuilder,
+    Signer,
+};
+
+fn cell(lock: &Script, capacity: u64) -> LiveCell {
+    let output = CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock.clone())
+        .build();
+    live_cell(output, Bytes::new())
+}
+
+/// A fully signed sweep of `capacities`.
+fn signed_sweep(capacities: &[u64]) -> SigningRequest {
+    let (signers, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let cells: Vec<_> = capacities.iter().map(|c| cell(&lock, *c)).collect();
+    let mut request = SweepBuilder::new(config, lock, vec![], 1000)
+        .build(&cells)
+        .unwrap()
+        .remove(0);
+    let message = request.message().unwrap();
+    for signer in &signers[..2] {
+        request
+            .add_signature(signer.sign(&message).unwrap())
+            .unwrap();
+    }
+    assert!(request.is_complete().unwrap());
+    request
+}
+
+#[test]
+fn test_bump_change_output() {
+    let request = signed_sweep(&[100_0000_0000, 200_0000_0000]);
+    let bump = FeeBumper::new(5000).bump(&request, &[]).unwrap();
+    let bumped = &bump.request;
+
+    // same inputs, outputs and data, only the change capacity moved
+    assert_eq!(
+        bumped.tx.inputs().as_slice(),
+        request.tx.inputs().as_slice()
+    );
+    assert_eq!(bumped.tx.outputs().len(), request.tx.outputs().len());
+    assert_eq!(bumped.script_group, request.script_group);
+    assert!(bumped.fee > request.fee);
+    let from = capacity(&request.tx.output(0).unwrap());
+    let to = capacity(&bumped.tx.output(0).unwrap());
+    assert_eq!(from - to, bumped.fee - request.fee);
+    assert_eq!(bump.changes, vec![Change::Output { index: 0, from, to }]);
+
+    // unsigned, and the fee pays the new rate
+    assert!(bumped.signed().unwrap().is_empty());
+    assert_ne!(bumped.message().unwrap(), request.message().unwrap());
+    let estimate = FeeEstimator::new(5000)
+        .estimate(
+            &bumped.tx,
+            &[(bumped.script_group.clone(), bumped.config.clone())],
+            None,
+        )
+        .unwrap();
+    assert_eq!(estimate.fee, bumped.fee);
+}
+
+#[test]
+fn test_bump_min_rbf_rate() {
+    let request = signed_sweep(&[100_0000_0000, 200_0000_0000]);
+    // the same rate still has to pay the RBF increment
+    let bump = FeeBumper::new(1000).bump(&request, &[]).unwrap();
+    let size = FeeEstimator::new(1000)
+        .estimate(
+            &bump.request.tx,
+            &[(
+                bump.request.script_group.clone(),
+                bump.request.config.clone(),
+            )],
+            None,
+        )
+        .unwrap()
+        .size;
+    assert_eq!(bump.request.fee, request.fee + size * 1500 / 1000);
+
+    let bump = FeeBumper::new(1000)
+        .min_rbf_rate(3000)
+        .bump(&request, &[])
+        .unwrap();
+    assert_eq!(bump.request.fee, request.fee + size * 3000 / 1000);
+}
+
+#[test]
+fn test_bump_extra_input() {
+    // the sweep output is left with just above its occupied capacity
+    let (_, config) = random_config(3, 0, 2);
+    let occupied = CellOutput::new_builder()
+        .lock(lock_script(&config))
+        .build()
+        .occupied_capacity(Capacity::zero())
+        .unwrap()
+        .as_u64();
+    let request = signed_sweep(&[occupied / 2, occupied / 2 + 2000]);
+    let lock = request.script_group.script.clone();
+    let extra = cell(&lock, 100_0000_0000);
+
+    let err = FeeBumper::new(100_000).bump(&request, &[]).unwrap_err();
+    assert!(err.to_string().contains("insufficient capacity"));
+    let err = FeeBumper::new(100_000)
+        .bump(&request, &[cell(&Script::default(), 100_0000_0000)])
+        .unwrap_err();
+    assert!(err.to_string().contains("not guarded by the config"));
+
+    let bump = FeeBumper::new(100_000)
+        .bump(&request, std::slice::from_ref(&extra))
+        .unwrap();
+    let bumped = &bump.request;
+    assert_eq!(bumped.tx.inputs().len(), 3);
+    assert_eq!(bumped.script_group.input_indices, vec![0, 1, 2]);
+    let to = capacity(&bumped.tx.output(0).unwrap());
+    assert_eq!(
+        bump.changes,
+        vec![
+            Change::Output {
+                index: 0,
+                from: capacity(&request.tx.output(0).unwrap()),
+                to,
+            },
+            Change::Input {
+                out_point: extra.out_point.clone(),
+                capacity: 100_0000_0000,
+            },
+        ]
+    );
+    assert_eq!(to + bumped.fee, occupied + 2000 + 100_0000_0000);
+}
+
+#[test]
+fn test_bump_without_change() {
+    let request = signed_sweep(&[100_0000_0000, 200_0000_0000]);
+    let tx = request
+        .tx
+        .as_advanced_builder()
+        .set_outputs(vec![CellOutput::new_builder()
+            .capacity(request.tx.output(0).unwrap().capacity())
+            .build()])
+        .build();
+    let request = SigningRequest { tx, ..request };
+    assert!(FeeBumper::new(5000).bump(&request, &[]).is_err());
+    assert!(FeeBumper::new(5000)
+        .change_index(1)
+        .bump(&request, &[])
+        .is_err());
+    // an explicit change output of another lock
+    assert!(FeeBumper::new(5000)
+        .change_index(0)
+        .bump(&request, &[])
+        .is_ok());
+}
+
+#[test]
+fn test_bump_change_data() {
+    // data the change output holds, which its capacity no longer covers
+    let request = signed_sweep(&[100_0000_0000, 200_0000_0000]);
+    let tx = request
+        .tx
+        .as_advanced_builder()
+        .set_outputs_data(vec![Bytes::from(vec![0; 300]).pack()])
+        .build();
+    let request = SigningRequest { tx, ..request };
+    let err = FeeBumper::new(5000).bump(&request, &[]).unwrap_err();
+    assert!(err.to_string().contains("insufficient capacity"));
+
+    let lock = request.script_group.script.clone();
+    let bump = FeeBumper::new(5000)
+        .bump(&request, &[cell(&lock, 100_0000_0000)])
+        .unwrap();
+    let change = bump.request.tx.output(0).unwrap();
+    let occupied = change
+        .occupied_capacity(Capacity::bytes(300).unwrap())
+        .unwrap()
+        .as_u64();
+    assert!(capacity(&change) >= occupied);
+}
+
+#[test]
+fn test_bump_capacity_overflow() {
+    let (_, config) = random_config(3, 0, 2);
+    let occupied = CellOutput::new_builder()
+        .lock(lock_script(&config))
+        .build()
+        .occupied_capacity(Capacity::zero())
+        .unwrap()
+        .as_u64();
+    let request = signed_sweep(&[occupied / 2, occupied / 2 + 2000]);
+    let lock = request.script_group.script.clone();
+    let err = FeeBumper::new(100_000)
+        .bump(&request, &[cell(&lock, u64::MAX)])
+        .unwrap_err();
+    assert!(err.to_string().contains("overflows"));
+}
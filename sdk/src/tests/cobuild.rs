@@ -0,0 +1,73 @@
+use ckb_sdk::NetworkType;
+use ckb_types::{
+    bytes::Bytes,
+    core::Capacity,
+    packed::{CellOutput, Script},
+    prelude::*,
+};
+
+use super::{gen_tx, lock_script, random_config, CODE_HASH};
+use crate::cobuild::{check_message, message, signing_message_hash, Transfer};
+
+fn receiver() -> Script {
+    Script::new_builder()
+        .code_hash(CODE_HASH.pack())
+        .args(Bytes::from(vec![7u8; 20]).pack())
+        .build()
+}
+
+#[test]
+fn test_transfers() {
+    let (_, config) = random_config(3, 0, 2);
+    let (tx, _) = gen_tx(&config, 2);
+    let tx = tx
+        .as_advanced_builder()
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::shannons(100_050_000_000).pack())
+                .lock(receiver())
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .build();
+    // the change output back to the config is not a transfer
+    let transfers = Transfer::from_tx(&tx, &lock_script(&config));
+    assert_eq!(transfers.len(), 1);
+    let line = transfers[0].describe(NetworkType::Testnet);
+    assert!(line.starts_with("send 1,000.5 CKB to ckt1"), "{}", line);
+}
+
+#[test]
+fn test_check_message() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let (tx, _) = gen_tx(&config, 1);
+    let msg = message(&config, &tx, &lock);
+    check_message(&config, &tx, &lock, &msg).unwrap();
+
+    let other = tx
+        .as_advanced_builder()
+        .output(CellOutput::new_builder().lock(receiver()).build())
+        .output_data(Bytes::new().pack())
+        .build();
+    assert!(check_message(&config, &other, &lock, &msg).is_err());
+}
+
+#[test]
+fn test_signing_message_hash() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let (tx, _) = gen_tx(&config, 1);
+    let msg = message(&config, &tx, &lock);
+    let inputs = vec![
+        (
+            CellOutput::new_builder().lock(lock.clone()).build(),
+            Bytes::new()
+        );
+        tx.inputs().len()
+    ];
+    let with = signing_message_hash(&tx, &inputs, Some(&msg)).unwrap();
+    let without = signing_message_hash(&tx, &inputs, None).unwrap();
+    assert_ne!(with, without);
+    assert!(signing_message_hash(&tx, &inputs[1..], None).is_err());
+}
@@ -0,0 +1,183 @@
+use std::sync::{Arc, Mutex};
+
+use ckb_sdk::traits::{CellCollector, CellCollectorError, CellQueryOptions, LiveCell};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, EpochNumberWithFraction, ScriptHashType},
+    packed::{CellOutput, OutPoint, Script, Transaction},
+    prelude::*,
+};
+
+use super::{capacity, live_cell, lock_script, random_config, CODE_HASH};
+use crate::{
+    collector::{lock_args_since, BlockInfoProvider, MaturedCellCollector},
+    error::Error,
+    scanner::{BlockInfo, Maturity},
+};
+
+const RELATIVE: u64 = 0x8000_0000_0000_0000;
+
+/// Returns its cells whatever the query, in order.
+#[derive(Clone, Default)]
+struct StaticCollector {
+    cells: Vec<LiveCell>,
+    locked: Arc<Mutex<Vec<OutPoint>>>,
+    queries: Arc<Mutex<Vec<u64>>>,
+}
+
+#[async_trait::async_trait]
+impl CellCollector for StaticCollector {
+    async fn collect_live_cells_async(
+        &mut self,
+        query: &CellQueryOptions,
+        _apply_changes: bool,
+    ) -> Result<(Vec<LiveCell>, u64), CellCollectorError> {
+        self.queries.lock().unwrap().push(query.min_total_capacity);
+        let total = self.cells.iter().map(|cell| capacity(&cell.output)).sum();
+        Ok((self.cells.clone(), total))
+    }
+
+    fn lock_cell(&mut self, out_point: OutPoint, _tip: u64) -> Result<(), CellCollectorError> {
+        self.locked.lock().unwrap().push(out_point);
+        Ok(())
+    }
+
+    fn apply_tx(&mut self, _tx: Transaction, _tip: u64) -> Result<(), CellCollectorError> {
+        Ok(())
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// Block `n` is at timestamp `n` seconds, the tip is block 100.
+struct Chain;
+
+impl BlockInfoProvider for Chain {
+    fn tip(&self) -> Result<BlockInfo, Error> {
+        self.block(100)
+    }
+
+    fn block(&self, number: u64) -> Result<BlockInfo, Error> {
+        Ok(BlockInfo {
+            number,
+            epoch: EpochNumberWithFraction::new(number / 10, number % 10, 10),
+            timestamp: number * 1000,
+        })
+    }
+}
+
+/// A cell of `lock` created in block `block_number`.
+fn cell_at(lock: &Script, capacity: u64, block_number: u64) -> LiveCell {
+    let output = CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock.clone())
+        .build();
+    LiveCell {
+        out_point: OutPoint::new([block_number as u8; 32].pack(), 0),
+        block_number,
+        ..live_cell(output, Bytes::new())
+    }
+}
+
+fn matured_collector(
+    lock: &Script,
+    blocks: &[u64],
+) -> (MaturedCellCollector<StaticCollector>, StaticCollector) {
+    let inner = StaticCollector {
+        cells: blocks.iter().map(|n| cell_at(lock, 1000, *n)).collect(),
+        ..StaticCollector::default()
+    };
+    let collector =
+        MaturedCellCollector::new(inner.clone(), Chain, &CODE_HASH, ScriptHashType::Data1);
+    (collector, inner)
+}
+
+#[test]
+fn test_lock_args_since() {
+    let (_, config) = random_config(2, 0, 1);
+    assert_eq!(lock_args_since(&config.lock_args()), None);
+    let config = config.with_since(Some(RELATIVE | 20));
+    assert_eq!(lock_args_since(&config.lock_args()), Some(RELATIVE | 20));
+}
+
+#[test]
+fn test_relative_since_skips_young_cells() {
+    let (_, config) = random_config(2, 0, 1);
+    let config = config.with_since(Some(RELATIVE | 20));
+    let lock = lock_script(&config);
+    // cells committed at blocks 50, 90 and 70, mature up to block 80
+    let (mut collector, inner) = matured_collector(&lock, &[50, 90, 70]);
+    let mut query = CellQueryOptions::new_lock(lock);
+    query.min_total_capacity = 5000;
+    let (cells, total) = collector.collect_live_cells(&query, true).unwrap();
+    assert_eq!(
+        cells.iter().map(|c| c.block_number).collect::<Vec<_>>(),
+        vec![50, 70]
+    );
+    assert_eq!(total, 2000);
+    // the inner collector is asked for every cell, without locking them
+    assert_eq!(*inner.queries.lock().unwrap(), vec![u64::MAX]);
+    assert_eq!(
+        *inner.locked.lock().unwrap(),
+        cells
+            .iter()
+            .map(|c| c.out_point.clone())
+            .collect::<Vec<_>>()
+    );
+    let immature = collector.immature();
+    assert_eq!(immature.len(), 1);
+    assert_eq!(immature[0].cell.block_number, 90);
+    assert_eq!(immature[0].maturity, Maturity::BlockNumber(110));
+}
+
+#[test]
+fn test_collect_stops_at_min_capacity() {
+    let (_, config) = random_config(2, 0, 1);
+    let config = config.with_since(Some(RELATIVE | 20));
+    let lock = lock_script(&config);
+    let (mut collector, inner) = matured_collector(&lock, &[10, 20, 95, 30]);
+    let mut query = CellQueryOptions::new_lock(lock);
+    query.min_total_capacity = 1500;
+    let (cells, total) = collector.collect_live_cells(&query, false).unwrap();
+    assert_eq!(cells.len(), 2);
+    assert_eq!(total, 2000);
+    assert!(inner.locked.lock().unwrap().is_empty());
+    // the immature cells after the selection are still reported
+    assert_eq!(collector.immature().len(), 1);
+}
+
+#[test]
+fn test_absolute_since_locks_every_cell() {
+    let (_, config) = random_config(2, 0, 1);
+    let config = config.with_since(Some(200));
+    let lock = lock_script(&config);
+    let (mut collector, _) = matured_collector(&lock, &[10, 20]);
+    let mut query = CellQueryOptions::new_lock(lock);
+    query.min_total_capacity = u64::MAX;
+    let (cells, total) = collector.collect_live_cells(&query, false).unwrap();
+    assert!(cells.is_empty());
+    assert_eq!(total, 0);
+    assert_eq!(collector.immature().len(), 2);
+    assert_eq!(collector.immature()[0].maturity, Maturity::BlockNumber(200));
+}
+
+#[test]
+fn test_other_locks_pass_through() {
+    let (_, config) = random_config(2, 0, 1);
+    let other = config
+        .clone()
+        .with_since(Some(RELATIVE | 20))
+        .lock_script(&CODE_HASH, ScriptHashType::Type);
+    let (mut collector, inner) = matured_collector(&other, &[95]);
+    let query = CellQueryOptions::new_lock(other);
+    let (cells, _) = collector.collect_live_cells(&query, false).unwrap();
+    assert_eq!(cells.len(), 1);
+    assert_eq!(*inner.queries.lock().unwrap(), vec![1]);
+
+    // no since, nothing to honor
+    let lock = lock_script(&config);
+    let (mut collector, _) = matured_collector(&lock, &[95]);
+    let query = CellQueryOptions::new_lock(lock);
+    let (cells, _) = collector.collect_live_cells(&query, false).unwrap();
+    assert_eq!(cells.len(), 1);
+}
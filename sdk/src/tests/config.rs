@@ -0,0 +1,201 @@
+use ckb_types::{bytes::Bytes, packed, prelude::*};
+
+use super::{gen_tx, random_config, random_digest, random_signer};
+use crate::{
+    blake160,
+    config::{ConfigEncoding, KeyFormat, CONFIG_V2, HEADER_TIME_FLAG, STRICT_WITNESS_FLAG},
+    signer::Signer,
+    witness::stuffed_witnesses,
+    MultisigConfig, MultisigLock,
+};
+
+#[test]
+fn test_reject_invalid_config() {
+    let (_, config) = random_config(3, 1, 2);
+    let hashes = config.pubkey_hashes().to_vec();
+    assert!(MultisigConfig::new(vec![], 0, 1).is_err());
+    assert!(MultisigConfig::new(hashes.clone(), 0, 0).is_err());
+    assert!(MultisigConfig::new(hashes.clone(), 0, 4).is_err());
+    assert!(MultisigConfig::new(hashes.clone(), 3, 2).is_err());
+    let dup = vec![hashes[0], hashes[1], hashes[0]];
+    assert!(MultisigConfig::new(dup, 0, 2).is_err());
+}
+
+#[test]
+fn test_multisig_script_and_args() {
+    let (_, config) = random_config(3, 1, 2);
+    let script = config.multisig_script();
+    assert_eq!(&script[..4], &[0, 1, 2, 3]);
+    assert_eq!(script.len(), config.multisig_script_len());
+    assert_eq!(&script[4..24], &config.pubkey_hashes()[0]);
+    assert_eq!(
+        MultisigConfig::from_multisig_script(&script).unwrap(),
+        config
+    );
+
+    assert_eq!(config.lock_args().as_ref(), &blake160(&script));
+    let since = 0x2000_0000_0000_0100u64;
+    let config = config.with_since(Some(since));
+    assert_eq!(config.lock_args().len(), 28);
+    assert_eq!(&config.lock_args()[20..], &since.to_le_bytes());
+    assert_eq!(&config.lock_args()[..20], &blake160(&script));
+}
+
+#[test]
+fn test_placeholder_lock() {
+    let (_, config) = random_config(3, 0, 2);
+    let placeholder = config.placeholder_lock();
+    assert_eq!(placeholder.len(), config.multisig_script_len() + 2 * 65);
+    let lock = MultisigLock::parse(&placeholder).unwrap();
+    assert_eq!(lock.filled_count(), 0);
+    assert_eq!(lock.to_bytes(), placeholder);
+    assert!(MultisigLock::parse(&placeholder[..placeholder.len() - 1]).is_err());
+}
+
+#[test]
+fn test_fee_key_args() {
+    let (_, config) = random_config(3, 1, 2);
+    let fee_key = [7u8; 20];
+    let hash = blake160(&config.multisig_script());
+
+    let args = config.clone().with_fee_key(Some(fee_key)).lock_args();
+    assert_eq!(args.len(), 48);
+    assert_eq!(&args[..20], &hash);
+    assert_eq!(&args[20..28], &[0u8; 8]);
+    assert_eq!(&args[28..], &fee_key);
+
+    let since = 0x2000_0000_0000_0100u64;
+    let config = config.with_since(Some(since)).with_fee_key(Some(fee_key));
+    let args = config.lock_args();
+    assert_eq!(&args[20..28], &since.to_le_bytes());
+    assert_eq!(&args[28..], &fee_key);
+
+    // the flags byte comes last
+    let args = config.clone().with_header_time(true).lock_args();
+    assert_eq!(args.len(), 49);
+    assert_eq!(args[48], HEADER_TIME_FLAG);
+    let args = config
+        .with_fee_key(None)
+        .with_since(None)
+        .with_header_time(true)
+        .lock_args();
+    assert_eq!(&args[20..], &[0, 0, 0, 0, 0, 0, 0, 0, HEADER_TIME_FLAG]);
+}
+
+#[test]
+fn test_strict_witnesses() {
+    let (_, config) = random_config(3, 0, 2);
+    let strict = config.clone().with_strict_witnesses(true);
+    assert!(strict.strict_witnesses());
+    let args = strict.lock_args();
+    assert_eq!(&args[20..], &[0, 0, 0, 0, 0, 0, 0, 0, STRICT_WITNESS_FLAG]);
+    let args = strict.with_header_time(true).lock_args();
+    assert_eq!(args[28], HEADER_TIME_FLAG | STRICT_WITNESS_FLAG);
+
+    let (tx, group) = gen_tx(&config, 3);
+    let inputs = &group.input_indices;
+    assert!(stuffed_witnesses(&tx, inputs).is_empty());
+    // the first witness holds the signatures, the others must stay empty
+    let witness = |data: &[u8]| -> packed::Bytes { Bytes::copy_from_slice(data).pack() };
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![witness(&[1; 100]), witness(&[]), witness(&[0; 4])])
+        .build();
+    assert_eq!(stuffed_witnesses(&tx, inputs), vec![2]);
+}
+
+#[test]
+fn test_uncompressed_keys() {
+    let signers = [random_signer(), random_signer()];
+    let compressed = signers[0].identity().unwrap();
+    let uncompressed = blake160(&signers[1].pubkey().serialize_uncompressed());
+    let config = MultisigConfig::new(vec![compressed, uncompressed], 0, 2).unwrap();
+    assert_eq!(config.multisig_script()[0], 0);
+    let mut unknown = config.multisig_script().to_vec();
+    unknown[0] = 3;
+    assert!(MultisigConfig::from_multisig_script(&unknown).is_err());
+
+    // the key format is part of the multisig script, thus of the lock args
+    let either = config.clone().with_key_format(KeyFormat::Either);
+    let script = either.multisig_script();
+    assert_eq!(script[0], KeyFormat::Either as u8);
+    assert_ne!(either.lock_args(), config.lock_args());
+    assert_eq!(
+        MultisigConfig::from_multisig_script(&script).unwrap(),
+        either
+    );
+
+    let digest = random_digest();
+    let mut lock = MultisigLock::new(either.clone());
+    for signer in &signers {
+        lock.add_signature(signer.sign(&digest).unwrap()).unwrap();
+    }
+    assert_eq!(
+        lock.signed_identities(&digest).unwrap(),
+        vec![compressed, uncompressed]
+    );
+    lock.verify(&digest).unwrap();
+
+    // each format only accepts its own hashes
+    let mut lock = MultisigLock::new(config.clone());
+    for signer in &signers {
+        lock.add_signature(signer.sign(&digest).unwrap()).unwrap();
+    }
+    assert!(lock.verify(&digest).is_err());
+    let uncompressed_only = MultisigConfig::new(vec![uncompressed], 0, 1)
+        .unwrap()
+        .with_key_format(KeyFormat::Uncompressed);
+    assert_eq!(
+        uncompressed_only.identity(&signers[1].pubkey()),
+        uncompressed
+    );
+    let mut lock = MultisigLock::new(uncompressed_only);
+    lock.add_signature(signers[1].sign(&digest).unwrap())
+        .unwrap();
+    lock.verify(&digest).unwrap();
+}
+
+#[test]
+fn test_molecule_config() {
+    let (signers, config) = random_config(3, 1, 2);
+    let v2 = config.clone().with_encoding(ConfigEncoding::Molecule);
+    let molecule = v2.molecule_config();
+    assert_eq!(molecule.len(), 27 + 3 * 20);
+    // total size, then the offsets of the 4 fields
+    let header: Vec<u8> = [87u32, 20, 21, 22, 23]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+    assert_eq!(&molecule[..20], &header[..]);
+    assert_eq!(&molecule[20..27], &[0, 1, 2, 3, 0, 0, 0]);
+    assert_eq!(&molecule[27..47], &config.pubkey_hashes()[0]);
+
+    // the lock args commit to the molecule config
+    assert_eq!(v2.hash160(), blake160(&molecule));
+    assert_ne!(v2.lock_args(), config.lock_args());
+    let script = v2.multisig_script();
+    assert_eq!(script[0], CONFIG_V2);
+    assert_eq!(&script[1..], &molecule[..]);
+    assert_eq!(script.len(), v2.multisig_script_len());
+    assert_eq!(MultisigConfig::from_multisig_script(&script).unwrap(), v2);
+
+    let digest = random_digest();
+    let mut lock = MultisigLock::new(v2.clone());
+    for signer in &signers[..2] {
+        lock.add_signature(signer.sign(&digest).unwrap()).unwrap();
+    }
+    let lock = MultisigLock::parse(&lock.to_bytes()).unwrap();
+    assert_eq!(lock.config(), &v2);
+    lock.verify(&digest).unwrap();
+
+    // a single encoding is accepted
+    let mut offsets = molecule.to_vec();
+    offsets[4] = 24;
+    assert!(MultisigConfig::from_molecule_config(&offsets).is_err());
+    let mut extra = molecule.to_vec();
+    extra.push(0);
+    assert!(MultisigConfig::from_molecule_config(&extra).is_err());
+    let mut count = molecule.to_vec();
+    count[23] = 2;
+    assert!(MultisigConfig::from_molecule_config(&count).is_err());
+}
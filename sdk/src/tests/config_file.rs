@@ -0,0 +1,121 @@
+use super::random_config;
+use crate::{
+    blake160,
+    config::KeyFormat,
+    config_file::{Algorithm, ConfigFile, CONFIG_VERSION},
+    signer::{SecpSigner, Signer},
+};
+
+#[test]
+fn test_config_file_round_trip() {
+    let (_, config) = random_config(3, 1, 2);
+    let config = config.with_since(Some(0x2000_0100_0000_00b4));
+    let file = ConfigFile::from(&config);
+    assert_eq!(file.version, CONFIG_VERSION);
+    assert_eq!(file.since.as_deref(), Some("after epoch 180"));
+
+    let json = file.to_json().unwrap();
+    assert_eq!(ConfigFile::from_json(&json).unwrap(), file);
+    let toml = file.to_toml().unwrap();
+    assert_eq!(ConfigFile::from_toml(&toml).unwrap(), file);
+    assert_eq!(file.to_config().unwrap(), config);
+}
+
+#[test]
+fn test_config_file_toml() {
+    let signer = SecpSigner::from_slice(&[1u8; 32]).unwrap();
+    let pubkey = hex::encode(signer.pubkey().serialize());
+    let hash = hex::encode(signer.identity().unwrap());
+    let toml = format!(
+        r#"
+version = 1
+algorithm = "secp256k1-blake160"
+require_first_n = 0
+threshold = 1
+since = "0x8000000000000064"
+
+[[keys]]
+pubkey_hash = "0x{}"
+pubkey = "0x{}"
+label = "alice"
+"#,
+        hash, pubkey
+    );
+    let file = ConfigFile::from_toml(&toml).unwrap();
+    assert_eq!(file.labels(), vec![Some("alice")]);
+    let config = file.to_config().unwrap();
+    assert_eq!(config.since(), Some(0x8000_0000_0000_0064));
+    assert_eq!(config.pubkey_hashes(), &[signer.identity().unwrap()]);
+    // the raw since is written back readable
+    assert_eq!(
+        ConfigFile::from(&config).since.as_deref(),
+        Some("after 100 blocks")
+    );
+
+    let other = SecpSigner::from_slice(&[2u8; 32]).unwrap();
+    let mismatch = toml.replace(&pubkey, &hex::encode(other.pubkey().serialize()));
+    assert!(ConfigFile::from_toml(&mismatch)
+        .unwrap()
+        .to_config()
+        .is_err());
+    // a since with the reserved metric is never satisfied as meant
+    let reserved = toml.replace("0x8000000000000064", "0xe000000000000064");
+    assert!(ConfigFile::from_toml(&reserved)
+        .unwrap()
+        .to_config()
+        .is_err());
+}
+
+#[test]
+fn test_config_file_strict() {
+    let (_, config) = random_config(2, 0, 2);
+    let json = ConfigFile::from(&config).to_json().unwrap();
+    let unknown = json.replacen("\"version\"", "\"color\": \"red\",\n  \"version\"", 1);
+    assert!(ConfigFile::from_json(&unknown).is_err());
+    let newer = json.replacen("\"version\": 1", "\"version\": 2", 1);
+    assert!(ConfigFile::from_json(&newer).is_err());
+    let algorithm = json.replacen("secp256k1-blake160", "schnorr", 1);
+    assert!(ConfigFile::from_json(&algorithm).is_err());
+    let key_field = json.replacen(
+        "\"pubkey_hash\"",
+        "\"weight\": 2,\n      \"pubkey_hash\"",
+        1,
+    );
+    assert!(ConfigFile::from_json(&key_field).is_err());
+    assert!(ConfigFile::from_json(&json).is_ok());
+}
+
+#[test]
+fn test_config_file_uncompressed_keys() {
+    let signer = SecpSigner::from_slice(&[2u8; 32]).unwrap();
+    let pubkey = signer.pubkey().serialize_uncompressed();
+    let toml = format!(
+        r#"
+version = 1
+algorithm = "secp256k1-blake160-uncompressed"
+require_first_n = 0
+threshold = 1
+
+[[keys]]
+pubkey_hash = "0x{}"
+pubkey = "0x{}"
+"#,
+        hex::encode(blake160(&pubkey)),
+        hex::encode(pubkey)
+    );
+    let file = ConfigFile::from_toml(&toml).unwrap();
+    assert_eq!(file.algorithm, Algorithm::Secp256k1Blake160Uncompressed);
+    let config = file.to_config().unwrap();
+    assert_eq!(config.key_format(), KeyFormat::Uncompressed);
+    assert_eq!(ConfigFile::from(&config).algorithm, file.algorithm);
+
+    // the hash of the compressed key doesn't match in this format
+    let compressed = toml.replace(
+        &hex::encode(blake160(&pubkey)),
+        &hex::encode(signer.identity().unwrap()),
+    );
+    assert!(ConfigFile::from_toml(&compressed)
+        .unwrap()
+        .to_config()
+        .is_err());
+}
@@ -0,0 +1,80 @@
+use super::{random_config, random_digest};
+use crate::{
+    config::{ConfigEncoding, CONFIG_PROOF},
+    config_file::ConfigFile,
+    config_tree::{ConfigProof, ConfigTree},
+    signer::Signer,
+    MultisigConfig, MultisigLock,
+};
+
+#[test]
+fn test_config_tree() {
+    let (signers, ops) = random_config(3, 0, 2);
+    let (_, emergency) = random_config(5, 1, 3);
+    let (_, recovery) = random_config(2, 0, 1);
+    let recovery = recovery.with_encoding(ConfigEncoding::Molecule);
+    let tree = ConfigTree::new(vec![ops.clone(), emergency, recovery]).unwrap();
+
+    // every config of the tree shares its lock args
+    let args: Vec<_> = (0..3)
+        .map(|i| tree.config(i).unwrap().lock_args())
+        .collect();
+    assert_eq!(&args[0][..], &tree.root()[..]);
+    assert!(args.iter().all(|a| *a == args[0]));
+    assert_ne!(args[0], ops.lock_args());
+    assert!(tree.config(3).is_none());
+
+    // the odd config is carried up, its proof has a single sibling
+    let proofs: Vec<_> = (0..3)
+        .map(|i| tree.config(i).unwrap().proof().unwrap().clone())
+        .collect();
+    assert_eq!(proofs[0].encoded_len(), 3 + 2 * 32);
+    assert_eq!(proofs[2].encoded_len(), 3 + 32);
+    assert_eq!(proofs[2].to_bytes()[2], 0b1);
+    assert!(proofs[2].root(&ops) != tree.root());
+
+    // the proof heads the lock field, and is read back from it
+    let config = tree.config(0).unwrap();
+    let script = config.multisig_script();
+    assert_eq!(script[0], CONFIG_PROOF);
+    assert_eq!(script.len(), config.multisig_script_len());
+    assert_eq!(
+        MultisigConfig::from_multisig_script(&script).unwrap(),
+        config
+    );
+    let digest = random_digest();
+    let mut lock = MultisigLock::new(config.clone());
+    for signer in &signers[..2] {
+        lock.add_signature(signer.sign(&digest).unwrap()).unwrap();
+    }
+    let lock = MultisigLock::parse(&lock.to_bytes()).unwrap();
+    assert_eq!(lock.config().hash160(), tree.root());
+    lock.verify(&digest).unwrap();
+
+    let file = ConfigFile::from(&config);
+    assert!(file.proof.is_some());
+    assert_eq!(file.to_config().unwrap(), config);
+
+    // a single encoding of a proof
+    let mut stray = proofs[2].to_bytes();
+    stray[2] |= 0b10;
+    assert!(ConfigProof::parse(&stray).is_err());
+    let mut deep = proofs[0].to_bytes();
+    deep[1] = 9;
+    assert!(ConfigProof::parse(&deep).is_err());
+}
+
+#[test]
+fn test_reject_invalid_tree() {
+    let (_, config) = random_config(3, 0, 2);
+    assert!(ConfigTree::new(vec![]).is_err());
+    assert!(ConfigTree::new(vec![config.clone(), config.clone()]).is_err());
+    let (_, other) = random_config(3, 0, 2);
+    let timelocked = other.with_since(Some(0x2000_0000_0000_0100));
+    assert!(ConfigTree::new(vec![config.clone(), timelocked.clone()]).is_err());
+    // a tree of one config is its leaf alone
+    let tree = ConfigTree::new(vec![config.clone()]).unwrap();
+    let single = tree.config(0).unwrap();
+    assert_eq!(single.proof().unwrap().encoded_len(), 3);
+    assert_ne!(single.hash160(), config.hash160());
+}
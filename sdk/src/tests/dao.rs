@@ -0,0 +1,250 @@
+use ckb_jsonrpc_types as json;
+use ckb_sdk::{traits::LiveCell, types::ScriptGroup};
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        Capacity, EpochNumberWithFraction, HeaderBuilder, HeaderView, ScriptHashType,
+        TransactionBuilder, TransactionView,
+    },
+    packed::{CellInput, CellOutput, OutPoint, WitnessArgs},
+    prelude::*,
+};
+use serde_json::json;
+
+use super::{gen_tx, light_client::serve, lock_script, random_config, CODE_HASH};
+use crate::{
+    dao::{dao_type_script, DaoCell, DaoPhase, MultisigDao},
+    light_client::LightClient,
+    since::{apply_since, merge_since},
+};
+
+const RELATIVE: u64 = 0x8000_0000_0000_0000;
+const EPOCH: u64 = 0x2000_0000_0000_0000;
+
+fn epoch(number: u64, index: u64, length: u64) -> u64 {
+    EPOCH | EpochNumberWithFraction::new(number, index, length).full_value()
+}
+
+#[test]
+fn test_merge_since() {
+    assert_eq!(merge_since(None, 42).unwrap(), 42);
+    assert_eq!(merge_since(Some(100), 0).unwrap(), 100);
+    assert_eq!(merge_since(Some(100), 42).unwrap(), 100);
+    assert_eq!(merge_since(Some(100), 142).unwrap(), 142);
+    assert_eq!(
+        merge_since(Some(epoch(10, 1, 2)), epoch(10, 2, 3)).unwrap(),
+        epoch(10, 2, 3)
+    );
+    // 10 + 2/3 is later than 10 + 600/1000 although its value is smaller
+    assert_eq!(
+        merge_since(Some(epoch(10, 2, 3)), epoch(10, 600, 1000)).unwrap(),
+        epoch(10, 2, 3)
+    );
+    assert!(merge_since(Some(RELATIVE | 100), 100).is_err());
+    assert!(merge_since(Some(epoch(10, 0, 1)), 100).is_err());
+    // relative epochs are fractions too
+    assert_eq!(
+        merge_since(
+            Some(RELATIVE | epoch(10, 2, 3)),
+            RELATIVE | epoch(10, 600, 1000)
+        )
+        .unwrap(),
+        RELATIVE | epoch(10, 2, 3)
+    );
+    // reserved bits, reserved metric and fractions not less than 1
+    assert!(merge_since(Some(0x0100_0000_0000_0064), 0).is_err());
+    assert!(merge_since(Some(0x6000_0000_0000_0064), 0).is_err());
+    assert!(merge_since(Some(100), 0x0100_0000_0000_0064).is_err());
+    assert!(merge_since(Some(EPOCH | (1 << 40) | (2 << 24)), 0).is_err());
+}
+
+#[test]
+fn test_apply_since() {
+    let (_, config) = random_config(2, 0, 1);
+    let (tx, group) = gen_tx(&config, 2);
+    let tx = apply_since(&tx, &group, Some(RELATIVE | 5)).unwrap();
+    let since: Vec<u64> = tx
+        .inputs()
+        .into_iter()
+        .map(|i| i.since().unpack())
+        .collect();
+    // the last input belongs to another lock
+    assert_eq!(since, vec![RELATIVE | 5, RELATIVE | 5, 0]);
+    let missing = ScriptGroup {
+        input_indices: vec![5],
+        ..group
+    };
+    assert!(apply_since(&tx, &missing, Some(1)).is_err());
+}
+
+#[test]
+fn test_dao_builders() {
+    let (_, config) = random_config(3, 0, 2);
+    let dao = MultisigDao::new(
+        config.clone().with_since(Some(epoch(100, 0, 1))),
+        &CODE_HASH,
+        ScriptHashType::Data1,
+    );
+    let deposit = dao.deposit(&[100_000_000_000, 200_000_000_000]);
+    assert_eq!(deposit.receivers.len(), 2);
+    assert_eq!(&deposit.receivers[0].lock_script, dao.lock_script());
+
+    let out_point = OutPoint::new(Default::default(), 0);
+    let prepare = dao.prepare(vec![out_point.clone()]);
+    assert_eq!(
+        prepare.items[0].input,
+        CellInput::new(out_point.clone(), epoch(100, 0, 1))
+    );
+
+    let withdraw = dao
+        .withdraw(vec![out_point.clone(), out_point.clone()], None, Some(1000))
+        .unwrap();
+    let witness: WitnessArgs = withdraw.items[0].init_witness.clone().unwrap();
+    assert_eq!(
+        witness.lock().to_opt().unwrap().raw_data(),
+        config.placeholder_lock()
+    );
+    assert!(withdraw.items[1].init_witness.is_none());
+
+    let relative = MultisigDao::new(
+        config.with_since(Some(RELATIVE | 100)),
+        &CODE_HASH,
+        ScriptHashType::Data1,
+    );
+    assert!(relative.withdraw(vec![out_point], None, None).is_err());
+}
+
+/// A header at `epoch` whose DAO field holds the accumulate rate `ar`.
+fn header(number: u64, epoch: EpochNumberWithFraction, ar: u64) -> HeaderView {
+    let mut dao = [0u8; 32];
+    dao[8..16].copy_from_slice(&ar.to_le_bytes());
+    HeaderBuilder::default()
+        .number(number)
+        .epoch(epoch.full_value())
+        .dao(dao.pack())
+        .build()
+}
+
+fn dao_cell(capacity: u64, data: [u8; 8]) -> LiveCell {
+    let (_, config) = random_config(2, 0, 1);
+    LiveCell {
+        output: CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(lock_script(&config))
+            .type_(Some(dao_type_script()).pack())
+            .build(),
+        output_data: Bytes::from(data.to_vec()),
+        out_point: OutPoint::new(Default::default(), 0),
+        block_number: 20,
+        tx_index: 1,
+    }
+}
+
+#[test]
+fn test_dao_cell() {
+    const CKB: u64 = 100_000_000;
+    const AR: u64 = 10_000_000_000_000_000;
+    let deposit = header(10, EpochNumberWithFraction::new(5, 100, 1000), AR);
+    let tip = header(
+        5000,
+        EpochNumberWithFraction::new(190, 50, 1000),
+        AR + AR / 10,
+    );
+    let cell = DaoCell::new(dao_cell(1000 * CKB, [0; 8]), &deposit, &tip).unwrap();
+    assert_eq!(cell.phase, DaoPhase::Deposited);
+    assert_eq!(cell.capacity(), 1000 * CKB);
+    // the occupied capacity of the cell earns nothing
+    let occupied = dao_cell(0, [0; 8])
+        .output
+        .occupied_capacity(Capacity::bytes(8).unwrap())
+        .unwrap()
+        .as_u64();
+    assert_eq!(cell.compensation, (1000 * CKB - occupied) / 10);
+    // 185 epochs passed, rounded up to two lock periods
+    assert_eq!(
+        cell.claimable_epoch,
+        EpochNumberWithFraction::new(365, 100, 1000)
+    );
+    assert!(!cell.is_claimable(EpochNumberWithFraction::new(400, 0, 1)));
+
+    let prepared = DaoCell::new(dao_cell(1000 * CKB, 10u64.to_le_bytes()), &deposit, &tip).unwrap();
+    assert_eq!(prepared.phase, DaoPhase::Prepared);
+    assert!(!prepared.is_claimable(EpochNumberWithFraction::new(365, 99, 1000)));
+    assert!(prepared.is_claimable(EpochNumberWithFraction::new(365, 1, 10)));
+    assert!(prepared.is_claimable(EpochNumberWithFraction::new(366, 0, 1)));
+
+    let mut invalid = dao_cell(1000 * CKB, [0; 8]);
+    invalid.output_data = Bytes::from(vec![0; 4]);
+    assert!(DaoCell::new(invalid, &deposit, &tip).is_err());
+}
+
+#[test]
+fn test_dao_cells_on_light_client() {
+    const CKB: u64 = 100_000_000;
+    const AR: u64 = 10_000_000_000_000_000;
+    let (_, config) = random_config(2, 0, 1);
+    let dao = MultisigDao::new(config, &CODE_HASH, ScriptHashType::Data1);
+    let deposited = dao_cell(1000 * CKB, [0; 8]);
+    let deposit_tx = TransactionBuilder::default()
+        .output(deposited.output.clone())
+        .output_data(deposited.output_data.pack())
+        .build();
+    let prepare_tx = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(deposit_tx.hash(), 0), 0))
+        .output(deposited.output.clone())
+        .output_data(Bytes::from(10u64.to_le_bytes().to_vec()).pack())
+        .build();
+    let deposit = header(10, EpochNumberWithFraction::new(5, 100, 1000), AR);
+    let prepare = header(20, EpochNumberWithFraction::new(5, 110, 1000), AR + 1);
+    let tip = header(
+        5000,
+        EpochNumberWithFraction::new(190, 50, 1000),
+        AR + AR / 10,
+    );
+    let committed = |tx: &TransactionView, header: &HeaderView| {
+        json!({
+            "transaction": json::TransactionView::from(tx.clone()),
+            "tx_status": {
+                "status": "committed",
+                "block_hash": json::Byte32::from(header.hash()),
+            },
+        })
+    };
+    let prepared = LiveCell {
+        out_point: OutPoint::new(prepare_tx.hash(), 0),
+        ..dao_cell(1000 * CKB, 10u64.to_le_bytes())
+    };
+
+    // the light client looks the headers up by the transactions in them
+    let answers = |deposit: &HeaderView| {
+        vec![
+            json!(json::HeaderView::from(tip.clone())),
+            committed(&prepare_tx, &prepare),
+            json!(json::HeaderView::from(prepare.clone())),
+            committed(&prepare_tx, &prepare),
+            committed(&deposit_tx, deposit),
+            json!(json::HeaderView::from(deposit.clone())),
+        ]
+    };
+    let (url, handle) = serve(answers(&deposit));
+    let cells = dao
+        .cells(&LightClient::new(&url), vec![prepared.clone()])
+        .unwrap();
+    let expected = DaoCell::new(prepared.clone(), &deposit, &prepare).unwrap();
+    assert_eq!(cells.len(), 1);
+    assert_eq!(cells[0].phase, DaoPhase::Prepared);
+    assert_eq!(cells[0].compensation, expected.compensation);
+    assert_eq!(cells[0].claimable_epoch, expected.claimable_epoch);
+    let calls = handle.join().unwrap();
+    assert_eq!(calls[0]["method"], "get_tip_header");
+    assert_eq!(
+        calls[4]["params"][0],
+        json!(json::Byte32::from(deposit_tx.hash()))
+    );
+
+    // the deposit transaction committed in another block than recorded
+    let other = header(11, EpochNumberWithFraction::new(5, 101, 1000), AR);
+    let (url, handle) = serve(answers(&other));
+    assert!(dao.cells(&LightClient::new(&url), vec![prepared]).is_err());
+    handle.join().unwrap();
+}
@@ -0,0 +1,126 @@
+use ckb_sdk::{constants::TYPE_ID_CODE_HASH, traits::LiveCell};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, DepType, ScriptHashType, TransactionBuilder},
+    packed::{OutPoint, Script},
+    prelude::*,
+};
+
+use super::{gen_tx, lock_script, random_config};
+use crate::{
+    deploy::{deploy, deployment, type_id_script, upgrade, verify_binary},
+    deployment::{Network, Registry},
+    error::Error,
+    nonce::type_id,
+};
+
+#[test]
+fn test_deploy() {
+    let (_, config) = random_config(3, 0, 2);
+    let (tx, _) = gen_tx(&config, 2);
+    let binary = vec![0x7f; 1000];
+    let (tx, type_script) = deploy(&tx, lock_script(&config), &binary).unwrap();
+
+    let index = tx.outputs().len() - 1;
+    let output = tx.output(index).unwrap();
+    assert_eq!(output.type_().to_opt(), Some(type_script.clone()));
+    assert_eq!(type_script.code_hash(), TYPE_ID_CODE_HASH.pack());
+    let expected = type_id(&tx.inputs().get(0).unwrap(), index as u64);
+    assert_eq!(type_script, type_id_script(&expected));
+    assert_eq!(tx.outputs_data().get(index).unwrap().raw_data(), binary);
+    let occupied = output
+        .occupied_capacity(Capacity::bytes(binary.len()).unwrap())
+        .unwrap();
+    assert_eq!(output.capacity(), occupied.pack());
+
+    let deployed = deployment(&tx, index as u32).unwrap();
+    assert_eq!(deployed.code_hash, type_script.calc_script_hash().unpack());
+    assert_eq!(deployed.hash_type, ScriptHashType::Type);
+    assert_eq!(deployed.cell_dep.dep_type(), DepType::Code.into());
+    assert_eq!(
+        deployed.cell_dep.out_point(),
+        OutPoint::new(tx.hash(), index as u32)
+    );
+    assert!(matches!(
+        deployment(&tx, 0),
+        Err(Error::InvalidParameter(_))
+    ));
+
+    // recorded into the registry format
+    let mut registry = Registry::new();
+    registry.set(Network::Devnet, deployed.clone());
+    let registry = Registry::from_toml(&registry.to_toml().unwrap()).unwrap();
+    assert_eq!(registry.get(Network::Devnet).unwrap(), &deployed);
+
+    let empty = TransactionBuilder::default().build();
+    assert!(matches!(
+        deploy(&empty, lock_script(&config), &binary),
+        Err(Error::InvalidParameter(_))
+    ));
+}
+
+#[test]
+fn test_upgrade() {
+    let (_, config) = random_config(3, 0, 2);
+    let (tx, _) = gen_tx(&config, 1);
+    let (deployed, type_script) = deploy(&tx, lock_script(&config), &[1u8; 100]).unwrap();
+    let index = deployed.outputs().len() - 1;
+    let cell = LiveCell {
+        output: deployed.output(index).unwrap(),
+        output_data: Bytes::from(vec![1u8; 100]),
+        out_point: OutPoint::new(deployed.hash(), index as u32),
+        block_number: 0,
+        tx_index: 0,
+    };
+    let before = deployment(&deployed, index as u32).unwrap();
+
+    let binary = vec![2u8; 300];
+    let upgraded = upgrade(&tx, &cell, None, &binary).unwrap();
+    let last = upgraded.inputs().len() - 1;
+    assert_eq!(
+        upgraded.inputs().get(last).unwrap().previous_output(),
+        cell.out_point
+    );
+    let index = upgraded.outputs().len() - 1;
+    let output = upgraded.output(index).unwrap();
+    assert_eq!(output.type_().to_opt(), Some(type_script));
+    assert_eq!(output.lock(), cell.output.lock());
+    assert_eq!(
+        upgraded.outputs_data().get(index).unwrap().raw_data(),
+        binary
+    );
+
+    // the code hash stays, the cell dep moves
+    let after = deployment(&upgraded, index as u32).unwrap();
+    assert_eq!(after.code_hash, before.code_hash);
+    assert_ne!(after.cell_dep, before.cell_dep);
+
+    let (_, other) = random_config(2, 0, 1);
+    let relocked = upgrade(&tx, &cell, Some(lock_script(&other)), &binary).unwrap();
+    assert_eq!(relocked.output(index).unwrap().lock(), lock_script(&other));
+
+    let plain = LiveCell {
+        output: cell
+            .output
+            .clone()
+            .as_builder()
+            .type_(None::<Script>.pack())
+            .build(),
+        ..cell
+    };
+    assert!(matches!(
+        upgrade(&tx, &plain, None, &binary),
+        Err(Error::InvalidParameter(_))
+    ));
+}
+
+#[test]
+fn test_verify_binary() {
+    let binary = b"\x7fELF binary".to_vec();
+    let hash = verify_binary(&binary, &binary).unwrap();
+    assert_eq!(hash.0, ckb_hash::blake2b_256(&binary));
+    assert!(matches!(
+        verify_binary(&binary, b"\x7fELF other"),
+        Err(Error::Verification(_))
+    ));
+}
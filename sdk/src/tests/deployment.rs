@@ -0,0 +1,238 @@
+use ckb_system_scripts::BUNDLED_CELL;
+use ckb_types::{
+    bytes::Bytes,
+    core::{BlockBuilder, DepType, ScriptHashType, TransactionBuilder},
+    packed::{CellOutput, OutPoint},
+    prelude::*,
+    H256,
+};
+use serde_json::json;
+
+use super::{random_config, CODE_HASH};
+use crate::{
+    deployment::{
+        find_secp256k1_data, Deployment, Network, Registry, SECP256K1_DATA_HASH, TESTNET_GENESIS_TX,
+    },
+    Error,
+};
+
+const REGISTRY: &str = r#"
+[testnet]
+code_hash = "0x4242424242424242424242424242424242424242424242424242424242424242"
+tx_hash = "0x0101010101010101010101010101010101010101010101010101010101010101"
+index = 0
+
+[devnet]
+code_hash = "0x4343434343434343434343434343434343434343434343434343434343434343"
+hash_type = "data1"
+tx_hash = "0x0202020202020202020202020202020202020202020202020202020202020202"
+index = 3
+dep_type = "code"
+"#;
+
+#[test]
+fn test_registry_file() {
+    let registry = Registry::from_toml(REGISTRY).unwrap();
+    assert_eq!(
+        registry.networks().collect::<Vec<_>>(),
+        vec![Network::Testnet, Network::Devnet]
+    );
+    let testnet = registry.get(Network::Testnet).unwrap();
+    assert_eq!(testnet.code_hash, CODE_HASH);
+    assert_eq!(testnet.hash_type, ScriptHashType::Type);
+    assert_eq!(testnet.cell_dep.dep_type(), DepType::DepGroup.into());
+    let devnet = registry.get(Network::Devnet).unwrap();
+    assert_eq!(devnet.hash_type, ScriptHashType::Data1);
+    assert_eq!(devnet.cell_dep.dep_type(), DepType::Code.into());
+    let index: u32 = devnet.cell_dep.out_point().index().unpack();
+    assert_eq!(index, 3);
+    assert!(matches!(
+        registry.get(Network::Mainnet),
+        Err(Error::InvalidDeployment(_))
+    ));
+
+    let (_, config) = random_config(3, 0, 2);
+    assert_eq!(
+        testnet.lock_script(&config),
+        config.lock_script(&CODE_HASH, ScriptHashType::Type)
+    );
+
+    assert_eq!(
+        Registry::from_toml(&registry.to_toml().unwrap()).unwrap(),
+        registry
+    );
+    assert_eq!(
+        Registry::from_json(&registry.to_json().unwrap()).unwrap(),
+        registry
+    );
+}
+
+#[test]
+fn test_registry_invalid() {
+    assert!(Registry::from_toml("[staging]\ncode_hash = \"0x00\"").is_err());
+    let unknown_field = format!("{}\nlabel = \"x\"", REGISTRY);
+    assert!(Registry::from_toml(&unknown_field).is_err());
+    let zero = REGISTRY.replace(
+        &format!("{:x}", CODE_HASH),
+        &format!("{:x}", H256::default()),
+    );
+    assert!(Registry::from_toml(&zero).is_err());
+    assert_eq!("devnet".parse::<Network>().unwrap(), Network::Devnet);
+    assert!("staging".parse::<Network>().is_err());
+}
+
+#[test]
+fn test_registry_override() {
+    let mut registry = Registry::from_toml(REGISTRY).unwrap();
+    let local = Registry::from_toml(
+        r#"
+[devnet]
+code_hash = "0x4444444444444444444444444444444444444444444444444444444444444444"
+tx_hash = "0x0303030303030303030303030303030303030303030303030303030303030303"
+index = 1
+"#,
+    )
+    .unwrap();
+    registry.merge(local.clone());
+    assert_eq!(
+        registry.get(Network::Devnet).unwrap(),
+        local.get(Network::Devnet).unwrap()
+    );
+    assert_eq!(registry.get(Network::Testnet).unwrap().code_hash, CODE_HASH);
+
+    let testnet = registry.get(Network::Testnet).unwrap().clone();
+    assert_eq!(registry.set(Network::Mainnet, testnet.clone()), None);
+    assert_eq!(registry.get(Network::Mainnet).unwrap(), &testnet);
+}
+
+#[test]
+fn test_capsule_migration() {
+    let migration = json!({
+        "cell_recipes": [
+            {
+                "name": "ckb-multisig",
+                "tx_hash": "0x0505050505050505050505050505050505050505050505050505050505050505",
+                "index": 0,
+                "occupied_capacity": 10000000000u64,
+                "data_hash": "0x0606060606060606060606060606060606060606060606060606060606060606",
+                "type_id": "0x0707070707070707070707070707070707070707070707070707070707070707"
+            },
+            {
+                "name": "ckb-multisig-nonce",
+                "tx_hash": "0x0505050505050505050505050505050505050505050505050505050505050505",
+                "index": 1,
+                "occupied_capacity": 10000000000u64,
+                "data_hash": "0x0808080808080808080808080808080808080808080808080808080808080808",
+                "type_id": null
+            }
+        ],
+        "dep_group_recipes": [
+            {
+                "name": "multisig-dep-group",
+                "tx_hash": "0x0909090909090909090909090909090909090909090909090909090909090909",
+                "index": 0,
+                "data_hash": "0x0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a",
+                "occupied_capacity": 10000000000u64
+            }
+        ]
+    })
+    .to_string();
+
+    let deployment =
+        Deployment::from_capsule_migration(&migration, "ckb-multisig", Some("multisig-dep-group"))
+            .unwrap();
+    assert_eq!(deployment.code_hash, H256([7; 32]));
+    assert_eq!(deployment.hash_type, ScriptHashType::Type);
+    assert_eq!(deployment.cell_dep.dep_type(), DepType::DepGroup.into());
+    let tx_hash: H256 = deployment.cell_dep.out_point().tx_hash().unpack();
+    assert_eq!(tx_hash, H256([9; 32]));
+
+    let deployment =
+        Deployment::from_capsule_migration(&migration, "ckb-multisig-nonce", None).unwrap();
+    assert_eq!(deployment.code_hash, H256([8; 32]));
+    assert_eq!(deployment.hash_type, ScriptHashType::Data1);
+    assert_eq!(deployment.cell_dep.dep_type(), DepType::Code.into());
+    let index: u32 = deployment.cell_dep.out_point().index().unpack();
+    assert_eq!(index, 1);
+
+    assert!(Deployment::from_capsule_migration(&migration, "other", None).is_err());
+    assert!(Deployment::from_capsule_migration(&migration, "ckb-multisig", Some("other")).is_err());
+}
+
+#[test]
+fn test_cell_deps() {
+    let mut registry = Registry::from_toml(REGISTRY).unwrap();
+    // a dep group carries the secp256k1_data cell
+    let testnet = registry.get(Network::Testnet).unwrap().clone();
+    assert!(!testnet.secp256k1_data);
+    assert_eq!(
+        registry.cell_deps(Network::Testnet).unwrap(),
+        vec![testnet.cell_dep.clone()]
+    );
+
+    let code = Deployment {
+        cell_dep: testnet
+            .cell_dep
+            .clone()
+            .as_builder()
+            .dep_type(DepType::Code)
+            .build(),
+        secp256k1_data: true,
+        ..testnet
+    };
+    registry.set(Network::Testnet, code.clone());
+    let cell_deps = registry.cell_deps(Network::Testnet).unwrap();
+    assert_eq!(cell_deps.len(), 2);
+    assert_eq!(cell_deps[0], code.cell_dep);
+    assert_eq!(
+        cell_deps[1].out_point(),
+        OutPoint::new(TESTNET_GENESIS_TX.pack(), 3)
+    );
+    assert_eq!(cell_deps[1].dep_type(), DepType::Code.into());
+
+    assert!(matches!(
+        registry.cell_deps(Network::Mainnet),
+        Err(Error::InvalidDeployment(_))
+    ));
+}
+
+#[test]
+fn test_devnet_secp256k1_data() {
+    let mut registry = Registry::from_toml(REGISTRY).unwrap();
+    assert!(registry.get(Network::Devnet).unwrap().secp256k1_data);
+    assert!(matches!(
+        registry.cell_deps(Network::Devnet),
+        Err(Error::InvalidDeployment(_))
+    ));
+
+    let data = Bytes::from(
+        BUNDLED_CELL
+            .get("specs/cells/secp256k1_data")
+            .unwrap()
+            .to_vec(),
+    );
+    assert_eq!(
+        CellOutput::calc_data_hash(&data),
+        SECP256K1_DATA_HASH.pack()
+    );
+    let cellbase = TransactionBuilder::default()
+        .output(CellOutput::default())
+        .output_data(Bytes::new().pack())
+        .build();
+    let genesis_tx = TransactionBuilder::default()
+        .outputs(vec![CellOutput::default(); 2])
+        .output_data(Bytes::from_static(b"other").pack())
+        .output_data(data.pack())
+        .build();
+    let genesis = BlockBuilder::default()
+        .transaction(cellbase)
+        .transaction(genesis_tx.clone())
+        .build();
+    let dep = find_secp256k1_data(&genesis).unwrap();
+    assert_eq!(dep.out_point(), OutPoint::new(genesis_tx.hash(), 1));
+    assert!(find_secp256k1_data(&BlockBuilder::default().build()).is_none());
+
+    registry.set_secp256k1_data(Network::Devnet, dep.clone());
+    let cell_deps = registry.cell_deps(Network::Devnet).unwrap();
+    assert_eq!(cell_deps[1], dep);
+}
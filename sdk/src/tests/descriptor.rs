@@ -0,0 +1,69 @@
+use super::random_signer;
+use crate::{
+    config::KeyFormat,
+    descriptor::{checksum, decode, encode},
+    signer::Signer,
+    since::parse_since,
+    MultisigConfig,
+};
+
+fn config(count: usize, require_first_n: u8, threshold: u8) -> MultisigConfig {
+    let hashes = (0..count)
+        .map(|_| random_signer().identity().unwrap())
+        .collect();
+    MultisigConfig::new(hashes, require_first_n, threshold).unwrap()
+}
+
+#[test]
+fn test_checksum() {
+    // The test vector of BIP-380.
+    assert_eq!(checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+    assert!(checksum("multi(1,0,é)").is_err());
+}
+
+#[test]
+fn test_roundtrip() {
+    let since = parse_since("after epoch 180").unwrap();
+    let configs = [
+        config(3, 1, 2),
+        config(2, 0, 2).with_since(Some(since)),
+        config(2, 0, 1).with_key_format(KeyFormat::Either),
+    ];
+    for config in configs {
+        let descriptor = encode(&config).unwrap();
+        assert_eq!(decode(&descriptor).unwrap(), config);
+    }
+
+    let config = config(2, 0, 2).with_since(Some(since));
+    let descriptor = encode(&config).unwrap();
+    let hashes: Vec<_> = config.pubkey_hashes().iter().map(hex::encode).collect();
+    assert_eq!(
+        descriptor,
+        format!(
+            "since({:#x},multi(2,0,{},{}))#{}",
+            since,
+            hashes[0],
+            hashes[1],
+            &descriptor[descriptor.len() - 8..]
+        )
+    );
+}
+
+#[test]
+fn test_decode_checks_checksum() {
+    let descriptor = encode(&config(3, 0, 2)).unwrap();
+    let (body, _) = descriptor.split_once('#').unwrap();
+    let error = decode(body).unwrap_err().to_string();
+    assert!(error.contains(&descriptor[body.len()..]), "{}", error);
+
+    for typo in ["multi(3,0", "multi(2,1"] {
+        let mistyped = descriptor.replacen("multi(2,0", typo, 1);
+        assert!(decode(&mistyped).is_err());
+    }
+}
+
+#[test]
+fn test_encode_refuses_options() {
+    let config = config(2, 0, 1).with_fee_key(Some([1; 20]));
+    assert!(encode(&config).is_err());
+}
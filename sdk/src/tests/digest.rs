@@ -0,0 +1,175 @@
+use std::convert::TryInto;
+
+use ckb_types::{packed, prelude::*};
+use serde_json::Value;
+
+use super::{gen_tx, random_config};
+use crate::{
+    compute_sighash,
+    digest::{compute_fee_sighash, generate_legacy_message, generate_message},
+    witness::{
+        legacy_lock_index, legacy_stuffed_witnesses, set_witness_lock, stuffed_witnesses,
+        witness_args,
+    },
+    MultisigConfig, MultisigLock,
+};
+
+const VECTORS: &str = include_str!("vectors/sighash.json");
+const LEGACY_VECTORS: &str = include_str!("vectors/legacy_witnesses.json");
+
+fn hex_field(value: &Value) -> Vec<u8> {
+    hex::decode(value.as_str().unwrap().trim_start_matches("0x")).unwrap()
+}
+
+fn vector_config(value: &Value) -> MultisigConfig {
+    let hashes = value["pubkey_hashes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hash| hex_field(hash).try_into().unwrap())
+        .collect();
+    let since = value["since"]
+        .as_str()
+        .map(|since| u64::from_str_radix(since.trim_start_matches("0x"), 16).unwrap());
+    MultisigConfig::new(
+        hashes,
+        value["require_first_n"].as_u64().unwrap() as u8,
+        value["threshold"].as_u64().unwrap() as u8,
+    )
+    .unwrap()
+    .with_since(since)
+}
+
+#[test]
+fn test_sighash_vectors() {
+    let vectors: Value = serde_json::from_str(VECTORS).unwrap();
+    let vectors = vectors["vectors"].as_array().unwrap();
+    assert!(!vectors.is_empty());
+    for vector in vectors {
+        let name = vector["name"].as_str().unwrap();
+        let tx = packed::Transaction::from_slice(&hex_field(&vector["tx"]))
+            .unwrap()
+            .into_view();
+        let indices: Vec<usize> = vector["group_indices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i.as_u64().unwrap() as usize)
+            .collect();
+        let config = vector_config(&vector["config"]);
+        let sighash = compute_sighash(&tx, &indices, &config).unwrap();
+        assert_eq!(sighash.to_vec(), hex_field(&vector["sighash"]), "{}", name);
+
+        // the message of the filled lock is the same
+        let tx = set_witness_lock(&tx, indices[0], &MultisigLock::new(config)).unwrap();
+        assert_eq!(
+            generate_message(&tx, &indices).unwrap(),
+            sighash,
+            "{}",
+            name
+        );
+        let witness = tx.witnesses().get(indices[0]).unwrap().raw_data();
+        assert_eq!(
+            witness.to_vec(),
+            hex_field(&vector["zero_witness"]),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_legacy_witness_vectors() {
+    let vectors: Value = serde_json::from_str(LEGACY_VECTORS).unwrap();
+    let vectors = vectors["vectors"].as_array().unwrap();
+    assert!(!vectors.is_empty());
+    for vector in vectors {
+        let name = vector["name"].as_str().unwrap();
+        let tx = packed::Transaction::from_slice(&hex_field(&vector["tx"]))
+            .unwrap()
+            .into_view();
+        let indices: Vec<usize> = vector["group_indices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i.as_u64().unwrap() as usize)
+            .collect();
+        let config = vector_config(&vector["config"]).with_legacy_witnesses(true);
+        assert_eq!(
+            config.lock_args().to_vec(),
+            hex_field(&vector["lock_args"]),
+            "{}",
+            name
+        );
+
+        let lock_index = vector["lock_index"].as_u64().unwrap() as usize;
+        assert_eq!(
+            legacy_lock_index(&tx, &indices),
+            Some(lock_index),
+            "{}",
+            name
+        );
+        let message = generate_legacy_message(&tx, &indices).unwrap();
+        assert_eq!(message.to_vec(), hex_field(&vector["message"]), "{}", name);
+        let lock = witness_args(&tx, lock_index)
+            .unwrap()
+            .lock()
+            .to_opt()
+            .unwrap();
+        let lock = MultisigLock::parse(&lock.raw_data()).unwrap();
+        lock.verify(&message).unwrap();
+
+        // placeholders are empty under the flag only
+        assert!(
+            legacy_stuffed_witnesses(&tx, &indices).is_empty(),
+            "{}",
+            name
+        );
+        if lock_index != indices[0] {
+            assert!(generate_message(&tx, &indices).is_err(), "{}", name);
+        } else {
+            assert!(!stuffed_witnesses(&tx, &indices).is_empty(), "{}", name);
+        }
+    }
+}
+
+#[test]
+fn test_sighash_checks_the_config() {
+    let (_, config) = random_config(3, 0, 2);
+    let (_, other) = random_config(3, 0, 2);
+    let (tx, group) = gen_tx(&config, 2);
+    assert!(compute_sighash(&tx, &[], &config).is_err());
+    assert!(compute_sighash(&tx, &[5], &config).is_err());
+
+    let unsigned = compute_sighash(&tx, &group.input_indices, &config).unwrap();
+    let tx = set_witness_lock(&tx, 0, &MultisigLock::new(config.clone())).unwrap();
+    assert_eq!(
+        compute_sighash(&tx, &group.input_indices, &config).unwrap(),
+        unsigned
+    );
+    assert!(compute_sighash(&tx, &group.input_indices, &other).is_err());
+}
+
+#[test]
+fn test_fee_sighash() {
+    let (_, config) = random_config(3, 0, 2);
+    let (tx, group) = gen_tx(&config, 2);
+    assert!(compute_fee_sighash(&tx, &[]).is_err());
+
+    let unsigned = compute_fee_sighash(&tx, &group.input_indices).unwrap();
+    assert_ne!(
+        unsigned,
+        compute_sighash(&tx, &group.input_indices, &config).unwrap()
+    );
+    let witness = packed::WitnessArgs::new_builder()
+        .lock(Some(ckb_types::bytes::Bytes::from(vec![1u8; 65])).pack())
+        .build();
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![witness.as_bytes().pack()])
+        .build();
+    assert_eq!(
+        compute_fee_sighash(&tx, &group.input_indices).unwrap(),
+        unsigned
+    );
+}
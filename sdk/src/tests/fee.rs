@@ -0,0 +1,61 @@
+use ckb_sdk::{traits::dummy_impls::DummyTransactionDependencyProvider, unlock::ScriptUnlocker};
+
+use super::{gen_tx, random_config};
+use crate::{
+    fee::{witness_size, FeeEstimator},
+    unlock::{BoxedSigner, MultisigUnlocker},
+};
+
+#[test]
+fn test_estimate_matches_signed_tx() {
+    let (signers, config) = random_config(5, 1, 3);
+    let (tx, group) = gen_tx(&config, 2);
+    let estimator = FeeEstimator::new(1000);
+    let groups = vec![(group.clone(), config.clone())];
+    let estimate = estimator.estimate(&tx, &groups, None).unwrap();
+
+    let signers = signers
+        .into_iter()
+        .map(|s| Box::new(s) as BoxedSigner)
+        .collect();
+    let unlocker = MultisigUnlocker::new(config.clone(), signers);
+    let provider = DummyTransactionDependencyProvider;
+    let signed = unlocker.unlock(&tx, &group, &provider).unwrap();
+    assert!(unlocker.is_unlocked(&signed, &group, &provider).unwrap());
+    let size = signed.data().as_reader().serialized_size_in_block() as u64;
+    assert_eq!(estimate.size, size);
+    assert_eq!(estimate.weight, size);
+    assert_eq!(estimate.fee, size);
+
+    // a partially signed proposal estimates the same
+    let partial = MultisigUnlocker::new(config, vec![])
+        .fill_placeholder_witness(&tx, &group, &provider)
+        .unwrap();
+    assert_eq!(
+        estimator.estimate(&partial, &groups, None).unwrap(),
+        estimate
+    );
+}
+
+#[test]
+fn test_witness_size() {
+    let (_, config) = random_config(3, 0, 2);
+    let (tx, group) = gen_tx(&config, 1);
+    let unsigned = tx.data().as_reader().serialized_size_in_block();
+    let sized = FeeEstimator::sized_tx(&tx, &[(group, config.clone())]).unwrap();
+    assert_eq!(
+        sized.data().as_reader().serialized_size_in_block() - unsigned,
+        witness_size(&config)
+    );
+}
+
+#[test]
+fn test_cycles_dominate_weight() {
+    let estimator = FeeEstimator::new(2000);
+    let estimate = estimator.estimate_with_size(500, Some(1000));
+    assert_eq!(estimate.weight, 500);
+    assert_eq!(estimate.fee, 1000);
+    let estimate = estimator.estimate_with_size(500, Some(70_000_000));
+    assert!(estimate.weight > 500);
+    assert_eq!(estimate.fee, estimate.weight * 2);
+}
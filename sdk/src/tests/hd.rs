@@ -0,0 +1,138 @@
+use crate::{
+    hd::{ckb_account_path, ckb_path, ExtendedPrivKey, Mnemonic},
+    ledger::{DerivationPath, DEFAULT_PATH},
+    SecpSigner, Signer,
+};
+
+/// From the BIP-39 test vectors of Trezor, with the passphrase `TREZOR`.
+const MNEMONIC_VECTORS: [(&str, &str, &str); 4] = [
+    (
+        "00000000000000000000000000000000",
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+    ),
+    (
+        "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+        "legal winner thank year wave sausage worth useful legal winner thank yellow",
+        "",
+    ),
+    (
+        "9e885d952ad362caeb4efe34a8e91bd2",
+        "ozone drill grab fiber curtain grace pudding thank cruise elder eight picnic",
+        "",
+    ),
+    (
+        "f585c11aec520db57dd353c69554b21a89b20fb0650966fa0a9d6f74fd989d8f",
+        "void come effort suffer camp survey warrior heavy shoot primary clutch crush open amazing screen patrol group space point ten exist slush involve unfold",
+        "",
+    ),
+];
+
+#[test]
+fn test_mnemonic_vectors() {
+    for (entropy, phrase, seed) in MNEMONIC_VECTORS.iter() {
+        let entropy = hex::decode(entropy).unwrap();
+        let mnemonic = Mnemonic::from_entropy(&entropy).unwrap();
+        assert_eq!(mnemonic.phrase(), *phrase);
+        let recovered: Mnemonic = phrase.parse().unwrap();
+        assert_eq!(recovered.entropy(), entropy.as_slice());
+        if !seed.is_empty() {
+            assert_eq!(hex::encode(mnemonic.to_seed("TREZOR")), *seed);
+        }
+    }
+}
+
+#[test]
+fn test_mnemonic_recovery() {
+    let mnemonic = Mnemonic::generate(24).unwrap();
+    assert_eq!(mnemonic.word_count(), 24);
+    assert_eq!(mnemonic.entropy().len(), 32);
+    // whitespace and case are forgiven, the checksum isn't
+    let written = format!("  {}\n", mnemonic.phrase().to_uppercase());
+    assert_eq!(Mnemonic::from_phrase(&written).unwrap(), mnemonic);
+    assert!(!format!("{:?}", mnemonic).contains(mnemonic.phrase()));
+
+    let mut words: Vec<_> = MNEMONIC_VECTORS[0].1.split(' ').collect();
+    words[11] = "abandon";
+    assert!(Mnemonic::from_phrase(&words.join(" ")).is_err());
+    words[11] = "abandonn";
+    assert!(Mnemonic::from_phrase(&words.join(" ")).is_err());
+    assert!(Mnemonic::from_phrase(&words[..11].join(" ")).is_err());
+    assert!(Mnemonic::generate(13).is_err());
+    assert!(Mnemonic::from_entropy(&[0; 15]).is_err());
+}
+
+#[test]
+fn test_bip32_vector() {
+    // test vector 1 of BIP-32
+    let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+    let master = ExtendedPrivKey::from_seed(&seed).unwrap();
+    let vectors = [
+        (
+            "m",
+            "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508",
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+        ),
+        (
+            "m/0'",
+            "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141",
+            "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+        ),
+        (
+            "m/0'/1",
+            "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19",
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+        ),
+        (
+            "m/0'/1/2'",
+            "04466b9cc8e161e966409ca52986c584f07e9dc81f735db683c3ff6ec7b1503f",
+            "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca",
+        ),
+    ];
+    for (path, chain_code, key) in vectors.iter() {
+        let derived = master.derive(&path.parse().unwrap()).unwrap();
+        assert_eq!(hex::encode(derived.chain_code()), *chain_code, "{}", path);
+        assert_eq!(
+            hex::encode(derived.secret_key().secret_bytes()),
+            *key,
+            "{}",
+            path
+        );
+    }
+    // the public derivation agrees, and refuses hardened indices
+    let parent = master.derive(&"m/0'".parse().unwrap()).unwrap();
+    let path: DerivationPath = "m/1/7".parse().unwrap();
+    assert_eq!(
+        parent.to_public().derive(&path).unwrap(),
+        parent.derive(&path).unwrap().to_public()
+    );
+    assert!(master.to_public().derive(&"m/0'".parse().unwrap()).is_err());
+}
+
+#[test]
+fn test_ckb_paths() {
+    assert_eq!(ckb_path(0, 0).unwrap(), DEFAULT_PATH.parse().unwrap());
+    assert_eq!(
+        ckb_path(2, 5).unwrap(),
+        "m/44'/309'/2'/0/5".parse().unwrap()
+    );
+    assert_eq!(
+        ckb_account_path(2).unwrap(),
+        "m/44'/309'/2'".parse().unwrap()
+    );
+    assert!(ckb_path(0x8000_0000, 0).is_err());
+
+    // the coordinator derives the identities from the account public key
+    let mnemonic: Mnemonic = MNEMONIC_VECTORS[1].1.parse().unwrap();
+    let master = ExtendedPrivKey::from_seed(&mnemonic.to_seed("")).unwrap();
+    let account = master.derive(&ckb_account_path(1).unwrap()).unwrap();
+    let identities = account.to_public().identities(0..3).unwrap();
+    for (index, identity) in identities.iter().enumerate() {
+        let signer =
+            SecpSigner::from_mnemonic(&mnemonic, "", &ckb_path(1, index as u32).unwrap()).unwrap();
+        assert_eq!(signer.identity().unwrap(), *identity);
+    }
+    // the passphrase gives other keys
+    let other = SecpSigner::from_mnemonic(&mnemonic, "x", &ckb_path(1, 0).unwrap()).unwrap();
+    assert_ne!(other.identity().unwrap(), identities[0]);
+}
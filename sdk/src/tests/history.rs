@@ -0,0 +1,66 @@
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, TransactionBuilder},
+    packed::{CellOutput, Script},
+    prelude::*,
+};
+
+use super::{lock_script, random_config};
+use crate::history::{Direction, HistoryEntry};
+
+fn output(lock: Script, capacity: u64) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock)
+        .build()
+}
+
+#[test]
+fn test_outgoing_entry() {
+    let (_, config) = random_config(3, 0, 2);
+    let (_, alice) = random_config(1, 0, 1);
+    let (_, bob) = random_config(1, 0, 1);
+    let tx = TransactionBuilder::default()
+        .output(output(lock_script(&alice), 1000))
+        .output_data(Bytes::new().pack())
+        .output(output(lock_script(&config), 5000))
+        .output_data(Bytes::new().pack())
+        .output(output(lock_script(&bob), 300))
+        .output_data(Bytes::new().pack())
+        .output(output(lock_script(&alice), 200))
+        .output_data(Bytes::new().pack())
+        .build();
+    let entry = HistoryEntry::outgoing(&tx, 9, &lock_script(&config));
+    assert_eq!(entry.tx_hash, tx.hash().unpack());
+    assert_eq!(entry.block_number, 9);
+    assert_eq!(entry.direction, Direction::Out);
+    // the change back to the config is not sent
+    assert_eq!(entry.amount, 1500);
+    assert_eq!(
+        entry.counterparties,
+        vec![lock_script(&alice), lock_script(&bob)]
+    );
+}
+
+#[test]
+fn test_incoming_entry() {
+    let (_, config) = random_config(3, 0, 2);
+    let (_, alice) = random_config(1, 0, 1);
+    let (_, bob) = random_config(1, 0, 1);
+    let tx = TransactionBuilder::default()
+        .output(output(lock_script(&config), 1000))
+        .output_data(Bytes::new().pack())
+        .output(output(lock_script(&alice), 400))
+        .output_data(Bytes::new().pack())
+        .output(output(lock_script(&config), 2000))
+        .output_data(Bytes::new().pack())
+        .build();
+    let senders = vec![lock_script(&alice), lock_script(&bob), lock_script(&alice)];
+    let entry = HistoryEntry::incoming(&tx, 4, &lock_script(&config), senders);
+    assert_eq!(entry.direction, Direction::In);
+    assert_eq!(entry.amount, 3000);
+    assert_eq!(
+        entry.counterparties,
+        vec![lock_script(&alice), lock_script(&bob)]
+    );
+}
@@ -0,0 +1,249 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use secp256k1::{Message, PublicKey, SecretKey, SECP256K1};
+use serde_json::{json, Value};
+
+use super::random_digest;
+use crate::{
+    kms::{
+        amz_date, parse_pem, parse_spki, parse_vault_signature, sigv4_authorization,
+        AwsCredentials, AwsKms, GcpKms, KmsKey, KmsSigner, SigV4Request, VaultTransit,
+    },
+    signer::{pubkey_identity, recover_pubkey},
+    Error, Signer,
+};
+
+fn spki(pubkey: &PublicKey) -> Vec<u8> {
+    let mut der = hex::decode("3056301006072a8648ce3d020106052b8104000a034200").unwrap();
+    der.extend_from_slice(&pubkey.serialize_uncompressed());
+    der
+}
+
+fn pem(der: &[u8]) -> String {
+    let encoded = BASE64.encode(der);
+    let lines: Vec<_> = encoded
+        .as_bytes()
+        .chunks(64)
+        .map(|line| std::str::from_utf8(line).unwrap())
+        .collect();
+    format!(
+        "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+        lines.join("\n")
+    )
+}
+
+/// A key signing in software, as a KMS does.
+struct SoftwareKms(SecretKey);
+
+impl KmsKey for SoftwareKms {
+    fn public_key_der(&self) -> Result<Vec<u8>, Error> {
+        Ok(spki(&PublicKey::from_secret_key(SECP256K1, &self.0)))
+    }
+
+    fn sign_der(&self, digest: &[u8; 32]) -> Result<Vec<u8>, Error> {
+        let signature = SECP256K1.sign_ecdsa(&Message::from_digest(*digest), &self.0);
+        Ok(signature.serialize_der().to_vec())
+    }
+}
+
+/// Serve the HTTP requests with `answers` in turn, returning the request
+/// lines, headers and bodies.
+fn serve(answers: Vec<Value>) -> (String, thread::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for answer in answers {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                request.push_str(&line);
+            }
+            let mut body = vec![0u8; length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            let answer = answer.to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                answer.len(),
+                answer
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            requests.push(request);
+        }
+        requests
+    });
+    (url, handle)
+}
+
+#[test]
+fn test_sigv4() {
+    // get-vanilla of the AWS SigV4 test suite
+    let credentials = AwsCredentials {
+        access_key_id: "AKIDEXAMPLE".to_string(),
+        secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+        session_token: None,
+    };
+    let date = amz_date(1_440_938_160);
+    assert_eq!(date, "20150830T123600Z");
+    let headers = [
+        ("x-amz-date", date.clone()),
+        ("host", "example.amazonaws.com".to_string()),
+    ];
+    let request = SigV4Request {
+        method: "GET",
+        path: "/",
+        headers: &headers,
+        payload: b"",
+    };
+    assert_eq!(
+        sigv4_authorization(&request, &date, "us-east-1", "service", &credentials),
+        "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+         SignedHeaders=host;x-amz-date, \
+         Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+    );
+    assert_eq!(amz_date(0), "19700101T000000Z");
+    assert_eq!(amz_date(951_825_599), "20000229T115959Z");
+}
+
+#[test]
+fn test_public_key_formats() {
+    let pubkey = PublicKey::from_secret_key(SECP256K1, &SecretKey::from_slice(&[7; 32]).unwrap());
+    let der = spki(&pubkey);
+    assert_eq!(parse_spki(&der).unwrap(), pubkey);
+    assert_eq!(parse_pem(&pem(&der)).unwrap(), der);
+    // a P-256 key
+    let mut p256 = der.clone();
+    p256[14..22].copy_from_slice(&hex::decode("06082a8648ce3d03").unwrap());
+    assert!(matches!(parse_spki(&p256), Err(Error::Kms(_))));
+
+    assert_eq!(
+        parse_vault_signature(&format!("vault:v2:{}", BASE64.encode([1, 2, 3]))).unwrap(),
+        vec![1, 2, 3]
+    );
+    assert!(parse_vault_signature("v2:AQID").is_err());
+}
+
+#[test]
+fn test_kms_signer() {
+    let signer = KmsSigner::new(SoftwareKms(SecretKey::from_slice(&[9; 32]).unwrap())).unwrap();
+    for _ in 0..8 {
+        let digest = random_digest();
+        let signature = signer.sign(&digest).unwrap();
+        assert_eq!(
+            &recover_pubkey(&digest, &signature).unwrap(),
+            signer.pubkey()
+        );
+    }
+    assert_eq!(signer.identity().unwrap(), pubkey_identity(signer.pubkey()));
+}
+
+#[test]
+fn test_vault_transit() {
+    let key = SecretKey::from_slice(&[5; 32]).unwrap();
+    let pubkey = PublicKey::from_secret_key(SECP256K1, &key);
+    let digest = random_digest();
+    let der = SECP256K1
+        .sign_ecdsa(&Message::from_digest(digest), &key)
+        .serialize_der();
+    let (url, server) = serve(vec![
+        json!({ "data": {
+            "latest_version": 2,
+            "keys": { "2": { "public_key": pem(&spki(&pubkey)) } },
+        } }),
+        json!({ "data": { "signature": format!("vault:v2:{}", BASE64.encode(der)) } }),
+    ]);
+    let signer = KmsSigner::new(VaultTransit::new(&url, "s.token", "treasury")).unwrap();
+    assert_eq!(signer.pubkey(), &pubkey);
+    let signature = signer.sign(&digest).unwrap();
+    assert_eq!(recover_pubkey(&digest, &signature).unwrap(), pubkey);
+
+    let requests = server.join().unwrap();
+    assert!(requests[0].starts_with("GET /v1/transit/keys/treasury "));
+    assert!(requests[1].starts_with("POST /v1/transit/sign/treasury "));
+    assert!(requests
+        .iter()
+        .all(|request| request.to_lowercase().contains("x-vault-token: s.token")));
+    let body: Value = serde_json::from_str(&requests[1][requests[1].find('{').unwrap()..]).unwrap();
+    assert_eq!(body["input"], BASE64.encode(digest));
+    assert_eq!(body["prehashed"], true);
+}
+
+#[test]
+fn test_aws_kms() {
+    let key = SecretKey::from_slice(&[6; 32]).unwrap();
+    let pubkey = PublicKey::from_secret_key(SECP256K1, &key);
+    let digest = random_digest();
+    let der = SECP256K1
+        .sign_ecdsa(&Message::from_digest(digest), &key)
+        .serialize_der();
+    let (url, server) = serve(vec![
+        json!({ "KeyId": "alias/treasury", "PublicKey": BASE64.encode(spki(&pubkey)) }),
+        json!({ "KeyId": "alias/treasury", "Signature": BASE64.encode(der) }),
+    ]);
+    let credentials = AwsCredentials {
+        access_key_id: "AKID".to_string(),
+        secret_access_key: "secret".to_string(),
+        session_token: Some("session".to_string()),
+    };
+    let kms = AwsKms::new("alias/treasury", "eu-west-1", credentials).endpoint(&url);
+    let signer = KmsSigner::new(kms).unwrap();
+    let signature = signer.sign(&digest).unwrap();
+    assert_eq!(recover_pubkey(&digest, &signature).unwrap(), pubkey);
+
+    let requests: Vec<_> = server
+        .join()
+        .unwrap()
+        .iter()
+        .map(|request| request.to_lowercase())
+        .collect();
+    assert!(requests[0].contains("x-amz-target: trentservice.getpublickey"));
+    assert!(requests[1].contains("x-amz-target: trentservice.sign"));
+    assert!(requests[1].contains("x-amz-security-token: session"));
+    assert!(requests[1]
+        .contains("signedheaders=content-type;host;x-amz-date;x-amz-security-token;x-amz-target"));
+    assert!(requests[1].contains("/eu-west-1/kms/aws4_request"));
+    assert!(requests[1].contains(r#""messagetype":"digest""#));
+}
+
+#[test]
+fn test_gcp_kms() {
+    let key = SecretKey::from_slice(&[8; 32]).unwrap();
+    let pubkey = PublicKey::from_secret_key(SECP256K1, &key);
+    let digest = random_digest();
+    let der = SECP256K1
+        .sign_ecdsa(&Message::from_digest(digest), &key)
+        .serialize_der();
+    let (url, server) = serve(vec![
+        json!({ "pem": pem(&spki(&pubkey)), "algorithm": "EC_SIGN_SECP256K1_SHA256" }),
+        json!({ "signature": BASE64.encode(der) }),
+    ]);
+    let name = "projects/p/locations/global/keyRings/r/cryptoKeys/k/cryptoKeyVersions/1";
+    let signer = KmsSigner::new(GcpKms::new(name, "ya29.token").endpoint(&url)).unwrap();
+    let signature = signer.sign(&digest).unwrap();
+    assert_eq!(recover_pubkey(&digest, &signature).unwrap(), pubkey);
+
+    let requests = server.join().unwrap();
+    assert!(requests[0].starts_with(&format!("GET /v1/{}/publicKey ", name)));
+    assert!(requests[1].starts_with(&format!("POST /v1/{}:asymmetricSign ", name)));
+    assert!(requests[1]
+        .to_lowercase()
+        .contains("authorization: bearer ya29.token"));
+}
@@ -0,0 +1,118 @@
+use std::cell::RefCell;
+
+use secp256k1::{Message, SecretKey, SECP256K1};
+
+use super::random_digest;
+use crate::{
+    ledger::{
+        ApduAnswer, ApduCommand, DerivationPath, LedgerSigner, LedgerTransport, CLA,
+        INS_GET_APP_VERSION, INS_GET_PUBLIC_KEY, INS_SIGN_MESSAGE_HASH, P1_FIRST, P1_LAST_MARKER,
+        P1_NEXT, SW_OK,
+    },
+    signer::{recover_pubkey, serialize_signature},
+    Error, Signer,
+};
+
+const SW_DENIED: u16 = 0x6985;
+
+/// Emulates the CKB app with a software key.
+struct MockDevice {
+    key: SecretKey,
+    deny: bool,
+    path: RefCell<Option<Vec<u8>>>,
+}
+
+impl MockDevice {
+    fn new(deny: bool) -> Self {
+        MockDevice {
+            key: SecretKey::new(&mut secp256k1::rand::thread_rng()),
+            deny,
+            path: RefCell::new(None),
+        }
+    }
+
+    fn answer(data: Vec<u8>) -> Result<ApduAnswer, Error> {
+        Ok(ApduAnswer {
+            data,
+            status: SW_OK,
+        })
+    }
+}
+
+impl LedgerTransport for MockDevice {
+    fn exchange(&self, command: &ApduCommand) -> Result<ApduAnswer, Error> {
+        assert_eq!(command.cla, CLA);
+        match (command.ins, command.p1) {
+            (INS_GET_APP_VERSION, _) => Self::answer(vec![0, 5, 0]),
+            (INS_GET_PUBLIC_KEY, _) => {
+                let pubkey = secp256k1::PublicKey::from_secret_key(SECP256K1, &self.key);
+                let mut data = vec![65];
+                data.extend_from_slice(&pubkey.serialize_uncompressed());
+                Self::answer(data)
+            }
+            (INS_SIGN_MESSAGE_HASH, P1_FIRST) => {
+                *self.path.borrow_mut() = Some(command.data.clone());
+                Self::answer(Vec::new())
+            }
+            (INS_SIGN_MESSAGE_HASH, p1) if p1 == P1_LAST_MARKER | P1_NEXT => {
+                assert!(self.path.borrow().is_some(), "path chunk goes first");
+                if self.deny {
+                    return Ok(ApduAnswer {
+                        data: Vec::new(),
+                        status: SW_DENIED,
+                    });
+                }
+                let mut digest = [0u8; 32];
+                digest.copy_from_slice(&command.data);
+                let signature =
+                    SECP256K1.sign_ecdsa_recoverable(&Message::from_digest(digest), &self.key);
+                Self::answer(serialize_signature(&signature).to_vec())
+            }
+            _ => panic!("unexpected apdu"),
+        }
+    }
+}
+
+#[test]
+fn test_derivation_path() {
+    let path: DerivationPath = "m/44'/309'/0'/0/7".parse().unwrap();
+    assert_eq!(
+        path.indices(),
+        &[0x8000_002c, 0x8000_0135, 0x8000_0000, 0, 7]
+    );
+    assert_eq!(path.serialize()[0], 5);
+    assert_eq!(&path.serialize()[1..5], &[0x80, 0x00, 0x00, 0x2c]);
+    assert_eq!(
+        DerivationPath::default(),
+        "m/44'/309'/0'/0/0".parse().unwrap()
+    );
+
+    assert!("44'/309'".parse::<DerivationPath>().is_err());
+    assert!("m/abc".parse::<DerivationPath>().is_err());
+    assert!("m/2147483648".parse::<DerivationPath>().is_err());
+}
+
+#[test]
+fn test_ledger_signer() {
+    let signer = LedgerSigner::new(MockDevice::new(false), DerivationPath::default()).unwrap();
+    assert_eq!(signer.app_version().unwrap(), (0, 5, 0));
+    let digest = random_digest();
+    let signature = signer.sign(&digest).unwrap();
+    assert_eq!(
+        &recover_pubkey(&digest, &signature).unwrap(),
+        signer.pubkey()
+    );
+    assert_eq!(
+        crate::signer::pubkey_identity(signer.pubkey()),
+        signer.identity().unwrap()
+    );
+}
+
+#[test]
+fn test_ledger_signer_denied() {
+    let signer = LedgerSigner::new(MockDevice::new(true), DerivationPath::default()).unwrap();
+    assert!(matches!(
+        signer.sign(&random_digest()),
+        Err(Error::LedgerStatus(SW_DENIED))
+    ));
+}
@@ -0,0 +1,180 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+use ckb_jsonrpc_types as json;
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        Capacity, EpochNumberWithFraction, ScriptHashType, TransactionBuilder, TransactionView,
+    },
+    packed::{CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+};
+use serde_json::{json, Value};
+
+use super::{lock_script, random_config, CODE_HASH};
+use crate::{
+    light_client::LightClient,
+    scanner::{BlockInfo, Scanner},
+    watcher::{Activity, DepositWatcher},
+};
+
+/// Answer the JSON-RPC calls with `results` in turn, returning the calls.
+pub(super) fn serve(results: Vec<Value>) -> (String, thread::JoinHandle<Vec<Value>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let mut calls = Vec::new();
+        for result in results {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0u8; length];
+            reader.read_exact(&mut body).unwrap();
+            let call: Value = serde_json::from_slice(&body).unwrap();
+            let answer =
+                json!({ "jsonrpc": "2.0", "id": call["id"], "result": result }).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                answer.len(),
+                answer
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            calls.push(call);
+        }
+        calls
+    });
+    (url, handle)
+}
+
+fn script_status(lock: &Script, block_number: u64) -> Value {
+    json!({
+        "script": json::Script::from(lock.clone()),
+        "script_type": "lock",
+        "block_number": format!("{:#x}", block_number),
+    })
+}
+
+fn output(lock: &Script, capacity: u64) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock.clone())
+        .build()
+}
+
+#[test]
+fn test_watch_registers_once() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let (url, handle) = serve(vec![json!([]), Value::Null]);
+    assert!(LightClient::new(&url).watch(&lock, 12_000_000).unwrap());
+    let calls = handle.join().unwrap();
+    assert_eq!(calls[0]["method"], "get_scripts");
+    assert_eq!(calls[1]["method"], "set_scripts");
+    assert_eq!(
+        calls[1]["params"][0],
+        json!([script_status(&lock, 12_000_000)])
+    );
+    assert_eq!(calls[1]["params"][1], "partial");
+
+    // already watched, its sync progress is kept
+    let status = json!([script_status(&lock, 12_345_678)]);
+    let (url, handle) = serve(vec![status.clone(), status]);
+    let client = LightClient::new(&url);
+    assert!(!client.watch(&lock, 12_000_000).unwrap());
+    assert_eq!(client.synced_block(&lock).unwrap(), Some(12_345_678));
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_watcher_on_light_client() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let (_, other) = random_config(2, 0, 1);
+    let deposit = TransactionBuilder::default()
+        .output(output(&lock, 2000))
+        .output_data(Bytes::new().pack())
+        .build();
+    let spend = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(deposit.hash(), 0), 0))
+        .output(output(&lock_script(&other), 1500))
+        .output_data(Bytes::new().pack())
+        .build();
+    let grouped = |tx: &TransactionView, block: u64, cell: &str| {
+        json!({
+            "transaction": json::TransactionView::from(tx.clone()),
+            "block_number": format!("{:#x}", block),
+            "tx_index": "0x1",
+            "cells": [[cell, "0x0"]],
+        })
+    };
+    let page = json!({
+        "objects": [grouped(&deposit, 40, "output"), grouped(&spend, 60, "input")],
+        "last_cursor": "0x",
+    });
+    // synced to block 100, 24 confirmations: blocks 0..77
+    let (url, handle) = serve(vec![json!([script_status(&lock, 100)]), page]);
+    let mut watcher =
+        DepositWatcher::light_client(&url, &config, &CODE_HASH, ScriptHashType::Data1);
+    let activity = watcher.poll_activity().unwrap();
+    let calls = handle.join().unwrap();
+    assert_eq!(calls[1]["method"], "get_transactions");
+    assert_eq!(
+        calls[1]["params"][0]["filter"]["block_range"],
+        json!(["0x0", "0x4d"])
+    );
+    assert_eq!(activity.len(), 2);
+    match &activity[0] {
+        Activity::Deposit(found) => {
+            assert_eq!(found.out_point, OutPoint::new(deposit.hash(), 0));
+            assert_eq!(found.block_number, 40);
+            assert_eq!(found.capacity(), 2000);
+        }
+        other => panic!("expected a deposit, got {:?}", other),
+    }
+    match &activity[1] {
+        Activity::Spend(found) => {
+            assert_eq!(found.tx.hash(), spend.hash());
+            assert_eq!(found.inputs, 1);
+            assert_eq!(found.outgoing, 1500);
+        }
+        other => panic!("expected a spend, got {:?}", other),
+    }
+    assert_eq!(watcher.next_block(), 77);
+}
+
+#[test]
+fn test_unwatched_lock_refused() {
+    let (_, config) = random_config(3, 0, 2);
+    let (url, handle) = serve(vec![json!([])]);
+    let mut watcher =
+        DepositWatcher::light_client(&url, &config, &CODE_HASH, ScriptHashType::Data1);
+    assert!(watcher.poll().is_err());
+    handle.join().unwrap();
+    assert_eq!(watcher.next_block(), 0);
+
+    let (url, handle) = serve(vec![json!([])]);
+    let scanner = Scanner::light_client(&url, &config, &CODE_HASH, ScriptHashType::Data1);
+    let tip = BlockInfo {
+        number: 0,
+        epoch: EpochNumberWithFraction::new(0, 0, 1),
+        timestamp: 0,
+    };
+    assert!(scanner.page(&tip, 10, None).is_err());
+    handle.join().unwrap();
+}
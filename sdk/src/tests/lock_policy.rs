@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use super::random_signer;
+use crate::{
+    config_tree::ConfigTree,
+    constants::BLAKE160_SIZE,
+    lock_policy::{Clause, Compiled, LockPolicy},
+    signer::Signer,
+    since::parse_since,
+    MultisigConfig,
+};
+
+fn members(names: &[&str]) -> BTreeMap<String, [u8; BLAKE160_SIZE]> {
+    names
+        .iter()
+        .map(|name| (name.to_string(), random_signer().identity().unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_parse() {
+    let policy: LockPolicy =
+        "after 90d allow 2 of [A, B,C] including [A]; after 90 days allow 1 of [D]"
+            .parse()
+            .unwrap();
+    let since = Some(parse_since("after 90 days").unwrap());
+    assert_eq!(
+        policy.clauses,
+        vec![
+            Clause {
+                since,
+                threshold: 2,
+                members: vec!["A".to_string(), "B".to_string(), "C".to_string()],
+                require_first_n: 1,
+            },
+            Clause {
+                since,
+                threshold: 1,
+                members: vec!["D".to_string()],
+                require_first_n: 0,
+            },
+        ]
+    );
+    let text = "after 90 days allow 2 of [A, B, C] including [A]; after 90 days allow 1 of [D]";
+    assert_eq!(policy.to_string(), text);
+    assert_eq!(text.parse::<LockPolicy>().unwrap(), policy);
+
+    for invalid in [
+        "",
+        "2 of A, B",
+        "two of [A, B]",
+        "2 of [A, , B]",
+        "2 of [A, B] including [B]",
+        "2 of [A, B] and more",
+        "after 90d 1 of [D]",
+        "after someday allow 1 of [D]",
+        "1 of [A];",
+    ] {
+        assert!(invalid.parse::<LockPolicy>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_compile() {
+    let members = members(&["A", "B", "C", "D"]);
+    let hash = |name: &str| members[name];
+
+    let policy: LockPolicy = "2 of [A, B, C] including [A]".parse().unwrap();
+    let compiled = policy.compile(&members).unwrap();
+    let config = MultisigConfig::new(vec![hash("A"), hash("B"), hash("C")], 1, 2).unwrap();
+    assert_eq!(compiled, Compiled::Config(config.clone()));
+    assert_eq!(compiled.lock_args(), config.lock_args());
+    assert_eq!(LockPolicy::decompile(&compiled, &members).unwrap(), policy);
+
+    let policy: LockPolicy = "after 90d allow 2 of [A, B, C]; after 90d allow 1 of [D]"
+        .parse()
+        .unwrap();
+    let compiled = policy.compile(&members).unwrap();
+    let since = Some(parse_since("after 90 days").unwrap());
+    let tree = ConfigTree::new(vec![
+        MultisigConfig::new(vec![hash("A"), hash("B"), hash("C")], 0, 2)
+            .unwrap()
+            .with_since(since),
+        MultisigConfig::new(vec![hash("D")], 0, 1)
+            .unwrap()
+            .with_since(since),
+    ])
+    .unwrap();
+    assert_eq!(compiled, Compiled::Tree(tree.clone()));
+    let configs = compiled.configs();
+    assert_eq!(configs.len(), 2);
+    assert!(configs
+        .iter()
+        .all(|config| config.lock_args() == compiled.lock_args()));
+    assert_eq!(configs[1], tree.config(1).unwrap());
+
+    // decompiled for review, by name or by pubkey hash
+    assert_eq!(
+        LockPolicy::decompile(&compiled, &members)
+            .unwrap()
+            .to_string(),
+        "after 90 days allow 2 of [A, B, C]; after 90 days allow 1 of [D]"
+    );
+    let anonymous = LockPolicy::decompile(&compiled, &BTreeMap::new()).unwrap();
+    assert_eq!(
+        anonymous.clauses[1].members,
+        vec![format!("0x{}", hex::encode(hash("D")))]
+    );
+    assert_eq!(anonymous.compile(&BTreeMap::new()).unwrap(), compiled);
+}
+
+#[test]
+fn test_reject_uncompilable() {
+    let members = members(&["A", "B", "C", "D"]);
+    for policy in [
+        // a timelock on one clause alone
+        "2 of [A, B, C]; after 90d allow 1 of [D]",
+        "4 of [A, B, C]",
+        "1 of [A, E]",
+        "1 of [A, A]",
+        "1 of [0x1234]",
+        "1 of [A]; 1 of [A]",
+    ] {
+        let parsed: LockPolicy = policy.parse().unwrap();
+        assert!(parsed.compile(&members).is_err(), "{}", policy);
+    }
+
+    let config = MultisigConfig::new(vec![members["A"]], 0, 1)
+        .unwrap()
+        .with_header_time(true);
+    assert!(LockPolicy::decompile(&Compiled::Config(config), &members).is_err());
+}
@@ -0,0 +1,197 @@
+use ckb_sdk::{constants::DAO_TYPE_HASH, traits::LiveCell};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, EpochNumberWithFraction, ScriptHashType},
+    packed::{CellOutput, OutPoint, Script},
+    prelude::*,
+};
+use secp256k1::rand;
+
+use super::{lock_script, random_config};
+use crate::{
+    migrate::Migration,
+    request::SigningRequest,
+    scanner::{BlockInfo, CellSet, Maturity, MultisigCell},
+    unlock::{BoxedSigner, MultisigScriptSigner},
+};
+
+fn cell(lock: &Script, capacity: u64, type_: Option<Script>, data: &[u8]) -> LiveCell {
+    LiveCell {
+        output: CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(lock.clone())
+            .type_(type_.pack())
+            .build(),
+        output_data: Bytes::copy_from_slice(data),
+        out_point: OutPoint::new(rand::random::<[u8; 32]>().pack(), 0),
+        block_number: 0,
+        tx_index: 0,
+    }
+}
+
+fn mature(cell: LiveCell) -> MultisigCell {
+    MultisigCell {
+        cell,
+        maturity: Maturity::Mature,
+    }
+}
+
+fn cell_set(cells: Vec<MultisigCell>) -> CellSet {
+    CellSet {
+        tip: BlockInfo {
+            number: 0,
+            epoch: EpochNumberWithFraction::new(0, 0, 1),
+            timestamp: 0,
+        },
+        cells,
+    }
+}
+
+#[test]
+fn test_migration_plan() {
+    let (signers, old) = random_config(3, 1, 2);
+    let (_, new) = random_config(3, 0, 2);
+    let old_lock = lock_script(&old);
+    let new_lock = lock_script(&new);
+    let udt = Script::new_builder()
+        .code_hash([1u8; 32].pack())
+        .hash_type(ScriptHashType::Type)
+        .build();
+    let dao = Script::new_builder()
+        .code_hash(DAO_TYPE_HASH.pack())
+        .hash_type(ScriptHashType::Type)
+        .build();
+    let set = cell_set(vec![
+        mature(cell(&old_lock, 100_0000_0000, None, &[])),
+        mature(cell(
+            &old_lock,
+            200_0000_0000,
+            Some(udt.clone()),
+            &[7u8; 16],
+        )),
+        mature(cell(&old_lock, 300_0000_0000, None, &[])),
+        mature(cell(&old_lock, 400_0000_0000, Some(dao), &[0u8; 8])),
+        MultisigCell {
+            cell: cell(&old_lock, 500_0000_0000, None, &[]),
+            maturity: Maturity::BlockNumber(100),
+        },
+    ]);
+    let mut plan = Migration::new(
+        old.clone(),
+        old_lock.clone(),
+        new_lock.clone(),
+        vec![],
+        1000,
+    )
+    .plan(&set)
+    .unwrap();
+    assert_eq!(plan.requests.len(), 1);
+    assert_eq!(plan.pending.len(), 1);
+    assert_eq!(plan.skipped.len(), 1);
+
+    let request = &plan.requests[0];
+    let tx = &request.tx;
+    assert_eq!(tx.inputs().len(), 3);
+    assert_eq!(tx.outputs().len(), 2);
+    assert!(tx
+        .outputs()
+        .into_iter()
+        .all(|output| output.lock() == new_lock));
+    let typed = tx.output(0).unwrap();
+    assert_eq!(typed.type_().to_opt(), Some(udt));
+    assert_eq!(Unpack::<u64>::unpack(&typed.capacity()), 200_0000_0000);
+    assert_eq!(tx.outputs_data().get(0).unwrap().raw_data(), vec![7u8; 16]);
+    let merged: u64 = tx.output(1).unwrap().capacity().unpack();
+    assert_eq!(merged + request.fee, 400_0000_0000);
+
+    assert!(!plan.is_complete().unwrap());
+    let signers = signers
+        .into_iter()
+        .map(|s| Box::new(s) as BoxedSigner)
+        .collect();
+    plan.sign(&MultisigScriptSigner::new(old, signers)).unwrap();
+    assert!(plan.is_complete().unwrap());
+
+    // the json form keeps the signatures
+    let json = plan.requests[0].to_json().unwrap();
+    let request = SigningRequest::from_json(&json).unwrap();
+    assert_eq!(request.tx.hash(), plan.requests[0].tx.hash());
+    assert!(request.is_complete().unwrap());
+}
+
+#[test]
+fn test_migration_chunks() {
+    let (_, old) = random_config(2, 0, 1);
+    let (_, new) = random_config(2, 0, 1);
+    let old_lock = lock_script(&old);
+    let new_lock = lock_script(&new);
+    let udt = Script::new_builder()
+        .code_hash([1u8; 32].pack())
+        .hash_type(ScriptHashType::Type)
+        .build();
+    let mut cells: Vec<_> = (0..20)
+        .map(|_| {
+            mature(cell(
+                &old_lock,
+                200_0000_0000,
+                Some(udt.clone()),
+                &[1u8; 16],
+            ))
+        })
+        .collect();
+    cells.extend((0..5).map(|_| mature(cell(&old_lock, 100_0000_0000, None, &[]))));
+    let migration = Migration::new(
+        old.clone(),
+        old_lock.clone(),
+        new_lock.clone(),
+        vec![],
+        1000,
+    )
+    .max_tx_size(2000);
+    let plan = migration.plan(&cell_set(cells.clone())).unwrap();
+    assert!(plan.requests.len() > 1);
+    let mut inputs = 0;
+    for request in &plan.requests {
+        let size = request.tx.data().as_reader().serialized_size_in_block()
+            + crate::fee::witness_size(&old);
+        assert!(size <= 2000);
+        inputs += request.tx.inputs().len();
+    }
+    assert_eq!(inputs, 25);
+
+    // typed cells alone can't pay
+    let typed_only = cell_set(cells[..20].to_vec());
+    assert!(migration.plan(&typed_only).is_err());
+}
+
+#[test]
+fn test_migration_combine() {
+    let (signers, old) = random_config(3, 0, 2);
+    let (_, new) = random_config(3, 0, 2);
+    let old_lock = lock_script(&old);
+    let set = cell_set(vec![
+        mature(cell(&old_lock, 100_0000_0000, None, &[])),
+        mature(cell(&old_lock, 300_0000_0000, None, &[])),
+    ]);
+    let mut plan = Migration::new(old.clone(), old_lock, lock_script(&new), vec![], 1000)
+        .plan(&set)
+        .unwrap();
+    let mut copy = plan.clone();
+    let mut signers = signers.into_iter().map(|s| Box::new(s) as BoxedSigner);
+    let first = signers.next().unwrap();
+    let second = signers.next().unwrap();
+    let identities = [first.identity().unwrap(), second.identity().unwrap()];
+    plan.sign(&MultisigScriptSigner::new(old.clone(), vec![first]))
+        .unwrap();
+    copy.sign(&MultisigScriptSigner::new(old, vec![second]))
+        .unwrap();
+    assert!(!plan.is_complete().unwrap());
+    assert_eq!(plan.combine(&copy).unwrap(), vec![identities[1]]);
+    assert!(plan.is_complete().unwrap());
+    // nothing new the second time
+    assert!(plan.combine(&copy).unwrap().is_empty());
+
+    let mut shorter = copy.clone();
+    shorter.requests.clear();
+    assert!(plan.combine(&shorter).is_err());
+}
@@ -0,0 +1,143 @@
+use ckb_sdk::{
+    constants::SIGHASH_TYPE_HASH, tx_builder::SinceSource, types::ScriptGroup,
+    unlock::generate_message as sdk_generate_message,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{ScriptHashType, TransactionView},
+    packed::{Script, WitnessArgs},
+    prelude::*,
+};
+use secp256k1::rand;
+
+use super::{gen_tx, lock_script, random_config, CODE_HASH};
+use crate::{
+    mixed::{MixedFunding, Party},
+    unlock::{BoxedSigner, MultisigScriptSigner},
+    witness::MultisigLock,
+    MultisigConfig, Signer,
+};
+
+fn sighash_lock() -> Script {
+    Script::new_builder()
+        .code_hash(SIGHASH_TYPE_HASH.pack())
+        .hash_type(ScriptHashType::Type)
+        .args(Bytes::from(rand::random::<[u8; 20]>().to_vec()).pack())
+        .build()
+}
+
+/// Two treasury inputs and a fee payer input, placeholders filled.
+fn gen_mixed_tx(config: &MultisigConfig) -> (MixedFunding, TransactionView, Vec<ScriptGroup>) {
+    let funding = MixedFunding::new(config.clone(), lock_script(config), sighash_lock()).unwrap();
+    let (tx, treasury) = gen_tx(config, 2);
+    let mut fee_payer = ScriptGroup::from_lock_script(funding.fee_payer());
+    fee_payer.input_indices = vec![2];
+    let groups = vec![fee_payer, treasury];
+    let tx = funding.fill_placeholders(&tx, &groups).unwrap();
+    (funding, tx, groups)
+}
+
+fn set_witness(tx: &TransactionView, index: usize, lock: Bytes) -> TransactionView {
+    let mut witnesses: Vec<_> = tx.witnesses().into_iter().collect();
+    witnesses[index] = WitnessArgs::new_builder()
+        .lock(Some(lock).pack())
+        .build()
+        .as_bytes()
+        .pack();
+    tx.as_advanced_builder().set_witnesses(witnesses).build()
+}
+
+#[test]
+fn test_fee_payer_must_be_sighash() {
+    let (_, config) = random_config(3, 0, 2);
+    let other = config.lock_script(&CODE_HASH, ScriptHashType::Type);
+    assert!(MixedFunding::new(config.clone(), lock_script(&config), other).is_err());
+    let spend_all = config.clone().with_spend_all(true);
+    assert!(MixedFunding::new(spend_all.clone(), lock_script(&spend_all), sighash_lock()).is_err());
+}
+
+#[test]
+fn test_balancer_order_and_placeholders() {
+    let (_, config) = random_config(3, 0, 2);
+    let config = config.with_since(Some(0x2000_0000_0000_0010));
+    let funding = MixedFunding::new(config.clone(), lock_script(&config), sighash_lock()).unwrap();
+    let balancer = funding.balancer(1000);
+    assert_eq!(
+        balancer.change_lock_script.as_ref(),
+        Some(funding.fee_payer())
+    );
+    let providers = &balancer.capacity_provider.lock_scripts;
+    assert_eq!(providers.len(), 2);
+    assert_eq!(&providers[0].0, funding.fee_payer());
+    assert_eq!(
+        providers[0].1.lock().to_opt().unwrap().raw_data(),
+        Bytes::from(vec![0u8; 65])
+    );
+    assert_eq!(&providers[1].0, funding.treasury());
+    assert_eq!(
+        providers[1].1.lock().to_opt().unwrap().raw_data(),
+        config.placeholder_lock()
+    );
+    assert!(matches!(
+        providers[1].2,
+        SinceSource::Value(0x2000_0000_0000_0010)
+    ));
+}
+
+#[test]
+fn test_digests_per_party() {
+    let (signers, config) = random_config(3, 0, 2);
+    let (funding, tx, groups) = gen_mixed_tx(&config);
+    let digests = funding.digests(&tx, &groups).unwrap();
+    assert_eq!(digests.len(), 2);
+    assert_eq!(digests[0].party, Party::FeePayer);
+    assert_eq!(digests[1].party, Party::Treasury);
+
+    // the sighash lock signs the message of ckb-sdk with a 65 bytes lock
+    let expected = sdk_generate_message(&tx, &groups[0], Bytes::from(vec![0u8; 65])).unwrap();
+    assert_eq!(digests[0].digest[..], expected[..]);
+    let request = funding.request(&tx, &groups[1], 0).unwrap();
+    assert_eq!(digests[1].digest, request.message().unwrap());
+    assert!(funding.request(&tx, &groups[0], 0).is_err());
+
+    // the cosigners sign first, the fee payer message is unchanged
+    let boxed = signers
+        .into_iter()
+        .map(|s| Box::new(s) as BoxedSigner)
+        .collect();
+    let signed = MultisigScriptSigner::new(config.clone(), boxed)
+        .sign(&tx, &groups[1])
+        .unwrap();
+    let after = funding.digests(&signed, &groups).unwrap();
+    assert_eq!(after[0].digest, digests[0].digest);
+    assert_eq!(after[1].digest, digests[1].digest);
+    let lock = MultisigLock::parse(
+        &WitnessArgs::from_slice(&signed.witnesses().get(0).unwrap().raw_data())
+            .unwrap()
+            .lock()
+            .to_opt()
+            .unwrap()
+            .raw_data(),
+    )
+    .unwrap();
+    assert!(lock.is_complete());
+    assert!(lock.verify(&digests[1].digest).is_ok());
+
+    // the fee payer signs, the treasury message is unchanged
+    let fee_signer = super::random_signer();
+    let signature = fee_signer.sign(&digests[0].digest).unwrap();
+    let signed = set_witness(&signed, 2, Bytes::from(signature.to_vec()));
+    let after = funding.digests(&signed, &groups).unwrap();
+    assert_eq!(after[0].digest, digests[0].digest);
+    assert_eq!(after[1].digest, digests[1].digest);
+}
+
+#[test]
+fn test_foreign_group() {
+    let (_, config) = random_config(3, 0, 2);
+    let (funding, tx, mut groups) = gen_mixed_tx(&config);
+    let (_, other) = random_config(2, 0, 1);
+    groups.push(ScriptGroup::from_lock_script(&lock_script(&other)));
+    assert!(funding.digests(&tx, &groups).is_err());
+    assert!(funding.fill_placeholders(&tx, &groups).is_err());
+}
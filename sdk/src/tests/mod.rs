@@ -0,0 +1,121 @@
+use ckb_sdk::{traits::LiveCell, types::ScriptGroup};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType, TransactionBuilder, TransactionView},
+    packed::{CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use secp256k1::{rand, SecretKey};
+
+use crate::{MultisigConfig, SecpSigner, Signer};
+
+mod approval;
+mod audit;
+mod backend;
+mod balance;
+mod batch;
+mod bump;
+mod cobuild;
+mod collector;
+mod config;
+mod config_file;
+mod config_tree;
+mod dao;
+mod deploy;
+mod deployment;
+mod descriptor;
+mod digest;
+mod fee;
+mod hd;
+mod history;
+mod kms;
+mod ledger;
+mod light_client;
+mod lock_policy;
+mod migrate;
+mod mixed;
+mod nft;
+mod nonce;
+mod policy;
+mod providers;
+mod qr;
+mod request;
+mod safe;
+mod scanner;
+mod signer;
+mod since;
+mod successor;
+mod sweep;
+mod trezor;
+mod udt;
+mod unlock;
+mod validate;
+mod watcher;
+mod xpub;
+
+pub const CODE_HASH: H256 = H256([0x42; 32]);
+
+pub fn random_signer() -> SecpSigner {
+    SecpSigner::new(SecretKey::new(&mut rand::thread_rng()))
+}
+
+pub fn random_digest() -> [u8; 32] {
+    rand::random()
+}
+
+pub fn random_config(
+    keys: usize,
+    require_first_n: u8,
+    threshold: u8,
+) -> (Vec<SecpSigner>, MultisigConfig) {
+    let signers: Vec<_> = (0..keys).map(|_| random_signer()).collect();
+    let hashes = signers.iter().map(|s| s.identity().unwrap()).collect();
+    let config = MultisigConfig::new(hashes, require_first_n, threshold).unwrap();
+    (signers, config)
+}
+
+pub fn lock_script(config: &MultisigConfig) -> Script {
+    config.lock_script(&CODE_HASH, ScriptHashType::Data1)
+}
+
+/// A transaction spending `inputs` cells of the config, plus one cell of
+/// another lock at the end.
+pub fn gen_tx(config: &MultisigConfig, inputs: usize) -> (TransactionView, ScriptGroup) {
+    let lock = lock_script(config);
+    let mut group = ScriptGroup::from_lock_script(&lock);
+    let mut builder = TransactionBuilder::default();
+    for i in 0..=inputs {
+        let out_point = OutPoint::new(rand::random::<[u8; 32]>().pack(), i as u32);
+        builder = builder.input(CellInput::new(out_point, 0));
+        if i < inputs {
+            group.input_indices.push(i);
+        }
+    }
+    let tx = builder
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::shannons(42).pack())
+                .lock(lock)
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .build();
+    (tx, group)
+}
+
+/// A live cell of `output` and `data`, at a random out point.
+pub fn live_cell(output: CellOutput, data: Bytes) -> LiveCell {
+    LiveCell {
+        output,
+        output_data: data,
+        out_point: OutPoint::new(rand::random::<[u8; 32]>().pack(), 0),
+        block_number: 0,
+        tx_index: 0,
+    }
+}
+
+/// The capacity of `output`, in shannons.
+pub fn capacity(output: &CellOutput) -> u64 {
+    output.capacity().unpack()
+}
@@ -0,0 +1,216 @@
+use ckb_sdk::traits::LiveCell;
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, DepType, ScriptHashType},
+    packed::{CellDep, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use secp256k1::rand;
+
+use super::{capacity, live_cell, lock_script, random_config};
+use crate::nft::{spore_cluster_id, NftStandard, NftTransfer, NftTransferItem};
+
+const CKB: u64 = 100_000_000;
+const SPORE_CODE_HASH: H256 = H256([0x5a; 32]);
+
+fn out_point(byte: u8) -> OutPoint {
+    OutPoint::new(H256([byte; 32]).pack(), 0)
+}
+
+fn cell_dep(byte: u8) -> CellDep {
+    CellDep::new_builder()
+        .out_point(out_point(byte))
+        .dep_type(DepType::Code)
+        .build()
+}
+
+fn spore_standard() -> NftStandard {
+    NftStandard {
+        name: "spore".to_string(),
+        code_hash: SPORE_CODE_HASH,
+        hash_type: ScriptHashType::Data1,
+        cell_deps: vec![cell_dep(0xd1)],
+    }
+}
+
+/// The molecule `SporeData` of an image, in `cluster_id` when given.
+fn spore_data(cluster_id: Option<[u8; 32]>) -> Bytes {
+    let bytes = |content: &[u8]| {
+        let mut field = (content.len() as u32).to_le_bytes().to_vec();
+        field.extend_from_slice(content);
+        field
+    };
+    let fields = [
+        bytes(b"image/png"),
+        bytes(&[0x89, b'P', b'N', b'G']),
+        cluster_id.map(|id| bytes(&id)).unwrap_or_default(),
+    ];
+    let header = 4 * (fields.len() + 1);
+    let total = header + fields.iter().map(Vec::len).sum::<usize>();
+    let mut data = (total as u32).to_le_bytes().to_vec();
+    let mut offset = header;
+    for field in &fields {
+        data.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    for field in &fields {
+        data.extend_from_slice(field);
+    }
+    Bytes::from(data)
+}
+
+fn spore(lock: &Script, capacity: u64, data: Bytes) -> LiveCell {
+    let type_script = Script::new_builder()
+        .code_hash(SPORE_CODE_HASH.pack())
+        .hash_type(ScriptHashType::Data1)
+        .args(Bytes::from(rand::random::<[u8; 32]>().to_vec()).pack())
+        .build();
+    let output = CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock.clone())
+        .type_(Some(type_script).pack())
+        .build();
+    live_cell(output, data)
+}
+
+fn plain(lock: &Script, capacity: u64) -> LiveCell {
+    let output = CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock.clone())
+        .build();
+    live_cell(output, Bytes::new())
+}
+
+fn recipient(args: usize) -> Script {
+    Script::new_builder()
+        .code_hash(H256([7; 32]).pack())
+        .hash_type(ScriptHashType::Type)
+        .args(Bytes::from(vec![7; args]).pack())
+        .build()
+}
+
+#[test]
+fn test_spore_cluster_id() {
+    let cluster = [0xc1; 32];
+    assert_eq!(spore_cluster_id(&spore_data(Some(cluster))), Some(cluster));
+    assert_eq!(spore_cluster_id(&spore_data(None)), None);
+    assert_eq!(spore_cluster_id(&[]), None);
+    assert_eq!(spore_cluster_id(b"not a table"), None);
+    let mut truncated = spore_data(Some(cluster)).to_vec();
+    truncated.pop();
+    assert_eq!(spore_cluster_id(&truncated), None);
+}
+
+#[test]
+fn test_transfer_keeps_the_token() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let cluster = [0xc1; 32];
+    let token = spore(&lock, 400 * CKB, spore_data(Some(cluster)));
+    let builder = NftTransfer::new(config, lock.clone(), vec![cell_dep(0xd0)], 1000)
+        .standard(spore_standard())
+        .cluster(cluster, out_point(0xc1));
+    let item = NftTransferItem {
+        cell: token.clone(),
+        to: recipient(20),
+    };
+    let request = builder.build(&[item], &[plain(&lock, 100 * CKB)]).unwrap();
+    let tx = &request.tx;
+
+    assert_eq!(tx.inputs().len(), 2);
+    assert_eq!(request.script_group.input_indices, vec![0, 1]);
+    let sent = tx.outputs().get(0).unwrap();
+    assert_eq!(sent.lock(), recipient(20));
+    assert_eq!(sent.type_(), token.output.type_());
+    assert_eq!(capacity(&sent), 400 * CKB);
+    assert_eq!(
+        tx.outputs_data().get(0).unwrap().raw_data(),
+        token.output_data
+    );
+    let deps: Vec<_> = tx.cell_deps().into_iter().collect();
+    assert_eq!(deps, vec![cell_dep(0xd0), cell_dep(0xd1), cell_dep(0xc1)]);
+
+    let change = tx.outputs().get(1).unwrap();
+    assert_eq!(change.lock(), lock);
+    assert!(change.type_().is_none());
+    assert_eq!(capacity(&change) + request.fee, 100 * CKB);
+    assert!(request.fee > 0 && request.fee < CKB / 1000);
+}
+
+#[test]
+fn test_transfer_tops_up_a_larger_lock() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let data = spore_data(None);
+    let occupied = |lock: &Script| {
+        let output = spore(lock, 0, data.clone()).output;
+        let data = Capacity::bytes(data.len()).unwrap();
+        output.occupied_capacity(data).unwrap().as_u64()
+    };
+    let token = spore(&lock, occupied(&lock), data.clone());
+    let to = recipient(100);
+    let builder = NftTransfer::new(config, lock.clone(), vec![], 1000).standard(spore_standard());
+    let item = NftTransferItem {
+        cell: token,
+        to: to.clone(),
+    };
+    let request = builder.build(&[item], &[plain(&lock, 200 * CKB)]).unwrap();
+
+    let sent = request.tx.outputs().get(0).unwrap();
+    assert_eq!(capacity(&sent), occupied(&to));
+    let change = request.tx.outputs().get(1).unwrap();
+    let top_up = occupied(&to) - occupied(&lock);
+    assert_eq!(capacity(&change) + request.fee + top_up, 200 * CKB);
+}
+
+#[test]
+fn test_transfer_refuses() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let builder = NftTransfer::new(config, lock.clone(), vec![], 1000).standard(spore_standard());
+    let item = |cell: LiveCell| NftTransferItem {
+        cell,
+        to: recipient(20),
+    };
+    let fee_cells = [plain(&lock, 100 * CKB)];
+
+    // no token, a plain cell, the token of another lock, an unknown type
+    assert!(builder.build(&[], &fee_cells).is_err());
+    assert!(builder
+        .build(&[item(plain(&lock, 100 * CKB))], &fee_cells)
+        .is_err());
+    let other = recipient(20);
+    assert!(builder
+        .build(
+            &[item(spore(&other, 400 * CKB, spore_data(None)))],
+            &fee_cells
+        )
+        .is_err());
+    let mut unknown = spore(&lock, 400 * CKB, spore_data(None));
+    let type_script = unknown.output.type_().to_opt().unwrap();
+    unknown.output = unknown
+        .output
+        .as_builder()
+        .type_(
+            Some(
+                type_script
+                    .as_builder()
+                    .code_hash(H256([1; 32]).pack())
+                    .build(),
+            )
+            .pack(),
+        )
+        .build();
+    assert!(builder.build(&[item(unknown)], &fee_cells).is_err());
+
+    // fee cells which are not plain cells of the config, or too small
+    let token = spore(&lock, 400 * CKB, spore_data(None));
+    assert!(builder
+        .build(
+            &[item(token.clone())],
+            &[spore(&lock, 100 * CKB, spore_data(None))]
+        )
+        .is_err());
+    assert!(builder.build(&[item(token)], &[]).is_err());
+}
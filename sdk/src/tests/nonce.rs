@@ -0,0 +1,107 @@
+use ckb_sdk::traits::LiveCell;
+use ckb_types::{
+    bytes::Bytes,
+    core::{ScriptHashType, TransactionBuilder},
+    packed::{CellInput, OutPoint},
+    prelude::*,
+    H256,
+};
+use secp256k1::rand;
+
+use super::{gen_tx, lock_script, random_config};
+use crate::{
+    error::Error,
+    nonce::{bump, nonce_data, parse_nonce, type_id, NonceScript},
+};
+
+const NONCE_CODE_HASH: H256 = H256([0x24; 32]);
+
+fn nonce_script() -> NonceScript {
+    NonceScript::new(NONCE_CODE_HASH, ScriptHashType::Data1)
+}
+
+#[test]
+fn test_create_nonce() {
+    let (_, config) = random_config(3, 0, 2);
+    let (tx, _) = gen_tx(&config, 2);
+    let (tx, type_script) = nonce_script()
+        .create(&tx, lock_script(&config), 200_0000_0000)
+        .unwrap();
+
+    let index = tx.outputs().len() - 1;
+    let output = tx.output(index).unwrap();
+    assert_eq!(output.type_().to_opt(), Some(type_script.clone()));
+    assert_eq!(
+        parse_nonce(&tx.outputs_data().get(index).unwrap().raw_data()).unwrap(),
+        0
+    );
+    let expected = type_id(&tx.inputs().get(0).unwrap(), index as u64);
+    assert_eq!(type_script.args().raw_data()[..], expected[..]);
+    assert_eq!(type_script, nonce_script().type_script(&expected));
+}
+
+#[test]
+fn test_create_nonce_checks() {
+    let (_, config) = random_config(3, 0, 2);
+    let empty = TransactionBuilder::default().build();
+    assert!(matches!(
+        nonce_script().create(&empty, lock_script(&config), 200_0000_0000),
+        Err(Error::InvalidParameter(_))
+    ));
+
+    let (tx, _) = gen_tx(&config, 1);
+    assert!(matches!(
+        nonce_script().create(&tx, lock_script(&config), 100_0000_0000),
+        Err(Error::InsufficientCapacity(_))
+    ));
+}
+
+#[test]
+fn test_type_id() {
+    let input = CellInput::new(OutPoint::new(rand::random::<[u8; 32]>().pack(), 1), 0);
+    assert_eq!(type_id(&input, 0), type_id(&input, 0));
+    assert_ne!(type_id(&input, 0), type_id(&input, 1));
+}
+
+#[test]
+fn test_bump_nonce() {
+    let (_, config) = random_config(3, 0, 2);
+    let (tx, _) = gen_tx(&config, 1);
+    let (created, _) = nonce_script()
+        .create(&tx, lock_script(&config), 200_0000_0000)
+        .unwrap();
+    let index = created.outputs().len() - 1;
+    let cell = LiveCell {
+        output: created.output(index).unwrap(),
+        output_data: nonce_data(41),
+        out_point: OutPoint::new(created.hash(), index as u32),
+        block_number: 0,
+        tx_index: 0,
+    };
+
+    let (bumped, nonce) = bump(&tx, &cell).unwrap();
+    assert_eq!(nonce, 41);
+    assert_eq!(bumped.inputs().len(), tx.inputs().len() + 1);
+    let last = bumped.inputs().len() - 1;
+    assert_eq!(
+        bumped.inputs().get(last).unwrap().previous_output(),
+        cell.out_point
+    );
+    let index = bumped.outputs().len() - 1;
+    assert_eq!(bumped.output(index).unwrap(), cell.output);
+    assert_eq!(
+        bumped.outputs_data().get(index).unwrap().raw_data(),
+        nonce_data(42)
+    );
+
+    let exhausted = LiveCell {
+        output_data: nonce_data(u64::MAX),
+        ..cell.clone()
+    };
+    assert!(bump(&tx, &exhausted).is_err());
+    let malformed = LiveCell {
+        output_data: Bytes::from_static(&[1, 2, 3]),
+        ..cell
+    };
+    assert!(bump(&tx, &malformed).is_err());
+}
@@ -0,0 +1,241 @@
+use ckb_sdk::{Address, AddressPayload, NetworkType};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType},
+    packed::{CellOutput, Script},
+    prelude::*,
+};
+
+use super::{gen_tx, random_config, random_digest};
+use crate::{
+    policy::{outgoing, BusinessHours, Context, Policy, SpendLedger, Violation},
+    request::SigningRequest,
+    SecpSigner, Signer,
+};
+
+const CKB: u64 = 100_000_000;
+/// 2024-01-01 00:00 UTC, a Monday.
+const MONDAY: u64 = 1_704_067_200;
+const HOUR: u64 = 3600;
+
+fn destination(byte: u8) -> Script {
+    Script::new_builder()
+        .code_hash([byte; 32].pack())
+        .hash_type(ScriptHashType::Type)
+        .args(Bytes::from(vec![byte; 20]).pack())
+        .build()
+}
+
+fn address(lock: &Script) -> String {
+    Address::new(
+        NetworkType::Testnet,
+        AddressPayload::from(lock.clone()),
+        true,
+    )
+    .to_string()
+}
+
+/// A request of a 2 of 3 config paying 100 CKB to `destination(1)` and 50
+/// CKB to `destination(2)`.
+fn request() -> (Vec<SecpSigner>, SigningRequest) {
+    let (signers, config) = random_config(3, 0, 2);
+    let (tx, script_group) = gen_tx(&config, 1);
+    let output = |lock: Script, capacity: u64| {
+        CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(lock)
+            .build()
+    };
+    let tx = tx
+        .as_advanced_builder()
+        .output(output(destination(1), 100 * CKB))
+        .output_data(Bytes::new().pack())
+        .output(output(destination(2), 50 * CKB))
+        .output_data(Bytes::new().pack())
+        .build();
+    let request = SigningRequest {
+        config,
+        tx,
+        script_group,
+        fee: 1000,
+        genesis_hash: None,
+    };
+    (signers, request)
+}
+
+#[test]
+fn test_from_toml() {
+    let policy = Policy::from_toml(&format!(
+        r#"
+        allowed_destinations = ["{}"]
+        max_per_transaction = "120"
+        daily_limit = "200.5"
+        required_approvers = ["0x{}"]
+
+        [business_hours]
+        utc_offset = 480
+        days = ["mon", "fri"]
+        start = "09:00"
+        end = "18:30"
+        "#,
+        address(&destination(1)),
+        hex::encode([7u8; 20]),
+    ))
+    .unwrap();
+    assert_eq!(policy.allowed_destinations, Some(vec![destination(1)]));
+    assert_eq!(policy.max_per_transaction, Some(120 * CKB));
+    assert_eq!(policy.daily_limit, Some(200 * CKB + CKB / 2));
+    assert_eq!(policy.required_approvers, vec![[7u8; 20]]);
+    assert_eq!(
+        policy.business_hours,
+        Some(BusinessHours {
+            utc_offset: 480,
+            days: vec![1, 5],
+            start: 9 * 60,
+            end: 18 * 60 + 30,
+        })
+    );
+    assert_eq!(Policy::from_toml("").unwrap(), Policy::default());
+
+    let hours = |days: &str, start: &str, end: &str| {
+        format!(
+            "[business_hours]\ndays = [{}]\nstart = \"{}\"\nend = \"{}\"",
+            days, start, end
+        )
+    };
+    for invalid in [
+        "allowed_destinations = [\"ckb1nope\"]".to_string(),
+        "max_per_transaction = \"1.123456789\"".to_string(),
+        "required_approvers = [\"0x1234\"]".to_string(),
+        "max_fee = \"1\"".to_string(),
+        hours("\"someday\"", "09:00", "18:00"),
+        hours("\"mon\"", "9h", "18:00"),
+        hours("\"mon\"", "18:00", "09:00"),
+        hours("\"mon\"", "09:00", "24:01"),
+    ] {
+        assert!(Policy::from_toml(&invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_evaluate() {
+    let (signers, mut request) = request();
+    assert_eq!(outgoing(&request), 150 * CKB);
+    assert_eq!(
+        Policy::default()
+            .evaluate(&request, &Context::default())
+            .unwrap(),
+        vec![]
+    );
+
+    let approver = signers[0].identity().unwrap();
+    let policy = Policy {
+        allowed_destinations: Some(vec![destination(1)]),
+        max_per_transaction: Some(120 * CKB),
+        daily_limit: Some(200 * CKB),
+        required_approvers: vec![approver],
+        business_hours: Some(BusinessHours {
+            utc_offset: 0,
+            days: vec![1, 2, 3, 4, 5],
+            start: 9 * 60,
+            end: 18 * 60,
+        }),
+    };
+    let context = Context {
+        now: MONDAY + 20 * HOUR,
+        spent: 100 * CKB,
+        signers: vec![signers[1].identity().unwrap()],
+    };
+    assert_eq!(
+        policy.evaluate(&request, &context).unwrap(),
+        vec![
+            Violation::Destination {
+                lock: destination(2)
+            },
+            Violation::TransactionLimit {
+                value: 150 * CKB,
+                limit: 120 * CKB
+            },
+            Violation::DailyLimit {
+                value: 150 * CKB,
+                spent: 100 * CKB,
+                limit: 200 * CKB
+            },
+            Violation::OutsideBusinessHours,
+            Violation::MissingApprover { identity: approver },
+        ]
+    );
+    assert_eq!(
+        Violation::MissingApprover { identity: approver }.to_string(),
+        format!("0x{} hasn't signed yet", hex::encode(approver))
+    );
+
+    // the approver signing, or having signed, is enough
+    let policy = Policy {
+        allowed_destinations: Some(vec![destination(1), destination(2)]),
+        max_per_transaction: Some(150 * CKB),
+        daily_limit: Some(250 * CKB),
+        ..policy
+    };
+    let context = Context {
+        now: MONDAY + 10 * HOUR,
+        ..context
+    };
+    let approving = Context {
+        signers: vec![approver],
+        ..context.clone()
+    };
+    assert_eq!(policy.evaluate(&request, &approving).unwrap(), vec![]);
+    let message = request.message().unwrap();
+    request
+        .add_signature(signers[0].sign(&message).unwrap())
+        .unwrap();
+    assert_eq!(policy.evaluate(&request, &context).unwrap(), vec![]);
+}
+
+#[test]
+fn test_business_hours() {
+    let hours = BusinessHours {
+        utc_offset: 480,
+        days: vec![1, 2, 3, 4, 5],
+        start: 9 * 60,
+        end: 18 * 60,
+    };
+    // 10:00 and 18:00 on Monday in UTC+8
+    assert!(hours.contains(MONDAY + 2 * HOUR));
+    assert!(!hours.contains(MONDAY + 10 * HOUR));
+    // 10:00 on Saturday in UTC+8
+    assert!(!hours.contains(MONDAY + 5 * 24 * HOUR + 2 * HOUR));
+    // 22:00 on Sunday in UTC-5
+    let west = BusinessHours {
+        utc_offset: -300,
+        ..hours
+    };
+    assert!(!west.contains(MONDAY + 3 * HOUR));
+    assert!(west.contains(MONDAY + 15 * HOUR));
+}
+
+#[test]
+fn test_spend_ledger() {
+    let path = std::env::temp_dir().join(format!(
+        "ckb-multisig-spends-{}.json",
+        hex::encode(random_digest())
+    ));
+    let mut ledger = SpendLedger::open(&path).unwrap();
+    assert_eq!(ledger.spent(MONDAY), 0);
+    let (_, first) = request();
+    let (_, second) = request();
+    ledger.record(&first, MONDAY);
+    // signing the same transaction again doesn't count twice
+    ledger.record(&first, MONDAY + HOUR);
+    ledger.record(&second, MONDAY + 12 * HOUR);
+    assert_eq!(ledger.spent(MONDAY + 12 * HOUR), 300 * CKB);
+    ledger.save(MONDAY + 12 * HOUR).unwrap();
+
+    let mut ledger = SpendLedger::open(&path).unwrap();
+    assert_eq!(ledger.spent(MONDAY + 12 * HOUR), 300 * CKB);
+    assert_eq!(ledger.spent(MONDAY + 24 * HOUR), 150 * CKB);
+    ledger.save(MONDAY + 24 * HOUR).unwrap();
+    assert_eq!(SpendLedger::open(&path).unwrap().spent(MONDAY), 150 * CKB);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use ckb_jsonrpc_types as json;
+use ckb_sdk::traits::{CellCollector, CellQueryOptions, HeaderDepResolver, LiveCell};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, EpochNumberWithFraction, HeaderBuilder, HeaderView, TransactionBuilder},
+    packed::{CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+};
+use serde_json::{json, Value};
+
+use super::{capacity, light_client::serve, lock_script, random_config};
+use crate::{
+    backend::NodeIndexer,
+    collector::BlockInfoProvider,
+    light_client::LightClient,
+    providers::{BackendCellCollector, BackendProvider},
+};
+
+fn output(lock: &Script, capacity: u64) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock.clone())
+        .build()
+}
+
+/// A cell of the indexer, not a cellbase output.
+fn indexer_cell(lock: &Script, capacity: u64, tx_byte: u8) -> Value {
+    json!({
+        "output": json::CellOutput::from(output(lock, capacity)),
+        "output_data": "0x",
+        "out_point": json::OutPoint::from(OutPoint::new([tx_byte; 32].pack(), 0)),
+        "block_number": "0x10",
+        "tx_index": "0x1",
+    })
+}
+
+fn committed(tx: json::TransactionView, header: &HeaderView) -> Value {
+    json!({
+        "transaction": tx,
+        "tx_status": {
+            "status": "committed",
+            "block_hash": json::Byte32::from(header.hash()),
+        },
+    })
+}
+
+#[test]
+fn test_collector_over_backend() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let page = json!({
+        "objects": [indexer_cell(&lock, 1000, 1), indexer_cell(&lock, 2000, 2)],
+        "last_cursor": "0x01",
+    });
+    let empty = json!({ "objects": [], "last_cursor": "0x01" });
+    let (url, handle) = serve(vec![page.clone(), page.clone(), empty, page]);
+    let mut collector = BackendCellCollector::new(Arc::new(NodeIndexer::new(&url)));
+    let mut query = CellQueryOptions::new_lock(lock.clone());
+    query.min_total_capacity = 1500;
+    let (cells, total) = collector.collect_live_cells(&query, true).unwrap();
+    assert_eq!(cells.len(), 2);
+    assert_eq!(total, 3000);
+
+    // the change of a transaction spending the first cell, the second locked
+    let change = output(&lock, 500);
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(cells[0].out_point.clone(), 0))
+        .output(change.clone())
+        .output_data(Bytes::new().pack())
+        .build();
+    collector.apply_tx(tx.data(), 0).unwrap();
+    query.min_total_capacity = 400;
+    let (found, total) = collector.collect_live_cells(&query, false).unwrap();
+    assert_eq!(total, 500);
+    assert_eq!(found[0].out_point, OutPoint::new(tx.hash(), 0));
+
+    // past the change, the cells of the backend less the locked ones
+    query.min_total_capacity = 1000;
+    let (found, total) = collector.collect_live_cells(&query, false).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(total, 500);
+    collector.reset();
+    let (found, _) = collector.collect_live_cells(&query, false).unwrap();
+    assert_eq!(capacity(&found[0].output), 1000);
+
+    let calls = handle.join().unwrap();
+    assert_eq!(calls[0]["method"], "get_cells");
+    assert_eq!(calls[0]["params"][1], "asc");
+    assert!(calls[1]["params"][3].is_null());
+    // then on past the page of locked cells
+    assert_eq!(calls[2]["params"][3], "0x01");
+}
+
+#[test]
+fn test_collector_skips_cellbase() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let mut cellbase = indexer_cell(&lock, 1000, 1);
+    cellbase["tx_index"] = json!("0x0");
+    let page = json!({ "objects": [cellbase], "last_cursor": "0x01" });
+    let empty = json!({ "objects": [], "last_cursor": "0x01" });
+    let (url, handle) = serve(vec![page, empty]);
+    let mut collector = BackendCellCollector::new(Arc::new(NodeIndexer::new(&url)));
+    let query = CellQueryOptions::new_lock(lock);
+    let (cells, total): (Vec<LiveCell>, u64) = collector.collect_live_cells(&query, false).unwrap();
+    assert!(cells.is_empty());
+    assert_eq!(total, 0);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_provider_resolves_by_transaction() {
+    let (_, config) = random_config(3, 0, 2);
+    let tx = TransactionBuilder::default()
+        .output(output(&lock_script(&config), 1000))
+        .output_data(Bytes::new().pack())
+        .build();
+    let header = HeaderBuilder::default()
+        .number(42)
+        .epoch(EpochNumberWithFraction::new(1, 2, 1000).full_value())
+        .build();
+    let (url, handle) = serve(vec![
+        committed(tx.clone().into(), &header),
+        json!(json::HeaderView::from(header.clone())),
+    ]);
+    let provider = BackendProvider::new(Arc::new(LightClient::new(&url)));
+    // the light client looks blocks up by hash only
+    assert!(provider.block(42).is_err());
+    let resolved = provider.resolve_by_tx(&tx.hash()).unwrap().unwrap();
+    assert_eq!(resolved.hash(), header.hash());
+    // then by number, from the headers kept
+    let resolved = provider.resolve_by_number(42).unwrap().unwrap();
+    assert_eq!(resolved.hash(), header.hash());
+    assert_eq!(provider.block(42).unwrap().number, 42);
+
+    let calls = handle.join().unwrap();
+    assert_eq!(calls[0]["method"], "get_transaction");
+    assert_eq!(calls[1]["method"], "get_header");
+}
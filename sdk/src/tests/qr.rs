@@ -0,0 +1,134 @@
+use ckb_types::prelude::*;
+
+use super::{gen_tx, random_config};
+use crate::{
+    approval::MAINNET_GENESIS_HASH,
+    qr::{FrameDecoder, Payload, SignatureSet, DEFAULT_FRAME_SIZE},
+    request::SigningRequest,
+    unlock::MultisigScriptSigner,
+};
+
+fn request(inputs: usize) -> (Vec<crate::SecpSigner>, SigningRequest) {
+    let (signers, config) = random_config(3, 1, 2);
+    let config = config.with_since(Some(0x2000_0000_0000_0010));
+    let (tx, script_group) = gen_tx(&config, inputs);
+    let request = SigningRequest {
+        config,
+        tx,
+        script_group,
+        fee: 1234,
+        genesis_hash: None,
+    };
+    (signers, request)
+}
+
+fn decode(frames: &[String]) -> Payload {
+    let mut decoder = FrameDecoder::new();
+    let mut payload = None;
+    for frame in frames {
+        assert!(payload.is_none());
+        payload = decoder.receive(frame).unwrap();
+    }
+    payload.unwrap()
+}
+
+#[test]
+fn test_request_frames() {
+    let (_, mut request) = request(40);
+    request.genesis_hash = Some(MAINNET_GENESIS_HASH);
+    let frames = Payload::Request(Box::new(request.clone()))
+        .to_frames(200)
+        .unwrap();
+    assert!(frames.len() > 1);
+    assert!(frames.iter().all(|frame| frame.len() <= 200));
+    assert!(frames.iter().all(|frame| frame
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || "/-".contains(c))));
+
+    // out of order, with a frame scanned twice
+    let mut shuffled: Vec<_> = frames.iter().rev().cloned().collect();
+    shuffled.insert(1, frames[frames.len() - 1].clone());
+    match decode(&shuffled) {
+        Payload::Request(decoded) => {
+            assert_eq!(decoded.tx.hash(), request.tx.hash());
+            assert_eq!(decoded.config, request.config);
+            assert_eq!(decoded.script_group.script, request.script_group.script);
+            assert_eq!(
+                decoded.script_group.input_indices,
+                request.script_group.input_indices
+            );
+            assert_eq!(decoded.fee, 1234);
+            assert_eq!(decoded.genesis_hash, Some(MAINNET_GENESIS_HASH));
+        }
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+#[test]
+fn test_signature_frames() {
+    let (signers, mut request) = request(2);
+    let mut signed = request.clone();
+    let signers = signers
+        .into_iter()
+        .take(1)
+        .map(|signer| Box::new(signer) as _)
+        .collect();
+    let signer = MultisigScriptSigner::new(request.config.clone(), signers);
+    signed.sign(&signer).unwrap();
+    let set = SignatureSet::from_request(&signed).unwrap();
+    assert_eq!(set.signatures.len(), 1);
+
+    let frames = Payload::Signatures(set.clone())
+        .to_frames(DEFAULT_FRAME_SIZE)
+        .unwrap();
+    assert_eq!(frames.len(), 1);
+    match decode(&frames) {
+        Payload::Signatures(decoded) => assert_eq!(decoded, set),
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+
+    set.apply(&mut request).unwrap();
+    // applying twice doesn't add the signature again
+    set.apply(&mut request).unwrap();
+    assert_eq!(request.lock().unwrap().filled_count(), 1);
+    assert_eq!(request.tx.hash(), signed.tx.hash());
+    assert_eq!(
+        request.tx.witnesses().as_slice(),
+        signed.tx.witnesses().as_slice()
+    );
+
+    let (_, mut other) = self::request(2);
+    assert!(set.apply(&mut other).is_err());
+}
+
+#[test]
+fn test_invalid_frames() {
+    let (_, first) = request(20);
+    let (_, second) = request(20);
+    let first = Payload::Request(Box::new(first)).to_frames(200).unwrap();
+    let second = Payload::Request(Box::new(second)).to_frames(200).unwrap();
+
+    let mut decoder = FrameDecoder::new();
+    assert!(decoder.receive(&first[0]).unwrap().is_none());
+    assert!(decoder.receive(&second[1]).is_err());
+    assert_eq!(decoder.progress(), (1, first.len()));
+    assert!(decoder.receive("CKBMS/0-1/00000000/AA").is_err());
+    assert!(decoder.receive("QR/1-1/00000000/AA").is_err());
+
+    // a corrupted frame fails the checksum
+    let mut corrupted = first.clone();
+    let last = corrupted.last_mut().unwrap();
+    let flipped = if last.ends_with('A') { 'B' } else { 'A' };
+    last.pop();
+    last.push(flipped);
+    let mut decoder = FrameDecoder::new();
+    let results: Vec<_> = corrupted
+        .iter()
+        .map(|frame| decoder.receive(frame))
+        .collect();
+    assert!(results.last().unwrap().is_err());
+
+    assert!(Payload::Request(Box::new(request(1).1))
+        .to_frames(20)
+        .is_err());
+}
@@ -0,0 +1,117 @@
+use ckb_types::H256;
+
+use super::{gen_tx, random_config, random_signer};
+use crate::{
+    approval::{MAINNET_GENESIS_HASH, TESTNET_GENESIS_HASH},
+    request::SigningRequest,
+    Signer,
+};
+
+#[test]
+fn test_add_signature() {
+    let (signers, config) = random_config(3, 0, 2);
+    let (tx, script_group) = gen_tx(&config, 2);
+    let mut request = SigningRequest {
+        config,
+        tx,
+        script_group,
+        fee: 0,
+        genesis_hash: None,
+    };
+    let message = request.message().unwrap();
+
+    let first = signers[2].sign(&message).unwrap();
+    assert_eq!(
+        request.add_signature(first).unwrap(),
+        signers[2].identity().unwrap()
+    );
+    assert_eq!(
+        request.signed().unwrap(),
+        vec![signers[2].identity().unwrap()]
+    );
+    // the message doesn't depend on the signatures
+    assert_eq!(request.message().unwrap(), message);
+
+    // twice, or from an outsider
+    assert!(request.add_signature(first).is_err());
+    let outsider = random_signer().sign(&message).unwrap();
+    assert!(request.add_signature(outsider).is_err());
+    assert!(!request.is_complete().unwrap());
+
+    let hash = request.tx.hash();
+    request
+        .add_signature(signers[0].sign(&message).unwrap())
+        .unwrap();
+    assert!(request.is_complete().unwrap());
+    assert_eq!(request.tx.hash(), hash);
+}
+
+#[test]
+fn test_combine() {
+    let (signers, config) = random_config(3, 0, 2);
+    let (tx, script_group) = gen_tx(&config, 1);
+    let request = SigningRequest {
+        config: config.clone(),
+        tx,
+        script_group,
+        fee: 0,
+        genesis_hash: None,
+    };
+    let message = request.message().unwrap();
+    let copy = |signer: usize| {
+        let mut copy = request.clone();
+        copy.add_signature(signers[signer].sign(&message).unwrap())
+            .unwrap();
+        copy
+    };
+
+    let mut combined = copy(0);
+    assert!(combined.combine(&copy(0)).unwrap().is_empty());
+    assert_eq!(
+        combined.combine(&copy(1)).unwrap(),
+        vec![signers[1].identity().unwrap()]
+    );
+    assert!(combined.is_complete().unwrap());
+    // complete, nothing more fits
+    assert!(combined.combine(&copy(2)).unwrap().is_empty());
+
+    let (other, _) = gen_tx(&config, 1);
+    let other = SigningRequest {
+        tx: other,
+        ..request.clone()
+    };
+    assert!(combined.combine(&other).is_err());
+
+    // the same transaction proposed on another chain
+    let other = SigningRequest {
+        genesis_hash: Some(TESTNET_GENESIS_HASH),
+        ..request.clone()
+    };
+    assert!(combined.combine(&other).is_err());
+}
+
+#[test]
+fn test_check_chain() {
+    let (_, config) = random_config(3, 0, 2);
+    let (tx, script_group) = gen_tx(&config, 1);
+    let mut request = SigningRequest {
+        config,
+        tx,
+        script_group,
+        fee: 0,
+        genesis_hash: None,
+    };
+    // proposed before the requests named their chain
+    assert!(request.check_chain(&MAINNET_GENESIS_HASH).is_err());
+
+    request.genesis_hash = Some(MAINNET_GENESIS_HASH);
+    assert!(request.check_chain(&MAINNET_GENESIS_HASH).is_ok());
+    let err = request.check_chain(&TESTNET_GENESIS_HASH).unwrap_err();
+    assert!(err.to_string().contains("not 0x10639e08"));
+    assert!(request.check_chain(&H256([1; 32])).is_err());
+
+    // the chain survives the file
+    let loaded = SigningRequest::from_json(&request.to_json().unwrap()).unwrap();
+    assert_eq!(loaded.genesis_hash, Some(MAINNET_GENESIS_HASH));
+    assert!(loaded.check_chain(&TESTNET_GENESIS_HASH).is_err());
+}
@@ -0,0 +1,105 @@
+use serde_json::Value;
+
+use super::{gen_tx, random_config, random_signer};
+use crate::{request::SigningRequest, safe::SafeProposal, SecpSigner, Signer};
+
+fn proposal() -> (Vec<SecpSigner>, SafeProposal) {
+    let (signers, config) = random_config(3, 0, 2);
+    let (tx, script_group) = gen_tx(&config, 2);
+    let mut request = SigningRequest {
+        config,
+        tx,
+        script_group,
+        fee: 1000,
+        genesis_hash: None,
+    };
+    let message = request.message().unwrap();
+    request
+        .add_signature(signers[1].sign(&message).unwrap())
+        .unwrap();
+    let mut proposal = SafeProposal::new(7, request);
+    proposal.description = Some("payroll".to_string());
+    proposal.labels = vec![Some("alice".to_string()), Some("bob".to_string()), None];
+    (signers, proposal)
+}
+
+#[test]
+fn test_round_trip() {
+    let (signers, proposal) = proposal();
+    let json = proposal.to_json().unwrap();
+    let value: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["nonce"], 7);
+    assert_eq!(value["confirmationsRequired"], 2);
+    assert_eq!(
+        value["safeTxHash"],
+        format!("0x{}", hex::encode(proposal.request.message().unwrap()))
+    );
+    let confirmations = value["confirmations"].as_array().unwrap();
+    assert_eq!(confirmations.len(), 1);
+    assert_eq!(
+        confirmations[0]["owner"],
+        format!("0x{}", hex::encode(signers[1].identity().unwrap()))
+    );
+    assert_eq!(confirmations[0]["ownerLabel"], "bob");
+    assert_eq!(confirmations[0]["signatureType"], "EOA");
+
+    let read = SafeProposal::from_json(&json).unwrap();
+    assert_eq!(read.nonce, 7);
+    assert_eq!(read.description.as_deref(), Some("payroll"));
+    assert_eq!(read.labels, proposal.labels);
+    assert_eq!(read.request.tx, proposal.request.tx);
+    assert_eq!(read.request.fee, 1000);
+    assert_eq!(
+        read.confirmations().unwrap(),
+        proposal.confirmations().unwrap()
+    );
+}
+
+#[test]
+fn test_dashboard_fields() {
+    let (_, proposal) = proposal();
+    let mut value: Value = serde_json::from_str(&proposal.to_json().unwrap()).unwrap();
+    value["isExecuted"] = Value::Bool(false);
+    value["origin"] = Value::String("dashboard".to_string());
+    let read = SafeProposal::from_json(&value.to_string()).unwrap();
+    assert_eq!(read.request.tx, proposal.request.tx);
+}
+
+#[test]
+fn test_tampered() {
+    let (signers, proposal) = proposal();
+    let value: Value = serde_json::from_str(&proposal.to_json().unwrap()).unwrap();
+    let check = |edit: &dyn Fn(&mut Value)| {
+        let mut value = value.clone();
+        edit(&mut value);
+        assert!(SafeProposal::from_json(&value.to_string()).is_err());
+    };
+
+    check(&|value| value["safeTxHash"] = Value::String(format!("0x{}", "00".repeat(32))));
+    check(&|value| value["transactionHash"] = Value::String(format!("0x{}", "00".repeat(32))));
+    check(&|value| value["safe"] = Value::String("0x00".to_string()));
+    // confirmed by another member than the signer
+    let other = format!("0x{}", hex::encode(signers[0].identity().unwrap()));
+    check(&|value| value["confirmations"][0]["owner"] = Value::String(other.clone()));
+    // a signature over something else
+    let message = [1u8; 32];
+    let wrong = format!("0x{}", hex::encode(signers[0].sign(&message).unwrap()));
+    check(&|value| value["confirmations"][0]["signature"] = Value::String(wrong.clone()));
+    // an outsider
+    let outsider = random_signer();
+    let signature = outsider.sign(&proposal.request.message().unwrap()).unwrap();
+    let confirmation = serde_json::json!({
+        "owner": format!("0x{}", hex::encode(outsider.identity().unwrap())),
+        "signature": format!("0x{}", hex::encode(signature)),
+        "signatureType": "EOA",
+    });
+    check(&|value| {
+        value["confirmations"]
+            .as_array_mut()
+            .unwrap()
+            .push(confirmation.clone())
+    });
+    check(&|value| {
+        value["confirmations"][0]["signatureType"] = Value::String("ETH_SIGN".to_string())
+    });
+}
@@ -0,0 +1,167 @@
+use ckb_sdk::traits::LiveCell;
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, EpochNumberWithFraction},
+    packed::{CellOutput, Script},
+    prelude::*,
+};
+
+use super::live_cell;
+use crate::scanner::{BlockInfo, CellSet, EpochTarget, Maturity, MultisigCell};
+
+const RELATIVE: u64 = 0x8000_0000_0000_0000;
+const EPOCH: u64 = 0x2000_0000_0000_0000;
+const TIMESTAMP: u64 = 0x4000_0000_0000_0000;
+
+fn block(number: u64, epoch: EpochNumberWithFraction, timestamp: u64) -> BlockInfo {
+    BlockInfo {
+        number,
+        epoch,
+        timestamp,
+    }
+}
+
+fn epoch(number: u64, index: u64, length: u64) -> EpochNumberWithFraction {
+    EpochNumberWithFraction::new(number, index, length)
+}
+
+fn cell(capacity: u64, data: &[u8]) -> LiveCell {
+    let output = CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .build();
+    live_cell(output, Bytes::copy_from_slice(data))
+}
+
+#[test]
+fn test_no_since_is_mature() {
+    let tip = block(100, epoch(1, 0, 1000), 0);
+    assert_eq!(Maturity::evaluate(None, &tip, &tip), Maturity::Mature);
+    assert_eq!(Maturity::evaluate(Some(0), &tip, &tip), Maturity::Mature);
+}
+
+#[test]
+fn test_block_number_since() {
+    let commit = block(100, epoch(1, 0, 1000), 0);
+    let tip = block(150, epoch(1, 50, 1000), 0);
+    assert_eq!(
+        Maturity::evaluate(Some(120), &commit, &tip),
+        Maturity::Mature
+    );
+    assert_eq!(
+        Maturity::evaluate(Some(200), &commit, &tip),
+        Maturity::BlockNumber(200)
+    );
+    assert_eq!(
+        Maturity::evaluate(Some(RELATIVE | 20), &commit, &tip),
+        Maturity::Mature
+    );
+    assert_eq!(
+        Maturity::evaluate(Some(RELATIVE | 60), &commit, &tip),
+        Maturity::BlockNumber(160)
+    );
+}
+
+#[test]
+fn test_epoch_since() {
+    let commit = block(100, epoch(10, 1, 2), 0);
+    let tip = block(200, epoch(11, 0, 1000), 0);
+    let half = epoch(0, 1, 2).full_value();
+    // 10 + 1/2 + 1/2 == 11
+    assert_eq!(
+        Maturity::evaluate(Some(RELATIVE | EPOCH | half), &commit, &tip),
+        Maturity::Mature
+    );
+    let third = epoch(1, 1, 3).full_value();
+    assert_eq!(
+        Maturity::evaluate(Some(RELATIVE | EPOCH | third), &commit, &tip),
+        Maturity::Epoch(EpochTarget {
+            number: 11,
+            index: 5,
+            length: 6,
+        })
+    );
+    let absolute = epoch(12, 0, 1).full_value();
+    assert_eq!(
+        Maturity::evaluate(Some(EPOCH | absolute), &commit, &tip),
+        Maturity::Epoch(EpochTarget {
+            number: 12,
+            index: 0,
+            length: 1,
+        })
+    );
+    // index is not less than length
+    let invalid = epoch(1, 3, 2).full_value();
+    assert_eq!(
+        Maturity::evaluate(Some(EPOCH | invalid), &commit, &tip),
+        Maturity::Unsatisfiable
+    );
+}
+
+#[test]
+fn test_timestamp_since() {
+    let commit = block(100, epoch(1, 0, 1000), 1_000_000);
+    let tip = block(200, epoch(1, 100, 1000), 2_000_000);
+    assert_eq!(
+        Maturity::evaluate(Some(TIMESTAMP | 1500), &commit, &tip),
+        Maturity::Mature
+    );
+    assert_eq!(
+        Maturity::evaluate(Some(RELATIVE | TIMESTAMP | 1500), &commit, &tip),
+        Maturity::Timestamp(2500)
+    );
+}
+
+#[test]
+fn test_invalid_since_flags() {
+    let tip = block(100, epoch(1, 0, 1000), 0);
+    // metric 0b11 is undefined
+    let since = 0x6000_0000_0000_0001;
+    assert_eq!(
+        Maturity::evaluate(Some(since), &tip, &tip),
+        Maturity::Unsatisfiable
+    );
+    // reserved bits set
+    let since = 0x0100_0000_0000_0001;
+    assert_eq!(
+        Maturity::evaluate(Some(since), &tip, &tip),
+        Maturity::Unsatisfiable
+    );
+}
+
+#[test]
+fn test_cell_set_capacity() {
+    let tip = block(100, epoch(1, 0, 1000), 0);
+    let mut typed = cell(3000, &[]);
+    typed.output = typed
+        .output
+        .as_builder()
+        .type_(Some(Script::default()).pack())
+        .build();
+    let set = CellSet {
+        tip,
+        cells: vec![
+            MultisigCell {
+                cell: cell(1000, &[]),
+                maturity: Maturity::Mature,
+            },
+            MultisigCell {
+                cell: cell(2000, &[1]),
+                maturity: Maturity::Mature,
+            },
+            MultisigCell {
+                cell: typed,
+                maturity: Maturity::Mature,
+            },
+            MultisigCell {
+                cell: cell(4000, &[]),
+                maturity: Maturity::BlockNumber(200),
+            },
+        ],
+    };
+    assert_eq!(set.total_capacity(), 10000);
+    assert_eq!(set.mature_capacity(), 6000);
+    assert_eq!(set.locked_capacity(), 4000);
+    let spendable = set.spendable();
+    assert_eq!(spendable.len(), 1);
+    assert_eq!(spendable[0].output_data, Bytes::new());
+}
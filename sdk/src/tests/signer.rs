@@ -0,0 +1,112 @@
+use secp256k1::{
+    hashes::{sha256, Hash},
+    Message, SECP256K1,
+};
+
+use super::{random_digest, random_signer};
+use crate::{
+    blake160,
+    signer::{recover_pubkey, to_recoverable},
+    Error, SecpSigner, Signer,
+};
+
+#[test]
+fn test_identity_is_blake160_of_compressed_pubkey() {
+    let signer = random_signer();
+    assert_eq!(
+        signer.identity().unwrap(),
+        blake160(&signer.pubkey().serialize())
+    );
+}
+
+#[test]
+fn test_signature_recovers_signer() {
+    let signer = random_signer();
+    let digest = random_digest();
+    let signature = signer.sign(&digest).unwrap();
+    assert_eq!(
+        recover_pubkey(&digest, &signature).unwrap(),
+        signer.pubkey()
+    );
+}
+
+#[test]
+fn test_boxed_signer() {
+    let signer: Box<dyn Signer> = Box::new(random_signer());
+    let digest = random_digest();
+    let signature = signer.sign(&digest).unwrap();
+    assert_eq!(
+        crate::signer::pubkey_identity(&recover_pubkey(&digest, &signature).unwrap()),
+        signer.identity().unwrap()
+    );
+}
+
+#[test]
+fn test_to_recoverable() {
+    let key = secp256k1::SecretKey::new(&mut secp256k1::rand::thread_rng());
+    let pubkey = secp256k1::PublicKey::from_secret_key(SECP256K1, &key);
+    let digest = random_digest();
+    let plain = SECP256K1.sign_ecdsa(&Message::from_digest(digest), &key);
+    let signature = to_recoverable(&digest, &plain, &pubkey).unwrap();
+    assert_eq!(recover_pubkey(&digest, &signature).unwrap(), pubkey);
+
+    let other = random_signer().pubkey();
+    assert!(matches!(
+        to_recoverable(&digest, &plain, &other),
+        Err(Error::SignatureMismatch)
+    ));
+}
+
+#[test]
+fn test_deterministic_signatures() {
+    let signer = random_signer();
+    let digest = random_digest();
+    let signature = signer.sign(&digest).unwrap();
+    for _ in 0..8 {
+        assert_eq!(signer.sign(&digest).unwrap(), signature);
+    }
+    // the same key for another digest, another key for the same digest
+    assert_ne!(
+        signer.sign(&random_digest()).unwrap()[..32],
+        signature[..32]
+    );
+    assert_ne!(
+        random_signer().sign(&digest).unwrap()[..32],
+        signature[..32]
+    );
+
+    // the plain signature of libsecp256k1 derives the same nonce
+    let key = secp256k1::SecretKey::from_slice(&[0x11; 32]).unwrap();
+    let signer = SecpSigner::new(key);
+    let plain = SECP256K1.sign_ecdsa(&Message::from_digest(digest), &key);
+    assert_eq!(
+        signer.sign(&digest).unwrap()[..64],
+        plain.serialize_compact()[..]
+    );
+}
+
+#[test]
+fn test_rfc6979_vectors() {
+    // the key 1 signing the sha256 of the messages, low s
+    let vectors = [
+        (
+            "Satoshi Nakamoto",
+            "934b1ea10a4b3c1757e2b0c017d0b6143ce3c9a7e6a4a49860d7a6ab210ee3d8",
+            "2442ce9d2b916064108014783e923ec36b49743e2ffa1c4496f01a512aafd9e5",
+        ),
+        (
+            "All those moments will be lost in time, like tears in rain. Time to die...",
+            "8600dbd41e348fe5c9465ab92d23e3db8b98b873beecd930736488696438cb6b",
+            "547fe64427496db33bf66019dacbf0039c04199abb0122918601db38a72cfc21",
+        ),
+    ];
+    let mut key = [0u8; 32];
+    key[31] = 1;
+    let signer = SecpSigner::from_slice(&key).unwrap();
+    for (message, r, s) in vectors {
+        let digest = sha256::Hash::hash(message.as_bytes()).to_byte_array();
+        let signature = signer.sign(&digest).unwrap();
+        assert_eq!(hex::encode(&signature[..32]), r);
+        assert_eq!(hex::encode(&signature[32..64]), s);
+    }
+}
@@ -0,0 +1,127 @@
+use ckb_types::core::{EpochNumberWithFraction, HeaderBuilder, HeaderView};
+
+use crate::since::{format_since, median_time, parse_since, SinceSpec};
+
+const RELATIVE: u64 = 0x8000_0000_0000_0000;
+const EPOCH: u64 = 0x2000_0000_0000_0000;
+const TIMESTAMP: u64 = 0x4000_0000_0000_0000;
+
+#[test]
+fn test_parse_since() {
+    assert_eq!(parse_since("after block 12,000,000").unwrap(), 12_000_000);
+    assert_eq!(parse_since("After Block 12_000_000").unwrap(), 12_000_000);
+    assert_eq!(
+        parse_since("after epoch 180").unwrap(),
+        EPOCH | (1 << 40) | 180
+    );
+    assert_eq!(
+        parse_since("after epoch 180 + 1/2").unwrap(),
+        EPOCH | (2 << 40) | (1 << 24) | 180
+    );
+    assert_eq!(
+        parse_since("after 180 epochs").unwrap(),
+        RELATIVE | EPOCH | (1 << 40) | 180
+    );
+    assert_eq!(parse_since("after 1000 blocks").unwrap(), RELATIVE | 1000);
+    assert_eq!(
+        parse_since("after 2024-06-01").unwrap(),
+        TIMESTAMP | 1_717_200_000
+    );
+    assert_eq!(
+        parse_since("after 2024-06-01T12:30:00Z").unwrap(),
+        TIMESTAMP | (1_717_200_000 + 45_000)
+    );
+    assert_eq!(
+        parse_since("after 2024-06-01 12:30:00").unwrap(),
+        TIMESTAMP | (1_717_200_000 + 45_000)
+    );
+    assert_eq!(
+        parse_since("after timestamp 1717200000").unwrap(),
+        TIMESTAMP | 1_717_200_000
+    );
+    assert_eq!(
+        parse_since("after 30 days").unwrap(),
+        RELATIVE | TIMESTAMP | (30 * 86_400)
+    );
+    assert_eq!(
+        parse_since("after 1 hour").unwrap(),
+        RELATIVE | TIMESTAMP | 3600
+    );
+}
+
+#[test]
+fn test_parse_since_errors() {
+    for s in [
+        "block 100",
+        "after",
+        "after block",
+        "after block -1",
+        "after block 0x10",
+        "after epoch 1 + 2/1",
+        "after epoch 16777216",
+        "after 2024-02-30",
+        "after 2024-06-01T24:00:00Z",
+        "after 1960-01-01",
+        "after 3 fortnights",
+        "after block 72057594037927936",
+    ] {
+        assert!(parse_since(s).is_err(), "{}", s);
+    }
+}
+
+#[test]
+fn test_format_since() {
+    for s in [
+        "after block 12000000",
+        "after epoch 180",
+        "after epoch 180 + 1/2",
+        "after 1 block",
+        "after 1000 blocks",
+        "after 180 epochs",
+        "after 1 + 1/2 epochs",
+        "after 2024-06-01",
+        "after 2024-06-01T12:30:05Z",
+        "after 2 weeks",
+        "after 90 minutes",
+        "after 61 seconds",
+    ] {
+        assert_eq!(format_since(parse_since(s).unwrap()), s);
+    }
+    // undefined metric and reserved bits
+    assert!(format_since(0x6000_0000_0000_0001).starts_with("invalid since"));
+    assert!(format_since(0x0100_0000_0000_0001).starts_with("invalid since"));
+    assert!(SinceSpec::from_value(EPOCH | (1 << 40) | (2 << 24)).is_err());
+    let spec = SinceSpec::from_value(RELATIVE | 10).unwrap();
+    assert!(spec.is_relative());
+}
+
+fn chain(count: u64) -> Vec<HeaderView> {
+    let mut headers: Vec<HeaderView> = Vec::new();
+    for number in 0..count {
+        let mut builder = HeaderBuilder::default()
+            .number(number)
+            .epoch(EpochNumberWithFraction::new(0, number, 1000))
+            // out of order, the median isn't the middle block
+            .timestamp((number * 7919) % 101 * 1000);
+        if let Some(parent) = headers.last() {
+            builder = builder.parent_hash(parent.hash());
+        }
+        headers.push(builder.build());
+    }
+    headers
+}
+
+#[test]
+fn test_median_time() {
+    let headers = chain(40);
+    let mut timestamps: Vec<u64> = headers[3..].iter().map(|h| h.timestamp()).collect();
+    timestamps.sort_unstable();
+    let mut shuffled = headers.clone();
+    shuffled.reverse();
+    assert_eq!(median_time(&shuffled).unwrap(), timestamps[18]);
+
+    assert!(median_time(&headers[..36]).is_err());
+    let mut broken = headers;
+    broken.remove(20);
+    assert!(median_time(&broken).is_err());
+}
@@ -0,0 +1,116 @@
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType, TransactionBuilder},
+    packed::{CellOutput, Script},
+    prelude::*,
+};
+
+use super::{lock_script, random_config, CODE_HASH};
+use crate::{
+    config::SUCCESSOR_FLAG, config_file::ConfigFile, successor::Successor, MultisigConfig,
+};
+
+fn vault_type(id: u8) -> Script {
+    Script::new_builder()
+        .code_hash(CODE_HASH.pack())
+        .hash_type(ScriptHashType::Type)
+        .args(Bytes::from(vec![id; 32]).pack())
+        .build()
+}
+
+fn output(lock: Script, capacity: u64, type_: Option<Script>) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock)
+        .type_(type_.pack())
+        .build()
+}
+
+fn successor_config() -> (MultisigConfig, Successor) {
+    let (_, full) = random_config(5, 0, 4);
+    let (_, reduced) = random_config(3, 0, 2);
+    let successor = Successor {
+        multisig_hash: reduced.hash160(),
+        type_hash: vault_type(1).calc_script_hash().unpack(),
+    };
+    (full.with_successor(Some(successor.clone())), successor)
+}
+
+#[test]
+fn test_successor_args() {
+    let (config, successor) = successor_config();
+    assert_eq!(config.successor(), Some(&successor));
+    let args = config.lock_args();
+    assert_eq!(args.len(), 20 + 8 + 1 + 52);
+    assert_eq!(args[28], SUCCESSOR_FLAG);
+    assert_eq!(&args[29..49], &successor.multisig_hash);
+    assert_eq!(&args[49..], &successor.type_hash);
+
+    // after the fee key, in the flags with the others
+    let args = config
+        .with_fee_key(Some([7; 20]))
+        .with_spend_all(true)
+        .lock_args();
+    assert_eq!(args.len(), 20 + 8 + 20 + 1 + 52);
+    assert_eq!(args[48] & SUCCESSOR_FLAG, SUCCESSOR_FLAG);
+}
+
+#[test]
+fn test_successor_config_file() {
+    let (config, _) = successor_config();
+    let file = ConfigFile::from(&config);
+    let toml = file.to_toml().unwrap();
+    assert!(toml.contains("[successor]"));
+    assert_eq!(ConfigFile::from_toml(&toml).unwrap(), file);
+    assert_eq!(file.to_config().unwrap(), config);
+
+    let mut short = file;
+    short.successor.as_mut().unwrap().type_hash = "0x00".to_string();
+    assert!(short.to_config().is_err());
+}
+
+#[test]
+fn test_successor_check_move() {
+    let (config, successor) = successor_config();
+    let lock = lock_script(&config);
+    let vault = vault_type(1);
+    let inputs = vec![
+        output(lock.clone(), 1_000, None),
+        output(lock.clone(), 2_000, None),
+        output(Script::default(), 500, Some(vault.clone())),
+        // pays the fee
+        output(Script::default(), 100, None),
+    ];
+    let group = [0, 1];
+    let tx = |outputs: Vec<CellOutput>| {
+        let data = vec![Bytes::new().pack(); outputs.len()];
+        TransactionBuilder::default()
+            .outputs(outputs)
+            .outputs_data(data)
+            .build()
+    };
+
+    let moved = tx(vec![output(Script::default(), 3_500, Some(vault.clone()))]);
+    successor.check_move(&moved, &inputs, &group).unwrap();
+    // the successor cell must hold the vault cell spent alongside too
+    let short = tx(vec![output(Script::default(), 3_000, Some(vault.clone()))]);
+    assert!(successor.check_move(&short, &inputs, &group).is_err());
+    // a single successor cell
+    let split = tx(vec![
+        output(Script::default(), 3_500, Some(vault.clone())),
+        output(Script::default(), 1, Some(vault.clone())),
+    ]);
+    assert!(successor.check_move(&split, &inputs, &group).is_err());
+    let elsewhere = tx(vec![output(Script::default(), 3_500, Some(vault_type(2)))]);
+    assert!(successor.check_move(&elsewhere, &inputs, &group).is_err());
+    // typed cells of the lock need the full quorum
+    assert!(successor.check_move(&moved, &inputs, &[0, 2]).is_err());
+
+    // the successor cell is spent, and keeps its lock
+    let stolen = tx(vec![output(lock.clone(), 3_500, Some(vault.clone()))]);
+    assert!(successor.check_move(&stolen, &inputs, &group).is_err());
+    let made_up = tx(vec![output(Script::default(), 3_000, Some(vault.clone()))]);
+    assert!(successor
+        .check_move(&made_up, &inputs[..2], &group)
+        .is_err());
+}
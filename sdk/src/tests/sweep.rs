@@ -0,0 +1,131 @@
+use ckb_sdk::traits::LiveCell;
+use ckb_types::{
+    bytes::Bytes,
+    core::Capacity,
+    packed::{CellOutput, OutPoint},
+    prelude::*,
+};
+use secp256k1::rand;
+
+use super::{lock_script, random_config};
+use crate::{
+    config::SPEND_ALL_FLAG,
+    fee::FeeEstimator,
+    sweep::{SweepBuilder, DEFAULT_MAX_TX_SIZE},
+    witness::spend_all_total,
+};
+
+fn cells(lock: &ckb_types::packed::Script, count: usize) -> Vec<LiveCell> {
+    (0..count)
+        .map(|i| LiveCell {
+            output: CellOutput::new_builder()
+                .capacity(Capacity::shannons(100_0000_0000 + i as u64).pack())
+                .lock(lock.clone())
+                .build(),
+            output_data: Bytes::new(),
+            out_point: OutPoint::new(rand::random::<[u8; 32]>().pack(), 0),
+            block_number: 0,
+            tx_index: 0,
+        })
+        .collect()
+}
+
+#[test]
+fn test_sweep_chunks() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let cells = cells(&lock, 21);
+    let requests = SweepBuilder::new(config.clone(), lock.clone(), vec![], 1000)
+        .max_inputs(10)
+        .build(&cells)
+        .unwrap();
+    // the 21st cell is left alone
+    assert_eq!(requests.len(), 2);
+    for (request, chunk) in requests.iter().zip(cells.chunks(10)) {
+        let tx = &request.tx;
+        assert_eq!(tx.inputs().len(), 10);
+        assert_eq!(
+            request.script_group.input_indices,
+            (0..10).collect::<Vec<_>>()
+        );
+        let total: u64 = chunk
+            .iter()
+            .map(|cell| Unpack::<u64>::unpack(&cell.output.capacity()))
+            .sum();
+        let output = tx.output(0).unwrap();
+        assert_eq!(output.lock(), lock);
+        assert_eq!(
+            Unpack::<u64>::unpack(&output.capacity()) + request.fee,
+            total
+        );
+        let estimate = FeeEstimator::new(1000)
+            .estimate(tx, &[(request.script_group.clone(), config.clone())], None)
+            .unwrap();
+        assert_eq!(estimate.fee, request.fee);
+        assert!(estimate.size <= DEFAULT_MAX_TX_SIZE);
+    }
+}
+
+#[test]
+fn test_sweep_size_limit() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let cells = cells(&lock, 30);
+    let builder = SweepBuilder::new(config.clone(), lock.clone(), vec![], 1000);
+    let one = builder.build(&cells[..1]).unwrap();
+    assert!(one.is_empty());
+    let sizes = |max: u64| {
+        SweepBuilder::new(config.clone(), lock.clone(), vec![], 1000)
+            .max_tx_size(max)
+            .build(&cells)
+            .unwrap()
+            .iter()
+            .map(|request| {
+                FeeEstimator::new(1000)
+                    .estimate(
+                        &request.tx,
+                        &[(request.script_group.clone(), config.clone())],
+                        None,
+                    )
+                    .unwrap()
+                    .size
+            })
+            .collect::<Vec<_>>()
+    };
+    let all = sizes(DEFAULT_MAX_TX_SIZE);
+    assert_eq!(all.len(), 1);
+    // just enough for 10 inputs
+    let limit = all[0] - 20 * 44;
+    let chunks = sizes(limit);
+    assert_eq!(chunks.len(), 3);
+    assert!(chunks.iter().all(|size| *size <= limit));
+    assert_eq!(chunks[0], limit);
+    assert!(SweepBuilder::new(config, lock, vec![], 1000)
+        .max_tx_size(100)
+        .build(&cells)
+        .is_err());
+}
+
+#[test]
+fn test_sweep_spend_all() {
+    let (_, config) = random_config(3, 0, 2);
+    let config = config.with_spend_all(true);
+    assert_eq!(config.lock_args()[28], SPEND_ALL_FLAG);
+    let lock = lock_script(&config);
+    let cells = cells(&lock, 12);
+    let builder = SweepBuilder::new(config.clone(), lock, vec![], 1000).max_inputs(10);
+    // never a part of the cells
+    assert!(builder.build(&cells).is_err());
+    let requests = builder.build(&cells[..8]).unwrap();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    assert_eq!(spend_all_total(&request.tx, 0).unwrap(), Some(8));
+    let estimate = FeeEstimator::new(1000)
+        .estimate(
+            &request.tx,
+            &[(request.script_group.clone(), config.clone())],
+            None,
+        )
+        .unwrap();
+    assert_eq!(estimate.fee, request.fee);
+}
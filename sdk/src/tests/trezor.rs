@@ -0,0 +1,206 @@
+use std::{cell::RefCell, rc::Rc};
+
+use secp256k1::{Message as Digest, SecretKey, SECP256K1};
+
+use super::random_digest;
+use crate::{
+    ledger::DerivationPath,
+    signer::{recover_pubkey, serialize_signature},
+    trezor::{
+        from_reports, to_reports, Message, TrezorSigner, TrezorTransport, Vendor,
+        FAILURE_ACTION_CANCELLED, FAILURE_DATA_ERROR, FAILURE_UNEXPECTED_MESSAGE,
+        MESSAGE_BUTTON_ACK, MESSAGE_BUTTON_REQUEST, MESSAGE_CKB_SIGNATURE, MESSAGE_CKB_SIGN_DIGEST,
+        MESSAGE_FAILURE, MESSAGE_FEATURES, MESSAGE_GET_PUBLIC_KEY, MESSAGE_INITIALIZE,
+        MESSAGE_PUBLIC_KEY, REPORT_SIZE,
+    },
+    Error, Signer,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Firmware {
+    /// No CKB messages.
+    Stock,
+    /// The CKB messages, without displaying cobuild messages.
+    Blind,
+    /// The CKB messages, displaying cobuild messages.
+    Cobuild,
+}
+
+/// Emulates a firmware with a software key.
+struct MockDevice {
+    key: SecretKey,
+    firmware: Firmware,
+    deny: bool,
+    pending: RefCell<Option<Message>>,
+    /// The cobuild messages shown.
+    shown: Rc<RefCell<Vec<Vec<u8>>>>,
+}
+
+impl MockDevice {
+    fn new(firmware: Firmware, deny: bool) -> Self {
+        MockDevice {
+            key: SecretKey::new(&mut secp256k1::rand::thread_rng()),
+            firmware,
+            deny,
+            pending: RefCell::new(None),
+            shown: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    fn failure(code: u8) -> Message {
+        let mut payload = vec![0x08, code, 0x12, 4];
+        payload.extend_from_slice(b"fail");
+        Message::new(MESSAGE_FAILURE, payload)
+    }
+
+    fn sign(&self, payload: &[u8]) -> Message {
+        // address_n varints, then the digest and the optional message
+        let mut offset = 0;
+        while payload[offset] == 0x08 {
+            offset += 1;
+            while payload[offset] & 0x80 != 0 {
+                offset += 1;
+            }
+            offset += 1;
+        }
+        assert_eq!(&payload[offset..offset + 2], &[0x12, 32]);
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&payload[offset + 2..offset + 34]);
+        let message = &payload[offset + 34..];
+        if !message.is_empty() {
+            if self.firmware != Firmware::Cobuild {
+                return Self::failure(FAILURE_DATA_ERROR as u8);
+            }
+            assert_eq!(message[0], 0x1a);
+            self.shown.borrow_mut().push(message[2..].to_vec());
+        }
+        if self.deny {
+            return Self::failure(FAILURE_ACTION_CANCELLED as u8);
+        }
+        let signature = SECP256K1.sign_ecdsa_recoverable(&Digest::from_digest(digest), &self.key);
+        let mut payload = vec![0x0a, 65];
+        payload.extend_from_slice(&serialize_signature(&signature));
+        Message::new(MESSAGE_CKB_SIGNATURE, payload)
+    }
+}
+
+impl TrezorTransport for MockDevice {
+    fn call(&self, message: &Message) -> Result<Message, Error> {
+        let answer = match message.kind {
+            MESSAGE_INITIALIZE => {
+                let mut payload = vec![0x0a, 9];
+                payload.extend_from_slice(b"onekey.so");
+                payload.extend_from_slice(&[0x10, 3, 0x18, 4, 0x20, 0]);
+                Message::new(MESSAGE_FEATURES, payload)
+            }
+            MESSAGE_GET_PUBLIC_KEY => {
+                assert!(message.payload.ends_with(b"secp256k1"));
+                let pubkey = secp256k1::PublicKey::from_secret_key(SECP256K1, &self.key);
+                let mut node = vec![0x08, 3, 0x32, 33];
+                node.extend_from_slice(&pubkey.serialize());
+                let mut payload = vec![0x0a, node.len() as u8];
+                payload.extend_from_slice(&node);
+                Message::new(MESSAGE_PUBLIC_KEY, payload)
+            }
+            MESSAGE_CKB_SIGN_DIGEST if self.firmware == Firmware::Stock => {
+                Self::failure(FAILURE_UNEXPECTED_MESSAGE as u8)
+            }
+            MESSAGE_CKB_SIGN_DIGEST => {
+                // confirm on the device first
+                *self.pending.borrow_mut() = Some(message.clone());
+                Message::new(MESSAGE_BUTTON_REQUEST, Vec::new())
+            }
+            MESSAGE_BUTTON_ACK => {
+                let pending = self.pending.borrow_mut().take().expect("pending request");
+                self.sign(&pending.payload)
+            }
+            kind => panic!("unexpected message {}", kind),
+        };
+        Ok(answer)
+    }
+}
+
+fn signer(firmware: Firmware, deny: bool) -> TrezorSigner<MockDevice> {
+    TrezorSigner::new(MockDevice::new(firmware, deny), DerivationPath::default()).unwrap()
+}
+
+#[test]
+fn test_reports() {
+    for len in [0, 10, 55, 56, 200] {
+        let message = Message::new(MESSAGE_CKB_SIGN_DIGEST, vec![0xab; len]);
+        let reports = to_reports(&message);
+        assert_eq!(
+            reports.len(),
+            (len + 8 + REPORT_SIZE - 2) / (REPORT_SIZE - 1)
+        );
+        assert_eq!(&reports[0][..5], &[b'?', b'#', b'#', 0x2d, 0xbe]);
+        let mut reports = reports.into_iter();
+        let read = from_reports(|| Ok(reports.next().unwrap())).unwrap();
+        assert_eq!(read, message);
+    }
+    assert!(from_reports(|| Ok([0u8; REPORT_SIZE])).is_err());
+}
+
+#[test]
+fn test_trezor_signer() {
+    let signer = signer(Firmware::Blind, false);
+    assert_eq!(signer.features().vendor_kind(), Vendor::OneKey);
+    assert_eq!(signer.features().version, (3, 4, 0));
+    let digest = random_digest();
+    let signature = signer.sign(&digest).unwrap();
+    assert_eq!(
+        &recover_pubkey(&digest, &signature).unwrap(),
+        signer.pubkey()
+    );
+    assert_eq!(
+        crate::signer::pubkey_identity(signer.pubkey()),
+        signer.identity().unwrap()
+    );
+}
+
+#[test]
+fn test_cobuild_display() {
+    let digest = random_digest();
+    let message = b"cobuild message".to_vec();
+
+    let device = MockDevice::new(Firmware::Cobuild, false);
+    let shown = Rc::clone(&device.shown);
+    let signer = TrezorSigner::new(device, DerivationPath::default()).unwrap();
+    let signature = signer.sign_displaying(&digest, &message).unwrap();
+    assert_eq!(
+        &recover_pubkey(&digest, &signature).unwrap(),
+        signer.pubkey()
+    );
+    assert_eq!(*shown.borrow(), vec![message.clone()]);
+    // through the signer trait
+    let signer = signer.display(message.clone());
+    signer.sign(&digest).unwrap();
+    assert_eq!(shown.borrow().len(), 2);
+
+    // blind signing only when allowed
+    let signer = self::signer(Firmware::Blind, false);
+    assert!(matches!(
+        signer.sign_displaying(&digest, &message),
+        Err(Error::TrezorFailure(FAILURE_DATA_ERROR, _))
+    ));
+    let signer = signer.allow_blind(true);
+    let signature = signer.sign_displaying(&digest, &message).unwrap();
+    assert_eq!(
+        &recover_pubkey(&digest, &signature).unwrap(),
+        signer.pubkey()
+    );
+}
+
+#[test]
+fn test_trezor_signer_failures() {
+    let signer = signer(Firmware::Stock, false);
+    assert!(matches!(
+        signer.sign(&random_digest()),
+        Err(Error::HardwareUnsupported(_))
+    ));
+    let signer = self::signer(Firmware::Cobuild, true);
+    assert!(matches!(
+        signer.sign(&random_digest()),
+        Err(Error::TrezorFailure(FAILURE_ACTION_CANCELLED, _))
+    ));
+}
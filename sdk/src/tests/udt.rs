@@ -0,0 +1,281 @@
+use ckb_sdk::traits::LiveCell;
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, DepType, ScriptHashType},
+    packed::{CellDep, CellOutput, OutPoint, Script, WitnessArgs},
+    prelude::*,
+    H256,
+};
+
+use super::{capacity, live_cell, lock_script, random_config};
+use crate::{
+    compute_sighash,
+    udt::{UdtPayment, UdtTransfer},
+    unlock::{BoxedSigner, MultisigScriptSigner},
+    witness::witness_args,
+};
+
+const CKB: u64 = 100_000_000;
+
+fn xudt() -> Script {
+    Script::new_builder()
+        .code_hash(H256([0x50; 32]).pack())
+        .hash_type(ScriptHashType::Type)
+        .args(Bytes::from(vec![0x0e; 32]).pack())
+        .build()
+}
+
+fn cell_dep(byte: u8) -> CellDep {
+    CellDep::new_builder()
+        .out_point(OutPoint::new(H256([byte; 32]).pack(), 0))
+        .dep_type(DepType::Code)
+        .build()
+}
+
+fn token(lock: &Script, capacity: u64, amount: u128) -> LiveCell {
+    let output = CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock.clone())
+        .type_(Some(xudt()).pack())
+        .build();
+    live_cell(output, Bytes::from(amount.to_le_bytes().to_vec()))
+}
+
+fn plain(lock: &Script, capacity: u64) -> LiveCell {
+    let output = CellOutput::new_builder()
+        .capacity(Capacity::shannons(capacity).pack())
+        .lock(lock.clone())
+        .build();
+    live_cell(output, Bytes::new())
+}
+
+fn recipient(args: usize) -> Script {
+    Script::new_builder()
+        .code_hash(H256([7; 32]).pack())
+        .hash_type(ScriptHashType::Type)
+        .args(Bytes::from(vec![7; args]).pack())
+        .build()
+}
+
+fn amount(data: &[u8]) -> u128 {
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(data);
+    u128::from_le_bytes(bytes)
+}
+
+/// The capacity a token cell of `lock` occupies.
+fn occupied(lock: &Script) -> u64 {
+    let output = token(lock, 0, 0).output;
+    output
+        .occupied_capacity(Capacity::bytes(16).unwrap())
+        .unwrap()
+        .as_u64()
+}
+
+#[test]
+fn test_transfer_splits_the_tokens() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let builder = UdtTransfer::new(config, lock.clone(), xudt(), vec![cell_dep(0xd0)], 1000);
+    let tokens = [
+        token(&lock, 300 * CKB, 600),
+        token(&lock, 300 * CKB, 500),
+        token(&lock, 300 * CKB, 900),
+    ];
+    let payments = [
+        UdtPayment {
+            to: recipient(20),
+            amount: 700,
+        },
+        UdtPayment {
+            to: recipient(32),
+            amount: 300,
+        },
+    ];
+    let request = builder.build(&payments, &tokens, &[]).unwrap();
+    let tx = &request.tx;
+
+    // the first two token cells cover the 1000, their capacity pays the rest
+    assert_eq!(tx.inputs().len(), 2);
+    assert_eq!(request.script_group.input_indices, vec![0, 1]);
+    let amounts: Vec<_> = tx
+        .outputs_data()
+        .into_iter()
+        .take(3)
+        .map(|data| amount(&data.raw_data()))
+        .collect();
+    assert_eq!(amounts, vec![700, 300, 100]);
+    for (i, to) in [recipient(20), recipient(32), lock.clone()]
+        .iter()
+        .enumerate()
+    {
+        let output = tx.outputs().get(i).unwrap();
+        assert_eq!(&output.lock(), to);
+        assert_eq!(output.type_().to_opt(), Some(xudt()));
+        assert_eq!(capacity(&output), occupied(to));
+    }
+
+    let change = tx.outputs().get(3).unwrap();
+    assert_eq!(change.lock(), lock);
+    assert!(change.type_().is_none());
+    assert!(tx.outputs_data().get(3).unwrap().raw_data().is_empty());
+    let paid = occupied(&recipient(20)) + occupied(&recipient(32)) + occupied(&lock);
+    assert_eq!(capacity(&change) + paid + request.fee, 600 * CKB);
+    assert!(request.fee > 0 && request.fee < CKB / 1000);
+}
+
+#[test]
+fn test_transfer_pays_capacity_from_plain_cells() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let change_lock = recipient(20);
+    let builder =
+        UdtTransfer::new(config, lock.clone(), xudt(), vec![], 1000).change(change_lock.clone());
+    let tokens = [token(&lock, occupied(&lock), 1000)];
+    let payments = [UdtPayment {
+        to: recipient(100),
+        amount: 1000,
+    }];
+
+    // the whole amount sent, no token change, the capacity from a plain cell
+    let request = builder
+        .build(&payments, &tokens, &[plain(&lock, 300 * CKB)])
+        .unwrap();
+    let tx = &request.tx;
+    assert_eq!(tx.inputs().len(), 2);
+    assert_eq!(tx.outputs().len(), 2);
+    let sent = tx.outputs().get(0).unwrap();
+    assert_eq!(capacity(&sent), occupied(&recipient(100)));
+    let change = tx.outputs().get(1).unwrap();
+    assert_eq!(change.lock(), change_lock);
+    assert!(change.type_().is_none());
+    assert_eq!(
+        capacity(&change) + capacity(&sent) + request.fee,
+        occupied(&lock) + 300 * CKB
+    );
+
+    // too little left for a plain change cell: into the token change
+    let tokens = [token(&lock, occupied(&lock), 1500)];
+    let top_up = occupied(&recipient(100)) + occupied(&change_lock) - occupied(&lock);
+    let request = builder
+        .build(&payments, &tokens, &[plain(&lock, top_up + 30 * CKB)])
+        .unwrap();
+    let tx = &request.tx;
+    assert_eq!(tx.outputs().len(), 2);
+    let token_change = tx.outputs().get(1).unwrap();
+    assert_eq!(token_change.lock(), change_lock);
+    assert_eq!(amount(&tx.outputs_data().get(1).unwrap().raw_data()), 500);
+    assert!(capacity(&token_change) > occupied(&change_lock));
+    let total = occupied(&lock) + top_up + 30 * CKB;
+    let paid = capacity(&tx.outputs().get(0).unwrap()) + capacity(&token_change);
+    assert_eq!(paid + request.fee, total);
+    assert!(request.fee < CKB / 1000);
+
+    // or to the fee without any token change
+    let tokens = [token(&lock, occupied(&lock), 1000)];
+    let top_up = occupied(&recipient(100)) - occupied(&lock);
+    let request = builder
+        .build(&payments, &tokens, &[plain(&lock, top_up + 30 * CKB)])
+        .unwrap();
+    assert_eq!(request.tx.outputs().len(), 1);
+    assert_eq!(request.fee, 30 * CKB);
+}
+
+#[test]
+fn test_transfer_refuses() {
+    let (_, config) = random_config(3, 0, 2);
+    let lock = lock_script(&config);
+    let builder = UdtTransfer::new(config, lock.clone(), xudt(), vec![], 1000);
+    let payment = |amount| UdtPayment {
+        to: recipient(20),
+        amount,
+    };
+    let tokens = [token(&lock, 200 * CKB, 1000)];
+
+    // no payment, no token, more than held, an overflowing total
+    assert!(builder.build(&[], &tokens, &[]).is_err());
+    assert!(builder.build(&[payment(0)], &tokens, &[]).is_err());
+    assert!(builder.build(&[payment(1001)], &tokens, &[]).is_err());
+    assert!(builder
+        .build(&[payment(u128::MAX), payment(1)], &tokens, &[])
+        .is_err());
+
+    // token cells of another lock or type, or without an amount
+    let other = [token(&recipient(20), 200 * CKB, 1000)];
+    assert!(builder.build(&[payment(10)], &other, &[]).is_err());
+    let mut other_type = token(&lock, 200 * CKB, 1000);
+    other_type.output = other_type
+        .output
+        .as_builder()
+        .type_(Some(recipient(32)).pack())
+        .build();
+    assert!(builder.build(&[payment(10)], &[other_type], &[]).is_err());
+    let mut short = token(&lock, 200 * CKB, 1000);
+    short.output_data = Bytes::from(vec![1; 8]);
+    assert!(builder.build(&[payment(10)], &[short], &[]).is_err());
+
+    // capacity cells which are not plain cells of the config, or too small
+    let tokens = [token(&lock, occupied(&lock), 1000)];
+    assert!(builder
+        .build(&[payment(10)], &tokens, &[token(&lock, 300 * CKB, 5)])
+        .is_err());
+    assert!(builder.build(&[payment(10)], &tokens, &[]).is_err());
+}
+
+#[test]
+fn test_transfer_witness_types_are_signed() {
+    let (signers, config) = random_config(3, 0, 2);
+    let config = config.with_strict_witnesses(true);
+    let lock = lock_script(&config);
+    let input_type = Bytes::from(vec![0x0a; 40]);
+    let builder = UdtTransfer::new(config.clone(), lock.clone(), xudt(), vec![], 1000)
+        .witness_types(Some(input_type.clone()), None);
+    let tokens = [token(&lock, 200 * CKB, 600), token(&lock, 200 * CKB, 600)];
+    let payment = UdtPayment {
+        to: recipient(20),
+        amount: 1000,
+    };
+    let mut request = builder.build(&[payment], &tokens, &[]).unwrap();
+
+    // in the first witness of the group alone, the others stay empty
+    let witness = witness_args(&request.tx, 0).unwrap();
+    assert_eq!(
+        witness.input_type().to_opt().unwrap().raw_data(),
+        input_type
+    );
+    assert!(witness.output_type().is_none());
+    assert_eq!(request.tx.witnesses().len(), 1);
+
+    // the digest covers it
+    let indices = &request.script_group.input_indices;
+    let digest = compute_sighash(&request.tx, indices, &config).unwrap();
+    let other = WitnessArgs::new_builder()
+        .input_type(Some(Bytes::from(vec![0x0b; 40])).pack())
+        .build();
+    let tampered = request
+        .tx
+        .as_advanced_builder()
+        .set_witnesses(vec![other.as_bytes().pack()])
+        .build();
+    assert_ne!(
+        compute_sighash(&tampered, indices, &config).unwrap(),
+        digest
+    );
+    assert_eq!(request.message().unwrap(), digest);
+
+    // and signing keeps it
+    let signers = signers
+        .into_iter()
+        .take(2)
+        .map(|s| Box::new(s) as BoxedSigner)
+        .collect();
+    request
+        .sign(&MultisigScriptSigner::new(config, signers))
+        .unwrap();
+    assert!(request.is_complete().unwrap());
+    let witness = witness_args(&request.tx, 0).unwrap();
+    assert_eq!(
+        witness.input_type().to_opt().unwrap().raw_data(),
+        input_type
+    );
+}
@@ -0,0 +1,147 @@
+use ckb_sdk::{
+    traits::dummy_impls::DummyTransactionDependencyProvider,
+    unlock::{generate_message as sdk_generate_message, ScriptUnlocker},
+};
+use ckb_types::{bytes::Bytes, packed::WitnessArgs, prelude::*};
+
+use super::{gen_tx, random_config, random_signer};
+use crate::{
+    compute_sighash,
+    config::ConfigEncoding,
+    config_tree::ConfigTree,
+    digest::generate_message,
+    unlock::{BoxedSigner, MultisigUnlocker},
+    MultisigLock, SecpSigner,
+};
+
+fn boxed(signers: Vec<SecpSigner>) -> Vec<BoxedSigner> {
+    signers
+        .into_iter()
+        .map(|s| Box::new(s) as BoxedSigner)
+        .collect()
+}
+
+fn lock_of(tx: &ckb_types::core::TransactionView, index: usize) -> MultisigLock {
+    let witness = WitnessArgs::from_slice(&tx.witnesses().get(index).unwrap().raw_data()).unwrap();
+    MultisigLock::parse(&witness.lock().to_opt().unwrap().raw_data()).unwrap()
+}
+
+#[test]
+fn test_message_matches_system_multisig() {
+    // this lock shares the signing message algorithm of the system multisig lock
+    let (_, config) = random_config(3, 0, 2);
+    let (tx, group) = gen_tx(&config, 2);
+    let unlocker = MultisigUnlocker::new(config.clone(), vec![]);
+    let tx = unlocker
+        .fill_placeholder_witness(&tx, &group, &DummyTransactionDependencyProvider)
+        .unwrap();
+    let extra = WitnessArgs::new_builder()
+        .input_type(Some(Bytes::from(vec![1, 2, 3])).pack())
+        .build();
+    let tx = tx
+        .as_advanced_builder()
+        .witness(Bytes::new().pack())
+        .witness(Bytes::new().pack())
+        .witness(extra.as_bytes().pack())
+        .build();
+    let expected = sdk_generate_message(&tx, &group, config.placeholder_lock()).unwrap();
+    assert_eq!(
+        generate_message(&tx, &group.input_indices).unwrap()[..],
+        expected[..]
+    );
+}
+
+#[test]
+fn test_partial_signing() {
+    let (signers, config) = random_config(3, 1, 2);
+    let (tx, group) = gen_tx(&config, 2);
+    let mut signers = signers.into_iter();
+    let first = MultisigUnlocker::new(config.clone(), boxed(vec![signers.next().unwrap()]));
+    let others = MultisigUnlocker::new(config.clone(), boxed(signers.collect()));
+    let provider = DummyTransactionDependencyProvider;
+
+    assert!(first.match_args(&config.lock_args()));
+    let tx = first
+        .fill_placeholder_witness(&tx, &group, &provider)
+        .unwrap();
+    assert!(!first.is_unlocked(&tx, &group, &provider).unwrap());
+
+    let tx = first.unlock(&tx, &group, &provider).unwrap();
+    assert_eq!(lock_of(&tx, 0).filled_count(), 1);
+    assert!(!first.is_unlocked(&tx, &group, &provider).unwrap());
+    // signing again doesn't add a second signature of the same key
+    let tx = first.unlock(&tx, &group, &provider).unwrap();
+    assert_eq!(lock_of(&tx, 0).filled_count(), 1);
+
+    let tx = others.unlock(&tx, &group, &provider).unwrap();
+    let lock = lock_of(&tx, 0);
+    assert!(lock.is_complete());
+    let message = generate_message(&tx, &group.input_indices).unwrap();
+    lock.verify(&message).expect("verify");
+    assert!(others.is_unlocked(&tx, &group, &provider).unwrap());
+}
+
+#[test]
+fn test_require_first_n_not_satisfied() {
+    let (signers, config) = random_config(3, 1, 2);
+    let (tx, group) = gen_tx(&config, 1);
+    let provider = DummyTransactionDependencyProvider;
+    let unlocker = MultisigUnlocker::new(config, boxed(signers.into_iter().skip(1).collect()));
+    let tx = unlocker.unlock(&tx, &group, &provider).unwrap();
+    let lock = lock_of(&tx, 0);
+    assert!(lock.is_complete());
+    let message = generate_message(&tx, &group.input_indices).unwrap();
+    assert!(lock.verify(&message).is_err());
+    assert!(!unlocker.is_unlocked(&tx, &group, &provider).unwrap());
+}
+
+#[test]
+fn test_outsider_is_ignored() {
+    let (_, config) = random_config(2, 0, 1);
+    let (tx, group) = gen_tx(&config, 1);
+    let provider = DummyTransactionDependencyProvider;
+    let unlocker = MultisigUnlocker::new(config, boxed(vec![random_signer()]));
+    let tx = unlocker.unlock(&tx, &group, &provider).unwrap();
+    assert_eq!(lock_of(&tx, 0).filled_count(), 0);
+}
+
+#[test]
+fn test_molecule_config_signing() {
+    let (signers, config) = random_config(3, 1, 2);
+    let config = config.with_encoding(ConfigEncoding::Molecule);
+    let (tx, group) = gen_tx(&config, 2);
+    let provider = DummyTransactionDependencyProvider;
+    let unlocker = MultisigUnlocker::new(config.clone(), boxed(signers));
+    let unsigned = compute_sighash(&tx, &group.input_indices, &config).unwrap();
+
+    // the signatures alone are zeroed, not the molecule config before them
+    let tx = unlocker.unlock(&tx, &group, &provider).unwrap();
+    let message = generate_message(&tx, &group.input_indices).unwrap();
+    assert_eq!(message, unsigned);
+    let lock = lock_of(&tx, 0);
+    assert!(lock.is_complete());
+    lock.verify(&message).unwrap();
+    assert!(unlocker.is_unlocked(&tx, &group, &provider).unwrap());
+}
+
+#[test]
+fn test_config_tree_signing() {
+    let (ops_signers, ops) = random_config(3, 0, 2);
+    let (recovery_signers, recovery) = random_config(2, 0, 1);
+    let recovery = recovery.with_encoding(ConfigEncoding::Molecule);
+    let tree = ConfigTree::new(vec![ops, recovery]).unwrap();
+    let provider = DummyTransactionDependencyProvider;
+
+    // the proof heading the lock field is kept as the config is
+    for (index, signers) in [(0, ops_signers), (1, recovery_signers)] {
+        let config = tree.config(index).unwrap();
+        let (tx, group) = gen_tx(&config, 2);
+        let unsigned = compute_sighash(&tx, &group.input_indices, &config).unwrap();
+        let unlocker = MultisigUnlocker::new(config, boxed(signers));
+        let tx = unlocker.unlock(&tx, &group, &provider).unwrap();
+        let message = generate_message(&tx, &group.input_indices).unwrap();
+        assert_eq!(message, unsigned);
+        lock_of(&tx, 0).verify(&message).unwrap();
+        assert!(unlocker.is_unlocked(&tx, &group, &provider).unwrap());
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use ckb_mock_tx_types::MockResourceLoader;
+use ckb_sdk::types::ScriptGroupType;
+use ckb_system_scripts::BUNDLED_CELL;
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, DepType, HeaderView, ScriptHashType, TransactionBuilder, TransactionView},
+    packed::{Byte32, CellDep, CellInput, CellOutput, OutPoint, Script, WitnessArgs},
+    prelude::*,
+    H256,
+};
+use secp256k1::rand;
+
+use super::random_signer;
+use crate::{
+    digest::compute_fee_sighash,
+    validate::{describe_exit_code, FailurePhase, Report, Validator},
+    SecpSigner, Signer,
+};
+
+/// The cells of the transaction, by out point.
+#[derive(Default)]
+struct Cells(HashMap<OutPoint, (CellOutput, Bytes)>);
+
+impl Cells {
+    fn add(&mut self, output: CellOutput, data: Bytes) -> OutPoint {
+        let out_point = OutPoint::new(rand::random::<[u8; 32]>().pack(), 0);
+        self.0.insert(out_point.clone(), (output, data));
+        out_point
+    }
+
+    fn add_code(&mut self, name: &str) -> (OutPoint, Byte32) {
+        let data = Bytes::from(BUNDLED_CELL.get(name).unwrap().to_vec());
+        let hash = CellOutput::calc_data_hash(&data);
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::bytes(data.len() + 100).unwrap().pack())
+            .build();
+        (self.add(output, data), hash)
+    }
+}
+
+impl MockResourceLoader for Cells {
+    fn get_header(&mut self, _hash: H256) -> Result<Option<HeaderView>, String> {
+        Ok(None)
+    }
+
+    fn get_live_cell(
+        &mut self,
+        out_point: OutPoint,
+    ) -> Result<Option<(CellOutput, Bytes, Option<Byte32>)>, String> {
+        Ok(self
+            .0
+            .get(&out_point)
+            .map(|(output, data)| (output.clone(), data.clone(), None)))
+    }
+}
+
+/// A transaction spending a sighash cell of `signer`, unsigned.
+fn sighash_tx(cells: &mut Cells, signer: &SecpSigner) -> TransactionView {
+    let (binary, code_hash) = cells.add_code("specs/cells/secp256k1_blake160_sighash_all");
+    let (data, _) = cells.add_code("specs/cells/secp256k1_data");
+    let lock = Script::new_builder()
+        .code_hash(code_hash)
+        .hash_type(ScriptHashType::Data)
+        .args(Bytes::from(signer.identity().unwrap().to_vec()).pack())
+        .build();
+    let input = cells.add(
+        CellOutput::new_builder()
+            .capacity(Capacity::shannons(100_000_000_000).pack())
+            .lock(lock.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let dep = |out_point| {
+        CellDep::new_builder()
+            .out_point(out_point)
+            .dep_type(DepType::Code)
+            .build()
+    };
+    TransactionBuilder::default()
+        .cell_dep(dep(binary))
+        .cell_dep(dep(data))
+        .input(CellInput::new(input, 0))
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::shannons(99_000_000_000).pack())
+                .lock(lock)
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .build()
+}
+
+fn sign(tx: &TransactionView, signer: &SecpSigner, tamper: bool) -> TransactionView {
+    let digest = compute_fee_sighash(tx, &[0]).unwrap();
+    let mut signature = signer.sign(&digest).unwrap();
+    if tamper {
+        signature[0] ^= 1;
+    }
+    let witness = WitnessArgs::new_builder()
+        .lock(Some(Bytes::from(signature.to_vec())).pack())
+        .build();
+    tx.as_advanced_builder()
+        .set_witnesses(vec![witness.as_bytes().pack()])
+        .build()
+}
+
+#[test]
+fn test_validate_signed_tx() {
+    let mut cells = Cells::default();
+    let signer = random_signer();
+    let tx = sign(&sighash_tx(&mut cells, &signer), &signer, false);
+    let validation = Validator::new().validate_with(&tx, &mut cells).unwrap();
+    assert!(validation.is_ok());
+    assert_eq!(validation.groups.len(), 1);
+    let group = &validation.groups[0];
+    assert_eq!(group.script_group.group_type, ScriptGroupType::Lock);
+    assert_eq!(group.script_group.input_indices, vec![0]);
+    assert!(group.cycles.unwrap() > 0);
+    assert_eq!(validation.cycles(), group.cycles);
+    assert_eq!(validation.failures().count(), 0);
+}
+
+#[test]
+fn test_validate_reports_exit_code() {
+    let mut cells = Cells::default();
+    let signer = random_signer();
+    let tx = sign(&sighash_tx(&mut cells, &signer), &signer, true);
+    let validation = Validator::new().validate_with(&tx, &mut cells).unwrap();
+    assert!(!validation.is_ok());
+    assert_eq!(validation.cycles(), None);
+    let failure = validation.failures().next().unwrap();
+    assert!(failure.exit_code.is_some());
+    assert!(failure.error.is_some());
+
+    let validation = Validator::new()
+        .max_cycles(1000)
+        .validate_with(&sign(&tx, &signer, false), &mut cells)
+        .unwrap();
+    let failure = validation.failures().next().unwrap();
+    assert_eq!(failure.exit_code, None);
+    assert!(failure.error.is_some());
+}
+
+#[test]
+fn test_validate_missing_cell() {
+    let mut cells = Cells::default();
+    let signer = random_signer();
+    let tx = sign(&sighash_tx(&mut cells, &signer), &signer, false);
+    let tx = tx
+        .as_advanced_builder()
+        .input(CellInput::new(OutPoint::new([1u8; 32].pack(), 0), 0))
+        .build();
+    assert!(Validator::new().validate_with(&tx, &mut cells).is_err());
+}
+
+#[test]
+fn test_report() {
+    let mut cells = Cells::default();
+    let signer = random_signer();
+    let unsigned = sighash_tx(&mut cells, &signer);
+    let tx = sign(&unsigned, &signer, false);
+    let report = Validator::new().report_with(&tx, &mut cells);
+    assert!(report.is_ok());
+    assert_eq!(report.tx_hash, tx.hash().unpack());
+    assert_eq!(report.groups.len(), 1);
+    let group = &report.groups[0];
+    assert_eq!(group.group_type, ScriptGroupType::Lock);
+    assert_eq!(group.inputs, vec![0]);
+    assert_eq!(report.cycles, group.cycles);
+
+    let tampered = sign(&unsigned, &signer, true);
+    let report = Validator::new().report_with(&tampered, &mut cells);
+    let failure = report.failure.as_ref().unwrap();
+    assert_eq!(failure.phase, FailurePhase::Script);
+    assert!(failure.exit_code.is_some());
+    assert_eq!(report.groups[0].failure.as_ref(), Some(failure));
+    assert_eq!(report.cycles, None);
+
+    let report = Validator::new()
+        .max_cycles(1000)
+        .report_with(&tx, &mut cells);
+    assert_eq!(report.failure.unwrap().phase, FailurePhase::Cycles);
+
+    let missing = tx
+        .as_advanced_builder()
+        .input(CellInput::new(OutPoint::new([1u8; 32].pack(), 0), 0))
+        .build();
+    let report = Validator::new().report_with(&missing, &mut cells);
+    assert_eq!(report.failure.unwrap().phase, FailurePhase::Resolve);
+    assert!(report.groups.is_empty());
+}
+
+#[test]
+fn test_report_json() {
+    let mut cells = Cells::default();
+    let signer = random_signer();
+    let tx = sign(&sighash_tx(&mut cells, &signer), &signer, true);
+    let report = Validator::new().report_with(&tx, &mut cells);
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["failure"]["phase"], "script");
+    assert_eq!(json["groups"][0]["group_type"], "lock");
+    assert!(json["groups"][0]["script_hash"]
+        .as_str()
+        .unwrap()
+        .starts_with("0x"));
+    let parsed: Report = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed, report);
+}
+
+#[test]
+fn test_describe_exit_code() {
+    assert!(describe_exit_code(-52).unwrap().contains("signature"));
+    assert!(describe_exit_code(-51).is_some());
+    assert!(describe_exit_code(-100).is_none());
+}
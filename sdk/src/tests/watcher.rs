@@ -0,0 +1,154 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, TransactionBuilder},
+    packed::{CellOutput, OutPoint},
+    prelude::*,
+};
+
+use super::{lock_script, random_config};
+use crate::{
+    error::Error,
+    watcher::{Activity, Deposit, DepositHook, Spend, Webhook},
+};
+
+fn deposit() -> Deposit {
+    let (_, config) = random_config(3, 0, 2);
+    Deposit {
+        out_point: OutPoint::new([7u8; 32].pack(), 1),
+        block_number: 12,
+        output: CellOutput::new_builder()
+            .capacity(Capacity::shannons(100_000_000_000).pack())
+            .lock(lock_script(&config))
+            .build(),
+        output_data: Bytes::from(vec![1, 2]),
+    }
+}
+
+/// Serve one HTTP request with `status`, returning its body.
+fn serve_once(status: &'static str) -> (String, thread::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/deposits", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body).unwrap();
+        let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status);
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+        String::from_utf8(body).unwrap()
+    });
+    (url, handle)
+}
+
+#[test]
+fn test_deposits_of_tx() {
+    let (_, config) = random_config(3, 0, 2);
+    let (_, other) = random_config(2, 0, 1);
+    let output = |config, capacity: u64| {
+        CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(lock_script(config))
+            .build()
+    };
+    let tx = TransactionBuilder::default()
+        .output(output(&other, 1000))
+        .output_data(Bytes::new().pack())
+        .output(output(&config, 2000))
+        .output_data(Bytes::from(vec![9]).pack())
+        .build();
+    let deposits = Deposit::from_tx(&tx, 5, &lock_script(&config));
+    assert_eq!(deposits.len(), 1);
+    assert_eq!(deposits[0].out_point, OutPoint::new(tx.hash(), 1));
+    assert_eq!(deposits[0].block_number, 5);
+    assert_eq!(deposits[0].capacity(), 2000);
+    assert_eq!(deposits[0].output_data, Bytes::from(vec![9]));
+    assert!(Deposit::from_tx(&tx, 5, &lock_script(&random_config(1, 0, 1).1)).is_empty());
+}
+
+#[test]
+fn test_spend_of_tx() {
+    let (_, config) = random_config(3, 0, 2);
+    let (_, other) = random_config(2, 0, 1);
+    let output = |config, capacity: u64| {
+        CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(lock_script(config))
+            .build()
+    };
+    let tx = TransactionBuilder::default()
+        .output(output(&other, 1000))
+        .output_data(Bytes::new().pack())
+        .output(output(&config, 2000))
+        .output_data(Bytes::new().pack())
+        .output(output(&other, 500))
+        .output_data(Bytes::new().pack())
+        .build();
+    let spend = Spend::from_tx(tx.clone(), 7, 2, &lock_script(&config));
+    assert_eq!(spend.tx_hash(), tx.hash().unpack());
+    assert_eq!(spend.inputs, 2);
+    // the change back to the config is not outgoing
+    assert_eq!(spend.outgoing, 1500);
+    assert_eq!(Activity::Spend(spend).block_number(), 7);
+    assert_eq!(Activity::Deposit(deposit()).block_number(), 12);
+}
+
+#[test]
+fn test_deposit_json() {
+    let json = deposit().to_json();
+    assert_eq!(json["out_point"]["index"], "0x1");
+    assert_eq!(json["block_number"], "0xc");
+    assert_eq!(json["output"]["capacity"], "0x174876e800");
+    assert_eq!(json["output_data"], "0x0102");
+}
+
+#[test]
+fn test_closure_hook() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+        let seen = seen.clone();
+        move |deposit: &Deposit| -> Result<(), Error> {
+            seen.lock().unwrap().push(deposit.out_point.clone());
+            Ok(())
+        }
+    };
+    let deposit = deposit();
+    hook.on_deposit(&deposit).unwrap();
+    assert_eq!(*seen.lock().unwrap(), vec![deposit.out_point]);
+}
+
+#[test]
+fn test_webhook_posts_deposit() {
+    let (url, server) = serve_once("200 OK");
+    let deposit = deposit();
+    Webhook::new(&url).on_deposit(&deposit).unwrap();
+    let body: serde_json::Value = serde_json::from_str(&server.join().unwrap()).unwrap();
+    assert_eq!(body, deposit.to_json());
+}
+
+#[test]
+fn test_webhook_failure() {
+    let (url, server) = serve_once("500 Internal Server Error");
+    let err = Webhook::new(&url).on_deposit(&deposit()).unwrap_err();
+    assert!(matches!(err, Error::Webhook(_)));
+    server.join().unwrap();
+}
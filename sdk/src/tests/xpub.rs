@@ -0,0 +1,72 @@
+use crate::{
+    hd::{ckb_account_path, ckb_path, ExtendedPrivKey, ExtendedPubKey, Mnemonic},
+    xpub::XpubConfig,
+    SecpSigner, Signer,
+};
+
+/// A cosigner setting up its keys, handing out the xpub of its account.
+fn cosigner(account: u32) -> (Mnemonic, ExtendedPubKey) {
+    let mnemonic = Mnemonic::generate(12).unwrap();
+    let master = ExtendedPrivKey::from_seed(&mnemonic.to_seed("")).unwrap();
+    let xpub = master
+        .derive(&ckb_account_path(account).unwrap())
+        .unwrap()
+        .to_public();
+    (mnemonic, xpub)
+}
+
+#[test]
+fn test_xpub_serialization() {
+    // test vector 1 of BIP-32
+    let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+    let master = ExtendedPrivKey::from_seed(&seed).unwrap();
+    let vectors = [
+        ("m", "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8"),
+        ("m/0'", "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw"),
+    ];
+    for (path, xpub) in vectors.iter() {
+        let key = master.derive(&path.parse().unwrap()).unwrap().to_public();
+        assert_eq!(key.to_string(), *xpub, "{}", path);
+        assert_eq!(xpub.parse::<ExtendedPubKey>().unwrap(), key, "{}", path);
+    }
+
+    let xpub = vectors[0].1;
+    let mut typo = xpub.to_string();
+    typo.replace_range(20..21, "z");
+    assert!(typo.parse::<ExtendedPubKey>().is_err());
+    assert!(xpub[..xpub.len() - 1].parse::<ExtendedPubKey>().is_err());
+    assert!("xpub0".parse::<ExtendedPubKey>().is_err());
+}
+
+#[test]
+fn test_xpub_config() {
+    let cosigners: Vec<_> = (0..3).map(cosigner).collect();
+    let xpubs: Vec<_> = cosigners.iter().map(|(_, xpub)| xpub.clone()).collect();
+    assert_eq!(xpubs[0].depth(), 3);
+    let config = XpubConfig::new(xpubs.clone(), 1, 2).unwrap();
+
+    // a fresh address per index, signed by the keys at that index
+    let first = config.config(0).unwrap();
+    let second = config.config(1).unwrap();
+    assert_ne!(first.lock_args(), second.lock_args());
+    for (account, (mnemonic, _)) in cosigners.iter().enumerate() {
+        let path = ckb_path(account as u32, 1).unwrap();
+        let signer = SecpSigner::from_mnemonic(mnemonic, "", &path).unwrap();
+        assert_eq!(second.pubkey_hashes()[account], signer.identity().unwrap());
+    }
+
+    let deposit = second.with_since(Some(0x2000_0100_0000_00b4)).lock_args();
+    assert_eq!(config.index_of(&deposit, 0..10).unwrap(), Some(1));
+    assert_eq!(config.index_of(&deposit, 2..10).unwrap(), None);
+
+    // the xpubs travel as strings
+    let parsed: Vec<ExtendedPubKey> = xpubs
+        .iter()
+        .map(|xpub| xpub.to_string().parse().unwrap())
+        .collect();
+    assert_eq!(XpubConfig::new(parsed, 1, 2).unwrap(), config);
+
+    assert!(XpubConfig::new(xpubs.clone(), 0, 4).is_err());
+    let duplicated = vec![xpubs[0].clone(), xpubs[1].clone(), xpubs[0].clone()];
+    assert!(XpubConfig::new(duplicated, 0, 2).is_err());
+}
@@ -0,0 +1,485 @@
+//! Trezor and OneKey backend of the `Signer` trait.
+//!
+//! Both speak the Trezor wire protocol: protobuf messages, each prefixed by
+//! its type and length and cut into 64 bytes reports, see `to_reports` and
+//! `from_reports`. The public key comes from the `GetPublicKey` message of
+//! every firmware, at a BIP32 path as for the Ledger. Signing goes through
+//! the CKB messages:
+//!
+//! ```text
+//! CkbSignDigest { address_n = 1 (repeated uint32), digest = 2, message = 3 }
+//! CkbSignature { signature = 1 }
+//! ```
+//!
+//! where `message` is the cobuild message of the transaction, see
+//! `cobuild.rs`, for the firmware to display what is signed, and the answer
+//! the 65 bytes `r | s | recid` signature of the digest, see
+//! `TrezorSigner::display`. A firmware without
+//! the CKB messages fails with an unexpected message. One with them but
+//! unable to display the cobuild message fails with a data error, and the
+//! digest is then signed blind when `TrezorSigner::allow_blind` is set,
+//! which requires blind signing to be enabled on the device.
+//!
+//! The device may ask for a button press, acknowledged here while the user
+//! confirms on the device, but the PIN and passphrase must be entered before
+//! the signer is connected.
+
+use std::convert::TryInto;
+
+use secp256k1::PublicKey;
+
+use crate::{
+    constants::{BLAKE160_SIZE, DIGEST_SIZE, SIGNATURE_SIZE},
+    error::Error,
+    ledger::DerivationPath,
+    signer::{pubkey_identity, recover_pubkey, Signer},
+};
+
+pub const MESSAGE_INITIALIZE: u16 = 0;
+pub const MESSAGE_FAILURE: u16 = 3;
+pub const MESSAGE_GET_PUBLIC_KEY: u16 = 11;
+pub const MESSAGE_PUBLIC_KEY: u16 = 12;
+pub const MESSAGE_FEATURES: u16 = 17;
+pub const MESSAGE_PIN_MATRIX_REQUEST: u16 = 18;
+pub const MESSAGE_BUTTON_REQUEST: u16 = 26;
+pub const MESSAGE_BUTTON_ACK: u16 = 27;
+pub const MESSAGE_PASSPHRASE_REQUEST: u16 = 41;
+pub const MESSAGE_CKB_SIGN_DIGEST: u16 = 11710;
+pub const MESSAGE_CKB_SIGNATURE: u16 = 11711;
+
+/// `Failure` codes.
+pub const FAILURE_UNEXPECTED_MESSAGE: u64 = 1;
+pub const FAILURE_DATA_ERROR: u64 = 3;
+pub const FAILURE_ACTION_CANCELLED: u64 = 4;
+
+/// Size of the reports of the wire protocol.
+pub const REPORT_SIZE: usize = 64;
+const MAGIC: u8 = b'?';
+const HEADER_SIZE: usize = 9;
+
+const CURVE: &str = "secp256k1";
+
+/// A message of the wire protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub kind: u16,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    pub fn new(kind: u16, payload: Vec<u8>) -> Self {
+        Message { kind, payload }
+    }
+}
+
+/// Transport delivering messages to a device, USB HID for real devices, or
+/// a mock in tests.
+pub trait TrezorTransport {
+    /// Send `message` and read the answer.
+    fn call(&self, message: &Message) -> Result<Message, Error>;
+}
+
+/// The reports carrying `message`: the first one starts with `?##`, the type
+/// and the length of the payload in big endian, the next ones with `?`, the
+/// last one padded with zeros.
+pub fn to_reports(message: &Message) -> Vec<[u8; REPORT_SIZE]> {
+    let mut data = vec![b'#', b'#'];
+    data.extend_from_slice(&message.kind.to_be_bytes());
+    data.extend_from_slice(&(message.payload.len() as u32).to_be_bytes());
+    data.extend_from_slice(&message.payload);
+    data.chunks(REPORT_SIZE - 1)
+        .map(|chunk| {
+            let mut report = [0u8; REPORT_SIZE];
+            report[0] = MAGIC;
+            report[1..=chunk.len()].copy_from_slice(chunk);
+            report
+        })
+        .collect()
+}
+
+/// The message of the reports returned by `read`, one by one.
+pub fn from_reports<F>(mut read: F) -> Result<Message, Error>
+where
+    F: FnMut() -> Result<[u8; REPORT_SIZE], Error>,
+{
+    let first = read()?;
+    if first[..3] != [MAGIC, b'#', b'#'] {
+        return Err(response_err("invalid first report"));
+    }
+    let kind = u16::from_be_bytes([first[3], first[4]]);
+    let len = u32::from_be_bytes(first[5..HEADER_SIZE].try_into().unwrap()) as usize;
+    let mut payload = first[HEADER_SIZE..].to_vec();
+    while payload.len() < len {
+        let report = read()?;
+        if report[0] != MAGIC {
+            return Err(response_err("invalid report"));
+        }
+        payload.extend_from_slice(&report[1..]);
+    }
+    payload.truncate(len);
+    Ok(Message { kind, payload })
+}
+
+/// Who made the device, from its `Features`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Vendor {
+    Trezor,
+    OneKey,
+    Other,
+}
+
+/// What the device reports on `Initialize`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Features {
+    pub vendor: String,
+    pub version: (u32, u32, u32),
+    pub model: Option<String>,
+}
+
+impl Features {
+    pub fn vendor_kind(&self) -> Vendor {
+        match self.vendor.as_str() {
+            "trezor.io" => Vendor::Trezor,
+            "onekey.so" => Vendor::OneKey,
+            _ => Vendor::Other,
+        }
+    }
+
+    fn decode(payload: &[u8]) -> Result<Self, Error> {
+        let fields = decode_fields(payload)?;
+        let version = |number| varint_field(&fields, number).unwrap_or_default() as u32;
+        Ok(Features {
+            vendor: string_field(&fields, 1)?.unwrap_or_default(),
+            version: (version(2), version(3), version(4)),
+            model: string_field(&fields, 21)?,
+        })
+    }
+}
+
+/// A key held by a Trezor or OneKey device at a given derivation path.
+pub struct TrezorSigner<T> {
+    transport: T,
+    path: DerivationPath,
+    features: Features,
+    pubkey: PublicKey,
+    message: Option<Vec<u8>>,
+    allow_blind: bool,
+}
+
+impl<T: TrezorTransport> TrezorSigner<T> {
+    /// Connect to the key at `path`, the features and the public key are
+    /// fetched once and cached.
+    pub fn new(transport: T, path: DerivationPath) -> Result<Self, Error> {
+        let features = call(&transport, Message::new(MESSAGE_INITIALIZE, Vec::new()))?;
+        let features = Features::decode(&expect(features, MESSAGE_FEATURES)?.payload)?;
+        let pubkey = get_public_key(&transport, &path)?;
+        Ok(TrezorSigner {
+            transport,
+            path,
+            features,
+            pubkey,
+            message: None,
+            allow_blind: false,
+        })
+    }
+
+    /// Display the cobuild `message` of the transaction when signing it, e.g.
+    /// `cobuild::message`, instead of signing blind.
+    pub fn display(mut self, message: Vec<u8>) -> Self {
+        self.message = Some(message);
+        self
+    }
+
+    /// Sign the bare digest when the firmware can't display the cobuild
+    /// message.
+    pub fn allow_blind(mut self, allow_blind: bool) -> Self {
+        self.allow_blind = allow_blind;
+        self
+    }
+
+    pub fn path(&self) -> &DerivationPath {
+        &self.path
+    }
+
+    pub fn pubkey(&self) -> &PublicKey {
+        &self.pubkey
+    }
+
+    pub fn features(&self) -> &Features {
+        &self.features
+    }
+
+    /// Sign `digest` with the device displaying the cobuild `message` of the
+    /// transaction, blind when allowed and the firmware can't display it.
+    pub fn sign_displaying(
+        &self,
+        digest: &[u8; DIGEST_SIZE],
+        message: &[u8],
+    ) -> Result<[u8; SIGNATURE_SIZE], Error> {
+        match self.sign_digest(digest, Some(message)) {
+            Err(Error::TrezorFailure(FAILURE_DATA_ERROR, _)) if self.allow_blind => {
+                self.sign_digest(digest, None)
+            }
+            result => result,
+        }
+    }
+
+    fn sign_digest(
+        &self,
+        digest: &[u8; DIGEST_SIZE],
+        message: Option<&[u8]>,
+    ) -> Result<[u8; SIGNATURE_SIZE], Error> {
+        let mut payload = path_fields(&self.path);
+        bytes_field(&mut payload, 2, digest);
+        if let Some(message) = message {
+            bytes_field(&mut payload, 3, message);
+        }
+        let answer = match call(
+            &self.transport,
+            Message::new(MESSAGE_CKB_SIGN_DIGEST, payload),
+        ) {
+            Err(Error::TrezorFailure(FAILURE_UNEXPECTED_MESSAGE, _)) => {
+                return Err(Error::HardwareUnsupported(format!(
+                    "the {} firmware {}.{}.{} can't sign CKB digests",
+                    self.features.vendor,
+                    self.features.version.0,
+                    self.features.version.1,
+                    self.features.version.2
+                )))
+            }
+            result => expect(result?, MESSAGE_CKB_SIGNATURE)?,
+        };
+        let signature: [u8; SIGNATURE_SIZE] = bytes_field_of(&decode_fields(&answer.payload)?, 1)
+            .and_then(|signature| signature.try_into().ok())
+            .ok_or_else(|| response_err("signature missing or not 65 bytes"))?;
+        // never hand out a signature the contract would reject
+        if recover_pubkey(digest, &signature)? != self.pubkey {
+            return Err(Error::SignatureMismatch);
+        }
+        Ok(signature)
+    }
+}
+
+impl<T: TrezorTransport> Signer for TrezorSigner<T> {
+    fn identity(&self) -> Result<[u8; BLAKE160_SIZE], Error> {
+        Ok(pubkey_identity(&self.pubkey))
+    }
+
+    /// Signs blind unless the cobuild message to display is set.
+    fn sign(&self, digest: &[u8; DIGEST_SIZE]) -> Result<[u8; SIGNATURE_SIZE], Error> {
+        match &self.message {
+            Some(message) => self.sign_displaying(digest, message),
+            None => self.sign_digest(digest, None),
+        }
+    }
+}
+
+fn get_public_key<T: TrezorTransport>(
+    transport: &T,
+    path: &DerivationPath,
+) -> Result<PublicKey, Error> {
+    let mut payload = path_fields(path);
+    bytes_field(&mut payload, 2, CURVE.as_bytes());
+    let answer = call(transport, Message::new(MESSAGE_GET_PUBLIC_KEY, payload))?;
+    let answer = expect(answer, MESSAGE_PUBLIC_KEY)?;
+    // PublicKey { node = 1: HDNodeType { public_key = 6 } }
+    let node = bytes_field_of(&decode_fields(&answer.payload)?, 1)
+        .ok_or_else(|| response_err("public key node missing"))?;
+    let key = bytes_field_of(&decode_fields(&node)?, 6)
+        .ok_or_else(|| response_err("public key missing"))?;
+    Ok(PublicKey::from_slice(&key)?)
+}
+
+/// Send `message`, acknowledging the button requests, and turn a `Failure`
+/// into an error.
+fn call<T: TrezorTransport>(transport: &T, message: Message) -> Result<Message, Error> {
+    let mut answer = transport.call(&message)?;
+    loop {
+        match answer.kind {
+            MESSAGE_BUTTON_REQUEST => {
+                answer = transport.call(&Message::new(MESSAGE_BUTTON_ACK, Vec::new()))?;
+            }
+            MESSAGE_PIN_MATRIX_REQUEST | MESSAGE_PASSPHRASE_REQUEST => {
+                return Err(Error::TrezorTransport(
+                    "the device is locked, enter the PIN and passphrase first".to_string(),
+                ))
+            }
+            MESSAGE_FAILURE => {
+                let fields = decode_fields(&answer.payload)?;
+                return Err(Error::TrezorFailure(
+                    varint_field(&fields, 1).unwrap_or_default(),
+                    string_field(&fields, 2)?.unwrap_or_default(),
+                ));
+            }
+            _ => return Ok(answer),
+        }
+    }
+}
+
+fn expect(message: Message, kind: u16) -> Result<Message, Error> {
+    if message.kind != kind {
+        return Err(response_err(&format!(
+            "message {} instead of {}",
+            message.kind, kind
+        )));
+    }
+    Ok(message)
+}
+
+fn response_err(message: &str) -> Error {
+    Error::TrezorResponse(message.to_string())
+}
+
+/// A protobuf field, the fixed size ones are skipped.
+#[derive(Clone, Debug)]
+enum Field {
+    Varint(u64),
+    Bytes(Vec<u8>),
+}
+
+/// `address_n = 1`, repeated and not packed as in the firmware messages.
+fn path_fields(path: &DerivationPath) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for index in path.indices() {
+        write_varint(&mut payload, 1 << 3);
+        write_varint(&mut payload, u64::from(*index));
+    }
+    payload
+}
+
+fn bytes_field(payload: &mut Vec<u8>, number: u32, bytes: &[u8]) {
+    write_varint(payload, u64::from(number) << 3 | 2);
+    write_varint(payload, bytes.len() as u64);
+    payload.extend_from_slice(bytes);
+}
+
+fn write_varint(payload: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        payload.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    payload.push(value as u8);
+}
+
+fn read_varint(data: &[u8], offset: &mut usize) -> Result<u64, Error> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data
+            .get(*offset)
+            .ok_or_else(|| response_err("truncated varint"))?;
+        *offset += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(response_err("varint too long"))
+}
+
+fn decode_fields(data: &[u8]) -> Result<Vec<(u32, Field)>, Error> {
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let key = read_varint(data, &mut offset)?;
+        let number = (key >> 3) as u32;
+        let skip = match key & 7 {
+            0 => {
+                fields.push((number, Field::Varint(read_varint(data, &mut offset)?)));
+                0
+            }
+            1 => 8,
+            2 => {
+                let len = read_varint(data, &mut offset)? as usize;
+                let bytes = data
+                    .get(offset..offset.saturating_add(len))
+                    .ok_or_else(|| response_err("truncated field"))?;
+                fields.push((number, Field::Bytes(bytes.to_vec())));
+                len
+            }
+            5 => 4,
+            wire_type => {
+                return Err(response_err(&format!(
+                    "unsupported wire type {}",
+                    wire_type
+                )))
+            }
+        };
+        offset += skip;
+    }
+    if offset > data.len() {
+        return Err(response_err("truncated field"));
+    }
+    Ok(fields)
+}
+
+fn varint_field(fields: &[(u32, Field)], number: u32) -> Option<u64> {
+    fields.iter().find_map(|(n, field)| match field {
+        Field::Varint(value) if *n == number => Some(*value),
+        _ => None,
+    })
+}
+
+fn bytes_field_of(fields: &[(u32, Field)], number: u32) -> Option<Vec<u8>> {
+    fields.iter().find_map(|(n, field)| match field {
+        Field::Bytes(bytes) if *n == number => Some(bytes.clone()),
+        _ => None,
+    })
+}
+
+fn string_field(fields: &[(u32, Field)], number: u32) -> Result<Option<String>, Error> {
+    bytes_field_of(fields, number)
+        .map(|bytes| String::from_utf8(bytes).map_err(|_| response_err("invalid string")))
+        .transpose()
+}
+
+#[cfg(feature = "trezor-hid")]
+pub mod hid {
+    use std::{convert::TryInto, sync::Mutex};
+
+    use hidapi::{HidApi, HidDevice};
+
+    use super::{from_reports, to_reports, Message, TrezorTransport, REPORT_SIZE};
+    use crate::error::Error;
+
+    /// USB ids of the Trezor One, and of the Trezor T and OneKey bootloader
+    /// and firmware.
+    pub const DEVICE_IDS: [(u16, u16); 3] = [(0x534c, 0x0001), (0x1209, 0x53c0), (0x1209, 0x53c1)];
+
+    /// USB HID transport of the first Trezor or OneKey device found.
+    pub struct HidTransport(Mutex<HidDevice>);
+
+    impl HidTransport {
+        pub fn new() -> Result<Self, Error> {
+            let api = HidApi::new().map_err(transport_err)?;
+            let info = api
+                .device_list()
+                .find(|info| DEVICE_IDS.contains(&(info.vendor_id(), info.product_id())))
+                .ok_or_else(|| Error::TrezorTransport("no device found".to_string()))?;
+            let device = info.open_device(&api).map_err(transport_err)?;
+            Ok(HidTransport(Mutex::new(device)))
+        }
+    }
+
+    impl TrezorTransport for HidTransport {
+        fn call(&self, message: &Message) -> Result<Message, Error> {
+            let device = self.0.lock().expect("poisoned lock");
+            for report in to_reports(message) {
+                // report id 0 first
+                let mut data = vec![0u8];
+                data.extend_from_slice(&report);
+                device.write(&data).map_err(transport_err)?;
+            }
+            from_reports(|| {
+                let mut report = [0u8; REPORT_SIZE];
+                let len = device.read(&mut report).map_err(transport_err)?;
+                report[..len]
+                    .try_into()
+                    .map_err(|_| Error::TrezorTransport(format!("short report of {} bytes", len)))
+            })
+        }
+    }
+
+    fn transport_err(err: hidapi::HidError) -> Error {
+        Error::TrezorTransport(err.to_string())
+    }
+}
@@ -0,0 +1,287 @@
+//! Transfers of the sUDT and xUDT tokens held by a config.
+//!
+//! The token cells of the config are taken in order until they cover the
+//! payments, each recipient gets a cell of its amount and the rest goes back
+//! to the change lock in a token cell of its own. The amount is the little
+//! endian u128 at the head of the cell data, the layout of sUDT and xUDT, the
+//! outputs carry the amount alone.
+//!
+//! Every token cell is given the capacity it occupies, no more: the capacity
+//! of the token cells spent and plain cells of the config pay for them and
+//! for the fee, the rest going back to the change lock in a plain cell, or
+//! into the token change when too small for a cell of its own, or else to
+//! the fee.
+//!
+//! The token inputs come first, so the witness of the first input is also
+//! the one the xUDT script reads its owner mode and extension data from:
+//! `UdtTransfer::witness_types` sets its `input_type` and `output_type`.
+//! The signatures cover the whole first witness, see `digest.rs`, so the
+//! cosigners approve that data too, and configs with strict witnesses, whose
+//! other witnesses must stay empty, transfer xUDT all the same.
+
+use std::convert::TryInto;
+
+use ckb_sdk::{traits::LiveCell, types::ScriptGroup};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, TransactionBuilder, TransactionView},
+    packed::{CellDep, CellInput, CellOutput, Script, WitnessArgs},
+    prelude::*,
+};
+
+use crate::{
+    config::MultisigConfig, error::Error, fee::FeeEstimator, request::SigningRequest,
+    since::apply_since,
+};
+
+const UDT_AMOUNT_SIZE: usize = 16;
+
+/// Tokens sent to a lock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UdtPayment {
+    pub to: Script,
+    pub amount: u128,
+}
+
+pub struct UdtTransfer {
+    config: MultisigConfig,
+    lock_script: Script,
+    type_script: Script,
+    cell_deps: Vec<CellDep>,
+    fee_rate: u64,
+    change: Option<Script>,
+    input_type: Option<Bytes>,
+    output_type: Option<Bytes>,
+}
+
+impl UdtTransfer {
+    /// `lock_script` is the lock of the config cells, `type_script` the one
+    /// of the token, `cell_deps` are the cell deps needed to run both and
+    /// `fee_rate` is in shannons per 1000 bytes.
+    pub fn new(
+        config: MultisigConfig,
+        lock_script: Script,
+        type_script: Script,
+        cell_deps: Vec<CellDep>,
+        fee_rate: u64,
+    ) -> Self {
+        UdtTransfer {
+            config,
+            lock_script,
+            type_script,
+            cell_deps,
+            fee_rate,
+            change: None,
+            input_type: None,
+            output_type: None,
+        }
+    }
+
+    /// Send the token and capacity change to another lock, the config lock
+    /// by default.
+    pub fn change(mut self, change: Script) -> Self {
+        self.change = Some(change);
+        self
+    }
+
+    /// The `input_type` and `output_type` of the first witness, e.g. the
+    /// `XudtWitnessInput` of an xUDT in owner mode or with extensions.
+    pub fn witness_types(mut self, input_type: Option<Bytes>, output_type: Option<Bytes>) -> Self {
+        self.input_type = input_type;
+        self.output_type = output_type;
+        self
+    }
+
+    /// Build the transaction paying every payment from `udt_cells`, token
+    /// cells of the config, with the capacity and the fee paid from `cells`,
+    /// plain cells of the config, both e.g. from `CellSet::spendable`.
+    pub fn build(
+        &self,
+        payments: &[UdtPayment],
+        udt_cells: &[LiveCell],
+        cells: &[LiveCell],
+    ) -> Result<SigningRequest, Error> {
+        if payments.is_empty() {
+            return Err(Error::InvalidParameter("no payment".to_string()));
+        }
+        let mut amount = 0u128;
+        for (i, payment) in payments.iter().enumerate() {
+            if payment.amount == 0 {
+                return Err(Error::InvalidParameter(format!(
+                    "payment #{} of no token",
+                    i + 1
+                )));
+            }
+            amount = amount.checked_add(payment.amount).ok_or_else(|| {
+                Error::InvalidParameter("the payments overflow the amount of a token".to_string())
+            })?;
+        }
+        if let Some(cell) = cells
+            .iter()
+            .find(|cell| cell.output.lock() != self.lock_script || cell.output.type_().is_some())
+        {
+            return Err(Error::InvalidParameter(format!(
+                "cell {} is not a plain cell of the config",
+                cell.out_point
+            )));
+        }
+
+        let mut inputs = Vec::new();
+        let mut held = 0u128;
+        for cell in udt_cells {
+            if held >= amount {
+                break;
+            }
+            held += self.udt_amount(cell)?;
+            inputs.push(cell.clone());
+        }
+        if held < amount {
+            return Err(Error::InvalidParameter(format!(
+                "{} token cells hold {}, short of the {} to send",
+                udt_cells.len(),
+                held,
+                amount
+            )));
+        }
+        let mut outputs: Vec<_> = payments
+            .iter()
+            .map(|payment| self.udt_output(&payment.to, payment.amount))
+            .collect();
+        let token_change = held - amount;
+        if token_change > 0 {
+            outputs.push(self.udt_output(&self.change_lock(), token_change));
+        }
+
+        let paid: u64 = outputs.iter().map(|(output, _)| capacity(output)).sum();
+        let change_output = CellOutput::new_builder().lock(self.change_lock()).build();
+        let change_occupied = occupied(&change_output, 0);
+        let estimator = FeeEstimator::new(self.fee_rate);
+        let mut next = cells.iter();
+        loop {
+            let total: u64 = inputs.iter().map(|cell| capacity(&cell.output)).sum();
+            let mut with_change = outputs.clone();
+            with_change.push((change_output.clone(), Bytes::new()));
+            let (tx, script_group) = self.tx(&inputs, &with_change)?;
+            let groups = [(script_group.clone(), self.config.clone())];
+            let fee = estimator.estimate(&tx, &groups, None)?.fee;
+            let change = total.checked_sub(paid + fee);
+            if let Some(change) = change.filter(|change| *change >= change_occupied) {
+                let last = with_change.len() - 1;
+                with_change[last].0 = change_output.as_builder().capacity(change).build();
+                let (tx, script_group) = self.tx(&inputs, &with_change)?;
+                return Ok(self.request(tx, script_group, fee));
+            }
+            match next.next() {
+                Some(cell) => inputs.push(cell.clone()),
+                None => {
+                    let (tx, script_group) = self.tx(&inputs, &outputs)?;
+                    let groups = [(script_group.clone(), self.config.clone())];
+                    let fee = estimator.estimate(&tx, &groups, None)?.fee;
+                    if total < paid + fee {
+                        return Err(Error::InsufficientCapacity(format!(
+                            "{} shannons can't pay the {} shannons of the token cells and {} fee",
+                            total, paid, fee
+                        )));
+                    }
+                    let rest = total - paid - fee;
+                    if token_change == 0 {
+                        // too little left for a change cell, it goes to the fee
+                        return Ok(self.request(tx, script_group, fee + rest));
+                    }
+                    let last = outputs.len() - 1;
+                    let output = &outputs[last].0;
+                    outputs[last].0 = output
+                        .clone()
+                        .as_builder()
+                        .capacity(capacity(output) + rest)
+                        .build();
+                    let (tx, script_group) = self.tx(&inputs, &outputs)?;
+                    return Ok(self.request(tx, script_group, fee));
+                }
+            }
+        }
+    }
+
+    fn change_lock(&self) -> Script {
+        self.change
+            .clone()
+            .unwrap_or_else(|| self.lock_script.clone())
+    }
+
+    fn udt_amount(&self, cell: &LiveCell) -> Result<u128, Error> {
+        if cell.output.lock() != self.lock_script
+            || cell.output.type_().to_opt() != Some(self.type_script.clone())
+        {
+            return Err(Error::InvalidParameter(format!(
+                "cell {} is not a token cell of the config",
+                cell.out_point
+            )));
+        }
+        let data = cell.output_data.get(..UDT_AMOUNT_SIZE).ok_or_else(|| {
+            Error::InvalidParameter(format!(
+                "token cell {} has no amount in its data",
+                cell.out_point
+            ))
+        })?;
+        Ok(u128::from_le_bytes(data.try_into().expect("16 bytes")))
+    }
+
+    /// A token cell of `amount` for `lock`, with the capacity it occupies.
+    fn udt_output(&self, lock: &Script, amount: u128) -> (CellOutput, Bytes) {
+        let data = Bytes::from(amount.to_le_bytes().to_vec());
+        let output = CellOutput::new_builder()
+            .lock(lock.clone())
+            .type_(Some(self.type_script.clone()).pack())
+            .build();
+        let required = occupied(&output, data.len());
+        (output.as_builder().capacity(required).build(), data)
+    }
+
+    fn tx(
+        &self,
+        inputs: &[LiveCell],
+        outputs: &[(CellOutput, Bytes)],
+    ) -> Result<(TransactionView, ScriptGroup), Error> {
+        let mut script_group = ScriptGroup::from_lock_script(&self.lock_script);
+        script_group.input_indices = (0..inputs.len()).collect();
+        let mut builder = TransactionBuilder::default()
+            .cell_deps(self.cell_deps.clone())
+            .inputs(
+                inputs
+                    .iter()
+                    .map(|cell| CellInput::new(cell.out_point.clone(), 0)),
+            )
+            .outputs(outputs.iter().map(|(output, _)| output.clone()))
+            .outputs_data(outputs.iter().map(|(_, data)| data.pack()));
+        if self.input_type.is_some() || self.output_type.is_some() {
+            let witness = WitnessArgs::new_builder()
+                .input_type(self.input_type.clone().pack())
+                .output_type(self.output_type.clone().pack())
+                .build();
+            builder = builder.witness(witness.as_bytes().pack());
+        }
+        let tx = apply_since(&builder.build(), &script_group, self.config.since())?;
+        Ok((tx, script_group))
+    }
+
+    fn request(&self, tx: TransactionView, script_group: ScriptGroup, fee: u64) -> SigningRequest {
+        SigningRequest {
+            config: self.config.clone(),
+            tx,
+            script_group,
+            fee,
+            genesis_hash: None,
+        }
+    }
+}
+
+fn occupied(output: &CellOutput, data_size: usize) -> u64 {
+    output
+        .occupied_capacity(Capacity::bytes(data_size).expect("data size"))
+        .expect("occupied capacity")
+        .as_u64()
+}
+
+fn capacity(output: &CellOutput) -> u64 {
+    Unpack::<u64>::unpack(&output.capacity())
+}
@@ -0,0 +1,195 @@
+//! ckb-sdk integration, lets the ckb-sdk transaction builders (transfer, DAO,
+//! UDT...) unlock cells guarded by this lock.
+//!
+//! Register a `MultisigUnlocker` under the `ScriptId` of the deployed contract:
+//!
+//! ```ignore
+//! let mut unlockers: HashMap<ScriptId, Box<dyn ScriptUnlocker>> = HashMap::new();
+//! unlockers.insert(script_id, Box::new(MultisigUnlocker::new(config, signers)));
+//! ```
+
+use ckb_sdk::{
+    traits::TransactionDependencyProvider,
+    types::ScriptGroup,
+    unlock::{fill_witness_lock, ScriptSignError, ScriptSigner, ScriptUnlocker, UnlockError},
+};
+use ckb_types::{core::TransactionView, packed::WitnessArgs};
+
+use crate::{
+    config::MultisigConfig,
+    digest::generate_message,
+    error::Error,
+    signer::Signer,
+    witness::{set_witness_lock, witness_args, MultisigLock},
+};
+
+pub type BoxedSigner = Box<dyn Signer + Send + Sync>;
+
+/// Signs the script groups of one config with the local signers.
+///
+/// Keys not in the config, or which already signed, are skipped, so the same
+/// transaction can be passed from cosigner to cosigner, each adding their
+/// signatures until the threshold is reached.
+pub struct MultisigScriptSigner {
+    config: MultisigConfig,
+    signers: Vec<BoxedSigner>,
+}
+
+impl MultisigScriptSigner {
+    pub fn new(config: MultisigConfig, signers: Vec<BoxedSigner>) -> Self {
+        MultisigScriptSigner { config, signers }
+    }
+
+    pub fn config(&self) -> &MultisigConfig {
+        &self.config
+    }
+
+    pub fn signers(&self) -> &[BoxedSigner] {
+        &self.signers
+    }
+
+    /// The current lock field of the script group, a placeholder when the
+    /// witness is not signed yet.
+    pub fn current_lock(
+        &self,
+        tx: &TransactionView,
+        script_group: &ScriptGroup,
+    ) -> Result<MultisigLock, Error> {
+        let lock = first_witness(tx, script_group)?
+            .lock()
+            .to_opt()
+            .map(|lock| MultisigLock::parse(&lock.raw_data()))
+            .transpose()?;
+        match lock {
+            Some(lock) if lock.config().multisig_script() != self.config.multisig_script() => Err(
+                Error::InvalidWitness("the witness belongs to another config".to_string()),
+            ),
+            Some(lock) if lock.filled_count() > 0 => Ok(lock),
+            _ => Ok(MultisigLock::new(self.config.clone())),
+        }
+    }
+
+    /// Add the signatures of the local signers to the script group.
+    pub fn sign(
+        &self,
+        tx: &TransactionView,
+        script_group: &ScriptGroup,
+    ) -> Result<TransactionView, Error> {
+        let mut lock = self.current_lock(tx, script_group)?;
+        let tx = set_lock(tx, script_group, &lock)?;
+        let message = generate_message(&tx, &script_group.input_indices)?;
+        let mut signed = lock.signed_identities(&message)?;
+        for signer in &self.signers {
+            if lock.is_complete() {
+                break;
+            }
+            let identity = signer.identity()?;
+            if self.config.position(&identity).is_none() || signed.contains(&identity) {
+                continue;
+            }
+            lock.add_signature(signer.sign(&message)?)?;
+            signed.push(identity);
+        }
+        set_lock(&tx, script_group, &lock)
+    }
+
+    fn is_unlocked(&self, tx: &TransactionView, script_group: &ScriptGroup) -> Result<bool, Error> {
+        let lock = self.current_lock(tx, script_group)?;
+        if !lock.is_complete() {
+            return Ok(false);
+        }
+        let message = generate_message(tx, &script_group.input_indices)?;
+        Ok(lock.verify(&message).is_ok())
+    }
+}
+
+impl ScriptSigner for MultisigScriptSigner {
+    fn match_args(&self, args: &[u8]) -> bool {
+        args == self.config.lock_args().as_ref()
+    }
+
+    fn sign_tx(
+        &self,
+        tx: &TransactionView,
+        script_group: &ScriptGroup,
+    ) -> Result<TransactionView, ScriptSignError> {
+        self.sign(tx, script_group)
+            .map_err(|err| ScriptSignError::Other(err.into()))
+    }
+}
+
+/// `ScriptUnlocker` of this lock, supports partially signed transactions.
+pub struct MultisigUnlocker {
+    signer: MultisigScriptSigner,
+}
+
+impl MultisigUnlocker {
+    pub fn new(config: MultisigConfig, signers: Vec<BoxedSigner>) -> Self {
+        MultisigUnlocker {
+            signer: MultisigScriptSigner::new(config, signers),
+        }
+    }
+
+    pub fn signer(&self) -> &MultisigScriptSigner {
+        &self.signer
+    }
+}
+
+impl From<MultisigScriptSigner> for MultisigUnlocker {
+    fn from(signer: MultisigScriptSigner) -> Self {
+        MultisigUnlocker { signer }
+    }
+}
+
+#[async_trait::async_trait]
+impl ScriptUnlocker for MultisigUnlocker {
+    fn match_args(&self, args: &[u8]) -> bool {
+        self.signer.match_args(args)
+    }
+
+    async fn is_unlocked_async(
+        &self,
+        tx: &TransactionView,
+        script_group: &ScriptGroup,
+        _tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<bool, UnlockError> {
+        self.signer
+            .is_unlocked(tx, script_group)
+            .map_err(|err| UnlockError::Other(err.into()))
+    }
+
+    async fn unlock_async(
+        &self,
+        tx: &TransactionView,
+        script_group: &ScriptGroup,
+        _tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<TransactionView, UnlockError> {
+        Ok(self.signer.sign_tx(tx, script_group)?)
+    }
+
+    async fn fill_placeholder_witness_async(
+        &self,
+        tx: &TransactionView,
+        script_group: &ScriptGroup,
+        _tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<TransactionView, UnlockError> {
+        fill_witness_lock(tx, script_group, self.signer.config().placeholder_lock())
+    }
+}
+
+fn first_witness(tx: &TransactionView, script_group: &ScriptGroup) -> Result<WitnessArgs, Error> {
+    let index = *script_group
+        .input_indices
+        .first()
+        .ok_or_else(|| Error::InvalidWitness("empty script group".to_string()))?;
+    witness_args(tx, index)
+}
+
+/// Replace the lock field of the first witness in the script group.
+pub fn set_lock(
+    tx: &TransactionView,
+    script_group: &ScriptGroup,
+    lock: &MultisigLock,
+) -> Result<TransactionView, Error> {
+    set_witness_lock(tx, script_group.input_indices[0], lock)
+}
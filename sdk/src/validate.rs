@@ -0,0 +1,422 @@
+//! Pre-broadcast validation: run the scripts of a transaction under ckb-vm,
+//! with the binaries of its actual cell deps, and report the exit code and
+//! cycles of every script group before the transaction is sent.
+//!
+//! A digest mismatch, a wrong config or a since the lock args refuse shows
+//! up as the exit code of the group, see `describe_exit_code`, instead of a
+//! transaction rejected by the node.
+//!
+//! The cells and headers come from a ckb-sdk `TransactionDependencyProvider`,
+//! or from any `MockResourceLoader` such as the mock transaction files of
+//! ckb-debugger. Every hardfork is active.
+//!
+//! `Validator::report` gives the same run as a `Report`, a typed value which
+//! serializes to JSON, for custody backends to attach to a failed proposal:
+//! the cycles, debug output and failure of every group, and the phase where
+//! the transaction failed, from resolving its cells to running its scripts.
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use ckb_chain_spec::consensus::{Consensus, ConsensusBuilder};
+use ckb_jsonrpc_types as json;
+use ckb_mock_tx_types::{MockResourceLoader, MockTransaction, Resource};
+use ckb_script::{ScriptError, TransactionScriptsVerifier, TxVerifyEnv};
+use ckb_sdk::{
+    traits::TransactionDependencyProvider,
+    types::{ScriptGroup, ScriptGroupType},
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        cell::resolve_transaction,
+        hardfork::{HardForks, CKB2021, CKB2023},
+        Cycle, EpochNumberWithFraction, HeaderView, TransactionView,
+    },
+    packed::{Byte32, CellOutput, OutPoint},
+    prelude::*,
+    H256,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Cycles limit of a transaction on the mainnet.
+pub const MAX_TX_CYCLES: Cycle = 70_000_000;
+
+/// The meaning of the exit codes of the contract, `None` for the codes it
+/// doesn't return.
+pub fn describe_exit_code(code: i8) -> Option<&'static str> {
+    let description = match code {
+        1 => "index out of bound",
+        2 => "item missing",
+        3 => "length not enough",
+        4 => "invalid molecule encoding",
+        -1 => "invalid lock args length or flags",
+        -22 => "invalid witness size",
+        -23 => "incorrect since flags",
+        -24 => "since value below the lock args",
+        -25 => "invalid since flags",
+        -26 => "incorrect since relative flag",
+        -27 => "invalid since epoch",
+        -28 => "missing header deps",
+        -29 => "unexpected witness in the group",
+        -41 => "invalid reserved field",
+        -42 => "invalid pubkeys count",
+        -43 => "invalid threshold",
+        -44 => "invalid require first n",
+        -45 => "too many pubkeys",
+        -46 => "invalid config proof",
+        -51 => "the lock field doesn't match the multisig hash of the lock args",
+        -52 => "signature verification failed, e.g. signed over another digest",
+        -53 => "the fee key lowered the net position of the lock",
+        -54 => "typed cell not allowed",
+        -55 => "invalid nonce cell",
+        -56 => "fee above the limit",
+        -57 => "not every live cell of the lock is spent",
+        -58 => "the successor quorum moved the funds elsewhere",
+        -59 => "the approval is for another chain, or the genesis header dep is missing",
+        _ => return None,
+    };
+    Some(description)
+}
+
+/// The run of a script group.
+#[derive(Clone, Debug)]
+pub struct GroupValidation {
+    pub script_group: ScriptGroup,
+    /// The cycles consumed when the script passed.
+    pub cycles: Option<Cycle>,
+    /// The exit code of the script when it failed.
+    pub exit_code: Option<i8>,
+    /// Why the script failed, including failures without exit code such as
+    /// the cycles limit.
+    pub error: Option<String>,
+    /// What the script printed with `debug!`.
+    pub debug: Vec<String>,
+    /// Whether the script ran out of cycles.
+    pub exceeded_cycles: bool,
+}
+
+impl GroupValidation {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Where a transaction failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePhase {
+    /// A cell or header of the transaction is missing, or it can't be
+    /// resolved.
+    Resolve,
+    /// The VM couldn't run the script: no binary, an invalid one, an
+    /// unsupported hash type or VM version.
+    Vm,
+    /// The script ran out of cycles.
+    Cycles,
+    /// The script returned a non zero exit code.
+    Script,
+}
+
+/// A failure in a `Report`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Failure {
+    pub phase: FailurePhase,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i8>,
+    /// What the exit code means, see `describe_exit_code`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub message: String,
+}
+
+impl Failure {
+    fn of_group(group: &GroupValidation) -> Option<Failure> {
+        let message = group.error.clone()?;
+        let phase = match group.exit_code {
+            Some(_) => FailurePhase::Script,
+            None if group.exceeded_cycles => FailurePhase::Cycles,
+            None => FailurePhase::Vm,
+        };
+        Some(Failure {
+            phase,
+            exit_code: group.exit_code,
+            description: group
+                .exit_code
+                .and_then(describe_exit_code)
+                .map(String::from),
+            message,
+        })
+    }
+}
+
+/// The run of a script group in a `Report`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupReport {
+    pub group_type: ScriptGroupType,
+    pub script_hash: H256,
+    pub script: json::Script,
+    pub inputs: Vec<usize>,
+    pub outputs: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cycles: Option<Cycle>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<Failure>,
+    pub debug: Vec<String>,
+}
+
+/// The run of a transaction, as a typed value.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub tx_hash: H256,
+    /// The cycles of the transaction, when every group passed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cycles: Option<Cycle>,
+    /// The first failure, that of the transaction or of a group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<Failure>,
+    /// Lock groups first, empty when the transaction can't be resolved.
+    pub groups: Vec<GroupReport>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    fn new(tx: &TransactionView, validation: Result<Validation, Error>) -> Self {
+        let tx_hash = tx.hash().unpack();
+        let validation = match validation {
+            Ok(validation) => validation,
+            Err(err) => {
+                return Report {
+                    tx_hash,
+                    cycles: None,
+                    failure: Some(Failure {
+                        phase: FailurePhase::Resolve,
+                        exit_code: None,
+                        description: None,
+                        message: err.to_string(),
+                    }),
+                    groups: Vec::new(),
+                }
+            }
+        };
+        let groups: Vec<_> = validation
+            .groups
+            .iter()
+            .map(|group| GroupReport {
+                group_type: group.script_group.group_type,
+                script_hash: group.script_group.script.calc_script_hash().unpack(),
+                script: group.script_group.script.clone().into(),
+                inputs: group.script_group.input_indices.clone(),
+                outputs: group.script_group.output_indices.clone(),
+                cycles: group.cycles,
+                failure: Failure::of_group(group),
+                debug: group.debug.clone(),
+            })
+            .collect();
+        Report {
+            tx_hash,
+            cycles: validation.cycles(),
+            failure: groups.iter().find_map(|group| group.failure.clone()),
+            groups,
+        }
+    }
+}
+
+/// The runs of every script group of a transaction, lock groups first.
+#[derive(Clone, Debug)]
+pub struct Validation {
+    pub groups: Vec<GroupValidation>,
+}
+
+impl Validation {
+    pub fn is_ok(&self) -> bool {
+        self.groups.iter().all(GroupValidation::is_ok)
+    }
+
+    /// The cycles of the transaction, when every group passed.
+    pub fn cycles(&self) -> Option<Cycle> {
+        self.groups.iter().map(|group| group.cycles).sum()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &GroupValidation> {
+        self.groups.iter().filter(|group| !group.is_ok())
+    }
+}
+
+/// Runs the scripts of transactions, with every hardfork active.
+pub struct Validator {
+    max_cycles: Cycle,
+    consensus: Arc<Consensus>,
+    env: Arc<TxVerifyEnv>,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Validator::new()
+    }
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        let hardforks = HardForks {
+            ckb2021: CKB2021::new_dev_default(),
+            ckb2023: CKB2023::new_dev_default(),
+        };
+        let consensus = ConsensusBuilder::default()
+            .hardfork_switch(hardforks)
+            .build();
+        let header = HeaderView::new_advanced_builder()
+            .epoch(EpochNumberWithFraction::new(0, 0, 1).pack())
+            .build();
+        Validator {
+            max_cycles: MAX_TX_CYCLES,
+            consensus: Arc::new(consensus),
+            env: Arc::new(TxVerifyEnv::new_submit(&header)),
+        }
+    }
+
+    /// Cycles limit of each script group.
+    pub fn max_cycles(mut self, max_cycles: Cycle) -> Self {
+        self.max_cycles = max_cycles;
+        self
+    }
+
+    /// Run `tx` with the cells and headers of `tx_dep_provider`.
+    pub fn validate(
+        &self,
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<Validation, Error> {
+        self.validate_with(tx, &mut ProviderLoader(tx_dep_provider))
+    }
+
+    /// Run `tx` with the cells and headers of `tx_dep_provider`, into a
+    /// `Report` which holds the failures to resolve the transaction too.
+    pub fn report(
+        &self,
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Report {
+        Report::new(tx, self.validate(tx, tx_dep_provider))
+    }
+
+    /// `report` with the cells and headers of `loader`.
+    pub fn report_with<L>(&self, tx: &TransactionView, loader: &mut L) -> Report
+    where
+        L: MockResourceLoader,
+    {
+        Report::new(tx, self.validate_with(tx, loader))
+    }
+
+    /// Run `tx` with the cells and headers of `loader`.
+    pub fn validate_with<L>(
+        &self,
+        tx: &TransactionView,
+        loader: &mut L,
+    ) -> Result<Validation, Error>
+    where
+        L: MockResourceLoader,
+    {
+        let mock = MockTransaction {
+            mock_info: Default::default(),
+            tx: tx.data(),
+        };
+        let resource = Resource::from_both(&mock, loader).map_err(Error::TransactionDependency)?;
+        let rtx = resolve_transaction(tx.clone(), &mut HashSet::new(), &resource, &resource)
+            .map_err(|err| Error::TransactionDependency(format!("resolve: {}", err)))?;
+        let debug = Arc::new(Mutex::new(Vec::new()));
+        let printer = {
+            let debug = Arc::clone(&debug);
+            Arc::new(move |_: &Byte32, message: &str| {
+                debug
+                    .lock()
+                    .expect("poisoned lock")
+                    .push(message.to_string());
+            })
+        };
+        let verifier = TransactionScriptsVerifier::new_with_debug_printer(
+            Arc::new(rtx),
+            resource,
+            Arc::clone(&self.consensus),
+            Arc::clone(&self.env),
+            printer,
+        );
+        let mut groups: Vec<_> = verifier
+            .groups_with_type()
+            .map(|(group_type, hash, group)| (group_type, hash.clone(), group.clone()))
+            .collect();
+        groups.sort_by_key(|(group_type, _, _)| *group_type != ckb_script::ScriptGroupType::Lock);
+        let groups = groups
+            .into_iter()
+            .map(|(group_type, hash, group)| {
+                let result = verifier.verify_single(group_type, &hash, self.max_cycles);
+                let debug = std::mem::take(&mut *debug.lock().expect("poisoned lock"));
+                let script_group = ScriptGroup {
+                    script: group.script,
+                    group_type: match group_type {
+                        ckb_script::ScriptGroupType::Lock => ScriptGroupType::Lock,
+                        ckb_script::ScriptGroupType::Type => ScriptGroupType::Type,
+                    },
+                    input_indices: group.input_indices,
+                    output_indices: group.output_indices,
+                };
+                let exceeded_cycles = matches!(
+                    result,
+                    Err(ScriptError::ExceededMaximumCycles(_))
+                        | Err(ScriptError::CyclesOverflow(_, _))
+                );
+                let (cycles, exit_code, error) = match result {
+                    Ok(cycles) => (Some(cycles), None, None),
+                    Err(err) => {
+                        let exit_code = match err {
+                            ScriptError::ValidationFailure(_, code) => Some(code),
+                            _ => None,
+                        };
+                        (None, exit_code, Some(err.to_string()))
+                    }
+                };
+                GroupValidation {
+                    script_group,
+                    cycles,
+                    exit_code,
+                    error,
+                    debug,
+                    exceeded_cycles,
+                }
+            })
+            .collect();
+        Ok(Validation { groups })
+    }
+}
+
+/// The cells and headers of a `TransactionDependencyProvider`.
+struct ProviderLoader<'a>(&'a dyn TransactionDependencyProvider);
+
+impl MockResourceLoader for ProviderLoader<'_> {
+    fn get_header(&mut self, hash: H256) -> Result<Option<HeaderView>, String> {
+        self.0
+            .get_header(&hash.pack())
+            .map(Some)
+            .map_err(|err| err.to_string())
+    }
+
+    fn get_live_cell(
+        &mut self,
+        out_point: OutPoint,
+    ) -> Result<Option<(CellOutput, Bytes, Option<Byte32>)>, String> {
+        let output = self.0.get_cell(&out_point).map_err(|err| err.to_string())?;
+        let data = self
+            .0
+            .get_cell_data(&out_point)
+            .map_err(|err| err.to_string())?;
+        Ok(Some((output, data, None)))
+    }
+}
@@ -0,0 +1,409 @@
+//! Deposit watcher: follows the indexer of a CKB node, or a light client,
+//! see `light_client.rs`, for the transactions paying the lock of a config, and passes every new deposit to pluggable
+//! hooks, e.g. to credit exchange accounts or feed a treasury dashboard.
+//!
+//! A deposit is an output of the config lock in a transaction spending no
+//! cell of that lock: the change of the cosigners' own transactions is not a
+//! deposit. Blocks are reported once `confirmations` blocks are on top of
+//! them, so a deposit is not reported before a likely reorg is over.
+//!
+//! Delivery is at least once: when a hook fails, `poll` stops and resumes at
+//! the block of the failed deposit on the next call, reporting the deposits
+//! of that block again to every hook. Hooks should be idempotent on the out
+//! point. Persist `next_block` to resume after a restart.
+//!
+//! `poll_activity` also reports the `Spend`s of the config cells, the
+//! transactions spending them, e.g. to tell the cosigners' own transactions
+//! from unexpected ones.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use ckb_jsonrpc_types::{self as json, Either};
+use ckb_sdk::{
+    rpc::ckb_indexer::{ScriptType, SearchKey, SearchKeyFilter},
+    CkbRpcClient,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{ScriptHashType, TransactionView},
+    packed::{self, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use serde::Serialize;
+
+use crate::{
+    backend::{BoxedBackend, NodeIndexer},
+    config::MultisigConfig,
+    error::Error,
+    light_client::LightClient,
+};
+
+const PAGE_SIZE: u32 = 256;
+
+/// Blocks on top of a deposit before it is reported, by default.
+pub const DEFAULT_CONFIRMATIONS: u64 = 24;
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(8);
+
+/// An output paid to the config lock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deposit {
+    pub out_point: OutPoint,
+    pub block_number: u64,
+    pub output: CellOutput,
+    pub output_data: Bytes,
+}
+
+impl Deposit {
+    /// The outputs of `tx` guarded by `lock_script`, whatever its inputs.
+    pub fn from_tx(tx: &TransactionView, block_number: u64, lock_script: &Script) -> Vec<Self> {
+        tx.outputs_with_data_iter()
+            .enumerate()
+            .filter(|(_, (output, _))| output.lock() == *lock_script)
+            .map(|(i, (output, output_data))| Deposit {
+                out_point: OutPoint::new(tx.hash(), i as u32),
+                block_number,
+                output,
+                output_data,
+            })
+            .collect()
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.output.capacity().unpack()
+    }
+
+    /// The JSON body posted by `Webhook`.
+    pub fn to_json(&self) -> serde_json::Value {
+        let body = DepositJson {
+            out_point: self.out_point.clone().into(),
+            block_number: self.block_number.into(),
+            output: self.output.clone().into(),
+            output_data: json::JsonBytes::from_bytes(self.output_data.clone()),
+        };
+        serde_json::to_value(body).expect("serialize deposit")
+    }
+}
+
+/// A transaction spending cells of the config lock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spend {
+    pub tx: TransactionView,
+    pub block_number: u64,
+    /// The config cells spent.
+    pub inputs: usize,
+    /// Shannons sent to other locks.
+    pub outgoing: u64,
+}
+
+impl Spend {
+    pub fn from_tx(
+        tx: TransactionView,
+        block_number: u64,
+        inputs: usize,
+        lock_script: &Script,
+    ) -> Self {
+        let outgoing = tx
+            .outputs()
+            .into_iter()
+            .filter(|output| output.lock() != *lock_script)
+            .map(|output| Unpack::<u64>::unpack(&output.capacity()))
+            .sum();
+        Spend {
+            tx,
+            block_number,
+            inputs,
+            outgoing,
+        }
+    }
+
+    pub fn tx_hash(&self) -> H256 {
+        self.tx.hash().unpack()
+    }
+}
+
+/// What happened to a config in a block, see `DepositWatcher::poll_activity`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Activity {
+    Deposit(Deposit),
+    Spend(Spend),
+}
+
+impl Activity {
+    pub fn block_number(&self) -> u64 {
+        match self {
+            Activity::Deposit(deposit) => deposit.block_number,
+            Activity::Spend(spend) => spend.block_number,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DepositJson {
+    out_point: json::OutPoint,
+    block_number: json::BlockNumber,
+    output: json::CellOutput,
+    output_data: json::JsonBytes,
+}
+
+/// Called for each deposit, in chain order.
+pub trait DepositHook {
+    fn on_deposit(&self, deposit: &Deposit) -> Result<(), Error>;
+}
+
+impl<F> DepositHook for F
+where
+    F: Fn(&Deposit) -> Result<(), Error>,
+{
+    fn on_deposit(&self, deposit: &Deposit) -> Result<(), Error> {
+        self(deposit)
+    }
+}
+
+/// Posts each deposit as `Deposit::to_json` to a URL, any status but a
+/// success fails the hook.
+pub struct Webhook {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Self {
+        Webhook {
+            url: url.to_string(),
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+}
+
+impl DepositHook for Webhook {
+    fn on_deposit(&self, deposit: &Deposit) -> Result<(), Error> {
+        let webhook_err = |err: reqwest::Error| Error::Webhook(format!("{}: {}", self.url, err));
+        self.client
+            .post(&self.url)
+            .json(&deposit.to_json())
+            .send()
+            .map_err(webhook_err)?
+            .error_for_status()
+            .map_err(webhook_err)?;
+        Ok(())
+    }
+}
+
+pub type BoxedHook = Box<dyn DepositHook + Send>;
+
+/// Watches the deposits to one config.
+pub struct DepositWatcher {
+    backend: BoxedBackend,
+    lock_script: Script,
+    confirmations: u64,
+    interval: Duration,
+    next_block: u64,
+    hooks: Vec<BoxedHook>,
+}
+
+impl DepositWatcher {
+    /// `url` is the RPC of a CKB node with the indexer module enabled. The
+    /// watch starts at the genesis block, see `from_block`.
+    pub fn new(
+        url: &str,
+        config: &MultisigConfig,
+        code_hash: &H256,
+        hash_type: ScriptHashType,
+    ) -> Self {
+        Self::with_backend(
+            Box::new(NodeIndexer::new(url)),
+            config,
+            code_hash,
+            hash_type,
+        )
+    }
+
+    /// `url` is the RPC of a light client watching the lock of the config,
+    /// see `LightClient::watch`. The confirmations are counted on top of the
+    /// block the lock is synced to.
+    pub fn light_client(
+        url: &str,
+        config: &MultisigConfig,
+        code_hash: &H256,
+        hash_type: ScriptHashType,
+    ) -> Self {
+        Self::with_backend(
+            Box::new(LightClient::new(url)),
+            config,
+            code_hash,
+            hash_type,
+        )
+    }
+
+    /// Read the chain from `backend`, see `backend.rs`.
+    pub fn with_backend(
+        backend: BoxedBackend,
+        config: &MultisigConfig,
+        code_hash: &H256,
+        hash_type: ScriptHashType,
+    ) -> Self {
+        DepositWatcher {
+            backend,
+            lock_script: config.lock_script(code_hash, hash_type),
+            confirmations: DEFAULT_CONFIRMATIONS,
+            interval: DEFAULT_INTERVAL,
+            next_block: 0,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// The first block to watch, e.g. the `next_block` saved by the previous
+    /// run.
+    pub fn from_block(mut self, number: u64) -> Self {
+        self.next_block = number;
+        self
+    }
+
+    pub fn confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// The pause between two polls of `run`.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn hook<H>(mut self, hook: H) -> Self
+    where
+        H: DepositHook + Send + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn lock_script(&self) -> &Script {
+        &self.lock_script
+    }
+
+    /// The first block not reported yet.
+    pub fn next_block(&self) -> u64 {
+        self.next_block
+    }
+
+    /// Report the deposits of the blocks confirmed since the last poll, and
+    /// return them.
+    pub fn poll(&mut self) -> Result<Vec<Deposit>, Error> {
+        let activity = self.poll_blocks(false)?;
+        Ok(activity
+            .into_iter()
+            .filter_map(|activity| match activity {
+                Activity::Deposit(deposit) => Some(deposit),
+                Activity::Spend(_) => None,
+            })
+            .collect())
+    }
+
+    /// As `poll`, also returning the spends of the config cells, everything
+    /// in chain order.
+    pub fn poll_activity(&mut self) -> Result<Vec<Activity>, Error> {
+        self.poll_blocks(true)
+    }
+
+    fn poll_blocks(&mut self, spends: bool) -> Result<Vec<Activity>, Error> {
+        let tip = self.backend.indexed_block(&self.lock_script)?;
+        let end = match (tip + 1).checked_sub(self.confirmations) {
+            Some(end) if end > self.next_block => end,
+            _ => return Ok(Vec::new()),
+        };
+        let activity = self.activity(self.next_block, end, spends)?;
+        for deposit in activity.iter().filter_map(|activity| match activity {
+            Activity::Deposit(deposit) => Some(deposit),
+            Activity::Spend(_) => None,
+        }) {
+            for hook in &self.hooks {
+                if let Err(err) = hook.on_deposit(deposit) {
+                    self.next_block = deposit.block_number;
+                    return Err(err);
+                }
+            }
+        }
+        self.next_block = end;
+        Ok(activity)
+    }
+
+    /// Poll every `interval` until `stop` is set or an error occurs.
+    pub fn run(&mut self, stop: &AtomicBool) -> Result<(), Error> {
+        while !stop.load(Ordering::Relaxed) {
+            self.poll()?;
+            thread::sleep(self.interval);
+        }
+        Ok(())
+    }
+
+    /// The deposits of the blocks `start..end`, and the spends too with
+    /// `spends`, in chain order.
+    fn activity(&self, start: u64, end: u64, spends: bool) -> Result<Vec<Activity>, Error> {
+        let search_key = SearchKey {
+            script: self.lock_script.clone().into(),
+            script_type: ScriptType::Lock,
+            script_search_mode: None,
+            filter: Some(SearchKeyFilter {
+                block_range: Some([start.into(), end.into()]),
+                ..SearchKeyFilter::default()
+            }),
+            with_data: None,
+            group_by_transaction: Some(true),
+        };
+        let mut activity = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (txs, next) = self
+                .backend
+                .transactions(search_key.clone(), PAGE_SIZE, cursor)?;
+            let last = txs.len() < PAGE_SIZE as usize;
+            for tx in txs {
+                let (block_number, inputs) = (tx.block_number, tx.inputs);
+                if inputs == 0 {
+                    let view = self.backend.lock_transaction(tx)?;
+                    activity.extend(
+                        Deposit::from_tx(&view, block_number, &self.lock_script)
+                            .into_iter()
+                            .map(Activity::Deposit),
+                    );
+                } else if spends {
+                    let view = self.backend.lock_transaction(tx)?;
+                    activity.push(Activity::Spend(Spend::from_tx(
+                        view,
+                        block_number,
+                        inputs,
+                        &self.lock_script,
+                    )));
+                }
+            }
+            if last {
+                break;
+            }
+            cursor = Some(next);
+        }
+        Ok(activity)
+    }
+}
+
+/// The transaction `hash` from the node.
+pub(crate) fn fetch_transaction(
+    client: &CkbRpcClient,
+    hash: H256,
+) -> Result<TransactionView, Error> {
+    let tx = client
+        .get_transaction(hash.clone())?
+        .and_then(|tx| tx.transaction)
+        .ok_or_else(|| Error::Rpc(format!("transaction {:#x} not found", hash)))?;
+    match tx.inner {
+        Either::Left(tx) => Ok(packed::Transaction::from(tx.inner).into_view()),
+        Either::Right(bytes) => packed::Transaction::from_slice(bytes.as_bytes())
+            .map(|tx| tx.into_view())
+            .map_err(|err| Error::Rpc(format!("transaction {:#x}: {}", hash, err))),
+    }
+}
@@ -0,0 +1,263 @@
+use std::convert::TryInto;
+
+use ckb_types::{
+    bytes::Bytes,
+    core::TransactionView,
+    packed::{self, WitnessArgs},
+    prelude::*,
+};
+
+use crate::{
+    config::{multisig_script_len_of, MultisigConfig},
+    constants::{BLAKE160_SIZE, DIGEST_SIZE, SIGNATURE_SIZE},
+    error::Error,
+    signer::recover_pubkey,
+};
+
+/// The lock field of the first witness in a script group: the multisig
+/// script followed by `threshold` signature slots.
+///
+/// Slots are zero filled until signed, cosigners can fill them in any order
+/// since the signing message is computed with all of them zeroed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultisigLock {
+    config: MultisigConfig,
+    signatures: Vec<[u8; SIGNATURE_SIZE]>,
+}
+
+impl MultisigLock {
+    /// An unsigned lock field.
+    pub fn new(config: MultisigConfig) -> Self {
+        let signatures = vec![[0u8; SIGNATURE_SIZE]; usize::from(config.threshold())];
+        MultisigLock { config, signatures }
+    }
+
+    pub fn parse(lock: &[u8]) -> Result<Self, Error> {
+        let script_len = match multisig_script_len_of(lock) {
+            Some(script_len) if lock.len() >= script_len => script_len,
+            _ => return Err(Error::InvalidWitness("lock field too short".to_string())),
+        };
+        let config = MultisigConfig::from_multisig_script(&lock[..script_len])?;
+        if lock.len() != config.lock_len() {
+            return Err(Error::InvalidWitness(format!(
+                "lock field length {}, expected {}",
+                lock.len(),
+                config.lock_len()
+            )));
+        }
+        let signatures = lock[script_len..]
+            .chunks(SIGNATURE_SIZE)
+            .map(|chunk| {
+                let mut signature = [0u8; SIGNATURE_SIZE];
+                signature.copy_from_slice(chunk);
+                signature
+            })
+            .collect();
+        Ok(MultisigLock { config, signatures })
+    }
+
+    /// The config parsed from the lock field, it never carries a since value.
+    pub fn config(&self) -> &MultisigConfig {
+        &self.config
+    }
+
+    pub fn signatures(&self) -> &[[u8; SIGNATURE_SIZE]] {
+        &self.signatures
+    }
+
+    /// Signatures in the filled slots.
+    pub fn filled(&self) -> impl Iterator<Item = &[u8; SIGNATURE_SIZE]> {
+        self.signatures.iter().filter(|sig| !is_empty_slot(sig))
+    }
+
+    pub fn filled_count(&self) -> usize {
+        self.filled().count()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.filled_count() == self.signatures.len()
+    }
+
+    /// Put a signature into the first empty slot.
+    pub fn add_signature(&mut self, signature: [u8; SIGNATURE_SIZE]) -> Result<(), Error> {
+        let slot = self
+            .signatures
+            .iter_mut()
+            .find(|sig| is_empty_slot(sig))
+            .ok_or(Error::TooManySignatures)?;
+        *slot = signature;
+        Ok(())
+    }
+
+    /// Identities of the keys which produced the filled signatures.
+    pub fn signed_identities(
+        &self,
+        digest: &[u8; DIGEST_SIZE],
+    ) -> Result<Vec<[u8; BLAKE160_SIZE]>, Error> {
+        self.filled()
+            .map(|sig| Ok(self.config.identity(&recover_pubkey(digest, sig)?)))
+            .collect()
+    }
+
+    /// Check the signatures the way the contract does: every signature must
+    /// come from a distinct member, and the first `require_first_n` members
+    /// must all have signed.
+    pub fn verify(&self, digest: &[u8; DIGEST_SIZE]) -> Result<(), Error> {
+        if !self.is_complete() {
+            return Err(Error::Verification(format!(
+                "{} of {} signatures",
+                self.filled_count(),
+                self.signatures.len()
+            )));
+        }
+        let mut used = vec![false; self.config.pubkey_hashes().len()];
+        for identity in self.signed_identities(digest)? {
+            let position = self
+                .config
+                .pubkey_hashes()
+                .iter()
+                .enumerate()
+                .position(|(i, hash)| !used[i] && hash == &identity)
+                .ok_or_else(|| {
+                    Error::Verification(format!("unexpected signer 0x{}", hex::encode(identity)))
+                })?;
+            used[position] = true;
+        }
+        if let Some(missing) = used
+            .iter()
+            .take(usize::from(self.config.require_first_n()))
+            .position(|used| !used)
+        {
+            return Err(Error::Verification(format!(
+                "required key #{} didn't sign",
+                missing
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Bytes {
+        let mut lock = self.config.multisig_script().to_vec();
+        for signature in &self.signatures {
+            lock.extend_from_slice(signature);
+        }
+        lock.into()
+    }
+}
+
+/// The `WitnessArgs` of witness `index`, default when the witness is missing
+/// or empty.
+pub fn witness_args(tx: &TransactionView, index: usize) -> Result<WitnessArgs, Error> {
+    match tx.witnesses().get(index).map(|witness| witness.raw_data()) {
+        Some(data) if !data.is_empty() => WitnessArgs::from_slice(&data)
+            .map_err(|err| Error::InvalidWitness(format!("witness #{}: {}", index, err))),
+        _ => Ok(WitnessArgs::default()),
+    }
+}
+
+/// The inputs of a script group but the first whose witness isn't empty, a
+/// config `with_strict_witnesses` refuses them.
+pub fn stuffed_witnesses(tx: &TransactionView, inputs: &[usize]) -> Vec<usize> {
+    inputs
+        .iter()
+        .skip(1)
+        .copied()
+        .filter(|index| {
+            tx.witnesses()
+                .get(*index)
+                .is_some_and(|witness| !witness.raw_data().is_empty())
+        })
+        .collect()
+}
+
+/// An empty witness, or a `WitnessArgs` without any field, which older
+/// wallets leave at the inputs of a group they sign in another pass.
+pub fn is_placeholder(witness: &[u8]) -> bool {
+    witness.is_empty() || witness == WitnessArgs::default().as_slice()
+}
+
+/// The input of the group holding the lock field under a config
+/// `with_legacy_witnesses`: the first of `inputs` whose witness isn't a
+/// placeholder, a missing witness being empty. Mirrors
+/// `ckb-multisig-core/src/legacy.rs`.
+pub fn legacy_lock_index(tx: &TransactionView, inputs: &[usize]) -> Option<usize> {
+    inputs.iter().copied().find(|index| {
+        tx.witnesses()
+            .get(*index)
+            .is_some_and(|witness| !is_placeholder(&witness.raw_data()))
+    })
+}
+
+/// `stuffed_witnesses` under a config `with_legacy_witnesses`: the inputs of
+/// the group but that of the lock field whose witness isn't a placeholder.
+pub fn legacy_stuffed_witnesses(tx: &TransactionView, inputs: &[usize]) -> Vec<usize> {
+    let lock_index = legacy_lock_index(tx, inputs);
+    inputs
+        .iter()
+        .copied()
+        .filter(|index| Some(*index) != lock_index)
+        .filter(|index| {
+            tx.witnesses()
+                .get(*index)
+                .is_some_and(|witness| !is_placeholder(&witness.raw_data()))
+        })
+        .collect()
+}
+
+/// Declare in the input type field of witness `index`, the first of the
+/// group, that the transaction spends the `total` live cells of a config
+/// `with_spend_all`. Set it before signing, the signatures cover it.
+pub fn set_spend_all_total(
+    tx: &TransactionView,
+    index: usize,
+    total: u32,
+) -> Result<TransactionView, Error> {
+    let witness = witness_args(tx, index)?
+        .as_builder()
+        .input_type(Some(Bytes::copy_from_slice(&total.to_le_bytes())).pack())
+        .build();
+    let mut witnesses: Vec<packed::Bytes> = tx.witnesses().into_iter().collect();
+    while witnesses.len() <= index {
+        witnesses.push(Default::default());
+    }
+    witnesses[index] = witness.as_bytes().pack();
+    Ok(tx.as_advanced_builder().set_witnesses(witnesses).build())
+}
+
+/// The count of live cells declared by `set_spend_all_total`, if any.
+pub fn spend_all_total(tx: &TransactionView, index: usize) -> Result<Option<u32>, Error> {
+    let field = match witness_args(tx, index)?.input_type().to_opt() {
+        Some(field) => field.raw_data(),
+        None => return Ok(None),
+    };
+    let total: [u8; 4] = field[..].try_into().map_err(|_| {
+        Error::InvalidWitness(format!(
+            "witness #{} declares a total of {} bytes, expected 4",
+            index,
+            field.len()
+        ))
+    })?;
+    Ok(Some(u32::from_le_bytes(total)))
+}
+
+/// Replace the lock field of witness `index`, the other fields are kept.
+pub fn set_witness_lock(
+    tx: &TransactionView,
+    index: usize,
+    lock: &MultisigLock,
+) -> Result<TransactionView, Error> {
+    let witness = witness_args(tx, index)?
+        .as_builder()
+        .lock(Some(lock.to_bytes()).pack())
+        .build();
+    let mut witnesses: Vec<packed::Bytes> = tx.witnesses().into_iter().collect();
+    while witnesses.len() <= index {
+        witnesses.push(Default::default());
+    }
+    witnesses[index] = witness.as_bytes().pack();
+    Ok(tx.as_advanced_builder().set_witnesses(witnesses).build())
+}
+
+fn is_empty_slot(signature: &[u8; SIGNATURE_SIZE]) -> bool {
+    signature.iter().all(|b| *b == 0)
+}
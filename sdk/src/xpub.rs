@@ -0,0 +1,82 @@
+//! Configs derived from the extended public keys of the cosigners: a fresh
+//! multisig address per deposit or per cell, as exchanges hand out, without
+//! any cosigner exposing private material during the setup.
+//!
+//! Every cosigner hands out the `xpub` of an account, its key at
+//! `hd::ckb_account_path`. The config at `index` lists the keys `0/index`
+//! under each of them, in the order of the xpubs, and its cosigners sign with
+//! their key at `hd::ckb_path(account, index)`.
+
+use std::ops::Range;
+
+use crate::{
+    config::MultisigConfig, constants::BLAKE160_SIZE, error::Error, hd::ExtendedPubKey,
+    ledger::DerivationPath,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XpubConfig {
+    xpubs: Vec<ExtendedPubKey>,
+    require_first_n: u8,
+    threshold: u8,
+}
+
+impl XpubConfig {
+    /// Checked as `MultisigConfig::new`, the first `require_first_n` xpubs
+    /// must sign.
+    pub fn new(
+        xpubs: Vec<ExtendedPubKey>,
+        require_first_n: u8,
+        threshold: u8,
+    ) -> Result<Self, Error> {
+        if let Some(i) = (1..xpubs.len()).find(|i| xpubs[..*i].contains(&xpubs[*i])) {
+            return Err(Error::InvalidConfig(format!("xpub #{} is duplicated", i)));
+        }
+        let config = XpubConfig {
+            xpubs,
+            require_first_n,
+            threshold,
+        };
+        config.config(0)?;
+        Ok(config)
+    }
+
+    pub fn xpubs(&self) -> &[ExtendedPubKey] {
+        &self.xpubs
+    }
+
+    pub fn require_first_n(&self) -> u8 {
+        self.require_first_n
+    }
+
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// The config at `index`, to be completed with the options of the lock
+    /// args, e.g. `MultisigConfig::with_since`.
+    pub fn config(&self, index: u32) -> Result<MultisigConfig, Error> {
+        let path = DerivationPath::new(vec![0, index]);
+        let hashes = self
+            .xpubs
+            .iter()
+            .map(|xpub| Ok(xpub.derive(&path)?.identity()))
+            .collect::<Result<Vec<_>, Error>>()?;
+        MultisigConfig::new(hashes, self.require_first_n, self.threshold)
+    }
+
+    /// The index among `indices` of the config whose multisig hash starts
+    /// `lock_args`, e.g. to credit a deposit to its account.
+    pub fn index_of(&self, lock_args: &[u8], indices: Range<u32>) -> Result<Option<u32>, Error> {
+        let hash = match lock_args.get(..BLAKE160_SIZE) {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+        for index in indices {
+            if self.config(index)?.hash160() == hash {
+                return Ok(Some(index));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,234 @@
+//! The HTTP endpoints, JSON in and out:
+//!
+//! ```text
+//! POST /proposals                      signing request      -> summary
+//! GET  /proposals?cosigner=<identity>                       -> [summary]
+//! GET  /proposals/<id>                                      -> signing request
+//! POST /proposals/<id>/signatures      {"signatures": [..]} -> summary
+//! GET  /proposals/<id>/transaction                          -> transaction
+//! GET  /proposals/<id>/session?cosigner=<identity>          -> WebSocket
+//! GET  /audit                                               -> audit log
+//! GET  /metrics                                             -> Prometheus metrics
+//! ```
+//!
+//! `<id>` is the transaction hash and `<identity>` the blake160 of a cosigner
+//! public key, both hex. The transaction is only served once completely
+//! signed, in the node RPC format ready for `send_transaction`. Errors are
+//! `{"error": "..."}` with a 4xx status. The audit log is the JSON lines of
+//! every signature accepted, see `audit.rs` of the SDK to re-verify it. See
+//! `session.rs` for the messages of the live sessions, `metrics.rs` for the
+//! metrics and `auth.rs` for the clients allowed when authentication is on.
+
+use std::{convert::TryInto, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{ws::WebSocketUpgrade, FromRef, Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use ckb_jsonrpc_types as json;
+use ckb_multisig_sdk::{
+    constants::{BLAKE160_SIZE, SIGNATURE_SIZE},
+    request::SigningRequest,
+};
+use ckb_types::H256;
+use serde::Deserialize;
+
+use crate::{
+    auth::{authorize, forbidden, Client},
+    session::{self, Hub},
+    store::{Error, Store, Summary},
+};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub store: Arc<Store>,
+    pub hub: Arc<Hub>,
+}
+
+impl FromRef<AppState> for Arc<Store> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.store)
+    }
+}
+
+pub fn router(store: Arc<Store>, hub: Arc<Hub>) -> Router {
+    Router::new()
+        .route("/proposals", post(create).get(pending))
+        .route("/proposals/{id}", get(proposal))
+        .route("/proposals/{id}/signatures", post(add_signatures))
+        .route("/proposals/{id}/transaction", get(transaction))
+        .route("/proposals/{id}/session", get(join_session))
+        .route("/audit", get(audit_log))
+        .route("/metrics", get(metrics))
+        .with_state(AppState { store, hub })
+}
+
+pub struct ApiError(pub StatusCode, pub String);
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        let status = match err {
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::Invalid(_) => StatusCode::BAD_REQUEST,
+            Error::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, err.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+fn bad_request(message: String) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, message)
+}
+
+#[derive(Deserialize)]
+struct CosignerQuery {
+    cosigner: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Signatures {
+    signatures: Vec<String>,
+}
+
+async fn create(
+    State(state): State<AppState>,
+    body: String,
+) -> Result<(StatusCode, Json<Summary>), ApiError> {
+    let request = SigningRequest::from_json(&body).map_err(Error::from)?;
+    let summary = state.hub.create(&state.store, request)?;
+    Ok((StatusCode::CREATED, Json(summary)))
+}
+
+async fn pending(
+    State(store): State<Arc<Store>>,
+    Query(query): Query<CosignerQuery>,
+) -> Result<Json<Vec<Summary>>, ApiError> {
+    let cosigner = query
+        .cosigner
+        .map(|cosigner| parse_hex::<BLAKE160_SIZE>(&cosigner, "cosigner identity"))
+        .transpose()?;
+    Ok(Json(store.pending(cosigner.as_ref())?))
+}
+
+async fn proposal(
+    State(store): State<Arc<Store>>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let request = store.get(&parse_id(&id)?)?;
+    let json = request.to_json().map_err(Error::from)?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], json).into_response())
+}
+
+async fn add_signatures(
+    State(state): State<AppState>,
+    client: Option<Extension<Client>>,
+    Path(id): Path<String>,
+    Json(body): Json<Signatures>,
+) -> Result<Json<Summary>, ApiError> {
+    let id = parse_id(&id)?;
+    authorize(client.as_deref(), &state.store.get(&id)?.config)?;
+    let signatures = parse_signatures(&body.signatures)?;
+    let summary = state
+        .hub
+        .add_signatures(&state.store, &id, &signatures)
+        .await?;
+    Ok(Json(summary))
+}
+
+async fn transaction(
+    State(store): State<Arc<Store>>,
+    Path(id): Path<String>,
+) -> Result<Json<json::Transaction>, ApiError> {
+    let id = parse_id(&id)?;
+    let request = store.get(&id)?;
+    if !request.is_complete().map_err(Error::from)? {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            format!("proposal {:#x} is not completely signed", id),
+        ));
+    }
+    Ok(Json(request.tx.data().into()))
+}
+
+async fn audit_log(State(store): State<Arc<Store>>) -> Result<Response, ApiError> {
+    let log = store.audit_log()?;
+    Ok(([(header::CONTENT_TYPE, "application/jsonl")], log).into_response())
+}
+
+async fn metrics(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let metrics = state.hub.metrics().render(&state.store)?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics,
+    )
+        .into_response())
+}
+
+async fn join_session(
+    State(state): State<AppState>,
+    client: Option<Extension<Client>>,
+    Path(id): Path<String>,
+    Query(query): Query<CosignerQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let id = parse_id(&id)?;
+    let request = state.store.get(&id)?;
+    let cosigner = match query.cosigner {
+        Some(cosigner) => {
+            let identity = parse_hex::<BLAKE160_SIZE>(&cosigner, "cosigner identity")?;
+            if request.config.position(&identity).is_none() {
+                return Err(bad_request(format!(
+                    "{} is not a cosigner of proposal {:#x}",
+                    cosigner, id
+                )));
+            }
+            if let Some(Extension(client)) = &client {
+                if client.cosigner != Some(identity) {
+                    return Err(forbidden(format!(
+                        "client {} can't join as {}",
+                        client.name, cosigner
+                    )));
+                }
+            }
+            Some(format!("0x{}", hex::encode(identity)))
+        }
+        None => None,
+    };
+    let client = client.map(|Extension(client)| client);
+    Ok(ws.on_upgrade(move |socket| session::run(socket, state, id, cosigner, client)))
+}
+
+pub fn parse_signatures(signatures: &[String]) -> Result<Vec<[u8; SIGNATURE_SIZE]>, ApiError> {
+    signatures
+        .iter()
+        .map(|signature| parse_hex::<SIGNATURE_SIZE>(signature, "signature"))
+        .collect()
+}
+
+fn parse_id(id: &str) -> Result<H256, ApiError> {
+    H256::from_str(id.trim_start_matches("0x"))
+        .map_err(|err| bad_request(format!("invalid proposal id `{}`: {}", id, err)))
+}
+
+fn parse_hex<const N: usize>(s: &str, what: &str) -> Result<[u8; N], ApiError> {
+    hex::decode(s.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            bad_request(format!(
+                "invalid {} `{}`, expected {} bytes hex",
+                what, s, N
+            ))
+        })
+}
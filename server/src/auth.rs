@@ -0,0 +1,269 @@
+//! Authentication, authorization and rate limiting, for a server on the
+//! public internet, enabled by the TOML file given with `--auth`:
+//!
+//! ```toml
+//! # optional, requests per minute of each client, in bursts of as many
+//! rate_limit = 60
+//!
+//! [[client]]
+//! name = "alice"
+//! # optional, the blake160 of the cosigner key of the client, hex
+//! cosigner = "0x..."
+//! # the SHA-256 of its API key, hex
+//! api_key_sha256 = "..."
+//! # and/or the SHA-256 of its TLS client certificate, DER, hex
+//! certificate_sha256 = "..."
+//! ```
+//!
+//! A client sends its API key as `authorization: Bearer <key>`, or presents
+//! its certificate when the server requires them, see `tls.rs`. Every request
+//! must come from a client (401 otherwise), and stay within the rate limit
+//! of the client (429 with a `retry-after` otherwise).
+//!
+//! A client with a cosigner identity may only sign the proposals of the
+//! configs it is a member of, and only join their live sessions as itself
+//! (403 otherwise). A client without one may propose and watch, not sign.
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use ckb_multisig_sdk::{constants::BLAKE160_SIZE, MultisigConfig};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::api::ApiError;
+
+pub const DEFAULT_RATE_LIMIT: u32 = 60;
+
+/// The SHA-256 of the TLS client certificate of a connection, put in the
+/// extensions of its requests by `tls.rs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PeerCertificate(pub [u8; 32]);
+
+impl PeerCertificate {
+    pub fn new(der: &[u8]) -> Self {
+        PeerCertificate(Sha256::digest(der).into())
+    }
+}
+
+/// The client a request comes from, in its extensions once authenticated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Client {
+    pub name: String,
+    pub cosigner: Option<[u8; BLAKE160_SIZE]>,
+}
+
+impl Client {
+    /// Whether the client may sign the proposals of `config`.
+    pub fn authorize(&self, config: &MultisigConfig) -> Result<(), ApiError> {
+        match &self.cosigner {
+            Some(identity) if config.position(identity).is_some() => Ok(()),
+            Some(identity) => Err(forbidden(format!(
+                "client {} (0x{}) is not a cosigner of the config",
+                self.name,
+                hex::encode(identity)
+            ))),
+            None => Err(forbidden(format!("client {} is not a cosigner", self.name))),
+        }
+    }
+}
+
+/// Whether the request may sign the proposals of `config`, always when
+/// authentication is off.
+pub fn authorize(client: Option<&Client>, config: &MultisigConfig) -> Result<(), ApiError> {
+    client.map_or(Ok(()), |client| client.authorize(config))
+}
+
+pub fn forbidden(message: String) -> ApiError {
+    ApiError(StatusCode::FORBIDDEN, message)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AuthFile {
+    rate_limit: Option<u32>,
+    #[serde(default, rename = "client")]
+    clients: Vec<ClientEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientEntry {
+    name: String,
+    cosigner: Option<String>,
+    api_key_sha256: Option<String>,
+    certificate_sha256: Option<String>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct Auth {
+    clients: Vec<Client>,
+    keys: HashMap<[u8; 32], usize>,
+    certificates: HashMap<[u8; 32], usize>,
+    rate_limit: u32,
+    buckets: Mutex<HashMap<usize, Bucket>>,
+}
+
+impl Auth {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        Self::from_toml(&content).with_context(|| format!("parse {}", path.display()))
+    }
+
+    pub fn from_toml(content: &str) -> anyhow::Result<Self> {
+        let file: AuthFile = toml::from_str(content)?;
+        let rate_limit = file.rate_limit.unwrap_or(DEFAULT_RATE_LIMIT);
+        if rate_limit == 0 {
+            bail!("rate_limit must be positive");
+        }
+        let mut auth = Auth {
+            clients: Vec::new(),
+            keys: HashMap::new(),
+            certificates: HashMap::new(),
+            rate_limit,
+            buckets: Mutex::new(HashMap::new()),
+        };
+        let mut names = HashSet::new();
+        for entry in file.clients {
+            if !names.insert(entry.name.clone()) {
+                bail!("client {} is listed twice", entry.name);
+            }
+            if entry.api_key_sha256.is_none() && entry.certificate_sha256.is_none() {
+                bail!(
+                    "client {} has neither an api_key_sha256 nor a certificate_sha256",
+                    entry.name
+                );
+            }
+            let index = auth.clients.len();
+            if let Some(hash) = &entry.api_key_sha256 {
+                let hash = parse_hex::<32>(hash, "api_key_sha256", &entry.name)?;
+                if auth.keys.insert(hash, index).is_some() {
+                    bail!("the API key of client {} is not unique", entry.name);
+                }
+            }
+            if let Some(hash) = &entry.certificate_sha256 {
+                let hash = parse_hex::<32>(hash, "certificate_sha256", &entry.name)?;
+                if auth.certificates.insert(hash, index).is_some() {
+                    bail!("the certificate of client {} is not unique", entry.name);
+                }
+            }
+            let cosigner = entry
+                .cosigner
+                .as_deref()
+                .map(|cosigner| parse_hex::<BLAKE160_SIZE>(cosigner, "cosigner", &entry.name))
+                .transpose()?;
+            auth.clients.push(Client {
+                name: entry.name,
+                cosigner,
+            });
+        }
+        Ok(auth)
+    }
+
+    /// The client of the certificate, else of the API key.
+    fn client(
+        &self,
+        certificate: Option<&PeerCertificate>,
+        api_key: Option<&str>,
+    ) -> Option<usize> {
+        certificate
+            .and_then(|certificate| self.certificates.get(&certificate.0))
+            .or_else(|| {
+                let hash: [u8; 32] = Sha256::digest(api_key?.as_bytes()).into();
+                self.keys.get(&hash)
+            })
+            .copied()
+    }
+
+    /// Take a request from the bucket of the client, or tell how long
+    /// until the next one is allowed.
+    fn take(&self, client: usize) -> Result<(), Duration> {
+        let per_second = f64::from(self.rate_limit) / 60.0;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("poisoned lock");
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: f64::from(self.rate_limit),
+            updated: now,
+        });
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * per_second;
+        bucket.tokens = (bucket.tokens + refill).min(f64::from(self.rate_limit));
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Require every request of `router` to come from a client of `auth`.
+pub fn layer(router: Router, auth: Arc<Auth>) -> Router {
+    router.layer(middleware::from_fn_with_state(auth, authenticate))
+}
+
+async fn authenticate(State(auth): State<Arc<Auth>>, mut request: Request, next: Next) -> Response {
+    let api_key = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let certificate = request.extensions().get::<PeerCertificate>();
+    let client = match auth.client(certificate, api_key) {
+        Some(client) => client,
+        None => {
+            return ApiError(
+                StatusCode::UNAUTHORIZED,
+                "unknown client, send an API key or a client certificate".to_string(),
+            )
+            .into_response()
+        }
+    };
+    if let Err(wait) = auth.take(client) {
+        let retry_after = wait.as_secs() + 1;
+        let mut response = ApiError(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("rate limit exceeded, retry in {} seconds", retry_after),
+        )
+        .into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, retry_after.into());
+        return response;
+    }
+    request
+        .extensions_mut()
+        .insert(auth.clients[client].clone());
+    next.run(request).await
+}
+
+fn parse_hex<const N: usize>(s: &str, what: &str, client: &str) -> anyhow::Result<[u8; N]> {
+    let bytes = hex::decode(s.trim_start_matches("0x")).ok();
+    match bytes.and_then(|bytes| bytes.try_into().ok()) {
+        Some(bytes) => Ok(bytes),
+        None => bail!(
+            "invalid {} of client {}, expected {} bytes hex",
+            what,
+            client,
+            N
+        ),
+    }
+}
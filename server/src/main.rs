@@ -0,0 +1,114 @@
+//! Signature coordination service of the ckb-multisig lock.
+//!
+//! A proposer uploads a signing request, the cosigners list the proposals
+//! waiting for them, sign offline and post their signatures, and anyone
+//! downloads the transaction once the threshold is reached. Cosigners can
+//! also meet in a live session, which sends the transaction to the node
+//! given with `--rpc` as soon as it is complete.
+//!
+//! See `api.rs` for the endpoints, `session.rs` for the live sessions,
+//! `notify.rs` for the webhooks, `metrics.rs` for the Prometheus metrics,
+//! `auth.rs` for the authentication of the clients, `tls.rs` for TLS,
+//! `store.rs` for the proposals and `storage.rs` for where they persist.
+
+mod api;
+mod auth;
+mod metrics;
+mod notify;
+mod session;
+mod storage;
+mod store;
+#[cfg(test)]
+mod tests;
+mod tls;
+
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(
+    name = "ckb-multisig-server",
+    version,
+    about = "Collect the signatures of ckb-multisig proposals"
+)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8120")]
+    listen: SocketAddr,
+
+    /// Directory the proposals are kept in
+    #[arg(
+        long,
+        required_unless_present = "database",
+        conflicts_with = "database"
+    )]
+    dir: Option<PathBuf>,
+
+    /// Database the proposals are kept in instead, `sqlite:<path>` or
+    /// `postgres://...`, with the sqlite or postgresql feature
+    #[arg(long)]
+    database: Option<String>,
+
+    /// RPC of a CKB node the completed transactions are sent to
+    #[arg(long)]
+    rpc: Option<String>,
+
+    /// TOML file of the webhooks notified of the proposals, see `notify.rs`
+    #[arg(long)]
+    webhooks: Option<PathBuf>,
+
+    /// TOML file of the clients allowed and their rate limit, see `auth.rs`
+    #[arg(long)]
+    auth: Option<PathBuf>,
+
+    /// PEM certificate chain to serve over TLS
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the TLS certificate
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA certificates the TLS clients must present a certificate of
+    #[arg(long, requires = "tls_cert")]
+    client_ca: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let store = match (&args.dir, &args.database) {
+        (Some(dir), _) => store::Store::open(dir)
+            .with_context(|| format!("open proposals in {}", dir.display()))?,
+        // the url may hold a password
+        (None, Some(url)) => storage::connect(url)
+            .and_then(store::Store::new)
+            .context("open proposals in the database")?,
+        (None, None) => unreachable!("--dir or --database"),
+    };
+    let listener = tokio::net::TcpListener::bind(args.listen)
+        .await
+        .with_context(|| format!("listen on {}", args.listen))?;
+    let broadcaster = args
+        .rpc
+        .as_deref()
+        .map(|url| Arc::new(session::RpcBroadcaster::new(url)) as Arc<dyn session::Broadcaster>);
+    let mut hub = session::Hub::new(broadcaster);
+    if let Some(path) = &args.webhooks {
+        hub = hub.with_notifier(notify::Notifier::new(notify::load(path)?));
+    }
+    let mut app = api::router(Arc::new(store), Arc::new(hub));
+    if let Some(path) = &args.auth {
+        app = auth::layer(app, Arc::new(auth::Auth::load(path)?));
+    }
+    match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => {
+            let config = tls::config(cert, key, args.client_ca.as_deref())?;
+            tls::serve(listener, config, app).await?;
+        }
+        _ => axum::serve(listener, app).await?,
+    }
+    Ok(())
+}
@@ -0,0 +1,247 @@
+//! Webhook notifications of the life of the proposals, for chat and ops
+//! systems which shouldn't poll:
+//!
+//! ```toml
+//! [[webhook]]
+//! url = "https://hooks.slack.com/services/..."
+//! # json (default), slack or discord
+//! format = "slack"
+//! # optional, every event when missing
+//! events = ["threshold_reached", "broadcast", "confirmed"]
+//! ```
+//!
+//! The events are `proposal_created`, `signature_added`, `threshold_reached`,
+//! `broadcast` and `confirmed`, the last once the node committed the sent
+//! transaction. A `json` webhook gets the `Notification` as is, a `slack` one
+//! `{"text": ..}` and a `discord` one `{"content": ..}`, the text being
+//! `Notification::text`.
+//!
+//! Notifications are posted in the background, tried `ATTEMPTS` times with a
+//! doubling pause, and dropped with a log line when every attempt failed: a
+//! webhook never holds the signing up.
+
+use std::{fs, path::Path, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use ckb_types::H256;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{session::Broadcaster, store::Summary};
+
+pub const ATTEMPTS: u32 = 3;
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+
+/// How often the node is asked whether a sent transaction is committed.
+pub const DEFAULT_CONFIRM_INTERVAL: Duration = Duration::from_secs(10);
+/// Give up waiting for the commit after this many checks.
+const CONFIRM_CHECKS: u32 = 360;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    ProposalCreated,
+    SignatureAdded,
+    ThresholdReached,
+    Broadcast,
+    Confirmed,
+}
+
+/// What the `json` webhooks get.
+#[derive(Clone, Debug, Serialize)]
+pub struct Notification {
+    pub event: EventKind,
+    pub summary: Summary,
+    /// The hash the node returned, with `broadcast` and `confirmed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<H256>,
+    /// The block committing the transaction, with `confirmed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+}
+
+impl Notification {
+    pub fn new(event: EventKind, summary: Summary) -> Self {
+        Notification {
+            event,
+            summary,
+            tx_hash: None,
+            block_number: None,
+        }
+    }
+
+    /// One line for the chats.
+    pub fn text(&self) -> String {
+        let summary = &self.summary;
+        let id = &summary.id;
+        match self.event {
+            EventKind::ProposalCreated => format!(
+                "New proposal {:#x} for {}, {} signatures required",
+                id, summary.lock_args, summary.threshold
+            ),
+            EventKind::SignatureAdded => format!(
+                "Proposal {:#x}: {} of {} signatures",
+                id,
+                summary.signed.len(),
+                summary.threshold
+            ),
+            EventKind::ThresholdReached => {
+                format!("Proposal {:#x} is completely signed", id)
+            }
+            EventKind::Broadcast => format!(
+                "Proposal {:#x} sent to the node as {:#x}",
+                id,
+                self.tx_hash.as_ref().unwrap_or(id)
+            ),
+            EventKind::Confirmed => format!(
+                "Proposal {:#x} committed in block {}",
+                id,
+                self.block_number.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    #[default]
+    Json,
+    Slack,
+    Discord,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub format: Format,
+    /// Every event when empty.
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+}
+
+impl Webhook {
+    pub fn wants(&self, event: EventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    pub fn body(&self, notification: &Notification) -> Value {
+        match self.format {
+            Format::Json => serde_json::to_value(notification).expect("serializable notification"),
+            Format::Slack => json!({ "text": notification.text() }),
+            Format::Discord => json!({ "content": notification.text() }),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WebhooksFile {
+    #[serde(default, rename = "webhook")]
+    webhooks: Vec<Webhook>,
+}
+
+/// The webhooks of a TOML file, see the module documentation.
+pub fn load(path: &Path) -> anyhow::Result<Vec<Webhook>> {
+    let content = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let file: WebhooksFile =
+        toml::from_str(&content).with_context(|| format!("parse {}", path.display()))?;
+    Ok(file.webhooks)
+}
+
+/// Posts the notifications to the webhooks.
+#[derive(Clone)]
+pub struct Notifier {
+    webhooks: Vec<Arc<Webhook>>,
+    client: reqwest::Client,
+    confirm_interval: Duration,
+}
+
+impl Notifier {
+    pub fn new(webhooks: Vec<Webhook>) -> Self {
+        Notifier {
+            webhooks: webhooks.into_iter().map(Arc::new).collect(),
+            client: reqwest::Client::new(),
+            confirm_interval: DEFAULT_CONFIRM_INTERVAL,
+        }
+    }
+
+    /// The pause between two checks of the commit of a sent transaction.
+    pub fn confirm_interval(mut self, interval: Duration) -> Self {
+        self.confirm_interval = interval;
+        self
+    }
+
+    pub fn wants(&self, event: EventKind) -> bool {
+        self.webhooks.iter().any(|webhook| webhook.wants(event))
+    }
+
+    /// Post in the background to every webhook wanting the event.
+    pub fn notify(&self, notification: Notification) {
+        for webhook in &self.webhooks {
+            if !webhook.wants(notification.event) {
+                continue;
+            }
+            let webhook = Arc::clone(webhook);
+            let client = self.client.clone();
+            let body = webhook.body(&notification);
+            tokio::spawn(async move {
+                let mut pause = FIRST_RETRY;
+                for attempt in 1..=ATTEMPTS {
+                    match post(&client, &webhook.url, &body).await {
+                        Ok(()) => return,
+                        Err(err) if attempt == ATTEMPTS => {
+                            eprintln!("webhook {} dropped a notification: {}", webhook.url, err)
+                        }
+                        Err(_) => {
+                            tokio::time::sleep(pause).await;
+                            pause *= 2;
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    /// Notify `confirmed` in the background once the node committed the
+    /// transaction, when a webhook wants it.
+    pub fn watch_commit(&self, broadcaster: Arc<dyn Broadcaster>, summary: Summary, tx_hash: H256) {
+        if !self.wants(EventKind::Confirmed) {
+            return;
+        }
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            for _ in 0..CONFIRM_CHECKS {
+                tokio::time::sleep(notifier.confirm_interval).await;
+                let broadcaster = Arc::clone(&broadcaster);
+                let hash = tx_hash.clone();
+                let committed =
+                    tokio::task::spawn_blocking(move || broadcaster.committed(&hash)).await;
+                if let Ok(Ok(Some(block_number))) = committed {
+                    notifier.notify(Notification {
+                        tx_hash: Some(tx_hash),
+                        block_number: Some(block_number),
+                        ..Notification::new(EventKind::Confirmed, summary)
+                    });
+                    return;
+                }
+            }
+            eprintln!(
+                "transaction {:#x} not committed, no confirmed notification",
+                tx_hash
+            );
+        });
+    }
+}
+
+async fn post(client: &reqwest::Client, url: &str, body: &Value) -> Result<(), reqwest::Error> {
+    client
+        .post(url)
+        .json(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,415 @@
+//! Live signing sessions over WebSocket:
+//!
+//! ```text
+//! GET /proposals/<id>/session?cosigner=<identity>   upgrade to WebSocket
+//! ```
+//!
+//! Every connection to the session of a proposal is told about the others,
+//! and gets the signatures as soon as they are added, through the socket or
+//! the HTTP endpoints. Messages are JSON objects tagged by `type`.
+//!
+//! From the server:
+//!
+//! ```text
+//! {"type": "proposal", "summary": {..}, "request": {..}, "present": [..]}   on join
+//! {"type": "joined", "cosigner": ".."}
+//! {"type": "left", "cosigner": ".."}
+//! {"type": "signed", "summary": {..}}
+//! {"type": "complete", "transaction": {..}}
+//! {"type": "broadcast", "tx_hash": ".."}
+//! {"type": "broadcast_failed", "error": ".."}
+//! {"type": "error", "error": ".."}                                          to the sender only
+//! ```
+//!
+//! From the cosigners:
+//!
+//! ```text
+//! {"type": "sign", "signatures": [..]}
+//! ```
+//!
+//! The session finalizes itself: the signature reaching the threshold
+//! publishes the complete transaction, and sends it to the node when the
+//! server has a `Broadcaster`. `cosigner` is optional, connections without
+//! it watch the session. With authentication on, only a cosigner may join as
+//! itself and sign, see `auth.rs`. The same steps go to the webhooks of the
+//! `Notifier`, see `notify.rs`.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::extract::ws::{Message, Utf8Bytes, WebSocket};
+use ckb_jsonrpc_types as json;
+use ckb_multisig_sdk::{constants::SIGNATURE_SIZE, request::SigningRequest};
+use ckb_sdk::CkbRpcClient;
+use ckb_types::{prelude::*, H256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::{
+    api::{parse_signatures, ApiError, AppState},
+    auth::Client,
+    metrics::Metrics,
+    notify::{EventKind, Notification, Notifier},
+    store::{Error, Store, Summary},
+};
+
+/// Events buffered for a slow connection before it misses some.
+const CHANNEL_SIZE: usize = 64;
+
+/// Sends the completed transactions to the chain.
+pub trait Broadcaster: Send + Sync {
+    /// The hash of the sent transaction, called on a blocking thread.
+    fn send(&self, request: &SigningRequest) -> Result<H256, String>;
+
+    /// The block committing a sent transaction, `None` while it isn't,
+    /// called on a blocking thread.
+    fn committed(&self, tx_hash: &H256) -> Result<Option<u64>, String>;
+}
+
+/// Broadcast through the `send_transaction` RPC of a node.
+pub struct RpcBroadcaster {
+    url: String,
+}
+
+impl RpcBroadcaster {
+    pub fn new(url: &str) -> Self {
+        RpcBroadcaster {
+            url: url.to_string(),
+        }
+    }
+}
+
+impl Broadcaster for RpcBroadcaster {
+    fn send(&self, request: &SigningRequest) -> Result<H256, String> {
+        request
+            .send(&CkbRpcClient::new(&self.url))
+            .map_err(|err| err.to_string())
+    }
+
+    fn committed(&self, tx_hash: &H256) -> Result<Option<u64>, String> {
+        let tx = CkbRpcClient::new(&self.url)
+            .get_transaction_status(tx_hash.clone())
+            .map_err(|err| err.to_string())?;
+        let status = tx.tx_status;
+        if status.status != json::Status::Committed {
+            return Ok(None);
+        }
+        Ok(status.block_number.map(|number| number.value()))
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Proposal {
+        summary: Summary,
+        /// In the signing request format of the SDK.
+        request: Value,
+        /// Identities of the connected cosigners.
+        present: Vec<String>,
+    },
+    Joined {
+        cosigner: String,
+    },
+    Left {
+        cosigner: String,
+    },
+    Signed {
+        summary: Summary,
+    },
+    Complete {
+        transaction: json::Transaction,
+    },
+    Broadcast {
+        tx_hash: H256,
+    },
+    BroadcastFailed {
+        error: String,
+    },
+    Error {
+        error: String,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum Command {
+    Sign { signatures: Vec<String> },
+}
+
+struct Session {
+    sender: broadcast::Sender<Event>,
+    connections: usize,
+    present: Vec<String>,
+}
+
+/// The open sessions, one per proposal with connections.
+pub struct Hub {
+    sessions: Mutex<HashMap<H256, Session>>,
+    broadcaster: Option<Arc<dyn Broadcaster>>,
+    notifier: Option<Notifier>,
+    metrics: Metrics,
+}
+
+impl Hub {
+    pub fn new(broadcaster: Option<Arc<dyn Broadcaster>>) -> Self {
+        Hub {
+            sessions: Mutex::new(HashMap::new()),
+            broadcaster,
+            notifier: None,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// The counters of the proposals, see `metrics.rs`.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Post the events of the proposals to webhooks, see `notify.rs`.
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Store a new proposal.
+    pub fn create(&self, store: &Store, request: SigningRequest) -> Result<Summary, Error> {
+        let summary = store.create(request)?;
+        self.metrics.proposal_created(&summary.id);
+        self.notify(Notification::new(
+            EventKind::ProposalCreated,
+            summary.clone(),
+        ));
+        Ok(summary)
+    }
+
+    /// Add the signatures and tell the session, finalize the proposal when
+    /// they complete it.
+    pub async fn add_signatures(
+        &self,
+        store: &Store,
+        id: &H256,
+        signatures: &[[u8; SIGNATURE_SIZE]],
+    ) -> Result<Summary, Error> {
+        let summary = store.add_signatures(id, signatures)?;
+        self.metrics.signatures_added(signatures.len());
+        self.publish(
+            id,
+            Event::Signed {
+                summary: summary.clone(),
+            },
+        );
+        self.notify(Notification::new(
+            EventKind::SignatureAdded,
+            summary.clone(),
+        ));
+        // the store refuses signatures once complete, this runs once
+        if summary.complete {
+            self.metrics.threshold_reached(id);
+            self.notify(Notification::new(
+                EventKind::ThresholdReached,
+                summary.clone(),
+            ));
+            self.finalize(store.get(id)?, &summary).await;
+        }
+        Ok(summary)
+    }
+
+    async fn finalize(&self, request: SigningRequest, summary: &Summary) {
+        let id: H256 = request.tx.hash().unpack();
+        self.publish(
+            &id,
+            Event::Complete {
+                transaction: request.tx.data().into(),
+            },
+        );
+        let broadcaster = match &self.broadcaster {
+            Some(broadcaster) => Arc::clone(broadcaster),
+            None => return,
+        };
+        let sent = {
+            let broadcaster = Arc::clone(&broadcaster);
+            tokio::task::spawn_blocking(move || broadcaster.send(&request)).await
+        };
+        self.metrics.broadcast(matches!(sent, Ok(Ok(_))));
+        let event = match sent {
+            Ok(Ok(tx_hash)) => {
+                if let Some(notifier) = &self.notifier {
+                    notifier.notify(Notification {
+                        tx_hash: Some(tx_hash.clone()),
+                        ..Notification::new(EventKind::Broadcast, summary.clone())
+                    });
+                    notifier.watch_commit(broadcaster, summary.clone(), tx_hash.clone());
+                }
+                Event::Broadcast { tx_hash }
+            }
+            Ok(Err(error)) => Event::BroadcastFailed { error },
+            Err(err) => Event::BroadcastFailed {
+                error: err.to_string(),
+            },
+        };
+        self.publish(&id, event);
+    }
+
+    fn notify(&self, notification: Notification) {
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(notification);
+        }
+    }
+
+    fn publish(&self, id: &H256, event: Event) {
+        if let Some(session) = self.sessions.lock().expect("poisoned lock").get(id) {
+            // no receiver left is fine
+            let _ = session.sender.send(event);
+        }
+    }
+
+    fn join(
+        &self,
+        id: &H256,
+        cosigner: Option<String>,
+    ) -> (broadcast::Receiver<Event>, Vec<String>) {
+        let mut sessions = self.sessions.lock().expect("poisoned lock");
+        let session = sessions.entry(id.clone()).or_insert_with(|| Session {
+            sender: broadcast::channel(CHANNEL_SIZE).0,
+            connections: 0,
+            present: Vec::new(),
+        });
+        session.connections += 1;
+        if let Some(cosigner) = cosigner {
+            let _ = session.sender.send(Event::Joined {
+                cosigner: cosigner.clone(),
+            });
+            session.present.push(cosigner);
+        }
+        (session.sender.subscribe(), session.present.clone())
+    }
+
+    fn present(&self, id: &H256) -> Vec<String> {
+        self.sessions
+            .lock()
+            .expect("poisoned lock")
+            .get(id)
+            .map(|session| session.present.clone())
+            .unwrap_or_default()
+    }
+
+    fn leave(&self, id: &H256, cosigner: Option<String>) {
+        let mut sessions = self.sessions.lock().expect("poisoned lock");
+        let session = match sessions.get_mut(id) {
+            Some(session) => session,
+            None => return,
+        };
+        session.connections -= 1;
+        if session.connections == 0 {
+            sessions.remove(id);
+            return;
+        }
+        if let Some(cosigner) = cosigner {
+            if let Some(i) = session.present.iter().position(|c| *c == cosigner) {
+                session.present.remove(i);
+            }
+            let _ = session.sender.send(Event::Left { cosigner });
+        }
+    }
+}
+
+/// Serve a connection until the cosigner or the server closes it.
+pub async fn run(
+    mut socket: WebSocket,
+    state: AppState,
+    id: H256,
+    cosigner: Option<String>,
+    client: Option<Client>,
+) {
+    let (mut events, present) = state.hub.join(&id, cosigner.clone());
+    if let Ok(proposal) = proposal_event(&state.store, &id, present) {
+        if send(&mut socket, &proposal).await.is_ok() {
+            serve(&mut socket, &state, &id, client.as_ref(), &mut events).await;
+        }
+    }
+    state.hub.leave(&id, cosigner);
+}
+
+async fn serve(
+    socket: &mut WebSocket,
+    state: &AppState,
+    id: &H256,
+    client: Option<&Client>,
+    events: &mut broadcast::Receiver<Event>,
+) {
+    loop {
+        let reply = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => event,
+                // the signatures in between are in the summary of the next
+                // event, or of a fresh proposal event
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    match proposal_event(&state.store, id, state.hub.present(id)) {
+                        Ok(event) => event,
+                        Err(_) => return,
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match handle(state, id, client, &text).await {
+                    Ok(()) => continue,
+                    Err(error) => Event::Error { error },
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if send(socket, &reply).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Apply a message of the cosigner, the outcome comes back as events.
+async fn handle(
+    state: &AppState,
+    id: &H256,
+    client: Option<&Client>,
+    text: &str,
+) -> Result<(), String> {
+    let command: Command =
+        serde_json::from_str(text).map_err(|err| format!("invalid message: {}", err))?;
+    match command {
+        Command::Sign { signatures } => {
+            if let Some(client) = client {
+                let request = state.store.get(id).map_err(|err| err.to_string())?;
+                client
+                    .authorize(&request.config)
+                    .map_err(|ApiError(_, message)| message)?;
+            }
+            let signatures =
+                parse_signatures(&signatures).map_err(|ApiError(_, message)| message)?;
+            state
+                .hub
+                .add_signatures(&state.store, id, &signatures)
+                .await
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        }
+    }
+}
+
+fn proposal_event(store: &Store, id: &H256, present: Vec<String>) -> Result<Event, Error> {
+    let request = store.get(id)?;
+    let json = request.to_json()?;
+    Ok(Event::Proposal {
+        summary: Summary::new(&request)?,
+        request: serde_json::from_str(&json).expect("JSON of the SDK"),
+        present,
+    })
+}
+
+async fn send(socket: &mut WebSocket, event: &Event) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(event).expect("serializable event");
+    socket.send(Message::Text(Utf8Bytes::from(text))).await
+}
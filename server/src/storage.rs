@@ -0,0 +1,159 @@
+//! Where the proposals and the audit log persist, behind `Storage`: the
+//! `Store` keeps them in memory and writes every change through before
+//! acknowledging it, so that they survive restarts.
+//!
+//! `DirStorage` is the backend of `--dir`: one `<tx hash>.json` file per
+//! proposal, in the signing request format of the SDK, and the audit log in
+//! `audit.jsonl`, see `audit.rs` of the SDK. The database backends of
+//! `--database`, `sqlite.rs` and `postgresql.rs` behind the features of the
+//! same names, implement the same four calls: they store the requests and
+//! the entries as they are given, the `Store` computes the hash chain of the
+//! log itself.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use ckb_multisig_sdk::{
+    audit::{parse_log, AuditEntry},
+    request::SigningRequest,
+};
+use ckb_types::H256;
+
+use crate::store::Error;
+
+#[cfg(feature = "postgresql")]
+mod postgresql;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "postgresql")]
+pub use postgresql::PostgresStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+pub trait Storage: Send + Sync {
+    /// Every proposal stored.
+    fn load(&self) -> Result<Vec<SigningRequest>, Error>;
+
+    /// Insert the proposal `id`, or replace it with more signatures.
+    fn save(&self, id: &H256, request: &SigningRequest) -> Result<(), Error>;
+
+    /// The entries of the audit log, in order.
+    fn audit_entries(&self) -> Result<Vec<AuditEntry>, Error>;
+
+    /// Append the entries to the audit log, durably.
+    fn append_audit(&self, entries: &[AuditEntry]) -> Result<(), Error>;
+}
+
+const AUDIT_LOG: &str = "audit.jsonl";
+
+/// The tables of the database backends, the proposal ids in hex.
+#[cfg_attr(not(any(feature = "sqlite", feature = "postgresql")), allow(dead_code))]
+const SCHEMA: &str = "\
+CREATE TABLE IF NOT EXISTS proposals (id TEXT PRIMARY KEY, request TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS audit (seq BIGINT PRIMARY KEY, entry TEXT NOT NULL);
+";
+
+/// The database of `--database`, `sqlite:<path>` or `postgres://...`.
+pub fn connect(url: &str) -> Result<Box<dyn Storage>, Error> {
+    match url.split_once(':') {
+        Some(("sqlite", path)) => open_sqlite(Path::new(path)),
+        Some(("postgres" | "postgresql", _)) => open_postgres(url),
+        _ => Err(unsupported(
+            "a database other than sqlite:<path> or postgres://...",
+        )),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(path: &Path) -> Result<Box<dyn Storage>, Error> {
+    Ok(Box::new(SqliteStorage::open(path)?))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite(_path: &Path) -> Result<Box<dyn Storage>, Error> {
+    Err(unsupported("SQLite, built without the sqlite feature"))
+}
+
+#[cfg(feature = "postgresql")]
+fn open_postgres(url: &str) -> Result<Box<dyn Storage>, Error> {
+    Ok(Box::new(PostgresStorage::connect(url)?))
+}
+
+#[cfg(not(feature = "postgresql"))]
+fn open_postgres(_url: &str) -> Result<Box<dyn Storage>, Error> {
+    Err(unsupported(
+        "PostgreSQL, built without the postgresql feature",
+    ))
+}
+
+fn unsupported(what: &str) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("unsupported {}", what),
+    ))
+}
+
+/// The proposals as files of a directory.
+pub struct DirStorage {
+    dir: PathBuf,
+}
+
+impl DirStorage {
+    /// The storage of `dir`, created when missing.
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(dir)?;
+        Ok(DirStorage {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn audit_path(&self) -> PathBuf {
+        self.dir.join(AUDIT_LOG)
+    }
+}
+
+impl Storage for DirStorage {
+    fn load(&self) -> Result<Vec<SigningRequest>, Error> {
+        let mut requests = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            requests.push(SigningRequest::from_json(&fs::read_to_string(&path)?)?);
+        }
+        Ok(requests)
+    }
+
+    fn save(&self, id: &H256, request: &SigningRequest) -> Result<(), Error> {
+        let path = self.dir.join(format!("{:x}.json", id));
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, request.to_json()?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn audit_entries(&self) -> Result<Vec<AuditEntry>, Error> {
+        match fs::read_to_string(self.audit_path()) {
+            Ok(content) => Ok(parse_log(&content)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn append_audit(&self, entries: &[AuditEntry]) -> Result<(), Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.audit_path())?;
+        for entry in entries {
+            writeln!(file, "{}", entry.to_json())?;
+        }
+        file.sync_data()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+//! The proposals in a PostgreSQL database, `--database postgres://...`, in
+//! the tables of the SQLite backend. Servers sharing the database share the
+//! proposals, the sequence numbers of the audit log being its key: the
+//! second of two servers appending the same entry fails instead of forking
+//! the chain.
+//!
+//! The client of `postgres` blocks on a runtime of its own, which can't start
+//! within the one of the server: its calls run on a thread apart.
+
+use std::{io, sync::Mutex, thread};
+
+use ckb_multisig_sdk::{audit::AuditEntry, request::SigningRequest};
+use ckb_types::H256;
+use postgres::{Client, NoTls};
+
+use super::{Storage, SCHEMA};
+use crate::store::Error;
+
+pub struct PostgresStorage {
+    client: Mutex<Client>,
+}
+
+impl PostgresStorage {
+    /// Connect to the database of `url`, without TLS, and create the tables
+    /// when missing.
+    pub fn connect(url: &str) -> Result<Self, Error> {
+        let client = thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let mut client = Client::connect(url, NoTls)?;
+                    client.batch_execute(SCHEMA)?;
+                    Ok(client)
+                })
+                .join()
+                .expect("postgres thread")
+        })
+        .map_err(|err: postgres::Error| io::Error::other(err))?;
+        Ok(PostgresStorage {
+            client: Mutex::new(client),
+        })
+    }
+
+    fn run<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send,
+        F: FnOnce(&mut Client) -> Result<T, postgres::Error> + Send,
+    {
+        let mut client = self.client.lock().expect("poisoned lock");
+        let client = &mut *client;
+        thread::scope(|scope| scope.spawn(|| f(client)).join().expect("postgres thread"))
+            .map_err(|err| io::Error::other(err).into())
+    }
+}
+
+impl Storage for PostgresStorage {
+    fn load(&self) -> Result<Vec<SigningRequest>, Error> {
+        let rows =
+            self.run(|client| client.query("SELECT request FROM proposals ORDER BY id", &[]))?;
+        let mut requests = Vec::new();
+        for row in rows {
+            requests.push(SigningRequest::from_json(row.get(0))?);
+        }
+        Ok(requests)
+    }
+
+    fn save(&self, id: &H256, request: &SigningRequest) -> Result<(), Error> {
+        let id = format!("{:x}", id);
+        let json = request.to_json()?;
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO proposals (id, request) VALUES ($1, $2) \
+                 ON CONFLICT (id) DO UPDATE SET request = excluded.request",
+                &[&id, &json],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn audit_entries(&self) -> Result<Vec<AuditEntry>, Error> {
+        let rows = self.run(|client| client.query("SELECT entry FROM audit ORDER BY seq", &[]))?;
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(AuditEntry::from_json(row.get(0))?);
+        }
+        Ok(entries)
+    }
+
+    fn append_audit(&self, entries: &[AuditEntry]) -> Result<(), Error> {
+        let rows: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.seq as i64, entry.to_json()))
+            .collect();
+        self.run(|client| {
+            let mut transaction = client.transaction()?;
+            for (seq, json) in &rows {
+                transaction.execute(
+                    "INSERT INTO audit (seq, entry) VALUES ($1, $2)",
+                    &[seq, json],
+                )?;
+            }
+            transaction.commit()
+        })
+    }
+}
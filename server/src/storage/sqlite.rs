@@ -0,0 +1,88 @@
+//! The proposals in a SQLite database, `--database sqlite:<path>`: a table
+//! of the signing requests by transaction hash and one of the audit entries
+//! by sequence number, both in their JSON formats.
+
+use std::{io, path::Path, sync::Mutex};
+
+use ckb_multisig_sdk::{audit::AuditEntry, request::SigningRequest};
+use ckb_types::H256;
+use rusqlite::{params, Connection};
+
+use super::{Storage, SCHEMA};
+use crate::store::Error;
+
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    /// The database of `path`, created when missing.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let connection = Connection::open(path).map_err(io::Error::other)?;
+        connection.execute_batch(SCHEMA).map_err(io::Error::other)?;
+        Ok(SqliteStorage {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self) -> Result<Vec<SigningRequest>, Error> {
+        let connection = self.connection.lock().expect("poisoned lock");
+        let mut statement = connection
+            .prepare("SELECT request FROM proposals ORDER BY id")
+            .map_err(io::Error::other)?;
+        let rows = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(io::Error::other)?;
+        let mut requests = Vec::new();
+        for json in rows {
+            requests.push(SigningRequest::from_json(&json.map_err(io::Error::other)?)?);
+        }
+        Ok(requests)
+    }
+
+    fn save(&self, id: &H256, request: &SigningRequest) -> Result<(), Error> {
+        let json = request.to_json()?;
+        self.connection
+            .lock()
+            .expect("poisoned lock")
+            .execute(
+                "INSERT INTO proposals (id, request) VALUES (?1, ?2) \
+                 ON CONFLICT (id) DO UPDATE SET request = excluded.request",
+                params![format!("{:x}", id), json],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn audit_entries(&self) -> Result<Vec<AuditEntry>, Error> {
+        let connection = self.connection.lock().expect("poisoned lock");
+        let mut statement = connection
+            .prepare("SELECT entry FROM audit ORDER BY seq")
+            .map_err(io::Error::other)?;
+        let rows = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(io::Error::other)?;
+        let mut entries = Vec::new();
+        for json in rows {
+            entries.push(AuditEntry::from_json(&json.map_err(io::Error::other)?)?);
+        }
+        Ok(entries)
+    }
+
+    fn append_audit(&self, entries: &[AuditEntry]) -> Result<(), Error> {
+        let mut connection = self.connection.lock().expect("poisoned lock");
+        let transaction = connection.transaction().map_err(io::Error::other)?;
+        for entry in entries {
+            transaction
+                .execute(
+                    "INSERT INTO audit (seq, entry) VALUES (?1, ?2)",
+                    params![entry.seq as i64, entry.to_json()],
+                )
+                .map_err(io::Error::other)?;
+        }
+        transaction.commit().map_err(io::Error::other)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+//! The proposals, kept in memory and written through to a `Storage`, see
+//! `storage.rs`, a directory by default.
+//!
+//! A proposal is identified by its transaction hash, which doesn't cover the
+//! witnesses and so stays the same while the signatures are collected.
+//!
+//! Every signature accepted is also appended to the audit log of the SDK: the
+//! digest, the signature, the signer and the time it came in, chained so that
+//! third parties can re-verify the export of the log.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ckb_multisig_sdk::{
+    audit::{verify_log, Approval, AuditEntry},
+    constants::SIGNATURE_SIZE,
+    request::SigningRequest,
+};
+use ckb_types::{prelude::*, H256};
+use serde::Serialize;
+
+use crate::storage::{DirStorage, Storage};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("unknown proposal {0:#x}")]
+    NotFound(H256),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error(transparent)]
+    Invalid(#[from] ckb_multisig_sdk::Error),
+
+    #[error("storage error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// What a cosigner needs to know about a proposal before fetching it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub id: H256,
+    /// Lock args of the config, hex.
+    pub lock_args: String,
+    /// In shannons.
+    pub fee: u64,
+    pub threshold: u8,
+    /// Identities of the cosigners who signed, hex.
+    pub signed: Vec<String>,
+    pub complete: bool,
+}
+
+impl Summary {
+    pub fn new(request: &SigningRequest) -> Result<Self, Error> {
+        Ok(Summary {
+            id: request.tx.hash().unpack(),
+            lock_args: format!("0x{}", hex::encode(request.config.lock_args())),
+            fee: request.fee,
+            threshold: request.config.threshold(),
+            signed: request
+                .signed()?
+                .iter()
+                .map(|identity| format!("0x{}", hex::encode(identity)))
+                .collect(),
+            complete: request.is_complete()?,
+        })
+    }
+}
+
+/// Where the next entry of the audit log chains.
+struct AuditHead {
+    next_seq: u64,
+    last_hash: [u8; 32],
+}
+
+pub struct Store {
+    storage: Box<dyn Storage>,
+    proposals: Mutex<BTreeMap<H256, SigningRequest>>,
+    audit: Mutex<AuditHead>,
+}
+
+impl Store {
+    /// Load the proposals of `dir`, created when missing.
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        Self::new(Box::new(DirStorage::open(dir)?))
+    }
+
+    /// Load the proposals of `storage`, whose audit log must verify.
+    pub fn new(storage: Box<dyn Storage>) -> Result<Self, Error> {
+        let mut proposals = BTreeMap::new();
+        for request in storage.load()? {
+            proposals.insert(request.tx.hash().unpack(), request);
+        }
+        let entries = storage.audit_entries()?;
+        verify_log(&entries)?;
+        let audit = AuditHead {
+            next_seq: entries.len() as u64,
+            last_hash: entries.last().map_or([0u8; 32], AuditEntry::hash),
+        };
+        Ok(Store {
+            storage,
+            proposals: Mutex::new(proposals),
+            audit: Mutex::new(audit),
+        })
+    }
+
+    pub fn create(&self, request: SigningRequest) -> Result<Summary, Error> {
+        let id: H256 = request.tx.hash().unpack();
+        let mut proposals = self.proposals.lock().expect("poisoned lock");
+        if proposals.contains_key(&id) {
+            return Err(Error::Conflict(format!(
+                "proposal {:#x} already exists",
+                id
+            )));
+        }
+        // refuse requests whose lock field can't be read
+        let summary = Summary::new(&request)?;
+        self.storage.save(&id, &request)?;
+        proposals.insert(id, request);
+        Ok(summary)
+    }
+
+    pub fn get(&self, id: &H256) -> Result<SigningRequest, Error> {
+        self.proposals
+            .lock()
+            .expect("poisoned lock")
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(id.clone()))
+    }
+
+    /// The proposals still missing signatures, only those `cosigner` can
+    /// sign when given: it is a member of the config and hasn't signed yet.
+    pub fn pending(&self, cosigner: Option<&[u8; 20]>) -> Result<Vec<Summary>, Error> {
+        let proposals = self.proposals.lock().expect("poisoned lock");
+        let mut pending = Vec::new();
+        for request in proposals.values() {
+            let summary = Summary::new(request)?;
+            if summary.complete {
+                continue;
+            }
+            if let Some(cosigner) = cosigner {
+                let identity = format!("0x{}", hex::encode(cosigner));
+                if request.config.position(cosigner).is_none() || summary.signed.contains(&identity)
+                {
+                    continue;
+                }
+            }
+            pending.push(summary);
+        }
+        Ok(pending)
+    }
+
+    /// Add the signatures, all or none: each must come from a member who
+    /// hasn't signed yet.
+    pub fn add_signatures(
+        &self,
+        id: &H256,
+        signatures: &[[u8; SIGNATURE_SIZE]],
+    ) -> Result<Summary, Error> {
+        let mut proposals = self.proposals.lock().expect("poisoned lock");
+        let mut request = proposals
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(id.clone()))?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let mut approvals = Vec::new();
+        for signature in signatures {
+            if request.lock()?.is_complete() {
+                return Err(Error::Conflict(format!(
+                    "proposal {:#x} is already completely signed",
+                    id
+                )));
+            }
+            let signer = request.add_signature(*signature)?;
+            approvals.push(Approval {
+                proposal: id.0,
+                lock_args: request.config.lock_args().to_vec(),
+                digest: request.message()?,
+                signature: *signature,
+                signer,
+                timestamp,
+            });
+        }
+        let summary = Summary::new(&request)?;
+        // logged before being kept, a signature kept is always in the log
+        let mut audit = self.audit.lock().expect("poisoned lock");
+        let mut entries = Vec::new();
+        let (mut seq, mut prev_hash) = (audit.next_seq, audit.last_hash);
+        for approval in approvals {
+            approval
+                .verify()
+                .map_err(|err| Error::Io(io::Error::other(err.to_string())))?;
+            let entry = AuditEntry {
+                seq,
+                prev_hash,
+                approval,
+            };
+            seq += 1;
+            prev_hash = entry.hash();
+            entries.push(entry);
+        }
+        self.storage.append_audit(&entries)?;
+        audit.next_seq = seq;
+        audit.last_hash = prev_hash;
+        self.storage.save(id, &request)?;
+        proposals.insert(id.clone(), request);
+        Ok(summary)
+    }
+
+    /// The audit log, as JSON lines.
+    pub fn audit_log(&self) -> Result<String, Error> {
+        let _audit = self.audit.lock().expect("poisoned lock");
+        Ok(self
+            .storage
+            .audit_entries()?
+            .iter()
+            .map(|entry| format!("{}\n", entry.to_json()))
+            .collect())
+    }
+}
@@ -0,0 +1,957 @@
+use std::{
+    convert::TryFrom,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use ckb_multisig_sdk::{
+    audit::{parse_log, verify_log, AuditEntry},
+    request::SigningRequest,
+    MultisigConfig, SecpSigner, Signer,
+};
+use ckb_sdk::types::ScriptGroup;
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType, TransactionBuilder},
+    packed::{CellInput, CellOutput, OutPoint},
+    prelude::*,
+    H256,
+};
+use http_body_util::BodyExt;
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, RootCertStore,
+};
+use secp256k1::{rand, SecretKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tower::ServiceExt;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_rustls::TlsConnector;
+use tokio_tungstenite::{
+    tungstenite::{self, client::IntoClientRequest},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{
+    api::router,
+    auth::{self, Auth},
+    notify::{self, Notifier},
+    session::{Broadcaster, Hub},
+    storage::{self, DirStorage, Storage},
+    store::{self, Store},
+    tls,
+};
+
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "ckb-multisig-server-{}",
+        hex::encode(rand::random::<[u8; 8]>())
+    ))
+}
+
+fn signers(count: usize) -> Vec<SecpSigner> {
+    (0..count)
+        .map(|_| SecpSigner::new(SecretKey::new(&mut rand::thread_rng())))
+        .collect()
+}
+
+fn identity(signer: &SecpSigner) -> String {
+    format!("0x{}", hex::encode(signer.identity().unwrap()))
+}
+
+fn proposal(signers: &[SecpSigner], threshold: u8) -> SigningRequest {
+    let hashes = signers.iter().map(|s| s.identity().unwrap()).collect();
+    let config = MultisigConfig::new(hashes, 0, threshold).unwrap();
+    let lock = config.lock_script(&H256([0x42; 32]), ScriptHashType::Data1);
+    let mut script_group = ScriptGroup::from_lock_script(&lock);
+    script_group.input_indices = vec![0];
+    let out_point = OutPoint::new(rand::random::<[u8; 32]>().pack(), 0);
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(out_point, 0))
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::shannons(42).pack())
+                .lock(lock)
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .build();
+    SigningRequest {
+        config,
+        tx,
+        script_group,
+        fee: 1000,
+        genesis_hash: None,
+    }
+}
+
+fn signature(signer: &SecpSigner, request: &SigningRequest) -> String {
+    format!(
+        "0x{}",
+        hex::encode(signer.sign(&request.message().unwrap()).unwrap())
+    )
+}
+
+async fn call(app: &Router, method: &str, uri: &str, body: String) -> (StatusCode, String) {
+    call_as(app, None, method, uri, body).await
+}
+
+/// Call with the API key `key`.
+async fn call_as(
+    app: &Router,
+    key: Option<&str>,
+    method: &str,
+    uri: &str,
+    body: String,
+) -> (StatusCode, String) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(key) = key {
+        request = request.header("authorization", format!("Bearer {}", key));
+    }
+    let request = request.body(Body::from(body)).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+async fn call_json(app: &Router, method: &str, uri: &str, body: String) -> (StatusCode, Value) {
+    let (status, body) = call(app, method, uri, body).await;
+    (status, serde_json::from_str(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_signing_flow() {
+    let dir = temp_dir();
+    let app = router(
+        Arc::new(Store::open(&dir).unwrap()),
+        Arc::new(Hub::new(None)),
+    );
+    let signers = signers(3);
+    let request = proposal(&signers, 2);
+    let id = format!("{:#x}", request.tx.hash());
+
+    let (status, summary) = call_json(&app, "POST", "/proposals", request.to_json().unwrap()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(summary["id"], id);
+    assert_eq!(summary["threshold"], 2);
+    let (status, _) = call(&app, "POST", "/proposals", request.to_json().unwrap()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let uri = format!("/proposals?cosigner={}", identity(&signers[0]));
+    let (status, pending) = call_json(&app, "GET", &uri, String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(pending.as_array().unwrap().len(), 1);
+    let outsider = &self::signers(1)[0];
+    let uri = format!("/proposals?cosigner={}", identity(outsider));
+    let (_, pending) = call_json(&app, "GET", &uri, String::new()).await;
+    assert!(pending.as_array().unwrap().is_empty());
+
+    // the cosigner fetches the request and signs it offline
+    let (status, body) = call(&app, "GET", &format!("/proposals/{}", id), String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    let fetched = SigningRequest::from_json(&body).unwrap();
+    let uri = format!("/proposals/{}/signatures", id);
+    let body = json!({ "signatures": [signature(&signers[0], &fetched)] }).to_string();
+    let (status, summary) = call_json(&app, "POST", &uri, body.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["signed"], json!([identity(&signers[0])]));
+    assert_eq!(summary["complete"], false);
+    let (status, _) = call(&app, "POST", &uri, body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let uri = format!("/proposals?cosigner={}", identity(&signers[0]));
+    let (_, pending) = call_json(&app, "GET", &uri, String::new()).await;
+    assert!(pending.as_array().unwrap().is_empty());
+
+    let tx_uri = format!("/proposals/{}/transaction", id);
+    let (status, _) = call(&app, "GET", &tx_uri, String::new()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let uri = format!("/proposals/{}/signatures", id);
+    let body = json!({ "signatures": [signature(&signers[2], &fetched)] }).to_string();
+    let (_, summary) = call_json(&app, "POST", &uri, body).await;
+    assert_eq!(summary["complete"], true);
+    let (status, tx) = call_json(&app, "GET", &tx_uri, String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tx["inputs"].as_array().unwrap().len(), 1);
+    assert_eq!(tx["witnesses"].as_array().unwrap().len(), 1);
+
+    // the audit log re-verifies, one entry per signature accepted
+    let (status, log) = call(&app, "GET", "/audit", String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = parse_log(&log).unwrap();
+    verify_log(&entries).unwrap();
+    let signed: Vec<_> = entries
+        .iter()
+        .map(|entry| format!("0x{}", hex::encode(entry.approval.signer)))
+        .collect();
+    assert_eq!(signed, vec![identity(&signers[0]), identity(&signers[2])]);
+    for entry in &entries {
+        assert_eq!(format!("0x{}", hex::encode(entry.approval.proposal)), id);
+        assert_eq!(entry.approval.digest, fetched.message().unwrap());
+        entry.check_member(&request.config).unwrap();
+    }
+
+    // the proposals survive a restart
+    let store = Store::open(&dir).unwrap();
+    assert!(store
+        .get(&request.tx.hash().unpack())
+        .unwrap()
+        .is_complete()
+        .unwrap());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_invalid_calls() {
+    let dir = temp_dir();
+    let app = router(
+        Arc::new(Store::open(&dir).unwrap()),
+        Arc::new(Hub::new(None)),
+    );
+    let signers = signers(2);
+    let request = proposal(&signers, 1);
+
+    let (status, body) = call_json(&app, "POST", "/proposals", "{}".to_string()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].is_string());
+    let unknown = format!("/proposals/{:#x}", H256([1; 32]));
+    let (status, _) = call(&app, "GET", &unknown, String::new()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = call(&app, "GET", "/proposals/0x12", String::new()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call(&app, "GET", "/proposals?cosigner=0x12", String::new()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    call(&app, "POST", "/proposals", request.to_json().unwrap()).await;
+    let uri = format!("/proposals/{:#x}/signatures", request.tx.hash());
+    // a signature over another message recovers to an unknown key
+    let other = proposal(&signers, 1);
+    let body = json!({ "signatures": [signature(&signers[0], &other)] }).to_string();
+    let (status, _) = call(&app, "POST", &uri, body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let body = json!({ "signatures": ["0x1234"] }).to_string();
+    let (status, _) = call(&app, "POST", &uri, body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let body = json!({ "signatures": [
+        signature(&signers[0], &request),
+        signature(&signers[1], &request),
+    ] })
+    .to_string();
+    let (status, _) = call(&app, "POST", &uri, body).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    // nothing was added
+    let (_, pending) = call_json(&app, "GET", "/proposals", String::new()).await;
+    assert_eq!(pending[0]["signed"], json!([]));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Records the transactions instead of sending them.
+#[derive(Default)]
+struct Recorder(Mutex<Vec<H256>>);
+
+impl Broadcaster for Recorder {
+    fn send(&self, request: &SigningRequest) -> Result<H256, String> {
+        let hash: H256 = request.tx.hash().unpack();
+        self.0.lock().unwrap().push(hash.clone());
+        Ok(hash)
+    }
+
+    fn committed(&self, tx_hash: &H256) -> Result<Option<u64>, String> {
+        Ok(self.0.lock().unwrap().contains(tx_hash).then_some(7))
+    }
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(url: &str) -> Result<Socket, tungstenite::Error> {
+    tokio_tungstenite::connect_async(url)
+        .await
+        .map(|(socket, _)| socket)
+}
+
+async fn next(socket: &mut Socket) -> Value {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("message in time")
+            .unwrap()
+            .unwrap();
+        if let tungstenite::Message::Text(text) = message {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+async fn sign(socket: &mut Socket, signatures: Vec<String>) {
+    let message = json!({ "type": "sign", "signatures": signatures }).to_string();
+    socket
+        .send(tungstenite::Message::Text(message.into()))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_session() {
+    let dir = temp_dir();
+    let store = Arc::new(Store::open(&dir).unwrap());
+    let recorder = Arc::new(Recorder::default());
+    let hub = Arc::new(Hub::new(Some(recorder.clone() as Arc<dyn Broadcaster>)));
+    let app = router(Arc::clone(&store), hub);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let signers = signers(3);
+    let request = proposal(&signers, 2);
+    store.create(request.clone()).unwrap();
+    let url = |signer: &SecpSigner| {
+        format!(
+            "ws://{}/proposals/{:#x}/session?cosigner={}",
+            addr,
+            request.tx.hash(),
+            identity(signer)
+        )
+    };
+
+    let mut alice = connect(&url(&signers[0])).await.unwrap();
+    let proposal = next(&mut alice).await;
+    assert_eq!(proposal["type"], "proposal");
+    assert_eq!(proposal["present"], json!([identity(&signers[0])]));
+    let fetched = SigningRequest::from_json(&proposal["request"].to_string()).unwrap();
+    assert_eq!(fetched.tx.hash(), request.tx.hash());
+
+    let mut bob = connect(&url(&signers[1])).await.unwrap();
+    let proposal = next(&mut bob).await;
+    assert_eq!(proposal["present"].as_array().unwrap().len(), 2);
+    let joined = next(&mut alice).await;
+    assert_eq!(
+        joined,
+        json!({ "type": "joined", "cosigner": identity(&signers[1]) })
+    );
+    // only cosigners of the proposal
+    assert!(connect(&url(&self::signers(1)[0])).await.is_err());
+
+    sign(&mut alice, vec!["0x1234".to_string()]).await;
+    assert_eq!(next(&mut alice).await["type"], "error");
+    sign(&mut alice, vec![signature(&signers[0], &request)]).await;
+    for socket in [&mut alice, &mut bob] {
+        let signed = next(socket).await;
+        assert_eq!(signed["type"], "signed");
+        assert_eq!(signed["summary"]["complete"], false);
+    }
+
+    sign(&mut bob, vec![signature(&signers[1], &request)]).await;
+    for socket in [&mut alice, &mut bob] {
+        assert_eq!(next(socket).await["summary"]["complete"], true);
+        let complete = next(socket).await;
+        assert_eq!(complete["type"], "complete");
+        assert_eq!(
+            complete["transaction"]["witnesses"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+        let broadcast = next(socket).await;
+        assert_eq!(broadcast["type"], "broadcast");
+        assert_eq!(broadcast["tx_hash"], format!("{:#x}", request.tx.hash()));
+    }
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        vec![request.tx.hash().unpack()]
+    );
+
+    bob.close(None).await.unwrap();
+    let left = next(&mut alice).await;
+    assert_eq!(
+        left,
+        json!({ "type": "left", "cosigner": identity(&signers[1]) })
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// The bodies posted to each path.
+type Received = Arc<Mutex<Vec<(String, Value)>>>;
+
+async fn webhook_receiver() -> (String, Received) {
+    let received = Received::default();
+    let app = Router::new().fallback({
+        let received = Arc::clone(&received);
+        move |uri: axum::http::Uri, axum::Json(body): axum::Json<Value>| async move {
+            received
+                .lock()
+                .unwrap()
+                .push((uri.path().to_string(), body));
+            StatusCode::OK
+        }
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{}", addr), received)
+}
+
+#[tokio::test]
+async fn test_webhooks() {
+    let dir = temp_dir();
+    let (url, received) = webhook_receiver().await;
+    let store = Store::open(&dir).unwrap();
+    let path = dir.join("webhooks.toml");
+    let webhooks = format!(
+        r#"
+        [[webhook]]
+        url = "{url}/ops"
+
+        [[webhook]]
+        url = "{url}/chat"
+        format = "slack"
+        events = ["threshold_reached"]
+        "#
+    );
+    std::fs::write(&path, webhooks).unwrap();
+    let notifier =
+        Notifier::new(notify::load(&path).unwrap()).confirm_interval(Duration::from_millis(10));
+    let recorder = Arc::new(Recorder::default());
+    let hub = Hub::new(Some(recorder as Arc<dyn Broadcaster>)).with_notifier(notifier);
+    let app = router(Arc::new(store), Arc::new(hub));
+
+    let signers = signers(3);
+    let request = proposal(&signers, 2);
+    let uri = format!("/proposals/{:#x}/signatures", request.tx.hash());
+    call(&app, "POST", "/proposals", request.to_json().unwrap()).await;
+    for signer in &signers[..2] {
+        let body = json!({ "signatures": [signature(signer, &request)] }).to_string();
+        let (status, _) = call(&app, "POST", &uri, body).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let events = |path: &str| -> Vec<Value> {
+        received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(other, _)| other == path)
+            .map(|(_, body)| body.clone())
+            .collect()
+    };
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while events("/ops").len() < 6 || events("/chat").is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("notifications in time");
+
+    let ops = events("/ops");
+    let kinds: Vec<_> = ops.iter().map(|body| body["event"].clone()).collect();
+    for kind in [
+        "proposal_created",
+        "signature_added",
+        "threshold_reached",
+        "broadcast",
+        "confirmed",
+    ] {
+        assert!(kinds.contains(&json!(kind)), "no {} in {:?}", kind, kinds);
+    }
+    let id = format!("{:#x}", request.tx.hash());
+    assert!(ops.iter().all(|body| body["summary"]["id"] == id));
+    let confirmed = ops
+        .iter()
+        .find(|body| body["event"] == "confirmed")
+        .unwrap();
+    assert_eq!(confirmed["block_number"], 7);
+    assert_eq!(confirmed["tx_hash"], id);
+
+    let chat = events("/chat");
+    assert_eq!(chat.len(), 1);
+    assert_eq!(
+        chat[0],
+        json!({ "text": format!("Proposal {} is completely signed", id) })
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn metric(metrics: &str, name: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("no metric {}", name))
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_metrics() {
+    let recorder = Arc::new(Recorder::default());
+    let app = router(
+        Arc::new(Store::open(&temp_dir()).unwrap()),
+        Arc::new(Hub::new(Some(recorder.clone() as Arc<dyn Broadcaster>))),
+    );
+    let signers = signers(3);
+    let stalled = proposal(&signers, 2);
+    let request = proposal(&signers, 2);
+    for request in [&stalled, &request] {
+        let (status, _) = call(&app, "POST", "/proposals", request.to_json().unwrap()).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (status, metrics) = call(&app, "GET", "/metrics", String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(metrics.contains("# TYPE ckb_multisig_threshold_seconds histogram"));
+    assert_eq!(metric(&metrics, "ckb_multisig_proposals_pending"), 2.0);
+    assert_eq!(
+        metric(&metrics, "ckb_multisig_proposals_created_total"),
+        2.0
+    );
+    assert_eq!(
+        metric(&metrics, "ckb_multisig_threshold_seconds_count"),
+        0.0
+    );
+
+    let uri = format!("/proposals/{:#x}/signatures", stalled.tx.hash());
+    let body = json!({ "signatures": [signature(&signers[0], &stalled)] });
+    call(&app, "POST", &uri, body.to_string()).await;
+    let uri = format!("/proposals/{:#x}/signatures", request.tx.hash());
+    let body = json!({
+        "signatures": [signature(&signers[1], &request), signature(&signers[2], &request)]
+    });
+    let (status, _) = call(&app, "POST", &uri, body.to_string()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, metrics) = call(&app, "GET", "/metrics", String::new()).await;
+    assert_eq!(metric(&metrics, "ckb_multisig_proposals_pending"), 1.0);
+    assert!(metric(&metrics, "ckb_multisig_oldest_pending_seconds") > 0.0);
+    assert_eq!(metric(&metrics, "ckb_multisig_signatures_total"), 3.0);
+    assert_eq!(
+        metric(&metrics, "ckb_multisig_threshold_seconds_count"),
+        1.0
+    );
+    assert_eq!(
+        metric(&metrics, "ckb_multisig_threshold_seconds_bucket{le=\"60\"}"),
+        1.0
+    );
+    assert_eq!(metric(&metrics, "ckb_multisig_broadcasts_total"), 1.0);
+    assert_eq!(
+        metric(&metrics, "ckb_multisig_broadcast_failures_total"),
+        0.0
+    );
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+#[tokio::test]
+async fn test_auth() {
+    let signers = signers(3);
+    let outsider = &self::signers(1)[0];
+    let auth = Auth::from_toml(&format!(
+        r#"
+        [[client]]
+        name = "proposer"
+        api_key_sha256 = "{}"
+
+        [[client]]
+        name = "alice"
+        cosigner = "{}"
+        api_key_sha256 = "{}"
+
+        [[client]]
+        name = "mallory"
+        cosigner = "{}"
+        api_key_sha256 = "{}"
+        "#,
+        sha256(b"proposer-key"),
+        identity(&signers[0]),
+        sha256(b"alice-key"),
+        identity(outsider),
+        sha256(b"mallory-key"),
+    ))
+    .unwrap();
+    let store = Arc::new(Store::open(&temp_dir()).unwrap());
+    let app = auth::layer(
+        router(Arc::clone(&store), Arc::new(Hub::new(None))),
+        Arc::new(auth),
+    );
+    let request = proposal(&signers, 2);
+
+    let (status, _) = call(&app, "GET", "/proposals", String::new()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call_as(&app, Some("guess"), "GET", "/proposals", String::new()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call_as(
+        &app,
+        Some("proposer-key"),
+        "POST",
+        "/proposals",
+        request.to_json().unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // only the members of the config sign, each its own signature or not
+    let uri = format!("/proposals/{:#x}/signatures", request.tx.hash());
+    let body = json!({ "signatures": [signature(&signers[0], &request)] }).to_string();
+    for key in ["proposer-key", "mallory-key"] {
+        let (status, _) = call_as(&app, Some(key), "POST", &uri, body.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+    let (status, _) = call_as(&app, Some("alice-key"), "POST", &uri, body).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let invalid = [
+        "[[client]]\nname = \"a\"".to_string(),
+        format!(
+            "[[client]]\nname = \"a\"\napi_key_sha256 = \"{0}\"\n[[client]]\nname = \"b\"\napi_key_sha256 = \"{0}\"",
+            sha256(b"key")
+        ),
+        "[[client]]\nname = \"a\"\napi_key_sha256 = \"0x12\"".to_string(),
+        "rate_limit = 0".to_string(),
+    ];
+    for content in &invalid {
+        assert!(Auth::from_toml(content).is_err(), "{}", content);
+    }
+}
+
+#[tokio::test]
+async fn test_rate_limit() {
+    let auth = Auth::from_toml(&format!(
+        "rate_limit = 2\n[[client]]\nname = \"a\"\napi_key_sha256 = \"{}\"\n[[client]]\nname = \"b\"\napi_key_sha256 = \"{}\"",
+        sha256(b"a"),
+        sha256(b"b"),
+    ))
+    .unwrap();
+    let app = auth::layer(
+        router(
+            Arc::new(Store::open(&temp_dir()).unwrap()),
+            Arc::new(Hub::new(None)),
+        ),
+        Arc::new(auth),
+    );
+    for _ in 0..2 {
+        let (status, _) = call_as(&app, Some("a"), "GET", "/proposals", String::new()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let request = Request::get("/proposals")
+        .header("authorization", "Bearer a")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "30");
+    // the limit is per client
+    let (status, _) = call_as(&app, Some("b"), "GET", "/proposals", String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_session_auth() {
+    let signers = signers(2);
+    let auth = Auth::from_toml(&format!(
+        "[[client]]\nname = \"alice\"\ncosigner = \"{}\"\napi_key_sha256 = \"{}\"",
+        identity(&signers[0]),
+        sha256(b"alice-key"),
+    ))
+    .unwrap();
+    let store = Arc::new(Store::open(&temp_dir()).unwrap());
+    let app = auth::layer(
+        router(Arc::clone(&store), Arc::new(Hub::new(None))),
+        Arc::new(auth),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let request = proposal(&signers, 2);
+    store.create(request.clone()).unwrap();
+
+    let join = |signer: &SecpSigner| {
+        let url = format!(
+            "ws://{}/proposals/{:#x}/session?cosigner={}",
+            addr,
+            request.tx.hash(),
+            identity(signer)
+        );
+        let mut request = url.into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("authorization", "Bearer alice-key".parse().unwrap());
+        tokio_tungstenite::connect_async(request)
+    };
+    match join(&signers[1]).await {
+        Err(tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::FORBIDDEN)
+        }
+        other => panic!("joined as another cosigner: {:?}", other.map(|_| ())),
+    }
+    let (mut alice, _) = join(&signers[0]).await.unwrap();
+    assert_eq!(next(&mut alice).await["type"], "proposal");
+    assert!(connect(&format!(
+        "ws://{}/proposals/{:#x}/session",
+        addr,
+        request.tx.hash()
+    ))
+    .await
+    .is_err());
+}
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/fixtures");
+
+/// GET `uri` over TLS as the holder of `identity`, a PEM certificate and
+/// key of the fixtures, returning the status line.
+async fn get_tls(addr: SocketAddr, identity: Option<&str>, uri: &str) -> std::io::Result<String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut roots = RootCertStore::empty();
+    for ca in CertificateDer::pem_file_iter(format!("{}/ca.pem", FIXTURES)).unwrap() {
+        roots.add(ca.unwrap()).unwrap();
+    }
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let config = match identity {
+        Some(name) => {
+            let chain = CertificateDer::pem_file_iter(format!("{}/{}.pem", FIXTURES, name))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let key = PrivateKeyDer::from_pem_file(format!("{}/{}.key", FIXTURES, name)).unwrap();
+            builder.with_client_auth_cert(chain, key).unwrap()
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let server = ServerName::try_from("localhost").unwrap();
+    let mut stream = TlsConnector::from(Arc::new(config))
+        .connect(server, stream)
+        .await?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n",
+        uri
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let response = String::from_utf8_lossy(&response);
+    Ok(response.lines().next().unwrap_or_default().to_string())
+}
+
+#[tokio::test]
+async fn test_mutual_tls() {
+    let certificate = CertificateDer::from_pem_file(format!("{}/client.pem", FIXTURES)).unwrap();
+    let auth = Auth::from_toml(&format!(
+        "[[client]]\nname = \"client\"\ncertificate_sha256 = \"{}\"",
+        sha256(&certificate),
+    ))
+    .unwrap();
+    let app = auth::layer(
+        router(
+            Arc::new(Store::open(&temp_dir()).unwrap()),
+            Arc::new(Hub::new(None)),
+        ),
+        Arc::new(auth),
+    );
+    let config = tls::config(
+        Path::new(&format!("{}/server.pem", FIXTURES)),
+        Path::new(&format!("{}/server.key", FIXTURES)),
+        Some(Path::new(&format!("{}/ca.pem", FIXTURES))),
+    )
+    .unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(tls::serve(listener, config, app));
+
+    let status = get_tls(addr, Some("client"), "/proposals").await.unwrap();
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    // the server certificate isn't a client one, nor a client of the auth file
+    let refused = get_tls(addr, Some("server"), "/proposals").await;
+    assert!(refused.map_or(true, |status| status.is_empty()));
+    let refused = get_tls(addr, None, "/proposals").await;
+    assert!(refused.map_or(true, |status| status.is_empty()));
+}
+
+/// A storage in memory, shared by the stores of a test as a database would
+/// be, which fails its writes when told to.
+#[derive(Clone, Default)]
+struct SharedStorage {
+    proposals: Arc<Mutex<Vec<(H256, String)>>>,
+    audit: Arc<Mutex<Vec<AuditEntry>>>,
+    failing: Arc<Mutex<bool>>,
+}
+
+impl SharedStorage {
+    fn check(&self) -> Result<(), store::Error> {
+        if *self.failing.lock().unwrap() {
+            return Err(store::Error::Io(std::io::Error::other("database is down")));
+        }
+        Ok(())
+    }
+}
+
+impl Storage for SharedStorage {
+    fn load(&self) -> Result<Vec<SigningRequest>, store::Error> {
+        let proposals = self.proposals.lock().unwrap();
+        Ok(proposals
+            .iter()
+            .map(|(_, json)| SigningRequest::from_json(json).unwrap())
+            .collect())
+    }
+
+    fn save(&self, id: &H256, request: &SigningRequest) -> Result<(), store::Error> {
+        self.check()?;
+        let mut proposals = self.proposals.lock().unwrap();
+        proposals.retain(|(known, _)| known != id);
+        proposals.push((id.clone(), request.to_json()?));
+        Ok(())
+    }
+
+    fn audit_entries(&self) -> Result<Vec<AuditEntry>, store::Error> {
+        Ok(self.audit.lock().unwrap().clone())
+    }
+
+    fn append_audit(&self, entries: &[AuditEntry]) -> Result<(), store::Error> {
+        self.check()?;
+        self.audit.lock().unwrap().extend_from_slice(entries);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_storage() {
+    let storage = SharedStorage::default();
+    let store = Store::new(Box::new(storage.clone())).unwrap();
+    let signers = signers(3);
+    let request = proposal(&signers, 2);
+    let id: H256 = request.tx.hash().unpack();
+    store.create(request.clone()).unwrap();
+    let sign = |store: &Store, signer: &SecpSigner| {
+        let signature = signer.sign(&request.message().unwrap()).unwrap();
+        store.add_signatures(&id, &[signature])
+    };
+    sign(&store, &signers[0]).unwrap();
+
+    // a write failing keeps nothing
+    *storage.failing.lock().unwrap() = true;
+    assert!(sign(&store, &signers[1]).is_err());
+    assert!(store.create(proposal(&signers, 2)).is_err());
+    *storage.failing.lock().unwrap() = false;
+    assert_eq!(store.get(&id).unwrap().signed().unwrap().len(), 1);
+
+    // a restart, or a replica, picks up where the store stopped
+    drop(store);
+    let store = Store::new(Box::new(storage.clone())).unwrap();
+    assert_eq!(store.pending(None).unwrap().len(), 1);
+    assert_eq!(sign(&store, &signers[1]).unwrap().signed.len(), 2);
+    let entries = parse_log(&store.audit_log().unwrap()).unwrap();
+    assert_eq!(entries, *storage.audit.lock().unwrap());
+    assert_eq!(entries.len(), 2);
+    verify_log(&entries).unwrap();
+
+    // the chain of the log is checked on load
+    storage.audit.lock().unwrap().remove(0);
+    assert!(Store::new(Box::new(storage)).is_err());
+}
+
+/// What a backend keeps across restarts: the proposals, replaced as they are
+/// signed, and the audit log.
+fn check_persistence(open: impl Fn() -> Box<dyn Storage>) {
+    let signers = signers(3);
+    let request = proposal(&signers, 2);
+    let id: H256 = request.tx.hash().unpack();
+    let signature = |signer: &SecpSigner| signer.sign(&request.message().unwrap()).unwrap();
+    let store = Store::new(open()).unwrap();
+    store.create(request.clone()).unwrap();
+    store
+        .add_signatures(&id, &[signature(&signers[0])])
+        .unwrap();
+    drop(store);
+
+    let store = Store::new(open()).unwrap();
+    assert_eq!(store.get(&id).unwrap().signed().unwrap().len(), 1);
+    store
+        .add_signatures(&id, &[signature(&signers[1])])
+        .unwrap();
+    drop(store);
+
+    let storage = open();
+    let requests = storage.load().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].is_complete().unwrap());
+    let entries = storage.audit_entries().unwrap();
+    assert_eq!(entries.len(), 2);
+    verify_log(&entries).unwrap();
+}
+
+/// The sequence numbers key the log of a database: an entry appended twice,
+/// e.g. by two servers, fails.
+#[cfg(any(feature = "sqlite", feature = "postgresql"))]
+fn check_audit_key(storage: &dyn Storage) {
+    let entries = storage.audit_entries().unwrap();
+    assert!(storage.append_audit(&entries[1..]).is_err());
+    assert_eq!(storage.audit_entries().unwrap(), entries);
+}
+
+#[test]
+fn test_dir_storage() {
+    let dir = temp_dir();
+    check_persistence(|| Box::new(DirStorage::open(&dir).unwrap()));
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_storage() {
+    let dir = temp_dir();
+    std::fs::create_dir_all(&dir).unwrap();
+    let url = format!("sqlite:{}", dir.join("proposals.db").display());
+    check_persistence(|| storage::connect(&url).unwrap());
+    check_audit_key(&*storage::connect(&url).unwrap());
+}
+
+/// Against the database of `CKB_MULTISIG_TEST_POSTGRES`, in a schema of its
+/// own, skipped when unset.
+#[cfg(feature = "postgresql")]
+#[test]
+fn test_postgres_storage() {
+    let url = match std::env::var("CKB_MULTISIG_TEST_POSTGRES") {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    let schema = format!("ckb_multisig_{}", hex::encode(rand::random::<[u8; 8]>()));
+    let mut client = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+    client
+        .batch_execute(&format!("CREATE SCHEMA {}", schema))
+        .unwrap();
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let url = format!("{}{}options=-csearch_path%3D{}", url, separator, schema);
+    check_persistence(|| storage::connect(&url).unwrap());
+    check_audit_key(&*storage::connect(&url).unwrap());
+    client
+        .batch_execute(&format!("DROP SCHEMA {} CASCADE", schema))
+        .unwrap();
+}
+
+#[test]
+fn test_unsupported_database() {
+    assert!(storage::connect("mysql://localhost/proposals").is_err());
+    assert!(storage::connect("proposals.db").is_err());
+    #[cfg(not(feature = "sqlite"))]
+    assert!(storage::connect("sqlite:proposals.db").is_err());
+}
@@ -0,0 +1,196 @@
+//! The error branches of the lock a transaction can reach, each refused with
+//! its own code rather than a panic or a wrong verdict: the since of the args
+//! against the group inputs, see `check_since` of `ckb-multisig-core`, and
+//! the witness of the lock field missing or malformed.
+//!
+//! The other syscall errors, `ItemMissing`, `LengthNotEnough` and `Unknown`,
+//! can't be provoked by a transaction at these load sites; they map to their
+//! codes in `error.rs` like the ones tested here.
+
+use super::*;
+use crate::vm_versions::{blake160, exit_code, multi_sign_tx, multisig_script, MAX_CYCLES};
+use ckb_testtool::ckb_crypto::secp::{Generator, Privkey};
+use ckb_testtool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
+use ckb_testtool::context::Context;
+
+// error numbers
+const ERROR_INDEX_OUT_OF_BOUND: i8 = 1;
+const ERROR_ENCODING: i8 = 4;
+const ERROR_WITNESS_SIZE: i8 = -22;
+const ERROR_INCORRECT_SINCE_FLAGS: i8 = -23;
+const ERROR_INCORRECT_SINCE_VALUE: i8 = -24;
+const ERROR_INVALID_SINCE_FLAGS: i8 = -25;
+const ERROR_INCORRECT_SINCE_RELATIVE: i8 = -26;
+const ERROR_INVALID_SINCE_EPOCH: i8 = -27;
+
+const RELATIVE: u64 = 0x80 << 56;
+const EPOCH: u64 = 0x20 << 56;
+
+/// An epoch since value: `number` and `index / length` of the next.
+fn epoch(number: u64, index: u64, length: u64) -> u64 {
+    EPOCH | length << 40 | index << 24 | number
+}
+
+/// A 2 of 3 multisig lock with `since` in its args, spending one input per
+/// since of `input_sinces`.
+struct Fault {
+    context: Context,
+    tx: TransactionView,
+    keys: Vec<Privkey>,
+    script: Bytes,
+}
+
+impl Fault {
+    fn new(since: u64, input_sinces: &[u64]) -> Self {
+        let keys: Vec<_> = (0..3).map(|_| Generator::random_privkey()).collect();
+        let script = multisig_script(&keys, 2, 0);
+        let mut args = blake160(&script).to_vec();
+        args.extend_from_slice(&since.to_le_bytes());
+
+        let mut context = Context::default();
+        let loader = Loader::default();
+        let lock_out_point = context.deploy_cell(loader.load_binary("ckb-multisig"));
+        let data_out_point = context.deploy_cell(loader.load_secp256k1_data());
+        let lock = context
+            .build_script(&lock_out_point, args.into())
+            .expect("script");
+        let inputs: Vec<_> = input_sinces
+            .iter()
+            .map(|since| {
+                let input = context.create_cell(
+                    CellOutput::new_builder()
+                        .capacity(1000u64.pack())
+                        .lock(lock.clone())
+                        .build(),
+                    Bytes::new(),
+                );
+                CellInput::new_builder()
+                    .previous_output(input)
+                    .since(since.pack())
+                    .build()
+            })
+            .collect();
+        let dep = |out_point: OutPoint| CellDep::new_builder().out_point(out_point).build();
+        let tx = TransactionBuilder::default()
+            .inputs(inputs)
+            .output(
+                CellOutput::new_builder()
+                    .capacity(1000u64.pack())
+                    .lock(lock)
+                    .build(),
+            )
+            .output_data(Bytes::new().pack())
+            .cell_dep(dep(lock_out_point))
+            .cell_dep(dep(data_out_point))
+            .witness(WitnessArgs::new_builder().build().as_bytes().pack())
+            .build();
+        let tx = context.complete_tx(tx);
+        Fault {
+            context,
+            tx,
+            keys,
+            script,
+        }
+    }
+
+    /// Sign with 2 of the keys and run the lock.
+    fn run(&self) -> Result<u64, i8> {
+        let tx = multi_sign_tx(&self.tx, &self.script, &[&self.keys[0], &self.keys[1]]);
+        self.verify(&tx)
+    }
+
+    /// Run the lock with `witnesses` instead of the signed ones.
+    fn run_with_witnesses(&self, witnesses: Vec<Bytes>) -> Result<u64, i8> {
+        let tx = self
+            .tx
+            .as_advanced_builder()
+            .set_witnesses(witnesses.iter().map(|witness| witness.pack()).collect())
+            .build();
+        self.verify(&tx)
+    }
+
+    fn verify(&self, tx: &TransactionView) -> Result<u64, i8> {
+        self.context
+            .verify_tx(tx, MAX_CYCLES)
+            .map_err(|err| exit_code(&err.to_string()))
+    }
+}
+
+#[test]
+fn test_since_faults() {
+    // the since of the args is checked before any input
+    for since in [0x01 << 56, 0x10 << 56, 0x60 << 56, RELATIVE | 0x60 << 56] {
+        assert_eq!(
+            Fault::new(since, &[0]).run(),
+            Err(ERROR_INVALID_SINCE_FLAGS),
+            "{:#x}",
+            since
+        );
+    }
+    for since in [epoch(1, 4, 4), epoch(1, 5, 4), epoch(1, 1, 0)] {
+        assert_eq!(
+            Fault::new(since, &[since]).run(),
+            Err(ERROR_INVALID_SINCE_EPOCH),
+            "{:#x}",
+            since
+        );
+    }
+    // 0 / 0 is a whole epoch
+    assert!(Fault::new(epoch(1, 0, 0), &[epoch(1, 0, 0)]).run().is_ok());
+
+    assert_eq!(
+        Fault::new(100, &[RELATIVE | 100]).run(),
+        Err(ERROR_INCORRECT_SINCE_RELATIVE)
+    );
+    assert_eq!(
+        Fault::new(RELATIVE | 100, &[100]).run(),
+        Err(ERROR_INCORRECT_SINCE_RELATIVE)
+    );
+    assert_eq!(
+        Fault::new(100, &[epoch(100, 0, 1)]).run(),
+        Err(ERROR_INCORRECT_SINCE_FLAGS)
+    );
+
+    // every input of the group is checked, not only the first
+    assert!(Fault::new(100, &[100, 101]).run().is_ok());
+    assert_eq!(
+        Fault::new(100, &[100, 99]).run(),
+        Err(ERROR_INCORRECT_SINCE_VALUE)
+    );
+    assert_eq!(
+        Fault::new(epoch(1, 1, 2), &[epoch(1, 1, 2), epoch(1, 1, 4)]).run(),
+        Err(ERROR_INCORRECT_SINCE_VALUE)
+    );
+    assert!(
+        Fault::new(epoch(1, 1, 2), &[epoch(1, 2, 4), epoch(1, 3, 4)])
+            .run()
+            .is_ok()
+    );
+}
+
+#[test]
+fn test_witness_faults() {
+    let fault = Fault::new(0, &[0, 0]);
+    assert!(fault.run().is_ok());
+    assert_eq!(
+        fault.run_with_witnesses(vec![]),
+        Err(ERROR_INDEX_OUT_OF_BOUND)
+    );
+    for witness in [vec![], vec![1, 2, 3], vec![16, 0, 0, 0]] {
+        assert_eq!(
+            fault.run_with_witnesses(vec![witness.clone().into()]),
+            Err(ERROR_ENCODING),
+            "{:?}",
+            witness
+        );
+    }
+    assert_eq!(
+        fault.run_with_witnesses(vec![WitnessArgs::new_builder().build().as_bytes()]),
+        Err(ERROR_WITNESS_SIZE)
+    );
+}
@@ -0,0 +1,201 @@
+//! The treasury of the governance contract spent on the ballots of its
+//! members: a proposal is good for the treasury cells it spends, once, and
+//! for at most its max fee.
+
+use super::*;
+use crate::vm_versions::{exit_code, MAX_CYCLES};
+use ckb_multisig_sdk::{MultisigConfig, SecpSigner, Signer};
+use ckb_testtool::builtin::ALWAYS_SUCCESS;
+use ckb_testtool::ckb_hash::blake2b_256;
+use ckb_testtool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
+use ckb_testtool::context::Context;
+
+// error numbers
+const ERROR_FEE: i8 = -56;
+const ERROR_NOT_PASSED: i8 = -92;
+const ERROR_MAX_FEE: i8 = -93;
+
+const CAPACITY: u64 = 1000;
+const MAX_FEE: u64 = 10;
+const YES: u8 = 1;
+
+struct Treasury {
+    context: Context,
+    signers: Vec<SecpSigner>,
+    multisig_script: Bytes,
+    governance: OutPoint,
+    always: Script,
+    /// The args of the treasury, `multisig hash | quorum | weights`.
+    args: Vec<u8>,
+}
+
+impl Treasury {
+    /// A treasury of three members weighing one each, with a quorum of two.
+    fn new() -> Self {
+        let mut context = Context::default();
+        let governance =
+            context.deploy_cell(Loader::default().load_binary("ckb-multisig-governance"));
+        let always_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+        let always = context
+            .build_script(&always_out_point, Bytes::new())
+            .expect("script");
+        let signers: Vec<_> = (1..=3u8)
+            .map(|i| SecpSigner::from_slice(&[i; 32]).expect("key"))
+            .collect();
+        let hashes = signers
+            .iter()
+            .map(|signer| signer.identity().expect("identity"))
+            .collect();
+        let config = MultisigConfig::new(hashes, 0, 2).unwrap();
+        let multisig_script = Bytes::copy_from_slice(&config.multisig_script());
+        let mut args = blake2b_256(&multisig_script)[..20].to_vec();
+        args.extend_from_slice(&2u16.to_le_bytes());
+        args.extend_from_slice(&[1, 1, 1]);
+        Treasury {
+            context,
+            signers,
+            multisig_script,
+            governance,
+            always,
+            args,
+        }
+    }
+
+    fn script(&mut self, args: &[u8]) -> Script {
+        self.context
+            .build_script(&self.governance, Bytes::copy_from_slice(args))
+            .expect("script")
+    }
+
+    /// A new treasury cell.
+    fn cell(&mut self) -> OutPoint {
+        let lock = self.script(&self.args.clone());
+        self.context.create_cell(
+            CellOutput::new_builder()
+                .capacity(CAPACITY.pack())
+                .lock(lock)
+                .build(),
+            Bytes::new(),
+        )
+    }
+
+    /// The ballots of `voters` for `proposal`, as cell deps.
+    fn ballots(&mut self, proposal: &[u8; 32], voters: &[u8]) -> Vec<CellDep> {
+        let mut args = self.args.clone();
+        args.extend_from_slice(proposal);
+        let ballot_type = self.script(&args);
+        let mut message = proposal.to_vec();
+        message.push(YES);
+        let digest = blake2b_256(&message);
+        voters
+            .iter()
+            .map(|voter| {
+                let signature = self.signers[usize::from(*voter)]
+                    .sign(&digest)
+                    .expect("signature");
+                let mut data = self.multisig_script.to_vec();
+                data.extend_from_slice(&[*voter, YES]);
+                data.extend_from_slice(&signature);
+                let out_point = self.context.create_cell(
+                    CellOutput::new_builder()
+                        .capacity(CAPACITY.pack())
+                        .lock(self.always.clone())
+                        .type_(Some(ballot_type.clone()).pack())
+                        .build(),
+                    data.into(),
+                );
+                CellDep::new_builder().out_point(out_point).build()
+            })
+            .collect()
+    }
+
+    /// The transaction of the proposal paying `paid` out of `input`, with
+    /// the max fee in its witness, and its proposal hash.
+    fn proposal(&mut self, input: OutPoint, paid: u64) -> (TransactionView, [u8; 32]) {
+        let output = CellOutput::new_builder()
+            .capacity(paid.pack())
+            .lock(self.always.clone())
+            .build();
+        let mut preimage = MAX_FEE.to_le_bytes().to_vec();
+        preimage.extend_from_slice(input.as_slice());
+        preimage.extend_from_slice(output.as_slice());
+        preimage.extend_from_slice(&0u64.to_le_bytes());
+        let witness = WitnessArgs::new_builder()
+            .input_type(Some(Bytes::copy_from_slice(&MAX_FEE.to_le_bytes())).pack())
+            .build();
+        let tx = TransactionBuilder::default()
+            .input(CellInput::new_builder().previous_output(input).build())
+            .output(output)
+            .output_data(Bytes::new().pack())
+            .witness(witness.as_bytes().pack())
+            .build();
+        (tx, blake2b_256(&preimage))
+    }
+
+    fn verify(&mut self, tx: TransactionView, ballots: Vec<CellDep>) -> Result<u64, i8> {
+        let tx = tx.as_advanced_builder().cell_deps(ballots).build();
+        let tx = self.context.complete_tx(tx);
+        self.context
+            .verify_tx(&tx, MAX_CYCLES)
+            .map_err(|err| exit_code(&err.to_string()))
+    }
+}
+
+#[test]
+fn test_governance_passed() {
+    let mut treasury = Treasury::new();
+    let input = treasury.cell();
+    let (tx, proposal) = treasury.proposal(input, CAPACITY - MAX_FEE);
+    let ballots = treasury.ballots(&proposal, &[0, 2]);
+    assert!(treasury.verify(tx, ballots).is_ok());
+}
+
+#[test]
+fn test_governance_not_passed() {
+    let mut treasury = Treasury::new();
+    let input = treasury.cell();
+    let (tx, proposal) = treasury.proposal(input, CAPACITY - MAX_FEE);
+    // a member voting twice weighs once
+    let ballots = treasury.ballots(&proposal, &[1, 1]);
+    assert_eq!(treasury.verify(tx, ballots), Err(ERROR_NOT_PASSED));
+}
+
+#[test]
+fn test_governance_replay_refused() {
+    let mut treasury = Treasury::new();
+    let input = treasury.cell();
+    let (tx, proposal) = treasury.proposal(input, CAPACITY - MAX_FEE);
+    let ballots = treasury.ballots(&proposal, &[0, 1]);
+    assert!(treasury.verify(tx, ballots.clone()).is_ok());
+
+    // the same outputs out of another treasury cell, on the ballots of the
+    // proposal executed
+    let other = treasury.cell();
+    let (replay, _) = treasury.proposal(other, CAPACITY - MAX_FEE);
+    assert_eq!(treasury.verify(replay, ballots), Err(ERROR_NOT_PASSED));
+}
+
+#[test]
+fn test_governance_fee_bounded() {
+    let mut treasury = Treasury::new();
+    let input = treasury.cell();
+    // the rest of the treasury cell burnt as fee
+    let (tx, proposal) = treasury.proposal(input, CAPACITY / 2);
+    let ballots = treasury.ballots(&proposal, &[0, 1]);
+    assert_eq!(treasury.verify(tx, ballots), Err(ERROR_FEE));
+
+    // no max fee in the witness
+    let input = treasury.cell();
+    let (tx, proposal) = treasury.proposal(input, CAPACITY - MAX_FEE);
+    let ballots = treasury.ballots(&proposal, &[0, 1]);
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![WitnessArgs::default().as_bytes().pack()])
+        .build();
+    assert_eq!(treasury.verify(tx, ballots), Err(ERROR_MAX_FEE));
+}
@@ -4,8 +4,18 @@ use std::fs;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+#[cfg(test)]
+mod faults;
+#[cfg(test)]
+mod governance;
+#[cfg(test)]
+mod registry;
+#[cfg(test)]
+mod roundtrip;
 #[cfg(test)]
 mod tests;
+#[cfg(test)]
+mod vm_versions;
 
 const TEST_ENV_VAR: &str = "CAPSULE_TEST_ENV";
 
@@ -58,4 +68,12 @@ impl Loader {
         path.push(name);
         fs::read(path).expect("binary").into()
     }
+
+    /// The secp256k1 tables the lock loads from a cell dep, dumped by the
+    /// build of `ckb-lib-secp256k1`.
+    pub fn load_secp256k1_data(&self) -> Bytes {
+        let mut path = env::current_dir().unwrap();
+        path.push("../contracts/ckb-multisig/ckb-lib-secp256k1/build/secp256k1_data");
+        fs::read(path).expect("secp256k1_data").into()
+    }
 }
@@ -0,0 +1,190 @@
+//! The config registry against the configs of the SDK: a config cell is
+//! created for every format of config and lock args the multisig lock
+//! takes, its data the head of the lock field, `MultisigConfig::
+//! multisig_script`.
+
+use super::*;
+use crate::vm_versions::{exit_code, MAX_CYCLES};
+use ckb_multisig_sdk::{
+    config::{ConfigEncoding, KeyFormat},
+    config_tree::ConfigTree,
+    signer::uncompressed_pubkey_identity,
+    successor::Successor,
+    MultisigConfig, SecpSigner, Signer,
+};
+use ckb_testtool::builtin::ALWAYS_SUCCESS;
+use ckb_testtool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
+use ckb_testtool::context::Context;
+
+// error numbers
+const ERROR_INVALID_RESERVE_FIELD: i8 = -41;
+const ERROR_CONFIG_SIZE: i8 = -45;
+const ERROR_DUPLICATED_PUBKEY: i8 = -46;
+const ERROR_MULTISIG_SCRIPT_HASH: i8 = -51;
+const ERROR_LOCK_SCRIPT: i8 = -63;
+
+const CAPACITY: u64 = 1000;
+
+fn signers(count: u8) -> Vec<SecpSigner> {
+    (1..=count)
+        .map(|i| SecpSigner::from_slice(&[i; 32]).expect("key"))
+        .collect()
+}
+
+/// Create a registry cell holding `config`, locked by the multisig lock of
+/// `lock_args`.
+fn register(config: &[u8], lock_args: &[u8]) -> Result<u64, i8> {
+    let mut context = Context::default();
+    let loader = Loader::default();
+    let registry_out_point = context.deploy_cell(loader.load_binary("ckb-multisig-registry"));
+    let lock_out_point = context.deploy_cell(loader.load_binary("ckb-multisig"));
+    let always_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let always = context
+        .build_script(&always_out_point, Bytes::new())
+        .expect("script");
+    let input = CellInput::new_builder()
+        .previous_output(
+            context.create_cell(
+                CellOutput::new_builder()
+                    .capacity(CAPACITY.pack())
+                    .lock(always)
+                    .build(),
+                Bytes::new(),
+            ),
+        )
+        .build();
+    let lock = context
+        .build_script(&lock_out_point, Bytes::copy_from_slice(lock_args))
+        .expect("script");
+
+    // the type id of the first output
+    let mut args =
+        ckb_testtool::ckb_hash::blake2b_256([input.as_slice(), &0u64.to_le_bytes()[..]].concat())
+            .to_vec();
+    args.extend_from_slice(lock.code_hash().as_slice());
+    args.extend_from_slice(lock.hash_type().as_slice());
+    let registry = context
+        .build_script(&registry_out_point, args.into())
+        .expect("script");
+
+    let dep = |out_point: OutPoint| CellDep::new_builder().out_point(out_point).build();
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .output(
+            CellOutput::new_builder()
+                .capacity(CAPACITY.pack())
+                .lock(lock)
+                .type_(Some(registry).pack())
+                .build(),
+        )
+        .output_data(Bytes::copy_from_slice(config).pack())
+        .cell_dep(dep(registry_out_point))
+        .cell_dep(dep(always_out_point))
+        .build();
+    let tx: TransactionView = context.complete_tx(tx);
+    context
+        .verify_tx(&tx, MAX_CYCLES)
+        .map_err(|err| exit_code(&err.to_string()))
+}
+
+/// Register `config` under its own lock args.
+fn register_config(config: &MultisigConfig) -> Result<u64, i8> {
+    register(&config.multisig_script(), &config.lock_args())
+}
+
+#[test]
+fn test_registry_formats() {
+    let keys = signers(3);
+    let hashes: Vec<_> = keys
+        .iter()
+        .map(|key| key.identity().expect("identity"))
+        .collect();
+    let uncompressed: Vec<_> = keys
+        .iter()
+        .map(|key| uncompressed_pubkey_identity(&key.pubkey()))
+        .collect();
+    let plain = MultisigConfig::new(hashes.clone(), 0, 2).unwrap();
+    let tree = ConfigTree::new(vec![
+        plain.clone(),
+        MultisigConfig::new(hashes[..2].to_vec(), 0, 1).unwrap(),
+    ])
+    .unwrap();
+    let cases = vec![
+        ("plain", plain.clone()),
+        (
+            "uncompressed keys",
+            MultisigConfig::new(uncompressed, 0, 2)
+                .unwrap()
+                .with_key_format(KeyFormat::Uncompressed),
+        ),
+        (
+            "either key format",
+            plain.clone().with_key_format(KeyFormat::Either),
+        ),
+        (
+            "molecule config",
+            plain.clone().with_encoding(ConfigEncoding::Molecule),
+        ),
+        ("config tree", tree.config(1).unwrap()),
+        ("since", plain.clone().with_since(Some(100))),
+        ("fee key", plain.clone().with_fee_key(Some([7; 20]))),
+        ("flags", plain.clone().with_strict_witnesses(true)),
+        (
+            "successor",
+            plain.clone().with_successor(Some(Successor {
+                multisig_hash: [8; 20],
+                type_hash: [9; 32],
+            })),
+        ),
+    ];
+    for (name, config) in cases {
+        assert!(register_config(&config).is_ok(), "{}", name);
+    }
+}
+
+#[test]
+fn test_registry_refuses() {
+    let keys = signers(3);
+    let hashes: Vec<_> = keys
+        .iter()
+        .map(|key| key.identity().expect("identity"))
+        .collect();
+    let plain = MultisigConfig::new(hashes.clone(), 0, 2).unwrap();
+    let args = plain.lock_args();
+
+    // another config, a reserved key format, a config with signatures
+    let other = MultisigConfig::new(hashes[..2].to_vec(), 0, 2).unwrap();
+    assert_eq!(
+        register(&other.multisig_script(), &args),
+        Err(ERROR_MULTISIG_SCRIPT_HASH)
+    );
+    let mut reserved = plain.multisig_script().to_vec();
+    reserved[0] = 3;
+    assert_eq!(register(&reserved, &args), Err(ERROR_INVALID_RESERVE_FIELD));
+    assert_eq!(
+        register(&plain.placeholder_lock(), &args),
+        Err(ERROR_CONFIG_SIZE)
+    );
+
+    // a key listed twice, under its own hash
+    let mut duplicated = plain.multisig_script().to_vec();
+    duplicated[24..44].copy_from_slice(&hashes[0]);
+    let hash = ckb_testtool::ckb_hash::blake2b_256(&duplicated);
+    assert_eq!(
+        register(&duplicated, &hash[..20]),
+        Err(ERROR_DUPLICATED_PUBKEY)
+    );
+
+    // lock args of no layout of the lock
+    let mut long = args.to_vec();
+    long.extend_from_slice(&[0; 5]);
+    assert_eq!(
+        register(&plain.multisig_script(), &long),
+        Err(ERROR_LOCK_SCRIPT)
+    );
+}
@@ -0,0 +1,320 @@
+//! The SDK against the contract: the configs, digests and witnesses are built
+//! with the public API of `ckb-multisig-sdk` alone, the transactions are run
+//! through the lock binary. A layout the two disagree on, the lock args, the
+//! multisig script, the molecule config, the proof of a config tree or the
+//! signed message, fails here.
+//!
+//! The SDK has its own ckb-types, newer than that of ckb-testtool: the
+//! transactions cross over as bytes.
+
+use super::*;
+use crate::vm_versions::{exit_code, MAX_CYCLES};
+use ckb_multisig_sdk::{
+    config::{ConfigEncoding, KeyFormat},
+    config_tree::ConfigTree,
+    digest::{compute_fee_sighash, compute_sighash, generate_legacy_message, generate_message},
+    signer::uncompressed_pubkey_identity,
+    witness::{set_spend_all_total, set_witness_lock},
+    MultisigConfig, MultisigLock, SecpSigner, Signer,
+};
+use ckb_testtool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
+use ckb_testtool::context::Context;
+
+type SdkTransaction = sdk_types::core::TransactionView;
+
+// error numbers
+const ERROR_VERIFICATION: i8 = -52;
+
+const CAPACITY: u64 = 1000;
+
+fn signers(count: u8) -> Vec<SecpSigner> {
+    (1..=count)
+        .map(|i| SecpSigner::from_slice(&[i; 32]).expect("key"))
+        .collect()
+}
+
+fn identities(signers: &[SecpSigner]) -> Vec<[u8; 20]> {
+    signers
+        .iter()
+        .map(|signer| signer.identity().expect("identity"))
+        .collect()
+}
+
+/// A transaction spending `inputs` cells of the lock with `lock_args`, each
+/// with `since`, back to the lock, with an empty `WitnessArgs` for the first
+/// input and a witness beyond the inputs, covered by the signatures.
+struct RoundTrip {
+    context: Context,
+    tx: TransactionView,
+    inputs: usize,
+}
+
+impl RoundTrip {
+    fn new(lock_args: &[u8], inputs: usize, since: u64) -> Self {
+        let mut context = Context::default();
+        let loader = Loader::default();
+        let lock_out_point = context.deploy_cell(loader.load_binary("ckb-multisig"));
+        let data_out_point = context.deploy_cell(loader.load_secp256k1_data());
+        let lock = context
+            .build_script(&lock_out_point, Bytes::copy_from_slice(lock_args))
+            .expect("script");
+        let cell_inputs: Vec<_> = (0..inputs)
+            .map(|_| {
+                let input = context.create_cell(
+                    CellOutput::new_builder()
+                        .capacity(CAPACITY.pack())
+                        .lock(lock.clone())
+                        .build(),
+                    Bytes::new(),
+                );
+                CellInput::new_builder()
+                    .previous_output(input)
+                    .since(since.pack())
+                    .build()
+            })
+            .collect();
+        let mut witnesses = vec![WitnessArgs::new_builder().build().as_bytes()];
+        witnesses.resize(inputs, Bytes::new());
+        witnesses.push(Bytes::from(vec![42u8; 10]));
+        let dep = |out_point: OutPoint| CellDep::new_builder().out_point(out_point).build();
+        let tx = TransactionBuilder::default()
+            .inputs(cell_inputs)
+            .output(
+                CellOutput::new_builder()
+                    .capacity((CAPACITY * inputs as u64).pack())
+                    .lock(lock)
+                    .build(),
+            )
+            .output_data(Bytes::new().pack())
+            .cell_dep(dep(lock_out_point))
+            .cell_dep(dep(data_out_point))
+            .witnesses(
+                witnesses
+                    .iter()
+                    .map(|witness| witness.pack())
+                    .collect::<Vec<_>>(),
+            )
+            .build();
+        let tx = context.complete_tx(tx);
+        RoundTrip {
+            context,
+            tx,
+            inputs,
+        }
+    }
+
+    /// The transaction as the SDK sees it.
+    fn sdk_tx(&self) -> SdkTransaction {
+        from_bytes(self.tx.data().as_slice())
+    }
+
+    /// The indices of the group, every input.
+    fn group(&self) -> Vec<usize> {
+        (0..self.inputs).collect()
+    }
+
+    /// Run the lock over the transaction the SDK completed.
+    fn run(&self, tx: &SdkTransaction) -> Result<u64, i8> {
+        let tx = Transaction::from_slice(&to_bytes(tx))
+            .expect("transaction")
+            .into_view();
+        assert_eq!(tx.hash(), self.tx.hash(), "the SDK changed the transaction");
+        self.context
+            .verify_tx(&tx, MAX_CYCLES)
+            .map_err(|err| exit_code(&err.to_string()))
+    }
+
+    /// Sign the group with `signers` into the first witness, the way the SDK
+    /// does: the sighash of `config`, the signatures in a `MultisigLock`.
+    fn sign(
+        &self,
+        tx: &SdkTransaction,
+        config: &MultisigConfig,
+        signers: &[&SecpSigner],
+    ) -> SdkTransaction {
+        let group = self.group();
+        let digest = compute_sighash(tx, &group, config).expect("sighash");
+        let mut lock = MultisigLock::new(config.clone());
+        for signer in signers {
+            lock.add_signature(signer.sign(&digest).expect("signature"))
+                .expect("slot");
+        }
+        // the SDK accepts what it built, the contract must too
+        lock.verify(&digest).expect("SDK verification");
+        let tx = set_witness_lock(tx, group[0], &lock).expect("witness");
+        // the signers reading the message from the filled lock field sign
+        // the same digest
+        assert_eq!(generate_message(&tx, &group).expect("message"), digest);
+        tx
+    }
+}
+
+#[test]
+fn test_roundtrip_configs() {
+    let keys = signers(3);
+    let hashes = identities(&keys);
+    let plain = MultisigConfig::new(hashes.clone(), 0, 2).unwrap();
+    let uncompressed: Vec<_> = keys
+        .iter()
+        .map(|key| uncompressed_pubkey_identity(&key.pubkey()))
+        .collect();
+    let mixed = vec![hashes[0], uncompressed[1], hashes[2]];
+    let cases = vec![
+        (
+            "1 of 1",
+            MultisigConfig::new(vec![hashes[0]], 0, 1).unwrap(),
+            vec![&keys[0]],
+        ),
+        ("2 of 3", plain.clone(), vec![&keys[2], &keys[0]]),
+        (
+            "2 of 3 including the first",
+            MultisigConfig::new(hashes.clone(), 1, 2).unwrap(),
+            vec![&keys[1], &keys[0]],
+        ),
+        (
+            "uncompressed keys",
+            MultisigConfig::new(uncompressed, 0, 2)
+                .unwrap()
+                .with_key_format(KeyFormat::Uncompressed),
+            vec![&keys[0], &keys[1]],
+        ),
+        (
+            "either key format",
+            MultisigConfig::new(mixed, 0, 2)
+                .unwrap()
+                .with_key_format(KeyFormat::Either),
+            vec![&keys[1], &keys[2]],
+        ),
+        (
+            "molecule config",
+            plain.clone().with_encoding(ConfigEncoding::Molecule),
+            vec![&keys[0], &keys[1]],
+        ),
+        (
+            "strict witnesses",
+            plain.clone().with_strict_witnesses(true),
+            vec![&keys[0], &keys[1]],
+        ),
+    ];
+    for (name, config, signers) in cases {
+        let roundtrip = RoundTrip::new(&config.lock_args(), 2, 0);
+        let tx = roundtrip.sign(&roundtrip.sdk_tx(), &config, &signers);
+        assert!(roundtrip.run(&tx).is_ok(), "{}", name);
+
+        // the witness beyond the inputs is signed by both
+        let tampered = with_witness(&tx, 2, &[43u8; 10]);
+        assert_eq!(
+            roundtrip.run(&tampered),
+            Err(ERROR_VERIFICATION),
+            "{}",
+            name
+        );
+    }
+
+    // the since of the lock args, as an absolute block number
+    let timelocked = plain.clone().with_since(Some(100));
+    for (since, expected) in [(100, true), (99, false)] {
+        let roundtrip = RoundTrip::new(&timelocked.lock_args(), 2, since);
+        let tx = roundtrip.sign(&roundtrip.sdk_tx(), &timelocked, &[&keys[0], &keys[1]]);
+        assert_eq!(roundtrip.run(&tx).is_ok(), expected, "since {}", since);
+    }
+
+    // the total of the spent cells declared before signing
+    let spend_all = plain.with_spend_all(true);
+    let roundtrip = RoundTrip::new(&spend_all.lock_args(), 2, 0);
+    let tx = set_spend_all_total(&roundtrip.sdk_tx(), 0, 2).unwrap();
+    let tx = roundtrip.sign(&tx, &spend_all, &[&keys[0], &keys[1]]);
+    assert!(roundtrip.run(&tx).is_ok());
+}
+
+#[test]
+fn test_roundtrip_config_tree() {
+    let keys = signers(4);
+    let hashes = identities(&keys);
+    let tree = ConfigTree::new(vec![
+        MultisigConfig::new(hashes[..3].to_vec(), 0, 2).unwrap(),
+        MultisigConfig::new(hashes.clone(), 1, 3).unwrap(),
+        MultisigConfig::new(vec![hashes[3]], 0, 1).unwrap(),
+    ])
+    .unwrap();
+    let quorums = [
+        vec![&keys[1], &keys[2]],
+        vec![&keys[3], &keys[0], &keys[2]],
+        vec![&keys[3]],
+    ];
+    for (index, signers) in quorums.iter().enumerate() {
+        let config = tree.config(index).unwrap();
+        let roundtrip = RoundTrip::new(&config.lock_args(), 1, 0);
+        let tx = roundtrip.sign(&roundtrip.sdk_tx(), &config, signers);
+        assert!(roundtrip.run(&tx).is_ok(), "config #{}", index);
+    }
+}
+
+#[test]
+fn test_roundtrip_legacy_witnesses() {
+    let keys = signers(3);
+    let config = MultisigConfig::new(identities(&keys), 0, 2)
+        .unwrap()
+        .with_legacy_witnesses(true);
+    let roundtrip = RoundTrip::new(&config.lock_args(), 2, 0);
+    let group = roundtrip.group();
+    // the lock field in the second witness, the first a placeholder
+    let unsigned =
+        set_witness_lock(&roundtrip.sdk_tx(), 1, &MultisigLock::new(config.clone())).unwrap();
+    let digest = generate_legacy_message(&unsigned, &group).unwrap();
+    let mut lock = MultisigLock::new(config);
+    for key in &keys[1..] {
+        lock.add_signature(key.sign(&digest).unwrap()).unwrap();
+    }
+    let tx = set_witness_lock(&unsigned, 1, &lock).unwrap();
+    assert!(roundtrip.run(&tx).is_ok());
+}
+
+#[test]
+fn test_roundtrip_fee_key() {
+    let keys = signers(3);
+    let fee_key = SecpSigner::from_slice(&[9; 32]).unwrap();
+    let config = MultisigConfig::new(identities(&keys), 0, 2)
+        .unwrap()
+        .with_fee_key(Some(fee_key.identity().unwrap()));
+    let roundtrip = RoundTrip::new(&config.lock_args(), 2, 0);
+    let tx = roundtrip.sdk_tx();
+    let digest = compute_fee_sighash(&tx, &roundtrip.group()).unwrap();
+    let signature = fee_key.sign(&digest).unwrap();
+    let witness = WitnessArgs::new_builder()
+        .lock(Some(Bytes::copy_from_slice(&signature)).pack())
+        .build();
+    let tx = with_witness(&tx, 0, witness.as_slice());
+    assert!(roundtrip.run(&tx).is_ok());
+
+    // the multisig signs as well alongside the fee key
+    let tx = roundtrip.sign(&roundtrip.sdk_tx(), &config, &[&keys[0], &keys[2]]);
+    assert!(roundtrip.run(&tx).is_ok());
+}
+
+// the traits of the SDK types, apart from those of ckb-testtool
+
+fn from_bytes(bytes: &[u8]) -> SdkTransaction {
+    use sdk_types::prelude::*;
+    sdk_types::packed::Transaction::from_slice(bytes)
+        .expect("transaction")
+        .into_view()
+}
+
+fn to_bytes(tx: &SdkTransaction) -> Vec<u8> {
+    use sdk_types::prelude::*;
+    tx.data().as_slice().to_vec()
+}
+
+/// `tx` with `witness` at `index`.
+fn with_witness(tx: &SdkTransaction, index: usize, witness: &[u8]) -> SdkTransaction {
+    use sdk_types::prelude::*;
+    let mut witnesses: Vec<_> = tx.witnesses().into_iter().collect();
+    witnesses[index] = sdk_types::bytes::Bytes::copy_from_slice(witness).pack();
+    tx.as_advanced_builder().set_witnesses(witnesses).build()
+}
@@ -0,0 +1,210 @@
+//! The lock run under every VM version: referenced by `data` it runs on VM 0,
+//! by `data1` on VM 1 and by `data2` on VM 2. The lock uses no syscall added
+//! after VM 0, every scenario must end the same under the three, only the
+//! cycles may differ.
+//!
+//! The one intentional difference is the exec callee, see `auth.rs` of
+//! `ckb-multisig-core`: `exec` only exists from VM 1, a script calling the
+//! lock binary that way must itself be referenced by `data1`, `data2` or a
+//! type hash.
+
+use super::*;
+use ckb_testtool::ckb_crypto::secp::{Generator, Privkey};
+use ckb_testtool::ckb_types::{
+    bytes::Bytes,
+    core::{ScriptHashType, TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+    H256,
+};
+use ckb_testtool::context::Context;
+
+pub(crate) const MAX_CYCLES: u64 = 70_000_000;
+const SIGNATURE_SIZE: usize = 65;
+const HASH_TYPES: [ScriptHashType; 3] = [
+    ScriptHashType::Data,
+    ScriptHashType::Data1,
+    ScriptHashType::Data2,
+];
+
+// error numbers
+const ERROR_ARGUMENTS_LEN: i8 = -1;
+const ERROR_INCORRECT_SINCE_VALUE: i8 = -24;
+const ERROR_VERIFICATION: i8 = -52;
+
+/// How a run ended: the cycles, or the exit code of the lock.
+type Outcome = Result<u64, i8>;
+
+/// A transaction of a single input of the lock under `hash_type`, with
+/// `args`, and the same input `since`.
+struct Scenario {
+    context: Context,
+    tx: TransactionView,
+}
+
+impl Scenario {
+    fn new(hash_type: ScriptHashType, args: Bytes, since: u64) -> Self {
+        let mut context = Context::default();
+        let loader = Loader::default();
+        let lock_out_point = context.deploy_cell(loader.load_binary("ckb-multisig"));
+        let data_out_point = context.deploy_cell(loader.load_secp256k1_data());
+        let lock = context
+            .build_script_with_hash_type(&lock_out_point, hash_type, args)
+            .expect("script");
+        let input = context.create_cell(
+            CellOutput::new_builder()
+                .capacity(1000u64.pack())
+                .lock(lock.clone())
+                .build(),
+            Bytes::new(),
+        );
+        let dep = |out_point: OutPoint| CellDep::new_builder().out_point(out_point).build();
+        let tx = TransactionBuilder::default()
+            .input(
+                CellInput::new_builder()
+                    .previous_output(input)
+                    .since(since.pack())
+                    .build(),
+            )
+            .output(
+                CellOutput::new_builder()
+                    .capacity(1000u64.pack())
+                    .lock(lock)
+                    .build(),
+            )
+            .output_data(Bytes::new().pack())
+            .cell_dep(dep(lock_out_point))
+            .cell_dep(dep(data_out_point))
+            .witness(WitnessArgs::new_builder().build().as_bytes().pack())
+            .build();
+        let tx = context.complete_tx(tx);
+        Scenario { context, tx }
+    }
+
+    /// Sign with `keys` behind the `multisig_script` and run the lock.
+    fn run(&self, multisig_script: &Bytes, keys: &[&Privkey]) -> Outcome {
+        let tx = multi_sign_tx(&self.tx, multisig_script, keys);
+        self.context
+            .verify_tx(&tx, MAX_CYCLES)
+            .map_err(|err| exit_code(&err.to_string()))
+    }
+}
+
+/// Run the scenario under every hash type, and check they end the same.
+fn assert_same_outcome<F>(name: &str, run: F) -> Outcome
+where
+    F: Fn(ScriptHashType) -> Outcome,
+{
+    let outcomes: Vec<_> = HASH_TYPES.iter().map(|hash_type| run(*hash_type)).collect();
+    for (hash_type, outcome) in HASH_TYPES.iter().zip(&outcomes) {
+        println!("{} under {:?}: {:?}", name, hash_type, outcome);
+    }
+    for outcome in &outcomes[1..] {
+        assert_eq!(
+            outcome.is_ok(),
+            outcomes[0].is_ok(),
+            "{}: {:?}",
+            name,
+            outcomes
+        );
+        if outcome.is_err() {
+            assert_eq!(*outcome, outcomes[0], "{}: {:?}", name, outcomes);
+        }
+    }
+    outcomes[0]
+}
+
+#[test]
+fn test_vm_versions() {
+    let keys: Vec<_> = (0..3).map(|_| Generator::random_privkey()).collect();
+    let script = multisig_script(&keys, 2, 0);
+    let args = blake160(&script);
+
+    let outcome = assert_same_outcome("2 of 3", |hash_type| {
+        Scenario::new(hash_type, args.clone(), 0).run(&script, &[&keys[0], &keys[2]])
+    });
+    assert!(outcome.is_ok());
+
+    let stranger = Generator::random_privkey();
+    let outcome = assert_same_outcome("2 of 3 with a stranger", |hash_type| {
+        Scenario::new(hash_type, args.clone(), 0).run(&script, &[&keys[0], &stranger])
+    });
+    assert_eq!(outcome, Err(ERROR_VERIFICATION));
+
+    let outcome = assert_same_outcome("args of 21 bytes", |hash_type| {
+        let mut args = args.to_vec();
+        args.push(0);
+        Scenario::new(hash_type, args.into(), 0).run(&script, &[&keys[0], &keys[1]])
+    });
+    assert_eq!(outcome, Err(ERROR_ARGUMENTS_LEN));
+
+    // after block 100 in the args, an input since of block 99
+    let mut timelocked = args.to_vec();
+    timelocked.extend_from_slice(&100u64.to_le_bytes());
+    let outcome = assert_same_outcome("since not reached", |hash_type| {
+        Scenario::new(hash_type, timelocked.clone().into(), 99).run(&script, &[&keys[0], &keys[1]])
+    });
+    assert_eq!(outcome, Err(ERROR_INCORRECT_SINCE_VALUE));
+    let outcome = assert_same_outcome("since reached", |hash_type| {
+        Scenario::new(hash_type, timelocked.clone().into(), 100).run(&script, &[&keys[0], &keys[1]])
+    });
+    assert!(outcome.is_ok());
+}
+
+pub(crate) fn multisig_script(keys: &[Privkey], threshold: u8, require_first_n: u8) -> Bytes {
+    let mut script = vec![0u8, require_first_n, threshold, keys.len() as u8];
+    for key in keys {
+        script.extend_from_slice(&blake160(&key.pubkey().unwrap().serialize()));
+    }
+    script.into()
+}
+
+pub(crate) fn blake160(data: &[u8]) -> Bytes {
+    Bytes::copy_from_slice(&ckb_testtool::ckb_hash::blake2b_256(data)[..20])
+}
+
+/// The lock field `multisig_script | signatures` in the first witness,
+/// signed over the tx hash and the witness with its signatures zeroed.
+pub(crate) fn multi_sign_tx(
+    tx: &TransactionView,
+    multisig_script: &Bytes,
+    keys: &[&Privkey],
+) -> TransactionView {
+    let witness = WitnessArgs::new_unchecked(tx.witnesses().get(0).unwrap().raw_data());
+    let mut zeroed = multisig_script.to_vec();
+    zeroed.resize(zeroed.len() + keys.len() * SIGNATURE_SIZE, 0);
+    let unsigned = witness
+        .clone()
+        .as_builder()
+        .lock(Some(Bytes::from(zeroed)).pack())
+        .build();
+    let mut blake2b = ckb_testtool::ckb_hash::new_blake2b();
+    blake2b.update(&tx.hash().raw_data());
+    blake2b.update(&(unsigned.as_bytes().len() as u64).to_le_bytes());
+    blake2b.update(&unsigned.as_bytes());
+    let mut message = [0u8; 32];
+    blake2b.finalize(&mut message);
+    let message = H256::from(message);
+
+    let mut lock = multisig_script.to_vec();
+    for key in keys {
+        lock.extend_from_slice(&key.sign_recoverable(&message).unwrap().serialize());
+    }
+    let witness = witness
+        .as_builder()
+        .lock(Some(Bytes::from(lock)).pack())
+        .build();
+    tx.as_advanced_builder()
+        .set_witnesses(vec![witness.as_bytes().pack()])
+        .build()
+}
+
+/// The exit code in the error of a failed run.
+pub(crate) fn exit_code(error: &str) -> i8 {
+    error
+        .split("error code ")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|code| code.parse().ok())
+        .unwrap_or_else(|| panic!("no exit code in {}", error))
+}
@@ -0,0 +1,266 @@
+//! wasm32 bindings of the SDK config, witness and digest logic, so browser
+//! extensions and web wallets build and check witnesses with the code the
+//! SDK tests against the contract.
+//!
+//! Transactions are passed in the JSON format of the node RPC, byte strings
+//! as `Uint8Array`, and the since value as a `BigInt`:
+//!
+//! ```js
+//! const config = new Config(["0x...", "0x...", "0x..."], 0, 2);
+//! let tx = setLock(txJson, [0, 1], new Lock(config));
+//! const message = generateMessage(tx, [0, 1]);
+//! const lock = Lock.parse(currentLock(tx, [0, 1]));
+//! lock.addSignature(signature);
+//! tx = setLock(tx, [0, 1], lock);
+//! verifyTransaction(tx, [0, 1], config);
+//! ```
+//!
+//! Build with `wasm-pack build wasm`, secp256k1 is compiled from C and needs
+//! a clang able to target wasm32.
+
+use std::convert::TryInto;
+
+use ckb_jsonrpc_types as json;
+use ckb_multisig_sdk::{
+    constants::{BLAKE160_SIZE, DIGEST_SIZE, SIGNATURE_SIZE},
+    digest,
+    witness::{set_witness_lock, witness_args},
+    Error, MultisigConfig, MultisigLock,
+};
+use ckb_types::{bytes::Bytes, core::TransactionView, packed, prelude::*};
+use wasm_bindgen::prelude::*;
+
+#[cfg(test)]
+mod tests;
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct Config(MultisigConfig);
+
+#[wasm_bindgen]
+impl Config {
+    /// `pubkey_hashes` are `0x` prefixed blake160 hex strings, in the order
+    /// of the multisig script.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        pubkey_hashes: Vec<String>,
+        require_first_n: u8,
+        threshold: u8,
+    ) -> Result<Config, JsError> {
+        Ok(Config(
+            new_config(&pubkey_hashes, require_first_n, threshold).map_err(js_error)?,
+        ))
+    }
+
+    #[wasm_bindgen(js_name = fromMultisigScript)]
+    pub fn from_multisig_script(script: &[u8]) -> Result<Config, JsError> {
+        Ok(Config(
+            MultisigConfig::from_multisig_script(script).map_err(js_error)?,
+        ))
+    }
+
+    /// The since carried by the lock args.
+    #[wasm_bindgen(js_name = withSince)]
+    pub fn with_since(&self, since: u64) -> Config {
+        Config(self.0.clone().with_since(Some(since)))
+    }
+
+    #[wasm_bindgen(js_name = multisigScript)]
+    pub fn multisig_script(&self) -> Vec<u8> {
+        self.0.multisig_script().to_vec()
+    }
+
+    #[wasm_bindgen(js_name = lockArgs)]
+    pub fn lock_args(&self) -> Vec<u8> {
+        self.0.lock_args().to_vec()
+    }
+
+    #[wasm_bindgen(js_name = placeholderLock)]
+    pub fn placeholder_lock(&self) -> Vec<u8> {
+        self.0.placeholder_lock().to_vec()
+    }
+}
+
+/// The lock field of the first witness in a script group.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct Lock(MultisigLock);
+
+#[wasm_bindgen]
+impl Lock {
+    /// An unsigned lock field.
+    #[wasm_bindgen(constructor)]
+    pub fn new(config: &Config) -> Lock {
+        Lock(MultisigLock::new(config.0.clone()))
+    }
+
+    pub fn parse(lock: &[u8]) -> Result<Lock, JsError> {
+        Ok(Lock(MultisigLock::parse(lock).map_err(js_error)?))
+    }
+
+    pub fn config(&self) -> Config {
+        Config(self.0.config().clone())
+    }
+
+    /// Put a 65 bytes `r | s | recid` signature into the first empty slot.
+    #[wasm_bindgen(js_name = addSignature)]
+    pub fn add_signature(&mut self, signature: &[u8]) -> Result<(), JsError> {
+        add_signature(&mut self.0, signature).map_err(js_error)
+    }
+
+    #[wasm_bindgen(js_name = filledCount)]
+    pub fn filled_count(&self) -> usize {
+        self.0.filled_count()
+    }
+
+    #[wasm_bindgen(js_name = isComplete)]
+    pub fn is_complete(&self) -> bool {
+        self.0.is_complete()
+    }
+
+    /// Check the signatures against `message` the way the contract does.
+    pub fn verify(&self, message: &[u8]) -> Result<(), JsError> {
+        self.0
+            .verify(&to_digest(message).map_err(js_error)?)
+            .map_err(js_error)
+    }
+
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes().to_vec()
+    }
+}
+
+/// The message the cosigners sign, the first witness of the group must
+/// carry a lock field, e.g. the placeholder set by `setLock`.
+#[wasm_bindgen(js_name = generateMessage)]
+pub fn generate_message(tx: &str, input_indices: Vec<usize>) -> Result<Vec<u8>, JsError> {
+    let tx = parse_tx(tx).map_err(js_error)?;
+    Ok(digest::generate_message(&tx, &input_indices)
+        .map_err(js_error)?
+        .to_vec())
+}
+
+/// The lock field of the first witness of the group.
+#[wasm_bindgen(js_name = currentLock)]
+pub fn current_lock(tx: &str, input_indices: Vec<usize>) -> Result<Vec<u8>, JsError> {
+    let tx = parse_tx(tx).map_err(js_error)?;
+    Ok(first_lock(&tx, &input_indices).map_err(js_error)?.to_vec())
+}
+
+/// Put `lock` into the first witness of the group, the other fields of the
+/// witness are kept.
+#[wasm_bindgen(js_name = setLock)]
+pub fn set_lock(tx: &str, input_indices: Vec<usize>, lock: &Lock) -> Result<String, JsError> {
+    let tx = parse_tx(tx).map_err(js_error)?;
+    let tx = put_lock(&tx, &input_indices, &lock.0).map_err(js_error)?;
+    format_tx(&tx).map_err(js_error)
+}
+
+/// Check that the group witness is completely signed by `config`.
+#[wasm_bindgen(js_name = verifyTransaction)]
+pub fn verify_transaction(
+    tx: &str,
+    input_indices: Vec<usize>,
+    config: &Config,
+) -> Result<(), JsError> {
+    let tx = parse_tx(tx).map_err(js_error)?;
+    verify(&tx, &input_indices, &config.0).map_err(js_error)
+}
+
+fn js_error(err: Error) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+fn new_config(
+    pubkey_hashes: &[String],
+    require_first_n: u8,
+    threshold: u8,
+) -> Result<MultisigConfig, Error> {
+    let hashes = pubkey_hashes
+        .iter()
+        .map(|hash| {
+            let bytes = hash
+                .strip_prefix("0x")
+                .and_then(|digits| hex::decode(digits).ok())
+                .ok_or_else(|| Error::InvalidParameter(format!("invalid pubkey hash {}", hash)))?;
+            bytes.as_slice().try_into().map_err(|_| {
+                Error::InvalidParameter(format!(
+                    "pubkey hash {} is not {} bytes",
+                    hash, BLAKE160_SIZE
+                ))
+            })
+        })
+        .collect::<Result<Vec<[u8; BLAKE160_SIZE]>, _>>()?;
+    MultisigConfig::new(hashes, require_first_n, threshold)
+}
+
+fn add_signature(lock: &mut MultisigLock, signature: &[u8]) -> Result<(), Error> {
+    let signature: [u8; SIGNATURE_SIZE] = signature.try_into().map_err(|_| {
+        Error::InvalidParameter(format!(
+            "signature of {} bytes, expected {}",
+            signature.len(),
+            SIGNATURE_SIZE
+        ))
+    })?;
+    lock.add_signature(signature)
+}
+
+fn to_digest(message: &[u8]) -> Result<[u8; DIGEST_SIZE], Error> {
+    message.try_into().map_err(|_| {
+        Error::InvalidParameter(format!(
+            "message of {} bytes, expected {}",
+            message.len(),
+            DIGEST_SIZE
+        ))
+    })
+}
+
+fn parse_tx(tx: &str) -> Result<TransactionView, Error> {
+    let tx: json::Transaction = serde_json::from_str(tx)
+        .map_err(|err| Error::InvalidParameter(format!("transaction: {}", err)))?;
+    Ok(packed::Transaction::from(tx).into_view())
+}
+
+fn format_tx(tx: &TransactionView) -> Result<String, Error> {
+    serde_json::to_string(&json::Transaction::from(tx.data()))
+        .map_err(|err| Error::InvalidParameter(format!("transaction: {}", err)))
+}
+
+fn first_index(input_indices: &[usize]) -> Result<usize, Error> {
+    input_indices
+        .first()
+        .copied()
+        .ok_or_else(|| Error::InvalidWitness("empty script group".to_string()))
+}
+
+fn first_lock(tx: &TransactionView, input_indices: &[usize]) -> Result<Bytes, Error> {
+    let first = first_index(input_indices)?;
+    witness_args(tx, first)?
+        .lock()
+        .to_opt()
+        .map(|lock| lock.raw_data())
+        .ok_or_else(|| Error::InvalidWitness(format!("witness #{} has no lock field", first)))
+}
+
+fn put_lock(
+    tx: &TransactionView,
+    input_indices: &[usize],
+    lock: &MultisigLock,
+) -> Result<TransactionView, Error> {
+    set_witness_lock(tx, first_index(input_indices)?, lock)
+}
+
+fn verify(
+    tx: &TransactionView,
+    input_indices: &[usize],
+    config: &MultisigConfig,
+) -> Result<(), Error> {
+    let lock = MultisigLock::parse(&first_lock(tx, input_indices)?)?;
+    if lock.config().multisig_script() != config.multisig_script() {
+        return Err(Error::InvalidWitness(
+            "the witness belongs to another config".to_string(),
+        ));
+    }
+    lock.verify(&digest::generate_message(tx, input_indices)?)
+}
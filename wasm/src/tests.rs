@@ -0,0 +1,69 @@
+use ckb_multisig_sdk::{SecpSigner, Signer};
+use ckb_types::{
+    bytes::Bytes,
+    core::TransactionBuilder,
+    packed::{CellInput, CellOutput, OutPoint},
+    prelude::*,
+};
+use secp256k1::{rand, SecretKey};
+
+use super::*;
+
+fn signers(count: usize) -> Vec<SecpSigner> {
+    (0..count)
+        .map(|_| SecpSigner::new(SecretKey::new(&mut rand::thread_rng())))
+        .collect()
+}
+
+fn hex_hashes(signers: &[SecpSigner]) -> Vec<String> {
+    signers
+        .iter()
+        .map(|signer| format!("0x{}", hex::encode(signer.identity().unwrap())))
+        .collect()
+}
+
+fn tx_json() -> String {
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new([1u8; 32].pack(), 0), 0))
+        .input(CellInput::new(OutPoint::new([2u8; 32].pack(), 0), 0))
+        .output(CellOutput::new_builder().build())
+        .output_data(Bytes::new().pack())
+        .build();
+    format_tx(&tx).unwrap()
+}
+
+#[test]
+fn test_sign_and_verify() {
+    let signers = signers(3);
+    let config = new_config(&hex_hashes(&signers), 1, 2).unwrap();
+    let indices = [0, 1];
+    let tx = parse_tx(&tx_json()).unwrap();
+
+    let mut lock = MultisigLock::new(config.clone());
+    let tx = put_lock(&tx, &indices, &lock).unwrap();
+    let message = digest::generate_message(&tx, &indices).unwrap();
+    add_signature(&mut lock, &signers[0].sign(&message).unwrap()).unwrap();
+    let partial = put_lock(&tx, &indices, &lock).unwrap();
+    assert!(verify(&partial, &indices, &config).is_err());
+    // the message doesn't depend on the filled slots
+    assert_eq!(
+        digest::generate_message(&partial, &indices).unwrap(),
+        message
+    );
+
+    add_signature(&mut lock, &signers[2].sign(&message).unwrap()).unwrap();
+    let signed = parse_tx(&format_tx(&put_lock(&tx, &indices, &lock).unwrap()).unwrap()).unwrap();
+    verify(&signed, &indices, &config).unwrap();
+    assert_eq!(first_lock(&signed, &indices).unwrap(), lock.to_bytes());
+}
+
+#[test]
+fn test_invalid_inputs() {
+    let signers = signers(2);
+    assert!(new_config(&["0x00".to_string()], 0, 1).is_err());
+    let config = new_config(&hex_hashes(&signers), 0, 1).unwrap();
+    let mut lock = MultisigLock::new(config);
+    assert!(add_signature(&mut lock, &[0u8; 64]).is_err());
+    assert!(to_digest(&[0u8; 31]).is_err());
+    assert!(parse_tx("{}").is_err());
+}